@@ -0,0 +1,611 @@
+//! Loading textures, meshes, shaders and fonts by path, in the background.
+//!
+//! `Assets::load` hands out a typed `Handle` right away and reads and decodes the file on
+//! the job pool. `get` returns the asset once a later `update` has created it, on the
+//! thread that owns the device. Loading a path again gives the same asset. Handles are
+//! reference counted, and an asset is freed by the first `update` after its last handle is
+//! dropped.
+//!
+//! `load_state` tells how a load is going and `events` what finished during the last
+//! `update`, for loading screens and for setting things up once their assets are in.
+//!
+//! Files are read through a `Vfs`, by default the working directory. Embed them there
+//! to ship without loose files.
+//!
+//! With `hot_reload` on, `update` also notices files changing on disk and loads them again
+//! in place, so handles stay valid, then calls the hooks registered with `on_reload`.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, SystemTime};
+
+use crate::mesh::{GpuMesh, Mesh, MeshStats};
+use crate::text::Font;
+use crate::texture::Texture;
+use crate::time::Instant;
+use crate::vfs::Vfs;
+
+/// How often `update` looks for changed files.
+const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug)]
+pub enum AssetError {
+    Io(PathBuf, std::io::Error),
+    Decode(PathBuf, BoxError),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            AssetError::Decode(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+/// Something `Assets` can load, in two steps: `decode` turns the file into `Data` on the
+/// job pool, `create` turns that into the asset on the thread calling `update`.
+pub trait Asset: Sized + 'static {
+    type Data: Send + 'static;
+
+    fn decode(bytes: Vec<u8>) -> Result<Self::Data, BoxError>;
+    fn create(
+        data: Self::Data,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+    ) -> Result<Self, BoxError>;
+}
+
+/// A decoded texture, waiting to be uploaded.
+pub enum TextureData {
+    Image(image::RgbaImage),
+    #[cfg(feature = "ktx2")]
+    Ktx2(crate::ktx::KtxImage),
+}
+
+/// Loaded from PNG or JPEG, or KTX2 with the `ktx2` feature.
+impl Asset for Texture {
+    type Data = TextureData;
+
+    fn decode(bytes: Vec<u8>) -> Result<Self::Data, BoxError> {
+        #[cfg(feature = "ktx2")]
+        if crate::ktx::is_ktx2(&bytes) {
+            return Ok(TextureData::Ktx2(crate::ktx::KtxImage::parse(&bytes)?));
+        }
+        Ok(TextureData::Image(
+            image::load_from_memory(&bytes)?.into_rgba8(),
+        ))
+    }
+
+    fn create(
+        data: Self::Data,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: &str,
+    ) -> Result<Self, BoxError> {
+        match data {
+            TextureData::Image(image) => Ok(Texture::from_rgba8(
+                device,
+                queue,
+                image.width(),
+                image.height(),
+                &image,
+                label,
+            )),
+            #[cfg(feature = "ktx2")]
+            TextureData::Ktx2(image) => {
+                Ok(Texture::builder(label).build_ktx_image(device, queue, image)?)
+            }
+        }
+    }
+}
+
+/// Loaded from Wavefront OBJ, and optimized for the GPU, see `Mesh::optimize`.
+impl Asset for GpuMesh {
+    type Data = (Mesh, MeshStats, MeshStats);
+
+    fn decode(bytes: Vec<u8>) -> Result<Self::Data, BoxError> {
+        let mut mesh = Mesh::from_obj(std::str::from_utf8(&bytes)?)?;
+        let (before, after) = mesh.optimize();
+        Ok((mesh, before, after))
+    }
+
+    fn create(
+        (mesh, before, after): Self::Data,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        label: &str,
+    ) -> Result<Self, BoxError> {
+        log::debug!("optimized {}: {} -> {}", label, before, after);
+        Ok(GpuMesh::new(device, &mesh, label))
+    }
+}
+
+impl Asset for Font {
+    type Data = Font;
+
+    fn decode(bytes: Vec<u8>) -> Result<Self::Data, BoxError> {
+        Ok(Font::from_bytes(bytes)?)
+    }
+
+    fn create(
+        font: Self::Data,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _label: &str,
+    ) -> Result<Self, BoxError> {
+        Ok(font)
+    }
+}
+
+/// A WGSL shader module. One that fails to compile is reported as a failed load instead of
+/// panicking, so a typo while hot reloading keeps the previous version.
+pub struct Shader {
+    pub module: wgpu::ShaderModule,
+}
+
+impl Asset for Shader {
+    type Data = String;
+
+    fn decode(bytes: Vec<u8>) -> Result<Self::Data, BoxError> {
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    fn create(
+        source: Self::Data,
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        label: &str,
+    ) -> Result<Self, BoxError> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(e) => Err(e.to_string().into()),
+            None => Ok(Shader { module }),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AssetId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    /// The handle is from another `Assets`.
+    NotLoaded,
+    Loading,
+    /// Stays so while a changed file is loaded again.
+    Loaded,
+    /// The reason was logged. A hot reload retries once the file changes.
+    Failed,
+}
+
+/// Something that happened to an asset during an `update`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AssetEvent {
+    Loaded(AssetId),
+    Reloaded(AssetId),
+    Failed(AssetId),
+}
+
+/// Keeps an asset loaded for as long as it or a clone of it exists.
+pub struct Handle<T> {
+    id: AssetId,
+    refs: Arc<()>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> AssetId {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            refs: self.refs.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.id.0).finish()
+    }
+}
+
+/// Reads and decodes files through the `Vfs` on the job pool, in the order queued.
+struct Loader {
+    vfs: Arc<Vfs>,
+}
+
+impl Loader {
+    fn new(vfs: Vfs) -> Self {
+        Self { vfs: Arc::new(vfs) }
+    }
+
+    fn spawn(&self, job: impl FnOnce(&Vfs) + Send + 'static) {
+        let vfs = self.vfs.clone();
+        // nobody waits for the job, the result comes back through the storage's channel
+        let _ = crate::jobs::spawn(move || job(&vfs));
+    }
+}
+
+struct Entry<T> {
+    /// `None` for assets added directly.
+    path: Option<PathBuf>,
+    refs: Arc<()>,
+    asset: Option<T>,
+    state: LoadState,
+    /// Modification time of the file when it was last read, `None` until it has been.
+    modified: Option<SystemTime>,
+}
+
+struct Loaded<T: Asset> {
+    id: AssetId,
+    modified: Option<SystemTime>,
+    result: Result<T::Data, AssetError>,
+}
+
+type ReloadHook<T> = Box<dyn FnMut(AssetId, &T)>;
+
+struct Storage<T: Asset> {
+    entries: HashMap<AssetId, Entry<T>>,
+    by_path: HashMap<PathBuf, AssetId>,
+    sender: mpsc::Sender<Loaded<T>>,
+    receiver: mpsc::Receiver<Loaded<T>>,
+    reload_hooks: Vec<ReloadHook<T>>,
+}
+
+impl<T: Asset> Storage<T> {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            entries: HashMap::new(),
+            by_path: HashMap::new(),
+            sender,
+            receiver,
+            reload_hooks: Vec::new(),
+        }
+    }
+
+    fn queue_load(&self, loader: &Loader, id: AssetId, path: PathBuf) {
+        let sender = self.sender.clone();
+        loader.spawn(move |vfs| {
+            let modified = vfs.modified(&path);
+            let result = vfs
+                .read(&path)
+                .map_err(|e| AssetError::Io(path.clone(), e))
+                .and_then(|bytes| T::decode(bytes).map_err(|e| AssetError::Decode(path, e)));
+            let _ = sender.send(Loaded {
+                id,
+                modified,
+                result,
+            });
+        });
+    }
+}
+
+/// What `Assets` needs of a `Storage` without knowing its asset type.
+trait AnyStorage {
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        loader: &Loader,
+        check_files: bool,
+        events: &mut Vec<AssetEvent>,
+    );
+    fn loading(&self) -> usize;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Asset> AnyStorage for Storage<T> {
+    fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        loader: &Loader,
+        check_files: bool,
+        events: &mut Vec<AssetEvent>,
+    ) {
+        while let Ok(loaded) = self.receiver.try_recv() {
+            // dropped while loading
+            let Some(entry) = self.entries.get_mut(&loaded.id) else {
+                continue;
+            };
+            // a failed load still records the time, so fixing the file retries it
+            entry.modified = loaded.modified.or(Some(SystemTime::UNIX_EPOCH));
+            let label = entry
+                .path
+                .as_deref()
+                .map_or(String::new(), |p| p.display().to_string());
+            let created = loaded.result.and_then(|data| {
+                T::create(data, device, queue, &label)
+                    .map_err(|e| AssetError::Decode(label.clone().into(), e))
+            });
+            match created {
+                Ok(asset) => {
+                    let reloaded = entry.asset.is_some();
+                    let asset = entry.asset.insert(asset);
+                    entry.state = LoadState::Loaded;
+                    if reloaded {
+                        log::info!("reloaded {}", label);
+                        events.push(AssetEvent::Reloaded(loaded.id));
+                        for hook in &mut self.reload_hooks {
+                            hook(loaded.id, asset);
+                        }
+                    } else {
+                        events.push(AssetEvent::Loaded(loaded.id));
+                    }
+                }
+                Err(e) => {
+                    log::warn!("loading asset failed: {}", e);
+                    // a failed reload keeps the previous version
+                    if entry.asset.is_none() {
+                        entry.state = LoadState::Failed;
+                    }
+                    events.push(AssetEvent::Failed(loaded.id));
+                }
+            }
+        }
+
+        let unused: Vec<AssetId> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| Arc::strong_count(&entry.refs) == 1)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in unused {
+            if let Some(path) = self.entries.remove(&id).and_then(|e| e.path) {
+                self.by_path.remove(&path);
+            }
+        }
+
+        if check_files {
+            let mut changed = Vec::new();
+            for (&id, entry) in &mut self.entries {
+                let (Some(path), Some(modified)) = (&entry.path, entry.modified) else {
+                    continue;
+                };
+                let Some(now) = loader.vfs.modified(path) else {
+                    continue;
+                };
+                if now != modified {
+                    // not checked again until this load is done
+                    entry.modified = None;
+                    if entry.state == LoadState::Failed {
+                        entry.state = LoadState::Loading;
+                    }
+                    changed.push((id, path.clone()));
+                }
+            }
+            for (id, path) in changed {
+                self.queue_load(loader, id, path);
+            }
+        }
+    }
+
+    fn loading(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.state == LoadState::Loading)
+            .count()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub struct Assets {
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    loader: Loader,
+    next_id: usize,
+    events: Vec<AssetEvent>,
+    /// Load files again when they change. On by default in debug builds.
+    pub hot_reload: bool,
+    last_check: Option<Instant>,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::with_vfs(Self::default_vfs())
+    }
+
+    /// Starts with `vfs` to read files from instead of the working directory.
+    pub fn with_vfs(vfs: Vfs) -> Self {
+        Self {
+            storages: HashMap::new(),
+            loader: Loader::new(vfs),
+            next_id: 0,
+            events: Vec::new(),
+            hot_reload: cfg!(debug_assertions) && !cfg!(target_arch = "wasm32"),
+            last_check: None,
+        }
+    }
+
+    fn default_vfs() -> Vfs {
+        if cfg!(target_arch = "wasm32") {
+            Vfs::new()
+        } else {
+            Vfs::new().with_directory(".")
+        }
+    }
+
+    pub fn vfs(&self) -> &Vfs {
+        &self.loader.vfs
+    }
+
+    /// Changes apply to loads started afterwards.
+    pub fn vfs_mut(&mut self) -> &mut Vfs {
+        Arc::make_mut(&mut self.loader.vfs)
+    }
+
+    fn storage<T: Asset>(storages: &mut HashMap<TypeId, Box<dyn AnyStorage>>) -> &mut Storage<T> {
+        storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("storages are keyed by their asset type")
+    }
+
+    fn next_id(&mut self) -> AssetId {
+        self.next_id += 1;
+        AssetId(self.next_id - 1)
+    }
+
+    /// Starts loading `path` in the background, or returns the handle to it if it is
+    /// already loaded or loading. Failures are logged by `update`.
+    pub fn load<T: Asset>(&mut self, path: impl AsRef<Path>) -> Handle<T> {
+        let path = path.as_ref();
+        let storage = Self::storage::<T>(&mut self.storages);
+        if let Some(id) = storage.by_path.get(path) {
+            return Handle {
+                id: *id,
+                refs: storage.entries[id].refs.clone(),
+                _marker: PhantomData,
+            };
+        }
+
+        let id = self.next_id();
+        let storage = Self::storage::<T>(&mut self.storages);
+        let refs = Arc::new(());
+        storage.entries.insert(
+            id,
+            Entry {
+                path: Some(path.to_path_buf()),
+                refs: refs.clone(),
+                asset: None,
+                state: LoadState::Loading,
+                modified: None,
+            },
+        );
+        storage.by_path.insert(path.to_path_buf(), id);
+        storage.queue_load(&self.loader, id, path.to_path_buf());
+        Handle {
+            id,
+            refs,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Takes an asset made some other way, so it can be handed around like loaded ones.
+    pub fn add<T: Asset>(&mut self, asset: T) -> Handle<T> {
+        let id = self.next_id();
+        let refs = Arc::new(());
+        Self::storage::<T>(&mut self.storages).entries.insert(
+            id,
+            Entry {
+                path: None,
+                refs: refs.clone(),
+                asset: Some(asset),
+                state: LoadState::Loaded,
+                modified: None,
+            },
+        );
+        Handle {
+            id,
+            refs,
+            _marker: PhantomData,
+        }
+    }
+
+    fn entry<T: Asset>(&self, handle: &Handle<T>) -> Option<&Entry<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref::<Storage<T>>()?
+            .entries
+            .get(&handle.id)
+    }
+
+    /// `None` while the asset is still loading, or if it failed to.
+    pub fn get<T: Asset>(&self, handle: &Handle<T>) -> Option<&T> {
+        self.entry(handle)?.asset.as_ref()
+    }
+
+    pub fn load_state<T: Asset>(&self, handle: &Handle<T>) -> LoadState {
+        self.entry(handle)
+            .map_or(LoadState::NotLoaded, |entry| entry.state)
+    }
+
+    /// Number of assets of every type still loading, not counting reloads, zero once a
+    /// loading screen can go.
+    pub fn loading(&self) -> usize {
+        self.storages
+            .values()
+            .map(|storage| storage.loading())
+            .sum()
+    }
+
+    /// What finished loading, reloading or failing during the last `update`.
+    pub fn events(&self) -> &[AssetEvent] {
+        &self.events
+    }
+
+    /// Calls `hook` after an asset of type `T` has been reloaded, e.g. to rebuild bind
+    /// groups using a texture.
+    pub fn on_reload<T: Asset>(&mut self, hook: impl FnMut(AssetId, &T) + 'static) {
+        Self::storage::<T>(&mut self.storages)
+            .reload_hooks
+            .push(Box::new(hook));
+    }
+
+    /// Creates the assets that finished loading, frees those no longer used and, every
+    /// so often, looks for changed files. The run loop calls this before `App::update`.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let check_files = self.hot_reload
+            && self
+                .last_check
+                .is_none_or(|last| last.elapsed() >= RELOAD_INTERVAL);
+        if check_files {
+            self.last_check = Some(Instant::now());
+        }
+        self.events.clear();
+        for storage in self.storages.values_mut() {
+            storage.update(device, queue, &self.loader, check_files, &mut self.events);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Renders a fixed number of frames of an app and reports how long they took, for tracking
+//! performance in CI.
+//!
+//! Frame times are measured from the start of one frame to the start of the next, so they
+//! include `App::update`, encoding, submission and presentation. Offscreen, each frame also
+//! waits for the GPU to finish before the next starts, so GPU time is included too.
+
+use std::fmt;
+use std::time::Instant;
+
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::WindowBuilder;
+
+use crate::context::Context;
+use crate::window::{run_updates, step_frame, App};
+
+#[derive(Clone, Debug)]
+pub struct BenchmarkConfig {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    /// Frames rendered before timing starts, while pipelines and caches warm up.
+    pub warmup_frames: u32,
+    /// Render into a texture instead of a window.
+    pub offscreen: bool,
+    /// Wait for vsync when presenting to a window. Off by default so the frame rate
+    /// isn't capped at the refresh rate.
+    pub vsync: bool,
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            title: "Benchmark".to_string(),
+            width: 1280,
+            height: 720,
+            warmup_frames: 10,
+            offscreen: false,
+            vsync: false,
+        }
+    }
+}
+
+/// Frame times in seconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BenchmarkReport {
+    pub frames: usize,
+    pub min: f32,
+    pub average: f32,
+    /// 99th percentile, 99% of frames were at least this fast.
+    pub p99: f32,
+    pub max: f32,
+    pub total: f32,
+}
+
+impl BenchmarkReport {
+    pub fn from_frame_times(times: &[f32]) -> Self {
+        if times.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = times.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        let total: f32 = sorted.iter().sum();
+        let p99 = ((sorted.len() as f32 * 0.99).ceil() as usize).clamp(1, sorted.len()) - 1;
+        Self {
+            frames: sorted.len(),
+            min: sorted[0],
+            average: total / sorted.len() as f32,
+            p99: sorted[p99],
+            max: sorted[sorted.len() - 1],
+            total,
+        }
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fps = if self.average > 0.0 {
+            1.0 / self.average
+        } else {
+            0.0
+        };
+        write!(
+            f,
+            "{} frames in {:.2} s: min {:.2} ms, avg {:.2} ms ({:.0} fps), p99 {:.2} ms, max {:.2} ms",
+            self.frames,
+            self.total,
+            self.min * 1000.0,
+            self.average * 1000.0,
+            fps,
+            self.p99 * 1000.0,
+            self.max * 1000.0
+        )
+    }
+}
+
+/// Renders `frames` frames of the app `init` builds after the warm up, prints the report
+/// and returns it. A window can only be opened once per process, as with `run_app`.
+pub fn run_benchmark<A: App>(
+    config: BenchmarkConfig,
+    frames: u32,
+    init: impl FnOnce(&mut Context) -> A,
+) -> BenchmarkReport {
+    let _ = env_logger::try_init();
+    let times = if config.offscreen {
+        run_offscreen(&config, frames, init)
+    } else {
+        run_windowed(&config, frames, init)
+    };
+    let report = BenchmarkReport::from_frame_times(&times);
+    println!("{}: {}", config.title, report);
+    report
+}
+
+fn run_offscreen<A: App>(
+    config: &BenchmarkConfig,
+    frames: u32,
+    init: impl FnOnce(&mut Context) -> A,
+) -> Vec<f32> {
+    let mut ctx = pollster::block_on(Context::new_headless(config.width, config.height));
+    let mut app = init(&mut ctx);
+    let mut times = Vec::with_capacity(frames as usize);
+    let mut last = Instant::now();
+    let mut dt = 0.0;
+    for frame in 0..config.warmup_frames + frames {
+        step_frame(&mut ctx, crate::deterministic::timestep().unwrap_or(dt));
+        run_updates(&mut app, &mut ctx);
+        ctx.render_frame(|ctx, frame| app.render(ctx, frame))
+            .expect("offscreen frames can't fail to start");
+        ctx.device().poll(wgpu::Maintain::Wait);
+
+        let now = Instant::now();
+        dt = (now - last).as_secs_f32();
+        last = now;
+        if frame >= config.warmup_frames {
+            times.push(dt);
+        }
+    }
+    times
+}
+
+fn run_windowed<A: App>(
+    config: &BenchmarkConfig,
+    frames: u32,
+    init: impl FnOnce(&mut Context) -> A,
+) -> Vec<f32> {
+    let mut event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(&config.title)
+        .with_inner_size(winit::dpi::PhysicalSize::new(config.width, config.height))
+        .with_resizable(false)
+        .build(&event_loop)
+        .expect("Window could not be created");
+    let mut ctx = pollster::block_on(Context::new(
+        std::sync::Arc::new(window),
+        &crate::settings::GraphicsSettings::default(),
+    ));
+    if !config.vsync {
+        ctx.set_present_mode(wgpu::PresentMode::AutoNoVsync);
+    }
+    let mut app = init(&mut ctx);
+
+    let total = config.warmup_frames + frames;
+    let mut times = Vec::with_capacity(frames as usize);
+    let mut rendered = 0;
+    let mut last = Instant::now();
+    let mut dt = 0.0;
+    event_loop.run_return(|event, _, control_flow| match event {
+        Event::RedrawRequested(_) => {
+            step_frame(&mut ctx, crate::deterministic::timestep().unwrap_or(dt));
+            run_updates(&mut app, &mut ctx);
+            match ctx.render_frame(|ctx, frame| app.render(ctx, frame)) {
+                Ok(()) => {}
+                Err(wgpu::SurfaceError::Lost) => ctx.resize(ctx.size()),
+                Err(e) => {
+                    log::warn!("benchmark stopped: {:?}", e);
+                    control_flow.set_exit();
+                }
+            }
+
+            let now = Instant::now();
+            dt = (now - last).as_secs_f32();
+            last = now;
+            if rendered >= config.warmup_frames {
+                times.push(dt);
+            }
+            rendered += 1;
+            if rendered >= total {
+                control_flow.set_exit();
+            }
+        }
+        Event::MainEventsCleared => ctx.window().request_redraw(),
+        Event::WindowEvent {
+            event: WindowEvent::CloseRequested,
+            ..
+        } => control_flow.set_exit(),
+        Event::WindowEvent {
+            event: WindowEvent::Resized(size),
+            ..
+        } => ctx.resize(size),
+        _ => {}
+    });
+    times
+}
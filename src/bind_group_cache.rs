@@ -0,0 +1,173 @@
+//! Reuse of bind groups across frames, keyed by their layout and the resources bound.
+//!
+//! A renderer that binds the same texture, sampler or buffer combination frame after frame
+//! asks the cache instead of creating a bind group each time. Bind groups are handed out
+//! as shared handles; one held by a caller counts as used, and ones left unused for
+//! `max_idle_frames` are dropped, along with the references they keep to their resources.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum ResourceKey {
+    Buffer {
+        buffer: wgpu::Id<wgpu::Buffer>,
+        offset: wgpu::BufferAddress,
+        size: Option<wgpu::BufferSize>,
+    },
+    Sampler(wgpu::Id<wgpu::Sampler>),
+    TextureView(wgpu::Id<wgpu::TextureView>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct BindGroupKey {
+    layout: wgpu::Id<wgpu::BindGroupLayout>,
+    /// Array bindings add an entry per element, in order.
+    resources: Vec<(u32, ResourceKey)>,
+}
+
+impl BindGroupKey {
+    /// `None` for resources the cache doesn't know how to key.
+    fn new(layout: &wgpu::BindGroupLayout, entries: &[wgpu::BindGroupEntry]) -> Option<Self> {
+        let mut resources = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let buffer = |binding: &wgpu::BufferBinding| ResourceKey::Buffer {
+                buffer: binding.buffer.global_id(),
+                offset: binding.offset,
+                size: binding.size,
+            };
+            match &entry.resource {
+                wgpu::BindingResource::Buffer(binding) => {
+                    resources.push((entry.binding, buffer(binding)))
+                }
+                wgpu::BindingResource::BufferArray(bindings) => resources.extend(
+                    bindings
+                        .iter()
+                        .map(|binding| (entry.binding, buffer(binding))),
+                ),
+                wgpu::BindingResource::Sampler(sampler) => {
+                    resources.push((entry.binding, ResourceKey::Sampler(sampler.global_id())))
+                }
+                wgpu::BindingResource::SamplerArray(samplers) => resources.extend(
+                    samplers
+                        .iter()
+                        .map(|sampler| (entry.binding, ResourceKey::Sampler(sampler.global_id()))),
+                ),
+                wgpu::BindingResource::TextureView(view) => {
+                    resources.push((entry.binding, ResourceKey::TextureView(view.global_id())))
+                }
+                wgpu::BindingResource::TextureViewArray(views) => resources.extend(
+                    views
+                        .iter()
+                        .map(|view| (entry.binding, ResourceKey::TextureView(view.global_id()))),
+                ),
+                _ => return None,
+            }
+        }
+        Some(Self {
+            layout: layout.global_id(),
+            resources,
+        })
+    }
+}
+
+struct Entry {
+    bind_group: Arc<wgpu::BindGroup>,
+    last_used: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BindGroupCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// Bind groups the cache holds, in use or idle.
+    pub len: usize,
+}
+
+pub struct BindGroupCache {
+    entries: HashMap<BindGroupKey, Entry>,
+    frame: u64,
+    stats: BindGroupCacheStats,
+    /// Frames an unused bind group is kept around for before it is dropped.
+    pub max_idle_frames: u64,
+}
+
+impl Default for BindGroupCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BindGroupCache {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            frame: 0,
+            stats: BindGroupCacheStats::default(),
+            max_idle_frames: 3,
+        }
+    }
+
+    /// The bind group of `layout` with `entries`, created the first time they're asked
+    /// for. `label` is only used when it's created. Bind groups with resources the cache
+    /// can't key are created every time.
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::BindGroupLayout,
+        entries: &[wgpu::BindGroupEntry],
+    ) -> Arc<wgpu::BindGroup> {
+        let key = BindGroupKey::new(layout, entries);
+        if let Some(entry) = key.as_ref().and_then(|key| self.entries.get_mut(key)) {
+            entry.last_used = self.frame;
+            self.stats.hits += 1;
+            return entry.bind_group.clone();
+        }
+
+        let bind_group = Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries,
+        }));
+        self.stats.misses += 1;
+        let Some(key) = key else {
+            return bind_group;
+        };
+        self.entries.insert(
+            key,
+            Entry {
+                bind_group: bind_group.clone(),
+                last_used: self.frame,
+            },
+        );
+        self.stats.len = self.entries.len();
+        bind_group
+    }
+
+    /// Call once per frame. Bind groups still held elsewhere count as used this frame,
+    /// and ones unused for longer than `max_idle_frames` are dropped.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        let oldest = frame.saturating_sub(self.max_idle_frames);
+        self.entries.retain(|_, entry| {
+            if Arc::strong_count(&entry.bind_group) > 1 {
+                entry.last_used = frame;
+            }
+            entry.last_used >= oldest
+        });
+        self.stats.len = self.entries.len();
+        self.frame += 1;
+    }
+
+    /// Drops every bind group not held elsewhere now.
+    pub fn trim(&mut self) {
+        self.entries
+            .retain(|_, entry| Arc::strong_count(&entry.bind_group) > 1);
+        self.stats.len = self.entries.len();
+    }
+
+    pub fn stats(&self) -> BindGroupCacheStats {
+        self.stats
+    }
+}
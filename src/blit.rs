@@ -0,0 +1,301 @@
+//! Copying one texture into another of any size with a fullscreen triangle, and a chain of
+//! ever smaller copies of an image for effects that work on blurred or averaged versions
+//! of the frame.
+
+use std::collections::HashMap;
+
+use crate::bind_group_cache::BindGroupCache;
+use crate::stats::Tracked;
+
+const SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // one triangle covering the target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, in.uv);
+}
+"#;
+
+/// Draws textures stretched over others. Keeps one pipeline per target format and the bind
+/// group of each source, so reuse one for every blit and call `end_frame` once a frame.
+pub struct Blitter {
+    shader: wgpu::ShaderModule,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    linear_sampler: wgpu::Sampler,
+    nearest_sampler: wgpu::Sampler,
+    pipelines: HashMap<wgpu::TextureFormat, (wgpu::RenderPipeline, Tracked)>,
+    bind_groups: BindGroupCache,
+}
+
+impl Blitter {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Blit Shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Blit Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_sampler = |filter| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Blit Sampler"),
+                mag_filter: filter,
+                min_filter: filter,
+                ..Default::default()
+            })
+        };
+        Self {
+            shader,
+            bind_group_layout,
+            pipeline_layout,
+            linear_sampler: create_sampler(wgpu::FilterMode::Linear),
+            nearest_sampler: create_sampler(wgpu::FilterMode::Nearest),
+            pipelines: HashMap::new(),
+            bind_groups: BindGroupCache::new(),
+        }
+    }
+
+    fn create_pipeline(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        self.pipelines.entry(format).or_insert_with(|| {
+            let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Blit Pipeline"),
+                layout: Some(&self.pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(format.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+            (pipeline, Tracked::new(1, 0, 0))
+        });
+    }
+
+    /// Records drawing `source` over the whole of `target`, replacing its contents.
+    /// `target_format` is the format of the texture `target` views, and `filter` picks
+    /// between smooth and blocky scaling. `source` has to be a filterable float texture
+    /// with a single mip level in the view.
+    pub fn blit(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        target_format: wgpu::TextureFormat,
+        filter: wgpu::FilterMode,
+    ) {
+        self.create_pipeline(device, target_format);
+        let (pipeline, _) = &self.pipelines[&target_format];
+        let sampler = match filter {
+            wgpu::FilterMode::Linear => &self.linear_sampler,
+            wgpu::FilterMode::Nearest => &self.nearest_sampler,
+        };
+        let bind_group = self.bind_groups.get(
+            device,
+            "Blit Bind Group",
+            &self.bind_group_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        );
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Blit Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    /// Drops the bind groups of sources not blitted from in the last few frames.
+    pub fn end_frame(&mut self) {
+        self.bind_groups.end_frame();
+    }
+
+    /// Drops the bind groups of every source, e.g. after blitting from textures that won't
+    /// be used again.
+    pub fn trim(&mut self) {
+        self.bind_groups.trim();
+    }
+}
+
+struct Level {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    _tracked: Tracked,
+}
+
+/// Textures each half the size of the one before, starting at half the source size, that
+/// `downsample` fills with smaller and smaller copies of an image. Bloom blurs across
+/// them, auto exposure reads the average brightness from the last.
+pub struct DownsampleChain {
+    format: wgpu::TextureFormat,
+    max_levels: u32,
+    levels: Vec<Level>,
+}
+
+impl DownsampleChain {
+    /// Up to `max_levels` levels for a `width` x `height` source, fewer if they reach 1x1
+    /// first.
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        max_levels: u32,
+    ) -> Self {
+        let mut chain = Self {
+            format,
+            max_levels,
+            levels: Vec::new(),
+        };
+        chain.resize(device, width, height);
+        chain
+    }
+
+    /// Recreates the levels for a new source size, e.g. after the window was resized.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.levels.clear();
+        let (mut width, mut height) = (width, height);
+        while (self.levels.len() as u32) < self.max_levels && (width > 1 || height > 1) {
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Downsample Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let tracked = Tracked::new(0, 0, 1).with_texture(&texture);
+            self.levels.push(Level {
+                texture,
+                view,
+                _tracked: tracked,
+            });
+        }
+    }
+
+    /// Records copying `source` into the first level and every level into the next.
+    pub fn downsample(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        blitter: &mut Blitter,
+        source: &wgpu::TextureView,
+    ) {
+        encoder.push_debug_group("Downsample");
+        let mut source = source;
+        for level in &self.levels {
+            blitter.blit(
+                device,
+                encoder,
+                source,
+                &level.view,
+                self.format,
+                wgpu::FilterMode::Linear,
+            );
+            source = &level.view;
+        }
+        encoder.pop_debug_group();
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    pub fn texture(&self, level: usize) -> &wgpu::Texture {
+        &self.levels[level].texture
+    }
+
+    pub fn view(&self, level: usize) -> &wgpu::TextureView {
+        &self.levels[level].view
+    }
+
+    /// The smallest level, `None` if the source was already 1x1 or `max_levels` was 0.
+    pub fn last(&self) -> Option<&wgpu::TextureView> {
+        self.levels.last().map(|level| &level.view)
+    }
+}
@@ -0,0 +1,311 @@
+//! Bounding volumes: boxes and spheres enclosing a set of points, for culling, picking
+//! and framing objects with the camera.
+
+use crate::camera::Camera;
+use crate::jobs;
+use crate::math::{Mat4, Vec3, Vec4};
+use crate::ray::Ray;
+
+/// Fewest boxes `Frustum::cull` hands to a worker at once.
+const CULL_CHUNK: usize = 1024;
+
+/// An axis aligned box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// `None` for no points.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Self::new(first, first), |aabb, p| {
+            Self::new(aabb.min.min(p), aabb.max.max(p))
+        }))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Distance from the center to the faces along each axis.
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        (0..3).all(|axis| point[axis] >= self.min[axis] && point[axis] <= self.max[axis])
+    }
+
+    /// The axis aligned box around this one after `transform`, which is larger than the
+    /// box itself when there's rotation.
+    pub fn transform(&self, transform: &Mat4) -> Aabb {
+        let center = transform.transform_point3(self.center());
+        let half = self.half_extents();
+        let extents = transform.col(0).truncate().abs() * half.x
+            + transform.col(1).truncate().abs() * half.y
+            + transform.col(2).truncate().abs() * half.z;
+        Self::new(center - extents, center + extents)
+    }
+
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        ray.intersect_aabb(self.min, self.max)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// Ritter's approximation, at most a few percent larger than the smallest sphere.
+    /// `None` for no points.
+    pub fn from_points(points: &[Vec3]) -> Option<Self> {
+        let first = *points.first()?;
+        let farthest = |from: Vec3| {
+            points
+                .iter()
+                .copied()
+                .max_by(|a, b| a.distance(from).total_cmp(&b.distance(from)))
+                .unwrap_or(from)
+        };
+        // two points far apart as the first guess at a diameter
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut sphere = Self::new((a + b) * 0.5, a.distance(b) * 0.5);
+        for &p in points {
+            let distance = p.distance(sphere.center);
+            if distance > sphere.radius {
+                // grow just enough to take the point in, keeping the far side in place
+                let radius = (sphere.radius + distance) * 0.5;
+                sphere.center += (p - sphere.center) * ((radius - sphere.radius) / distance);
+                sphere.radius = radius;
+            }
+        }
+        Some(sphere)
+    }
+
+    /// The sphere after `transform`, scaled by its largest axis scale.
+    pub fn transform(&self, transform: &Mat4) -> BoundingSphere {
+        let scale = (0..3)
+            .map(|i| transform.col(i).truncate().length())
+            .fold(0.0, f32::max);
+        Self::new(transform.transform_point3(self.center), self.radius * scale)
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.distance(self.center) <= self.radius
+    }
+
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        ray.intersect_sphere(self.center, self.radius)
+    }
+}
+
+/// The planes enclosing what a camera sees, for culling what's outside.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    /// Normals in xyz pointing inwards, with the distance along them in w.
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// The frustum of a view-projection matrix, in world space, for either depth mode.
+    pub fn from_view_proj(view_proj: &Mat4) -> Self {
+        let (x, y, z, w) = (
+            view_proj.row(0),
+            view_proj.row(1),
+            view_proj.row(2),
+            view_proj.row(3),
+        );
+        // wgpu's clip space has depth from 0 to w, so near and far are z and w - z
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.truncate().length();
+            // an infinite far plane has no normal and takes everything in
+            if length > 0.0 {
+                plane * (1.0 / length)
+            } else {
+                plane
+            }
+        });
+        Self { planes }
+    }
+
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self::from_view_proj(&camera.view_proj())
+    }
+
+    /// Whether any of the box is inside. Boxes near a corner may pass without being
+    /// inside, which is only ever a wasted draw.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let corner = Vec3::new(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+
+    /// Which of `boxes` are at least partly inside, in the same order. Long lists are split
+    /// between the job pool's workers.
+    pub fn cull(&self, boxes: &[Aabb]) -> Vec<bool> {
+        let mut visible = vec![false; boxes.len()];
+        jobs::for_each_chunk(&mut visible, CULL_CHUNK, |start, chunk| {
+            for (visible, aabb) in chunk.iter_mut().zip(&boxes[start..]) {
+                *visible = self.intersects_aabb(aabb);
+            }
+        });
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Quat;
+
+    fn corners() -> Vec<Vec3> {
+        (0..8)
+            .map(|i| {
+                Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32) * 2.0 - Vec3::ONE
+            })
+            .collect()
+    }
+
+    #[test]
+    fn boxes_enclose_their_points() {
+        assert_eq!(Aabb::from_points([]), None);
+        let aabb =
+            Aabb::from_points([Vec3::new(1.0, -2.0, 0.0), Vec3::new(-1.0, 2.0, 4.0)]).unwrap();
+        assert_eq!(
+            aabb,
+            Aabb::new(Vec3::new(-1.0, -2.0, 0.0), Vec3::new(1.0, 2.0, 4.0))
+        );
+        assert_eq!(aabb.center(), Vec3::new(0.0, 0.0, 2.0));
+        assert_eq!(aabb.half_extents(), Vec3::new(1.0, 2.0, 2.0));
+        assert!(aabb.contains(Vec3::new(1.0, 0.0, 4.0)));
+        assert!(!aabb.contains(Vec3::new(0.0, 0.0, 5.0)));
+        let other = Aabb::new(Vec3::splat(3.0), Vec3::splat(4.0));
+        assert_eq!(aabb.union(&other).max, Vec3::new(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn transformed_boxes_grow_with_rotation() {
+        let aabb = Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0));
+        let moved = aabb.transform(&Mat4::translation(Vec3::new(5.0, 0.0, 0.0)));
+        assert_eq!(moved.center(), Vec3::new(5.0, 0.0, 0.0));
+        assert_eq!(moved.size(), Vec3::splat(2.0));
+        let turned = aabb.transform(&Mat4::from_quat(Quat::from_axis_angle(
+            Vec3::Y,
+            std::f32::consts::FRAC_PI_4,
+        )));
+        let diagonal = 2.0f32.sqrt();
+        assert!((turned.max.x - diagonal).abs() < 1e-5);
+        assert!((turned.max.y - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn spheres_enclose_their_points() {
+        assert_eq!(BoundingSphere::from_points(&[]), None);
+        let points = corners();
+        let sphere = BoundingSphere::from_points(&points).unwrap();
+        for &p in &points {
+            assert!(p.distance(sphere.center) <= sphere.radius + 1e-4);
+        }
+        // Ritter's sphere is close to the smallest one, of radius sqrt(3)
+        assert!(sphere.radius < 3f32.sqrt() * 1.05);
+
+        let scaled = sphere.transform(&Mat4::scale(Vec3::new(1.0, 3.0, 2.0)));
+        assert!((scaled.radius - sphere.radius * 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn volumes_are_hit_by_rays() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), -Vec3::Z);
+        let aabb = Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0));
+        assert_eq!(aabb.intersect_ray(&ray), Some(4.0));
+        let sphere = BoundingSphere::new(Vec3::ZERO, 2.0);
+        assert_eq!(sphere.intersect_ray(&ray), Some(3.0));
+        assert!(sphere.contains(Vec3::new(0.0, 2.0, 0.0)));
+    }
+
+    #[test]
+    fn frusta_cull_what_is_outside() {
+        // looking down -z from the origin
+        let view = Mat4::look_at_rh(Vec3::ZERO, -Vec3::Z, Vec3::Y);
+        let fovy = 90f32.to_radians();
+        let frusta = [
+            Mat4::perspective_rh(fovy, 1.0, 0.1, 100.0),
+            Mat4::perspective_infinite_reverse_rh(fovy, 1.0, 0.1),
+        ]
+        .map(|projection| Frustum::from_view_proj(&(projection * view)));
+        let at = |x: f32, z: f32| {
+            Aabb::new(
+                Vec3::new(x, 0.0, z),
+                Vec3::new(x, 0.0, z) + Vec3::splat(0.5),
+            )
+        };
+        for (frustum, far_visible) in frusta.iter().zip([false, true]) {
+            assert!(frustum.intersects_aabb(&at(0.0, -10.0)));
+            assert!(!frustum.intersects_aabb(&at(0.0, 10.0)));
+            assert!(!frustum.intersects_aabb(&at(-20.0, -10.0)));
+            assert_eq!(frustum.intersects_aabb(&at(0.0, -500.0)), far_visible);
+            assert!(
+                frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(-10.5, 0.0, -10.0), 1.0))
+            );
+            assert!(!frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(0.0, 0.0, 2.0), 1.0)));
+        }
+
+        // enough boxes to be split between workers
+        let boxes: Vec<Aabb> = (0..3000)
+            .map(|i| at(0.0, if i % 3 == 0 { 10.0 } else { -10.0 }))
+            .collect();
+        let visible = frusta[0].cull(&boxes);
+        assert_eq!(visible.len(), boxes.len());
+        assert!(visible
+            .iter()
+            .enumerate()
+            .all(|(i, &visible)| visible == (i % 3 != 0)));
+    }
+}
@@ -0,0 +1,89 @@
+//! Pre-recorded draw commands for static scene content.
+//!
+//! Recording a bundle does the validation and encoding work for its draws once, after which
+//! replaying it into a pass each frame costs about as much as a single draw call. Anything
+//! the bundle draws with is captured as it was recorded: rebuild it when buffers or bind
+//! groups are replaced, or when the target formats change.
+
+/// Attachments a bundle is recorded for. Every pass replaying it must match them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BundleTargets {
+    pub color_format: wgpu::TextureFormat,
+    pub depth_format: Option<wgpu::TextureFormat>,
+    pub sample_count: u32,
+}
+
+impl BundleTargets {
+    pub fn new(color_format: wgpu::TextureFormat) -> Self {
+        Self {
+            color_format,
+            depth_format: None,
+            sample_count: 1,
+        }
+    }
+
+    pub fn with_depth(mut self, format: wgpu::TextureFormat) -> Self {
+        self.depth_format = Some(format);
+        self
+    }
+
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+}
+
+pub struct RenderBundle {
+    bundle: wgpu::RenderBundle,
+    targets: BundleTargets,
+}
+
+impl RenderBundle {
+    /// Records the commands `record` issues. It sees a `wgpu::RenderBundleEncoder`, which
+    /// takes the same calls as a render pass, except for viewports, scissors and blend
+    /// constants that are left to the pass replaying the bundle.
+    pub fn record<'a>(
+        device: &'a wgpu::Device,
+        label: &str,
+        targets: BundleTargets,
+        record: impl FnOnce(&mut wgpu::RenderBundleEncoder<'a>),
+    ) -> Self {
+        let mut encoder =
+            device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                label: Some(label),
+                color_formats: &[Some(targets.color_format)],
+                depth_stencil: targets
+                    .depth_format
+                    .map(|format| wgpu::RenderBundleDepthStencil {
+                        format,
+                        depth_read_only: false,
+                        stencil_read_only: false,
+                    }),
+                sample_count: targets.sample_count,
+                multiview: None,
+            });
+        record(&mut encoder);
+        let bundle = encoder.finish(&wgpu::RenderBundleDescriptor { label: Some(label) });
+        Self { bundle, targets }
+    }
+
+    pub fn targets(&self) -> BundleTargets {
+        self.targets
+    }
+
+    /// Whether the bundle can be replayed into a pass with these attachments, re-record it
+    /// when not.
+    pub fn is_compatible(&self, targets: &BundleTargets) -> bool {
+        self.targets == *targets
+    }
+
+    /// Leaves the pass with no pipeline, bind groups or buffers set, as bundles always do.
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.execute_bundles(std::iter::once(&self.bundle));
+    }
+
+    /// Replays several bundles in order with a single call.
+    pub fn render_all<'a>(bundles: &'a [RenderBundle], render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.execute_bundles(bundles.iter().map(|bundle| &bundle.bundle));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::depth;
+use crate::math::{Mat4, Quat, Vec2, Vec3, Vec4};
+use crate::ray::Ray;
+
+/// Perspective camera looking from `eye` at `target`.
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub aspect: f32,
+    /// Vertical field of view in radians.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            eye: Vec3::new(0.0, 1.0, 3.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            aspect: 16.0 / 9.0,
+            fovy: 45f32.to_radians(),
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// Reversed and without a far plane in `DepthMode::Reversed`, see `depth`.
+    pub fn projection(&self) -> Mat4 {
+        if depth::mode().is_reversed() {
+            Mat4::perspective_infinite_reverse_rh(self.fovy, self.aspect, self.znear)
+        } else {
+            Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar)
+        }
+    }
+
+    pub fn view_proj(&self) -> Mat4 {
+        self.projection() * self.view()
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        (self.target - self.eye).normalize()
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.forward().cross(self.up).normalize()
+    }
+
+    /// Up vector orthogonal to the view direction (unlike `up`, which is just a hint).
+    pub fn true_up(&self) -> Vec3 {
+        self.right().cross(self.forward())
+    }
+
+    pub fn set_aspect(&mut self, width: u32, height: u32) {
+        if height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    /// Ray from the near plane through a point on screen, in pixels from the top left of a
+    /// `viewport` sized view.
+    pub fn screen_to_ray(&self, position: Vec2, viewport: Vec2) -> Ray {
+        let ndc = Vec2::new(
+            position.x / viewport.x * 2.0 - 1.0,
+            1.0 - position.y / viewport.y * 2.0,
+        );
+        let inverse = self.view_proj().inverse();
+        let unproject = |z: f32| {
+            let p = inverse.mul_vec4(Vec4::new(ndc.x, ndc.y, z, 1.0));
+            p.truncate() / p.w
+        };
+        let near = unproject(0.0);
+        Ray::new(near, unproject(1.0) - near)
+    }
+
+    /// Pixel position of a world point in a `viewport` sized view, `None` behind the camera.
+    pub fn world_to_screen(&self, point: Vec3, viewport: Vec2) -> Option<Vec2> {
+        let clip = self.view_proj().mul_vec4(point.extend(1.0));
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(Vec2::new(
+            (ndc.x + 1.0) * 0.5 * viewport.x,
+            (1.0 - ndc.y) * 0.5 * viewport.y,
+        ))
+    }
+}
+
+/// Orthographic camera for 2D scenes, y down, with `position` at the centre of the view.
+///
+/// At zoom 1 a world unit is one logical pixel, so content keeps its size across DPI scale
+/// factors. With a `virtual_size` the camera instead shows exactly that many world units,
+/// scaled to fit the window and letterboxed to keep their aspect ratio.
+#[derive(Copy, Clone, Debug)]
+pub struct Camera2D {
+    pub position: Vec2,
+    pub zoom: f32,
+    /// Radians, the world appears turned the opposite way.
+    pub rotation: f32,
+    pub virtual_size: Option<Vec2>,
+    /// Window size in physical pixels.
+    window: Vec2,
+    scale_factor: f32,
+}
+
+impl Camera2D {
+    /// `width` and `height` in physical pixels, as given by `Context::size`.
+    pub fn new(width: u32, height: u32, scale_factor: f64) -> Self {
+        Self {
+            position: Vec2::ZERO,
+            zoom: 1.0,
+            rotation: 0.0,
+            virtual_size: None,
+            window: Vec2::new(width as f32, height as f32),
+            scale_factor: scale_factor as f32,
+        }
+    }
+
+    pub fn with_virtual_size(mut self, size: Vec2) -> Self {
+        self.virtual_size = Some(size);
+        self
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32, scale_factor: f64) {
+        self.window = Vec2::new(width as f32, height as f32);
+        self.scale_factor = scale_factor as f32;
+    }
+
+    /// Physical pixels per world unit.
+    pub fn pixels_per_unit(&self) -> f32 {
+        let base = match self.virtual_size {
+            Some(size) => (self.window.x / size.x).min(self.window.y / size.y),
+            None => self.scale_factor,
+        };
+        base * self.zoom
+    }
+
+    /// Top left corner and size in physical pixels of the part of the window the camera
+    /// draws to, the whole window unless letterboxed.
+    pub fn viewport_rect(&self) -> (Vec2, Vec2) {
+        match self.virtual_size {
+            Some(size) => {
+                let size = size * (self.window.x / size.x).min(self.window.y / size.y);
+                ((self.window - size) * 0.5, size)
+            }
+            None => (Vec2::ZERO, self.window),
+        }
+    }
+
+    /// Restricts drawing to `viewport_rect`, which `view_proj` maps the visible world to.
+    pub fn set_viewport(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        let (min, size) = self.viewport_rect();
+        render_pass.set_viewport(min.x, min.y, size.x, size.y, 0.0, 1.0);
+    }
+
+    pub fn view_proj(&self) -> Mat4 {
+        let (_, size) = self.viewport_rect();
+        let half = size * 0.5 / self.pixels_per_unit();
+        let projection = Mat4::orthographic_rh(-half.x, half.x, half.y, -half.y, -1.0, 1.0);
+        let view = Mat4::from_quat(Quat::from_axis_angle(Vec3::Z, -self.rotation))
+            * Mat4::translation(-self.position.extend(0.0));
+        projection * view
+    }
+
+    /// World position under a point in physical pixels, such as a cursor position.
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        let (min, size) = self.viewport_rect();
+        let offset = (screen - (min + size * 0.5)) / self.pixels_per_unit();
+        self.position + offset.rotate(self.rotation)
+    }
+
+    pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+        let (min, size) = self.viewport_rect();
+        min + size * 0.5 + (world - self.position).rotate(-self.rotation) * self.pixels_per_unit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.distance(b) < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn screen_rays_go_through_the_points_they_came_from() {
+        let camera = Camera::default();
+        let viewport = Vec2::new(1600.0, 900.0);
+        let ray = camera.screen_to_ray(viewport * 0.5, viewport);
+        assert_near(ray.direction, camera.forward());
+
+        let point = Vec3::new(0.5, 0.25, -1.0);
+        let screen = camera.world_to_screen(point, viewport).unwrap();
+        let ray = camera.screen_to_ray(screen, viewport);
+        let t = (point - ray.origin).dot(ray.direction);
+        assert_near(ray.at(t), point);
+        assert_eq!(
+            camera.world_to_screen(Vec3::new(0.0, 1.0, 5.0), viewport),
+            None
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Controllers that move cameras in response to input.
+
+use winit::event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+
+use crate::camera::{Camera, Camera2D};
+use crate::math::{Vec2, Vec3};
+use crate::random::Rng;
+
+/// Model viewer controls orbiting `target`: left drag rotates, middle drag pans and the
+/// scroll wheel zooms. Feed it window events with `input`, then `apply` it to a camera.
+#[derive(Copy, Clone, Debug)]
+pub struct OrbitCameraController {
+    pub target: Vec3,
+    /// Radians around the y axis, 0 looks down -z.
+    pub yaw: f32,
+    /// Radians above the horizon.
+    pub pitch: f32,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Radians per pixel dragged.
+    pub rotate_speed: f32,
+    /// Fraction of the distance panned per pixel dragged.
+    pub pan_speed: f32,
+    /// Fraction of the distance moved per scroll line.
+    pub zoom_speed: f32,
+    rotating: bool,
+    panning: bool,
+    cursor: Option<(f64, f64)>,
+    /// Vertical field of view of the last camera applied to, for `focus_on_bounds`.
+    fovy: f32,
+}
+
+impl OrbitCameraController {
+    pub fn new(target: Vec3, distance: f32) -> Self {
+        Self {
+            target,
+            yaw: 0.0,
+            pitch: 0.3,
+            distance,
+            min_distance: 0.01,
+            max_distance: f32::INFINITY,
+            rotate_speed: 0.005,
+            pan_speed: 0.0015,
+            zoom_speed: 0.1,
+            rotating: false,
+            panning: false,
+            cursor: None,
+            fovy: 45f32.to_radians(),
+        }
+    }
+
+    /// Picks up the orbit that puts the camera where it already is.
+    pub fn from_camera(camera: &Camera) -> Self {
+        let offset = camera.eye - camera.target;
+        let distance = offset.length();
+        let mut controller = Self::new(camera.target, distance);
+        if distance > 0.0 {
+            controller.yaw = offset.x.atan2(offset.z);
+            controller.pitch = (offset.y / distance).clamp(-1.0, 1.0).asin();
+        }
+        controller.fovy = camera.fovy;
+        controller
+    }
+
+    /// Centres the orbit on a box and backs off far enough to see all of it.
+    pub fn focus_on_bounds(&mut self, min: Vec3, max: Vec3) {
+        self.target = (min + max) * 0.5;
+        let radius = (max - min).length() * 0.5;
+        self.distance =
+            (radius / (self.fovy * 0.5).sin()).clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Unit vector from the target towards the camera.
+    fn direction(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Vec3::new(sin_yaw * cos_pitch, sin_pitch, cos_yaw * cos_pitch)
+    }
+
+    /// Returns `true` if the event drove the camera.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.rotating = pressed,
+                    MouseButton::Middle => self.panning = pressed,
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let (x, y) = (position.x, position.y);
+                let Some((last_x, last_y)) = self.cursor.replace((x, y)) else {
+                    return false;
+                };
+                let (dx, dy) = ((x - last_x) as f32, (y - last_y) as f32);
+                if self.rotating {
+                    self.yaw -= dx * self.rotate_speed;
+                    // stop just short of the poles, where the up vector flips
+                    let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+                    self.pitch = (self.pitch + dy * self.rotate_speed).clamp(-limit, limit);
+                    true
+                } else if self.panning {
+                    let forward = -self.direction();
+                    let right = forward.cross(Vec3::Y).normalize();
+                    let up = right.cross(forward);
+                    let scale = self.distance * self.pan_speed;
+                    self.target += (up * dy - right * dx) * scale;
+                    true
+                } else {
+                    false
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                false
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+                self.distance = (self.distance * (1.0 - self.zoom_speed).powf(lines))
+                    .clamp(self.min_distance, self.max_distance);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves the camera onto the orbit, looking at the target with y up.
+    pub fn apply(&mut self, camera: &mut Camera) {
+        self.fovy = camera.fovy;
+        camera.target = self.target;
+        camera.eye = self.target + self.direction() * self.distance;
+        camera.up = Vec3::Y;
+    }
+}
+
+/// How a follow camera closes the gap to where it wants to be.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Smoothing {
+    /// Snap straight there.
+    None,
+    /// Exponential approach that closes about two thirds of the gap every `1 / rate`
+    /// seconds, whatever the frame rate.
+    Lerp { rate: f32 },
+    /// Damped spring, overshoots when `damping` is below `2 * stiffness.sqrt()`.
+    Spring { stiffness: f32, damping: f32 },
+}
+
+/// Longest step the spring is integrated over at once, so long frames stay stable.
+const SPRING_STEP: f32 = 1.0 / 120.0;
+
+/// Follows a moving target with a 2D camera. The camera only moves once the target leaves
+/// the `deadzone` around the centre of the view, then eases after it with `smoothing`, and
+/// never shows anything outside `bounds`. Screen shake is driven by trauma: `add_trauma` on
+/// impacts, and the shake grows with its square and decays over time.
+#[derive(Clone, Debug)]
+pub struct FollowCameraController {
+    pub target: Vec2,
+    pub smoothing: Smoothing,
+    /// Half size in world units of the box around the view centre the target may move in
+    /// without the camera following.
+    pub deadzone: Vec2,
+    /// World space min and max corners the view stays inside, ignoring camera rotation.
+    pub bounds: Option<(Vec2, Vec2)>,
+    /// Offset in world units at full trauma.
+    pub max_shake_offset: Vec2,
+    /// Radians at full trauma.
+    pub max_shake_angle: f32,
+    /// Trauma lost per second.
+    pub trauma_decay: f32,
+    /// How fast the shake wobbles, in cycles per second.
+    pub shake_frequency: f32,
+    trauma: f32,
+    /// Camera centre before shake is added.
+    focus: Vec2,
+    velocity: Vec2,
+    /// Rotation the last shake added to the camera, taken off again before the next.
+    shake_angle: f32,
+    time: f32,
+    seed: u64,
+}
+
+impl FollowCameraController {
+    pub fn new(target: Vec2) -> Self {
+        Self {
+            target,
+            smoothing: Smoothing::Lerp { rate: 5.0 },
+            deadzone: Vec2::ZERO,
+            bounds: None,
+            max_shake_offset: Vec2::splat(16.0),
+            max_shake_angle: 0.05,
+            trauma_decay: 1.0,
+            shake_frequency: 15.0,
+            trauma: 0.0,
+            focus: target,
+            velocity: Vec2::ZERO,
+            shake_angle: 0.0,
+            time: 0.0,
+            seed: Rng::from_time().next_u64(),
+        }
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    pub fn with_deadzone(mut self, deadzone: Vec2) -> Self {
+        self.deadzone = deadzone;
+        self
+    }
+
+    pub fn with_bounds(mut self, min: Vec2, max: Vec2) -> Self {
+        self.bounds = Some((min, max));
+        self
+    }
+
+    /// Seeds the shake pattern, for reproducible runs.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Jumps straight to the target, e.g. after a level change.
+    pub fn snap(&mut self) {
+        self.focus = self.target;
+        self.velocity = Vec2::ZERO;
+    }
+
+    /// Adds to the trauma, which is kept within 0..=1.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Where the camera is centred before shake.
+    pub fn focus(&self) -> Vec2 {
+        self.focus
+    }
+
+    /// Advances by `dt` seconds and moves `camera`, which also sizes the view for `bounds`.
+    pub fn update(&mut self, camera: &mut Camera2D, dt: f32) {
+        // where the focus has to be for the target to sit on the deadzone's edge
+        let offset = self.target - self.focus;
+        let goal = Vec2::new(
+            self.target.x - offset.x.clamp(-self.deadzone.x, self.deadzone.x),
+            self.target.y - offset.y.clamp(-self.deadzone.y, self.deadzone.y),
+        );
+
+        match self.smoothing {
+            Smoothing::None => self.focus = goal,
+            Smoothing::Lerp { rate } => {
+                self.focus += (goal - self.focus) * (1.0 - (-rate * dt).exp());
+            }
+            Smoothing::Spring { stiffness, damping } => {
+                let mut remaining = dt;
+                while remaining > 0.0 {
+                    let step = remaining.min(SPRING_STEP);
+                    let acceleration = (goal - self.focus) * stiffness - self.velocity * damping;
+                    self.velocity += acceleration * step;
+                    self.focus += self.velocity * step;
+                    remaining -= step;
+                }
+            }
+        }
+
+        if let Some((min, max)) = self.bounds {
+            let (_, size) = camera.viewport_rect();
+            let half = size * 0.5 / camera.pixels_per_unit();
+            let clamp_axis = |value: f32, min: f32, max: f32, half: f32| {
+                if max - min <= half * 2.0 {
+                    // the view is larger than the bounds, keep them centred
+                    (min + max) * 0.5
+                } else {
+                    value.clamp(min + half, max - half)
+                }
+            };
+            let clamped = Vec2::new(
+                clamp_axis(self.focus.x, min.x, max.x, half.x),
+                clamp_axis(self.focus.y, min.y, max.y, half.y),
+            );
+            // drop the velocity pushing into the edge so the spring doesn't wind up against it
+            if clamped.x != self.focus.x {
+                self.velocity.x = 0.0;
+            }
+            if clamped.y != self.focus.y {
+                self.velocity.y = 0.0;
+            }
+            self.focus = clamped;
+        }
+
+        self.time += dt;
+        self.trauma = (self.trauma - self.trauma_decay * dt).max(0.0);
+        let shake = self.trauma * self.trauma;
+        let t = self.time * self.shake_frequency;
+        let offset = Vec2::new(
+            self.max_shake_offset.x * shake * noise(self.seed, t),
+            self.max_shake_offset.y * shake * noise(self.seed.wrapping_add(1), t),
+        );
+        let angle = self.max_shake_angle * shake * noise(self.seed.wrapping_add(2), t);
+
+        camera.position = self.focus + offset;
+        camera.rotation += angle - self.shake_angle;
+        self.shake_angle = angle;
+    }
+}
+
+/// Smooth value noise in -1..1, with a new random value at every whole `t`.
+fn noise(seed: u64, t: f32) -> f32 {
+    let cell = t.floor();
+    let lattice = |i: f32| {
+        Rng::new(seed ^ (i as i64 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)).next_f32() * 2.0
+            - 1.0
+    };
+    let f = t - cell;
+    let f = f * f * (3.0 - 2.0 * f);
+    let a = lattice(cell);
+    a + (lattice(cell + 1.0) - a) * f
+}
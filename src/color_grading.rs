@@ -0,0 +1,398 @@
+//! Color grading through a 3D lookup table, as a `PostEffect`.
+//!
+//! A LUT maps every color to a graded one. They're loaded from `.cube` files or from strip
+//! PNGs, the blue slices of the cube side by side from left to right. Artists grade a
+//! screenshot with `Lut::neutral(..).to_strip()` pasted into it in their image editor,
+//! then cut the strip back out as the game's LUT. Lookups are made with sRGB encoded
+//! colors, the space the strip is edited in.
+
+use std::path::Path;
+
+use crate::post::{PostEffect, PostFrame, PostPipeline, PostTarget};
+use crate::stats::Tracked;
+
+const SHADER: &str = r#"
+struct Params {
+    intensity: f32,
+    size: f32,
+    // 1 when the source holds linear colors that are encoded before the lookup
+    linear: u32,
+};
+
+@group(1) @binding(0) var lut: texture_3d<f32>;
+@group(1) @binding(1) var lut_sampler: sampler;
+
+fn srgb_encode(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn srgb_decode(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    var encoded = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    if params.linear != 0u {
+        encoded = srgb_encode(encoded);
+    }
+    // sample the texel centres, the first and last texels are black and white
+    let coordinates = encoded * ((params.size - 1.0) / params.size) + 0.5 / params.size;
+    var graded = textureSampleLevel(lut, lut_sampler, coordinates, 0.0).rgb;
+    if params.linear != 0u {
+        graded = srgb_decode(graded);
+    }
+    return vec4<f32>(mix(color.rgb, graded, params.intensity), color.a);
+}
+"#;
+
+#[derive(Debug)]
+pub enum LutError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    /// A strip that isn't `size * size` wide and `size` high.
+    StripSize {
+        width: u32,
+        height: u32,
+    },
+    /// A `.cube` file that couldn't be parsed, at a 1 based line.
+    Cube {
+        line: usize,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for LutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LutError::Io(e) => write!(f, "failed to read LUT: {}", e),
+            LutError::Image(e) => write!(f, "failed to decode LUT strip: {}", e),
+            LutError::StripSize { width, height } => write!(
+                f,
+                "a {}x{} LUT strip should be {} pixels wide",
+                width,
+                height,
+                height * height
+            ),
+            LutError::Cube { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for LutError {}
+
+/// A `size` cubed table of colors, red varying fastest, then green, then blue.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    size: u32,
+    data: Vec<[u8; 4]>,
+}
+
+impl Lut {
+    /// The table that leaves colors as they are. 16 or 32 is the usual size.
+    pub fn neutral(size: u32) -> Self {
+        let size = size.max(2);
+        let level = |i: u32| (i as f32 / (size - 1) as f32 * 255.0).round() as u8;
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push([level(r), level(g), level(b), 255]);
+                }
+            }
+        }
+        Self { size, data }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// The color `(r, g, b)` grades to, each index below `size`.
+    pub fn get(&self, r: u32, g: u32, b: u32) -> [u8; 4] {
+        self.data[(r + g * self.size + b * self.size * self.size) as usize]
+    }
+
+    /// From a strip `size * size` pixels wide and `size` high, blue slice `b` covering
+    /// columns from `b * size`.
+    pub fn from_strip(image: &image::RgbaImage) -> Result<Self, LutError> {
+        let (width, height) = image.dimensions();
+        if height < 2 || width != height * height {
+            return Err(LutError::StripSize { width, height });
+        }
+        let size = height;
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let [red, green, blue, _] = image.get_pixel(b * size + r, g).0;
+                    data.push([red, green, blue, 255]);
+                }
+            }
+        }
+        Ok(Self { size, data })
+    }
+
+    /// The table as a strip, see `from_strip`.
+    pub fn to_strip(&self) -> image::RgbaImage {
+        let size = self.size;
+        image::RgbaImage::from_fn(size * size, size, |x, y| {
+            image::Rgba(self.get(x % size, y, x / size))
+        })
+    }
+
+    /// Parses a `.cube` file with a `LUT_3D_SIZE`. Values are clamped to 0..1, and
+    /// `DOMAIN_MIN` and `DOMAIN_MAX` other than 0 and 1 are not supported.
+    pub fn from_cube(source: &str) -> Result<Self, LutError> {
+        let mut size = None;
+        let mut data = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let error = |message: String| LutError::Cube {
+                line: index + 1,
+                message,
+            };
+            let mut words = line.split_whitespace();
+            let Some(first) = words.next() else {
+                continue;
+            };
+            match first {
+                _ if first.starts_with('#') => {}
+                "TITLE" | "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {}
+                "LUT_1D_SIZE" => return Err(error("1D LUTs are not supported".to_string())),
+                "LUT_3D_SIZE" => {
+                    let value = words.next().and_then(|word| word.parse::<u32>().ok());
+                    match value {
+                        Some(value) if (2..=256).contains(&value) => size = Some(value),
+                        _ => return Err(error(format!("invalid size in '{}'", line.trim()))),
+                    }
+                }
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let expected = if first == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                    let values: Vec<f32> = words.filter_map(|word| word.parse().ok()).collect();
+                    if values.len() != 3 || values.iter().any(|&value| value != expected) {
+                        return Err(error(format!("unsupported '{}'", line.trim())));
+                    }
+                }
+                _ => {
+                    let values: Vec<f32> = line
+                        .split_whitespace()
+                        .map(|word| word.parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| error(format!("expected a color, got '{}'", line.trim())))?;
+                    let [r, g, b] = values[..] else {
+                        return Err(error(format!("expected 3 values, got {}", values.len())));
+                    };
+                    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    data.push([channel(r), channel(g), channel(b), 255]);
+                }
+            }
+        }
+        let Some(size) = size else {
+            return Err(LutError::Cube {
+                line: source.lines().count(),
+                message: "missing LUT_3D_SIZE".to_string(),
+            });
+        };
+        if data.len() != (size * size * size) as usize {
+            return Err(LutError::Cube {
+                line: source.lines().count(),
+                message: format!("expected {} colors, got {}", size * size * size, data.len()),
+            });
+        }
+        Ok(Self { size, data })
+    }
+
+    /// Reads a `.cube` file, or a strip image of any other extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LutError> {
+        let path = path.as_ref();
+        let is_cube = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("cube"));
+        if is_cube {
+            let source = std::fs::read_to_string(path).map_err(LutError::Io)?;
+            Self::from_cube(&source)
+        } else {
+            let image = image::open(path).map_err(LutError::Image)?;
+            Self::from_strip(&image.to_rgba8())
+        }
+    }
+
+    /// Writes the table as a strip PNG, e.g. a neutral one to grade.
+    pub fn save_strip(&self, path: impl AsRef<Path>) -> Result<(), LutError> {
+        self.to_strip().save(path).map_err(LutError::Image)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Params {
+    intensity: f32,
+    size: f32,
+    linear: u32,
+    _padding: u32,
+}
+unsafe impl bytemuck::Pod for Params {}
+unsafe impl bytemuck::Zeroable for Params {}
+
+/// Grades the frame with a `Lut`.
+pub struct ColorGrading {
+    /// How much of the graded color replaces the original, from 0 to 1.
+    pub intensity: f32,
+    pass: PostPipeline,
+    lut_layout: wgpu::BindGroupLayout,
+    lut_bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    size: u32,
+    _tracked: Tracked,
+}
+
+impl ColorGrading {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, lut: &Lut) -> Self {
+        let lut_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Color Grading LUT Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Color Grading LUT Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let pass = PostPipeline::new(
+            device,
+            "Color Grading",
+            SHADER,
+            std::mem::size_of::<Params>() as u64,
+            &[&lut_layout],
+        );
+        let (lut_bind_group, tracked) = Self::upload(device, queue, &lut_layout, &sampler, lut);
+        Self {
+            intensity: 1.0,
+            pass,
+            lut_layout,
+            lut_bind_group,
+            sampler,
+            size: lut.size(),
+            _tracked: tracked,
+        }
+    }
+
+    fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        lut: &Lut,
+    ) -> (wgpu::BindGroup, Tracked) {
+        let size = wgpu::Extent3d {
+            width: lut.size,
+            height: lut.size,
+            depth_or_array_layers: lut.size,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Color Grading LUT"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&lut.data),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * lut.size),
+                rows_per_image: Some(lut.size),
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Color Grading LUT Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+        (bind_group, Tracked::new(0, 0, 1).with_texture(&texture))
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Swaps the table, e.g. when entering an area graded differently.
+    pub fn set_lut(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lut: &Lut) {
+        let (bind_group, tracked) =
+            Self::upload(device, queue, &self.lut_layout, &self.sampler, lut);
+        self.lut_bind_group = bind_group;
+        self._tracked = tracked;
+        self.size = lut.size();
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn label(&self) -> &str {
+        "Color Grading"
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    ) {
+        // sampled values are linear when the texture decodes sRGB or holds floats
+        let linear = frame.format.is_srgb()
+            || matches!(
+                frame.format,
+                wgpu::TextureFormat::Rgba16Float
+                    | wgpu::TextureFormat::Rgba32Float
+                    | wgpu::TextureFormat::Rg11b10Float
+            );
+        let params = Params {
+            intensity: self.intensity.clamp(0.0, 1.0),
+            size: self.size as f32,
+            linear: linear as u32,
+            _padding: 0,
+        };
+        self.pass.draw(
+            frame,
+            encoder,
+            source,
+            target,
+            &params,
+            &[&self.lut_bind_group],
+        );
+    }
+}
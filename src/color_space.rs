@@ -0,0 +1,175 @@
+//! Color gamuts and converting between them, so colors authored in sRGB look as intended on
+//! wide-gamut displays, and colors authored in Display P3 can use the wider range where the
+//! surface can show it.
+//!
+//! A display shows the surface's values in its own gamut unless the platform manages
+//! colors for it: macOS and iOS always do, taking the values as sRGB, and Windows and
+//! Wayland do for extended linear sRGB surfaces. Elsewhere sRGB colors come out
+//! oversaturated on a wide-gamut display. wgpu can't ask the platform what the display's
+//! gamut is, so `Context::set_display_gamut` tells it, from the app's settings or the
+//! user, and `Context::output_gamut` is then the gamut colors written to the surface end
+//! up in. A `GamutConversion` at the end of a `PostChain` converts the frame into it.
+//!
+//! Wider colors than sRGB need an extended linear sRGB surface, which
+//! `Context::enable_wide_gamut` switches to where there is one: they're written as sRGB
+//! values below 0 or above 1.
+
+use crate::math::{Mat4, Vec2, Vec3, Vec4};
+use crate::post::{PostEffect, PostFrame, PostPipeline, PostTarget};
+
+/// CIE xy chromaticity of the D65 white point all three gamuts share.
+const D65: Vec2 = Vec2 {
+    x: 0.3127,
+    y: 0.3290,
+};
+
+const GAMUT_CONVERSION_SHADER: &str = r#"
+struct Params {
+    matrix: mat4x4<f32>,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    return vec4<f32>((params.matrix * vec4<f32>(color.rgb, 0.0)).rgb, color.a);
+}
+"#;
+
+/// The range of colors a display or an RGB color space can show, all with a D65 white.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Gamut {
+    /// sRGB and Rec. 709, what colors are authored in unless said otherwise.
+    #[default]
+    Srgb,
+    /// Wide-gamut Apple displays and most recent laptop and phone screens, about a quarter
+    /// larger than sRGB. Uses the sRGB transfer function.
+    DisplayP3,
+    /// HDR video and displays.
+    Rec2020,
+}
+
+impl Gamut {
+    /// CIE xy chromaticities of the red, green and blue primaries.
+    pub fn primaries(self) -> [Vec2; 3] {
+        let [r, g, b] = match self {
+            Gamut::Srgb => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06)],
+            Gamut::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)],
+            Gamut::Rec2020 => [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046)],
+        };
+        [r, g, b].map(|(x, y)| Vec2::new(x, y))
+    }
+
+    /// Linear RGB in this gamut to CIE XYZ, in the upper left 3x3 of the matrix.
+    pub fn to_xyz(self) -> Mat4 {
+        let [r, g, b] = self.primaries().map(xy_to_xyz);
+        let primaries = Mat4::from_cols(
+            r.extend(0.0),
+            g.extend(0.0),
+            b.extend(0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        );
+        // scaled so that RGB 1, 1, 1 is the white point
+        let scale = primaries.inverse().transform_vector3(xy_to_xyz(D65));
+        primaries * Mat4::scale(scale)
+    }
+
+    /// Linear RGB in this gamut to linear RGB in `to`. Colors `to` can't show come out
+    /// below 0 or above 1.
+    pub fn conversion(self, to: Gamut) -> Mat4 {
+        if self == to {
+            return Mat4::IDENTITY;
+        }
+        to.to_xyz().inverse() * self.to_xyz()
+    }
+
+    /// Converts a linear color from this gamut to `to`.
+    pub fn convert(self, to: Gamut, linear: Vec3) -> Vec3 {
+        self.conversion(to).transform_vector3(linear)
+    }
+
+    /// Converts a linear `wgpu::Color`, e.g. a clear color, keeping its alpha.
+    pub fn convert_color(self, to: Gamut, color: wgpu::Color) -> wgpu::Color {
+        let rgb = Vec3::new(color.r as f32, color.g as f32, color.b as f32);
+        let rgb = self.convert(to, rgb);
+        wgpu::Color {
+            r: rgb.x as f64,
+            g: rgb.y as f64,
+            b: rgb.z as f64,
+            a: color.a,
+        }
+    }
+}
+
+fn xy_to_xyz(xy: Vec2) -> Vec3 {
+    Vec3::new(xy.x / xy.y, 1.0, (1.0 - xy.x - xy.y) / xy.y)
+}
+
+/// Decodes an sRGB or Display P3 encoded channel to linear.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear channel as sRGB or Display P3.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct GamutConversionParams {
+    matrix: [[f32; 4]; 4],
+}
+unsafe impl bytemuck::Pod for GamutConversionParams {}
+unsafe impl bytemuck::Zeroable for GamutConversionParams {}
+
+/// Converts the frame's linear colors from one gamut to another, e.g. from `Gamut::Srgb`
+/// to `Context::output_gamut` as the last effect of a `PostChain`. The chain's textures
+/// need an sRGB or float format, colors the target can't store are clipped.
+pub struct GamutConversion {
+    pub from: Gamut,
+    pub to: Gamut,
+    pass: PostPipeline,
+}
+
+impl GamutConversion {
+    pub fn new(device: &wgpu::Device, from: Gamut, to: Gamut) -> Self {
+        Self {
+            from,
+            to,
+            pass: PostPipeline::new(
+                device,
+                "Gamut Conversion",
+                GAMUT_CONVERSION_SHADER,
+                std::mem::size_of::<GamutConversionParams>() as u64,
+                &[],
+            ),
+        }
+    }
+}
+
+impl PostEffect for GamutConversion {
+    fn label(&self) -> &str {
+        "Gamut Conversion"
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    ) {
+        let params = GamutConversionParams {
+            matrix: self.from.conversion(self.to).cols,
+        };
+        self.pass.draw(frame, encoder, source, target, &params, &[]);
+    }
+}
@@ -0,0 +1,173 @@
+//! Compute work without a window: `run_compute` sets up the device the way `Context` does,
+//! from the same `GraphicsSettings`, and hands a `ComputeContext` to a function that
+//! creates buffers, dispatches shaders and reads the results back, for GPGPU experiments
+//! and tools.
+//!
+//! Everything taking a `wgpu::Device` and `wgpu::Queue` works with it, such as `Kernels`,
+//! `StagingRing` and `readback`. The helpers here cover the usual steps: `storage_buffer`
+//! to upload the input, `pipeline` for a WGSL entry point, `bind_group` for the buffers in
+//! binding order, `dispatch` to run it and `read_buffer` to wait for the output.
+
+use wgpu::util::DeviceExt;
+
+use crate::context::Context;
+use crate::readback::{self, ReadbackError};
+use crate::settings::GraphicsSettings;
+
+/// The device of `run_compute`, see the module docs.
+pub struct ComputeContext {
+    // kept alive for as long as the device
+    _instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl ComputeContext {
+    /// Picks the adapter `settings` name, or else the default one. Settings for the window
+    /// and drawing are ignored.
+    pub async fn new(settings: &GraphicsSettings) -> Self {
+        let instance = Context::create_instance(settings);
+        let (adapter, device, queue) = Context::request_device(&instance, None, settings).await;
+        Self {
+            _instance: instance,
+            adapter,
+            device,
+            queue,
+        }
+    }
+
+    pub fn adapter(&self) -> &wgpu::Adapter {
+        &self.adapter
+    }
+
+    /// The GPU, its features and main limits, see `context::gpu_diagnostics`.
+    pub fn diagnostics(&self) -> String {
+        crate::context::gpu_diagnostics(&self.adapter, &self.device)
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// A buffer holding `values`, usable as a storage buffer and copied from and to, so
+    /// it can be both input and output of a dispatch.
+    pub fn storage_buffer<T: bytemuck::Pod>(&self, label: &str, values: &[T]) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(values),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            })
+    }
+
+    /// A zeroed storage buffer for `len` values of `T`, for output.
+    pub fn output_buffer<T: bytemuck::Pod>(&self, label: &str, len: usize) -> wgpu::Buffer {
+        self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (len * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// A pipeline running `entry_point` of the WGSL `source`, with its bind group layouts
+    /// derived from the shader.
+    pub fn pipeline(&self, label: &str, source: &str, entry_point: &str) -> wgpu::ComputePipeline {
+        let module = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        self.device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: None,
+                module: &module,
+                entry_point,
+            })
+    }
+
+    /// Binds `buffers` whole to bindings 0, 1, 2... of the pipeline's bind group `group`.
+    pub fn bind_group(
+        &self,
+        pipeline: &wgpu::ComputePipeline,
+        group: u32,
+        buffers: &[&wgpu::Buffer],
+    ) -> wgpu::BindGroup {
+        let entries: Vec<wgpu::BindGroupEntry> = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Bind Group"),
+            layout: &pipeline.get_bind_group_layout(group),
+            entries: &entries,
+        })
+    }
+
+    /// Runs `workgroups` workgroups of `pipeline` with `bind_groups` bound in order, and
+    /// submits it on its own. Dispatches run in the order they're made.
+    pub fn dispatch(
+        &self,
+        pipeline: &wgpu::ComputePipeline,
+        bind_groups: &[&wgpu::BindGroup],
+        workgroups: [u32; 3],
+    ) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute Encoder"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(pipeline);
+            for (index, bind_group) in bind_groups.iter().enumerate() {
+                pass.set_bind_group(index as u32, bind_group, &[]);
+            }
+            let [x, y, z] = workgroups;
+            pass.dispatch_workgroups(x, y, z);
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// The values in `buffer`, after every dispatch made so far, see
+    /// `readback::read_buffer`.
+    pub fn read_buffer<T: bytemuck::Pod>(
+        &self,
+        buffer: &wgpu::Buffer,
+    ) -> Result<Vec<T>, ReadbackError> {
+        readback::read_buffer(&self.device, &self.queue, buffer)
+    }
+
+    /// Waits for everything submitted so far to finish.
+    pub fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+}
+
+/// Sets up a device from `settings` without a window and runs `f` with it, waiting for its
+/// work to finish before returning what `f` did.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_compute<R>(settings: &GraphicsSettings, f: impl FnOnce(&ComputeContext) -> R) -> R {
+    let ctx = pollster::block_on(ComputeContext::new(settings));
+    let result = f(&ctx);
+    ctx.wait();
+    result
+}
@@ -0,0 +1,265 @@
+//! Tuning values read from a RON or TOML file into your own types, and read again when the
+//! file changes, so speeds, colors and thresholds can be tweaked while the game runs.
+//!
+//! `Config::load` deserializes the file into any `DeserializeOwned` type, picking the
+//! format from the extension. Call `update` once per frame: it looks at the file's
+//! modification time every so often and, when it changed, parses it again, replaces the
+//! value, calls the hooks registered with `on_change` and returns `true`. A file that no
+//! longer parses is reported and the previous value kept, so a typo doesn't bring the game
+//! down. Needs the `config` feature.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::de::DeserializeOwned;
+
+use crate::time::Instant;
+
+/// How often `update` looks at the file.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Ron(PathBuf, ron::error::SpannedError),
+    Toml(PathBuf, toml::de::Error),
+    /// The path's extension is neither `ron` nor `toml`.
+    UnknownFormat(PathBuf),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Ron(path, e) => write!(f, "{}: invalid RON: {}", path.display(), e),
+            ConfigError::Toml(path, e) => write!(f, "{}: invalid TOML: {}", path.display(), e),
+            ConfigError::UnknownFormat(path) => {
+                write!(f, "unknown config format: {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The file formats a config is read from, picked from the extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Ron,
+    Toml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "ron" => Some(ConfigFormat::Ron),
+            "toml" => Some(ConfigFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Deserializes `source`, with `path` for the error.
+    pub fn parse<T: DeserializeOwned>(self, source: &str, path: &Path) -> Result<T, ConfigError> {
+        match self {
+            ConfigFormat::Ron => {
+                ron::from_str(source).map_err(|e| ConfigError::Ron(path.to_path_buf(), e))
+            }
+            ConfigFormat::Toml => {
+                toml::from_str(source).map_err(|e| ConfigError::Toml(path.to_path_buf(), e))
+            }
+        }
+    }
+}
+
+/// Reads `path` into a `T`, in the format its extension names.
+pub fn read<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, ConfigError> {
+    let path = path.as_ref();
+    let format =
+        ConfigFormat::from_path(path).ok_or_else(|| ConfigError::UnknownFormat(path.into()))?;
+    let source =
+        std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    format.parse(&source, path)
+}
+
+type ChangeHook<T> = Box<dyn FnMut(&T)>;
+
+/// A value read from a file and kept up to date with it, see the module docs.
+pub struct Config<T> {
+    path: PathBuf,
+    value: T,
+    /// Modification time of the file when it was last read.
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    hooks: Vec<ChangeHook<T>>,
+}
+
+impl<T: DeserializeOwned> Config<T> {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, ConfigError> {
+        let path = path.into();
+        let modified = modified(&path);
+        let value = read(&path)?;
+        Ok(Self {
+            path,
+            value,
+            modified,
+            last_check: Instant::now(),
+            hooks: Vec::new(),
+        })
+    }
+
+    /// Like `load`, starting from `default` when the file can't be read yet. It's picked
+    /// up once it can, e.g. after being created.
+    pub fn load_or(path: impl Into<PathBuf>, default: T) -> Self {
+        let path = path.into();
+        match Self::load(path.clone()) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!("loading config failed: {}", e);
+                Self {
+                    path,
+                    value: default,
+                    modified: None,
+                    last_check: Instant::now(),
+                    hooks: Vec::new(),
+                }
+            }
+        }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Calls `hook` with the new value whenever the file is read again.
+    pub fn on_change(&mut self, hook: impl FnMut(&T) + 'static) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Reads the file again if it changed since it was last read, at most every half
+    /// second. Returns whether the value was replaced.
+    pub fn update(&mut self) -> bool {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.reload(modified)
+    }
+
+    /// Reads the file again right away, whether it changed or not.
+    pub fn reload_now(&mut self) -> bool {
+        self.reload(modified(&self.path))
+    }
+
+    fn reload(&mut self, modified: Option<SystemTime>) -> bool {
+        // a failed read still records the time, so the file isn't parsed again until it
+        // changes once more
+        self.modified = modified;
+        match read(&self.path) {
+            Ok(value) => {
+                self.value = value;
+                log::info!("reloaded {}", self.path.display());
+                for hook in &mut self.hooks {
+                    hook(&self.value);
+                }
+                true
+            }
+            Err(e) => {
+                log::warn!("reloading config failed: {}", e);
+                false
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Tuning {
+        speed: f32,
+        name: String,
+    }
+
+    /// Writes `source` to `path` and dates it `seconds` after the epoch, so a rewrite within
+    /// the file system's timestamp resolution still counts as a change.
+    fn write(path: &Path, source: &str, seconds: u64) {
+        std::fs::write(path, source).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    /// `update` without waiting for the check interval.
+    fn update(config: &mut Config<Tuning>) -> bool {
+        config.last_check = Instant::now() - CHECK_INTERVAL;
+        config.update()
+    }
+
+    #[test]
+    fn reloads_only_when_changed() {
+        let path = std::env::temp_dir().join(format!("config_test_{}.ron", std::process::id()));
+        write(&path, "(speed: 1.5, name: \"slow\")", 1_000);
+        let mut config = Config::<Tuning>::load(&path).unwrap();
+        let changes = Rc::new(Cell::new(0));
+        let counted = changes.clone();
+        config.on_change(move |_| counted.set(counted.get() + 1));
+        assert_eq!(config.get().speed, 1.5);
+        // the interval hasn't passed yet
+        assert!(!config.update());
+        assert!(!update(&mut config));
+
+        write(&path, "(speed: 3.0, name: \"fast\")", 2_000);
+        assert!(update(&mut config));
+        assert_eq!(
+            config.get(),
+            &Tuning {
+                speed: 3.0,
+                name: "fast".into()
+            }
+        );
+        assert!(!update(&mut config));
+
+        // a typo keeps the last good value and isn't parsed again until the next change
+        write(&path, "(speed: 4.0, name: ", 3_000);
+        assert!(!update(&mut config));
+        assert_eq!(config.get().speed, 3.0);
+        write(&path, "(speed: 4.0, name: \"faster\")", 4_000);
+        assert!(update(&mut config));
+        assert_eq!(config.get().speed, 4.0);
+        assert_eq!(changes.get(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn format_from_extension() {
+        let path = Path::new("tuning.toml");
+        assert_eq!(ConfigFormat::from_path(path), Some(ConfigFormat::Toml));
+        let tuning: Tuning = ConfigFormat::Toml
+            .parse("speed = 2.0\nname = \"toml\"", path)
+            .unwrap();
+        assert_eq!(tuning.name, "toml");
+        assert!(matches!(
+            read::<Tuning>("tuning.json"),
+            Err(ConfigError::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,1159 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use wgpu::{Instance, InstanceDescriptor, RequestAdapterOptions};
+use winit::window::Theme;
+
+use crate::assets::Assets;
+use crate::color_space::Gamut;
+use crate::crash_dump::CrashDump;
+use crate::debug_overlay::DebugOverlay;
+#[cfg(feature = "ecs")]
+use crate::ecs::{DrawLists, World};
+use crate::events::Events;
+use crate::frame::{Frame, FrameOutput, FramePacing};
+use crate::gpu_capture::GpuCapture;
+use crate::input::{FocusPolicy, InputState};
+use crate::math::Vec2;
+use crate::profile::profile_scope;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::recording::{FrameEncoder, FrameRecorder, PngSequence, RecordingError};
+use crate::settings::GraphicsSettings;
+use crate::time::{Instant, Time};
+use crate::timers::Timers;
+use crate::tool_windows::ToolWindows;
+use crate::tween::Tweens;
+use crate::vsync::{AdaptiveVsync, VsyncEvent};
+use crate::web::{self, WebBackend};
+
+/// Offscreen targets are created with these usages, so they can be read back and sampled.
+const OFFSCREEN_USAGE: wgpu::TextureUsages = wgpu::TextureUsages::RENDER_ATTACHMENT
+    .union(wgpu::TextureUsages::COPY_SRC)
+    .union(wgpu::TextureUsages::TEXTURE_BINDING);
+
+/// What frames are drawn into.
+enum Target {
+    Window {
+        /// `None` while the app is suspended, see `Context::suspend`.
+        surface: Option<wgpu::Surface>,
+        window: Arc<winit::window::Window>,
+    },
+    /// Taken by the frame being drawn, put back when it ends.
+    Offscreen(Option<wgpu::Texture>),
+}
+
+/// Insets in physical pixels from the edges of the surface to the part of it that isn't
+/// covered by a notch, rounded corners or the home indicator, see `Context::safe_area`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SafeArea {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl SafeArea {
+    /// Top left corner and size in pixels of the unobstructed part of a `size` surface.
+    pub fn rect(&self, size: winit::dpi::PhysicalSize<u32>) -> (Vec2, Vec2) {
+        let width = size.width.saturating_sub(self.left + self.right);
+        let height = size.height.saturating_sub(self.top + self.bottom);
+        (
+            Vec2::new(self.left as f32, self.top as f32),
+            Vec2::new(width as f32, height as f32),
+        )
+    }
+}
+
+/// How the colors written to the surface are shown, which follows from its format. wgpu
+/// has no say in the display's color space, so this is the common reading of each format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SurfaceColorSpace {
+    /// sRGB, encoded on write: shaders output linear colors.
+    Srgb,
+    /// sRGB stored as written, so shaders encode linear colors themselves.
+    SrgbUnencoded,
+    /// Linear extended sRGB (scRGB) in a float format, values above 1 are brighter than
+    /// white where the display and compositor do HDR.
+    ExtendedLinearSrgb,
+}
+
+impl SurfaceColorSpace {
+    pub fn of(format: wgpu::TextureFormat) -> Self {
+        match format {
+            wgpu::TextureFormat::Rgba16Float
+            | wgpu::TextureFormat::Rgba32Float
+            | wgpu::TextureFormat::Rg11b10Float => SurfaceColorSpace::ExtendedLinearSrgb,
+            format if format.is_srgb() => SurfaceColorSpace::Srgb,
+            _ => SurfaceColorSpace::SrgbUnencoded,
+        }
+    }
+}
+
+/// The first of `preferences` in `available`, or else the first sRGB format, or else the
+/// first available.
+pub(crate) fn choose_surface_format(
+    available: &[wgpu::TextureFormat],
+    preferences: &[wgpu::TextureFormat],
+) -> wgpu::TextureFormat {
+    preferences
+        .iter()
+        .chain(available.iter().filter(|format| format.is_srgb()))
+        .copied()
+        .find(|format| available.contains(format))
+        .unwrap_or(available[0])
+}
+
+/// The size to configure a window's surface with. On iOS the Metal layer covers the whole
+/// view, while the window's inner size leaves out the safe area.
+fn surface_size(window: &winit::window::Window) -> winit::dpi::PhysicalSize<u32> {
+    if cfg!(target_os = "ios") {
+        window.outer_size()
+    } else {
+        window.inner_size()
+    }
+}
+
+/// A report of the GPU for logs, bug reports and about screens: the adapter, the features
+/// the device has and the limits that most often decide what an app can do.
+pub fn gpu_diagnostics(adapter: &wgpu::Adapter, device: &wgpu::Device) -> String {
+    let info = adapter.get_info();
+    let features: Vec<&str> = device
+        .features()
+        .iter_names()
+        .map(|(name, _)| name)
+        .collect();
+    let limits = device.limits();
+    let mut report = format!(
+        "GPU: {} ({:?}, {:?})\nVendor: {:#06x}  Device: {:#06x}\n",
+        info.name, info.device_type, info.backend, info.vendor, info.device
+    );
+    if !info.driver.is_empty() || !info.driver_info.is_empty() {
+        report += &format!("Driver: {} {}\n", info.driver, info.driver_info);
+    }
+    report += &format!(
+        "Features: {}\n",
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    );
+    report += &format!(
+        "Limits: 2D textures {}px, {} bind groups, {} MiB storage bindings, \
+         {} MiB buffers, workgroups {}x{}x{} of {} invocations",
+        limits.max_texture_dimension_2d,
+        limits.max_bind_groups,
+        limits.max_storage_buffer_binding_size >> 20,
+        limits.max_buffer_size >> 20,
+        limits.max_compute_workgroup_size_x,
+        limits.max_compute_workgroup_size_y,
+        limits.max_compute_workgroup_size_z,
+        limits.max_compute_invocations_per_workgroup,
+    );
+    report
+}
+
+/// Everything set up once per window: the surface and the device/queue used to draw into it.
+/// Handed to the `App` callbacks.
+///
+/// A headless context draws into an offscreen texture instead, for benchmarks and tests.
+pub struct Context {
+    instance: Instance,
+    target: Target,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    size: winit::dpi::PhysicalSize<u32>,
+    pub tweens: Tweens,
+    /// Ticked by the run loop at the start of every frame.
+    pub time: Time,
+    /// Run by the run loop before `App::fixed_update`.
+    pub timers: Timers,
+    /// Window, input and asset events, and your own, updated by the run loop right before
+    /// `App::update`.
+    pub events: Events,
+    /// The cursor and scale factor, updated by the run loop from the window's events.
+    pub input: InputState,
+    /// What the run loop does while the window isn't focused.
+    pub focus_policy: FocusPolicy,
+    /// See `theme`.
+    system_theme: Option<Theme>,
+    theme_override: Option<Theme>,
+    /// Inspectors, previews and other windows next to the main one.
+    pub tool_windows: ToolWindows,
+    /// Updated by the run loop before `App::update`.
+    pub assets: Assets,
+    #[cfg(feature = "ecs")]
+    pub world: World,
+    /// Extracted from `world` when a frame starts, before `App::render`.
+    #[cfg(feature = "ecs")]
+    pub draw_lists: DrawLists,
+    /// Drawn over every frame by the run loop when set.
+    pub debug_overlay: Option<DebugOverlay>,
+    gpu_capture: GpuCapture,
+    crash_dump: CrashDump,
+    /// See `set_surface_format_preferences`.
+    format_preferences: Vec<wgpu::TextureFormat>,
+    /// See `set_display_gamut`.
+    display_gamut: Option<Gamut>,
+    /// Key that captures the next frame in RenderDoc, see `trigger_capture`.
+    pub capture_key: Option<winit::event::VirtualKeyCode>,
+    #[cfg(not(target_arch = "wasm32"))]
+    recorder: Option<FrameRecorder>,
+    /// Submissions of the frames the GPU may still be working on, oldest first.
+    in_flight: VecDeque<wgpu::SubmissionIndex>,
+    max_frames_in_flight: usize,
+    /// See `set_sample_count`.
+    sample_count: u32,
+    pub frame_pacing: FramePacing,
+    pacing_wait: Duration,
+    adaptive_vsync: Option<AdaptiveVsync>,
+    vsync_events: Vec<VsyncEvent>,
+    last_frame_end: Option<Instant>,
+}
+
+impl Context {
+    /// On Android the window has nothing to draw into until the app is resumed, so the
+    /// surface is created by the first `resume` instead.
+    pub(crate) async fn new(
+        window: Arc<winit::window::Window>,
+        settings: &GraphicsSettings,
+    ) -> Self {
+        let size = surface_size(&window);
+
+        let instance = Self::create_instance(settings);
+        let surface = if cfg!(target_os = "android") {
+            None
+        } else {
+            Some(unsafe { instance.create_surface(&*window) }.unwrap())
+        };
+        let (adapter, device, queue) =
+            Self::request_device(&instance, surface.as_ref(), settings).await;
+
+        let mut config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        };
+        if let Some(surface) = &surface {
+            let surface_caps = surface.get_capabilities(&adapter);
+            config.format = choose_surface_format(&surface_caps.formats, &[]);
+            config.alpha_mode = surface_caps.alpha_modes[0];
+            surface.configure(&device, &config);
+        }
+
+        let mut ctx = Self::from_parts(
+            instance,
+            Target::Window { surface, window },
+            adapter,
+            device,
+            queue,
+            config,
+        );
+        if let Some(vsync) = settings.vsync {
+            ctx.set_present_mode(if vsync {
+                wgpu::PresentMode::AutoVsync
+            } else {
+                wgpu::PresentMode::AutoNoVsync
+            });
+        }
+        ctx.set_sample_count(settings.msaa);
+        ctx
+    }
+
+    /// A context without a window, drawing `width` by `height` sRGB frames into a texture.
+    pub async fn new_headless(width: u32, height: u32) -> Self {
+        let settings = GraphicsSettings::default();
+        let instance = Self::create_instance(&settings);
+        let (adapter, device, queue) = Self::request_device(&instance, None, &settings).await;
+        let config = wgpu::SurfaceConfiguration {
+            usage: OFFSCREEN_USAGE,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+        let texture = Self::create_offscreen_texture(&device, &config);
+        Self::from_parts(
+            instance,
+            Target::Offscreen(Some(texture)),
+            adapter,
+            device,
+            queue,
+            config,
+        )
+    }
+
+    fn from_parts(
+        instance: Instance,
+        target: Target,
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+    ) -> Self {
+        crate::globals::reset(config.width, config.height);
+        let crash_dump = CrashDump::new(matches!(target, Target::Window { .. }));
+        crash_dump.install(&adapter, &device);
+        crash_dump.set_config(&config);
+        let (scale_factor, system_theme) = match &target {
+            Target::Window { window, .. } => (window.scale_factor(), window.theme()),
+            Target::Offscreen(_) => (1.0, None),
+        };
+        Self {
+            instance,
+            target,
+            adapter,
+            device,
+            queue,
+            size: winit::dpi::PhysicalSize::new(config.width, config.height),
+            config,
+            tweens: Tweens::new(),
+            time: Time::new(),
+            timers: Timers::new(),
+            events: Events::new(),
+            input: InputState::new(scale_factor),
+            focus_policy: FocusPolicy::default(),
+            system_theme,
+            theme_override: None,
+            tool_windows: ToolWindows::default(),
+            assets: Assets::new(),
+            #[cfg(feature = "ecs")]
+            world: World::new(),
+            #[cfg(feature = "ecs")]
+            draw_lists: DrawLists::default(),
+            debug_overlay: None,
+            gpu_capture: GpuCapture::new(),
+            crash_dump,
+            format_preferences: Vec::new(),
+            display_gamut: None,
+            capture_key: Some(winit::event::VirtualKeyCode::F9),
+            #[cfg(not(target_arch = "wasm32"))]
+            recorder: None,
+            in_flight: VecDeque::new(),
+            max_frames_in_flight: 2,
+            sample_count: 1,
+            frame_pacing: FramePacing::default(),
+            pacing_wait: Duration::ZERO,
+            adaptive_vsync: None,
+            vsync_events: Vec::new(),
+            last_frame_end: None,
+        }
+    }
+
+    /// `WGPU_BACKEND` (e.g. `vulkan` or `gl`) overrides the backends tried, handy on CI
+    /// machines with only a software GL driver, unless the settings name them. On the web
+    /// it's WebGPU or WebGL2, see `web`.
+    pub(crate) fn create_instance(settings: &GraphicsSettings) -> Instance {
+        let backends = if cfg!(target_arch = "wasm32") {
+            web::select().backends()
+        } else {
+            settings.instance_backends()
+        };
+        Instance::new(InstanceDescriptor {
+            backends,
+            ..InstanceDescriptor::default()
+        })
+    }
+
+    pub(crate) async fn request_device(
+        instance: &Instance,
+        surface: Option<&wgpu::Surface>,
+        settings: &GraphicsSettings,
+    ) -> (wgpu::Adapter, wgpu::Device, wgpu::Queue) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let chosen = settings
+            .find_adapter(instance, settings.instance_backends())
+            .filter(|adapter| {
+                let compatible = surface.is_none_or(|s| adapter.is_surface_supported(s));
+                if !compatible {
+                    log::warn!("{} can't draw to the window", adapter.get_info().name);
+                }
+                compatible
+            });
+        #[cfg(target_arch = "wasm32")]
+        let chosen = {
+            let _ = settings;
+            None
+        };
+        let options = RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::LowPower,
+            compatible_surface: surface,
+            force_fallback_adapter: false,
+        };
+
+        let adapter = match chosen {
+            Some(adapter) => Some(adapter),
+            None => instance.request_adapter(&options).await,
+        };
+
+        let adapter = match adapter {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions::default())
+                .await
+                .expect("Failed to find any suitable adapter"),
+        };
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    // whichever block compressed formats there are, for KTX2 textures,
+                    // read-write storage textures in formats beyond what WebGPU guarantees,
+                    // multiview for `multiview::Multiview` and the rasterizer options of
+                    // `pipeline::PipelineBuilder`
+                    features: adapter.features()
+                        & (wgpu::Features::TEXTURE_COMPRESSION_BC
+                            | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+                            | wgpu::Features::TEXTURE_COMPRESSION_ASTC
+                            | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                            | wgpu::Features::MULTIVIEW
+                            | wgpu::Features::CONSERVATIVE_RASTERIZATION
+                            | wgpu::Features::DEPTH_CLIP_CONTROL),
+                    limits: WebBackend::from_backend(adapter.get_info().backend)
+                        .map_or_else(wgpu::Limits::default, WebBackend::limits),
+                    label: Some("Device"),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        if let Some(backend) = WebBackend::from_backend(adapter.get_info().backend) {
+            log::info!("drawing with {:?}", backend);
+        }
+        (adapter, device, queue)
+    }
+
+    fn create_offscreen_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        })
+    }
+
+    /// Creates the assets that finished loading and frees unused ones, see `Assets::update`.
+    pub fn update_assets(&mut self) {
+        self.assets.update(&self.device, &self.queue);
+        for event in self.assets.events() {
+            self.events.send(*event);
+        }
+    }
+
+    /// Panics on a headless context, see `is_headless`.
+    pub fn window(&self) -> &winit::window::Window {
+        match &self.target {
+            Target::Window { window, .. } => window,
+            Target::Offscreen(_) => panic!("a headless context has no window"),
+        }
+    }
+
+    /// Records what a window event changes in `input`, the theme and `events`, for the run
+    /// loops.
+    pub(crate) fn record_window_event(&mut self, event: &winit::event::WindowEvent) {
+        if let winit::event::WindowEvent::ThemeChanged(theme) = event {
+            // with an override the window reports its own theme changing
+            if self.theme_override.is_none() {
+                self.system_theme = Some(*theme);
+            }
+        }
+        self.input.handle(event);
+        self.events.send_winit(event);
+    }
+
+    /// Light or dark, as `set_theme_override` says or else the system, for picking clear
+    /// colors and palettes such as `UiStyle::for_theme` to match. Light where the platform
+    /// doesn't tell, which is Linux, iOS and Android. `events::WindowEvent::ThemeChanged`
+    /// is sent when it changes with the system.
+    pub fn theme(&self) -> Theme {
+        self.theme_override
+            .or(self.system_theme)
+            .unwrap_or(Theme::Light)
+    }
+
+    /// The system's theme as last reported, `None` where the platform doesn't tell.
+    pub fn system_theme(&self) -> Option<Theme> {
+        self.system_theme
+    }
+
+    /// Overrides the system's theme for `theme` and the window's decorations where the
+    /// platform lets it, e.g. from a setting in the app. `None` follows the system again.
+    pub fn set_theme_override(&mut self, theme: Option<Theme>) {
+        self.theme_override = theme;
+        if let Target::Window { window, .. } = &self.target {
+            window.set_theme(theme);
+            if theme.is_none() {
+                self.system_theme = window.theme();
+            }
+        }
+    }
+
+    pub fn theme_override(&self) -> Option<Theme> {
+        self.theme_override
+    }
+
+    /// Whether `focus_policy` holds `event` back from the app right now.
+    pub(crate) fn mutes_input(&self, event: &winit::event::WindowEvent) -> bool {
+        self.focus_policy.mute_input && !self.input.is_focused() && FocusPolicy::is_input(event)
+    }
+
+    /// Whether `focus_policy` has frames paused right now.
+    pub fn is_rendering_paused(&self) -> bool {
+        self.focus_policy.pause_rendering && !self.input.is_focused()
+    }
+
+    /// Physical pixels per logical pixel, 1 for a headless context.
+    pub fn scale_factor(&self) -> f64 {
+        match &self.target {
+            Target::Window { window, .. } => window.scale_factor(),
+            Target::Offscreen(_) => 1.0,
+        }
+    }
+
+    /// The part of the surface UI should stay within. Nonzero only on displays with
+    /// notches or rounded corners, i.e. iOS, where the window's inner rectangle is the
+    /// safe area of the screen the surface covers.
+    pub fn safe_area(&self) -> SafeArea {
+        let Target::Window { window, .. } = &self.target else {
+            return SafeArea::default();
+        };
+        if !cfg!(target_os = "ios") {
+            return SafeArea::default();
+        }
+        let (Ok(inner), Ok(outer)) = (window.inner_position(), window.outer_position()) else {
+            return SafeArea::default();
+        };
+        let (inner_size, outer_size) = (window.inner_size(), window.outer_size());
+        let left = (inner.x - outer.x).max(0) as u32;
+        let top = (inner.y - outer.y).max(0) as u32;
+        SafeArea {
+            top,
+            bottom: outer_size.height.saturating_sub(top + inner_size.height),
+            left,
+            right: outer_size.width.saturating_sub(left + inner_size.width),
+        }
+    }
+
+    /// Whether frames are drawn with WebGPU or the WebGL2 fallback, `None` when not on the
+    /// web.
+    pub fn web_backend(&self) -> Option<WebBackend> {
+        WebBackend::from_backend(self.adapter.get_info().backend)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        matches!(self.target, Target::Offscreen(_))
+    }
+
+    /// Everything wgpu holds across all devices, by resource type. Complements
+    /// `stats::memory_report`, which covers only the crate's own renderers but knows sizes.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wgpu_report(&self) -> wgpu::core::global::GlobalReport {
+        self.instance.generate_report()
+    }
+
+    /// Captures the next frame in RenderDoc. Needs the `renderdoc` feature and the app
+    /// running under RenderDoc, returns `false` otherwise.
+    pub fn trigger_capture(&mut self) -> bool {
+        self.gpu_capture.trigger()
+    }
+
+    pub fn gpu_capture(&self) -> &GpuCapture {
+        &self.gpu_capture
+    }
+
+    /// Triggers a capture if the event is a press of `capture_key`.
+    pub(crate) fn capture_input(&mut self, event: &winit::event::WindowEvent) -> bool {
+        match event {
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: winit::event::ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if Some(*key) == self.capture_key => {
+                self.trigger_capture();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// What's written to a file if the app panics, see `crash_dump`.
+    pub fn crash_dump(&self) -> &CrashDump {
+        &self.crash_dump
+    }
+
+    pub fn adapter(&self) -> &wgpu::Adapter {
+        &self.adapter
+    }
+
+    /// Name, vendor, backend and driver of the GPU.
+    pub fn adapter_info(&self) -> wgpu::AdapterInfo {
+        self.adapter.get_info()
+    }
+
+    /// The features the device was created with, a subset of what the adapter has: those
+    /// the crate can use, see `has_features`.
+    pub fn features(&self) -> wgpu::Features {
+        self.device.features()
+    }
+
+    /// Whether the device has all of `features`, to skip or swap effects that need them.
+    pub fn has_features(&self, features: wgpu::Features) -> bool {
+        self.device.features().contains(features)
+    }
+
+    /// The limits the device was created with, the defaults or WebGL2's, not the best the
+    /// adapter could do.
+    pub fn limits(&self) -> wgpu::Limits {
+        self.device.limits()
+    }
+
+    /// The GPU, its features and main limits on a few lines, see `gpu_diagnostics`.
+    pub fn diagnostics(&self) -> String {
+        gpu_diagnostics(&self.adapter, &self.device)
+    }
+
+    /// For surfaces of other windows, see `tool_windows`.
+    pub(crate) fn instance(&self) -> &Instance {
+        &self.instance
+    }
+
+    /// Whether storage textures of this format can be bound with
+    /// `StorageTextureAccess::ReadWrite`, only `R32Float`, `R32Uint` and `R32Sint` are
+    /// everywhere.
+    pub fn supports_read_write_storage(&self, format: wgpu::TextureFormat) -> bool {
+        self.adapter
+            .get_texture_format_features(format)
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    pub fn config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.config.format
+    }
+
+    /// See `set_surface_format_preferences`.
+    pub(crate) fn surface_format_preferences(&self) -> &[wgpu::TextureFormat] {
+        &self.format_preferences
+    }
+
+    pub fn surface_color_space(&self) -> SurfaceColorSpace {
+        SurfaceColorSpace::of(self.config.format)
+    }
+
+    /// Whether the platform converts the surface's colors for the display, taking them as
+    /// sRGB, see `color_space`. Always for a headless context.
+    pub fn is_color_managed(&self) -> bool {
+        self.is_headless()
+            || cfg!(any(target_os = "macos", target_os = "ios"))
+            || self.surface_color_space() == SurfaceColorSpace::ExtendedLinearSrgb
+    }
+
+    /// Tells the context the gamut of the display the window is on, which wgpu can't find
+    /// out, `None` for unknown. Only matters where colors aren't managed.
+    pub fn set_display_gamut(&mut self, gamut: Option<Gamut>) {
+        self.display_gamut = gamut;
+    }
+
+    pub fn display_gamut(&self) -> Option<Gamut> {
+        self.display_gamut
+    }
+
+    /// The gamut the colors written to the surface are shown in: sRGB where colors are
+    /// managed, or else the display's, sRGB too if unknown. Convert authored colors into
+    /// it, e.g. with `color_space::GamutConversion`.
+    pub fn output_gamut(&self) -> Gamut {
+        if self.is_color_managed() {
+            Gamut::Srgb
+        } else {
+            self.display_gamut.unwrap_or(Gamut::Srgb)
+        }
+    }
+
+    /// Switches the surface to extended linear sRGB where it can be, so colors beyond sRGB
+    /// such as Display P3 ones can be shown, and returns whether it is. Replaces the
+    /// preferences of `set_surface_format_preferences`, the same caveats apply.
+    pub fn enable_wide_gamut(&mut self) -> bool {
+        let format = self.set_surface_format_preferences(&[
+            wgpu::TextureFormat::Rgba16Float,
+            self.config.format,
+        ]);
+        SurfaceColorSpace::of(format) == SurfaceColorSpace::ExtendedLinearSrgb
+    }
+
+    /// The formats the surface can be configured with, best first as the platform sees
+    /// it. Empty while suspended. For a headless context, the formats the offscreen target
+    /// can be: any the adapter can render to among `preferences` given so far, and the
+    /// current one.
+    pub fn available_surface_formats(&self) -> Vec<wgpu::TextureFormat> {
+        match &self.target {
+            Target::Window { surface, .. } => surface
+                .as_ref()
+                .map(|surface| surface.get_capabilities(&self.adapter).formats)
+                .unwrap_or_default(),
+            Target::Offscreen(_) => {
+                let mut formats: Vec<_> = self
+                    .format_preferences
+                    .iter()
+                    .copied()
+                    .filter(|&format| self.can_render_to(format))
+                    .collect();
+                if !formats.contains(&self.config.format) {
+                    formats.push(self.config.format);
+                }
+                formats
+            }
+        }
+    }
+
+    fn can_render_to(&self, format: wgpu::TextureFormat) -> bool {
+        self.adapter
+            .get_texture_format_features(format)
+            .allowed_usages
+            .contains(OFFSCREEN_USAGE)
+    }
+
+    /// Switches the surface to the first of `preferences` it supports, e.g.
+    /// `[Rgba16Float, Bgra8UnormSrgb]` for HDR where there is any, or else the first sRGB
+    /// format as by default. The preferences are kept for surfaces created on resume.
+    /// Returns the format chosen; pipelines made for the previous one need recreating, so
+    /// call this in the app's `init`, before creating any.
+    pub fn set_surface_format_preferences(
+        &mut self,
+        preferences: &[wgpu::TextureFormat],
+    ) -> wgpu::TextureFormat {
+        self.format_preferences = preferences.to_vec();
+        let format = match &self.target {
+            Target::Window { surface: None, .. } => return self.config.format,
+            Target::Window { .. } => {
+                let available = self.available_surface_formats();
+                if available.is_empty() {
+                    log::warn!("the adapter can't present to the surface");
+                    return self.config.format;
+                }
+                choose_surface_format(&available, preferences)
+            }
+            Target::Offscreen(_) => self.available_surface_formats()[0],
+        };
+        if format == self.config.format {
+            return format;
+        }
+        log::info!("surface format {:?}", format);
+        self.config.format = format;
+        match &mut self.target {
+            Target::Window {
+                surface: Some(surface),
+                ..
+            } => surface.configure(&self.device, &self.config),
+            Target::Window { surface: None, .. } => {}
+            Target::Offscreen(texture) => {
+                *texture = Some(Self::create_offscreen_texture(&self.device, &self.config));
+            }
+        }
+        format
+    }
+
+    /// Whether frames can be drawn, `false` for a window while the app is suspended.
+    pub fn has_surface(&self) -> bool {
+        match &self.target {
+            Target::Window { surface, .. } => surface.is_some(),
+            Target::Offscreen(_) => true,
+        }
+    }
+
+    /// Creates the window's surface if there is none, e.g. when an Android app comes back
+    /// to the foreground. Keeps the surface format the app's pipelines were made for if
+    /// the new surface supports it. The run loop calls this on `Event::Resumed`.
+    pub fn resume(&mut self) {
+        let Target::Window { surface, window } = &mut self.target else {
+            return;
+        };
+        if surface.is_some() {
+            return;
+        }
+        let new_surface = match unsafe { self.instance.create_surface(&**window) } {
+            Ok(surface) => surface,
+            Err(e) => {
+                log::warn!("failed to create a surface on resume: {}", e);
+                return;
+            }
+        };
+        let caps = new_surface.get_capabilities(&self.adapter);
+        if caps.formats.is_empty() {
+            log::warn!("the adapter can't present to the resumed surface");
+            return;
+        }
+        if !caps.formats.contains(&self.config.format) {
+            let format = choose_surface_format(&caps.formats, &self.format_preferences);
+            log::warn!(
+                "surface format {:?} not supported after resume, using {:?}",
+                self.config.format,
+                format
+            );
+            self.config.format = format;
+        }
+        if !caps.alpha_modes.contains(&self.config.alpha_mode) {
+            self.config.alpha_mode = caps.alpha_modes[0];
+        }
+        let size = surface_size(window);
+        if size.width > 0 && size.height > 0 {
+            self.size = size;
+            self.config.width = size.width;
+            self.config.height = size.height;
+        }
+        new_surface.configure(&self.device, &self.config);
+        *surface = Some(new_surface);
+    }
+
+    /// Drops the window's surface, which Android requires once the app is in the
+    /// background. Frames are skipped until `resume`. The run loop calls this on
+    /// `Event::Suspended`.
+    pub fn suspend(&mut self) {
+        if let Target::Window { surface, .. } = &mut self.target {
+            *surface = None;
+        }
+    }
+
+    /// Switches how frames are presented, falling back to `Fifo`, which every surface
+    /// supports, if the surface can't do `mode`.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) {
+        let Target::Window {
+            surface: Some(surface),
+            ..
+        } = &self.target
+        else {
+            self.config.present_mode = mode;
+            return;
+        };
+        let supported = surface.get_capabilities(&self.adapter).present_modes;
+        // wgpu picks a supported mode for the automatic ones itself
+        let automatic = matches!(
+            mode,
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+        );
+        self.config.present_mode = if automatic || supported.contains(&mode) {
+            mode
+        } else {
+            log::warn!("present mode {:?} not supported, using Fifo", mode);
+            wgpu::PresentMode::Fifo
+        };
+        surface.configure(&self.device, &self.config);
+    }
+
+    /// Sets the samples per pixel apps should draw with, e.g. from `GraphicsSettings::msaa`,
+    /// falling back to 1 if the surface format can't be multisampled that much. The crate's
+    /// renderers don't read it, pass `sample_count` to their `with_sample_count`.
+    pub fn set_sample_count(&mut self, samples: u32) {
+        let supported = self
+            .adapter
+            .get_texture_format_features(self.config.format)
+            .flags
+            .sample_count_supported(samples);
+        self.sample_count = if supported {
+            samples
+        } else {
+            log::warn!(
+                "{}x MSAA not supported for {:?}, using 1",
+                samples,
+                self.config.format
+            );
+            1
+        };
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// How many submitted frames the GPU may still be working on when the next one starts,
+    /// at least 1. Lower means less input latency, higher smoother frame times when frame
+    /// costs vary. wgpu 0.18 has no swapchain latency setting, so this is kept by waiting
+    /// for the oldest frame's submission before a new frame starts.
+    pub fn set_max_frames_in_flight(&mut self, frames: usize) {
+        self.max_frames_in_flight = frames.max(1);
+    }
+
+    pub fn max_frames_in_flight(&self) -> usize {
+        self.max_frames_in_flight
+    }
+
+    /// Time the last frame spent waiting for earlier ones in `pace_frame`.
+    pub fn pacing_wait(&self) -> Duration {
+        self.pacing_wait
+    }
+
+    /// Waits until the GPU is far enough behind for another frame to start, as
+    /// `frame_pacing` and `max_frames_in_flight` say. The run loop calls this before
+    /// `App::update`, so input is read after the wait.
+    pub fn pace_frame(&mut self) {
+        profile_scope!("pace");
+        let allowed = match self.frame_pacing {
+            FramePacing::Throughput => self.max_frames_in_flight - 1,
+            FramePacing::LowLatency => 0,
+        };
+        let start = Instant::now();
+        let excess = self.in_flight.len().saturating_sub(allowed);
+        // waiting for the newest of them covers the ones before
+        if let Some(submission) = self.in_flight.drain(..excess).next_back() {
+            self.device
+                .poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+        }
+        self.pacing_wait = start.elapsed();
+    }
+
+    /// Switches vsync off while frames keep missing the refresh and back on when they fit
+    /// again, see `AdaptiveVsync`. `None` leaves the present mode as it is now. A refresh
+    /// rate of 0 is taken from the window's monitor, or 60 Hz if that's unknown.
+    pub fn set_adaptive_vsync(&mut self, adaptive: Option<AdaptiveVsync>) {
+        self.adaptive_vsync = adaptive.map(|mut adaptive| {
+            if adaptive.refresh_rate <= 0.0 {
+                adaptive.refresh_rate = match &self.target {
+                    Target::Window { window, .. } => window
+                        .current_monitor()
+                        .and_then(|monitor| monitor.refresh_rate_millihertz())
+                        .map_or(60.0, |millihertz| millihertz as f32 / 1000.0),
+                    Target::Offscreen(_) => 60.0,
+                };
+            }
+            adaptive
+        });
+        if let Some(mode) = self
+            .adaptive_vsync
+            .as_ref()
+            .map(AdaptiveVsync::present_mode)
+        {
+            self.set_present_mode(mode);
+        }
+    }
+
+    pub fn adaptive_vsync(&self) -> Option<&AdaptiveVsync> {
+        self.adaptive_vsync.as_ref()
+    }
+
+    /// Present mode switches the adaptive vsync made at the end of the last frame.
+    pub fn vsync_events(&self) -> &[VsyncEvent] {
+        &self.vsync_events
+    }
+
+    fn update_vsync(&mut self) {
+        let now = Instant::now();
+        let interval = self.last_frame_end.replace(now).map(|last| now - last);
+        let Some(adaptive) = &mut self.adaptive_vsync else {
+            return;
+        };
+        let Some(event) = interval.and_then(|interval| adaptive.record(interval.as_secs_f32()))
+        else {
+            return;
+        };
+        match event {
+            VsyncEvent::Disabled { missed, window } => {
+                log::info!(
+                    "{} of {} frames missed the refresh, vsync off",
+                    missed,
+                    window
+                )
+            }
+            VsyncEvent::Enabled => log::info!("frames fit in the refresh again, vsync on"),
+        }
+        let mode = adaptive.present_mode();
+        self.set_present_mode(mode);
+        self.vsync_events.push(event);
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.size
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.size.width as f32 / self.size.height.max(1) as f32
+    }
+
+    pub(crate) fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width == 0 && new_size.height == 0 {
+            return;
+        }
+        self.size = new_size;
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        match &mut self.target {
+            Target::Window { surface, .. } => {
+                if let Some(surface) = surface {
+                    surface.configure(&self.device, &self.config);
+                }
+            }
+            Target::Offscreen(texture) => {
+                *texture = Some(Self::create_offscreen_texture(&self.device, &self.config));
+            }
+        }
+    }
+
+    /// Resizes the offscreen target of a headless context, or the surface of a window
+    /// (the window itself keeps its size).
+    pub fn resize_headless(&mut self, width: u32, height: u32) {
+        self.resize(winit::dpi::PhysicalSize::new(width.max(1), height.max(1)));
+    }
+
+    pub(crate) fn begin_frame(&mut self) -> Result<Frame, wgpu::SurfaceError> {
+        #[cfg(feature = "ecs")]
+        self.draw_lists.extract(&self.world);
+        self.vsync_events.clear();
+        self.crash_dump.set_config(&self.config);
+        self.crash_dump.marker("begin frame");
+        profile_scope!("acquire");
+        let output = match &mut self.target {
+            Target::Window { surface, .. } => match surface {
+                Some(surface) => FrameOutput::Surface(surface.get_current_texture()?),
+                // suspended, there is nothing to draw into
+                None => return Err(wgpu::SurfaceError::Lost),
+            },
+            Target::Offscreen(texture) => FrameOutput::Texture(
+                texture
+                    .take()
+                    .expect("begin_frame called while a frame is in flight"),
+            ),
+        };
+        let encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        Ok(Frame::new(output, encoder, self.crash_dump.clone()))
+    }
+
+    pub(crate) fn end_frame(&mut self, frame: Frame) {
+        let (output, mut encoder) = frame.finish();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(recorder) = &mut self.recorder {
+            recorder.capture(&self.device, &mut encoder, output.texture());
+        }
+        self.crash_dump.marker("submit frame");
+        {
+            profile_scope!("submit");
+            let submission = self.queue.submit(std::iter::once(encoder.finish()));
+            self.in_flight.push_back(submission);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(recorder) = &mut self.recorder {
+            recorder.after_submit(&self.device);
+        }
+        match output {
+            FrameOutput::Surface(output) => {
+                profile_scope!("present");
+                output.present();
+            }
+            FrameOutput::Texture(texture) => {
+                if let Target::Offscreen(slot) = &mut self.target {
+                    *slot = Some(texture);
+                }
+            }
+        }
+        self.update_vsync();
+        crate::profile::finish_frame();
+    }
+
+    /// Records and submits one frame without a run loop, as the benchmark and test
+    /// helpers do. Returns whatever `draw` returns, after the frame is submitted.
+    pub fn render_frame<T>(
+        &mut self,
+        draw: impl FnOnce(&mut Self, &mut Frame) -> T,
+    ) -> Result<T, wgpu::SurfaceError> {
+        self.pace_frame();
+        let mut frame = self.begin_frame()?;
+        let result = draw(self, &mut frame);
+        self.end_frame(frame);
+        Ok(result)
+    }
+
+    /// Starts writing every frame presented to `dir` as `frame_00000.png`,
+    /// `frame_00001.png` and so on, replacing any recording in progress.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start_recording(
+        &mut self,
+        dir: impl Into<std::path::PathBuf>,
+    ) -> Result<(), RecordingError> {
+        self.start_recording_with(PngSequence::new(dir)?)
+    }
+
+    /// Starts handing every frame presented to `encoder`, replacing any recording in
+    /// progress. Frames reach it a few frames late, on a thread of its own.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start_recording_with(
+        &mut self,
+        encoder: impl FrameEncoder,
+    ) -> Result<(), RecordingError> {
+        self.stop_recording();
+        let recorder = FrameRecorder::new(self.config.format, Box::new(encoder))?;
+        if let Target::Window {
+            surface: Some(surface),
+            ..
+        } = &self.target
+        {
+            if !self.config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+                let usages = surface.get_capabilities(&self.adapter).usages;
+                if !usages.contains(wgpu::TextureUsages::COPY_SRC) {
+                    return Err(RecordingError::NotCopyable);
+                }
+                self.config.usage |= wgpu::TextureUsages::COPY_SRC;
+                surface.configure(&self.device, &self.config);
+            }
+        }
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Finishes encoding the frames recorded so far, returns how many there were.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stop_recording(&mut self) -> u32 {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(&self.device),
+            None => 0,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// The offscreen target of a headless context, holding the last frame drawn.
+    pub fn offscreen_texture(&self) -> Option<&wgpu::Texture> {
+        match &self.target {
+            Target::Offscreen(texture) => texture.as_ref(),
+            Target::Window { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Diagnostics written to a file when the app panics, for bug reports that say more than
+//! "it crashed".
+//!
+//! Every `Context` keeps a `CrashDump` with the adapter, its features and limits and the
+//! surface configuration, along with the last debug markers recorded through `Frame` and
+//! the last GPU errors. wgpu 0.18 reports a lost device and validation errors as uncaptured
+//! errors, which the dump records before panicking like wgpu would; a panic hook then
+//! writes everything to `crash_<seconds since 1970>.txt` in `dir` and logs where.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Once, Weak};
+
+use crate::time::{SystemTime, UNIX_EPOCH};
+
+/// Debug markers kept, older ones are dropped.
+pub const MAX_MARKERS: usize = 64;
+/// GPU errors kept.
+pub const MAX_ERRORS: usize = 16;
+
+struct State {
+    enabled: bool,
+    dir: PathBuf,
+    adapter: Option<(wgpu::AdapterInfo, wgpu::Features, wgpu::Limits)>,
+    config: Option<wgpu::SurfaceConfiguration>,
+    markers: VecDeque<String>,
+    errors: VecDeque<String>,
+    /// Where the dump went, it's only written once.
+    written: Option<PathBuf>,
+}
+
+/// The last moments of a context, shared with its panic hook and error handler. Cloning
+/// gives another handle to the same dump.
+#[derive(Clone)]
+pub struct CrashDump {
+    state: Arc<Mutex<State>>,
+}
+
+/// The dumps of every context alive, written by the one panic hook.
+static DUMPS: Mutex<Vec<Weak<Mutex<State>>>> = Mutex::new(Vec::new());
+static INSTALL_HOOK: Once = Once::new();
+
+impl CrashDump {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                enabled,
+                dir: PathBuf::from("."),
+                adapter: None,
+                config: None,
+                markers: VecDeque::new(),
+                errors: VecDeque::new(),
+                written: None,
+            })),
+        }
+    }
+
+    /// Records GPU errors and writes the dump on panics from now on. The panic hook is
+    /// installed once and calls the one that was there before.
+    pub(crate) fn install(&self, adapter: &wgpu::Adapter, device: &wgpu::Device) {
+        self.lock().adapter = Some((adapter.get_info(), device.features(), device.limits()));
+        let dump = self.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            dump.error(&error.to_string());
+            panic!("wgpu error: {}", error);
+        }));
+        DUMPS.lock().unwrap().push(Arc::downgrade(&self.state));
+        INSTALL_HOOK.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                let reason = info.to_string();
+                let dumps: Vec<_> = {
+                    let mut dumps = DUMPS.lock().unwrap_or_else(|e| e.into_inner());
+                    dumps.retain(|dump| dump.strong_count() > 0);
+                    dumps.iter().filter_map(|dump| dump.upgrade()).collect()
+                };
+                for state in dumps {
+                    let dump = CrashDump { state };
+                    if dump.is_enabled() {
+                        match dump.write(&reason) {
+                            Ok(path) => log::error!("crash dump written to {}", path.display()),
+                            Err(e) => log::error!("failed to write the crash dump: {}", e),
+                        }
+                    }
+                }
+                previous(info);
+            }));
+        });
+    }
+
+    /// Whether panics write the dump, on by default for windows and off for headless
+    /// contexts, whose tests panic on purpose.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.lock().enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lock().enabled
+    }
+
+    /// Where the dump is written, the working directory by default.
+    pub fn set_dir(&self, dir: impl Into<PathBuf>) {
+        self.lock().dir = dir.into();
+    }
+
+    /// Records a point reached, e.g. the start of a pass. `Frame`'s debug groups and
+    /// markers land here along with the graphics debugger.
+    pub fn marker(&self, label: &str) {
+        let mut state = self.lock();
+        if state.markers.len() == MAX_MARKERS {
+            state.markers.pop_front();
+        }
+        state.markers.push_back(label.to_string());
+    }
+
+    /// Records an error for the dump, GPU errors are recorded by themselves.
+    pub fn error(&self, message: &str) {
+        let mut state = self.lock();
+        if state.errors.len() == MAX_ERRORS {
+            state.errors.pop_front();
+        }
+        state.errors.push_back(message.to_string());
+    }
+
+    /// The markers recorded so far, oldest first.
+    pub fn markers(&self) -> Vec<String> {
+        self.lock().markers.iter().cloned().collect()
+    }
+
+    pub fn errors(&self) -> Vec<String> {
+        self.lock().errors.iter().cloned().collect()
+    }
+
+    pub(crate) fn set_config(&self, config: &wgpu::SurfaceConfiguration) {
+        let mut state = self.lock();
+        if state.config.as_ref() != Some(config) {
+            state.config = Some(config.clone());
+        }
+    }
+
+    /// The dump as it would be written now, with `reason` at the top.
+    pub fn report(&self, reason: &str) -> String {
+        let state = self.lock();
+        let mut report = String::new();
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let _ = writeln!(
+            report,
+            "{} {} crash dump",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        let _ = writeln!(report, "time: {} seconds since 1970", seconds);
+        let _ = writeln!(
+            report,
+            "os: {} {}",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        let _ = writeln!(report, "reason: {}", reason);
+        match &state.adapter {
+            Some((info, features, limits)) => {
+                let _ = writeln!(report, "\nadapter: {:#?}", info);
+                let _ = writeln!(report, "\nfeatures: {:?}", features);
+                let _ = writeln!(report, "\nlimits: {:#?}", limits);
+            }
+            None => report.push_str("\nadapter: none yet\n"),
+        }
+        match &state.config {
+            Some(config) => {
+                let _ = writeln!(report, "\nsurface: {:#?}", config);
+            }
+            None => report.push_str("\nsurface: not configured\n"),
+        }
+        report.push_str("\nlast debug markers, oldest first:\n");
+        for marker in &state.markers {
+            let _ = writeln!(report, "  {}", marker);
+        }
+        report.push_str("\nrecent errors, oldest first:\n");
+        for error in &state.errors {
+            let _ = writeln!(report, "  {}", error.replace('\n', "\n  "));
+        }
+        report
+    }
+
+    /// Writes the dump, once: later calls return where the first one went.
+    pub fn write(&self, reason: &str) -> std::io::Result<PathBuf> {
+        let (dir, written) = {
+            let state = self.lock();
+            (state.dir.clone(), state.written.clone())
+        };
+        if let Some(path) = written {
+            return Ok(path);
+        }
+        let report = self.report(reason);
+        let path = Self::path_in(&dir);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(&path, report)?;
+        self.lock().written = Some(path.clone());
+        Ok(path)
+    }
+
+    fn path_in(dir: &Path) -> PathBuf {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        dir.join(format!("crash_{}.txt", seconds))
+    }
+
+    /// Still usable after a panic poisoned it, which is when it's needed most.
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,170 @@
+//! An F3 style overlay with frame timings, adapter and surface details and the number of
+//! live resources owned by the crate's renderers.
+//!
+//! Put one in `Context::debug_overlay` and the run loop feeds it input and frame times and
+//! draws it on top of everything the app rendered.
+
+use std::collections::VecDeque;
+
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+use crate::context::Context;
+use crate::frame::Frame;
+use crate::math::Vec2;
+use crate::shapes::ShapeRenderer;
+use crate::stats;
+use crate::text::{Font, FontId, TextRenderer};
+
+/// Frame times kept for the graph.
+const HISTORY: usize = 120;
+/// Narrowest the panel gets, wider lines of text widen it.
+const MIN_WIDTH: f32 = 240.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+const FONT_SIZE: f32 = 14.0;
+const PADDING: f32 = 6.0;
+
+pub struct DebugOverlay {
+    shapes: ShapeRenderer,
+    text: TextRenderer,
+    font: FontId,
+    /// Seconds per frame, oldest first.
+    frame_times: VecDeque<f32>,
+    pub visible: bool,
+    /// Key that shows and hides the overlay.
+    pub toggle_key: VirtualKeyCode,
+    /// Top left corner in window pixels.
+    pub position: Vec2,
+}
+
+impl DebugOverlay {
+    /// Starts hidden, press F3 to show it.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, font: Font) -> Self {
+        let mut text = TextRenderer::new(device, format);
+        let font = text.add_font(font);
+        Self {
+            shapes: ShapeRenderer::new(device, format),
+            text,
+            font,
+            frame_times: VecDeque::with_capacity(HISTORY),
+            visible: false,
+            toggle_key: VirtualKeyCode::F3,
+            position: Vec2::splat(8.0),
+        }
+    }
+
+    /// Toggles visibility on the toggle key, returns `true` if the event was used.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if *key == self.toggle_key => {
+                self.visible = !self.visible;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Records the length of the last frame, in seconds.
+    pub fn record_frame(&mut self, dt: f32) {
+        if self.frame_times.len() == HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+    }
+
+    /// Average frame time over the history, in seconds.
+    pub fn average_frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
+    /// Draws the overlay over the frame's current contents, if it is visible.
+    pub fn draw(&mut self, ctx: &Context, frame: &mut Frame) {
+        if !self.visible {
+            return;
+        }
+        let info = ctx.adapter().get_info();
+        let size = ctx.size();
+        let memory = stats::memory_report();
+        let counts = memory.counts;
+        let average = self.average_frame_time();
+        let fps = if average > 0.0 { 1.0 / average } else { 0.0 };
+        let lines = [
+            format!("{:.0} fps  {:.2} ms", fps, average * 1000.0),
+            info.name.clone(),
+            format!("{:?} ({:?})", info.backend, info.device_type),
+            format!("{}x{} {:?}", size.width, size.height, ctx.surface_format()),
+            format!(
+                "{} pipelines  {} buffers  {} textures",
+                counts.pipelines, counts.buffers, counts.textures
+            ),
+            format!(
+                "{:.1} MiB in buffers and textures",
+                memory.total_bytes() as f64 / (1024.0 * 1024.0)
+            ),
+        ];
+
+        let sizes: Vec<Vec2> = lines
+            .iter()
+            .map(|line| self.text.measure(self.font, line, FONT_SIZE))
+            .collect();
+        let text_width = sizes.iter().map(|s| s.x).fold(MIN_WIDTH, f32::max);
+        let text_height: f32 = sizes.iter().map(|s| s.y).sum();
+        let graph_min = self.position + Vec2::new(PADDING, PADDING * 2.0 + text_height);
+        let graph_max = graph_min + Vec2::new(text_width, GRAPH_HEIGHT);
+        self.shapes.fill_rect(
+            self.position,
+            graph_max + Vec2::splat(PADDING),
+            [0.0, 0.0, 0.0, 0.6],
+        );
+
+        let mut pen = self.position + Vec2::splat(PADDING);
+        for (line, line_size) in lines.iter().zip(&sizes) {
+            self.text.draw(self.font, line, pen, FONT_SIZE, [1.0; 4]);
+            pen.y += line_size.y;
+        }
+
+        // scaled so a 30 fps frame reaches the top, unless something was slower
+        let max_time = self.frame_times.iter().copied().fold(1.0 / 30.0, f32::max);
+        let bar_width = text_width / HISTORY as f32;
+        let first = HISTORY - self.frame_times.len();
+        for (i, &dt) in self.frame_times.iter().enumerate() {
+            let x = graph_min.x + (first + i) as f32 * bar_width;
+            let color = if dt > 1.0 / 30.0 {
+                [0.95, 0.3, 0.25, 1.0]
+            } else if dt > 1.0 / 59.0 {
+                [0.95, 0.8, 0.25, 1.0]
+            } else {
+                [0.35, 0.85, 0.4, 1.0]
+            };
+            self.shapes.fill_rect(
+                Vec2::new(x, graph_max.y - dt / max_time * GRAPH_HEIGHT),
+                Vec2::new(x + bar_width, graph_max.y),
+                color,
+            );
+        }
+        // 60 fps mark
+        let y = graph_max.y - (1.0 / 60.0) / max_time * GRAPH_HEIGHT;
+        self.shapes.fill_rect(
+            Vec2::new(graph_min.x, y),
+            Vec2::new(graph_max.x, y + 1.0),
+            [1.0, 1.0, 1.0, 0.5],
+        );
+
+        let view_proj = ShapeRenderer::pixel_projection(size.width as f32, size.height as f32);
+        self.shapes.prepare(ctx.device(), ctx.queue(), view_proj);
+        self.text.prepare(ctx.device(), ctx.queue(), view_proj);
+        let mut render_pass = frame.begin_named_pass("Debug Overlay Pass", None);
+        self.shapes.render(&mut render_pass);
+        self.text.render(&mut render_pass);
+    }
+}
@@ -0,0 +1,416 @@
+//! Projected decals: images stamped onto whatever is inside a box, for bullet holes,
+//! stains and scorch marks, without touching the meshes underneath.
+//!
+//! Decals are drawn after the opaque pass, reading its depth back to find the surface
+//! under each pixel of a decal's box. The depth texture needs
+//! `TextureUsages::TEXTURE_BINDING`, and since it's sampled it can't be the depth
+//! attachment of the pass the decals are drawn in.
+
+use crate::camera::Camera;
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::math::{Mat4, Vec2};
+use crate::stats::Tracked;
+use crate::texture::Texture;
+use crate::transform::Transform;
+
+const SHADER: &str = r#"
+@group(1) @binding(0) var decal_texture: texture_2d<f32>;
+@group(1) @binding(1) var decal_sampler: sampler;
+@group(2) @binding(0) var scene_depth: texture_2d<f32>;
+
+struct InstanceInput {
+    // rows of the box's affine transform and of its inverse
+    @location(0) model_0: vec4<f32>,
+    @location(1) model_1: vec4<f32>,
+    @location(2) model_2: vec4<f32>,
+    @location(3) inverse_0: vec4<f32>,
+    @location(4) inverse_1: vec4<f32>,
+    @location(5) inverse_2: vec4<f32>,
+    // xyz the direction the decal faces, w the normal threshold
+    @location(6) axis: vec4<f32>,
+    @location(7) uv_rect: vec4<f32>,
+    @location(8) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) inverse_0: vec4<f32>,
+    @location(1) @interpolate(flat) inverse_1: vec4<f32>,
+    @location(2) @interpolate(flat) inverse_2: vec4<f32>,
+    @location(3) @interpolate(flat) axis: vec4<f32>,
+    @location(4) @interpolate(flat) uv_rect: vec4<f32>,
+    @location(5) @interpolate(flat) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: InstanceInput) -> VertexOutput {
+    // a unit cube as a 14 vertex triangle strip
+    let bit = 1u << index;
+    let corner = vec4<f32>(
+        select(-0.5, 0.5, (0x287au & bit) != 0u),
+        select(-0.5, 0.5, (0x02afu & bit) != 0u),
+        select(-0.5, 0.5, (0x31e3u & bit) != 0u),
+        1.0,
+    );
+    let world = vec3<f32>(
+        dot(instance.model_0, corner),
+        dot(instance.model_1, corner),
+        dot(instance.model_2, corner),
+    );
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(world, 1.0);
+    out.inverse_0 = instance.inverse_0;
+    out.inverse_1 = instance.inverse_1;
+    out.inverse_2 = instance.inverse_2;
+    out.axis = instance.axis;
+    out.uv_rect = instance.uv_rect;
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(scene_depth));
+    let depth = textureLoad(scene_depth, vec2<i32>(in.clip_position.xy), 0).r;
+    let ndc = vec2<f32>(in.clip_position.x / size.x * 2.0 - 1.0, 1.0 - in.clip_position.y / size.y * 2.0);
+    let unprojected = globals.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+    let world = vec4<f32>(unprojected.xyz / unprojected.w, 1.0);
+    let local = vec3<f32>(dot(in.inverse_0, world), dot(in.inverse_1, world), dot(in.inverse_2, world));
+
+    // the surface's normal from how the reconstructed position changes across pixels,
+    // turned towards the camera
+    var normal = normalize(cross(dpdx(world.xyz), dpdy(world.xyz)));
+    if dot(normal, globals.eye.xyz - world.xyz) < 0.0 {
+        normal = -normal;
+    }
+
+    let uv = mix(in.uv_rect.xy, in.uv_rect.zw, vec2<f32>(local.x + 0.5, 0.5 - local.z));
+    let color = textureSample(decal_texture, decal_sampler, uv) * in.color;
+    if any(abs(local) > vec3<f32>(0.5)) || dot(normal, in.axis.xyz) < in.axis.w {
+        discard;
+    }
+    return color;
+}
+"#;
+
+/// An image projected down the local -y axis of a box onto the surfaces inside it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Decal {
+    /// Places a unit cube centred on the origin. The image covers its local x and z, with
+    /// its top towards -z, and the scale's y is how far above and below the transform's
+    /// position surfaces are stamped.
+    pub transform: Transform,
+    /// Texture region in UVs, for decals sharing an atlas.
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+    pub color: [f32; 4],
+    /// Surfaces are left alone where the cosine of the angle between their normal and the
+    /// decal's y axis is below this, so a decal on a floor doesn't smear down the walls.
+    pub normal_threshold: f32,
+}
+
+impl Decal {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            transform,
+            uv_min: Vec2::ZERO,
+            uv_max: Vec2::ONE,
+            color: [1.0; 4],
+            normal_threshold: 0.5,
+        }
+    }
+
+    pub fn with_region(mut self, uv_min: Vec2, uv_max: Vec2) -> Self {
+        self.uv_min = uv_min;
+        self.uv_max = uv_max;
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_normal_threshold(mut self, threshold: f32) -> Self {
+        self.normal_threshold = threshold;
+        self
+    }
+
+    fn instance(&self) -> DecalInstance {
+        let model = self.transform.matrix();
+        let inverse = model.inverse();
+        let row = |matrix: &Mat4, i| matrix.row(i).to_array();
+        DecalInstance {
+            model: [row(&model, 0), row(&model, 1), row(&model, 2)],
+            inverse: [row(&inverse, 0), row(&inverse, 1), row(&inverse, 2)],
+            axis: self
+                .transform
+                .up()
+                .normalize()
+                .extend(self.normal_threshold)
+                .to_array(),
+            uv_rect: [self.uv_min.x, self.uv_min.y, self.uv_max.x, self.uv_max.y],
+            color: self.color,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct DecalInstance {
+    model: [[f32; 4]; 3],
+    inverse: [[f32; 4]; 3],
+    axis: [f32; 4],
+    uv_rect: [f32; 4],
+    color: [f32; 4],
+}
+unsafe impl bytemuck::Pod for DecalInstance {}
+unsafe impl bytemuck::Zeroable for DecalInstance {}
+
+impl DecalInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 9] = wgpu::vertex_attr_array![
+        0 => Float32x4,
+        1 => Float32x4,
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4,
+        7 => Float32x4,
+        8 => Float32x4
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Draws queued decals with one texture, usually an atlas the decals pick regions of.
+/// Decals overlapping each other blend in the order they were queued.
+pub struct DecalRenderer {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    texture_bind_group: wgpu::BindGroup,
+    depth_layout: wgpu::BindGroupLayout,
+    depth_bind_group: Option<wgpu::BindGroup>,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    queued: Vec<DecalInstance>,
+    count: u32,
+    texture: Texture,
+    tracked: Tracked,
+}
+
+impl DecalRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, texture: Texture) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Decal Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Decal Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Decal Globals Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Decal Globals Bind Group"),
+            layout: &globals_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Decal Texture Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Decal Texture Bind Group"),
+            layout: &texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        });
+
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Decal Depth Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    // loaded as a float texture, GL can't load from depth textures
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Decal Pipeline Layout"),
+            bind_group_layouts: &[&globals_layout, &texture_layout, &depth_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Decal Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[DecalInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            // only the box's far side, so each pixel is stamped once and the decal still
+            // shows with the camera inside the box
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                cull_mode: Some(wgpu::Face::Front),
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let instance_capacity = 64;
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+        let tracked = Tracked::new(1, 2, 0)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&instance_buffer);
+
+        Self {
+            pipeline,
+            globals_buffer,
+            globals_bind_group,
+            texture_bind_group,
+            depth_layout,
+            depth_bind_group: None,
+            instance_buffer,
+            instance_capacity,
+            queued: Vec::new(),
+            count: 0,
+            texture,
+            tracked,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Decal Instance Buffer"),
+            size: (capacity * std::mem::size_of::<DecalInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// The depth of the opaque pass to project onto. Set it again when the depth texture
+    /// is recreated, e.g. on resize.
+    pub fn set_depth(&mut self, device: &wgpu::Device, depth: &wgpu::TextureView) {
+        self.depth_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Decal Depth Bind Group"),
+            layout: &self.depth_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(depth),
+            }],
+        }));
+    }
+
+    pub fn draw(&mut self, decal: &Decal) {
+        self.queued.push(decal.instance());
+    }
+
+    /// Uploads the decals queued since the last call, seen from `camera`.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera) {
+        let globals = FrameGlobals::from_camera(camera);
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        if self.queued.len() > self.instance_capacity {
+            self.instance_capacity = self.queued.len().next_power_of_two();
+            let instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+            self.tracked
+                .replace_buffer(&self.instance_buffer, &instance_buffer);
+            self.instance_buffer = instance_buffer;
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.queued));
+        self.count = self.queued.len() as u32;
+        self.queued.clear();
+    }
+
+    /// Draws into a pass without a depth attachment, over the finished opaque pass.
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.count == 0 {
+            return;
+        }
+        let Some(depth) = &self.depth_bind_group else {
+            log::warn!("decals drawn before the depth to project onto was set");
+            return;
+        };
+        render_pass.push_debug_group("Decals");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.texture_bind_group, &[]);
+        render_pass.set_bind_group(2, depth, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..14, 0..self.count);
+        render_pass.pop_debug_group();
+    }
+}
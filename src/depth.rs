@@ -0,0 +1,206 @@
+//! Which way depth runs, for every built-in pipeline and projection.
+//!
+//! With the default `DepthMode::Standard` depth goes from 0 at the near plane to 1 at the
+//! far one. `DepthMode::Reversed` runs it from 1 at the near plane to 0 at infinity: float
+//! depth has most of its precision near 0, which then lands on the far distances where
+//! z-fighting happens, and the far plane goes away. It needs a float depth format such as
+//! `Depth32Float` to pay off, and on GL drivers without clip control, which squeeze depth
+//! into -1..1 and back, it's only as precise as standard depth.
+//!
+//! The mode is global. Set it before creating renderers, whose pipelines keep the depth
+//! test they were created with; `Camera::projection`, the passes the crate begins and the
+//! effects reading depth follow it as they're used.
+//!
+//! `DepthTarget` is a depth texture to draw with, `Depth32Float` or, made `with_stencil`,
+//! `Depth24PlusStencil8` for pipelines testing the stencil too.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::stats::Tracked;
+
+/// Format of a `DepthTarget` without stencil.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// Format of a `DepthTarget` with 8 bits of stencil.
+pub const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+static REVERSED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DepthMode {
+    /// Near at 0, far at 1, tested with `Less`.
+    #[default]
+    Standard,
+    /// Near at 1, infinitely far at 0, tested with `Greater`. Cameras' `zfar` is unused.
+    Reversed,
+}
+
+impl DepthMode {
+    /// The depth test keeping what's nearer.
+    pub fn compare(self) -> wgpu::CompareFunction {
+        match self {
+            DepthMode::Standard => wgpu::CompareFunction::Less,
+            DepthMode::Reversed => wgpu::CompareFunction::Greater,
+        }
+    }
+
+    /// The depth of nothing drawn, to clear depth targets to.
+    pub fn clear_value(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::Reversed => 0.0,
+        }
+    }
+
+    /// The depth of the near plane.
+    pub fn near_value(self) -> f32 {
+        1.0 - self.clear_value()
+    }
+
+    pub fn is_reversed(self) -> bool {
+        self == DepthMode::Reversed
+    }
+}
+
+pub fn set_mode(mode: DepthMode) {
+    REVERSED.store(mode.is_reversed(), Ordering::Relaxed);
+}
+
+pub fn mode() -> DepthMode {
+    if REVERSED.load(Ordering::Relaxed) {
+        DepthMode::Reversed
+    } else {
+        DepthMode::Standard
+    }
+}
+
+/// A depth test and write in `format` following the mode, as the built-in pipelines use.
+pub fn depth_stencil(format: wgpu::TextureFormat) -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format,
+        depth_write_enabled: true,
+        depth_compare: mode().compare(),
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+/// A depth texture the size of what's drawn, with or without stencil.
+pub struct DepthTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    _tracked: Tracked,
+}
+
+impl DepthTarget {
+    /// A `DEPTH_FORMAT` target.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Self::multisampled(device, DEPTH_FORMAT, width, height, 1)
+    }
+
+    /// A `DEPTH_STENCIL_FORMAT` target, for masks and portals.
+    pub fn with_stencil(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Self::multisampled(device, DEPTH_STENCIL_FORMAT, width, height, 1)
+    }
+
+    /// A target of `format` with `sample_count` samples per pixel, for passes drawing into
+    /// multisampled color targets. Effects can't read multisampled depth, so above 1 it's
+    /// only an attachment, which on GL also keeps alpha-to-coverage working.
+    pub fn multisampled(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Target"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: if sample_count > 1 {
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+            } else {
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+            },
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Depth Target View"),
+            ..Default::default()
+        });
+        // effects can only bind one aspect of a depth stencil texture
+        let depth_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Depth Target Depth View"),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+        let tracked = Tracked::new(0, 0, 1).with_texture(&texture);
+        Self {
+            texture,
+            view,
+            depth_view,
+            _tracked: tracked,
+        }
+    }
+
+    /// Recreates the texture at a new size, keeping the format and sample count.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let sample_count = self.texture.sample_count();
+        *self = Self::multisampled(device, self.format(), width, height, sample_count);
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.texture.format()
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        self.format().has_stencil_aspect()
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// The view passes draw with, see `attachment`.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// The depth aspect alone, to read in effects such as `DepthOfField::set_depth` when
+    /// the target isn't multisampled.
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth_view
+    }
+
+    /// The attachment of a pass drawing with the target. `clear` clears depth to the mode's
+    /// `clear_value` and the stencil to 0, otherwise both are kept.
+    pub fn attachment(&self, clear: bool) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        let store = wgpu::StoreOp::Store;
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.view,
+            depth_ops: Some(wgpu::Operations {
+                load: if clear {
+                    wgpu::LoadOp::Clear(mode().clear_value())
+                } else {
+                    wgpu::LoadOp::Load
+                },
+                store,
+            }),
+            stencil_ops: self.has_stencil().then_some(wgpu::Operations {
+                load: if clear {
+                    wgpu::LoadOp::Clear(0)
+                } else {
+                    wgpu::LoadOp::Load
+                },
+                store,
+            }),
+        }
+    }
+}
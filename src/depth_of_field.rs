@@ -0,0 +1,345 @@
+//! Depth of field: blurring what's out of focus, as a `PostEffect`.
+//!
+//! A first pass works out each pixel's circle of confusion, how many pixels wide it
+//! would be smeared by a lens with `aperture` focused at the `Focus`, from the scene's
+//! depth. Two blur passes, horizontal then vertical, then gather every neighbour whose
+//! circle reaches the pixel, scatter as gather, so blurry foreground spills over what's
+//! in focus behind it but not the other way around.
+//!
+//! The depth is the opaque pass's, given to `set_depth` and read like `DecalRenderer`
+//! does, so it needs `TextureUsages::TEXTURE_BINDING`.
+
+use crate::camera::Camera;
+use crate::depth;
+use crate::math::Vec3;
+use crate::post::{PostEffect, PostFrame, PostPipeline, PostTarget};
+use crate::stats::Tracked;
+
+/// Format of the frame with each pixel's circle of confusion in alpha.
+const COC_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Samples on each side of a pixel, spread out further for larger radii.
+const MAX_TAPS: f32 = 16.0;
+
+const COC_SHADER: &str = r#"
+struct Params {
+    focus_distance: f32,
+    // aperture times the focal length in pixels
+    aperture: f32,
+    znear: f32,
+    zfar: f32,
+    max_radius: f32,
+    autofocus: u32,
+    focus_pixel: vec2<u32>,
+    reversed: u32,
+};
+
+// loaded as a float texture, GL can't load from depth textures
+@group(1) @binding(0) var scene_depth: texture_2d<f32>;
+
+fn linear_depth(depth: f32) -> f32 {
+    if params.reversed != 0u {
+        // infinitely far at 0
+        return params.znear / max(depth, 1e-7);
+    }
+    return params.znear * params.zfar / (params.zfar - depth * (params.zfar - params.znear));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    let size = vec2<u32>(textureDimensions(scene_depth));
+    let z = linear_depth(textureLoad(scene_depth, vec2<i32>(min(vec2<u32>(in.position.xy), size - 1u)), 0).r);
+    var focus = params.focus_distance;
+    if params.autofocus != 0u {
+        focus = linear_depth(textureLoad(scene_depth, vec2<i32>(min(params.focus_pixel, size - 1u)), 0).r);
+    }
+    // negative in front of the focus, positive behind
+    let coc = params.aperture * (z - focus) / (z * max(focus, 1e-4));
+    return vec4<f32>(color.rgb, clamp(coc, -params.max_radius, params.max_radius));
+}
+"#;
+
+const BLUR_SHADER: &str = r#"
+struct Params {
+    direction: vec2<f32>,
+    max_radius: f32,
+    step: f32,
+    last: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = params.direction / vec2<f32>(textureDimensions(source));
+    let centre = textureSample(source, source_sampler, in.uv);
+    let taps = i32(ceil(params.max_radius / params.step));
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = -taps; i <= taps; i = i + 1) {
+        let offset = f32(i) * params.step;
+        let sample = textureSample(source, source_sampler, in.uv + texel * offset);
+        // what's behind the centre only blurs as far as the centre itself does, so the
+        // background doesn't bleed over a sharp foreground
+        let reach = select(min(abs(sample.a), abs(centre.a)), abs(sample.a), sample.a <= centre.a);
+        let w = clamp(reach - abs(offset) + 1.0, 0.0, 1.0);
+        sum = sum + sample.rgb * w;
+        weight = weight + w;
+    }
+    let alpha = select(centre.a, 1.0, params.last != 0u);
+    return vec4<f32>(sum / weight, alpha);
+}
+"#;
+
+/// What the lens is focused on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Focus {
+    /// Distance from the camera along its view direction.
+    Distance(f32),
+    /// Whatever is under a pixel, in pixels from the top left, looked up on the GPU every
+    /// frame. Give it the cursor `Picker::pick` reads to focus on what's picked.
+    Pixel([u32; 2]),
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct CocParams {
+    focus_distance: f32,
+    aperture: f32,
+    znear: f32,
+    zfar: f32,
+    max_radius: f32,
+    autofocus: u32,
+    focus_pixel: [u32; 2],
+    reversed: u32,
+    _padding: u32,
+}
+unsafe impl bytemuck::Pod for CocParams {}
+unsafe impl bytemuck::Zeroable for CocParams {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct BlurParams {
+    direction: [f32; 2],
+    max_radius: f32,
+    step: f32,
+    last: u32,
+    _padding: u32,
+}
+unsafe impl bytemuck::Pod for BlurParams {}
+unsafe impl bytemuck::Zeroable for BlurParams {}
+
+pub struct DepthOfField {
+    pub focus: Focus,
+    /// Diameter of the lens in world units, larger blurs more, 0 keeps everything sharp.
+    pub aperture: f32,
+    /// Largest blur radius in pixels.
+    pub max_radius: f32,
+    camera: Camera,
+    coc_pass: PostPipeline,
+    horizontal_pass: PostPipeline,
+    vertical_pass: PostPipeline,
+    depth_layout: wgpu::BindGroupLayout,
+    depth_bind_group: Option<wgpu::BindGroup>,
+    targets: [(wgpu::Texture, wgpu::TextureView); 2],
+    tracked: Tracked,
+}
+
+impl DepthOfField {
+    /// For a chain of `width` by `height`.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth Of Field Depth Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let coc_pass = PostPipeline::new(
+            device,
+            "Depth Of Field CoC",
+            COC_SHADER,
+            std::mem::size_of::<CocParams>() as u64,
+            &[&depth_layout],
+        );
+        let blur_pass = |label| {
+            PostPipeline::new(
+                device,
+                label,
+                BLUR_SHADER,
+                std::mem::size_of::<BlurParams>() as u64,
+                &[],
+            )
+        };
+        let (targets, tracked) = Self::create_targets(device, width, height);
+        Self {
+            focus: Focus::Distance(10.0),
+            aperture: 0.1,
+            max_radius: 16.0,
+            camera: Camera::default(),
+            coc_pass,
+            horizontal_pass: blur_pass("Depth Of Field Horizontal Blur"),
+            vertical_pass: blur_pass("Depth Of Field Vertical Blur"),
+            depth_layout,
+            depth_bind_group: None,
+            targets,
+            tracked,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> ([(wgpu::Texture, wgpu::TextureView); 2], Tracked) {
+        let create = || {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Depth Of Field Target"),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: COC_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+        let targets = [create(), create()];
+        let tracked = Tracked::new(0, 0, 2)
+            .with_texture(&targets[0].0)
+            .with_texture(&targets[1].0);
+        (targets, tracked)
+    }
+
+    pub fn with_focus(mut self, focus: Focus) -> Self {
+        self.focus = focus;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: f32) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    /// The depth of the opaque pass. Set it again when the depth texture is recreated,
+    /// e.g. on resize.
+    pub fn set_depth(&mut self, device: &wgpu::Device, depth: &wgpu::TextureView) {
+        self.depth_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Depth Of Field Depth Bind Group"),
+            layout: &self.depth_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(depth),
+            }],
+        }));
+    }
+
+    /// The camera the depth was drawn with, call whenever it changes.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = *camera;
+    }
+
+    /// Focuses at `point`, e.g. the position of the entity the `Picker` picked.
+    pub fn focus_on(&mut self, camera: &Camera, point: Vec3) {
+        self.focus = Focus::Distance((point - camera.eye).dot(camera.forward()));
+    }
+}
+
+impl PostEffect for DepthOfField {
+    fn label(&self) -> &str {
+        "Depth Of Field"
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (targets, tracked) = Self::create_targets(device, width, height);
+        self.targets = targets;
+        self.tracked = tracked;
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    ) {
+        let Some(depth) = &self.depth_bind_group else {
+            log::warn!("depth of field applied before the depth was set");
+            // a blur of radius 0 is a copy
+            let params = BlurParams {
+                direction: [0.0, 1.0],
+                max_radius: 0.0,
+                step: 1.0,
+                last: 1,
+                _padding: 0,
+            };
+            self.vertical_pass
+                .draw(frame, encoder, source, target, &params, &[]);
+            return;
+        };
+
+        let max_radius = self.max_radius.max(0.0);
+        // the focal length in pixels, so the circle of confusion comes out in pixels
+        let focal_length = frame.height as f32 / (2.0 * (self.camera.fovy * 0.5).tan());
+        let (focus_distance, autofocus, focus_pixel) = match self.focus {
+            Focus::Distance(distance) => (distance, 0, [0; 2]),
+            Focus::Pixel(pixel) => (0.0, 1, pixel),
+        };
+        let coc = CocParams {
+            focus_distance,
+            aperture: self.aperture * focal_length,
+            znear: self.camera.znear,
+            zfar: self.camera.zfar,
+            max_radius,
+            autofocus,
+            focus_pixel,
+            reversed: depth::mode().is_reversed() as u32,
+            _padding: 0,
+        };
+        let coc_target = PostTarget {
+            view: &self.targets[0].1,
+            format: COC_FORMAT,
+        };
+        self.coc_pass
+            .draw(frame, encoder, source, coc_target, &coc, &[depth]);
+
+        let blur = |direction, last| BlurParams {
+            direction,
+            max_radius,
+            step: (max_radius / MAX_TAPS).max(1.0),
+            last,
+            _padding: 0,
+        };
+        let horizontal_target = PostTarget {
+            view: &self.targets[1].1,
+            format: COC_FORMAT,
+        };
+        self.horizontal_pass.draw(
+            frame,
+            encoder,
+            &self.targets[0].1,
+            horizontal_target,
+            &blur([1.0, 0.0], 0),
+            &[],
+        );
+        self.vertical_pass.draw(
+            frame,
+            encoder,
+            &self.targets[1].1,
+            target,
+            &blur([0.0, 1.0], 1),
+            &[],
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! A mode for replay and image tests, in which the same inputs always produce the same
+//! frames.
+//!
+//! While it is on, the run loops pass a fixed `dt` instead of the measured frame time,
+//! `Rng::from_time` draws its seeds from a sequence started at a fixed seed instead of the
+//! clock, and `run_threaded` draws every update in lockstep instead of skipping states.
+//!
+//! The mode is per thread, so tests running in parallel don't share a seed sequence.
+//! `run_threaded` carries it over to its render thread.
+
+use std::cell::RefCell;
+
+use crate::random::Rng;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Deterministic {
+    /// Starts the sequence `Rng::from_time` seeds are drawn from.
+    pub seed: u64,
+    /// The `dt` every update gets, in seconds.
+    pub timestep: f32,
+}
+
+impl Default for Deterministic {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            timestep: 1.0 / 60.0,
+        }
+    }
+}
+
+struct State {
+    settings: Deterministic,
+    seeds: Rng,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+/// Turns the mode on for this thread, restarting the seed sequence.
+pub fn enable(settings: Deterministic) {
+    STATE.with(|state| {
+        *state.borrow_mut() = Some(State {
+            settings,
+            seeds: Rng::new(settings.seed),
+        })
+    });
+}
+
+pub fn disable() {
+    STATE.with(|state| *state.borrow_mut() = None);
+}
+
+/// The settings in effect on this thread, `None` when the mode is off.
+pub fn current() -> Option<Deterministic> {
+    STATE.with(|state| state.borrow().as_ref().map(|s| s.settings))
+}
+
+pub fn is_enabled() -> bool {
+    current().is_some()
+}
+
+/// The fixed time step, `None` when frames should use the measured time.
+pub fn timestep() -> Option<f32> {
+    current().map(|s| s.timestep)
+}
+
+/// The next seed in the sequence, `None` when randomness should come from the clock.
+pub(crate) fn next_seed() -> Option<u64> {
+    STATE.with(|state| {
+        state
+            .borrow_mut()
+            .as_mut()
+            .map(|state| state.seeds.next_u64())
+    })
+}
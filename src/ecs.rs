@@ -0,0 +1,315 @@
+//! A small entity component system, and the draw lists extracted from it every frame.
+//!
+//! Entities are ids, components any `'static` type stored per type in a sparse array. The
+//! render components are the crate's own types: `Transform`, `MeshHandle`,
+//! `MaterialHandle`, `Sprite` with a `TextureId`, and `Camera`. The run loop extracts
+//! them into `Context::draw_lists` before `App::render`.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use crate::assets::Handle;
+use crate::camera::Camera;
+use crate::math::Mat4;
+use crate::mesh::GpuMesh;
+use crate::sprites::{Sprite, SpriteBatch, TextureId};
+use crate::transform::Transform;
+
+/// Stays unique after the entity is despawned, its slot is reused with a new generation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+trait AnyStorage {
+    fn remove(&mut self, index: usize);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Vec<Option<T>> {
+    fn remove(&mut self, index: usize) {
+        if let Some(slot) = self.get_mut(index) {
+            *slot = None;
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct World {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    components: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.alive.push(false);
+                self.generations.len() as u32 - 1
+            }
+        };
+        self.alive[index as usize] = true;
+        Entity {
+            index,
+            generation: self.generations[index as usize],
+        }
+    }
+
+    /// Removes the entity and its components, does nothing if it's already gone.
+    pub fn despawn(&mut self, entity: Entity) {
+        if !self.is_alive(entity) {
+            return;
+        }
+        let index = entity.index as usize;
+        for storage in self.components.values_mut() {
+            storage.remove(index);
+        }
+        self.alive[index] = false;
+        self.generations[index] += 1;
+        self.free.push(entity.index);
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index) == Some(&true) && self.generations[index] == entity.generation
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&Vec<Option<T>>> {
+        self.components
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut Vec<Option<T>> {
+        self.components
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Vec::<Option<T>>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("storages are keyed by their type")
+    }
+
+    /// Adds or replaces the entity's component of type `T`. Ignored for a despawned entity.
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> &mut Self {
+        if !self.is_alive(entity) {
+            return self;
+        }
+        let index = entity.index as usize;
+        let storage = self.storage_mut::<T>();
+        if storage.len() <= index {
+            storage.resize_with(index + 1, || None);
+        }
+        storage[index] = Some(component);
+        self
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage_mut::<T>()
+            .get_mut(entity.index as usize)
+            .and_then(Option::take)
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage::<T>()?.get(entity.index as usize)?.as_ref()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage_mut::<T>()
+            .get_mut(entity.index as usize)?
+            .as_mut()
+    }
+
+    fn entity(&self, index: usize) -> Entity {
+        Entity {
+            index: index as u32,
+            generation: self.generations[index],
+        }
+    }
+
+    /// Every entity with a `T`, in spawn slot order.
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.storage::<T>()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(i, c)| Some((self.entity(i), c.as_ref()?)))
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        let generations = &self.generations;
+        self.components
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut::<Vec<Option<T>>>())
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(i, c)| {
+                let entity = Entity {
+                    index: i as u32,
+                    generation: generations[i],
+                };
+                Some((entity, c.as_mut()?))
+            })
+    }
+
+    /// Every entity with both an `A` and a `B`.
+    pub fn query2<A: 'static, B: 'static>(&self) -> impl Iterator<Item = (Entity, &A, &B)> {
+        let b = self.storage::<B>();
+        self.query::<A>().filter_map(move |(entity, a)| {
+            let b = b?.get(entity.index as usize)?.as_ref()?;
+            Some((entity, a, b))
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive.iter().filter(|&&alive| alive).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The mesh an entity is drawn with.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub Handle<GpuMesh>);
+
+/// Which of the app's materials a mesh is drawn with. The draw lists group meshes by it
+/// and otherwise carry it through.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialHandle(pub u32);
+
+#[derive(Clone, Debug)]
+pub struct MeshDraw {
+    pub entity: Entity,
+    pub mesh: Handle<GpuMesh>,
+    pub material: Option<MaterialHandle>,
+    /// From the entity's `Transform`, identity without one.
+    pub model: Mat4,
+}
+
+/// What to draw this frame, extracted from a `World`.
+#[derive(Clone, Debug, Default)]
+pub struct DrawLists {
+    /// Entities with a `MeshHandle`, sorted by material and then mesh so state changes
+    /// are few.
+    pub meshes: Vec<MeshDraw>,
+    /// Entities with a `Sprite` and a `TextureId`, in spawn slot order.
+    pub sprites: Vec<(TextureId, Sprite)>,
+    /// The first entity with a `Camera`. With a `Transform` too, it looks from the
+    /// transform's translation along its forward direction.
+    pub camera: Option<Camera>,
+}
+
+impl DrawLists {
+    /// Replaces the lists with the world's current contents.
+    pub fn extract(&mut self, world: &World) {
+        self.meshes.clear();
+        self.meshes
+            .extend(world.query::<MeshHandle>().map(|(entity, mesh)| {
+                MeshDraw {
+                    entity,
+                    mesh: mesh.0.clone(),
+                    material: world.get::<MaterialHandle>(entity).copied(),
+                    model: world
+                        .get::<Transform>(entity)
+                        .map_or(Mat4::IDENTITY, Transform::matrix),
+                }
+            }));
+        self.meshes
+            .sort_by_key(|draw| (draw.material, draw.mesh.id()));
+
+        self.sprites.clear();
+        self.sprites.extend(
+            world
+                .query2::<Sprite, TextureId>()
+                .map(|(_, sprite, &texture)| (texture, *sprite)),
+        );
+
+        self.camera = world.query::<Camera>().next().map(|(entity, camera)| {
+            let mut camera = *camera;
+            if let Some(transform) = world.get::<Transform>(entity) {
+                camera.eye = transform.translation;
+                camera.target = transform.translation + transform.forward();
+                camera.up = transform.up();
+            }
+            camera
+        });
+    }
+
+    /// Queues the extracted sprites into `batch`.
+    pub fn draw_sprites(&self, batch: &mut SpriteBatch) {
+        for (texture, sprite) in &self.sprites {
+            batch.draw(*texture, sprite);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components_by_type() {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.insert(a, 1u32).insert(a, "a");
+        world.insert(b, 2u32);
+        assert_eq!(world.get::<u32>(a), Some(&1));
+        assert_eq!(world.get::<&str>(b), None);
+        assert_eq!(world.get::<f32>(a), None);
+        *world.get_mut::<u32>(b).unwrap() += 10;
+        let numbers: Vec<_> = world.query::<u32>().map(|(e, n)| (e, *n)).collect();
+        assert_eq!(numbers, [(a, 1), (b, 12)]);
+        assert_eq!(world.query2::<u32, &str>().count(), 1);
+        assert_eq!(world.remove::<u32>(a), Some(1));
+        assert_eq!(world.query::<u32>().count(), 1);
+    }
+
+    #[test]
+    fn despawned_slots_are_reused_with_a_new_generation() {
+        let mut world = World::new();
+        let old = world.spawn();
+        world.insert(old, 1u32);
+        world.despawn(old);
+        assert!(!world.is_alive(old));
+        let new = world.spawn();
+        assert_ne!(old, new);
+        assert_eq!(world.len(), 1);
+        // the old entity's component went with it, and the stale id can't reach the new one
+        assert_eq!(world.get::<u32>(new), None);
+        world.insert(old, 2u32);
+        assert_eq!(world.get::<u32>(new), None);
+        world.insert(new, 3u32);
+        assert_eq!(world.get::<u32>(old), None);
+        assert_eq!(world.query::<u32>().next(), Some((new, &3)));
+    }
+}
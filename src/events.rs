@@ -0,0 +1,222 @@
+//! A typed event queue on the `Context`, so systems can react to the window, input,
+//! assets and each other from `App::update` without matching winit events in `App::input`
+//! or calling into one another.
+//!
+//! Anything `'static` can be an event. `send` queues one and `read` returns the events of
+//! a type that were sent before the frame's update began. The run loops call `update`
+//! right before `App::update`, which makes everything sent since the last frame readable
+//! and forgets the frame before, so each event is seen for exactly one frame whichever
+//! system sent it and whichever reads it. Events sent during an update are read in the
+//! next one.
+//!
+//! The run loops send a `WindowEvent` and an `InputEvent` for what winit reports, except
+//! what the debug overlay or the capture key took, and an `AssetEvent` for what
+//! `Assets::update` finished.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use winit::event::{ElementState, ModifiersState, MouseButton, MouseScrollDelta, TouchPhase};
+
+use crate::math::Vec2;
+
+/// Lines a `MouseScrollDelta::PixelDelta` counts as, so wheels and touchpads scroll alike.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Something that happened to the window.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WindowEvent {
+    /// In physical pixels.
+    Resized(winit::dpi::PhysicalSize<u32>),
+    ScaleFactorChanged(f64),
+    /// Whether the window now has the keyboard focus.
+    Focused(bool),
+    /// The run loop exits after it unless `App::input` handled it.
+    CloseRequested,
+    Moved(winit::dpi::PhysicalPosition<i32>),
+    FileDropped(PathBuf),
+    CursorEntered,
+    CursorLeft,
+    /// The system switched between light and dark mode, see `Context::theme`.
+    ThemeChanged(winit::window::Theme),
+}
+
+/// A key, button or pointer change.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    Key {
+        key: Option<winit::event::VirtualKeyCode>,
+        scancode: u32,
+        pressed: bool,
+    },
+    /// Text typed, after the keyboard layout and input methods had their say.
+    Character(char),
+    Modifiers(ModifiersState),
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    /// The cursor's new position in physical pixels from the top left corner.
+    CursorMoved(Vec2),
+    /// Scrolled lines, positive up and to the right.
+    Scroll(Vec2),
+    Touch {
+        id: u64,
+        phase: TouchPhase,
+        /// In physical pixels from the top left corner.
+        position: Vec2,
+    },
+}
+
+struct Queue<T> {
+    /// Readable this frame.
+    current: Vec<T>,
+    /// Sent since the last `update`.
+    pending: Vec<T>,
+}
+
+/// What `Events` needs of a `Queue` without knowing its event type.
+trait AnyQueue {
+    fn update(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyQueue for Queue<T> {
+    fn update(&mut self) {
+        self.current.clear();
+        std::mem::swap(&mut self.current, &mut self.pending);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Queued events by type, see the module docs.
+#[derive(Default)]
+pub struct Events {
+    queues: HashMap<TypeId, Box<dyn AnyQueue>>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues `event` for the next frame's `read`.
+    pub fn send<T: 'static>(&mut self, event: T) {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                Box::new(Queue::<T> {
+                    current: Vec::new(),
+                    pending: Vec::new(),
+                })
+            })
+            .as_any_mut()
+            .downcast_mut::<Queue<T>>()
+            .expect("queue of the event type")
+            .pending
+            .push(event);
+    }
+
+    /// The events of type `T` readable this frame, oldest first.
+    pub fn read<T: 'static>(&self) -> &[T] {
+        self.queues
+            .get(&TypeId::of::<T>())
+            .and_then(|queue| queue.as_any().downcast_ref::<Queue<T>>())
+            .map_or(&[], |queue| &queue.current)
+    }
+
+    /// Starts a frame: what was sent since the last call becomes readable, what was
+    /// readable is dropped. The run loops call this, custom loops should too.
+    pub fn update(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.update();
+        }
+    }
+
+    /// Sends the `WindowEvent` or `InputEvent` a winit event amounts to, if any.
+    pub(crate) fn send_winit(&mut self, event: &winit::event::WindowEvent) {
+        use winit::event::WindowEvent as E;
+        match event {
+            E::Resized(size) => self.send(WindowEvent::Resized(*size)),
+            E::ScaleFactorChanged { scale_factor, .. } => {
+                self.send(WindowEvent::ScaleFactorChanged(*scale_factor))
+            }
+            E::Focused(focused) => self.send(WindowEvent::Focused(*focused)),
+            E::CloseRequested => self.send(WindowEvent::CloseRequested),
+            E::Moved(position) => self.send(WindowEvent::Moved(*position)),
+            E::DroppedFile(path) => self.send(WindowEvent::FileDropped(path.clone())),
+            E::CursorEntered { .. } => self.send(WindowEvent::CursorEntered),
+            E::CursorLeft { .. } => self.send(WindowEvent::CursorLeft),
+            E::ThemeChanged(theme) => self.send(WindowEvent::ThemeChanged(*theme)),
+            E::KeyboardInput { input, .. } => self.send(InputEvent::Key {
+                key: input.virtual_keycode,
+                scancode: input.scancode,
+                pressed: input.state == ElementState::Pressed,
+            }),
+            E::ReceivedCharacter(c) => self.send(InputEvent::Character(*c)),
+            E::ModifiersChanged(modifiers) => self.send(InputEvent::Modifiers(*modifiers)),
+            E::MouseInput { state, button, .. } => self.send(InputEvent::MouseButton {
+                button: *button,
+                pressed: *state == ElementState::Pressed,
+            }),
+            E::CursorMoved { position, .. } => self.send(InputEvent::CursorMoved(Vec2::new(
+                position.x as f32,
+                position.y as f32,
+            ))),
+            E::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => Vec2::new(*x, *y),
+                    MouseScrollDelta::PixelDelta(delta) => {
+                        Vec2::new(delta.x as f32, delta.y as f32) * (1.0 / PIXELS_PER_LINE)
+                    }
+                };
+                self.send(InputEvent::Scroll(lines));
+            }
+            E::Touch(touch) => self.send(InputEvent::Touch {
+                id: touch.id,
+                phase: touch.phase,
+                position: Vec2::new(touch.location.x as f32, touch.location.y as f32),
+            }),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readable_for_one_frame() {
+        let mut events = Events::new();
+        events.send(1u32);
+        events.send(2u32);
+        assert!(events.read::<u32>().is_empty());
+        events.update();
+        assert_eq!(events.read::<u32>(), &[1, 2]);
+        assert!(events.read::<i32>().is_empty());
+        events.update();
+        assert!(events.read::<u32>().is_empty());
+    }
+
+    #[test]
+    fn sent_while_reading_shows_up_next_frame() {
+        let mut events = Events::new();
+        events.send("first");
+        events.update();
+        assert_eq!(events.read::<&str>(), &["first"]);
+        events.send("second");
+        assert_eq!(events.read::<&str>(), &["first"]);
+        events.update();
+        assert_eq!(events.read::<&str>(), &["second"]);
+    }
+}
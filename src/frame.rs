@@ -0,0 +1,166 @@
+use crate::crash_dump::CrashDump;
+use crate::depth::DepthTarget;
+
+/// The image a frame draws into.
+pub(crate) enum FrameOutput {
+    Surface(wgpu::SurfaceTexture),
+    /// The target of a headless context.
+    Texture(wgpu::Texture),
+}
+
+impl FrameOutput {
+    pub(crate) fn texture(&self) -> &wgpu::Texture {
+        match self {
+            FrameOutput::Surface(output) => &output.texture,
+            FrameOutput::Texture(texture) => texture,
+        }
+    }
+}
+
+/// How far the CPU may run ahead of the GPU, see `Context::set_max_frames_in_flight`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FramePacing {
+    /// Starts a frame as soon as fewer than the maximum are in flight. Keeps the GPU
+    /// busiest.
+    #[default]
+    Throughput,
+    /// Waits for the GPU to finish every frame before starting the next one, so input is
+    /// read as late as possible. Worth it with `Mailbox` and `Immediate`, where presenting
+    /// doesn't hold the CPU back, at the cost of some GPU idle time.
+    LowLatency,
+}
+
+/// One frame being recorded: the swapchain image and the encoder everything is recorded into.
+/// Submitted and presented by the run loop after `App::render` returns.
+pub struct Frame {
+    output: FrameOutput,
+    view: wgpu::TextureView,
+    encoder: wgpu::CommandEncoder,
+    crash_dump: CrashDump,
+}
+
+impl Frame {
+    pub(crate) fn new(
+        output: FrameOutput,
+        encoder: wgpu::CommandEncoder,
+        crash_dump: CrashDump,
+    ) -> Self {
+        let view = output.texture().create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Frame View"),
+            ..Default::default()
+        });
+        Self {
+            output,
+            view,
+            encoder,
+            crash_dump,
+        }
+    }
+
+    pub(crate) fn finish(self) -> (FrameOutput, wgpu::CommandEncoder) {
+        (self.output, self.encoder)
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        self.output.texture()
+    }
+
+    /// The format of the image drawn into, what pipelines drawing into it need to be built
+    /// for, see `pipeline::PipelineVariants`.
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.output.texture().format()
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        let texture = self.output.texture();
+        winit::dpi::PhysicalSize::new(texture.width(), texture.height())
+    }
+
+    pub fn encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        &mut self.encoder
+    }
+
+    /// Submits everything recorded so far and carries on with a fresh encoder. Buffer
+    /// writes made after this land after the submitted work, so a renderer can be prepared
+    /// and drawn again with different data within the same frame.
+    pub fn submit(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        let encoder = std::mem::replace(&mut self.encoder, encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Opens a named group in graphics debuggers such as RenderDoc or Xcode, around
+    /// everything recorded until the matching `pop_debug_group`. Also recorded in the
+    /// context's `CrashDump`.
+    pub fn push_debug_group(&mut self, label: &str) {
+        self.crash_dump.marker(&format!("push {}", label));
+        self.encoder.push_debug_group(label);
+    }
+
+    pub fn pop_debug_group(&mut self) {
+        self.crash_dump.marker("pop");
+        self.encoder.pop_debug_group();
+    }
+
+    /// Marks a point between passes in graphics debuggers.
+    pub fn insert_debug_marker(&mut self, label: &str) {
+        self.crash_dump.marker(label);
+        self.encoder.insert_debug_marker(label);
+    }
+
+    /// Starts a pass drawing into the swapchain image. `None` keeps the existing contents.
+    pub fn begin_pass(&mut self, clear: Option<wgpu::Color>) -> wgpu::RenderPass<'_> {
+        self.begin_named_pass("Render Pass", clear)
+    }
+
+    /// Like `begin_pass`, with the name the pass shows up under in graphics debuggers.
+    pub fn begin_named_pass(
+        &mut self,
+        label: &str,
+        clear: Option<wgpu::Color>,
+    ) -> wgpu::RenderPass<'_> {
+        self.begin_pass_with(label, clear, None)
+    }
+
+    /// Like `begin_pass`, also testing against `depth`, which is cleared along with the
+    /// color. Set the stencil reference of pipelines testing the stencil with
+    /// `RenderPass::set_stencil_reference`.
+    pub fn begin_depth_pass<'a>(
+        &'a mut self,
+        clear: Option<wgpu::Color>,
+        depth: &'a DepthTarget,
+    ) -> wgpu::RenderPass<'a> {
+        self.begin_pass_with("Render Pass", clear, Some(depth))
+    }
+
+    fn begin_pass_with<'a>(
+        &'a mut self,
+        label: &str,
+        clear: Option<wgpu::Color>,
+        depth: Option<&'a DepthTarget>,
+    ) -> wgpu::RenderPass<'a> {
+        self.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: match clear {
+                        Some(color) => wgpu::LoadOp::Clear(color),
+                        None => wgpu::LoadOp::Load,
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: depth.map(|depth| depth.attachment(clear.is_some())),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        })
+    }
+}
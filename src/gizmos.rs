@@ -0,0 +1,218 @@
+//! World space debug drawing: lines, axes, grids, wire boxes, spheres and camera frustums.
+//!
+//! Shapes are queued during the frame and drawn as constant width lines in a pass of their
+//! own on top of the scene, then forgotten, so anything that should stay visible is queued
+//! again every frame.
+
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::camera::Camera;
+use crate::frame::Frame;
+use crate::lines::{LineJoin, LineRenderer, LineStyle, LineWidth};
+use crate::math::{Mat4, Vec3, Vec4};
+use crate::mesh::GpuMesh;
+use crate::transform::Transform;
+
+/// Segments used for circles and spheres.
+const CIRCLE_SEGMENTS: usize = 32;
+
+const RED: [f32; 4] = [0.95, 0.25, 0.25, 1.0];
+const GREEN: [f32; 4] = [0.3, 0.9, 0.3, 1.0];
+const BLUE: [f32; 4] = [0.3, 0.45, 0.95, 1.0];
+const YELLOW: [f32; 4] = [0.95, 0.85, 0.2, 1.0];
+const CYAN: [f32; 4] = [0.2, 0.85, 0.95, 1.0];
+
+pub struct Gizmos {
+    lines: LineRenderer,
+    /// Line width in pixels.
+    pub line_width: f32,
+    /// Whether `mesh_bounds` draws anything, for toggling bounds on and off without
+    /// touching every call.
+    pub show_bounds: bool,
+}
+
+impl Gizmos {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self {
+            lines: LineRenderer::new(device, format),
+            line_width: 1.5,
+            show_bounds: false,
+        }
+    }
+
+    fn style(&self, color: [f32; 4]) -> LineStyle {
+        LineStyle::new(LineWidth::Pixels(self.line_width), color).with_join(LineJoin::Bevel)
+    }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: [f32; 4]) {
+        let style = self.style(color);
+        self.lines.line(a, b, &style);
+    }
+
+    pub fn ray(&mut self, origin: Vec3, direction: Vec3, color: [f32; 4]) {
+        self.line(origin, origin + direction, color);
+    }
+
+    /// Red, green and blue lines along the transform's local x, y and z axes.
+    pub fn axes(&mut self, transform: &Transform, length: f32) {
+        let origin = transform.translation;
+        let rotation = transform.rotation;
+        self.ray(origin, rotation.rotate(Vec3::X) * length, RED);
+        self.ray(origin, rotation.rotate(Vec3::Y) * length, GREEN);
+        self.ray(origin, rotation.rotate(Vec3::Z) * length, BLUE);
+    }
+
+    /// A square grid on the XZ plane centred on `center`, `cells` cells across.
+    pub fn grid(&mut self, center: Vec3, cell_size: f32, cells: u32, color: [f32; 4]) {
+        let half = cells as f32 * cell_size * 0.5;
+        for i in 0..=cells {
+            let offset = i as f32 * cell_size - half;
+            self.line(
+                center + Vec3::new(offset, 0.0, -half),
+                center + Vec3::new(offset, 0.0, half),
+                color,
+            );
+            self.line(
+                center + Vec3::new(-half, 0.0, offset),
+                center + Vec3::new(half, 0.0, offset),
+                color,
+            );
+        }
+    }
+
+    /// An axis aligned box.
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: [f32; 4]) {
+        let corners = std::array::from_fn(|i| {
+            Vec3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        });
+        self.wire_box(&corners, color);
+    }
+
+    /// The unit cube centred on the origin, moved into place by `transform`.
+    pub fn cube(&mut self, transform: &Mat4, color: [f32; 4]) {
+        let corners = std::array::from_fn(|i| {
+            transform.transform_point3(Vec3::new(
+                if i & 1 == 0 { -0.5 } else { 0.5 },
+                if i & 2 == 0 { -0.5 } else { 0.5 },
+                if i & 4 == 0 { -0.5 } else { 0.5 },
+            ))
+        });
+        self.wire_box(&corners, color);
+    }
+
+    /// Outline of the volume a view projection matrix sees, near plane to far plane.
+    pub fn frustum(&mut self, view_proj: &Mat4, color: [f32; 4]) {
+        let inverse = view_proj.inverse();
+        let corners = std::array::from_fn(|i| {
+            let clip = Vec4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+                1.0,
+            );
+            let world = inverse.mul_vec4(clip);
+            world.truncate() / world.w
+        });
+        self.wire_box(&corners, color);
+    }
+
+    pub fn camera(&mut self, camera: &Camera, color: [f32; 4]) {
+        self.frustum(&camera.view_proj(), color);
+    }
+
+    /// Edges of a box given its corners, indexed by bit 0 for x, bit 1 for y and bit 2 for z.
+    fn wire_box(&mut self, corners: &[Vec3; 8], color: [f32; 4]) {
+        let style = self.style(color);
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    self.lines.line(corners[i], corners[i | axis], &style);
+                }
+            }
+        }
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: [f32; 4]) {
+        let normal = normal.normalize();
+        let u = normal.any_orthogonal();
+        let v = normal.cross(u);
+        let points: Vec<Vec3> = (0..CIRCLE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            })
+            .collect();
+        let style = self.style(color);
+        self.lines.polygon(&points, &style);
+    }
+
+    /// Three great circles around the x, y and z axes.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: [f32; 4]) {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.circle(center, axis, radius, color);
+        }
+    }
+
+    /// An axis aligned box moved into place by `transform`, so rotated with it.
+    pub fn oriented_aabb(&mut self, aabb: &Aabb, transform: &Mat4, color: [f32; 4]) {
+        let corners = std::array::from_fn(|i| {
+            transform.transform_point3(Vec3::new(
+                if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+            ))
+        });
+        self.wire_box(&corners, color);
+    }
+
+    pub fn bounding_sphere(&mut self, sphere: &BoundingSphere, color: [f32; 4]) {
+        self.sphere(sphere.center, sphere.radius, color);
+    }
+
+    /// The box and sphere around a mesh drawn with `transform`, in yellow and cyan. Does
+    /// nothing unless `show_bounds` is set.
+    pub fn mesh_bounds(&mut self, mesh: &GpuMesh, transform: &Mat4) {
+        if !self.show_bounds {
+            return;
+        }
+        self.oriented_aabb(&mesh.aabb, transform, YELLOW);
+        self.bounding_sphere(&mesh.bounding_sphere.transform(transform), CYAN);
+    }
+
+    /// Builds and uploads the lines queued since the last call, `viewport` in pixels.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_proj: Mat4,
+        viewport: [f32; 2],
+    ) {
+        self.lines.prepare(device, queue, view_proj, viewport);
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.lines.render(render_pass);
+    }
+
+    /// Prepares the queued gizmos and draws them in their own pass over the frame.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &mut Frame,
+        view_proj: Mat4,
+    ) {
+        let size = frame.texture().size();
+        self.prepare(
+            device,
+            queue,
+            view_proj,
+            [size.width as f32, size.height as f32],
+        );
+        let mut render_pass = frame.begin_named_pass("Gizmo Pass", None);
+        self.render(&mut render_pass);
+    }
+}
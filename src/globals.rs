@@ -0,0 +1,289 @@
+//! The uniform block every built-in pipeline with a view binds at group 0, binding 0:
+//! the camera's matrices and their inverses, its position, the size of the target and the
+//! time.
+//!
+//! Renderers with settings of their own put them in a block that starts with
+//! `FrameGlobals`, so any shader declaring just the standard block can read the group 0
+//! buffer of any of them. To use it in your own shaders, start the source with
+//! `with_frame_globals`, which adds the struct and
+//!
+//! ```wgsl
+//! @group(0) @binding(0)
+//! var<uniform> globals: FrameGlobals;
+//! ```
+//!
+//! and bind a `GlobalsBuffer` there, or build the source with `FRAME_GLOBALS_WGSL` alone
+//! to declare the binding yourself.
+//!
+//! The time, the time since the last frame and the resolution are kept per thread, like
+//! the deterministic mode: the run loops set them with `set_frame` before `App::update`,
+//! and `FrameGlobals` made from a camera take them from there. A new `Context` starts the
+//! time over at 0, so each one counts its own, whatever ran on the thread before.
+
+use std::cell::Cell;
+
+use crate::camera::{Camera, Camera2D};
+use crate::math::{Mat4, Vec3};
+use crate::stats::Tracked;
+
+/// The WGSL declaration of `FrameGlobals`.
+pub const FRAME_GLOBALS_WGSL: &str = r#"
+struct FrameGlobals {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    view_proj: mat4x4<f32>,
+    inverse_view: mat4x4<f32>,
+    inverse_proj: mat4x4<f32>,
+    inverse_view_proj: mat4x4<f32>,
+    // xyz the camera position, w 1
+    eye: vec4<f32>,
+    // size of the target in pixels
+    resolution: vec2<f32>,
+    // seconds since the run loop started
+    time: f32,
+    // seconds since the last frame
+    delta_time: f32,
+};
+"#;
+
+/// `FRAME_GLOBALS_WGSL` and the group 0 binding, followed by `source`.
+pub fn with_frame_globals(source: &str) -> String {
+    format!(
+        "{}\n@group(0) @binding(0)\nvar<uniform> globals: FrameGlobals;\n{}",
+        FRAME_GLOBALS_WGSL, source
+    )
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct FrameTime {
+    time: f32,
+    delta_time: f32,
+    resolution: [f32; 2],
+}
+
+thread_local! {
+    static FRAME: Cell<FrameTime> = const {
+        Cell::new(FrameTime {
+            time: 0.0,
+            delta_time: 0.0,
+            resolution: [0.0; 2],
+        })
+    };
+}
+
+fn frame_time() -> FrameTime {
+    FRAME.with(Cell::get)
+}
+
+/// Moves the time on by `delta_time` seconds and sets the resolution, once per frame.
+pub fn set_frame(delta_time: f32, width: u32, height: u32) {
+    let mut frame = frame_time();
+    frame.time += delta_time;
+    frame.delta_time = delta_time;
+    frame.resolution = [width as f32, height as f32];
+    FRAME.with(|current| current.set(frame));
+}
+
+/// Sets the resolution alone, while drawing into a target of another size than the
+/// window's, such as a tool window. Returns the previous one to put back afterwards.
+pub fn set_resolution(width: u32, height: u32) -> (u32, u32) {
+    let mut frame = frame_time();
+    let previous = frame.resolution;
+    frame.resolution = [width as f32, height as f32];
+    FRAME.with(|current| current.set(frame));
+    (previous[0] as u32, previous[1] as u32)
+}
+
+/// Starts the time over at 0, with the resolution of a new context's target.
+pub(crate) fn reset(width: u32, height: u32) {
+    FRAME.with(|current| {
+        current.set(FrameTime {
+            time: 0.0,
+            delta_time: 0.0,
+            resolution: [width as f32, height as f32],
+        })
+    });
+}
+
+/// Seconds since the run loop started, as `set_frame` counted them.
+pub fn time() -> f32 {
+    frame_time().time
+}
+
+/// The standard per frame uniform block, see the module docs.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameGlobals {
+    pub view: Mat4,
+    pub proj: Mat4,
+    pub view_proj: Mat4,
+    pub inverse_view: Mat4,
+    pub inverse_proj: Mat4,
+    pub inverse_view_proj: Mat4,
+    pub eye: [f32; 4],
+    pub resolution: [f32; 2],
+    pub time: f32,
+    pub delta_time: f32,
+}
+unsafe impl bytemuck::Pod for FrameGlobals {}
+unsafe impl bytemuck::Zeroable for FrameGlobals {}
+
+impl Default for FrameGlobals {
+    fn default() -> Self {
+        Self::new(Mat4::IDENTITY, Mat4::IDENTITY)
+    }
+}
+
+impl FrameGlobals {
+    /// The globals of a `view` and `proj`, with the time and resolution of this frame.
+    pub fn new(view: Mat4, proj: Mat4) -> Self {
+        let inverse_view = view.inverse();
+        let frame = frame_time();
+        Self {
+            view,
+            proj,
+            view_proj: proj * view,
+            inverse_view,
+            inverse_proj: proj.inverse(),
+            inverse_view_proj: (proj * view).inverse(),
+            eye: inverse_view.col(3).to_array(),
+            resolution: frame.resolution,
+            time: frame.time,
+            delta_time: frame.delta_time,
+        }
+    }
+
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self::new(camera.view(), camera.projection())
+    }
+
+    /// A `Camera2D`'s view, with the whole transform in `proj`.
+    pub fn from_camera_2d(camera: &Camera2D) -> Self {
+        Self::from_view_proj(camera.view_proj())
+    }
+
+    /// For views only known as one matrix, such as `ShapeRenderer::pixel_projection`. The
+    /// view is the identity, so `eye` is the origin unless set `with_eye`.
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        Self::new(Mat4::IDENTITY, view_proj)
+    }
+
+    pub fn with_eye(mut self, eye: Vec3) -> Self {
+        self.eye = eye.extend(1.0).to_array();
+        self
+    }
+
+    /// Replaces the frame's time, for renderers driven by a clock of their own.
+    pub fn with_time(mut self, time: f32) -> Self {
+        self.time = time;
+        self
+    }
+
+    /// Replaces the frame's resolution, for targets other than the window.
+    pub fn with_resolution(mut self, width: u32, height: u32) -> Self {
+        self.resolution = [width as f32, height as f32];
+        self
+    }
+
+    pub fn eye(&self) -> Vec3 {
+        Vec3::new(self.eye[0], self.eye[1], self.eye[2])
+    }
+}
+
+/// The layout entry of a uniform starting with `FrameGlobals`, visible to every stage.
+fn layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(
+                std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress
+            ),
+        },
+        count: None,
+    }
+}
+
+/// A `FrameGlobals` buffer and a group 0 bind group for it, for your own pipelines.
+pub struct GlobalsBuffer {
+    buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    _tracked: Tracked,
+}
+
+impl GlobalsBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Frame Globals Layout"),
+            entries: &[layout_entry(0)],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Frame Globals Bind Group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+        Self {
+            buffer,
+            layout,
+            bind_group,
+            _tracked: tracked,
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, globals: &FrameGlobals) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(globals));
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Goes first in the pipeline layout, at group 0.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_time_is_per_thread_and_resets() {
+        reset(640, 480);
+        set_frame(0.5, 640, 480);
+        set_frame(0.25, 800, 600);
+        assert_eq!(time(), 0.75);
+        let globals = FrameGlobals::default();
+        assert_eq!(
+            (globals.resolution, globals.delta_time),
+            ([800.0, 600.0], 0.25)
+        );
+
+        std::thread::spawn(|| assert_eq!(time(), 0.0))
+            .join()
+            .unwrap();
+        assert_eq!(set_resolution(100, 50), (800, 600));
+        assert_eq!(FrameGlobals::default().resolution, [100.0, 50.0]);
+
+        reset(1, 1);
+        assert_eq!(time(), 0.0);
+    }
+}
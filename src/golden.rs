@@ -0,0 +1,278 @@
+//! Golden image tests: render a scene offscreen, compare it with a reference PNG and keep
+//! the differences as images when they don't match.
+//!
+//! Scenes are rendered headless in deterministic mode, so the same app draws the same image
+//! every run on the same machine. Different GPUs and drivers rasterize slightly differently,
+//! which the tolerance allows for. A missing reference is written from the current output,
+//! as is every reference when `UPDATE_GOLDEN` is set in the environment.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use image::{Rgba, RgbaImage};
+
+use crate::context::Context;
+use crate::deterministic;
+use crate::readback;
+use crate::window::{run_updates, step_frame, App};
+
+/// How different two images may be and still match.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tolerance {
+    /// Perceptual colour difference, 0..1, below which a pixel counts as equal. Weighs
+    /// brightness over hue the way the eye does.
+    pub threshold: f32,
+    /// Fraction of pixels allowed to differ.
+    pub max_differing: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            threshold: 0.1,
+            max_differing: 0.001,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GoldenConfig {
+    pub width: u32,
+    pub height: u32,
+    /// Frames updated and rendered before the one compared, for scenes that animate in.
+    pub frames: u32,
+    pub tolerance: Tolerance,
+    /// Where the reference PNGs live, usually checked in next to the tests.
+    pub reference_dir: PathBuf,
+    /// Where the actual output and the difference image go on failure.
+    pub output_dir: PathBuf,
+}
+
+impl GoldenConfig {
+    pub fn new(reference_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            frames: 1,
+            tolerance: Tolerance::default(),
+            reference_dir: reference_dir.into(),
+            output_dir: std::env::temp_dir().join("golden"),
+        }
+    }
+
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn with_frames(mut self, frames: u32) -> Self {
+        self.frames = frames.max(1);
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = dir.into();
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(PathBuf, std::io::Error),
+    Image(PathBuf, image::ImageError),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Mismatch {
+        differing: usize,
+        total: usize,
+        actual: PathBuf,
+        diff: PathBuf,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            GoldenError::Image(path, e) => write!(f, "{}: {}", path.display(), e),
+            GoldenError::SizeMismatch { expected, actual } => write!(
+                f,
+                "image is {}x{}, the reference is {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            GoldenError::Mismatch {
+                differing,
+                total,
+                actual,
+                diff,
+            } => write!(
+                f,
+                "{} of {} pixels differ, see {} and {}",
+                differing,
+                total,
+                actual.display(),
+                diff.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+/// Outcome of comparing two images of the same size.
+pub struct Comparison {
+    pub differing: usize,
+    pub total: usize,
+    /// The reference faded to grey, with differing pixels in red.
+    pub diff: RgbaImage,
+}
+
+impl Comparison {
+    pub fn matches(&self, tolerance: &Tolerance) -> bool {
+        self.differing as f32 <= self.total as f32 * tolerance.max_differing
+    }
+}
+
+/// Renders `config.frames` frames of the app `init` builds into a headless context and
+/// reads back the last one. Runs in deterministic mode, with the default settings unless
+/// the thread is already in it.
+pub fn render_offscreen<A: App>(
+    config: &GoldenConfig,
+    init: impl FnOnce(&mut Context) -> A,
+) -> RgbaImage {
+    let previous = deterministic::current();
+    deterministic::enable(previous.unwrap_or_default());
+    let mut ctx = pollster::block_on(Context::new_headless(config.width, config.height));
+    let mut app = init(&mut ctx);
+    for _ in 0..config.frames.max(1) {
+        step_frame(&mut ctx, deterministic::timestep().unwrap_or_default());
+        run_updates(&mut app, &mut ctx);
+        ctx.render_frame(|ctx, frame| app.render(ctx, frame))
+            .expect("offscreen frames can't fail to start");
+    }
+    if previous.is_none() {
+        deterministic::disable();
+    }
+    read_offscreen(&ctx)
+}
+
+/// Reads back the last frame of a headless context.
+pub fn read_offscreen(ctx: &Context) -> RgbaImage {
+    let texture = ctx
+        .offscreen_texture()
+        .expect("only headless contexts can be read back");
+    readback::read_texture(ctx.device(), ctx.queue(), texture)
+        .unwrap_or_else(|e| panic!("golden image read back failed: {}", e))
+}
+
+/// Perceptual difference between two colours, 0..1, after pixelmatch's YIQ metric.
+fn color_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    // blend onto white first so transparent pixels compare by what they'd look like
+    let blend = |c: &Rgba<u8>| {
+        let alpha = c[3] as f32 / 255.0;
+        [0, 1, 2].map(|i| 255.0 + (c[i] as f32 - 255.0) * alpha)
+    };
+    let [r1, g1, b1] = blend(a);
+    let [r2, g2, b2] = blend(b);
+    let y = |r: f32, g: f32, b: f32| r * 0.299 + g * 0.587 + b * 0.114;
+    let i = |r: f32, g: f32, b: f32| r * 0.596 - g * 0.274 - b * 0.322;
+    let q = |r: f32, g: f32, b: f32| r * 0.211 - g * 0.523 + b * 0.312;
+    let dy = y(r1, g1, b1) - y(r2, g2, b2);
+    let di = i(r1, g1, b1) - i(r2, g2, b2);
+    let dq = q(r1, g1, b1) - q(r2, g2, b2);
+    // 35215 is the largest possible value, black against white
+    ((0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / 35215.0).sqrt()
+}
+
+/// Compares two images of the same size pixel by pixel.
+pub fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: &Tolerance) -> Comparison {
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    let mut differing = 0;
+    for ((a, e), d) in actual
+        .pixels()
+        .zip(expected.pixels())
+        .zip(diff.pixels_mut())
+    {
+        if color_delta(a, e) > tolerance.threshold {
+            differing += 1;
+            *d = Rgba([255, 0, 0, 255]);
+        } else {
+            let grey = (255.0
+                - (255.0 - e.0[0..3].iter().map(|&c| c as f32).sum::<f32>() / 3.0) * 0.1)
+                as u8;
+            *d = Rgba([grey, grey, grey, 255]);
+        }
+    }
+    Comparison {
+        differing,
+        total: expected.pixels().len(),
+        diff,
+    }
+}
+
+fn save(image: &RgbaImage, path: &Path) -> Result<(), GoldenError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| GoldenError::Io(dir.to_path_buf(), e))?;
+    }
+    image
+        .save(path)
+        .map_err(|e| GoldenError::Image(path.to_path_buf(), e))
+}
+
+/// Checks `image` against the reference `<name>.png`.
+pub fn check_golden(
+    name: &str,
+    image: &RgbaImage,
+    config: &GoldenConfig,
+) -> Result<(), GoldenError> {
+    let reference = config.reference_dir.join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() || !reference.exists() {
+        log::info!("writing golden image {}", reference.display());
+        return save(image, &reference);
+    }
+    let expected = image::open(&reference)
+        .map_err(|e| GoldenError::Image(reference.clone(), e))?
+        .into_rgba8();
+    if expected.dimensions() != image.dimensions() {
+        return Err(GoldenError::SizeMismatch {
+            expected: expected.dimensions(),
+            actual: image.dimensions(),
+        });
+    }
+
+    let comparison = compare(image, &expected, &config.tolerance);
+    if comparison.matches(&config.tolerance) {
+        return Ok(());
+    }
+    let actual = config.output_dir.join(format!("{}.actual.png", name));
+    let diff = config.output_dir.join(format!("{}.diff.png", name));
+    save(image, &actual)?;
+    save(&comparison.diff, &diff)?;
+    Err(GoldenError::Mismatch {
+        differing: comparison.differing,
+        total: comparison.total,
+        actual,
+        diff,
+    })
+}
+
+/// Renders the app offscreen and panics with a description of the difference if it doesn't
+/// match its reference, for use in `#[test]`s.
+pub fn assert_golden<A: App>(
+    name: &str,
+    config: &GoldenConfig,
+    init: impl FnOnce(&mut Context) -> A,
+) {
+    let image = render_offscreen(config, init);
+    if let Err(e) = check_golden(name, &image, config) {
+        panic!("golden image {} failed: {}", name, e);
+    }
+}
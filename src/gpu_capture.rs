@@ -0,0 +1,95 @@
+//! Programmatic frame captures with RenderDoc, behind the `renderdoc` feature.
+//!
+//! The in-application API is only available when the app is launched from RenderDoc or has
+//! it injected. Otherwise, on platforms RenderDoc doesn't support, or without the feature,
+//! capture requests are logged and ignored.
+
+#[cfg(all(
+    feature = "renderdoc",
+    not(any(target_os = "macos", target_os = "ios", target_arch = "wasm32"))
+))]
+mod api {
+    pub struct Api(renderdoc::RenderDoc<renderdoc::V141>);
+
+    impl Api {
+        pub fn load() -> Option<Self> {
+            match renderdoc::RenderDoc::new() {
+                Ok(api) => {
+                    log::info!("RenderDoc attached, captures available");
+                    Some(Self(api))
+                }
+                Err(e) => {
+                    log::info!("RenderDoc not attached: {}", e);
+                    None
+                }
+            }
+        }
+
+        pub fn trigger_capture(&mut self) {
+            self.0.trigger_capture();
+        }
+
+        pub fn capture_count(&self) -> u32 {
+            self.0.get_num_captures()
+        }
+    }
+}
+
+#[cfg(not(all(
+    feature = "renderdoc",
+    not(any(target_os = "macos", target_os = "ios", target_arch = "wasm32"))
+)))]
+mod api {
+    pub enum Api {}
+
+    impl Api {
+        pub fn load() -> Option<Self> {
+            None
+        }
+
+        pub fn trigger_capture(&mut self) {
+            match *self {}
+        }
+
+        pub fn capture_count(&self) -> u32 {
+            match *self {}
+        }
+    }
+}
+
+pub struct GpuCapture {
+    api: Option<api::Api>,
+}
+
+impl GpuCapture {
+    pub(crate) fn new() -> Self {
+        Self {
+            api: api::Api::load(),
+        }
+    }
+
+    /// Whether captures can be taken.
+    pub fn is_available(&self) -> bool {
+        self.api.is_some()
+    }
+
+    /// Captures the next frame presented. Returns `false` if RenderDoc isn't available.
+    pub fn trigger(&mut self) -> bool {
+        match &mut self.api {
+            Some(api) => {
+                api.trigger_capture();
+                log::info!("capturing the next frame");
+                true
+            }
+            None => {
+                log::warn!("frame capture requested, but RenderDoc isn't attached");
+                false
+            }
+        }
+    }
+
+    /// Number of captures taken so far.
+    pub fn capture_count(&self) -> u32 {
+        self.api.as_ref().map_or(0, |api| api.capture_count())
+    }
+}
@@ -0,0 +1,575 @@
+//! Particles simulated entirely on the GPU. A compute pass updates a storage buffer of
+//! particles, writes the indices of the alive ones into a list and bumps the instance
+//! count of an indirect draw, so the CPU never touches individual particles.
+
+use crate::camera::Camera;
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::math::Vec3;
+use crate::particles::BlendMode;
+use crate::stats::Tracked;
+
+const WORKGROUP_SIZE: u32 = 64;
+const MAX_WORKGROUPS_PER_DIM: u32 = 65535;
+
+const COMPUTE_SHADER: &str = r#"
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+};
+
+struct Params {
+    origin: vec3<f32>,
+    dt: f32,
+    direction: vec3<f32>,
+    spread: f32,
+    acceleration: vec3<f32>,
+    drag: f32,
+    start_color: vec4<f32>,
+    end_color: vec4<f32>,
+    speed_min: f32,
+    speed_max: f32,
+    lifetime_min: f32,
+    lifetime_max: f32,
+    start_size: f32,
+    end_size: f32,
+    spawn_count: u32,
+    seed: u32,
+    capacity: u32,
+};
+
+struct Counters {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+    spawned: atomic<u32>,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(2) var<storage, read_write> alive: array<u32>;
+@group(0) @binding(3) var<storage, read_write> counters: Counters;
+
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn rand(seed: ptr<function, u32>) -> f32 {
+    *seed = pcg(*seed);
+    return f32(*seed) / 4294967295.0;
+}
+
+fn spawn(index: u32) -> Particle {
+    var seed = pcg(index ^ params.seed);
+    // direction inside a cone around params.direction
+    let axis = normalize(params.direction);
+    var other = vec3<f32>(1.0, 0.0, 0.0);
+    if abs(axis.x) > 0.9 {
+        other = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let u = normalize(cross(axis, other));
+    let v = cross(axis, u);
+    let z = mix(cos(params.spread), 1.0, rand(&seed));
+    let a = rand(&seed) * 6.2831853;
+    let r = sqrt(max(1.0 - z * z, 0.0));
+    let dir = u * (r * cos(a)) + v * (r * sin(a)) + axis * z;
+
+    var p: Particle;
+    p.position = params.origin;
+    p.velocity = dir * mix(params.speed_min, params.speed_max, rand(&seed));
+    p.age = 0.0;
+    p.lifetime = max(mix(params.lifetime_min, params.lifetime_max, rand(&seed)), 0.0001);
+    return p;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let index = id.x + id.y * groups.x * 64u;
+    if index >= params.capacity {
+        return;
+    }
+
+    var p = particles[index];
+    if p.age < p.lifetime {
+        p.age += params.dt;
+        p.velocity += params.acceleration * params.dt;
+        p.velocity *= max(1.0 - params.drag * params.dt, 0.0);
+        p.position += p.velocity * params.dt;
+    }
+
+    if p.age >= p.lifetime {
+        // dead particles are recycled while this frame's spawn budget lasts
+        let slot = atomicAdd(&counters.spawned, 1u);
+        if slot >= params.spawn_count {
+            particles[index] = p;
+            return;
+        }
+        p = spawn(index);
+    }
+
+    particles[index] = p;
+    let alive_index = atomicAdd(&counters.instance_count, 1u);
+    alive[alive_index] = index;
+}
+"#;
+
+const RENDER_SHADER: &str = r#"
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+};
+
+struct Params {
+    origin: vec3<f32>,
+    dt: f32,
+    direction: vec3<f32>,
+    spread: f32,
+    acceleration: vec3<f32>,
+    drag: f32,
+    start_color: vec4<f32>,
+    end_color: vec4<f32>,
+    speed_min: f32,
+    speed_max: f32,
+    lifetime_min: f32,
+    lifetime_max: f32,
+    start_size: f32,
+    end_size: f32,
+    spawn_count: u32,
+    seed: u32,
+    capacity: u32,
+};
+
+@group(0) @binding(1) var<uniform> params: Params;
+@group(0) @binding(2) var<storage, read> particles: array<Particle>;
+@group(0) @binding(3) var<storage, read> alive: array<u32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, 0.5),
+    );
+    let p = particles[alive[instance]];
+    let t = clamp(p.age / p.lifetime, 0.0, 1.0);
+    let corner = corners[vertex];
+    let size = mix(params.start_size, params.end_size, t);
+    let world = p.position
+        + globals.inverse_view[0].xyz * corner.x * size
+        + globals.inverse_view[1].xyz * corner.y * size;
+
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(world, 1.0);
+    out.uv = corner * 2.0;
+    out.color = mix(params.start_color, params.end_color, t);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(in.uv));
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
+"#;
+
+/// Emission settings. Size and color are interpolated linearly over each particle's life.
+#[derive(Clone, Debug)]
+pub struct GpuEmitterConfig {
+    pub position: Vec3,
+    pub spawn_rate: f32,
+    pub lifetime: (f32, f32),
+    pub direction: Vec3,
+    /// Half angle of the emission cone in radians.
+    pub spread: f32,
+    pub speed: (f32, f32),
+    pub acceleration: Vec3,
+    pub drag: f32,
+    pub start_size: f32,
+    pub end_size: f32,
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    /// Alpha blended particles are not sorted on the GPU, prefer additive for dense effects.
+    pub blend: BlendMode,
+}
+
+impl Default for GpuEmitterConfig {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            spawn_rate: 10_000.0,
+            lifetime: (1.0, 3.0),
+            direction: Vec3::Y,
+            spread: 0.5,
+            speed: (1.0, 3.0),
+            acceleration: Vec3::new(0.0, -1.0, 0.0),
+            drag: 0.0,
+            start_size: 0.02,
+            end_size: 0.0,
+            start_color: [1.0, 0.6, 0.2, 1.0],
+            end_color: [1.0, 0.1, 0.0, 0.0],
+            blend: BlendMode::Additive,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Params {
+    origin: [f32; 3],
+    dt: f32,
+    direction: [f32; 3],
+    spread: f32,
+    acceleration: [f32; 3],
+    drag: f32,
+    start_color: [f32; 4],
+    end_color: [f32; 4],
+    speed_min: f32,
+    speed_max: f32,
+    lifetime_min: f32,
+    lifetime_max: f32,
+    start_size: f32,
+    end_size: f32,
+    spawn_count: u32,
+    seed: u32,
+    capacity: u32,
+    _padding: [u32; 3],
+}
+unsafe impl bytemuck::Pod for Params {}
+unsafe impl bytemuck::Zeroable for Params {}
+
+/// Size in bytes of one particle in the storage buffer.
+const PARTICLE_SIZE: u64 = 32;
+
+/// A single GPU emitter with a fixed particle budget.
+///
+/// Call `update` from `App::update`, `encode` before the render pass that draws it
+/// and `render` inside that pass.
+pub struct GpuParticleSystem {
+    pub config: GpuEmitterConfig,
+    pub emitting: bool,
+    capacity: u32,
+    spawn_accumulator: f32,
+    pending_dt: f32,
+    pending_spawn: u32,
+    frame: u32,
+    params_buffer: wgpu::Buffer,
+    globals_buffer: wgpu::Buffer,
+    counters_buffer: wgpu::Buffer,
+    compute_pipeline: wgpu::ComputePipeline,
+    compute_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    render_bind_group: wgpu::BindGroup,
+    _tracked: Tracked,
+}
+
+impl GpuParticleSystem {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        capacity: u32,
+        config: GpuEmitterConfig,
+    ) -> Self {
+        let capacity = capacity.max(1);
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Particle Params"),
+            size: std::mem::size_of::<Params>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Particle Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // zero initialized, so every particle starts out dead (age >= lifetime)
+        let particle_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Particle Buffer"),
+            size: capacity as u64 * PARTICLE_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let alive_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Particle Alive List"),
+            size: capacity as u64 * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        // draw_indirect arguments followed by the spawn counter
+        let counters_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU Particle Counters"),
+            size: 5 * 4,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let storage = |binding, read_only, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let uniform = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let compute_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("GPU Particle Compute Layout"),
+            entries: &[
+                uniform(0, wgpu::ShaderStages::COMPUTE),
+                storage(1, false, wgpu::ShaderStages::COMPUTE),
+                storage(2, false, wgpu::ShaderStages::COMPUTE),
+                storage(3, false, wgpu::ShaderStages::COMPUTE),
+            ],
+        });
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("GPU Particle Compute Bind Group"),
+            layout: &compute_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: alive_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: counters_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("GPU Particle Compute Shader"),
+            source: wgpu::ShaderSource::Wgsl(COMPUTE_SHADER.into()),
+        });
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("GPU Particle Compute Pipeline Layout"),
+                bind_group_layouts: &[&compute_layout],
+                push_constant_ranges: &[],
+            });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("GPU Particle Compute Pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &compute_shader,
+            entry_point: "cs_main",
+        });
+
+        let vertex_fragment = wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT;
+        let render_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("GPU Particle Render Layout"),
+            entries: &[
+                uniform(0, wgpu::ShaderStages::VERTEX),
+                uniform(1, vertex_fragment),
+                storage(2, true, wgpu::ShaderStages::VERTEX),
+                storage(3, true, wgpu::ShaderStages::VERTEX),
+            ],
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("GPU Particle Render Bind Group"),
+            layout: &render_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: globals_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: alive_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let render_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("GPU Particle Render Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(RENDER_SHADER).into()),
+        });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("GPU Particle Render Pipeline Layout"),
+                bind_group_layouts: &[&render_layout],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("GPU Particle Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &render_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &render_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(config.blend.blend_state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let tracked = Tracked::new(2, 5, 0)
+            .with_buffer(&params_buffer)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&particle_buffer)
+            .with_buffer(&alive_buffer)
+            .with_buffer(&counters_buffer);
+
+        Self {
+            config,
+            emitting: true,
+            capacity,
+            spawn_accumulator: 0.0,
+            pending_dt: 0.0,
+            pending_spawn: 0,
+            frame: 0,
+            params_buffer,
+            globals_buffer,
+            counters_buffer,
+            compute_pipeline,
+            compute_bind_group,
+            render_pipeline,
+            render_bind_group,
+            _tracked: tracked,
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Accumulates time and the number of particles to spawn on the next `encode`.
+    pub fn update(&mut self, dt: f32) {
+        self.pending_dt += dt;
+        if self.emitting {
+            self.spawn_accumulator += self.config.spawn_rate * dt;
+            let whole = self.spawn_accumulator.floor();
+            self.spawn_accumulator -= whole;
+            self.pending_spawn = self
+                .pending_spawn
+                .saturating_add(whole as u32)
+                .min(self.capacity);
+        }
+    }
+
+    /// Spawns up to `count` particles on the next `encode`, ignoring the spawn rate.
+    pub fn burst(&mut self, count: u32) {
+        self.pending_spawn = self.pending_spawn.saturating_add(count).min(self.capacity);
+    }
+
+    /// Records the simulation compute pass. Must run before `render` in the same frame.
+    pub fn encode(
+        &mut self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera: &Camera,
+    ) {
+        let c = &self.config;
+        let params = Params {
+            origin: c.position.to_array(),
+            dt: self.pending_dt,
+            direction: c.direction.to_array(),
+            spread: c.spread,
+            acceleration: c.acceleration.to_array(),
+            drag: c.drag,
+            start_color: c.start_color,
+            end_color: c.end_color,
+            speed_min: c.speed.0,
+            speed_max: c.speed.1,
+            lifetime_min: c.lifetime.0,
+            lifetime_max: c.lifetime.1,
+            start_size: c.start_size,
+            end_size: c.end_size,
+            spawn_count: self.pending_spawn,
+            seed: self.frame.wrapping_mul(0x9E37_79B9),
+            capacity: self.capacity,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        let globals = FrameGlobals::from_camera(camera);
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        // vertex_count, instance_count, first_vertex, first_instance, spawned
+        let counters: [u32; 5] = [6, 0, 0, 0, 0];
+        queue.write_buffer(&self.counters_buffer, 0, bytemuck::cast_slice(&counters));
+
+        let groups = self.capacity.div_ceil(WORKGROUP_SIZE);
+        let groups_x = groups.min(MAX_WORKGROUPS_PER_DIM);
+        let groups_y = groups.div_ceil(groups_x);
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("GPU Particle Simulation"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.compute_pipeline);
+            pass.set_bind_group(0, &self.compute_bind_group, &[]);
+            pass.dispatch_workgroups(groups_x, groups_y, 1);
+        }
+
+        self.pending_dt = 0.0;
+        self.pending_spawn = 0;
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.push_debug_group("GPU Particles");
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.render_bind_group, &[]);
+        render_pass.draw_indirect(&self.counters_buffer, 0);
+        render_pass.pop_debug_group();
+    }
+}
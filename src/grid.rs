@@ -0,0 +1,225 @@
+//! An endless ground grid on the XZ plane, drawn as a single full screen triangle.
+//!
+//! Each fragment reconstructs its view ray from the inverse view projection, intersects it
+//! with the ground and shades minor and major lines with screen space derivatives, so they
+//! stay one line width wide at any distance. Lines fade out with distance, and as their cells
+//! get too small to tell apart.
+
+use crate::camera::Camera;
+use crate::depth;
+use crate::globals::{FrameGlobals, FRAME_GLOBALS_WGSL};
+use crate::stats::Tracked;
+
+const SHADER: &str = r#"
+struct Globals {
+    frame: FrameGlobals,
+    // cell size, cells per major line, line width in pixels, depth of the near plane
+    params: vec4<f32>,
+    minor_color: vec4<f32>,
+    major_color: vec4<f32>,
+    // x the distance the grid has faded out at
+    fade: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> globals: Globals;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// coverage of the nearest line of a grid with unit cells, for a line `width` pixels wide
+fn grid_lines(coord: vec2<f32>, width: f32) -> f32 {
+    let derivative = fwidth(coord);
+    let distance = abs(fract(coord - 0.5) - 0.5) / derivative;
+    return clamp(width * 0.5 + 0.5 - min(distance.x, distance.y), 0.0, 1.0);
+}
+
+fn axis_line(coord: f32, width: f32) -> f32 {
+    return clamp(width * 0.5 + 0.5 - abs(coord) / fwidth(coord), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // halfway is in front of the near plane and finite either way depth runs
+    let near = globals.frame.inverse_view_proj * vec4<f32>(in.ndc, globals.params.w, 1.0);
+    let far = globals.frame.inverse_view_proj * vec4<f32>(in.ndc, 0.5, 1.0);
+    let origin = near.xyz / near.w;
+    let dir = far.xyz / far.w - origin;
+    let t = -origin.y / dir.y;
+    let p = origin + dir * t;
+
+    let cell = globals.params.x;
+    let width = globals.params.z;
+    let coord = p.xz / cell;
+    let major_coord = coord / globals.params.y;
+    // cells only a few pixels across would alias, fade their lines out instead
+    let minor_size = max(fwidth(coord).x, fwidth(coord).y);
+    let major_size = max(fwidth(major_coord).x, fwidth(major_coord).y);
+    let minor = grid_lines(coord, width) * (1.0 - smoothstep(0.15, 0.4, minor_size));
+    let major = grid_lines(major_coord, width) * (1.0 - smoothstep(0.15, 0.4, major_size));
+    let x_axis = axis_line(p.z, width * 1.5);
+    let z_axis = axis_line(p.x, width * 1.5);
+
+    var color = globals.minor_color * minor;
+    color = mix(color, globals.major_color, major);
+    color = mix(color, vec4<f32>(0.9, 0.25, 0.25, 1.0), x_axis);
+    color = mix(color, vec4<f32>(0.25, 0.4, 0.9, 1.0), z_axis);
+
+    let distance = length(p.xz - globals.frame.eye.xz);
+    let fade = 1.0 - smoothstep(globals.fade.x * 0.5, globals.fade.x, distance);
+    // derivatives are taken above, only now may fragments off the plane leave
+    if (t <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(color.rgb, color.a * fade);
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Globals {
+    frame: FrameGlobals,
+    params: [f32; 4],
+    minor_color: [f32; 4],
+    major_color: [f32; 4],
+    fade: [f32; 4],
+}
+unsafe impl bytemuck::Pod for Globals {}
+unsafe impl bytemuck::Zeroable for Globals {}
+
+/// The grid has no depth, draw it before the scene so geometry covers it.
+pub struct InfiniteGrid {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// World size of a minor cell.
+    pub cell_size: f32,
+    /// Minor cells between major lines.
+    pub major_every: u32,
+    /// Line width in pixels.
+    pub line_width: f32,
+    /// Distance from the camera at which the grid has faded out.
+    pub fade_distance: f32,
+    pub minor_color: [f32; 4],
+    pub major_color: [f32; 4],
+    _tracked: Tracked,
+}
+
+impl InfiniteGrid {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Grid Shader"),
+            source: wgpu::ShaderSource::Wgsl([FRAME_GLOBALS_WGSL, SHADER].concat().into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Grid Globals"),
+            size: std::mem::size_of::<Globals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grid Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grid Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grid Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grid Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let tracked = Tracked::new(1, 1, 0).with_buffer(&globals_buffer);
+
+        Self {
+            pipeline,
+            globals_buffer,
+            bind_group,
+            cell_size: 1.0,
+            major_every: 10,
+            line_width: 1.0,
+            fade_distance: 100.0,
+            minor_color: [0.5, 0.5, 0.5, 0.5],
+            major_color: [0.7, 0.7, 0.7, 0.8],
+            _tracked: tracked,
+        }
+    }
+
+    pub fn prepare(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let globals = Globals {
+            frame: FrameGlobals::from_camera(camera),
+            params: [
+                self.cell_size,
+                self.major_every.max(1) as f32,
+                self.line_width,
+                depth::mode().near_value(),
+            ],
+            minor_color: self.minor_color,
+            major_color: self.major_color,
+            fade: [self.fade_distance, 0.0, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.push_debug_group("Grid");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
@@ -0,0 +1,948 @@
+//! The state of the keyboard, the pointer and the window's scale, kept up to date by the
+//! run loops so apps can ask what's held and where things are instead of tracking winit
+//! events themselves.
+//!
+//! winit reports the cursor in physical pixels, while UI is usually laid out in logical
+//! ones, and the two differ by the scale factor, which changes when the window moves to
+//! another monitor. Mixing them up is what makes hit-testing miss on HiDPI displays, so
+//! `InputState` gives the cursor in both and converts between them with the scale factor
+//! the events were reported with.
+//!
+//! Keys are asked for by `VirtualKeyCode`, which names what a key types in the user's
+//! layout, so `W` is a different key on AZERTY. With `KeyMapping::Physical` the same names
+//! stand for positions instead, those of the keys on a US QWERTY keyboard, so movement on
+//! WASD stays under the left hand everywhere. winit only gives the position as a platform
+//! specific scancode; the letter, digit and punctuation keys are mapped on Windows, macOS,
+//! Linux and Android, keys elsewhere and on other platforms are matched by name either way.
+//!
+//! Changes are recorded as winit reports them and move on in `update`, which the run loops
+//! call right before `App::update`, along with `Events::update`. Whatever was pressed or
+//! released in between is `just_pressed` or `just_released` for that one frame, both for
+//! a key tapped within it. The presses the OS repeats while a key is held are left out.
+//!
+//! Mouse buttons are tracked the same way, and on top of that as clicks and drags: a press
+//! and release with the cursor staying within `ClickConfig::drag_threshold` is a click,
+//! counted as a double or triple click when quickly following the last near the same
+//! spot, and a press that moves further is a `Drag` until released.
+//!
+//! Whether the window has the keyboard focus and the cursor is over it is tracked too,
+//! and `FocusPolicy` says what the run loops do while it hasn't: nothing by default.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use winit::event::{ElementState, MouseButton, VirtualKeyCode};
+
+use crate::math::Vec2;
+
+/// What `InputState::key_down` and friends take a `VirtualKeyCode` to mean.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeyMapping {
+    /// The key that types it in the user's layout, for shortcuts named after letters.
+    #[default]
+    Logical,
+    /// The key where it is on a US QWERTY keyboard, for controls laid out by position.
+    Physical,
+}
+
+/// What the run loops do while the window doesn't have the focus, see
+/// `Context::focus_policy`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FocusPolicy {
+    /// Keeps keyboard, mouse and touch events from the app, `InputState` and `Events`,
+    /// e.g. so a game in the background doesn't react to the cursor passing over it.
+    pub mute_input: bool,
+    /// Stops drawing frames, and with them updates, until the focus is back, to save
+    /// power. Time doesn't pass meanwhile.
+    pub pause_rendering: bool,
+}
+
+impl FocusPolicy {
+    /// Whether `event` is input, which `mute_input` holds back.
+    pub(crate) fn is_input(event: &winit::event::WindowEvent) -> bool {
+        use winit::event::WindowEvent as E;
+        matches!(
+            event,
+            E::KeyboardInput { .. }
+                | E::ReceivedCharacter(_)
+                | E::Ime(_)
+                | E::CursorMoved { .. }
+                | E::MouseWheel { .. }
+                | E::MouseInput { .. }
+                | E::TouchpadMagnify { .. }
+                | E::TouchpadRotate { .. }
+                | E::TouchpadPressure { .. }
+                | E::Touch(_)
+        )
+    }
+}
+
+/// When clicks count as double clicks and presses become drags. Distances are in logical
+/// pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClickConfig {
+    /// Seconds a press may follow the last to add to its click count.
+    pub double_click_time: f32,
+    /// How far from the last press it may be.
+    pub double_click_distance: f32,
+    /// How far the cursor moves with a button held before it's a drag instead of a click.
+    pub drag_threshold: f32,
+}
+
+impl Default for ClickConfig {
+    fn default() -> Self {
+        Self {
+            double_click_time: 0.4,
+            double_click_distance: 4.0,
+            drag_threshold: 4.0,
+        }
+    }
+}
+
+/// The cursor moving with a button held, see `InputState::drag`. Positions are in physical
+/// pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Drag {
+    pub button: MouseButton,
+    /// Where the button was pressed.
+    pub start: Vec2,
+    pub position: Vec2,
+    /// How far the cursor moved since the last frame.
+    pub frame_delta: Vec2,
+    /// Whether the button was released since the last frame, ending the drag.
+    pub finished: bool,
+}
+
+impl Drag {
+    /// How far the cursor moved since the press.
+    pub fn delta(&self) -> Vec2 {
+        self.position - self.start
+    }
+}
+
+/// A mouse button held down.
+#[derive(Copy, Clone, Debug)]
+struct Press {
+    start: Vec2,
+    /// Where the cursor was at the last `update`.
+    last_position: Vec2,
+    /// Clicks in a row this press adds to.
+    clicks: u32,
+    dragging: bool,
+}
+
+/// The last press of any button, for counting clicks in a row.
+#[derive(Copy, Clone, Debug)]
+struct LastPress {
+    button: MouseButton,
+    time: f64,
+    position: Vec2,
+    clicks: u32,
+}
+
+/// What's held of some kind of button and what changed this frame.
+#[derive(Clone, Debug)]
+struct Held<K> {
+    /// Seconds each has been held as of this frame.
+    down: HashMap<K, f32>,
+    pressed: HashSet<K>,
+    released: HashSet<K>,
+    /// Pressed and released since the last `update`.
+    pending_pressed: HashSet<K>,
+    pending_released: HashSet<K>,
+}
+
+impl<K: Copy + Eq + Hash> Held<K> {
+    fn new() -> Self {
+        Self {
+            down: HashMap::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            pending_pressed: HashSet::new(),
+            pending_released: HashSet::new(),
+        }
+    }
+
+    /// Presses of something already held are the OS repeating them and ignored.
+    fn press(&mut self, key: K) {
+        if let Entry::Vacant(entry) = self.down.entry(key) {
+            entry.insert(0.0);
+            self.pending_pressed.insert(key);
+        }
+    }
+
+    fn release(&mut self, key: K) {
+        if self.down.remove(&key).is_some() {
+            self.pending_released.insert(key);
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.pending_released
+            .extend(self.down.drain().map(|(key, _)| key));
+    }
+
+    fn update(&mut self, dt: f32) {
+        for (key, held) in &mut self.down {
+            // pressed since the last frame, held from now on
+            if !self.pending_pressed.contains(key) {
+                *held += dt;
+            }
+        }
+        self.pressed = std::mem::take(&mut self.pending_pressed);
+        self.released = std::mem::take(&mut self.pending_released);
+    }
+}
+
+/// See the module docs.
+#[derive(Clone, Debug)]
+pub struct InputState {
+    scale_factor: f64,
+    /// In physical pixels, `None` while the cursor is outside the window.
+    cursor: Option<Vec2>,
+    /// Whether the scale factor changed before this frame.
+    scale_changed: bool,
+    /// Whether it changed since the last `update`.
+    pending_scale_change: bool,
+    pub key_mapping: KeyMapping,
+    keys: Held<VirtualKeyCode>,
+    scancodes: Held<u32>,
+    pub click_config: ClickConfig,
+    buttons: Held<MouseButton>,
+    /// Where the cursor was last seen, for presses while it's outside the window.
+    last_cursor: Vec2,
+    focused: bool,
+    hovered: bool,
+    /// Seconds of real time passed by `update`, for timing clicks.
+    time: f64,
+    presses: HashMap<MouseButton, Press>,
+    last_press: Option<LastPress>,
+    /// Click counts of the clicks finished before this frame.
+    clicks: HashMap<MouseButton, u32>,
+    pending_clicks: HashMap<MouseButton, u32>,
+    drags: Vec<Drag>,
+    /// Drags finished since the last `update`.
+    finished_drags: Vec<Drag>,
+}
+
+impl InputState {
+    pub fn new(scale_factor: f64) -> Self {
+        Self {
+            scale_factor,
+            cursor: None,
+            scale_changed: false,
+            pending_scale_change: false,
+            key_mapping: KeyMapping::default(),
+            keys: Held::new(),
+            scancodes: Held::new(),
+            click_config: ClickConfig::default(),
+            buttons: Held::new(),
+            last_cursor: Vec2::ZERO,
+            focused: true,
+            hovered: false,
+            time: 0.0,
+            presses: HashMap::new(),
+            last_press: None,
+            clicks: HashMap::new(),
+            pending_clicks: HashMap::new(),
+            drags: Vec::new(),
+            finished_drags: Vec::new(),
+        }
+    }
+
+    pub fn with_click_config(mut self, config: ClickConfig) -> Self {
+        self.click_config = config;
+        self
+    }
+
+    pub fn with_key_mapping(mut self, mapping: KeyMapping) -> Self {
+        self.key_mapping = mapping;
+        self
+    }
+
+    /// The scancode `key` stands for under `key_mapping`, `None` to go by the name.
+    fn mapped(&self, key: VirtualKeyCode) -> Option<u32> {
+        match self.key_mapping {
+            KeyMapping::Logical => None,
+            KeyMapping::Physical => scancode(key),
+        }
+    }
+
+    /// Whether `key` is held, as `key_mapping` says to read it.
+    pub fn key_down(&self, key: VirtualKeyCode) -> bool {
+        match self.mapped(key) {
+            Some(scancode) => self.scancodes.down.contains_key(&scancode),
+            None => self.keys.down.contains_key(&key),
+        }
+    }
+
+    /// Whether `key` was pressed since the last frame.
+    pub fn just_pressed(&self, key: VirtualKeyCode) -> bool {
+        match self.mapped(key) {
+            Some(scancode) => self.scancodes.pressed.contains(&scancode),
+            None => self.keys.pressed.contains(&key),
+        }
+    }
+
+    /// Whether `key` was released since the last frame.
+    pub fn just_released(&self, key: VirtualKeyCode) -> bool {
+        match self.mapped(key) {
+            Some(scancode) => self.scancodes.released.contains(&scancode),
+            None => self.keys.released.contains(&key),
+        }
+    }
+
+    /// Seconds of real time `key` has been held for as of this frame, 0 in the frame it
+    /// was pressed, `None` while it's up.
+    pub fn pressed_duration(&self, key: VirtualKeyCode) -> Option<f32> {
+        match self.mapped(key) {
+            Some(scancode) => self.scancodes.down.get(&scancode).copied(),
+            None => self.keys.down.get(&key).copied(),
+        }
+    }
+
+    /// Whether the key that types `key` in the user's layout is held, whatever the mapping.
+    pub fn logical_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys.down.contains_key(&key)
+    }
+
+    /// Whether the key with the platform's `scancode` is held.
+    pub fn scancode_down(&self, scancode: u32) -> bool {
+        self.scancodes.down.contains_key(&scancode)
+    }
+
+    /// The keys pressed since the last frame, by name, for rebinding screens.
+    pub fn keys_just_pressed(&self) -> impl Iterator<Item = VirtualKeyCode> + '_ {
+        self.keys.pressed.iter().copied()
+    }
+
+    /// -1, 0 or 1 along an axis held by two keys, e.g. `A` and `D`.
+    pub fn key_axis(&self, negative: VirtualKeyCode, positive: VirtualKeyCode) -> f32 {
+        self.key_down(positive) as i32 as f32 - self.key_down(negative) as i32 as f32
+    }
+
+    pub fn mouse_down(&self, button: MouseButton) -> bool {
+        self.buttons.down.contains_key(&button)
+    }
+
+    pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons.pressed.contains(&button)
+    }
+
+    pub fn mouse_just_released(&self, button: MouseButton) -> bool {
+        self.buttons.released.contains(&button)
+    }
+
+    /// Seconds of real time `button` has been held for, `None` while it's up.
+    pub fn mouse_pressed_duration(&self, button: MouseButton) -> Option<f32> {
+        self.buttons.down.get(&button).copied()
+    }
+
+    /// The clicks in a row of a click of `button` finished since the last frame, 1 for a
+    /// single click, 2 for a double click and so on, 0 without one.
+    pub fn click_count(&self, button: MouseButton) -> u32 {
+        self.clicks.get(&button).copied().unwrap_or(0)
+    }
+
+    pub fn clicked(&self, button: MouseButton) -> bool {
+        self.click_count(button) >= 1
+    }
+
+    pub fn double_clicked(&self, button: MouseButton) -> bool {
+        self.click_count(button) == 2
+    }
+
+    /// The drag with `button` as of this frame, including the frame it finished in.
+    pub fn drag(&self, button: MouseButton) -> Option<&Drag> {
+        self.drags.iter().find(|drag| drag.button == button)
+    }
+
+    pub fn drags(&self) -> &[Drag] {
+        &self.drags
+    }
+
+    /// Whether the window has the keyboard focus. Assumed until told otherwise.
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Whether the cursor is over the window.
+    pub fn is_hovered(&self) -> bool {
+        self.hovered
+    }
+
+    /// Physical pixels per logical pixel, as of the last `ScaleFactorChanged`.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Whether the scale factor changed before this frame's update, e.g. because the
+    /// window moved to another monitor. Layouts in logical pixels need redoing then. A
+    /// `events::WindowEvent::ScaleFactorChanged` is sent too.
+    pub fn scale_factor_changed(&self) -> bool {
+        self.scale_changed
+    }
+
+    /// The cursor in physical pixels from the window's top left corner, what surface
+    /// coordinates, picking and `Viewport`s use. `None` while it's outside the window.
+    pub fn cursor_physical(&self) -> Option<Vec2> {
+        self.cursor
+    }
+
+    /// The cursor in logical pixels from the window's top left corner, for UI laid out
+    /// independent of the display's density.
+    pub fn cursor_logical(&self) -> Option<Vec2> {
+        self.cursor.map(|cursor| self.to_logical(cursor))
+    }
+
+    pub fn to_logical(&self, physical: Vec2) -> Vec2 {
+        physical * (1.0 / self.scale_factor as f32)
+    }
+
+    pub fn to_physical(&self, logical: Vec2) -> Vec2 {
+        logical * self.scale_factor as f32
+    }
+
+    /// Starts a frame `dt` seconds of real time after the last, see the module docs. The
+    /// run loops call this, custom loops should too.
+    pub fn update(&mut self, dt: f32) {
+        self.scale_changed = std::mem::take(&mut self.pending_scale_change);
+        self.keys.update(dt);
+        self.scancodes.update(dt);
+        self.buttons.update(dt);
+        self.time += dt as f64;
+        self.clicks = std::mem::take(&mut self.pending_clicks);
+        self.drags = std::mem::take(&mut self.finished_drags);
+        let position = self.last_cursor;
+        for (&button, press) in &mut self.presses {
+            if press.dragging {
+                self.drags.push(Drag {
+                    button,
+                    start: press.start,
+                    position,
+                    frame_delta: position - press.last_position,
+                    finished: false,
+                });
+            }
+            press.last_position = position;
+        }
+    }
+
+    fn press_mouse(&mut self, button: MouseButton) {
+        if self.presses.contains_key(&button) {
+            return;
+        }
+        self.buttons.press(button);
+        let position = self.last_cursor;
+        let config = self.click_config;
+        let near = config.double_click_distance * self.scale_factor as f32;
+        let clicks = match self.last_press {
+            Some(last)
+                if last.button == button
+                    && self.time - last.time <= config.double_click_time as f64
+                    && last.position.distance(position) <= near =>
+            {
+                last.clicks + 1
+            }
+            _ => 1,
+        };
+        self.last_press = Some(LastPress {
+            button,
+            time: self.time,
+            position,
+            clicks,
+        });
+        self.presses.insert(
+            button,
+            Press {
+                start: position,
+                last_position: position,
+                clicks,
+                dragging: false,
+            },
+        );
+    }
+
+    fn release_mouse(&mut self, button: MouseButton) {
+        self.buttons.release(button);
+        let Some(press) = self.presses.remove(&button) else {
+            return;
+        };
+        if press.dragging {
+            // a drag doesn't count towards a double click
+            self.last_press = None;
+            let position = self.last_cursor;
+            self.finished_drags.push(Drag {
+                button,
+                start: press.start,
+                position,
+                frame_delta: position - press.last_position,
+                finished: true,
+            });
+        } else {
+            self.pending_clicks.insert(button, press.clicks);
+        }
+    }
+
+    fn move_cursor(&mut self, position: Vec2) {
+        self.cursor = Some(position);
+        self.last_cursor = position;
+        let threshold = self.click_config.drag_threshold * self.scale_factor as f32;
+        for press in self.presses.values_mut() {
+            if press.start.distance(position) > threshold {
+                press.dragging = true;
+            }
+        }
+    }
+
+    pub(crate) fn set_scale_factor(&mut self, scale_factor: f64) {
+        if scale_factor != self.scale_factor {
+            self.scale_factor = scale_factor;
+            self.pending_scale_change = true;
+        }
+    }
+
+    /// Records what a winit event changes.
+    pub(crate) fn handle(&mut self, event: &winit::event::WindowEvent) {
+        use winit::event::WindowEvent as E;
+        match event {
+            E::ScaleFactorChanged { scale_factor, .. } => self.set_scale_factor(*scale_factor),
+            E::CursorMoved { position, .. } => {
+                self.move_cursor(Vec2::new(position.x as f32, position.y as f32));
+            }
+            E::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => self.press_mouse(*button),
+                ElementState::Released => self.release_mouse(*button),
+            },
+            E::CursorEntered { .. } => self.hovered = true,
+            E::CursorLeft { .. } => {
+                self.cursor = None;
+                self.hovered = false;
+            }
+            E::Focused(true) => self.focused = true,
+            E::KeyboardInput { input, .. } => match input.state {
+                ElementState::Pressed => {
+                    self.scancodes.press(input.scancode);
+                    if let Some(key) = input.virtual_keycode {
+                        self.keys.press(key);
+                    }
+                }
+                ElementState::Released => {
+                    self.scancodes.release(input.scancode);
+                    if let Some(key) = input.virtual_keycode {
+                        self.keys.release(key);
+                    }
+                }
+            },
+            // keys let go of elsewhere are never reported
+            E::Focused(false) => {
+                self.focused = false;
+                self.keys.release_all();
+                self.scancodes.release_all();
+                self.buttons.release_all();
+                self.presses.clear();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The scancodes of the keys `physical_key` knows, by the key at that position on a US
+/// QWERTY keyboard. Windows' set 1 scancodes and Linux's evdev codes agree on these.
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_arch = "wasm32")))]
+const SCANCODES: &[(u32, VirtualKeyCode)] = {
+    use VirtualKeyCode::*;
+    &[
+        (0x01, Escape),
+        (0x02, Key1),
+        (0x03, Key2),
+        (0x04, Key3),
+        (0x05, Key4),
+        (0x06, Key5),
+        (0x07, Key6),
+        (0x08, Key7),
+        (0x09, Key8),
+        (0x0A, Key9),
+        (0x0B, Key0),
+        (0x0C, Minus),
+        (0x0D, Equals),
+        (0x0E, Back),
+        (0x0F, Tab),
+        (0x10, Q),
+        (0x11, W),
+        (0x12, E),
+        (0x13, R),
+        (0x14, T),
+        (0x15, Y),
+        (0x16, U),
+        (0x17, I),
+        (0x18, O),
+        (0x19, P),
+        (0x1A, LBracket),
+        (0x1B, RBracket),
+        (0x1C, Return),
+        (0x1D, LControl),
+        (0x1E, A),
+        (0x1F, S),
+        (0x20, D),
+        (0x21, F),
+        (0x22, G),
+        (0x23, H),
+        (0x24, J),
+        (0x25, K),
+        (0x26, L),
+        (0x27, Semicolon),
+        (0x28, Apostrophe),
+        (0x29, Grave),
+        (0x2A, LShift),
+        (0x2B, Backslash),
+        (0x2C, Z),
+        (0x2D, X),
+        (0x2E, C),
+        (0x2F, V),
+        (0x30, B),
+        (0x31, N),
+        (0x32, M),
+        (0x33, Comma),
+        (0x34, Period),
+        (0x35, Slash),
+        (0x36, RShift),
+        (0x38, LAlt),
+        (0x39, Space),
+    ]
+};
+
+/// macOS' virtual key codes, which name positions despite what the name says.
+#[cfg(target_os = "macos")]
+const SCANCODES: &[(u32, VirtualKeyCode)] = {
+    use VirtualKeyCode::*;
+    &[
+        (0x00, A),
+        (0x01, S),
+        (0x02, D),
+        (0x03, F),
+        (0x04, H),
+        (0x05, G),
+        (0x06, Z),
+        (0x07, X),
+        (0x08, C),
+        (0x09, V),
+        (0x0B, B),
+        (0x0C, Q),
+        (0x0D, W),
+        (0x0E, E),
+        (0x0F, R),
+        (0x10, Y),
+        (0x11, T),
+        (0x12, Key1),
+        (0x13, Key2),
+        (0x14, Key3),
+        (0x15, Key4),
+        (0x16, Key6),
+        (0x17, Key5),
+        (0x18, Equals),
+        (0x19, Key9),
+        (0x1A, Key7),
+        (0x1B, Minus),
+        (0x1C, Key8),
+        (0x1D, Key0),
+        (0x1E, RBracket),
+        (0x1F, O),
+        (0x20, U),
+        (0x21, LBracket),
+        (0x22, I),
+        (0x23, P),
+        (0x24, Return),
+        (0x25, L),
+        (0x26, J),
+        (0x27, Apostrophe),
+        (0x28, K),
+        (0x29, Semicolon),
+        (0x2A, Backslash),
+        (0x2B, Comma),
+        (0x2C, Slash),
+        (0x2D, N),
+        (0x2E, M),
+        (0x2F, Period),
+        (0x30, Tab),
+        (0x31, Space),
+        (0x32, Grave),
+        (0x33, Back),
+        (0x35, Escape),
+    ]
+};
+
+/// The web reports layout dependent key codes and iOS none worth mapping.
+#[cfg(any(target_os = "ios", target_arch = "wasm32"))]
+const SCANCODES: &[(u32, VirtualKeyCode)] = &[];
+
+/// The key at the position of `scancode` on a US QWERTY keyboard, if it's one of those
+/// mapped on this platform.
+pub fn physical_key(scancode: u32) -> Option<VirtualKeyCode> {
+    SCANCODES
+        .iter()
+        .find(|(code, _)| *code == scancode)
+        .map(|&(_, key)| key)
+}
+
+/// The scancode of the key at `key`'s position on a US QWERTY keyboard, if it's mapped on
+/// this platform.
+pub fn scancode(key: VirtualKeyCode) -> Option<u32> {
+    SCANCODES
+        .iter()
+        .find(|(_, mapped)| *mapped == key)
+        .map(|&(code, _)| code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::dpi::{PhysicalPosition, PhysicalSize};
+    use winit::event::{DeviceId, ModifiersState, WindowEvent};
+
+    fn device() -> DeviceId {
+        unsafe { DeviceId::dummy() }
+    }
+
+    #[allow(deprecated)]
+    fn cursor_moved(x: f64, y: f64) -> WindowEvent<'static> {
+        WindowEvent::CursorMoved {
+            device_id: device(),
+            position: PhysicalPosition::new(x, y),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn key(input: &mut InputState, scancode: u32, key: VirtualKeyCode, state: ElementState) {
+        input.handle(&WindowEvent::KeyboardInput {
+            device_id: device(),
+            input: winit::event::KeyboardInput {
+                scancode,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        });
+    }
+
+    #[allow(deprecated)]
+    fn mouse(input: &mut InputState, state: ElementState) {
+        input.handle(&WindowEvent::MouseInput {
+            device_id: device(),
+            state,
+            button: MouseButton::Left,
+            modifiers: ModifiersState::empty(),
+        });
+    }
+
+    fn click(input: &mut InputState) {
+        mouse(input, ElementState::Pressed);
+        mouse(input, ElementState::Released);
+        input.update(0.1);
+    }
+
+    fn set_scale(input: &mut InputState, scale_factor: f64) {
+        let mut size = PhysicalSize::new(800, 600);
+        input.handle(&WindowEvent::ScaleFactorChanged {
+            scale_factor,
+            new_inner_size: &mut size,
+        });
+    }
+
+    #[test]
+    fn cursor_in_physical_and_logical_pixels() {
+        let mut input = InputState::new(2.0);
+        assert_eq!(input.cursor_physical(), None);
+        input.handle(&cursor_moved(200.0, 100.0));
+        assert_eq!(input.cursor_physical(), Some(Vec2::new(200.0, 100.0)));
+        assert_eq!(input.cursor_logical(), Some(Vec2::new(100.0, 50.0)));
+        assert_eq!(input.to_physical(Vec2::new(1.5, 3.0)), Vec2::new(3.0, 6.0));
+        input.handle(&WindowEvent::CursorLeft {
+            device_id: device(),
+        });
+        assert_eq!(input.cursor_logical(), None);
+    }
+
+    #[test]
+    fn scale_factor_changes_show_for_one_frame() {
+        let mut input = InputState::new(1.0);
+        input.handle(&cursor_moved(300.0, 150.0));
+        set_scale(&mut input, 1.5);
+        assert_eq!(input.scale_factor(), 1.5);
+        assert_eq!(input.cursor_logical(), Some(Vec2::new(200.0, 100.0)));
+        assert!(!input.scale_factor_changed());
+        input.update(0.016);
+        assert!(input.scale_factor_changed());
+        input.update(0.016);
+        assert!(!input.scale_factor_changed());
+        // the same factor again is no change
+        set_scale(&mut input, 1.5);
+        input.update(0.016);
+        assert!(!input.scale_factor_changed());
+    }
+
+    #[test]
+    fn scancodes_and_keys_map_both_ways() {
+        for &(code, key) in SCANCODES {
+            assert_eq!(physical_key(code), Some(key));
+            assert_eq!(scancode(key), Some(code));
+        }
+        assert_eq!(scancode(VirtualKeyCode::F13), None);
+    }
+
+    // AZERTY types Z where QWERTY has W
+    #[cfg(not(any(target_os = "ios", target_arch = "wasm32")))]
+    #[test]
+    fn physical_mapping_goes_by_position() {
+        let w = scancode(VirtualKeyCode::W).unwrap();
+        let mut input = InputState::new(1.0);
+        key(&mut input, w, VirtualKeyCode::Z, ElementState::Pressed);
+        assert!(input.key_down(VirtualKeyCode::Z));
+        assert!(!input.key_down(VirtualKeyCode::W));
+        assert!(input.scancode_down(w));
+
+        input.key_mapping = KeyMapping::Physical;
+        assert!(input.key_down(VirtualKeyCode::W));
+        assert!(!input.key_down(VirtualKeyCode::Z));
+        assert!(input.logical_key_down(VirtualKeyCode::Z));
+        assert_eq!(input.key_axis(VirtualKeyCode::S, VirtualKeyCode::W), 1.0);
+        // keys without a mapped position still go by name
+        key(
+            &mut input,
+            0xFFFF,
+            VirtualKeyCode::F13,
+            ElementState::Pressed,
+        );
+        assert!(input.key_down(VirtualKeyCode::F13));
+    }
+
+    #[test]
+    fn presses_show_for_one_frame_and_repeats_are_ignored() {
+        let mut input = InputState::new(1.0);
+        key(&mut input, 1, VirtualKeyCode::A, ElementState::Pressed);
+        assert!(!input.just_pressed(VirtualKeyCode::A));
+        input.update(0.1);
+        assert!(input.just_pressed(VirtualKeyCode::A));
+        assert_eq!(input.pressed_duration(VirtualKeyCode::A), Some(0.0));
+        // the OS repeating the press
+        key(&mut input, 1, VirtualKeyCode::A, ElementState::Pressed);
+        input.update(0.25);
+        assert!(!input.just_pressed(VirtualKeyCode::A));
+        assert_eq!(input.pressed_duration(VirtualKeyCode::A), Some(0.25));
+        key(&mut input, 1, VirtualKeyCode::A, ElementState::Released);
+        input.update(0.1);
+        assert!(input.just_released(VirtualKeyCode::A));
+        assert_eq!(input.pressed_duration(VirtualKeyCode::A), None);
+        input.update(0.1);
+        assert!(!input.just_released(VirtualKeyCode::A));
+    }
+
+    #[test]
+    fn taps_within_a_frame_are_pressed_and_released() {
+        let mut input = InputState::new(1.0);
+        key(&mut input, 1, VirtualKeyCode::A, ElementState::Pressed);
+        key(&mut input, 1, VirtualKeyCode::A, ElementState::Released);
+        input.update(0.1);
+        assert!(input.just_pressed(VirtualKeyCode::A));
+        assert!(input.just_released(VirtualKeyCode::A));
+        assert!(!input.key_down(VirtualKeyCode::A));
+        assert_eq!(
+            input.keys_just_pressed().collect::<Vec<_>>(),
+            [VirtualKeyCode::A]
+        );
+    }
+
+    #[test]
+    fn quick_clicks_nearby_count_up() {
+        let mut input = InputState::new(2.0);
+        input.handle(&cursor_moved(10.0, 10.0));
+        click(&mut input);
+        assert_eq!(input.click_count(MouseButton::Left), 1);
+        // within double_click_distance, which is in logical pixels
+        input.handle(&cursor_moved(16.0, 10.0));
+        click(&mut input);
+        assert!(input.double_clicked(MouseButton::Left));
+        click(&mut input);
+        assert_eq!(input.click_count(MouseButton::Left), 3);
+        input.update(0.1);
+        assert!(!input.clicked(MouseButton::Left));
+
+        input.update(1.0);
+        click(&mut input);
+        assert_eq!(input.click_count(MouseButton::Left), 1);
+        input.handle(&cursor_moved(100.0, 10.0));
+        click(&mut input);
+        assert_eq!(input.click_count(MouseButton::Left), 1);
+    }
+
+    #[test]
+    fn moving_far_enough_while_held_drags() {
+        let mut input = InputState::new(1.0);
+        input.handle(&cursor_moved(10.0, 10.0));
+        mouse(&mut input, ElementState::Pressed);
+        input.handle(&cursor_moved(12.0, 10.0));
+        input.update(0.1);
+        assert!(input.drag(MouseButton::Left).is_none());
+
+        input.handle(&cursor_moved(20.0, 10.0));
+        input.update(0.1);
+        let drag = *input.drag(MouseButton::Left).unwrap();
+        assert_eq!(drag.start, Vec2::new(10.0, 10.0));
+        assert_eq!(drag.delta(), Vec2::new(10.0, 0.0));
+        assert_eq!(drag.frame_delta, Vec2::new(8.0, 0.0));
+        assert!(!drag.finished);
+
+        input.handle(&cursor_moved(20.0, 15.0));
+        mouse(&mut input, ElementState::Released);
+        input.update(0.1);
+        let drag = *input.drag(MouseButton::Left).unwrap();
+        assert!(drag.finished);
+        assert_eq!(drag.frame_delta, Vec2::new(0.0, 5.0));
+        assert!(!input.clicked(MouseButton::Left));
+        input.update(0.1);
+        assert!(input.drags().is_empty());
+    }
+
+    #[test]
+    fn losing_the_focus_lets_go_of_everything() {
+        let mut input = InputState::new(1.0);
+        assert!(input.is_focused());
+        assert!(!input.is_hovered());
+        input.handle(&WindowEvent::CursorEntered {
+            device_id: device(),
+        });
+        assert!(input.is_hovered());
+        key(&mut input, 1, VirtualKeyCode::A, ElementState::Pressed);
+        mouse(&mut input, ElementState::Pressed);
+        input.update(0.1);
+
+        input.handle(&WindowEvent::Focused(false));
+        assert!(!input.is_focused());
+        input.update(0.1);
+        assert!(input.just_released(VirtualKeyCode::A));
+        assert!(!input.key_down(VirtualKeyCode::A));
+        assert!(input.mouse_just_released(MouseButton::Left));
+        // a release after the button was let go of is no click
+        mouse(&mut input, ElementState::Released);
+        input.update(0.1);
+        assert!(!input.clicked(MouseButton::Left));
+
+        input.handle(&WindowEvent::Focused(true));
+        input.handle(&WindowEvent::CursorLeft {
+            device_id: device(),
+        });
+        assert!(input.is_focused());
+        assert!(!input.is_hovered());
+    }
+
+    #[test]
+    fn focus_policy_knows_input_events() {
+        assert!(FocusPolicy::is_input(&cursor_moved(0.0, 0.0)));
+        assert!(FocusPolicy::is_input(&WindowEvent::ReceivedCharacter('a')));
+        assert!(!FocusPolicy::is_input(&WindowEvent::Focused(true)));
+        assert!(!FocusPolicy::is_input(&WindowEvent::CloseRequested));
+    }
+}
@@ -0,0 +1,346 @@
+//! A pool of worker threads shared by the engine and the app, for work that would
+//! otherwise start threads of its own. Asset decoding, SVG tessellation and culling run
+//! on it, and so can anything else.
+//!
+//! `spawn` runs a job on the pool and hands back a `JobHandle` to wait for its result.
+//! `scope` lets jobs borrow from the stack, like `std::thread::scope`, and only returns
+//! once all of them have finished. `for_each_chunk` splits a slice between the workers.
+//!
+//! The pool starts with the first job, with a worker for each core but one, which is left
+//! to the thread rendering. A worker waiting on another job runs queued jobs meanwhile, so
+//! jobs can wait for jobs without the pool running out of workers. On the web there are
+//! no threads and jobs run on the spot.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// How often a worker waiting on a job looks for queued jobs to run meanwhile.
+const HELP_INTERVAL: Duration = Duration::from_millis(1);
+
+struct Queue {
+    jobs: Mutex<VecDeque<Job>>,
+    available: Condvar,
+    workers: usize,
+}
+
+impl Queue {
+    fn push(&self, job: Job) {
+        lock(&self.jobs).push_back(job);
+        self.available.notify_one();
+    }
+
+    fn pop(&self) -> Option<Job> {
+        lock(&self.jobs).pop_front()
+    }
+
+    fn wait(&self) -> Job {
+        let mut jobs = lock(&self.jobs);
+        loop {
+            if let Some(job) = jobs.pop_front() {
+                return job;
+            }
+            jobs = self.available.wait(jobs).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+static QUEUE: OnceLock<Arc<Queue>> = OnceLock::new();
+
+thread_local! {
+    static IS_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+fn queue() -> &'static Queue {
+    QUEUE.get_or_init(|| {
+        let workers = std::thread::available_parallelism()
+            .map_or(1, |n| n.get().saturating_sub(1))
+            .max(1);
+        let queue = Arc::new(Queue {
+            jobs: Mutex::new(VecDeque::new()),
+            available: Condvar::new(),
+            workers,
+        });
+        for i in 0..workers {
+            let queue = queue.clone();
+            std::thread::Builder::new()
+                .name(format!("job worker {}", i))
+                .spawn(move || {
+                    IS_WORKER.with(|worker| worker.set(true));
+                    loop {
+                        // jobs catch their own panics, so a worker never dies
+                        queue.wait()();
+                    }
+                })
+                .expect("Job worker thread could not be started");
+        }
+        queue
+    })
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn submit(job: Job) {
+    #[cfg(not(target_arch = "wasm32"))]
+    queue().push(job);
+    #[cfg(target_arch = "wasm32")]
+    job();
+}
+
+/// Blocks until `finished` holds for the state behind `mutex`, which is signalled through
+/// `condvar`. A worker runs queued jobs instead of blocking.
+fn wait_for<'a, S>(
+    mutex: &'a Mutex<S>,
+    condvar: &Condvar,
+    finished: impl Fn(&S) -> bool,
+) -> MutexGuard<'a, S> {
+    let is_worker = IS_WORKER.with(Cell::get);
+    let mut state = lock(mutex);
+    while !finished(&state) {
+        if !is_worker {
+            state = condvar.wait(state).unwrap_or_else(|e| e.into_inner());
+            continue;
+        }
+        if let Some(job) = queue().pop() {
+            drop(state);
+            job();
+            state = lock(mutex);
+        } else {
+            state = condvar
+                .wait_timeout(state, HELP_INTERVAL)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+    state
+}
+
+/// Worker threads in the pool, 1 on the web where jobs run on the calling thread.
+pub fn workers() -> usize {
+    if cfg!(target_arch = "wasm32") {
+        1
+    } else {
+        queue().workers
+    }
+}
+
+struct Slot<T> {
+    result: Mutex<Option<std::thread::Result<T>>>,
+    done: Condvar,
+}
+
+impl<T> Slot<T> {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            result: Mutex::new(None),
+            done: Condvar::new(),
+        })
+    }
+
+    /// Runs `job` and keeps what it returned. Returns whether it panicked.
+    fn run(&self, job: impl FnOnce() -> T) -> bool {
+        let result = panic::catch_unwind(AssertUnwindSafe(job));
+        let panicked = result.is_err();
+        *lock(&self.result) = Some(result);
+        self.done.notify_all();
+        panicked
+    }
+}
+
+/// A job on the pool, from `spawn` or `Scope::spawn`. Dropping it lets the job run on
+/// without anyone waiting for it.
+pub struct JobHandle<T> {
+    slot: Arc<Slot<T>>,
+}
+
+impl<T> JobHandle<T> {
+    pub fn is_finished(&self) -> bool {
+        lock(&self.slot.result).is_some()
+    }
+
+    /// Waits for the job and returns what it returned. Panics if the job did.
+    pub fn join(self) -> T {
+        let result = wait_for(&self.slot.result, &self.slot.done, Option::is_some)
+            .take()
+            .expect("a job finishes once");
+        match result {
+            Ok(value) => value,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+/// Runs `job` on the pool.
+pub fn spawn<T: Send + 'static>(job: impl FnOnce() -> T + Send + 'static) -> JobHandle<T> {
+    let slot = Slot::new();
+    let handle = JobHandle { slot: slot.clone() };
+    submit(Box::new(move || {
+        slot.run(job);
+    }));
+    handle
+}
+
+struct ScopeState {
+    running: Mutex<usize>,
+    done: Condvar,
+    panicked: AtomicBool,
+}
+
+/// Spawns jobs that may borrow from outside the `scope` they're spawned in.
+pub struct Scope<'scope, 'env: 'scope> {
+    state: Arc<ScopeState>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Runs `job` on the pool. The scope waits for it before returning.
+    pub fn spawn<T: Send + 'scope>(
+        &'scope self,
+        job: impl FnOnce() -> T + Send + 'scope,
+    ) -> JobHandle<T> {
+        let slot = Slot::new();
+        let handle = JobHandle { slot: slot.clone() };
+        let state = self.state.clone();
+        *lock(&state.running) += 1;
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+            if slot.run(job) {
+                state.panicked.store(true, Ordering::Relaxed);
+            }
+            // the result may borrow from the scope, so it mustn't outlive it here
+            drop(slot);
+            let mut running = lock(&state.running);
+            *running -= 1;
+            if *running == 0 {
+                state.done.notify_all();
+            }
+        });
+        // Safety: `scope` doesn't return before every job spawned in it has finished, so
+        // whatever the job borrows outlives it.
+        let job: Job = unsafe { std::mem::transmute(job) };
+        submit(job);
+        handle
+    }
+}
+
+/// Calls `f` with a `Scope` to spawn jobs borrowing from the stack, then waits for all of
+/// them. Panics if any of them did.
+pub fn scope<'env, F, T>(f: F) -> T
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+{
+    let scope = Scope {
+        state: Arc::new(ScopeState {
+            running: Mutex::new(0),
+            done: Condvar::new(),
+            panicked: AtomicBool::new(false),
+        }),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    drop(wait_for(
+        &scope.state.running,
+        &scope.state.done,
+        |running| *running == 0,
+    ));
+    match result {
+        Err(payload) => panic::resume_unwind(payload),
+        Ok(_) if scope.state.panicked.load(Ordering::Relaxed) => {
+            panic!("a job spawned in a scope panicked")
+        }
+        Ok(value) => value,
+    }
+}
+
+/// Calls `f` with consecutive chunks of `items` and the index each starts at, spread over
+/// the workers and the calling thread. Chunks have at least `min_chunk` items, so short
+/// slices aren't worth the hand-off and run on the calling thread alone.
+pub fn for_each_chunk<T: Send>(
+    items: &mut [T],
+    min_chunk: usize,
+    f: impl Fn(usize, &mut [T]) + Sync,
+) {
+    let size = items.len().div_ceil(workers() + 1).max(min_chunk).max(1);
+    if size >= items.len() {
+        f(0, items);
+        return;
+    }
+    scope(|s| {
+        let f = &f;
+        let mut chunks = items.chunks_mut(size).enumerate();
+        let (_, first) = chunks.next().expect("more than one chunk");
+        for (i, chunk) in chunks {
+            s.spawn(move || f(i * size, chunk));
+        }
+        f(0, first);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn scoped_jobs_borrow_and_return() {
+        let numbers: Vec<u64> = (1..=100).collect();
+        let (low, high) = numbers.split_at(50);
+        let sums = scope(|s| {
+            let low = s.spawn(|| low.iter().sum::<u64>());
+            let high = s.spawn(|| high.iter().sum::<u64>());
+            (low.join(), high.join())
+        });
+        assert_eq!(sums, (1275, 3775));
+    }
+
+    #[test]
+    fn scope_waits_for_every_job() {
+        let finished = AtomicUsize::new(0);
+        scope(|s| {
+            for i in 0..8 {
+                let finished = &finished;
+                // dropping the handle leaves the waiting to the scope
+                s.spawn(move || {
+                    std::thread::sleep(Duration::from_millis(5 * (i % 3)));
+                    finished.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        });
+        assert_eq!(finished.load(Ordering::Relaxed), 8);
+    }
+
+    #[test]
+    fn panics_reach_the_caller() {
+        let joined = panic::catch_unwind(|| spawn(|| panic!("job failed")).join());
+        assert!(joined.is_err());
+        let ran = AtomicUsize::new(0);
+        let scoped = panic::catch_unwind(AssertUnwindSafe(|| {
+            scope(|s| {
+                s.spawn(|| panic!("job failed"));
+                s.spawn(|| ran.fetch_add(1, Ordering::Relaxed));
+            })
+        }));
+        assert!(scoped.is_err());
+        assert_eq!(ran.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn chunks_cover_the_slice() {
+        let mut items = vec![0usize; 1000];
+        for_each_chunk(&mut items, 16, |start, chunk| {
+            for (i, item) in chunk.iter_mut().enumerate() {
+                *item = start + i;
+            }
+        });
+        assert!(items.iter().enumerate().all(|(i, item)| *item == i));
+    }
+}
@@ -0,0 +1,406 @@
+//! Common image processing on `Texture`s in compute shaders, to put together without
+//! writing WGSL: blurs, edge detection, luminance, tonemapping and halving the size.
+//!
+//! `Kernels::run` reads the source with `textureLoad` and writes the target as a storage
+//! texture, which has to be `Rgba8Unorm`, `Rgba16Float` or `Rgba32Float` and the size of
+//! the source, or half of it for `Kernel::Downsample`; `Kernels::target` creates one.
+//! Values are written as they're computed, in linear space: an `Rgba8Unorm` target holds
+//! them without sRGB encoding. Each run is submitted on its own, so runs chain in the
+//! order they're made, each reading what the last one wrote.
+
+use std::collections::HashMap;
+
+use crate::stats::Tracked;
+use crate::texture::Texture;
+
+const WORKGROUP_SIZE: u32 = 8;
+/// Format of the texture separable blurs write their first pass into.
+const SCRATCH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+const SHADER: &str = r#"
+struct Params {
+    direction: vec2<i32>,
+    radius: i32,
+    // the gaussian's standard deviation, 0 for a box
+    sigma: f32,
+    exposure: f32,
+};
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var output: texture_storage_2d<FORMAT, write>;
+@group(0) @binding(2) var<uniform> params: Params;
+
+fn load(position: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(source));
+    return textureLoad(source, clamp(position, vec2<i32>(0), size - 1), 0);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn in_target(id: vec3<u32>) -> bool {
+    return all(id.xy < textureDimensions(output));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_blur(@builtin(global_invocation_id) id: vec3<u32>) {
+    if !in_target(id) {
+        return;
+    }
+    var sum = vec4<f32>(0.0);
+    var weight = 0.0;
+    for (var i = -params.radius; i <= params.radius; i = i + 1) {
+        var w = 1.0;
+        if params.sigma > 0.0 {
+            let x = f32(i);
+            w = exp(-x * x / (2.0 * params.sigma * params.sigma));
+        }
+        sum = sum + load(vec2<i32>(id.xy) + params.direction * i) * w;
+        weight = weight + w;
+    }
+    textureStore(output, vec2<i32>(id.xy), sum / weight);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_sobel(@builtin(global_invocation_id) id: vec3<u32>) {
+    if !in_target(id) {
+        return;
+    }
+    let p = vec2<i32>(id.xy);
+    var l: array<f32, 9>;
+    for (var i = 0; i < 9; i = i + 1) {
+        l[i] = luminance(load(p + vec2<i32>(i % 3 - 1, i / 3 - 1)).rgb);
+    }
+    let gx = (l[2] + 2.0 * l[5] + l[8]) - (l[0] + 2.0 * l[3] + l[6]);
+    let gy = (l[6] + 2.0 * l[7] + l[8]) - (l[0] + 2.0 * l[1] + l[2]);
+    let magnitude = length(vec2<f32>(gx, gy));
+    textureStore(output, p, vec4<f32>(vec3<f32>(magnitude), 1.0));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_luminance(@builtin(global_invocation_id) id: vec3<u32>) {
+    if !in_target(id) {
+        return;
+    }
+    let color = load(vec2<i32>(id.xy));
+    textureStore(output, vec2<i32>(id.xy), vec4<f32>(vec3<f32>(luminance(color.rgb)), color.a));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_tonemap(@builtin(global_invocation_id) id: vec3<u32>) {
+    if !in_target(id) {
+        return;
+    }
+    let color = load(vec2<i32>(id.xy));
+    let x = max(color.rgb * params.exposure, vec3<f32>(0.0));
+    // Narkowicz's fit of the ACES filmic curve, as tonemap::ToneCurve::Aces
+    let mapped = clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+    textureStore(output, vec2<i32>(id.xy), vec4<f32>(mapped, color.a));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    if !in_target(id) {
+        return;
+    }
+    let p = vec2<i32>(id.xy) * 2;
+    let sum = load(p) + load(p + vec2<i32>(1, 0)) + load(p + vec2<i32>(0, 1)) + load(p + vec2<i32>(1, 1));
+    textureStore(output, vec2<i32>(id.xy), sum * 0.25);
+}
+"#;
+
+/// An operation `Kernels::run` applies.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Kernel {
+    /// Averages the pixels within `radius` horizontally, then vertically.
+    BoxBlur { radius: u32 },
+    /// Blurs with a gaussian of standard deviation `sigma` pixels, out to 3 sigma.
+    GaussianBlur { sigma: f32 },
+    /// How sharply luminance changes at each pixel, grey with 0 where it's flat.
+    Sobel,
+    /// Grey at the color's Rec. 709 luminance, keeping alpha.
+    Luminance,
+    /// Scales by `exposure` and compresses HDR colors to 0..1 with the ACES curve.
+    Tonemap { exposure: f32 },
+    /// Averages each 2x2 block into a target half the size, rounded down, e.g. to fill in
+    /// a level of a mip or bloom chain.
+    Downsample,
+}
+
+impl Kernel {
+    fn entry_point(self) -> &'static str {
+        match self {
+            Kernel::BoxBlur { .. } | Kernel::GaussianBlur { .. } => "cs_blur",
+            Kernel::Sobel => "cs_sobel",
+            Kernel::Luminance => "cs_luminance",
+            Kernel::Tonemap { .. } => "cs_tonemap",
+            Kernel::Downsample => "cs_downsample",
+        }
+    }
+
+    /// The target's size for a source of this size.
+    pub fn target_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Kernel::Downsample => ((width / 2).max(1), (height / 2).max(1)),
+            _ => (width, height),
+        }
+    }
+
+    fn params(self, direction: [i32; 2]) -> Params {
+        let (radius, sigma) = match self {
+            Kernel::BoxBlur { radius } => (radius as i32, 0.0),
+            Kernel::GaussianBlur { sigma } => ((sigma * 3.0).ceil().max(0.0) as i32, sigma),
+            _ => (0, 0.0),
+        };
+        Params {
+            direction,
+            radius,
+            sigma,
+            exposure: match self {
+                Kernel::Tonemap { exposure } => exposure,
+                _ => 1.0,
+            },
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Params {
+    direction: [i32; 2],
+    radius: i32,
+    sigma: f32,
+    exposure: f32,
+    _padding: [u32; 3],
+}
+unsafe impl bytemuck::Pod for Params {}
+unsafe impl bytemuck::Zeroable for Params {}
+
+/// The WGSL name of a format targets can have.
+fn storage_format_name(format: wgpu::TextureFormat) -> Option<&'static str> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm => Some("rgba8unorm"),
+        wgpu::TextureFormat::Rgba16Float => Some("rgba16float"),
+        wgpu::TextureFormat::Rgba32Float => Some("rgba32float"),
+        _ => None,
+    }
+}
+
+/// Runs `Kernel`s, compiling the pipeline for each kernel and target format the first
+/// time it's used. Reuse it rather than creating one per run.
+pub struct Kernels {
+    layouts: HashMap<wgpu::TextureFormat, wgpu::BindGroupLayout>,
+    pipelines: HashMap<(&'static str, wgpu::TextureFormat), (wgpu::ComputePipeline, Tracked)>,
+    /// One per pass of a run, a blur's passes are in the same submission.
+    params_buffers: [wgpu::Buffer; 2],
+    /// Between the passes of separable blurs, kept at the size of the last source.
+    scratch: Option<Texture>,
+    _tracked: Tracked,
+}
+
+impl Kernels {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let params_buffer = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: std::mem::size_of::<Params>() as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let params_buffers = [
+            params_buffer("Kernels First Params"),
+            params_buffer("Kernels Second Params"),
+        ];
+        let tracked = Tracked::new(0, 2, 0)
+            .with_buffer(&params_buffers[0])
+            .with_buffer(&params_buffers[1]);
+        Self {
+            layouts: HashMap::new(),
+            pipelines: HashMap::new(),
+            params_buffers,
+            scratch: None,
+            _tracked: tracked,
+        }
+    }
+
+    /// An empty texture `kernel` can write the result of running on `source` into.
+    pub fn target(
+        device: &wgpu::Device,
+        kernel: Kernel,
+        source: &Texture,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Texture {
+        let (width, height) = kernel.target_size(source.width(), source.height());
+        Texture::builder(label)
+            .with_format(format)
+            .with_usage(wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC)
+            .build_empty(device, width, height)
+    }
+
+    /// Submits `kernel` reading `source` and writing `target`. Targets of another format or
+    /// size than the kernel needs are left as they are, with a warning.
+    pub fn run(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        kernel: Kernel,
+        source: &Texture,
+        target: &Texture,
+    ) {
+        if storage_format_name(target.format()).is_none() {
+            log::warn!(
+                "{:?} can't write into {:?}, only Rgba8Unorm, Rgba16Float or Rgba32Float",
+                kernel,
+                target.format()
+            );
+            return;
+        }
+        let size = kernel.target_size(source.width(), source.height());
+        if (target.width(), target.height()) != size {
+            log::warn!(
+                "{:?} of a {}x{} texture needs a {}x{} target, not {}x{}",
+                kernel,
+                source.width(),
+                source.height(),
+                size.0,
+                size.1,
+                target.width(),
+                target.height()
+            );
+            return;
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Kernels Encoder"),
+        });
+        match kernel {
+            Kernel::BoxBlur { .. } | Kernel::GaussianBlur { .. } => {
+                let scratch = match self.scratch.take() {
+                    Some(scratch) if scratch.width() == size.0 && scratch.height() == size.1 => {
+                        scratch
+                    }
+                    _ => Texture::builder("Kernels Scratch")
+                        .with_format(SCRATCH_FORMAT)
+                        .with_usage(wgpu::TextureUsages::STORAGE_BINDING)
+                        .build_empty(device, size.0, size.1),
+                };
+                queue.write_buffer(
+                    &self.params_buffers[0],
+                    0,
+                    bytemuck::bytes_of(&kernel.params([1, 0])),
+                );
+                queue.write_buffer(
+                    &self.params_buffers[1],
+                    0,
+                    bytemuck::bytes_of(&kernel.params([0, 1])),
+                );
+                self.dispatch(device, &mut encoder, kernel, source, &scratch, 0);
+                self.dispatch(device, &mut encoder, kernel, &scratch, target, 1);
+                self.scratch = Some(scratch);
+            }
+            _ => {
+                queue.write_buffer(
+                    &self.params_buffers[0],
+                    0,
+                    bytemuck::bytes_of(&kernel.params([0, 0])),
+                );
+                self.dispatch(device, &mut encoder, kernel, source, target, 0);
+            }
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    fn dispatch(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        kernel: Kernel,
+        source: &Texture,
+        target: &Texture,
+        params: usize,
+    ) {
+        let format = target.format();
+        let entry_point = kernel.entry_point();
+        let layout = self.layouts.entry(format).or_insert_with(|| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Kernels Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    target.storage_layout_entry(1, wgpu::StorageTextureAccess::WriteOnly),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        });
+        let (pipeline, _) = self
+            .pipelines
+            .entry((entry_point, format))
+            .or_insert_with(|| {
+                let name = storage_format_name(format).unwrap_or_default();
+                let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("Kernels Shader"),
+                    source: wgpu::ShaderSource::Wgsl(SHADER.replace("FORMAT", name).into()),
+                });
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("Kernels Pipeline Layout"),
+                        bind_group_layouts: &[layout],
+                        push_constant_ranges: &[],
+                    });
+                let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(&format!("Kernels Pipeline {} {}", entry_point, name)),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                });
+                (pipeline, Tracked::new(1, 0, 0))
+            });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Kernels Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&target.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.params_buffers[params].as_entire_binding(),
+                },
+            ],
+        });
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(&format!("Kernels {:?}", kernel)),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        let (x, y) = target.workgroups(WORKGROUP_SIZE);
+        pass.dispatch_workgroups(x, y, 1);
+    }
+}
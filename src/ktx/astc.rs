@@ -0,0 +1,618 @@
+//! Decoding ASTC blocks of every 2D footprint to RGBA8, for devices without ASTC support.
+//!
+//! Follows the LDR profile of the Khronos data format spec: HDR endpoints and malformed
+//! blocks decode to the error colour, magenta.
+
+/// The magenta of blocks this can't decode.
+const ERROR: [u8; 4] = [255, 0, 255, 255];
+
+/// Bits, trits and quints of each quantization level, from 2 values up to 256.
+const LEVELS: [(u32, u32, u32); 21] = [
+    (1, 0, 0),
+    (0, 1, 0),
+    (2, 0, 0),
+    (0, 0, 1),
+    (1, 1, 0),
+    (3, 0, 0),
+    (1, 0, 1),
+    (2, 1, 0),
+    (4, 0, 0),
+    (2, 0, 1),
+    (3, 1, 0),
+    (5, 0, 0),
+    (3, 0, 1),
+    (4, 1, 0),
+    (6, 0, 0),
+    (4, 0, 1),
+    (5, 1, 0),
+    (7, 0, 0),
+    (5, 0, 1),
+    (6, 1, 0),
+    (8, 0, 0),
+];
+
+/// The lowest level endpoints may be quantized to, 6 values.
+const MIN_COLOR_LEVEL: usize = 4;
+
+/// Bits taken by `count` values of quantization level `level`.
+fn sequence_bits(count: u32, level: usize) -> u32 {
+    let (bits, trits, quints) = LEVELS[level];
+    count * bits + trits * (count * 8).div_ceil(5) + quints * (count * 7).div_ceil(3)
+}
+
+/// Reads a bit range of a block, with bits past the end of the range reading 0.
+struct Bits {
+    value: u128,
+    offset: u32,
+    end: u32,
+}
+
+impl Bits {
+    fn take(&mut self, count: u32) -> u32 {
+        let available = self.end.saturating_sub(self.offset).min(count);
+        let value = if available == 0 {
+            0
+        } else {
+            (self.value >> self.offset) as u32 & ((1u64 << available) - 1) as u32
+        };
+        self.offset += count;
+        value
+    }
+}
+
+fn bit(value: u32, index: u32) -> u32 {
+    value >> index & 1
+}
+
+/// Decodes `count` values of level `level` from the integer sequence encoding starting at
+/// bit `start` of `value`.
+fn decode_sequence(value: u128, start: u32, count: usize, level: usize) -> Vec<u32> {
+    let (bits, trits, quints) = LEVELS[level];
+    let mut reader = Bits {
+        value,
+        offset: start,
+        end: start + sequence_bits(count as u32, level),
+    };
+    let mut values = Vec::with_capacity(count + 4);
+    while values.len() < count {
+        if trits == 1 {
+            let mut low = [0; 5];
+            let mut packed = 0;
+            for (i, shift) in [(0, 2), (1, 2), (2, 1), (3, 2), (4, 1)] {
+                low[i] = reader.take(bits);
+                let at = [0, 2, 4, 5, 7][i];
+                packed |= reader.take(shift) << at;
+            }
+            for (trit, low) in decode_trits(packed).into_iter().zip(low) {
+                values.push(trit << bits | low);
+            }
+        } else if quints == 1 {
+            let mut low = [0; 3];
+            let mut packed = 0;
+            for (i, (shift, at)) in [(3, 0), (2, 3), (2, 5)].into_iter().enumerate() {
+                low[i] = reader.take(bits);
+                packed |= reader.take(shift) << at;
+            }
+            for (quint, low) in decode_quints(packed).into_iter().zip(low) {
+                values.push(quint << bits | low);
+            }
+        } else {
+            values.push(reader.take(bits));
+        }
+    }
+    values.truncate(count);
+    values
+}
+
+/// The five trits packed into 8 bits.
+fn decode_trits(t: u32) -> [u32; 5] {
+    let (c, t3, t4);
+    if t >> 2 & 7 == 7 {
+        c = (t >> 5 & 7) << 2 | t & 3;
+        t4 = 2;
+        t3 = 2;
+    } else {
+        c = t & 31;
+        if t >> 5 & 3 == 3 {
+            t4 = 2;
+            t3 = bit(t, 7);
+        } else {
+            t4 = bit(t, 7);
+            t3 = t >> 5 & 3;
+        }
+    }
+    let (t0, t1, t2);
+    if c & 3 == 3 {
+        t2 = 2;
+        t1 = bit(c, 4);
+        t0 = bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1);
+    } else if c >> 2 & 3 == 3 {
+        t2 = 2;
+        t1 = 2;
+        t0 = c & 3;
+    } else {
+        t2 = bit(c, 4);
+        t1 = c >> 2 & 3;
+        t0 = bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1);
+    }
+    [t0, t1, t2, t3, t4]
+}
+
+/// The three quints packed into 7 bits.
+fn decode_quints(q: u32) -> [u32; 3] {
+    if q >> 1 & 3 == 3 && q >> 5 & 3 == 0 {
+        let q2 = bit(q, 0) << 2 | (bit(q, 4) & !bit(q, 0) & 1) << 1 | (bit(q, 3) & !bit(q, 0) & 1);
+        return [4, 4, q2];
+    }
+    let (c, q2) = if q >> 1 & 3 == 3 {
+        (((q >> 3) & 3) << 3 | (!(q >> 5) & 3) << 1 | bit(q, 0), 4)
+    } else {
+        (q & 31, q >> 5 & 3)
+    };
+    if c & 7 == 5 {
+        [c >> 3 & 3, 4, q2]
+    } else {
+        [c & 7, c >> 3 & 3, q2]
+    }
+}
+
+/// `value` of `bits` bits repeated to fill `to` bits.
+fn replicate(value: u32, bits: u32, to: u32) -> u32 {
+    if bits == 0 {
+        return 0;
+    }
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < to {
+        result = result << bits | value;
+        filled += bits;
+    }
+    result >> (filled - to)
+}
+
+/// A weight of level `level` as 0 to 64.
+fn unquantize_weight(value: u32, level: usize) -> u32 {
+    let (bits, trits, quints) = LEVELS[level];
+    let weight = if trits == 0 && quints == 0 {
+        replicate(value, bits, 6)
+    } else if bits == 0 {
+        [[0, 32, 63, 0, 0], [0, 16, 32, 47, 63]][quints as usize][value as usize]
+    } else {
+        let d = value >> bits;
+        let a = if value & 1 == 1 { 0x7F } else { 0 };
+        let (b, c) = match (trits, bits) {
+            (1, 1) => (0, 50),
+            (1, 2) => (bit(value, 1) * 0b1000101, 23),
+            (1, 3) => ((value >> 1 & 3) * 0b100001, 11),
+            (_, 1) => (0, 28),
+            _ => (bit(value, 1) * 0b1000010, 13),
+        };
+        let t = (d * c + b) ^ a;
+        (a & 0x20) | t >> 2
+    };
+    weight + (weight > 32) as u32
+}
+
+/// An endpoint value of level `level` as 0 to 255.
+fn unquantize_color(value: u32, level: usize) -> u32 {
+    let (bits, trits, quints) = LEVELS[level];
+    if trits == 0 && quints == 0 {
+        return replicate(value, bits, 8);
+    }
+    let d = value >> bits;
+    let a = if value & 1 == 1 { 0x1FF } else { 0 };
+    // the bits above the lowest, spread out the way the spec's table has them
+    let h = value >> 1 & ((1 << (bits - 1)) - 1);
+    let (b, c) = match (trits, bits) {
+        (1, 1) => (0, 204),
+        (1, 2) => (h * 0b100010110, 93),
+        (1, 3) => (h << 7 | h << 2 | h, 44),
+        (1, 4) => (h << 6 | h, 22),
+        (1, 5) => (h << 5 | h >> 2, 11),
+        (1, _) => (h << 4 | h >> 4, 5),
+        (_, 1) => (0, 113),
+        (_, 2) => (h * 0b100001100, 54),
+        (_, 3) => (h << 7 | h << 1 | h >> 1, 26),
+        (_, 4) => (h << 6 | h >> 1, 13),
+        _ => (h << 5 | h >> 3, 6),
+    };
+    let t = (d * c + b) ^ a;
+    (a & 0x80) | t >> 2
+}
+
+/// The weight grid, partitions and planes of a block, from its first 11 bits.
+struct BlockMode {
+    grid: (usize, usize),
+    dual_plane: bool,
+    level: usize,
+}
+
+fn block_mode(mode: u32) -> Option<BlockMode> {
+    let a = mode >> 5 & 3;
+    let mut high = bit(mode, 9) == 1;
+    let mut dual_plane = bit(mode, 10) == 1;
+    let mut range = bit(mode, 4);
+    let (x, y);
+    if mode & 3 != 0 {
+        range |= (mode & 3) << 1;
+        let b = mode >> 7 & 3;
+        (x, y) = match mode >> 2 & 3 {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if bit(mode, 8) == 1 => ((b & 1) + 2, a + 2),
+            _ => (a + 2, (b & 1) + 6),
+        };
+    } else {
+        range |= (mode >> 2 & 3) << 1;
+        if mode >> 2 & 3 == 0 {
+            return None;
+        }
+        let b = mode >> 9 & 3;
+        (x, y) = match mode >> 7 & 3 {
+            0 => (12, a + 2),
+            1 => (a + 2, 12),
+            2 => {
+                dual_plane = false;
+                high = false;
+                (a + 6, b + 6)
+            }
+            _ => match a {
+                0 => (6, 10),
+                1 => (10, 6),
+                _ => return None,
+            },
+        };
+    }
+    let level = (range - 2 + 6 * high as u32) as usize;
+    let count = x * y * (dual_plane as u32 + 1);
+    let bits = sequence_bits(count, level);
+    (count <= 64 && (24..=96).contains(&bits)).then_some(BlockMode {
+        grid: (x as usize, y as usize),
+        dual_plane,
+        level,
+    })
+}
+
+/// Which partition texel `(x, y)` is in, from the spec's hash.
+fn select_partition(seed: u32, mut x: u32, mut y: u32, partitions: u32, small: bool) -> usize {
+    if small {
+        x <<= 1;
+        y <<= 1;
+    }
+    let seed = seed + (partitions - 1) * 1024;
+    let rnum = hash52(seed);
+    let mut seeds = [0, 4, 8, 12, 16, 20, 24, 28, 18, 22, 26, 30].map(|shift| {
+        let seed = rnum.rotate_right(shift) & 15;
+        seed * seed
+    });
+    let (sh1, sh2) = if seed & 1 == 1 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if partitions == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partitions == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+    let sh3 = if seed & 0x10 != 0 { sh1 } else { sh2 };
+    for (i, seed) in seeds.iter_mut().enumerate() {
+        *seed >>= match i {
+            0..=7 if i % 2 == 0 => sh1,
+            0..=7 => sh2,
+            _ => sh3,
+        };
+    }
+    // z is always 0 in 2D
+    let a = (seeds[0] * x + seeds[1] * y + (rnum >> 14)) & 0x3F;
+    let b = (seeds[2] * x + seeds[3] * y + (rnum >> 10)) & 0x3F;
+    let c = (seeds[4] * x + seeds[5] * y + (rnum >> 6)) & 0x3F;
+    let d = (seeds[6] * x + seeds[7] * y + (rnum >> 2)) & 0x3F;
+    let c = if partitions >= 3 { c } else { 0 };
+    let d = if partitions >= 4 { d } else { 0 };
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+fn hash52(mut p: u32) -> u32 {
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+/// Moves a bit from the base to the offset, for the endpoint modes that store one.
+fn bit_transfer_signed(offset: i32, base: i32) -> (i32, i32) {
+    let base = base >> 1 | offset & 0x80;
+    let offset = offset >> 1 & 0x3F;
+    let offset = if offset & 0x20 != 0 {
+        offset - 0x40
+    } else {
+        offset
+    };
+    (offset, base)
+}
+
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// The two endpoints of colour endpoint mode `mode`, `None` for HDR ones.
+fn endpoints(mode: u32, v: &[i32]) -> Option<[[i32; 4]; 2]> {
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = v[0] >> 2 | v[1] & 0xC0;
+            let l1 = (l0 + (v[1] & 0x3F)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (l, l0) = bit_transfer_signed(v[1], v[0]);
+            let (a, a0) = bit_transfer_signed(v[3], v[2]);
+            [[l0, l0, l0, a0], [l0 + l, l0 + l, l0 + l, a0 + a]]
+        }
+        6 | 10 => {
+            let scale = |c: i32| (c * v[3]) >> 8;
+            let (a0, a1) = if mode == 10 { (v[4], v[5]) } else { (255, 255) };
+            [
+                [scale(v[0]), scale(v[1]), scale(v[2]), a0],
+                [v[0], v[1], v[2], a1],
+            ]
+        }
+        8 | 12 => {
+            let (a0, a1) = if mode == 12 { (v[6], v[7]) } else { (255, 255) };
+            let e0 = [v[0], v[2], v[4], a0];
+            let e1 = [v[1], v[3], v[5], a1];
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [e0, e1]
+            } else {
+                [blue_contract(e1), blue_contract(e0)]
+            }
+        }
+        9 | 13 => {
+            let pairs = if mode == 13 { 4 } else { 3 };
+            let mut base = [0, 0, 0, 255];
+            let mut offset = [0; 4];
+            for c in 0..pairs {
+                (offset[c], base[c]) = bit_transfer_signed(v[2 * c + 1], v[2 * c]);
+            }
+            let moved = std::array::from_fn(|c| base[c] + offset[c]);
+            if offset[0] + offset[1] + offset[2] >= 0 {
+                [base, moved]
+            } else {
+                [blue_contract(moved), blue_contract(base)]
+            }
+        }
+        _ => return None,
+    };
+    Some(endpoints.map(|endpoint| endpoint.map(|c| c.clamp(0, 255))))
+}
+
+/// Decodes a block `size` texels across and down into `texels`, row by row, with sRGB
+/// blocks keeping the top bits of the interpolated values the way the spec has them.
+pub(super) fn decode(block: &[u8], size: (usize, usize), srgb: bool, texels: &mut [[u8; 4]]) {
+    if decode_block(block, size, srgb, texels).is_none() {
+        texels.fill(ERROR);
+    }
+}
+
+fn decode_block(
+    block: &[u8],
+    (width, height): (usize, usize),
+    srgb: bool,
+    texels: &mut [[u8; 4]],
+) -> Option<()> {
+    let value = u128::from_le_bytes(block[..16].try_into().unwrap());
+    let field = |offset: u32, count: u32| (value >> offset) as u32 & ((1u64 << count) - 1) as u32;
+    // 16 bit values to bytes
+    let output = |c: u32| {
+        if srgb {
+            (c >> 8) as u8
+        } else {
+            ((c * 255 + 32767) / 65535) as u8
+        }
+    };
+    if field(0, 9) == 0x1FC {
+        // void extent, one colour for the whole block
+        if field(9, 1) == 1 || field(10, 2) != 3 {
+            return None;
+        }
+        let extent = [field(12, 13), field(25, 13), field(38, 13), field(51, 13)];
+        if extent != [0x1FFF; 4] && (extent[0] >= extent[1] || extent[2] >= extent[3]) {
+            return None;
+        }
+        let color = std::array::from_fn(|c| output(field(64 + 16 * c as u32, 16)));
+        texels.fill(color);
+        return Some(());
+    }
+    let mode = block_mode(field(0, 11))?;
+    let (grid_width, grid_height) = mode.grid;
+    let partitions = field(11, 2) + 1;
+    if grid_width > width || grid_height > height || (mode.dual_plane && partitions == 4) {
+        return None;
+    }
+    let planes = mode.dual_plane as usize + 1;
+    let weight_count = grid_width * grid_height * planes;
+    let weight_bits = sequence_bits(weight_count as u32, mode.level);
+    let mut below_weights = 128 - weight_bits;
+
+    let mut modes = [0; 4];
+    let color_start;
+    if partitions == 1 {
+        modes[0] = field(13, 4);
+        color_start = 17;
+    } else {
+        color_start = 29;
+        let class = field(23, 2);
+        if class == 0 {
+            modes = [field(25, 4); 4];
+        } else {
+            let extra = 3 * partitions - 4;
+            below_weights -= extra;
+            let encoded = field(23, 6) | field(below_weights, extra) << 6;
+            for (i, mode) in modes.iter_mut().enumerate().take(partitions as usize) {
+                let class = bit(encoded, 2 + i as u32) + class - 1;
+                let low = encoded >> (2 + partitions + 2 * i as u32) & 3;
+                *mode = class << 2 | low;
+            }
+        }
+    }
+    let plane2_component = if mode.dual_plane {
+        below_weights -= 2;
+        Some(field(below_weights, 2) as usize)
+    } else {
+        None
+    };
+
+    let color_count: u32 = modes[..partitions as usize]
+        .iter()
+        .map(|mode| (mode >> 2) * 2 + 2)
+        .sum();
+    if color_count > 18 || below_weights < color_start {
+        return None;
+    }
+    let color_bits = below_weights - color_start;
+    let color_level = (0..LEVELS.len())
+        .rev()
+        .find(|&level| sequence_bits(color_count, level) <= color_bits)?;
+    if color_level < MIN_COLOR_LEVEL {
+        return None;
+    }
+    let colors: Vec<i32> = decode_sequence(value, color_start, color_count as usize, color_level)
+        .into_iter()
+        .map(|c| unquantize_color(c, color_level) as i32)
+        .collect();
+    // partitions with HDR endpoints are in the error colour, the others decode
+    let mut partition_endpoints = [None; 4];
+    let mut offset = 0;
+    for (partition, mode) in modes[..partitions as usize].iter().enumerate() {
+        let count = ((mode >> 2) * 2 + 2) as usize;
+        partition_endpoints[partition] = endpoints(*mode, &colors[offset..offset + count]);
+        offset += count;
+    }
+
+    // the weights are stored backwards from the top of the block
+    let weights: Vec<u32> = decode_sequence(value.reverse_bits(), 0, weight_count, mode.level)
+        .into_iter()
+        .map(|w| unquantize_weight(w, mode.level))
+        .collect();
+
+    // fixed point steps from texels to the weight grid
+    let step_x = (1024 + width / 2) / (width - 1);
+    let step_y = (1024 + height / 2) / (height - 1);
+    let small = width * height < 31;
+    let seed = field(13, 10);
+    for (i, texel) in texels.iter_mut().enumerate().take(width * height) {
+        let (x, y) = (i % width, i / width);
+        let gx = (step_x * x * (grid_width - 1) + 32) >> 6;
+        let gy = (step_y * y * (grid_height - 1) + 32) >> 6;
+        let (jx, fx) = (gx >> 4, gx & 15);
+        let (jy, fy) = (gy >> 4, gy & 15);
+        let w11 = (fx * fy + 8) >> 4;
+        let factors = [16 + w11 - fx - fy, fx - w11, fy - w11, w11];
+        let corners = [(0, 0), (1, 0), (0, 1), (1, 1)];
+        let weight = |plane: usize| {
+            let mut sum = 8;
+            for ((dx, dy), factor) in corners.into_iter().zip(factors) {
+                if factor != 0 {
+                    let index = (jy + dy) * grid_width + jx + dx;
+                    sum += weights[index * planes + plane] as usize * factor;
+                }
+            }
+            (sum >> 4) as u32
+        };
+        let partition = if partitions == 1 {
+            0
+        } else {
+            select_partition(seed, x as u32, y as u32, partitions, small)
+        };
+        let Some([e0, e1]) = partition_endpoints[partition] else {
+            *texel = ERROR;
+            continue;
+        };
+        let (plane1, plane2) = (weight(0), plane2_component.map(|_| weight(1)));
+        *texel = std::array::from_fn(|c| {
+            let w = match plane2 {
+                Some(w) if plane2_component == Some(c) => w,
+                _ => plane1,
+            };
+            let expand = |e: i32| {
+                let e = e as u32;
+                if srgb {
+                    e << 8 | 0x80
+                } else {
+                    e << 8 | e
+                }
+            };
+            let c16 = (expand(e0[c]) * (64 - w) + expand(e1[c]) * w + 32) >> 6;
+            output(c16)
+        });
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_trits_and_quints_cover_every_combination() {
+        let trits: std::collections::HashSet<_> = (0..256).map(decode_trits).collect();
+        assert_eq!(trits.len(), 243);
+        assert!(trits.iter().flatten().all(|&t| t < 3));
+        let quints: std::collections::HashSet<_> = (0..128).map(decode_quints).collect();
+        assert_eq!(quints.len(), 125);
+        assert!(quints.iter().flatten().all(|&q| q < 5));
+    }
+
+    #[test]
+    fn unquantized_values_span_the_range() {
+        for (level, (bits, trits, quints)) in LEVELS.into_iter().enumerate() {
+            // with trits or quints and bits the lowest bit mirrors the range, so 1 is
+            // the top
+            let max = match (bits, trits + quints) {
+                (0, _) => 2 * trits + 4 * quints,
+                (_, 0) => (1 << bits) - 1,
+                _ => 1,
+            };
+            if level >= MIN_COLOR_LEVEL {
+                assert_eq!(unquantize_color(0, level), 0);
+                assert_eq!(unquantize_color(max, level), 255);
+            }
+            if level < 12 {
+                assert_eq!(unquantize_weight(0, level), 0);
+                assert_eq!(unquantize_weight(max, level), 64);
+            }
+        }
+    }
+
+    #[test]
+    fn void_extent_fills_the_block() {
+        // all ones extent, red 0xFFFF, green 0, blue 0x8000, alpha 0xFFFF
+        let mut block = 0xFFFF_FFFF_FFFF_FDFCu128;
+        block |= (0xFFFF_8000_0000_FFFFu128) << 64;
+        let mut texels = [[0; 4]; 30];
+        decode(&block.to_le_bytes(), (6, 5), false, &mut texels);
+        assert_eq!(texels, [[255, 0, 128, 255]; 30]);
+        // reserved bits cleared
+        decode(&(block & !0xC00).to_le_bytes(), (6, 5), false, &mut texels);
+        assert_eq!(texels, [ERROR; 30]);
+    }
+}
@@ -0,0 +1,914 @@
+//! Transcoding Basis Universal ETC1S textures, supercompressed with BasisLZ, to ETC2 where
+//! the device supports it, as ETC1S blocks are ETC1 blocks and so valid ETC2 ones, to BC7
+//! where it supports BC, and to RGBA8 otherwise.
+//!
+//! An ETC1S block is an endpoint, a 5 bit colour and an intensity table, and a selector, a
+//! 2 bit index per texel, both out of codebooks in the global data. Each level is a slice of
+//! Huffman coded references to them, with a second slice whose green is the alpha where the
+//! texture has alpha.
+
+use std::collections::HashMap;
+
+use wgpu::TextureFormat;
+
+use super::{error, etc};
+use crate::texture::TextureError;
+
+fn invalid() -> TextureError {
+    error("invalid ETC1S data")
+}
+
+/// Reads fields from their least significant bit up, zeros past the end.
+struct Bits<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    fn take(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.data.get(self.offset / 8).copied().unwrap_or(0);
+            value |= ((byte >> (self.offset % 8)) as u32 & 1) << i;
+            self.offset += 1;
+        }
+        value
+    }
+
+    /// A number in chunks of `bits`, each followed by a bit set if another one follows.
+    fn take_vlc(&mut self, bits: u32) -> u32 {
+        let mut value = 0;
+        let mut shift = 0;
+        while shift < 32 {
+            let chunk = self.take(bits + 1);
+            value |= (chunk & ((1 << bits) - 1)) << shift;
+            shift += bits;
+            if chunk >> bits == 0 {
+                break;
+            }
+        }
+        value
+    }
+}
+
+/// The order code lengths of code lengths are stored in.
+const CODE_LENGTH_ORDER: [usize; 21] = [
+    17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16,
+];
+
+/// A canonical Huffman code, as DEFLATE's, of up to 16 bits.
+struct Huffman {
+    /// Codes of each length.
+    counts: [u32; 17],
+    /// Symbols by code length, then by value.
+    symbols: Vec<u32>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 17];
+        for &length in lengths.iter().filter(|&&length| length > 0) {
+            counts[length as usize] += 1;
+        }
+        let mut symbols: Vec<u32> = (0..lengths.len() as u32)
+            .filter(|&symbol| lengths[symbol as usize] > 0)
+            .collect();
+        symbols.sort_by_key(|&symbol| lengths[symbol as usize]);
+        Self { counts, symbols }
+    }
+
+    /// Reads the code lengths of a table, coded with a table of their own.
+    fn read(bits: &mut Bits) -> Result<Self, TextureError> {
+        let total = bits.take(14) as usize;
+        if total == 0 {
+            return Ok(Self::new(&[]));
+        }
+        let code_length_count = bits.take(5) as usize;
+        if !(1..=21).contains(&code_length_count) {
+            return Err(invalid());
+        }
+        let mut code_lengths = [0; 21];
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[symbol] = bits.take(3) as u8;
+        }
+        let code_lengths = Self::new(&code_lengths);
+        let mut lengths = vec![0; total];
+        let mut i = 0;
+        while i < total {
+            let symbol = code_lengths.decode(bits)?;
+            let previous = || i.checked_sub(1).map(|j| lengths[j]).ok_or_else(invalid);
+            let (length, count) = match symbol {
+                0..=16 => (symbol as u8, 1),
+                17 => (0, bits.take(3) + 3),
+                18 => (0, bits.take(7) + 11),
+                19 => (previous()?, bits.take(2) + 3),
+                _ => (previous()?, bits.take(7) + 7),
+            };
+            let end = i + count as usize;
+            if end > total {
+                return Err(invalid());
+            }
+            lengths[i..end].fill(length);
+            i = end;
+        }
+        Ok(Self::new(&lengths))
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u32, TextureError> {
+        // the first code of each length follows the last of the one before
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= bits.take(1);
+            if code >= first && code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid())
+    }
+}
+
+/// The colour of a block and how far its texels stray from it.
+#[derive(Copy, Clone)]
+struct Endpoint {
+    color: [u8; 3],
+    intensity: usize,
+}
+
+impl Endpoint {
+    /// The colours of the four selector values, darkest first.
+    fn colors(self) -> [[u8; 4]; 4] {
+        let [small, large] = etc::MODIFIERS[self.intensity];
+        [-large, -small, small, large].map(|offset| {
+            let [r, g, b] = self
+                .color
+                .map(|c| ((c << 3 | c >> 2) as i32 + offset).clamp(0, 255) as u8);
+            [r, g, b, 255]
+        })
+    }
+}
+
+/// Selector values of a block, row by row.
+type Selector = [u8; 16];
+
+/// Where a level's slices are in its data.
+struct ImageDesc {
+    rgb: (usize, usize),
+    alpha: (usize, usize),
+}
+
+/// The global data shared by the levels.
+struct Codebooks {
+    endpoints: Vec<Endpoint>,
+    selectors: Vec<Selector>,
+    images: Vec<ImageDesc>,
+    endpoint_pred: Huffman,
+    delta_endpoint: Huffman,
+    selector: Huffman,
+    selector_history_rle: Huffman,
+    selector_history_size: usize,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<usize, TextureError> {
+    let bytes = data.get(offset..offset + 2).ok_or_else(invalid)?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<usize, TextureError> {
+    let bytes = data.get(offset..offset + 4).ok_or_else(invalid)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+impl Codebooks {
+    fn parse(data: &[u8], level_count: usize) -> Result<Self, TextureError> {
+        let endpoint_count = u16_at(data, 0)?;
+        let selector_count = u16_at(data, 2)?;
+        let endpoints_length = u32_at(data, 4)?;
+        let selectors_length = u32_at(data, 8)?;
+        let tables_length = u32_at(data, 12)?;
+        let mut offset = 20;
+        let mut images = Vec::with_capacity(level_count);
+        for _ in 0..level_count {
+            if u32_at(data, offset)? & 2 != 0 {
+                return Err(error("ETC1S video frames are not supported"));
+            }
+            images.push(ImageDesc {
+                rgb: (u32_at(data, offset + 4)?, u32_at(data, offset + 8)?),
+                alpha: (u32_at(data, offset + 12)?, u32_at(data, offset + 16)?),
+            });
+            offset += 20;
+        }
+        let mut section = |length: usize| {
+            let section = data.get(offset..offset + length).ok_or_else(invalid);
+            offset += length;
+            section
+        };
+        let endpoints = read_endpoints(&mut Bits::new(section(endpoints_length)?), endpoint_count)?;
+        let selectors = read_selectors(&mut Bits::new(section(selectors_length)?), selector_count)?;
+        let mut bits = Bits::new(section(tables_length)?);
+        Ok(Self {
+            endpoints,
+            selectors,
+            images,
+            endpoint_pred: Huffman::read(&mut bits)?,
+            delta_endpoint: Huffman::read(&mut bits)?,
+            selector: Huffman::read(&mut bits)?,
+            selector_history_rle: Huffman::read(&mut bits)?,
+            selector_history_size: bits.take(13) as usize,
+        })
+    }
+
+    /// The endpoint and selector indices of a slice's blocks, row by row.
+    fn decode_slice(
+        &self,
+        data: &[u8],
+        blocks_x: usize,
+        blocks_y: usize,
+    ) -> Result<Vec<(usize, usize)>, TextureError> {
+        let mut bits = Bits::new(data);
+        let (endpoint_count, selector_count) = (self.endpoints.len(), self.selectors.len());
+        let history_rle_symbol = selector_count + self.selector_history_size;
+        let mut history = History::new(self.selector_history_size);
+        // endpoints of this row and the one above, by which row is even
+        let mut row_endpoints = [vec![0; blocks_x], vec![0; blocks_x]];
+        // predictions are coded for 2x2 blocks, those of the odd row are kept till it
+        let mut odd_row_preds = vec![0; blocks_x];
+        let (mut preds, mut previous_preds, mut pred_repeats) = (0, 0, 0);
+        let (mut endpoint, mut selector_repeats) = (0, 0);
+        let mut blocks = Vec::with_capacity(blocks_x * blocks_y);
+        for y in 0..blocks_y {
+            let row = y & 1;
+            for x in 0..blocks_x {
+                if x & 1 == 0 && row == 0 {
+                    if pred_repeats > 0 {
+                        pred_repeats -= 1;
+                        preds = previous_preds;
+                    } else {
+                        preds = self.endpoint_pred.decode(&mut bits)?;
+                        if preds == 256 {
+                            pred_repeats = bits.take_vlc(4) + 2;
+                            preds = previous_preds;
+                        } else {
+                            previous_preds = preds;
+                        }
+                    }
+                    odd_row_preds[x] = preds >> 4;
+                } else if x & 1 == 0 {
+                    preds = odd_row_preds[x];
+                }
+                let above = &row_endpoints[row ^ 1];
+                endpoint = match preds & 3 {
+                    0 if x > 0 => endpoint,
+                    1 if y > 0 => above[x],
+                    2 if x > 0 && y > 0 => above[x - 1],
+                    3 => match endpoint + self.delta_endpoint.decode(&mut bits)? as usize {
+                        sum if sum >= endpoint_count => sum - endpoint_count,
+                        sum => sum,
+                    },
+                    _ => return Err(invalid()),
+                };
+                preds >>= 2;
+                row_endpoints[row][x] = endpoint;
+
+                let symbol = if selector_repeats > 0 {
+                    selector_repeats -= 1;
+                    selector_count
+                } else {
+                    let symbol = self.selector.decode(&mut bits)? as usize;
+                    if symbol == history_rle_symbol {
+                        let run = match self.selector_history_rle.decode(&mut bits)? {
+                            63 => bits.take_vlc(7) + 3,
+                            run => run + 3,
+                        } as usize;
+                        if run > blocks_x * blocks_y {
+                            return Err(invalid());
+                        }
+                        selector_repeats = run - 1;
+                        selector_count
+                    } else {
+                        symbol
+                    }
+                };
+                let selector = if symbol >= selector_count {
+                    let index = symbol - selector_count;
+                    let selector = *history.values.get(index).ok_or_else(invalid)?;
+                    history.use_value(index);
+                    selector
+                } else {
+                    history.add(symbol);
+                    symbol
+                };
+                if endpoint >= endpoint_count || selector >= selector_count {
+                    return Err(invalid());
+                }
+                blocks.push((endpoint, selector));
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+fn read_endpoints(bits: &mut Bits, count: usize) -> Result<Vec<Endpoint>, TextureError> {
+    let color_models = [
+        Huffman::read(bits)?,
+        Huffman::read(bits)?,
+        Huffman::read(bits)?,
+    ];
+    let intensity_model = Huffman::read(bits)?;
+    let grayscale = bits.take(1) == 1;
+    let mut previous = Endpoint {
+        color: [16; 3],
+        intensity: 0,
+    };
+    let mut endpoints = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut endpoint = previous;
+        endpoint.intensity = (previous.intensity + intensity_model.decode(bits)? as usize) & 7;
+        let channels = if grayscale { 1 } else { 3 };
+        for channel in &mut endpoint.color[..channels] {
+            // deltas are coded by how bright the channel was
+            let model = match *channel {
+                0..=9 => &color_models[0],
+                10..=21 => &color_models[1],
+                _ => &color_models[2],
+            };
+            *channel = (*channel + model.decode(bits)? as u8) & 31;
+        }
+        if grayscale {
+            endpoint.color = [endpoint.color[0]; 3];
+        }
+        endpoints.push(endpoint);
+        previous = endpoint;
+    }
+    Ok(endpoints)
+}
+
+fn read_selectors(bits: &mut Bits, count: usize) -> Result<Vec<Selector>, TextureError> {
+    if bits.take(1) == 1 || bits.take(1) == 1 {
+        return Err(error("ETC1S global selector codebooks are not supported"));
+    }
+    let raw = bits.take(1) == 1;
+    let model = if raw {
+        None
+    } else {
+        Some(Huffman::read(bits)?)
+    };
+    let mut rows = [0; 4];
+    let mut selectors = Vec::with_capacity(count);
+    for i in 0..count {
+        for row in &mut rows {
+            *row = match &model {
+                // the rest are each a delta to the one before
+                Some(model) if i > 0 => *row ^ model.decode(bits)? as u8,
+                _ => bits.take(8) as u8,
+            };
+        }
+        selectors.push(std::array::from_fn(|j| rows[j / 4] >> (j % 4 * 2) & 3));
+    }
+    Ok(selectors)
+}
+
+/// Recently used selectors, roughly the most used first.
+struct History {
+    values: Vec<usize>,
+    next: usize,
+}
+
+impl History {
+    fn new(size: usize) -> Self {
+        Self {
+            values: vec![0; size],
+            next: size / 2,
+        }
+    }
+
+    /// Adds a selector over the back half, round and round.
+    fn add(&mut self, value: usize) {
+        if self.values.is_empty() {
+            return;
+        }
+        self.values[self.next] = value;
+        self.next += 1;
+        if self.next == self.values.len() {
+            self.next = self.values.len() / 2;
+        }
+    }
+
+    /// Moves a selector halfway to the front.
+    fn use_value(&mut self, index: usize) {
+        self.values.swap(index / 2, index);
+    }
+}
+
+/// The ETC1 block of an ETC1S one, in differential mode with no difference.
+fn etc1_block(endpoint: Endpoint, selector: &Selector) -> [u8; 8] {
+    let [r, g, b] = endpoint.color.map(|c| c << 3);
+    let table = endpoint.intensity as u8;
+    let mut indices = 0u32;
+    for (i, &value) in selector.iter().enumerate() {
+        // ETC1's indices, column by column, go small positive, large positive, then negative
+        let index = [3, 2, 0, 1][value as usize];
+        let bit = i % 4 * 4 + i / 4;
+        indices |= (index >> 1) << (bit + 16) | (index & 1) << bit;
+    }
+    let mut block = [r, g, b, table << 5 | table << 2 | 3, 0, 0, 0, 0];
+    block[4..].copy_from_slice(&indices.to_be_bytes());
+    block
+}
+
+/// An EAC block's base, multiplier and table, and the index of each selector value.
+type EacCode = ([u8; 2], [u8; 4]);
+
+/// The EAC code closest to the alphas of the selector values.
+fn eac_code(alphas: [u8; 4]) -> EacCode {
+    let mut best = (u32::MAX, ([0; 2], [0; 4]));
+    for (table, modifiers) in etc::EAC_MODIFIERS.iter().enumerate() {
+        let (low, high) = (modifiers[3], modifiers[7]);
+        for multiplier in 1..16 {
+            let middle = (alphas[0] as i32 + alphas[3] as i32 - (low + high) * multiplier) / 2;
+            for base in (middle - 2..=middle + 2).map(|base| base.clamp(0, 255)) {
+                let mut error = 0;
+                let indices = alphas.map(|alpha| {
+                    let (index, distance) = (0..8)
+                        .map(|index| {
+                            let value = (base + modifiers[index] * multiplier).clamp(0, 255);
+                            (index, (value - alpha as i32).unsigned_abs())
+                        })
+                        .min_by_key(|&(_, distance)| distance)
+                        .unwrap();
+                    error += distance * distance;
+                    index as u8
+                });
+                if error < best.0 {
+                    best = (
+                        error,
+                        ([base as u8, (multiplier << 4) as u8 | table as u8], indices),
+                    );
+                }
+            }
+        }
+    }
+    best.1
+}
+
+fn eac_block((header, indices): EacCode, selector: &Selector) -> [u8; 8] {
+    let mut value = (header[0] as u64) << 56 | (header[1] as u64) << 48;
+    for (i, &selector) in selector.iter().enumerate() {
+        value |= (indices[selector as usize] as u64) << (45 - 3 * (i % 4 * 4 + i / 4));
+    }
+    value.to_be_bytes()
+}
+
+/// Weights, out of 64, of BC7's 2 bit indices.
+const BC7_WEIGHTS: [u32; 4] = [0, 21, 43, 64];
+
+/// BC7 endpoints of `bits` per channel for the colours of the selector values, and the
+/// index of each value, fitted by least squares over the block's texels. The indices
+/// start as the selector values and are picked again once, as clamping bends the colours
+/// off a line.
+fn fit<const N: usize>(
+    values: [[u8; N]; 4],
+    selector: &Selector,
+    bits: u32,
+) -> ([[u32; N]; 2], [u8; 4]) {
+    let expand = |value: u32| value << (8 - bits) | value >> (2 * bits - 8);
+    let mut indices = [0, 1, 2, 3];
+    let mut best = (u32::MAX, [[0; N]; 2], indices);
+    for _ in 0..2 {
+        let (mut sum_w, mut sum_ww) = (0.0, 0.0);
+        let (mut sum_v, mut sum_wv) = ([0.0; N], [0.0; N]);
+        for &s in selector {
+            let w = BC7_WEIGHTS[indices[s as usize] as usize] as f32 / 64.0;
+            sum_w += w;
+            sum_ww += w * w;
+            for c in 0..N {
+                let v = values[s as usize][c] as f32;
+                sum_v[c] += v;
+                sum_wv[c] += w * v;
+            }
+        }
+        let determinant = 16.0 * sum_ww - sum_w * sum_w;
+        let quantize = |value: f32| {
+            (value.clamp(0.0, 255.0) * ((1 << bits) - 1) as f32 / 255.0).round() as u32
+        };
+        let mut endpoints = [[0; N]; 2];
+        for c in 0..N {
+            let (start, end) = if determinant.abs() < 1e-6 {
+                (sum_v[c] / 16.0, sum_v[c] / 16.0)
+            } else {
+                let slope = (16.0 * sum_wv[c] - sum_w * sum_v[c]) / determinant;
+                let start = (sum_v[c] - slope * sum_w) / 16.0;
+                (start, start + slope)
+            };
+            endpoints[0][c] = quantize(start);
+            endpoints[1][c] = quantize(end);
+        }
+        let palette: [[i32; N]; 4] = BC7_WEIGHTS.map(|w| {
+            std::array::from_fn(|c| {
+                (((64 - w) * expand(endpoints[0][c]) + w * expand(endpoints[1][c]) + 32) >> 6)
+                    as i32
+            })
+        });
+        let distance = |index: usize, value: [u8; N]| -> u32 {
+            (0..N)
+                .map(|c| (palette[index][c] - value[c] as i32).pow(2) as u32)
+                .sum()
+        };
+        let error = selector
+            .iter()
+            .map(|&s| distance(indices[s as usize] as usize, values[s as usize]))
+            .sum();
+        if error < best.0 {
+            best = (error, endpoints, indices);
+        }
+        indices =
+            values.map(|value| (0..4).min_by_key(|&index| distance(index, value)).unwrap() as u8);
+    }
+    (best.1, best.2)
+}
+
+/// A BC7 mode 5 block of the selector values' colours and alphas.
+fn bc7_block(
+    colors: [[u8; 4]; 4],
+    selector: &Selector,
+    alpha: Option<([u8; 4], &Selector)>,
+) -> [u8; 16] {
+    let (mut color, indices) = fit(colors.map(|c| [c[0], c[1], c[2]]), selector, 7);
+    let mut color_indices = selector.map(|s| indices[s as usize]);
+    let (mut alpha, mut alpha_indices) = match alpha {
+        Some((alphas, selector)) => {
+            let (endpoints, indices) = fit(alphas.map(|a| [a]), selector, 8);
+            (
+                endpoints.map(|[a]| a),
+                selector.map(|s| indices[s as usize]),
+            )
+        }
+        None => ([255; 2], [0; 16]),
+    };
+    // the top bit of the first texel's indices is implied 0, so swap the ends to make it
+    if color_indices[0] >= 2 {
+        color.swap(0, 1);
+        color_indices = color_indices.map(|i| 3 - i);
+    }
+    if alpha_indices[0] >= 2 {
+        alpha.swap(0, 1);
+        alpha_indices = alpha_indices.map(|i| 3 - i);
+    }
+
+    let mut value = 1u128 << 5;
+    let mut offset = 8;
+    let mut put = |field: u32, count: u32| {
+        value |= (field as u128) << offset;
+        offset += count;
+    };
+    for (start, end) in color[0].into_iter().zip(color[1]) {
+        put(start, 7);
+        put(end, 7);
+    }
+    put(alpha[0], 8);
+    put(alpha[1], 8);
+    for indices in [color_indices, alpha_indices] {
+        for (i, &index) in indices.iter().enumerate() {
+            put(index as u32, if i == 0 { 1 } else { 2 });
+        }
+    }
+    value.to_le_bytes()
+}
+
+/// The format to transcode to on a device with `features`, uncompressed for sizes that
+/// aren't whole blocks, which wgpu doesn't create compressed textures of.
+fn target(features: wgpu::Features, size: (u32, u32), alpha: bool, srgb: bool) -> TextureFormat {
+    use TextureFormat as F;
+    let whole_blocks = size.0.is_multiple_of(4) && size.1.is_multiple_of(4);
+    let (linear, srgb_format) = if !whole_blocks {
+        (F::Rgba8Unorm, F::Rgba8UnormSrgb)
+    } else if features.contains(wgpu::Features::TEXTURE_COMPRESSION_ETC2) {
+        if alpha {
+            (F::Etc2Rgba8Unorm, F::Etc2Rgba8UnormSrgb)
+        } else {
+            (F::Etc2Rgb8Unorm, F::Etc2Rgb8UnormSrgb)
+        }
+    } else if features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC) {
+        (F::Bc7RgbaUnorm, F::Bc7RgbaUnormSrgb)
+    } else {
+        (F::Rgba8Unorm, F::Rgba8UnormSrgb)
+    };
+    if srgb {
+        srgb_format
+    } else {
+        linear
+    }
+}
+
+fn slice(level: &[u8], (offset, length): (usize, usize)) -> Result<&[u8], TextureError> {
+    level.get(offset..offset + length).ok_or_else(invalid)
+}
+
+/// Transcodes the levels of an ETC1S texture for a device with `features`, every level one
+/// after the other.
+pub(super) fn transcode(
+    global_data: &[u8],
+    levels: &[Vec<u8>],
+    (width, height): (u32, u32),
+    srgb: bool,
+    features: wgpu::Features,
+) -> Result<(TextureFormat, Vec<u8>), TextureError> {
+    let codebooks = Codebooks::parse(global_data, levels.len())?;
+    let alpha = codebooks
+        .images
+        .first()
+        .is_some_and(|image| image.alpha.1 > 0);
+    let format = target(features, (width, height), alpha, srgb);
+    log::info!("transcoding ETC1S texture to {:?}", format);
+    let mut eac_codes = HashMap::new();
+    let mut data = Vec::new();
+    for (i, (level, image)) in levels.iter().zip(&codebooks.images).enumerate() {
+        let width = (width as usize >> i).max(1);
+        let height = (height as usize >> i).max(1);
+        let (blocks_x, blocks_y) = (width.div_ceil(4), height.div_ceil(4));
+        let rgb = codebooks.decode_slice(slice(level, image.rgb)?, blocks_x, blocks_y)?;
+        let alpha = if alpha {
+            Some(codebooks.decode_slice(slice(level, image.alpha)?, blocks_x, blocks_y)?)
+        } else {
+            None
+        };
+        if matches!(
+            format,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+        ) {
+            let mut pixels = vec![0; width * height * 4];
+            for (b, &(endpoint, selector)) in rgb.iter().enumerate() {
+                let colors = codebooks.endpoints[endpoint].colors();
+                let selector = &codebooks.selectors[selector];
+                let alphas = alpha.as_ref().map(|alpha| {
+                    let (endpoint, selector) = alpha[b];
+                    (
+                        codebooks.endpoints[endpoint].colors(),
+                        &codebooks.selectors[selector],
+                    )
+                });
+                for j in 0..16 {
+                    let (x, y) = (b % blocks_x * 4 + j % 4, b / blocks_x * 4 + j / 4);
+                    if x < width && y < height {
+                        let mut pixel = colors[selector[j] as usize];
+                        if let Some((colors, selector)) = alphas {
+                            pixel[3] = colors[selector[j] as usize][1];
+                        }
+                        let offset = (y * width + x) * 4;
+                        pixels[offset..offset + 4].copy_from_slice(&pixel);
+                    }
+                }
+            }
+            data.extend(pixels);
+            continue;
+        }
+        for (b, &(endpoint, selector)) in rgb.iter().enumerate() {
+            let (endpoint, selector) = (
+                codebooks.endpoints[endpoint],
+                &codebooks.selectors[selector],
+            );
+            let alpha = alpha.as_ref().map(|alpha| {
+                let (endpoint, selector) = alpha[b];
+                (
+                    codebooks.endpoints[endpoint],
+                    &codebooks.selectors[selector],
+                )
+            });
+            match (format, alpha) {
+                (TextureFormat::Bc7RgbaUnorm | TextureFormat::Bc7RgbaUnormSrgb, _) => {
+                    let alpha = alpha.map(|(endpoint, selector)| {
+                        (endpoint.colors().map(|color| color[1]), selector)
+                    });
+                    data.extend(bc7_block(endpoint.colors(), selector, alpha));
+                }
+                (_, Some((alpha_endpoint, alpha_selector))) => {
+                    let key = (alpha_endpoint.color[1], alpha_endpoint.intensity);
+                    let code = *eac_codes
+                        .entry(key)
+                        .or_insert_with(|| eac_code(alpha_endpoint.colors().map(|color| color[1])));
+                    data.extend(eac_block(code, alpha_selector));
+                    data.extend(etc1_block(endpoint, selector));
+                }
+                (_, None) => data.extend(etc1_block(endpoint, selector)),
+            }
+        }
+    }
+    Ok((format, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ktx::bc;
+
+    /// Writes what `Bits` reads.
+    #[derive(Default)]
+    struct Writer {
+        bytes: Vec<u8>,
+        offset: usize,
+    }
+
+    impl Writer {
+        fn put(&mut self, value: u32, count: u32) {
+            for i in 0..count {
+                if self.offset.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                *self.bytes.last_mut().unwrap() |= ((value >> i & 1) as u8) << (self.offset % 8);
+                self.offset += 1;
+            }
+        }
+
+        /// A symbol of a table written by `table`, whose codes are the symbols themselves.
+        fn code(&mut self, symbol: u32, length: u32) {
+            for i in (0..length).rev() {
+                self.put(symbol >> i, 1);
+            }
+        }
+
+        /// A table of `total` symbols of `length` bits each.
+        fn table(&mut self, total: u32, length: u32) {
+            self.put(total, 14);
+            self.put(21, 5);
+            (0..21).for_each(|_| self.put(5, 3));
+            (0..total).for_each(|_| self.code(length, 5));
+        }
+    }
+
+    /// Global data and a level of an 8x8 texture: endpoints 1, 0 over 1, 0 and selectors
+    /// 1, 0 over 1, 0, each row coded differently. The level is its own alpha slice too.
+    fn texture(alpha: bool) -> (Vec<u8>, Vec<u8>) {
+        let mut endpoints = Writer::default();
+        // a table of a different length per colour model, to tell them apart
+        endpoints.table(32, 5);
+        endpoints.table(32, 6);
+        endpoints.table(32, 7);
+        endpoints.table(8, 3);
+        endpoints.put(0, 1);
+        // (10, 4, 30) of intensity 1, from (16, 16, 16) of 0
+        endpoints.code(1, 3);
+        for delta in [26, 20, 14] {
+            endpoints.code(delta, 6);
+        }
+        // (31, 0, 5) of intensity 7
+        endpoints.code(6, 3);
+        endpoints.code(21, 6);
+        endpoints.code(28, 5);
+        endpoints.code(7, 7);
+
+        let mut selectors = Writer::default();
+        selectors.put(0b100, 3);
+        for byte in [0xE4, 0xE4, 0xE4, 0xE4, 0xFF, 0x00, 0xAA, 0x55] {
+            selectors.put(byte, 8);
+        }
+
+        let mut tables = Writer::default();
+        tables.table(257, 9);
+        tables.table(2, 1);
+        tables.table(7, 3);
+        tables.table(64, 6);
+        tables.put(4, 13);
+
+        let mut slice = Writer::default();
+        // deltas on top, the endpoint above below
+        slice.code(3 | 3 << 2 | 1 << 4 | 1 << 6, 9);
+        slice.code(1, 1);
+        slice.code(1, 3);
+        slice.code(1, 1);
+        slice.code(0, 3);
+        // the history's third, the 1 just added, then a run of its first
+        slice.code(4, 3);
+        slice.code(6, 3);
+        slice.code(0, 6);
+
+        let length = slice.bytes.len() as u32;
+        let alpha_length = if alpha { length } else { 0 };
+        let mut data = vec![];
+        data.extend(2u16.to_le_bytes());
+        data.extend(2u16.to_le_bytes());
+        for value in [
+            endpoints.bytes.len() as u32,
+            selectors.bytes.len() as u32,
+            tables.bytes.len() as u32,
+            0,
+            0,
+            0,
+            length,
+            0,
+            alpha_length,
+        ] {
+            data.extend(value.to_le_bytes());
+        }
+        data.extend(endpoints.bytes);
+        data.extend(selectors.bytes);
+        data.extend(tables.bytes);
+        (data, slice.bytes)
+    }
+
+    fn transcode_texture(alpha: bool, features: wgpu::Features) -> (TextureFormat, Vec<u8>) {
+        let (global_data, level) = texture(alpha);
+        transcode(&global_data, &[level], (8, 8), false, features).unwrap()
+    }
+
+    fn pixel(data: &[u8], x: usize, y: usize) -> [u8; 4] {
+        data[(y * 8 + x) * 4..][..4].try_into().unwrap()
+    }
+
+    /// Decoded 4x4 blocks, laid out as RGBA8.
+    fn decode_blocks(data: &[u8], block_bytes: usize, decode: fn(&[u8]) -> bc::Texels) -> Vec<u8> {
+        let mut pixels = vec![0; 8 * 8 * 4];
+        for (b, block) in data.chunks_exact(block_bytes).enumerate() {
+            for (i, texel) in decode(block).iter().enumerate() {
+                let (x, y) = (b % 2 * 4 + i % 4, b / 2 * 4 + i / 4);
+                pixels[(y * 8 + x) * 4..][..4].copy_from_slice(texel);
+            }
+        }
+        pixels
+    }
+
+    fn max_difference(a: &[u8], b: &[u8]) -> u8 {
+        a.iter().zip(b).map(|(a, b)| a.abs_diff(*b)).max().unwrap()
+    }
+
+    #[test]
+    fn huffman_codes_are_canonical() {
+        let huffman = Huffman::new(&[2, 1, 3, 0, 3]);
+        let mut writer = Writer::default();
+        for (code, length) in [(0b111, 3), (0b0, 1), (0b10, 2), (0b110, 3)] {
+            writer.code(code, length);
+        }
+        let mut bits = Bits::new(&writer.bytes);
+        let symbols: Vec<_> = (0..4).map(|_| huffman.decode(&mut bits).unwrap()).collect();
+        assert_eq!(symbols, [4, 1, 0, 2]);
+    }
+
+    #[test]
+    fn slices_pick_endpoints_and_selectors() {
+        let (format, pixels) = transcode_texture(false, wgpu::Features::empty());
+        assert_eq!(format, TextureFormat::Rgba8Unorm);
+        // endpoint 1 at selector 3, its colour plus 183
+        assert_eq!(pixel(&pixels, 0, 0), [255, 183, 224, 255]);
+        // endpoint 0 at selector 0, its colour less 17
+        assert_eq!(pixel(&pixels, 4, 1), [65, 16, 230, 255]);
+        // selector 2 of both, plus 47 and plus 5
+        assert_eq!(pixel(&pixels, 0, 2), [255, 47, 88, 255]);
+        assert_eq!(pixel(&pixels, 6, 5), [87, 38, 252, 255]);
+        // the bottom row repeats the top one
+        assert_eq!(pixels[..4 * 8 * 4], pixels[4 * 8 * 4..]);
+    }
+
+    #[test]
+    fn partial_blocks_are_transcoded_to_rgba8() {
+        let features = wgpu::Features::TEXTURE_COMPRESSION_BC;
+        assert_eq!(
+            target(features, (8, 8), false, true),
+            TextureFormat::Bc7RgbaUnormSrgb
+        );
+        assert_eq!(
+            target(features, (8, 6), false, true),
+            TextureFormat::Rgba8UnormSrgb
+        );
+    }
+
+    #[test]
+    fn etc2_holds_etc1s_blocks_exactly() {
+        let (_, expected) = transcode_texture(false, wgpu::Features::empty());
+        let (format, data) = transcode_texture(false, wgpu::Features::TEXTURE_COMPRESSION_ETC2);
+        assert_eq!(format, TextureFormat::Etc2Rgb8Unorm);
+        assert_eq!(decode_blocks(&data, 8, etc::decode_rgb8), expected);
+
+        let (_, expected) = transcode_texture(true, wgpu::Features::empty());
+        assert_eq!(pixel(&expected, 0, 0)[3], 183);
+        let (format, data) = transcode_texture(true, wgpu::Features::TEXTURE_COMPRESSION_ETC2);
+        assert_eq!(format, TextureFormat::Etc2Rgba8Unorm);
+        let pixels = decode_blocks(&data, 16, etc::decode_rgba8);
+        let colors = |pixels: &[u8]| {
+            pixels
+                .chunks(4)
+                .flat_map(|p| &p[..3])
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(colors(&pixels), colors(&expected));
+        assert!(max_difference(&pixels, &expected) <= 4);
+    }
+
+    #[test]
+    fn bc7_fits_the_selector_colors() {
+        let (_, expected) = transcode_texture(true, wgpu::Features::empty());
+        let (format, data) = transcode_texture(true, wgpu::Features::TEXTURE_COMPRESSION_BC);
+        assert_eq!(format, TextureFormat::Bc7RgbaUnorm);
+        let pixels = decode_blocks(&data, 16, bc::decode_bc7);
+        // endpoint 0's colours are on a line, endpoint 1's are bent off it by clamping
+        for (i, (pixel, expected)) in pixels.chunks(4).zip(expected.chunks(4)).enumerate() {
+            let tolerance = if i % 8 >= 4 { 4 } else { 64 };
+            assert!(max_difference(pixel, expected) <= tolerance);
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Decoding BC1 to BC5 and BC7 blocks to RGBA8, for devices without BC support.
+
+/// Texels of a 4x4 block, row by row.
+pub(super) type Texels = [[u8; 4]; 16];
+
+fn rgb565(c: u16) -> [u8; 4] {
+    let r = (c >> 11) & 31;
+    let g = (c >> 5) & 63;
+    let b = c & 31;
+    [
+        (r << 3 | r >> 2) as u8,
+        (g << 2 | g >> 4) as u8,
+        (b << 3 | b >> 2) as u8,
+        255,
+    ]
+}
+
+fn mix(a: [u8; 4], b: [u8; 4], wa: u16, wb: u16) -> [u8; 4] {
+    std::array::from_fn(|i| ((a[i] as u16 * wa + b[i] as u16 * wb) / (wa + wb)) as u8)
+}
+
+/// The colour half of BC1 to BC3. BC2 and BC3 always use the four colour mode.
+fn decode_colors(block: &[u8], four_colors: bool) -> Texels {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let palette = if four_colors || c0 > c1 {
+        [a, b, mix(a, b, 2, 1), mix(a, b, 1, 2)]
+    } else {
+        [a, b, mix(a, b, 1, 1), [0; 4]]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[(indices >> (2 * i) & 3) as usize])
+}
+
+pub(super) fn decode_bc1(block: &[u8]) -> Texels {
+    decode_colors(block, false)
+}
+
+pub(super) fn decode_bc2(block: &[u8]) -> Texels {
+    let mut texels = decode_colors(&block[8..], true);
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = (alpha >> (4 * i) & 15) as u8 * 17;
+    }
+    texels
+}
+
+pub(super) fn decode_bc3(block: &[u8]) -> Texels {
+    let mut texels = decode_colors(&block[8..], true);
+    for (texel, alpha) in texels.iter_mut().zip(decode_channel(&block[..8], false)) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+/// The single channel blocks of BC3's alpha, BC4 and BC5, as unsigned bytes or, when
+/// `signed`, as the bytes of `i8`s.
+fn decode_channel(block: &[u8], signed: bool) -> [u8; 16] {
+    let endpoint = |byte: u8| {
+        if signed {
+            // -128 is -1 as well
+            (byte as i8).max(-127) as i32
+        } else {
+            byte as i32
+        }
+    };
+    let (a0, a1) = (endpoint(block[0]), endpoint(block[1]));
+    let (min, max) = if signed { (-127, 127) } else { (0, 255) };
+    // rounded, the way GPUs interpolate
+    let mix = |wa: i32, wb: i32| {
+        let sum = a0 * wa + a1 * wb;
+        let total = wa + wb;
+        (sum + sum.signum() * total / 2) / total
+    };
+    let values: [i32; 8] = if a0 > a1 {
+        std::array::from_fn(|i| match i {
+            0 => a0,
+            1 => a1,
+            i => mix(8 - i as i32, i as i32 - 1),
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => a0,
+            1 => a1,
+            6 => min,
+            7 => max,
+            i => mix(6 - i as i32, i as i32 - 1),
+        })
+    };
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    std::array::from_fn(|i| values[(indices >> (3 * i) & 7) as usize] as u8)
+}
+
+/// Red in BC4, red and green in BC5, like the GPU samples them: blue is 0 and alpha 1.
+fn decode_red_green(red: [u8; 16], green: Option<[u8; 16]>, signed: bool) -> Texels {
+    let one = if signed { 127 } else { 255 };
+    std::array::from_fn(|i| [red[i], green.map_or(0, |green| green[i]), 0, one])
+}
+
+pub(super) fn decode_bc4(block: &[u8]) -> Texels {
+    decode_red_green(decode_channel(block, false), None, false)
+}
+
+pub(super) fn decode_bc4_signed(block: &[u8]) -> Texels {
+    decode_red_green(decode_channel(block, true), None, true)
+}
+
+pub(super) fn decode_bc5(block: &[u8]) -> Texels {
+    let green = decode_channel(&block[8..], false);
+    decode_red_green(decode_channel(block, false), Some(green), false)
+}
+
+pub(super) fn decode_bc5_signed(block: &[u8]) -> Texels {
+    let green = decode_channel(&block[8..], true);
+    decode_red_green(decode_channel(block, true), Some(green), true)
+}
+
+/// Reads a block's fields from its least significant bit up.
+pub(super) struct Bits {
+    value: u128,
+    offset: u32,
+}
+
+impl Bits {
+    pub(super) fn new(block: &[u8]) -> Self {
+        Self {
+            value: u128::from_le_bytes(block[..16].try_into().unwrap()),
+            offset: 0,
+        }
+    }
+
+    pub(super) fn take(&mut self, count: u32) -> u32 {
+        let value = (self.value >> self.offset) as u32 & ((1u64 << count) - 1) as u32;
+        self.offset += count;
+        value
+    }
+}
+
+/// The fields of one of the eight BC7 modes.
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    /// 0 for opaque modes.
+    alpha_bits: u32,
+    /// A p-bit per endpoint.
+    endpoint_pbits: bool,
+    /// A p-bit per subset, shared by its endpoints.
+    shared_pbits: bool,
+    index_bits: u32,
+    /// Bits of the separate alpha indices of modes 4 and 5, 0 for the others.
+    index2_bits: u32,
+}
+
+/// The fields of a mode, paired up to keep the table below readable.
+const fn bc7_mode(
+    (subsets, partition_bits): (usize, u32),
+    (rotation_bits, index_selection_bits): (u32, u32),
+    (color_bits, alpha_bits): (u32, u32),
+    (endpoint_pbits, shared_pbits): (bool, bool),
+    (index_bits, index2_bits): (u32, u32),
+) -> Bc7Mode {
+    Bc7Mode {
+        subsets,
+        partition_bits,
+        rotation_bits,
+        index_selection_bits,
+        color_bits,
+        alpha_bits,
+        endpoint_pbits,
+        shared_pbits,
+        index_bits,
+        index2_bits,
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode((3, 4), (0, 0), (4, 0), (true, false), (3, 0)),
+    bc7_mode((2, 6), (0, 0), (6, 0), (false, true), (3, 0)),
+    bc7_mode((3, 6), (0, 0), (5, 0), (false, false), (2, 0)),
+    bc7_mode((2, 6), (0, 0), (7, 0), (true, false), (2, 0)),
+    bc7_mode((1, 0), (2, 1), (5, 6), (false, false), (2, 3)),
+    bc7_mode((1, 0), (2, 0), (7, 8), (false, false), (2, 2)),
+    bc7_mode((1, 0), (0, 0), (7, 7), (true, false), (4, 0)),
+    bc7_mode((2, 6), (0, 0), (5, 5), (true, false), (2, 0)),
+];
+
+/// Which texels are in the second subset of the two subset partitions, a bit per texel.
+#[rustfmt::skip]
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80,
+    0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+    0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+    0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A,
+    0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C,
+    0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// The subset of each texel in the three subset partitions.
+#[rustfmt::skip]
+const PARTITIONS_3: [[u8; 16]; 64] = [
+    [0,0,1,1,0,0,1,1,0,2,2,1,2,2,2,2], [0,0,0,1,0,0,1,1,2,2,1,1,2,2,2,1],
+    [0,0,0,0,2,0,0,1,2,2,1,1,2,2,1,1], [0,2,2,2,0,0,2,2,0,0,1,1,0,1,1,1],
+    [0,0,0,0,0,0,0,0,1,1,2,2,1,1,2,2], [0,0,1,1,0,0,1,1,0,0,2,2,0,0,2,2],
+    [0,0,2,2,0,0,2,2,1,1,1,1,1,1,1,1], [0,0,1,1,0,0,1,1,2,2,1,1,2,2,1,1],
+    [0,0,0,0,0,0,0,0,1,1,1,1,2,2,2,2], [0,0,0,0,1,1,1,1,1,1,1,1,2,2,2,2],
+    [0,0,0,0,1,1,1,1,2,2,2,2,2,2,2,2], [0,0,1,2,0,0,1,2,0,0,1,2,0,0,1,2],
+    [0,1,1,2,0,1,1,2,0,1,1,2,0,1,1,2], [0,1,2,2,0,1,2,2,0,1,2,2,0,1,2,2],
+    [0,0,1,1,0,1,1,2,1,1,2,2,1,2,2,2], [0,0,1,1,2,0,0,1,2,2,0,0,2,2,2,0],
+    [0,0,0,1,0,0,1,1,0,1,1,2,1,1,2,2], [0,1,1,1,0,0,1,1,2,0,0,1,2,2,0,0],
+    [0,0,0,0,1,1,2,2,1,1,2,2,1,1,2,2], [0,0,2,2,0,0,2,2,0,0,2,2,1,1,1,1],
+    [0,1,1,1,0,1,1,1,0,2,2,2,0,2,2,2], [0,0,0,1,0,0,0,1,2,2,2,1,2,2,2,1],
+    [0,0,0,0,0,0,1,1,0,1,2,2,0,1,2,2], [0,0,0,0,1,1,0,0,2,2,1,0,2,2,1,0],
+    [0,1,2,2,0,1,2,2,0,0,1,1,0,0,0,0], [0,0,1,2,0,0,1,2,1,1,2,2,2,2,2,2],
+    [0,1,1,0,1,2,2,1,1,2,2,1,0,1,1,0], [0,0,0,0,0,1,1,0,1,2,2,1,1,2,2,1],
+    [0,0,2,2,1,1,0,2,1,1,0,2,0,0,2,2], [0,1,1,0,0,1,1,0,2,0,0,2,2,2,2,2],
+    [0,0,1,1,0,1,2,2,0,1,2,2,0,0,1,1], [0,0,0,0,2,0,0,0,2,2,1,1,2,2,2,1],
+    [0,0,0,0,0,0,0,2,1,1,2,2,1,2,2,2], [0,2,2,2,0,0,2,2,0,0,1,2,0,0,1,1],
+    [0,0,1,1,0,0,1,2,0,0,2,2,0,2,2,2], [0,1,2,0,0,1,2,0,0,1,2,0,0,1,2,0],
+    [0,0,0,0,1,1,1,1,2,2,2,2,0,0,0,0], [0,1,2,0,1,2,0,1,2,0,1,2,0,1,2,0],
+    [0,1,2,0,2,0,1,2,1,2,0,1,0,1,2,0], [0,0,1,1,2,2,0,0,1,1,2,2,0,0,1,1],
+    [0,0,1,1,1,1,2,2,2,2,0,0,0,0,1,1], [0,1,0,1,0,1,0,1,2,2,2,2,2,2,2,2],
+    [0,0,0,0,0,0,0,0,2,1,2,1,2,1,2,1], [0,0,2,2,1,1,2,2,0,0,2,2,1,1,2,2],
+    [0,0,2,2,0,0,1,1,0,0,2,2,0,0,1,1], [0,2,2,0,1,2,2,1,0,2,2,0,1,2,2,1],
+    [0,1,0,1,2,2,2,2,2,2,2,2,0,1,0,1], [0,0,0,0,2,1,2,1,2,1,2,1,2,1,2,1],
+    [0,1,0,1,0,1,0,1,0,1,0,1,2,2,2,2], [0,2,2,2,0,1,1,1,0,2,2,2,0,1,1,1],
+    [0,0,0,2,1,1,1,2,0,0,0,2,1,1,1,2], [0,0,0,0,2,1,1,2,2,1,1,2,2,1,1,2],
+    [0,2,2,2,0,1,1,1,0,1,1,1,0,2,2,2], [0,0,0,2,1,1,1,2,1,1,1,2,0,0,0,2],
+    [0,1,1,0,0,1,1,0,0,1,1,0,2,2,2,2], [0,0,0,0,0,0,0,0,2,1,1,2,2,1,1,2],
+    [0,1,1,0,0,1,1,0,2,2,2,2,2,2,2,2], [0,0,2,2,0,0,1,1,0,0,1,1,0,0,2,2],
+    [0,0,2,2,1,1,2,2,1,1,2,2,0,0,2,2], [0,0,0,0,0,0,0,0,0,0,0,0,2,1,1,2],
+    [0,0,0,2,0,0,0,1,0,0,0,2,0,0,0,1], [0,2,2,2,1,2,2,2,0,2,2,2,1,2,2,2],
+    [0,1,0,1,2,2,2,2,2,2,2,2,2,2,2,2], [0,1,1,1,2,0,1,1,2,2,0,1,2,2,2,0],
+];
+
+/// The texel of the second subset whose index is a bit short, for two subsets.
+#[rustfmt::skip]
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The same for the second and the third subset, for three subsets.
+#[rustfmt::skip]
+const ANCHORS_3: [[u8; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8],
+    [8, 15], [8, 15], [6, 15], [6, 15], [6, 15], [5, 15], [3, 15], [3, 8],
+    [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8],
+    [8, 15], [15, 3], [3, 15], [5, 10], [6, 10], [10, 8], [8, 9], [15, 10],
+    [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15],
+    [5, 15], [10, 15], [8, 15], [13, 15], [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn interpolate(e0: u8, e1: u8, index: u32, bits: u32) -> u8 {
+    let weight = match bits {
+        2 => WEIGHTS_2[index as usize],
+        3 => WEIGHTS_3[index as usize],
+        _ => WEIGHTS_4[index as usize],
+    };
+    (((64 - weight) * e0 as u32 + weight * e1 as u32 + 32) >> 6) as u8
+}
+
+pub(super) fn decode_bc7(block: &[u8]) -> Texels {
+    let mut bits = Bits::new(block);
+    let Some(mode) = (0..8).find(|_| bits.take(1) == 1) else {
+        // reserved, decodes to transparent black
+        return [[0; 4]; 16];
+    };
+    let mode = &BC7_MODES[mode];
+    let partition = bits.take(mode.partition_bits) as usize;
+    let rotation = bits.take(mode.rotation_bits);
+    let index_selection = bits.take(mode.index_selection_bits);
+
+    let endpoints = mode.subsets * 2;
+    let mut colors = [[0u32; 4]; 6];
+    for channel in 0..4 {
+        let channel_bits = if channel < 3 {
+            mode.color_bits
+        } else {
+            mode.alpha_bits
+        };
+        for color in &mut colors[..endpoints] {
+            color[channel] = bits.take(channel_bits);
+        }
+    }
+    let mut pbits = [0; 6];
+    if mode.endpoint_pbits {
+        pbits[..endpoints].fill_with(|| bits.take(1));
+    } else if mode.shared_pbits {
+        for subset in pbits[..endpoints].chunks_mut(2) {
+            subset.fill(bits.take(1));
+        }
+    }
+    let has_pbits = mode.endpoint_pbits || mode.shared_pbits;
+    let endpoints: [[u8; 4]; 6] = std::array::from_fn(|e| {
+        std::array::from_fn(|channel| {
+            let channel_bits = if channel < 3 {
+                mode.color_bits
+            } else {
+                mode.alpha_bits
+            };
+            if channel_bits == 0 {
+                return 255;
+            }
+            let (value, count) = if has_pbits {
+                (colors[e][channel] << 1 | pbits[e], channel_bits + 1)
+            } else {
+                (colors[e][channel], channel_bits)
+            };
+            (value << (8 - count) | value >> (2 * count - 8)) as u8
+        })
+    });
+
+    let subset = |texel: usize| match mode.subsets {
+        1 => 0,
+        2 => (PARTITIONS_2[partition] >> texel & 1) as usize,
+        _ => PARTITIONS_3[partition][texel] as usize,
+    };
+    let is_anchor = |texel: usize| {
+        texel == 0
+            || match mode.subsets {
+                1 => false,
+                2 => texel == ANCHORS_2[partition] as usize,
+                _ => ANCHORS_3[partition].contains(&(texel as u8)),
+            }
+    };
+    let indices: [u32; 16] =
+        std::array::from_fn(|texel| bits.take(mode.index_bits - is_anchor(texel) as u32));
+    let indices2: [u32; 16] = if mode.index2_bits > 0 {
+        std::array::from_fn(|texel| bits.take(mode.index2_bits - (texel == 0) as u32))
+    } else {
+        indices
+    };
+    let (color_bits, alpha_bits) = (mode.index_bits, mode.index2_bits.max(mode.index_bits));
+    let (color_indices, color_bits, alpha_indices, alpha_bits) = if index_selection == 1 {
+        (indices2, alpha_bits, indices, color_bits)
+    } else {
+        (indices, color_bits, indices2, alpha_bits)
+    };
+
+    std::array::from_fn(|texel| {
+        let s = subset(texel);
+        let (e0, e1) = (endpoints[2 * s], endpoints[2 * s + 1]);
+        let mut color: [u8; 4] = std::array::from_fn(|channel| {
+            if channel < 3 {
+                interpolate(e0[channel], e1[channel], color_indices[texel], color_bits)
+            } else {
+                interpolate(e0[3], e1[3], alpha_indices[texel], alpha_bits)
+            }
+        });
+        if rotation > 0 {
+            color.swap(3, rotation as usize - 1);
+        }
+        color
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anchors_are_in_their_subsets() {
+        for partition in 0..64 {
+            assert_eq!(PARTITIONS_2[partition] & 1, 0);
+            assert_eq!(PARTITIONS_2[partition] >> ANCHORS_2[partition] & 1, 1);
+            let [second, third] = ANCHORS_3[partition];
+            assert_eq!(PARTITIONS_3[partition][0], 0);
+            assert_eq!(PARTITIONS_3[partition][second as usize], 1);
+            assert_eq!(PARTITIONS_3[partition][third as usize], 2);
+        }
+    }
+
+    #[test]
+    fn bc7_mode_6_interpolates() {
+        // mode 6, endpoints 0 and all ones with p-bits 0 and 1, index 15 for texel 15
+        let mut value: u128 = 1 << 6;
+        let mut offset = 7;
+        for _ in 0..4 {
+            value |= 0x7F << (offset + 7);
+            offset += 14;
+        }
+        value |= 1 << (offset + 1);
+        offset += 2;
+        value |= 0xF << (offset + 3 + 14 * 4);
+        let texels = decode_bc7(&value.to_le_bytes());
+        assert_eq!(texels[0], [0; 4]);
+        assert_eq!(texels[15], [255; 4]);
+    }
+
+    #[test]
+    fn bc4_interpolates_in_both_modes() {
+        // every texel takes index 2, 6/7 of the way from the second endpoint to the first
+        let indices: u64 = (0..16).map(|i| 2 << (3 * i)).sum();
+        let mut block = [70, 0, 0, 0, 0, 0, 0, 0];
+        block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+        assert_eq!(decode_bc4(&block), [[60, 0, 0, 255]; 16]);
+
+        // with the first endpoint not above the second, index 7 is the maximum
+        let block = [10, 20, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert_eq!(decode_bc4(&block)[5], [255, 0, 0, 255]);
+        assert_eq!(decode_bc4_signed(&block)[5], [127, 0, 0, 127]);
+    }
+}
@@ -0,0 +1,309 @@
+//! Decoding ETC2 and EAC blocks to RGBA8, for devices without ETC2 support.
+
+use super::bc::Texels;
+
+/// Modifiers of the individual and differential modes, for index 0 and 1, negated for 2
+/// and 3.
+pub(super) const MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+/// Distances of the T and H modes.
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+/// Modifiers of EAC blocks.
+#[rustfmt::skip]
+pub(super) const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14], [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12], [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11], [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10], [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9], [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9], [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9], [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8], [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn bits(value: u64, high: u32, low: u32) -> i32 {
+    (value >> low & ((1 << (high - low + 1)) - 1)) as i32
+}
+
+fn extend4(value: i32) -> i32 {
+    value << 4 | value
+}
+
+fn extend5(value: i32) -> i32 {
+    value << 3 | value >> 2
+}
+
+fn add(color: [i32; 3], offset: i32) -> [u8; 4] {
+    let [r, g, b] = color.map(|c| (c + offset).clamp(0, 255) as u8);
+    [r, g, b, 255]
+}
+
+/// The colour blocks of ETC2, RGB8A1's when `punchthrough`.
+fn decode_color(block: &[u8], punchthrough: bool) -> Texels {
+    let value = u64::from_be_bytes(block[..8].try_into().unwrap());
+    // the differential bit, or in RGB8A1 whether the block is opaque
+    let diff = value >> 33 & 1 == 1;
+    let opaque = !punchthrough || diff;
+    let mut texels = [[0; 4]; 16];
+    if !punchthrough && !diff {
+        let base = [
+            [
+                bits(value, 63, 60),
+                bits(value, 55, 52),
+                bits(value, 47, 44),
+            ],
+            [
+                bits(value, 59, 56),
+                bits(value, 51, 48),
+                bits(value, 43, 40),
+            ],
+        ];
+        decode_subblocks(
+            value,
+            base.map(|color| color.map(extend4)),
+            opaque,
+            &mut texels,
+        );
+        return texels;
+    }
+    let r = bits(value, 63, 59);
+    let g = bits(value, 55, 51);
+    let b = bits(value, 47, 43);
+    // deltas are 3 bit two's complement
+    let delta = |high| (bits(value, high, high - 2) << 29) >> 29;
+    let (r2, g2, b2) = (r + delta(58), g + delta(50), b + delta(42));
+    let paint = if !(0..32).contains(&r2) {
+        // T mode
+        let c1 = [
+            bits(value, 60, 59) << 2 | bits(value, 57, 56),
+            bits(value, 55, 52),
+            bits(value, 51, 48),
+        ]
+        .map(extend4);
+        let c2 = [
+            bits(value, 47, 44),
+            bits(value, 43, 40),
+            bits(value, 39, 36),
+        ]
+        .map(extend4);
+        let d = DISTANCES[(bits(value, 35, 34) << 1 | bits(value, 32, 32)) as usize];
+        [add(c1, 0), add(c2, d), add(c2, 0), add(c2, -d)]
+    } else if !(0..32).contains(&g2) {
+        // H mode
+        let c1 = [
+            bits(value, 62, 59),
+            bits(value, 58, 56) << 1 | bits(value, 52, 52),
+            bits(value, 51, 51) << 3 | bits(value, 49, 47),
+        ];
+        let c2 = [
+            bits(value, 46, 43),
+            bits(value, 42, 39),
+            bits(value, 38, 35),
+        ];
+        let packed = |[r, g, b]: [i32; 3]| r << 8 | g << 4 | b;
+        let order = (packed(c1) >= packed(c2)) as i32;
+        let d = DISTANCES[(bits(value, 34, 34) << 2 | bits(value, 32, 32) << 1 | order) as usize];
+        let (c1, c2) = (c1.map(extend4), c2.map(extend4));
+        [add(c1, d), add(c1, -d), add(c2, d), add(c2, -d)]
+    } else if !(0..32).contains(&b2) {
+        // planar mode, always opaque
+        let extend6 = |c: i32| c << 2 | c >> 4;
+        let extend7 = |c: i32| c << 1 | c >> 6;
+        let origin = [
+            extend6(bits(value, 62, 57)),
+            extend7(bits(value, 56, 56) << 6 | bits(value, 54, 49)),
+            extend6(bits(value, 48, 48) << 5 | bits(value, 44, 43) << 3 | bits(value, 41, 39)),
+        ];
+        let horizontal = [
+            extend6(bits(value, 38, 34) << 1 | bits(value, 32, 32)),
+            extend7(bits(value, 31, 25)),
+            extend6(bits(value, 24, 19)),
+        ];
+        let vertical = [
+            extend6(bits(value, 18, 13)),
+            extend7(bits(value, 12, 6)),
+            extend6(bits(value, 5, 0)),
+        ];
+        for (i, texel) in texels.iter_mut().enumerate() {
+            let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+            let [r, g, b] = std::array::from_fn(|c| {
+                let h = horizontal[c] - origin[c];
+                let v = vertical[c] - origin[c];
+                ((x * h + y * v + 4 * origin[c] + 2) >> 2).clamp(0, 255) as u8
+            });
+            *texel = [r, g, b, 255];
+        }
+        return texels;
+    } else {
+        let base = [[r, g, b], [r2, g2, b2]].map(|color| color.map(extend5));
+        decode_subblocks(value, base, opaque, &mut texels);
+        return texels;
+    };
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let index = pixel_index(value, i);
+        *texel = if !opaque && index == 2 {
+            [0; 4]
+        } else {
+            paint[index]
+        };
+    }
+    texels
+}
+
+/// The two bit index of texel `i`, counting row by row, in the indices of a colour block.
+fn pixel_index(value: u64, i: usize) -> usize {
+    // the indices go column by column
+    let bit = i % 4 * 4 + i / 4;
+    ((value >> (bit + 16) & 1) << 1 | value >> bit & 1) as usize
+}
+
+/// The individual and differential modes, where each half of the block has a base colour
+/// and modifier table of its own.
+fn decode_subblocks(value: u64, base: [[i32; 3]; 2], opaque: bool, texels: &mut Texels) {
+    let tables = [bits(value, 39, 37), bits(value, 36, 34)];
+    let flip = value >> 32 & 1 == 1;
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let subblock = if flip { y / 2 } else { x / 2 };
+        let [a, b] = MODIFIERS[tables[subblock] as usize];
+        *texel = match pixel_index(value, i) {
+            0 if opaque => add(base[subblock], a),
+            0 => add(base[subblock], 0),
+            1 => add(base[subblock], b),
+            2 if opaque => add(base[subblock], -a),
+            2 => [0; 4],
+            _ => add(base[subblock], -b),
+        };
+    }
+}
+
+/// An EAC block, as 11 bit values, signed or not, row by row.
+fn decode_eac(block: &[u8], signed: bool) -> [i32; 16] {
+    let value = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let multiplier = bits(value, 55, 52);
+    let modifiers = EAC_MODIFIERS[bits(value, 51, 48) as usize];
+    std::array::from_fn(|i| {
+        // the indices go column by column, from the top bits down
+        let bit = 45 - 3 * (i % 4 * 4 + i / 4) as u32;
+        let modifier = modifiers[bits(value, bit + 2, bit) as usize];
+        let offset = if multiplier == 0 {
+            modifier
+        } else {
+            modifier * multiplier * 8
+        };
+        if signed {
+            let base = (block[0] as i8).max(-127) as i32;
+            (base * 8 + offset).clamp(-1023, 1023)
+        } else {
+            (block[0] as i32 * 8 + 4 + offset).clamp(0, 2047)
+        }
+    })
+}
+
+/// The 8 bit alpha of EAC blocks of ETC2 RGBA8.
+fn decode_alpha(block: &[u8]) -> [u8; 16] {
+    let value = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let modifiers = EAC_MODIFIERS[bits(value, 51, 48) as usize];
+    let multiplier = bits(value, 55, 52);
+    std::array::from_fn(|i| {
+        let bit = 45 - 3 * (i % 4 * 4 + i / 4) as u32;
+        let modifier = modifiers[bits(value, bit + 2, bit) as usize];
+        (block[0] as i32 + modifier * multiplier).clamp(0, 255) as u8
+    })
+}
+
+/// 11 bit values to bytes, unsigned or those of `i8`s.
+fn to_byte(value: i32, signed: bool) -> u8 {
+    if signed {
+        (value * 127 + value.signum() * 511) / 1023
+    } else {
+        (value * 255 + 1023) / 2047
+    }
+    .clamp(-127, 255) as u8
+}
+
+pub(super) fn decode_rgb8(block: &[u8]) -> Texels {
+    decode_color(block, false)
+}
+
+pub(super) fn decode_rgb8a1(block: &[u8]) -> Texels {
+    decode_color(block, true)
+}
+
+pub(super) fn decode_rgba8(block: &[u8]) -> Texels {
+    let mut texels = decode_color(&block[8..], false);
+    for (texel, alpha) in texels.iter_mut().zip(decode_alpha(block)) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+/// Red in R11, red and green in RG11, like the GPU samples them: blue is 0 and alpha 1.
+fn decode_red_green(block: &[u8], channels: usize, signed: bool) -> Texels {
+    let mut texels = [[0, 0, 0, if signed { 127 } else { 255 }]; 16];
+    for channel in 0..channels {
+        let values = decode_eac(&block[channel * 8..], signed);
+        for (texel, value) in texels.iter_mut().zip(values) {
+            texel[channel] = to_byte(value, signed);
+        }
+    }
+    texels
+}
+
+pub(super) fn decode_r11(block: &[u8]) -> Texels {
+    decode_red_green(block, 1, false)
+}
+
+pub(super) fn decode_r11_signed(block: &[u8]) -> Texels {
+    decode_red_green(block, 1, true)
+}
+
+pub(super) fn decode_rg11(block: &[u8]) -> Texels {
+    decode_red_green(block, 2, false)
+}
+
+pub(super) fn decode_rg11_signed(block: &[u8]) -> Texels {
+    decode_red_green(block, 2, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn individual_mode_adds_the_modifier() {
+        // both halves 0x88 with table 0, every index 0 so +2
+        let block = [0x88, 0x88, 0x88, 0, 0, 0, 0, 0];
+        assert_eq!(decode_rgb8(&block), [[138, 138, 138, 255]; 16]);
+    }
+
+    #[test]
+    fn punchthrough_index_2_is_transparent() {
+        // differential with the opaque bit clear and every index 2
+        let block = [0x80, 0x80, 0x80, 0, 0xFF, 0xFF, 0, 0];
+        assert_eq!(decode_rgb8a1(&block), [[0; 4]; 16]);
+        let opaque = [0x80, 0x80, 0x80, 0x02, 0xFF, 0xFF, 0, 0];
+        assert_eq!(decode_rgb8a1(&opaque)[0], [130, 130, 130, 255]);
+    }
+
+    #[test]
+    fn eac_values_fill_the_byte_range() {
+        assert_eq!(to_byte(2047, false), 255);
+        assert_eq!(to_byte(0, false), 0);
+        assert_eq!(to_byte(1023, true), 127);
+        assert_eq!(to_byte(-1023, true) as i8, -127);
+        // with multiplier 0 the modifier is still added, unscaled
+        let block = [128, 0x0D, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert_eq!(decode_r11(&block)[0][0], to_byte(128 * 8 + 4 + 9, false));
+    }
+}
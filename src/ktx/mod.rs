@@ -0,0 +1,342 @@
+//! Reading KTX2 containers into a texture format the device can sample.
+//!
+//! Block compressed data is uploaded as is when the device supports its format, and decoded
+//! to RGBA8 on the CPU when it doesn't: BC1 to BC5 and BC7, ETC2 and EAC, and LDR ASTC of
+//! every footprint. BC6H and HDR ASTC hold more than RGBA8 can, so they need the device's
+//! support. Basis Universal ETC1S textures are transcoded to whichever of ETC2, BC7 and
+//! RGBA8 the device supports first, see `basis`. UASTC ones need transcoding to one of the
+//! formats above beforehand, e.g. with `ktx transcode`.
+
+use std::io::Read;
+
+use ktx2::{ColorModel, DfdBlockBasic, Format, SupercompressionScheme, TransferFunction};
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+use crate::texture::TextureError;
+
+mod astc;
+mod basis;
+mod bc;
+mod etc;
+
+/// The mip levels of a KTX2 file, decompressed, largest first.
+pub struct KtxImage {
+    content: Content,
+    width: u32,
+    height: u32,
+    levels: Vec<Vec<u8>>,
+}
+
+/// What the levels hold.
+enum Content {
+    Format(Format),
+    /// BasisLZ supercompressed ETC1S, with the codebooks and tables of every level.
+    Etc1s {
+        global_data: Vec<u8>,
+        srgb: bool,
+    },
+}
+
+fn error(message: impl Into<String>) -> TextureError {
+    TextureError::Ktx2(message.into())
+}
+
+/// The identifier every KTX2 file starts with.
+const MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+pub fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+impl KtxImage {
+    /// Parses and decompresses, everything that doesn't need the device.
+    pub fn parse(bytes: &[u8]) -> Result<Self, TextureError> {
+        let reader = ktx2::Reader::new(bytes).map_err(|e| error(e.to_string()))?;
+        let header = reader.header();
+        if header.face_count > 1 || header.layer_count > 1 || header.pixel_depth > 1 {
+            return Err(error("only single 2D textures are supported"));
+        }
+        let content = match header.format {
+            Some(format) => Content::Format(format),
+            None => basis_content(&reader)?,
+        };
+        let etc1s = matches!(content, Content::Etc1s { .. });
+        let levels = reader
+            .levels()
+            .map(|level| match header.supercompression_scheme {
+                None => Ok(level.data.to_vec()),
+                // transcoded along with the global data
+                Some(SupercompressionScheme::BasisLZ) if etc1s => Ok(level.data.to_vec()),
+                Some(SupercompressionScheme::Zstandard) => {
+                    let mut data = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                    ruzstd::StreamingDecoder::new(level.data)
+                        .map_err(|e| error(e.to_string()))?
+                        .read_to_end(&mut data)
+                        .map_err(|e| error(e.to_string()))?;
+                    Ok(data)
+                }
+                Some(scheme) => Err(error(format!("{:?} supercompression", scheme))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            content,
+            width: header.pixel_width,
+            height: header.pixel_height.max(1),
+            levels,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    /// The format to create the texture with on a device with `features`, and the data
+    /// for it, every level one after the other.
+    pub fn into_data(
+        self,
+        features: wgpu::Features,
+    ) -> Result<(TextureFormat, Vec<u8>), TextureError> {
+        let format = match self.content {
+            Content::Format(format) => format,
+            Content::Etc1s { global_data, srgb } => {
+                let size = (self.width, self.height);
+                return basis::transcode(&global_data, &self.levels, size, srgb, features);
+            }
+        };
+        let format =
+            wgpu_format(format).ok_or_else(|| error(format!("{:?} is not supported", format)))?;
+        if features.contains(format.required_features()) {
+            return Ok((format, self.levels.concat()));
+        }
+        let Some(decoder) = decoder(format) else {
+            return Err(error(format!(
+                "{:?} is not supported by this device",
+                format
+            )));
+        };
+        log::info!("decoding {:?} texture on the CPU", format);
+        let mut data = Vec::new();
+        for (i, level) in self.levels.iter().enumerate() {
+            let width = (self.width >> i).max(1);
+            let height = (self.height >> i).max(1);
+            data.extend(decoder.decode_level(level, width, height));
+        }
+        Ok((decoder.format, data))
+    }
+}
+
+/// The content of a texture without a format, which Basis Universal ones are.
+fn basis_content(reader: &ktx2::Reader<&[u8]>) -> Result<Content, TextureError> {
+    let block = reader
+        .dfd_blocks()
+        .next()
+        .ok_or_else(|| error("missing data format descriptor"))?;
+    let dfd = DfdBlockBasic::parse(block.data).map_err(|e| error(e.to_string()))?;
+    let scheme = reader.header().supercompression_scheme;
+    match dfd.header.color_model {
+        Some(ColorModel::ETC1S) if scheme == Some(SupercompressionScheme::BasisLZ) => {
+            Ok(Content::Etc1s {
+                global_data: reader.supercompression_global_data().to_vec(),
+                srgb: dfd.header.transfer_function == Some(TransferFunction::SRGB),
+            })
+        }
+        Some(ColorModel::UASTC) => Err(error(
+            "UASTC textures need transcoding, e.g. with `ktx transcode`",
+        )),
+        _ => Err(error("textures without a format must be ETC1S or UASTC")),
+    }
+}
+
+fn wgpu_format(format: Format) -> Option<TextureFormat> {
+    use AstcBlock::*;
+    let astc = |block, srgb| TextureFormat::Astc {
+        block,
+        channel: if srgb {
+            AstcChannel::UnormSrgb
+        } else {
+            AstcChannel::Unorm
+        },
+    };
+    Some(match format {
+        Format::R8G8B8A8_UNORM => TextureFormat::Rgba8Unorm,
+        Format::R8G8B8A8_SRGB => TextureFormat::Rgba8UnormSrgb,
+        Format::B8G8R8A8_UNORM => TextureFormat::Bgra8Unorm,
+        Format::B8G8R8A8_SRGB => TextureFormat::Bgra8UnormSrgb,
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+        Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1RgbaUnormSrgb,
+        Format::BC2_UNORM_BLOCK => TextureFormat::Bc2RgbaUnorm,
+        Format::BC2_SRGB_BLOCK => TextureFormat::Bc2RgbaUnormSrgb,
+        Format::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+        Format::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+        Format::BC4_UNORM_BLOCK => TextureFormat::Bc4RUnorm,
+        Format::BC4_SNORM_BLOCK => TextureFormat::Bc4RSnorm,
+        Format::BC5_UNORM_BLOCK => TextureFormat::Bc5RgUnorm,
+        Format::BC5_SNORM_BLOCK => TextureFormat::Bc5RgSnorm,
+        Format::BC6H_UFLOAT_BLOCK => TextureFormat::Bc6hRgbUfloat,
+        Format::BC6H_SFLOAT_BLOCK => TextureFormat::Bc6hRgbFloat,
+        Format::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+        Format::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+        Format::ETC2_R8G8B8_UNORM_BLOCK => TextureFormat::Etc2Rgb8Unorm,
+        Format::ETC2_R8G8B8_SRGB_BLOCK => TextureFormat::Etc2Rgb8UnormSrgb,
+        Format::ETC2_R8G8B8A1_UNORM_BLOCK => TextureFormat::Etc2Rgb8A1Unorm,
+        Format::ETC2_R8G8B8A1_SRGB_BLOCK => TextureFormat::Etc2Rgb8A1UnormSrgb,
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => TextureFormat::Etc2Rgba8Unorm,
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => TextureFormat::Etc2Rgba8UnormSrgb,
+        Format::EAC_R11_UNORM_BLOCK => TextureFormat::EacR11Unorm,
+        Format::EAC_R11_SNORM_BLOCK => TextureFormat::EacR11Snorm,
+        Format::EAC_R11G11_UNORM_BLOCK => TextureFormat::EacRg11Unorm,
+        Format::EAC_R11G11_SNORM_BLOCK => TextureFormat::EacRg11Snorm,
+        Format::ASTC_4x4_UNORM_BLOCK => astc(B4x4, false),
+        Format::ASTC_4x4_SRGB_BLOCK => astc(B4x4, true),
+        Format::ASTC_5x4_UNORM_BLOCK => astc(B5x4, false),
+        Format::ASTC_5x4_SRGB_BLOCK => astc(B5x4, true),
+        Format::ASTC_5x5_UNORM_BLOCK => astc(B5x5, false),
+        Format::ASTC_5x5_SRGB_BLOCK => astc(B5x5, true),
+        Format::ASTC_6x5_UNORM_BLOCK => astc(B6x5, false),
+        Format::ASTC_6x5_SRGB_BLOCK => astc(B6x5, true),
+        Format::ASTC_6x6_UNORM_BLOCK => astc(B6x6, false),
+        Format::ASTC_6x6_SRGB_BLOCK => astc(B6x6, true),
+        Format::ASTC_8x5_UNORM_BLOCK => astc(B8x5, false),
+        Format::ASTC_8x5_SRGB_BLOCK => astc(B8x5, true),
+        Format::ASTC_8x6_UNORM_BLOCK => astc(B8x6, false),
+        Format::ASTC_8x6_SRGB_BLOCK => astc(B8x6, true),
+        Format::ASTC_8x8_UNORM_BLOCK => astc(B8x8, false),
+        Format::ASTC_8x8_SRGB_BLOCK => astc(B8x8, true),
+        Format::ASTC_10x5_UNORM_BLOCK => astc(B10x5, false),
+        Format::ASTC_10x5_SRGB_BLOCK => astc(B10x5, true),
+        Format::ASTC_10x6_UNORM_BLOCK => astc(B10x6, false),
+        Format::ASTC_10x6_SRGB_BLOCK => astc(B10x6, true),
+        Format::ASTC_10x8_UNORM_BLOCK => astc(B10x8, false),
+        Format::ASTC_10x8_SRGB_BLOCK => astc(B10x8, true),
+        Format::ASTC_10x10_UNORM_BLOCK => astc(B10x10, false),
+        Format::ASTC_10x10_SRGB_BLOCK => astc(B10x10, true),
+        Format::ASTC_12x10_UNORM_BLOCK => astc(B12x10, false),
+        Format::ASTC_12x10_SRGB_BLOCK => astc(B12x10, true),
+        Format::ASTC_12x12_UNORM_BLOCK => astc(B12x12, false),
+        Format::ASTC_12x12_SRGB_BLOCK => astc(B12x12, true),
+        _ => return None,
+    })
+}
+
+type DecodeFn = Box<dyn Fn(&[u8], &mut [[u8; 4]])>;
+
+/// How to decode blocks of a format on the CPU.
+struct Decoder {
+    /// Texels across and down a block.
+    block_size: (usize, usize),
+    /// Bytes per block.
+    block_bytes: usize,
+    /// Decodes a block into its texels, row by row.
+    decode: DecodeFn,
+    /// What the texels are in.
+    format: TextureFormat,
+}
+
+impl Decoder {
+    fn new(block_bytes: usize, decode: fn(&[u8]) -> bc::Texels, format: TextureFormat) -> Self {
+        Self {
+            block_size: (4, 4),
+            block_bytes,
+            decode: Box::new(move |block, texels| texels.copy_from_slice(&decode(block))),
+            format,
+        }
+    }
+
+    /// Decodes a whole level.
+    fn decode_level(&self, data: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let (width, height) = (width as usize, height as usize);
+        let (block_width, block_height) = self.block_size;
+        let blocks_x = width.div_ceil(block_width);
+        let mut texels = vec![[0; 4]; block_width * block_height];
+        let mut pixels = vec![0; width * height * 4];
+        for (i, block) in data.chunks_exact(self.block_bytes).enumerate() {
+            let (bx, by) = (i % blocks_x * block_width, i / blocks_x * block_height);
+            (self.decode)(block, &mut texels);
+            for (j, texel) in texels.iter().enumerate() {
+                let (x, y) = (bx + j % block_width, by + j / block_width);
+                // blocks overhang the edges of sizes that aren't multiples of theirs
+                if x < width && y < height {
+                    let offset = (y * width + x) * 4;
+                    pixels[offset..offset + 4].copy_from_slice(texel);
+                }
+            }
+        }
+        pixels
+    }
+}
+
+/// The decoder for a format, `None` for those that are always supported or that RGBA8
+/// can't hold.
+fn decoder(format: TextureFormat) -> Option<Decoder> {
+    use TextureFormat as F;
+    let rgba8 = if format.is_srgb() {
+        F::Rgba8UnormSrgb
+    } else {
+        F::Rgba8Unorm
+    };
+    Some(match format {
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => Decoder::new(8, bc::decode_bc1, rgba8),
+        F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => Decoder::new(16, bc::decode_bc2, rgba8),
+        F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => Decoder::new(16, bc::decode_bc3, rgba8),
+        F::Bc4RUnorm => Decoder::new(8, bc::decode_bc4, rgba8),
+        F::Bc4RSnorm => Decoder::new(8, bc::decode_bc4_signed, F::Rgba8Snorm),
+        F::Bc5RgUnorm => Decoder::new(16, bc::decode_bc5, rgba8),
+        F::Bc5RgSnorm => Decoder::new(16, bc::decode_bc5_signed, F::Rgba8Snorm),
+        F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => Decoder::new(16, bc::decode_bc7, rgba8),
+        F::Etc2Rgb8Unorm | F::Etc2Rgb8UnormSrgb => Decoder::new(8, etc::decode_rgb8, rgba8),
+        F::Etc2Rgb8A1Unorm | F::Etc2Rgb8A1UnormSrgb => Decoder::new(8, etc::decode_rgb8a1, rgba8),
+        F::Etc2Rgba8Unorm | F::Etc2Rgba8UnormSrgb => Decoder::new(16, etc::decode_rgba8, rgba8),
+        F::EacR11Unorm => Decoder::new(8, etc::decode_r11, rgba8),
+        F::EacR11Snorm => Decoder::new(8, etc::decode_r11_signed, F::Rgba8Snorm),
+        F::EacRg11Unorm => Decoder::new(16, etc::decode_rg11, rgba8),
+        F::EacRg11Snorm => Decoder::new(16, etc::decode_rg11_signed, F::Rgba8Snorm),
+        F::Astc {
+            channel: AstcChannel::Unorm | AstcChannel::UnormSrgb,
+            ..
+        } => {
+            let (width, height) = format.block_dimensions();
+            let size = (width as usize, height as usize);
+            let srgb = format.is_srgb();
+            Decoder {
+                block_size: size,
+                block_bytes: 16,
+                decode: Box::new(move |block, texels| astc::decode(block, size, srgb, texels)),
+                format: rgba8,
+            }
+        }
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_crop_blocks_past_the_edges() {
+        let decoder = decoder(TextureFormat::Astc {
+            block: AstcBlock::B5x4,
+            channel: AstcChannel::Unorm,
+        })
+        .unwrap();
+        // two void extent blocks, grey and white, side by side
+        let block = |value: u128| (0xFFFF_FFFF_FFFF_FDFC | value << 64).to_le_bytes();
+        let data = [block(0x8080_8080_8080_8080), block(u64::MAX as u128)].concat();
+        let pixels = decoder.decode_level(&data, 7, 3);
+        assert_eq!(pixels.len(), 7 * 3 * 4);
+        assert_eq!(pixels[4 * 4..5 * 4], [128; 4]);
+        assert_eq!(pixels[5 * 4..6 * 4], [255; 4]);
+        assert_eq!(pixels[(2 * 7 + 6) * 4..], [255; 4]);
+        assert_eq!(decoder.format, TextureFormat::Rgba8Unorm);
+    }
+}
@@ -0,0 +1,558 @@
+//! Thick polylines expanded into triangles on the CPU, with joins, caps and
+//! anti-aliased edges. Geometry is built in screen space so pixel widths stay exact.
+
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::math::{Mat4, Vec2, Vec3};
+use crate::stats::Tracked;
+
+const SHADER: &str = r#"
+struct VertexInput {
+    // position in pixels, origin top left
+    @location(0) position: vec2<f32>,
+    @location(1) depth: f32,
+    // signed distance from the center line in pixels
+    @location(2) distance: f32,
+    @location(3) half_width: f32,
+    @location(4) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) distance: f32,
+    @location(1) half_width: f32,
+    @location(2) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let ndc = vec2<f32>(
+        in.position.x / globals.resolution.x * 2.0 - 1.0,
+        1.0 - in.position.y / globals.resolution.y * 2.0,
+    );
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, in.depth, 1.0);
+    out.distance = in.distance;
+    out.half_width = in.half_width;
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // one pixel wide ramp centered on the real edge
+    let coverage = clamp(in.half_width - abs(in.distance) + 0.5, 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
+"#;
+
+/// Extra geometry around every edge used for the anti-aliasing ramp.
+const FEATHER: f32 = 1.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineWidth {
+    /// Constant on screen regardless of distance.
+    Pixels(f32),
+    /// Measured in world units, so it shrinks with distance under perspective.
+    World(f32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum LineJoin {
+    /// Sharp corners, falling back to bevel past `LineStyle::miter_limit`.
+    #[default]
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum LineCap {
+    #[default]
+    Butt,
+    Square,
+    Round,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LineStyle {
+    pub width: LineWidth,
+    pub color: [f32; 4],
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// Maximum miter length as a multiple of the half width.
+    pub miter_limit: f32,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self {
+            width: LineWidth::Pixels(2.0),
+            color: [1.0, 1.0, 1.0, 1.0],
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+        }
+    }
+}
+
+impl LineStyle {
+    pub fn new(width: LineWidth, color: [f32; 4]) -> Self {
+        Self {
+            width,
+            color,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_join(mut self, join: LineJoin) -> Self {
+        self.join = join;
+        self
+    }
+
+    pub fn with_cap(mut self, cap: LineCap) -> Self {
+        self.cap = cap;
+        self
+    }
+}
+
+struct Polyline {
+    points: Vec<Vec3>,
+    closed: bool,
+    style: LineStyle,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct LineVertex {
+    position: [f32; 2],
+    depth: f32,
+    distance: f32,
+    half_width: f32,
+    color: [f32; 4],
+}
+unsafe impl bytemuck::Pod for LineVertex {}
+unsafe impl bytemuck::Zeroable for LineVertex {}
+
+impl LineVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0 => Float32x2, 1 => Float32, 2 => Float32, 3 => Float32, 4 => Float32x4
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// A projected polyline point.
+#[derive(Copy, Clone, Debug)]
+struct ScreenPoint {
+    position: Vec2,
+    depth: f32,
+    half_width: f32,
+}
+
+/// Appends triangles for one screen space polyline.
+struct Tessellator<'a> {
+    out: &'a mut Vec<LineVertex>,
+    color: [f32; 4],
+}
+
+impl Tessellator<'_> {
+    fn vertex(&mut self, p: Vec2, depth: f32, distance: f32, half_width: f32) {
+        self.out.push(LineVertex {
+            position: p.to_array(),
+            depth,
+            distance,
+            half_width,
+            color: self.color,
+        });
+    }
+
+    /// Triangle fan around `center` from direction `from` sweeping `angle` radians.
+    fn fan(&mut self, center: &ScreenPoint, from: Vec2, angle: f32) {
+        let radius = center.half_width + FEATHER;
+        let steps = ((angle.abs() * radius / 3.0).ceil() as usize).clamp(1, 32);
+        let step = angle / steps as f32;
+        for i in 0..steps {
+            let a = from.rotate(step * i as f32) * radius;
+            let b = from.rotate(step * (i + 1) as f32) * radius;
+            self.vertex(center.position, center.depth, 0.0, center.half_width);
+            self.vertex(center.position + a, center.depth, radius, center.half_width);
+            self.vertex(center.position + b, center.depth, radius, center.half_width);
+        }
+    }
+
+    fn segment(&mut self, a: &ScreenPoint, b: &ScreenPoint, extend_start: f32, extend_end: f32) {
+        let dir = (b.position - a.position).normalize();
+        let n = dir.perp();
+        let ra = a.half_width + FEATHER;
+        let rb = b.half_width + FEATHER;
+        let pa = a.position - dir * extend_start;
+        let pb = b.position + dir * extend_end;
+        let (hwa, hwb) = (a.half_width, b.half_width);
+
+        self.vertex(pa + n * ra, a.depth, ra, hwa);
+        self.vertex(pa - n * ra, a.depth, -ra, hwa);
+        self.vertex(pb + n * rb, b.depth, rb, hwb);
+
+        self.vertex(pa - n * ra, a.depth, -ra, hwa);
+        self.vertex(pb - n * rb, b.depth, -rb, hwb);
+        self.vertex(pb + n * rb, b.depth, rb, hwb);
+    }
+
+    fn join(&mut self, p: &ScreenPoint, d0: Vec2, d1: Vec2, style: &LineStyle) {
+        let turn = d0.cross(d1);
+        if turn.abs() < 1e-4 && d0.dot(d1) > 0.0 {
+            return;
+        }
+        // the gap to fill is on the outside of the turn
+        let side = if turn > 0.0 { -1.0 } else { 1.0 };
+        let o0 = d0.perp() * side;
+        let o1 = d1.perp() * side;
+        let r = p.half_width + FEATHER;
+
+        match style.join {
+            LineJoin::Round => {
+                let angle = o0.cross(o1).atan2(o0.dot(o1));
+                self.fan(p, o0, angle);
+            }
+            LineJoin::Miter | LineJoin::Bevel => {
+                let miter = (o0 + o1).normalize();
+                let cos = miter.dot(o0);
+                let miter_ok = style.join == LineJoin::Miter
+                    && cos > f32::EPSILON
+                    && 1.0 / cos <= style.miter_limit;
+                if miter_ok {
+                    let tip = p.position + miter * (r / cos);
+                    self.vertex(p.position, p.depth, 0.0, p.half_width);
+                    self.vertex(p.position + o0 * r, p.depth, r, p.half_width);
+                    self.vertex(tip, p.depth, r, p.half_width);
+                    self.vertex(p.position, p.depth, 0.0, p.half_width);
+                    self.vertex(tip, p.depth, r, p.half_width);
+                    self.vertex(p.position + o1 * r, p.depth, r, p.half_width);
+                } else {
+                    self.vertex(p.position, p.depth, 0.0, p.half_width);
+                    self.vertex(p.position + o0 * r, p.depth, r, p.half_width);
+                    self.vertex(p.position + o1 * r, p.depth, r, p.half_width);
+                }
+            }
+        }
+    }
+
+    fn polyline(&mut self, points: &[ScreenPoint], closed: bool, style: &LineStyle) {
+        if points.len() < 2 {
+            return;
+        }
+        let closed = closed && points.len() > 2;
+        let segment_count = if closed {
+            points.len()
+        } else {
+            points.len() - 1
+        };
+        let dir = |i: usize| {
+            let a = points[i % points.len()].position;
+            let b = points[(i + 1) % points.len()].position;
+            (b - a).normalize()
+        };
+
+        for i in 0..segment_count {
+            let a = &points[i];
+            let b = &points[(i + 1) % points.len()];
+            let mut extend_start = 0.0;
+            let mut extend_end = 0.0;
+            if !closed && style.cap == LineCap::Square {
+                if i == 0 {
+                    extend_start = a.half_width;
+                }
+                if i == segment_count - 1 {
+                    extend_end = b.half_width;
+                }
+            }
+            self.segment(a, b, extend_start, extend_end);
+        }
+
+        let joins = if closed {
+            0..points.len()
+        } else {
+            1..points.len() - 1
+        };
+        for i in joins {
+            let prev = (i + segment_count - 1) % segment_count;
+            self.join(&points[i], dir(prev), dir(i), style);
+        }
+
+        if !closed && style.cap == LineCap::Round {
+            let first = dir(0);
+            self.fan(&points[0], first.perp(), std::f32::consts::PI);
+            let last = dir(segment_count - 1);
+            self.fan(
+                &points[points.len() - 1],
+                -last.perp(),
+                std::f32::consts::PI,
+            );
+        }
+    }
+}
+
+/// Immediate mode line drawing: queue lines every frame, then `prepare` and `render`.
+pub struct LineRenderer {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    capacity: usize,
+    lines: Vec<Polyline>,
+    vertices: Vec<LineVertex>,
+    vertex_count: u32,
+    tracked: Tracked,
+}
+
+impl LineRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Line Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Line Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Line Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Line Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Line Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Line Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[LineVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let capacity = 4096;
+        let vertex_buffer = Self::create_vertex_buffer(device, capacity);
+        let tracked = Tracked::new(1, 2, 0)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&vertex_buffer);
+
+        Self {
+            pipeline,
+            globals_buffer,
+            bind_group,
+            vertex_buffer,
+            capacity,
+            lines: Vec::new(),
+            vertices: Vec::new(),
+            vertex_count: 0,
+            tracked,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Line Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, style: &LineStyle) {
+        self.polyline(&[a, b], style);
+    }
+
+    pub fn polyline(&mut self, points: &[Vec3], style: &LineStyle) {
+        self.lines.push(Polyline {
+            points: points.to_vec(),
+            closed: false,
+            style: *style,
+        });
+    }
+
+    /// Connects the last point back to the first with a proper join.
+    pub fn polygon(&mut self, points: &[Vec3], style: &LineStyle) {
+        self.lines.push(Polyline {
+            points: points.to_vec(),
+            closed: true,
+            style: *style,
+        });
+    }
+
+    /// Builds and uploads the geometry for everything queued since the last call.
+    /// `viewport` is the target size in pixels.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_proj: Mat4,
+        viewport: [f32; 2],
+    ) {
+        let mut globals = FrameGlobals::from_view_proj(view_proj);
+        globals.resolution = viewport;
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        // world space right vector, used to measure world widths in pixels
+        let right = view_proj.row(0).truncate().normalize();
+        let viewport = Vec2::from(viewport);
+        let to_screen = |clip: crate::math::Vec4| {
+            let ndc = clip.truncate() / clip.w;
+            ScreenPoint {
+                position: Vec2::new(
+                    (ndc.x + 1.0) * 0.5 * viewport.x,
+                    (1.0 - ndc.y) * 0.5 * viewport.y,
+                ),
+                depth: ndc.z,
+                half_width: 0.0,
+            }
+        };
+
+        self.vertices.clear();
+        let mut run: Vec<ScreenPoint> = Vec::new();
+        for line in self.lines.drain(..) {
+            let mut tess = Tessellator {
+                out: &mut self.vertices,
+                color: line.style.color,
+            };
+            let project = |p: Vec3| {
+                let clip = view_proj.mul_vec4(p.extend(1.0));
+                let mut sp = to_screen(clip);
+                sp.half_width = match line.style.width {
+                    LineWidth::Pixels(px) => px * 0.5,
+                    LineWidth::World(w) => {
+                        let edge = to_screen(view_proj.mul_vec4((p + right * w).extend(1.0)));
+                        sp.position.distance(edge.position) * 0.5
+                    }
+                };
+                sp
+            };
+
+            // split into runs that stay in front of the camera
+            const NEAR_W: f32 = 1e-4;
+            let n = line.points.len();
+            let segments = if line.closed { n } else { n.saturating_sub(1) };
+            let fully_visible = line
+                .points
+                .iter()
+                .all(|p| view_proj.mul_vec4(p.extend(1.0)).w > NEAR_W);
+            run.clear();
+            if fully_visible {
+                run.extend(line.points.iter().map(|&p| project(p)));
+                dedup(&mut run);
+                tess.polyline(&run, line.closed, &line.style);
+                continue;
+            }
+            for i in 0..segments {
+                let a = line.points[i];
+                let b = line.points[(i + 1) % n];
+                let wa = view_proj.mul_vec4(a.extend(1.0)).w;
+                let wb = view_proj.mul_vec4(b.extend(1.0)).w;
+                match (wa > NEAR_W, wb > NEAR_W) {
+                    (true, true) => {
+                        if run.is_empty() {
+                            run.push(project(a));
+                        }
+                        run.push(project(b));
+                    }
+                    (true, false) => {
+                        if run.is_empty() {
+                            run.push(project(a));
+                        }
+                        let t = (wa - NEAR_W) / (wa - wb);
+                        run.push(project(a + (b - a) * t));
+                        dedup(&mut run);
+                        tess.polyline(&run, false, &line.style);
+                        run.clear();
+                    }
+                    (false, true) => {
+                        let t = (NEAR_W - wa) / (wb - wa);
+                        run.push(project(a + (b - a) * t));
+                        run.push(project(b));
+                    }
+                    (false, false) => {}
+                }
+            }
+            dedup(&mut run);
+            tess.polyline(&run, false, &line.style);
+        }
+
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            let vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+            self.tracked
+                .replace_buffer(&self.vertex_buffer, &vertex_buffer);
+            self.vertex_buffer = vertex_buffer;
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.vertex_count = self.vertices.len() as u32;
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        render_pass.push_debug_group("Lines");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
+
+/// Drops points that land on the same pixel as their predecessor,
+/// they would produce zero length segments with no direction.
+fn dedup(points: &mut Vec<ScreenPoint>) {
+    points.dedup_by(|b, a| a.position.distance(b.position) < 0.01);
+}
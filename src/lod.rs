@@ -0,0 +1,184 @@
+//! Several meshes of one object at decreasing detail, switched between by the distance
+//! to the camera.
+
+use crate::camera::Camera;
+use crate::math::Vec3;
+use crate::mesh::{GpuMesh, Mesh};
+
+pub struct LodLevel {
+    pub mesh: GpuMesh,
+    /// Distance up to which this level is drawn.
+    pub max_distance: f32,
+}
+
+/// The levels of one object, ordered by distance. Call `update` or `select` once a frame
+/// before drawing.
+pub struct LodGroup {
+    levels: Vec<LodLevel>,
+    current: Option<usize>,
+    /// Scales every distance, above 1 keeps detailed levels further out.
+    pub bias: f32,
+    /// How far past its distances, as a fraction of them, the current level is kept, so an
+    /// object at a threshold doesn't flicker between two levels. 0 by default.
+    pub hysteresis: f32,
+}
+
+impl LodGroup {
+    /// A group with one level, the object isn't drawn beyond `max_distance`. Pass
+    /// `f32::INFINITY` to always draw it.
+    pub fn new(mesh: GpuMesh, max_distance: f32) -> Self {
+        Self {
+            levels: vec![LodLevel { mesh, max_distance }],
+            current: Some(0),
+            bias: 1.0,
+            hysteresis: 0.0,
+        }
+    }
+
+    /// Builds every level from one mesh, each `(ratio, max_distance)` pair simplifying it
+    /// to `ratio` of its triangles, see `Mesh::simplify`. A ratio of 1 uploads it as is.
+    pub fn generate(
+        device: &wgpu::Device,
+        mesh: &Mesh,
+        levels: &[(f32, f32)],
+        label: &str,
+    ) -> Self {
+        let mut levels = levels
+            .iter()
+            .enumerate()
+            .map(|(i, &(ratio, max_distance))| {
+                let label = format!("{} LOD {}", label, i);
+                LodLevel {
+                    mesh: GpuMesh::new(device, &mesh.simplify(ratio), &label),
+                    max_distance,
+                }
+            });
+        let first = levels.next().expect("at least one level");
+        let mut group = Self::new(first.mesh, first.max_distance);
+        for level in levels {
+            group = group.with_level(level.mesh, level.max_distance);
+        }
+        group
+    }
+
+    /// Adds a level drawn up to `max_distance`, kept in order among the others.
+    pub fn with_level(mut self, mesh: GpuMesh, max_distance: f32) -> Self {
+        let index = self
+            .levels
+            .partition_point(|level| level.max_distance <= max_distance);
+        self.levels.insert(index, LodLevel { mesh, max_distance });
+        self
+    }
+
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis.max(0.0);
+        self
+    }
+
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// Picks the first level whose distance reaches `distance`, `None` past the last. The
+    /// current level is kept while `distance` is within `hysteresis` of its range.
+    pub fn select(&mut self, distance: f32) -> Option<usize> {
+        let levels = &self.levels;
+        self.current = select_level(
+            levels.len(),
+            |i| levels[i].max_distance * self.bias,
+            self.current,
+            distance,
+            self.hysteresis,
+        );
+        self.current
+    }
+
+    /// Selects the level for an object at `position` seen by `camera`.
+    pub fn update(&mut self, camera: &Camera, position: Vec3) -> Option<usize> {
+        self.select(camera.eye.distance(position))
+    }
+
+    /// The level picked by the last `select` or `update`.
+    pub fn current(&self) -> Option<&GpuMesh> {
+        self.current.map(|i| &self.levels[i].mesh)
+    }
+
+    /// Draws the current level, nothing if the object is too far away.
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, instances: std::ops::Range<u32>) {
+        if let Some(mesh) = self.current() {
+            mesh.draw(pass, instances);
+        }
+    }
+}
+
+/// The level among `count` for `distance`, given the distance each is drawn up to. Stays at
+/// `current`, where `None` means past the last level, while `distance` is less than
+/// `hysteresis` outside its range.
+fn select_level(
+    count: usize,
+    max_distance: impl Fn(usize) -> f32,
+    current: Option<usize>,
+    distance: f32,
+    hysteresis: f32,
+) -> Option<usize> {
+    let selected = (0..count).position(|i| distance <= max_distance(i));
+    if hysteresis <= 0.0 || selected == current {
+        return selected;
+    }
+    let current_index = current.unwrap_or(count);
+    let min = match current_index {
+        0 => 0.0,
+        i => max_distance(i - 1),
+    };
+    let max = if current_index < count {
+        max_distance(current_index)
+    } else {
+        f32::INFINITY
+    };
+    if distance > min * (1.0 - hysteresis) && distance <= max * (1.0 + hysteresis) {
+        current
+    } else {
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISTANCES: [f32; 3] = [10.0, 20.0, 40.0];
+
+    fn select(current: Option<usize>, distance: f32, hysteresis: f32) -> Option<usize> {
+        select_level(3, |i| DISTANCES[i], current, distance, hysteresis)
+    }
+
+    #[test]
+    fn picks_by_distance() {
+        assert_eq!(select(None, 5.0, 0.0), Some(0));
+        assert_eq!(select(Some(0), 10.5, 0.0), Some(1));
+        assert_eq!(select(Some(2), 20.0, 0.0), Some(1));
+        assert_eq!(select(Some(2), 41.0, 0.0), None);
+    }
+
+    #[test]
+    fn hysteresis_keeps_the_current_level_near_a_threshold() {
+        // moving out: level 0 is kept up to 11, then level 1 takes over
+        assert_eq!(select(Some(0), 10.5, 0.1), Some(0));
+        assert_eq!(select(Some(0), 11.5, 0.1), Some(1));
+        // moving back in: level 1 is kept down to 9
+        assert_eq!(select(Some(1), 9.5, 0.1), Some(1));
+        assert_eq!(select(Some(1), 8.5, 0.1), Some(0));
+        // past the last level the object stays hidden until it's clearly back in range
+        assert_eq!(select(None, 38.0, 0.1), None);
+        assert_eq!(select(None, 35.0, 0.1), Some(2));
+        assert_eq!(select(Some(2), 43.0, 0.1), Some(2));
+        assert_eq!(select(Some(2), 45.0, 0.1), None);
+        // a jump further than the margin switches straight away
+        assert_eq!(select(Some(0), 30.0, 0.1), Some(2));
+    }
+}
@@ -0,0 +1,1011 @@
+//! Lit meshes drawn with materials: one base shader whose `#ifdef` blocks turn features on
+//! and off, compiled into a pipeline per combination of features the first time a draw
+//! needs it.
+//!
+//! A mesh is drawn with a `Material`, its parameter block and optional texture, and a
+//! model matrix. The model matrices of a frame share one `UniformArena`, bound at a
+//! dynamic offset per draw. Vertex colors and skinning come from extra vertex buffers
+//! passed along with the draw.
+//!
+//! With `with_velocity` the renderer also writes how far each pixel moved since the last
+//! frame into a second color target, for `MotionBlur`, from last frame's camera and each
+//! instance's `previous_model`.
+//!
+//! Materials given an alpha cutoff are masked, for foliage and fences: drawn opaque with
+//! whatever is below the cutoff cut out. On a renderer made `with_sample_count` above 1
+//! the edge of the cutout goes through alpha-to-coverage, which antialiases it across the
+//! samples of each pixel for about the cost of the plain cutout.
+
+use std::collections::HashMap;
+
+use crate::camera::Camera;
+use crate::depth;
+use crate::globals::{FrameGlobals, FRAME_GLOBALS_WGSL};
+use crate::math::{Mat4, Vec3};
+use crate::mesh::{GpuMesh, MeshVertex};
+use crate::motion_blur::VELOCITY_FORMAT;
+use crate::probe::ReflectionProbe;
+use crate::stats::Tracked;
+use crate::texture::Texture;
+use crate::uniform_arena::UniformArena;
+
+/// Joint matrices a `Skin` holds, the size of the shader's joint array.
+pub const MAX_JOINTS: usize = 64;
+
+const SHADER: &str = r#"
+struct Globals {
+    frame: FrameGlobals,
+    // xyz the direction the light travels in, w the ambient light
+    light: vec4<f32>,
+    previous_view_proj: mat4x4<f32>,
+};
+
+struct MaterialParams {
+    base_color: vec4<f32>,
+    emissive: vec4<f32>,
+    // x metallic, y roughness, z alpha cutoff
+    surface: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+#ifdef REFLECTIONS
+@group(0) @binding(1) var probe_texture: texture_cube<f32>;
+@group(0) @binding(2) var probe_sampler: sampler;
+#endif
+@group(1) @binding(0) var<uniform> material: MaterialParams;
+#ifdef TEXTURED
+@group(1) @binding(1) var base_texture: texture_2d<f32>;
+@group(1) @binding(2) var base_sampler: sampler;
+#endif
+struct Object {
+    model: mat4x4<f32>,
+    previous_model: mat4x4<f32>,
+};
+
+@group(2) @binding(0) var<uniform> object: Object;
+#ifdef SKINNED
+@group(3) @binding(0) var<uniform> joints: array<mat4x4<f32>, 64>;
+#endif
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+#ifdef VERTEX_COLORS
+    @location(3) color: vec4<f32>,
+#endif
+#ifdef SKINNED
+    @location(4) joint_indices: vec4<u32>,
+    @location(5) joint_weights: vec4<f32>,
+#endif
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+#ifdef VELOCITY
+    @location(4) current_clip: vec4<f32>,
+    @location(5) previous_clip: vec4<f32>,
+#endif
+};
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+#ifdef VELOCITY
+    // in uv units per frame
+    @location(1) velocity: vec2<f32>,
+#endif
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var transform = object.model;
+#ifdef SKINNED
+    let skin = joints[in.joint_indices.x] * in.joint_weights.x
+        + joints[in.joint_indices.y] * in.joint_weights.y
+        + joints[in.joint_indices.z] * in.joint_weights.z
+        + joints[in.joint_indices.w] * in.joint_weights.w;
+    transform = object.model * skin;
+#endif
+    let world = transform * vec4<f32>(in.position, 1.0);
+    var out: VertexOutput;
+    out.clip_position = globals.frame.view_proj * world;
+    out.world_position = world.xyz;
+    out.normal = (transform * vec4<f32>(in.normal, 0.0)).xyz;
+    out.uv = in.uv;
+    out.color = vec4<f32>(1.0);
+#ifdef VERTEX_COLORS
+    out.color = in.color;
+#endif
+#ifdef VELOCITY
+    // skinned meshes move with their model matrix only, last frame's joints aren't kept
+    var previous_transform = object.previous_model;
+#ifdef SKINNED
+    previous_transform = object.previous_model * skin;
+#endif
+    out.current_clip = out.clip_position;
+    out.previous_clip = globals.previous_view_proj * previous_transform * vec4<f32>(in.position, 1.0);
+#endif
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    var color = material.base_color * in.color;
+#ifdef TEXTURED
+    color = color * textureSample(base_texture, base_sampler, in.uv);
+#endif
+#ifdef ALPHA_MASK
+    let cutoff = material.surface.z;
+#ifdef ALPHA_TO_COVERAGE
+    // sharpened so coverage goes from none to full within about a pixel of the cutoff
+    color.a = clamp((color.a - cutoff) / max(fwidth(color.a), 1e-4) + 0.5, 0.0, 1.0);
+#else
+    if color.a < cutoff {
+        discard;
+    }
+    color.a = 1.0;
+#endif
+#endif
+    let metallic = material.surface.x;
+    let roughness = material.surface.y;
+    let n = normalize(in.normal);
+    let l = -globals.light.xyz;
+    let v = normalize(globals.frame.eye.xyz - in.world_position);
+    let h = normalize(l + v);
+    let ambient = globals.light.w;
+    let diffuse = max(dot(n, l), 0.0) * (1.0 - metallic);
+    let shininess = mix(256.0, 4.0, roughness);
+    let specular_color = mix(vec3<f32>(0.04), color.rgb, metallic);
+    let specular = pow(max(dot(n, h), 0.0), shininess) * (1.0 - roughness) * specular_color;
+    var lit = color.rgb * (ambient + (1.0 - ambient) * diffuse) + specular;
+#ifdef REFLECTIONS
+    // cubemaps are left handed, see probe::PROBE_SHADER
+    let r = reflect(-v, n);
+    let reflected = textureSample(probe_texture, probe_sampler, vec3<f32>(r.x, r.y, -r.z)).rgb;
+    lit = lit + reflected * specular_color * (1.0 - roughness);
+#endif
+    var out: FragmentOutput;
+    out.color = vec4<f32>(lit + material.emissive.rgb, color.a);
+#ifdef VELOCITY
+    let current = in.current_clip.xy / in.current_clip.w;
+    let previous = in.previous_clip.xy / in.previous_clip.w;
+    out.velocity = (current - previous) * vec2<f32>(0.5, -0.5);
+#endif
+    return out;
+}
+"#;
+
+/// Keeps the lines of `source` inside `#ifdef NAME` blocks whose name is in `defines` and
+/// inside `#ifndef NAME` blocks whose name isn't, with `#else` and nesting. The directive
+/// lines themselves are dropped.
+pub fn preprocess(source: &str, defines: &[&str]) -> String {
+    // for each open block, whether its lines are kept and whether its parent's are
+    let mut blocks: Vec<(bool, bool)> = Vec::new();
+    let mut out = String::with_capacity(source.len());
+    for line in source.lines() {
+        let active = blocks.last().is_none_or(|&(active, _)| active);
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("#ifdef") => {
+                let defined = words.next().is_some_and(|name| defines.contains(&name));
+                blocks.push((active && defined, active));
+            }
+            Some("#ifndef") => {
+                let defined = words.next().is_some_and(|name| defines.contains(&name));
+                blocks.push((active && !defined, active));
+            }
+            Some("#else") => match blocks.last_mut() {
+                Some((active, parent)) => *active = *parent && !*active,
+                None => log::warn!("#else outside of an #ifdef block"),
+            },
+            Some("#endif") if blocks.pop().is_none() => {
+                log::warn!("#endif without an #ifdef");
+            }
+            Some("#endif") => {}
+            _ if active => {
+                out.push_str(line);
+                out.push('\n');
+            }
+            _ => {}
+        }
+    }
+    if !blocks.is_empty() {
+        log::warn!("{} #ifdef blocks left open", blocks.len());
+    }
+    out
+}
+
+/// Which parts of the base shader a pipeline variant has.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderFeatures {
+    /// Multiplies the base color by the material's texture.
+    pub textured: bool,
+    /// Multiplies the base color by a `[f32; 4]` color per vertex.
+    pub vertex_colors: bool,
+    /// Blends each vertex between up to four of a `Skin`'s joints, see `SkinVertex`.
+    pub skinned: bool,
+    /// Adds the reflection of a `ReflectionProbe`, stronger the smoother and more metallic
+    /// the material.
+    pub reflections: bool,
+    /// Writes each pixel's motion into a second color target, see
+    /// `MaterialRenderer::with_velocity`.
+    pub velocity: bool,
+    /// Cuts out what's below the material's alpha cutoff and draws the rest opaque,
+    /// through alpha-to-coverage when multisampled.
+    pub alpha_mask: bool,
+}
+
+impl ShaderFeatures {
+    /// The names `preprocess` is given for the variant.
+    pub fn defines(&self) -> Vec<&'static str> {
+        [
+            (self.textured, "TEXTURED"),
+            (self.vertex_colors, "VERTEX_COLORS"),
+            (self.skinned, "SKINNED"),
+            (self.reflections, "REFLECTIONS"),
+            (self.velocity, "VELOCITY"),
+            (self.alpha_mask, "ALPHA_MASK"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect()
+    }
+}
+
+/// Joints and weights of a vertex of a skinned mesh, in a vertex buffer next to the
+/// mesh's own.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SkinVertex {
+    pub joints: [u32; 4],
+    /// Sum to 1.
+    pub weights: [f32; 4],
+}
+unsafe impl bytemuck::Pod for SkinVertex {}
+unsafe impl bytemuck::Zeroable for SkinVertex {}
+
+impl SkinVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![4 => Uint32x4, 5 => Float32x4];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+const COLOR_ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![3 => Float32x4];
+
+fn color_desc() -> wgpu::VertexBufferLayout<'static> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &COLOR_ATTRIBS,
+    }
+}
+
+/// A material's uniform parameter block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MaterialParams {
+    pub base_color: [f32; 4],
+    /// Added to the lit color, the alpha is unused.
+    pub emissive: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Alpha below which the material is cut out, 0 blends it instead.
+    pub alpha_cutoff: f32,
+    _padding: f32,
+}
+unsafe impl bytemuck::Pod for MaterialParams {}
+unsafe impl bytemuck::Zeroable for MaterialParams {}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            emissive: [0.0; 4],
+            metallic: 0.0,
+            roughness: 0.5,
+            alpha_cutoff: 0.0,
+            _padding: 0.0,
+        }
+    }
+}
+
+impl MaterialParams {
+    pub fn new(base_color: [f32; 4]) -> Self {
+        Self {
+            base_color,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_emissive(mut self, emissive: [f32; 3]) -> Self {
+        self.emissive = [emissive[0], emissive[1], emissive[2], 0.0];
+        self
+    }
+
+    pub fn with_metallic(mut self, metallic: f32) -> Self {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
+    /// Masks the material, see the module docs. 0.5 suits most cutout textures.
+    pub fn with_alpha_cutoff(mut self, cutoff: f32) -> Self {
+        self.alpha_cutoff = cutoff;
+        self
+    }
+}
+
+/// Parameters and an optional texture, created with `MaterialRenderer::create_material`.
+pub struct Material {
+    params: MaterialParams,
+    textured: bool,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    _tracked: Tracked,
+}
+
+impl Material {
+    pub fn params(&self) -> &MaterialParams {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, queue: &wgpu::Queue, params: MaterialParams) {
+        self.params = params;
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&params));
+    }
+
+    pub fn is_textured(&self) -> bool {
+        self.textured
+    }
+
+    /// Whether it's cut out at its `alpha_cutoff` instead of blended.
+    pub fn is_masked(&self) -> bool {
+        self.params.alpha_cutoff > 0.0
+    }
+}
+
+/// The joint matrices of a skinned mesh, created with `MaterialRenderer::create_skin`.
+pub struct Skin {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    _tracked: Tracked,
+}
+
+impl Skin {
+    /// Each joint's transform from the mesh's bind pose to its current pose. Joints past
+    /// `MAX_JOINTS` are ignored.
+    pub fn set_joints(&self, queue: &wgpu::Queue, joints: &[Mat4]) {
+        if joints.len() > MAX_JOINTS {
+            log::warn!(
+                "skin has {} joints, only {} are used",
+                joints.len(),
+                MAX_JOINTS
+            );
+        }
+        let joints = &joints[..joints.len().min(MAX_JOINTS)];
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(joints));
+    }
+}
+
+/// One mesh to draw. The features of the pipeline it's drawn with follow from what's set:
+/// `textured` from the material, `vertex_colors` and `skinned` from the extra buffers.
+#[derive(Copy, Clone)]
+pub struct MeshInstance<'a> {
+    pub mesh: &'a GpuMesh,
+    pub material: &'a Material,
+    pub model: Mat4,
+    /// Last frame's model matrix, for the velocity of moving objects. The same as `model`
+    /// unless set.
+    pub previous_model: Mat4,
+    /// A `[f32; 4]` color per vertex of the mesh.
+    pub colors: Option<&'a wgpu::Buffer>,
+    /// A `SkinVertex` per vertex of the mesh, and the joints they refer to.
+    pub skin: Option<(&'a wgpu::Buffer, &'a Skin)>,
+}
+
+impl<'a> MeshInstance<'a> {
+    pub fn new(mesh: &'a GpuMesh, material: &'a Material, model: Mat4) -> Self {
+        Self {
+            mesh,
+            material,
+            model,
+            previous_model: model,
+            colors: None,
+            skin: None,
+        }
+    }
+
+    pub fn with_previous_model(mut self, previous_model: Mat4) -> Self {
+        self.previous_model = previous_model;
+        self
+    }
+
+    pub fn with_colors(mut self, colors: &'a wgpu::Buffer) -> Self {
+        self.colors = Some(colors);
+        self
+    }
+
+    pub fn with_skin(mut self, vertices: &'a wgpu::Buffer, skin: &'a Skin) -> Self {
+        self.skin = Some((vertices, skin));
+        self
+    }
+
+    fn features(&self, material: &Material, reflections: bool, velocity: bool) -> ShaderFeatures {
+        ShaderFeatures {
+            textured: material.textured,
+            vertex_colors: self.colors.is_some(),
+            skinned: self.skin.is_some(),
+            reflections,
+            velocity,
+            alpha_mask: material.is_masked(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Globals {
+    frame: FrameGlobals,
+    light: [f32; 4],
+    previous_view_proj: Mat4,
+}
+unsafe impl bytemuck::Pod for Globals {}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Object {
+    model: Mat4,
+    previous_model: Mat4,
+}
+unsafe impl bytemuck::Pod for Object {}
+unsafe impl bytemuck::Zeroable for Object {}
+unsafe impl bytemuck::Zeroable for Globals {}
+
+fn uniform_entry(binding: u32, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Draws `MeshInstance`s, with a pipeline per `ShaderFeatures` variant of its base shader.
+/// Depth testing needs a depth attachment in the pass of the format given to `new`.
+pub struct MaterialRenderer {
+    source: String,
+    format: wgpu::TextureFormat,
+    depth_format: Option<wgpu::TextureFormat>,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    globals_layout: wgpu::BindGroupLayout,
+    /// Globals with a reflection probe's cubemap, see `set_reflection_probe`.
+    probe_layout: wgpu::BindGroupLayout,
+    probe_bind_group: Option<wgpu::BindGroup>,
+    material_layout: wgpu::BindGroupLayout,
+    textured_material_layout: wgpu::BindGroupLayout,
+    objects: UniformArena,
+    /// Offset of each instance's model matrix in `objects`, in the order given to
+    /// `prepare`.
+    offsets: Vec<u32>,
+    skin_layout: wgpu::BindGroupLayout,
+    pipelines: HashMap<ShaderFeatures, (wgpu::RenderPipeline, Tracked)>,
+    velocity: bool,
+    sample_count: u32,
+    /// The camera of the last `prepare`, for velocity.
+    previous_view_proj: Option<Mat4>,
+    /// Drawn with instead of every instance's own material, e.g. to highlight everything
+    /// or debug lighting.
+    pub override_material: Option<Material>,
+    /// Direction the light travels in.
+    pub light_direction: Vec3,
+    /// Light reaching surfaces facing away from the light, from 0 to 1.
+    pub ambient: f32,
+    _tracked: Tracked,
+}
+
+impl MaterialRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) -> Self {
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Material Globals"),
+            size: std::mem::size_of::<Globals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Globals Bind Group Layout"),
+            entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT)],
+        });
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Material Globals Bind Group"),
+            layout: &globals_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let probe_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Probe Globals Bind Group Layout"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let material_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Bind Group Layout"),
+            entries: &[uniform_entry(0, wgpu::ShaderStages::FRAGMENT)],
+        });
+        let textured_material_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Textured Material Bind Group Layout"),
+                entries: &[
+                    uniform_entry(0, wgpu::ShaderStages::FRAGMENT),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let objects = UniformArena::new(
+            device,
+            "Material Objects",
+            std::mem::size_of::<Object>() as u64,
+            wgpu::ShaderStages::VERTEX,
+        );
+        let skin_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Skin Bind Group Layout"),
+            entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX)],
+        });
+
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&globals_buffer);
+        Self {
+            source: SHADER.to_string(),
+            format,
+            depth_format,
+            globals_buffer,
+            globals_bind_group,
+            globals_layout,
+            probe_layout,
+            probe_bind_group: None,
+            material_layout,
+            textured_material_layout,
+            objects,
+            offsets: Vec::new(),
+            skin_layout,
+            pipelines: HashMap::new(),
+            velocity: false,
+            sample_count: 1,
+            previous_view_proj: None,
+            override_material: None,
+            light_direction: Vec3::new(-0.4, -1.0, -0.3).normalize(),
+            ambient: 0.3,
+            _tracked: tracked,
+        }
+    }
+
+    /// Replaces the base shader. It has to keep the built in shader's bind groups, vertex
+    /// inputs and `#ifdef` names, and gets `globals::FRAME_GLOBALS_WGSL` put in front of
+    /// it like the built in one. Variants already compiled are dropped.
+    pub fn with_shader(mut self, source: impl Into<String>) -> Self {
+        self.source = source.into();
+        self.pipelines.clear();
+        self
+    }
+
+    /// Also writes each pixel's motion since the last frame to a second color target of
+    /// `VELOCITY_FORMAT`, which the pass then needs along with the color target. Other
+    /// renderers' pipelines have a single target, so draw them in a pass of their own.
+    pub fn with_velocity(mut self) -> Self {
+        self.velocity = true;
+        self.pipelines.clear();
+        self
+    }
+
+    pub fn has_velocity(&self) -> bool {
+        self.velocity
+    }
+
+    /// Draws into multisampled targets with `sample_count` samples per pixel, along with a
+    /// depth target of as many, e.g. `DepthTarget::multisampled`. Masked materials then
+    /// antialias their cutouts with alpha-to-coverage.
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self.pipelines.clear();
+        self
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// The built in base shader, as a starting point for `with_shader`. It uses
+    /// `FrameGlobals` without declaring it.
+    pub fn base_shader() -> &'static str {
+        SHADER
+    }
+
+    pub fn create_material(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        params: MaterialParams,
+        texture: Option<&Texture>,
+    ) -> Material {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Material Params", label)),
+            size: std::mem::size_of::<MaterialParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: true,
+        });
+        buffer
+            .slice(..)
+            .get_mapped_range_mut()
+            .copy_from_slice(bytemuck::bytes_of(&params));
+        buffer.unmap();
+
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }];
+        if let Some(texture) = texture {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} Material Bind Group", label)),
+            layout: if texture.is_some() {
+                &self.textured_material_layout
+            } else {
+                &self.material_layout
+            },
+            entries: &entries,
+        });
+
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+        Material {
+            params,
+            textured: texture.is_some(),
+            buffer,
+            bind_group,
+            _tracked: tracked,
+        }
+    }
+
+    /// Reflects `probe` in every material from now on, or nothing with `None`. The probe's
+    /// cubemap is bound as it is now; set it again after recreating the probe.
+    pub fn set_reflection_probe(&mut self, device: &wgpu::Device, probe: Option<&ReflectionProbe>) {
+        self.probe_bind_group = probe.map(|probe| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Material Probe Globals Bind Group"),
+                layout: &self.probe_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.globals_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(probe.view()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(probe.sampler()),
+                    },
+                ],
+            })
+        });
+    }
+
+    /// A skin with every joint at its bind pose.
+    pub fn create_skin(&self, device: &wgpu::Device, label: &str) -> Skin {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Joints", label)),
+            size: (std::mem::size_of::<Mat4>() * MAX_JOINTS) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: true,
+        });
+        buffer
+            .slice(..)
+            .get_mapped_range_mut()
+            .copy_from_slice(bytemuck::cast_slice(&[Mat4::IDENTITY; MAX_JOINTS]));
+        buffer.unmap();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} Skin Bind Group", label)),
+            layout: &self.skin_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+        Skin {
+            buffer,
+            bind_group,
+            _tracked: tracked,
+        }
+    }
+
+    /// Compiles the variant with `features` if it isn't yet.
+    pub fn variant(
+        &mut self,
+        device: &wgpu::Device,
+        features: ShaderFeatures,
+    ) -> &wgpu::RenderPipeline {
+        if !self.pipelines.contains_key(&features) {
+            let pipeline = self.create_pipeline(device, features);
+            self.pipelines
+                .insert(features, (pipeline, Tracked::new(1, 0, 0)));
+        }
+        &self.pipelines[&features].0
+    }
+
+    /// The variants compiled so far.
+    pub fn variants(&self) -> impl Iterator<Item = ShaderFeatures> + '_ {
+        self.pipelines.keys().copied()
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        features: ShaderFeatures,
+    ) -> wgpu::RenderPipeline {
+        let name = format!(
+            "Material Pipeline{}",
+            features
+                .defines()
+                .iter()
+                .map(|name| format!(" {}", name))
+                .collect::<String>()
+        );
+        let alpha_to_coverage = features.alpha_mask && self.sample_count > 1;
+        let mut defines = features.defines();
+        if alpha_to_coverage {
+            defines.push("ALPHA_TO_COVERAGE");
+        }
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&name),
+            source: wgpu::ShaderSource::Wgsl(
+                preprocess(&[FRAME_GLOBALS_WGSL, &self.source].concat(), &defines).into(),
+            ),
+        });
+
+        let material_layout = if features.textured {
+            &self.textured_material_layout
+        } else {
+            &self.material_layout
+        };
+        let globals_layout = if features.reflections {
+            &self.probe_layout
+        } else {
+            &self.globals_layout
+        };
+        let mut bind_group_layouts = vec![globals_layout, material_layout, self.objects.layout()];
+        if features.skinned {
+            bind_group_layouts.push(&self.skin_layout);
+        }
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&name),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let mut buffers = vec![MeshVertex::desc()];
+        if features.vertex_colors {
+            buffers.push(color_desc());
+        }
+        if features.skinned {
+            buffers.push(SkinVertex::desc());
+        }
+        let mut targets = vec![Some(wgpu::ColorTargetState {
+            format: self.format,
+            // masked materials are opaque where they're not cut out
+            blend: (!features.alpha_mask).then_some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        })];
+        if features.velocity {
+            targets.push(Some(VELOCITY_FORMAT.into()));
+        }
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&name),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &targets,
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: self.depth_format.map(depth::depth_stencil),
+            multisample: wgpu::MultisampleState {
+                count: self.sample_count,
+                alpha_to_coverage_enabled: alpha_to_coverage,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+    }
+
+    /// Uploads the camera, the light and the model matrices of `instances`, and compiles
+    /// the variants they need. `render` has to be given the same instances. Call once per
+    /// frame, the camera is kept for next frame's velocity.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        instances: &[MeshInstance],
+    ) {
+        let frame = FrameGlobals::from_camera(camera);
+        let previous_view_proj = self.previous_view_proj.replace(frame.view_proj);
+        self.prepare_globals(
+            device,
+            queue,
+            frame,
+            previous_view_proj.unwrap_or(frame.view_proj),
+            instances,
+        );
+    }
+
+    /// Like `prepare`, for views a `Camera` can't describe, such as a
+    /// `PlanarReflection`'s. Velocity comes from object motion only.
+    pub fn prepare_view(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_proj: Mat4,
+        eye: Vec3,
+        instances: &[MeshInstance],
+    ) {
+        let frame = FrameGlobals::from_view_proj(view_proj).with_eye(eye);
+        self.prepare_globals(device, queue, frame, view_proj, instances);
+    }
+
+    fn prepare_globals(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: FrameGlobals,
+        previous_view_proj: Mat4,
+        instances: &[MeshInstance],
+    ) {
+        let globals = Globals {
+            frame,
+            light: self
+                .light_direction
+                .normalize()
+                .extend(self.ambient)
+                .to_array(),
+            previous_view_proj,
+        };
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        self.objects.clear();
+        self.offsets.clear();
+        for instance in instances {
+            let material = self.override_material.as_ref().unwrap_or(instance.material);
+            let features =
+                instance.features(material, self.probe_bind_group.is_some(), self.velocity);
+            self.variant(device, features);
+            let object = Object {
+                model: instance.model,
+                previous_model: instance.previous_model,
+            };
+            self.offsets.push(self.objects.push(&object));
+        }
+        self.objects.upload(device, queue);
+    }
+
+    /// Draws `instances` in order. Ones whose variant wasn't compiled by `prepare` are
+    /// skipped.
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        instances: &[MeshInstance<'a>],
+    ) {
+        render_pass.push_debug_group("Materials");
+        let globals = self
+            .probe_bind_group
+            .as_ref()
+            .unwrap_or(&self.globals_bind_group);
+        render_pass.set_bind_group(0, globals, &[]);
+        if instances.len() != self.offsets.len() {
+            log::warn!(
+                "rendering {} mesh instances, {} were prepared",
+                instances.len(),
+                self.offsets.len()
+            );
+        }
+        let mut current = None;
+        for (instance, &offset) in instances.iter().zip(&self.offsets) {
+            let material = self.override_material.as_ref().unwrap_or(instance.material);
+            let features =
+                instance.features(material, self.probe_bind_group.is_some(), self.velocity);
+            let Some((pipeline, _)) = self.pipelines.get(&features) else {
+                log::warn!("material variant {:?} wasn't prepared", features);
+                continue;
+            };
+            if current != Some(features) {
+                render_pass.set_pipeline(pipeline);
+                current = Some(features);
+            }
+            render_pass.set_bind_group(1, &material.bind_group, &[]);
+            render_pass.set_bind_group(2, self.objects.bind_group(), &[offset]);
+            let mut slot = 1;
+            if let Some(colors) = instance.colors {
+                render_pass.set_vertex_buffer(slot, colors.slice(..));
+                slot += 1;
+            }
+            if let Some((vertices, skin)) = instance.skin {
+                render_pass.set_vertex_buffer(slot, vertices.slice(..));
+                render_pass.set_bind_group(3, &skin.bind_group, &[]);
+            }
+            instance.mesh.draw(render_pass, 0..1);
+        }
+        render_pass.pop_debug_group();
+    }
+}
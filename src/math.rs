@@ -0,0 +1,662 @@
+//! Small vector/matrix library, just enough for cameras, transforms and effects.
+//! Matrices are column major and follow wgpu's clip space (depth 0..1, right handed view).
+
+use std::ops::{Add, AddAssign, Div, Index, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use crate::tween::Lerp;
+
+macro_rules! impl_vector {
+    ($name:ident, $n:expr, $($field:ident),+) => {
+        impl $name {
+            pub const ZERO: Self = Self { $($field: 0.0),+ };
+            pub const ONE: Self = Self { $($field: 1.0),+ };
+
+            pub const fn new($($field: f32),+) -> Self {
+                Self { $($field),+ }
+            }
+
+            pub const fn splat(v: f32) -> Self {
+                Self { $($field: v),+ }
+            }
+
+            pub fn dot(self, other: Self) -> f32 {
+                0.0 $(+ self.$field * other.$field)+
+            }
+
+            pub fn length_squared(self) -> f32 {
+                self.dot(self)
+            }
+
+            pub fn length(self) -> f32 {
+                self.length_squared().sqrt()
+            }
+
+            pub fn distance(self, other: Self) -> f32 {
+                (self - other).length()
+            }
+
+            /// Returns zero instead of NaN for zero length vectors.
+            pub fn normalize(self) -> Self {
+                let len = self.length();
+                if len > f32::EPSILON {
+                    self / len
+                } else {
+                    Self::ZERO
+                }
+            }
+
+            pub fn min(self, other: Self) -> Self {
+                Self { $($field: self.$field.min(other.$field)),+ }
+            }
+
+            pub fn max(self, other: Self) -> Self {
+                Self { $($field: self.$field.max(other.$field)),+ }
+            }
+
+            pub fn abs(self) -> Self {
+                Self { $($field: self.$field.abs()),+ }
+            }
+
+            pub fn mul_elem(self, other: Self) -> Self {
+                Self { $($field: self.$field * other.$field),+ }
+            }
+
+            pub fn min_element(self) -> f32 {
+                [$(self.$field),+].into_iter().fold(f32::INFINITY, f32::min)
+            }
+
+            pub fn max_element(self) -> f32 {
+                [$(self.$field),+].into_iter().fold(f32::NEG_INFINITY, f32::max)
+            }
+
+            pub fn to_array(self) -> [f32; $n] {
+                [$(self.$field),+]
+            }
+        }
+
+        impl From<[f32; $n]> for $name {
+            fn from(a: [f32; $n]) -> Self {
+                let [$($field),+] = a;
+                Self { $($field),+ }
+            }
+        }
+
+        impl From<$name> for [f32; $n] {
+            fn from(v: $name) -> Self {
+                v.to_array()
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+            fn add(self, o: Self) -> Self {
+                Self { $($field: self.$field + o.$field),+ }
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, o: Self) {
+                $(self.$field += o.$field;)+
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+            fn sub(self, o: Self) -> Self {
+                Self { $($field: self.$field - o.$field),+ }
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, o: Self) {
+                $(self.$field -= o.$field;)+
+            }
+        }
+
+        impl Mul<f32> for $name {
+            type Output = Self;
+            fn mul(self, s: f32) -> Self {
+                Self { $($field: self.$field * s),+ }
+            }
+        }
+
+        impl Mul<$name> for f32 {
+            type Output = $name;
+            fn mul(self, v: $name) -> $name {
+                v * self
+            }
+        }
+
+        impl MulAssign<f32> for $name {
+            fn mul_assign(&mut self, s: f32) {
+                $(self.$field *= s;)+
+            }
+        }
+
+        impl Div<f32> for $name {
+            type Output = Self;
+            fn div(self, s: f32) -> Self {
+                Self { $($field: self.$field / s),+ }
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+            fn neg(self) -> Self {
+                Self { $($field: -self.$field),+ }
+            }
+        }
+
+        impl Lerp for $name {
+            fn lerp(&self, other: &Self, t: f32) -> Self {
+                *self + (*other - *self) * t
+            }
+        }
+
+        unsafe impl bytemuck::Pod for $name {}
+        unsafe impl bytemuck::Zeroable for $name {}
+    };
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "scene", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "scene", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "scene", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl_vector!(Vec2, 2, x, y);
+impl_vector!(Vec3, 3, x, y, z);
+impl_vector!(Vec4, 4, x, y, z, w);
+
+impl Vec2 {
+    pub const X: Self = Self::new(1.0, 0.0);
+    pub const Y: Self = Self::new(0.0, 1.0);
+
+    /// Counter clockwise perpendicular.
+    pub fn perp(self) -> Self {
+        Self::new(-self.y, self.x)
+    }
+
+    /// 2D cross product (z of the 3D cross product).
+    pub fn cross(self, other: Self) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn extend(self, z: f32) -> Vec3 {
+        Vec3::new(self.x, self.y, z)
+    }
+
+    pub fn from_angle(radians: f32) -> Self {
+        Self::new(radians.cos(), radians.sin())
+    }
+
+    pub fn rotate(self, radians: f32) -> Self {
+        let (s, c) = radians.sin_cos();
+        Self::new(self.x * c - self.y * s, self.x * s + self.y * c)
+    }
+}
+
+impl Vec3 {
+    pub const X: Self = Self::new(1.0, 0.0, 0.0);
+    pub const Y: Self = Self::new(0.0, 1.0, 0.0);
+    pub const Z: Self = Self::new(0.0, 0.0, 1.0);
+
+    pub fn cross(self, o: Self) -> Self {
+        Self::new(
+            self.y * o.z - self.z * o.y,
+            self.z * o.x - self.x * o.z,
+            self.x * o.y - self.y * o.x,
+        )
+    }
+
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4::new(self.x, self.y, self.z, w)
+    }
+
+    pub fn truncate(self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+
+    /// Any unit vector perpendicular to `self`.
+    pub fn any_orthogonal(self) -> Self {
+        let other = if self.x.abs() < 0.9 { Self::X } else { Self::Y };
+        self.cross(other).normalize()
+    }
+}
+
+impl Index<usize> for Vec3 {
+    type Output = f32;
+    fn index(&self, i: usize) -> &f32 {
+        match i {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index out of range: {}", i),
+        }
+    }
+}
+
+impl Vec4 {
+    pub fn truncate(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+/// Rotation quaternion.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "scene", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+unsafe impl bytemuck::Pod for Quat {}
+unsafe impl bytemuck::Zeroable for Quat {}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Self = Self {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    };
+
+    pub fn from_axis_angle(axis: Vec3, radians: f32) -> Self {
+        let axis = axis.normalize();
+        let (s, c) = (radians * 0.5).sin_cos();
+        Self {
+            x: axis.x * s,
+            y: axis.y * s,
+            z: axis.z * s,
+            w: c,
+        }
+    }
+
+    /// Yaw around Y, then pitch around X, then roll around Z.
+    pub fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Self {
+        Self::from_axis_angle(Vec3::Y, yaw)
+            * Self::from_axis_angle(Vec3::X, pitch)
+            * Self::from_axis_angle(Vec3::Z, roll)
+    }
+
+    pub fn normalize(self) -> Self {
+        let len = (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt();
+        if len <= f32::EPSILON {
+            return Self::IDENTITY;
+        }
+        Self {
+            x: self.x / len,
+            y: self.y / len,
+            z: self.z / len,
+            w: self.w / len,
+        }
+    }
+
+    pub fn conjugate(self) -> Self {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+            w: self.w,
+        }
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = 2.0 * q.cross(v);
+        v + self.w * t + q.cross(t)
+    }
+
+    pub fn slerp(self, other: Self, t: f32) -> Self {
+        let mut cos = self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w;
+        let mut other = other;
+        if cos < 0.0 {
+            cos = -cos;
+            other = Self {
+                x: -other.x,
+                y: -other.y,
+                z: -other.z,
+                w: -other.w,
+            };
+        }
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Self {
+            x: self.x * a + other.x * b,
+            y: self.y * a + other.y * b,
+            z: self.z * a + other.z * b,
+            w: self.w * a + other.w * b,
+        }
+        .normalize()
+    }
+}
+
+impl Mul for Quat {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        Self {
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+        }
+    }
+}
+
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+    fn mul(self, v: Vec3) -> Vec3 {
+        self.rotate(v)
+    }
+}
+
+impl Lerp for Quat {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self.slerp(*other, t)
+    }
+}
+
+/// Column major 4x4 matrix, `cols[c][r]`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    pub cols: [[f32; 4]; 4],
+}
+
+unsafe impl bytemuck::Pod for Mat4 {}
+unsafe impl bytemuck::Zeroable for Mat4 {}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Self = Self {
+        cols: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn from_cols(x: Vec4, y: Vec4, z: Vec4, w: Vec4) -> Self {
+        Self {
+            cols: [x.to_array(), y.to_array(), z.to_array(), w.to_array()],
+        }
+    }
+
+    pub fn col(&self, i: usize) -> Vec4 {
+        Vec4::from(self.cols[i])
+    }
+
+    pub fn row(&self, i: usize) -> Vec4 {
+        Vec4::new(
+            self.cols[0][i],
+            self.cols[1][i],
+            self.cols[2][i],
+            self.cols[3][i],
+        )
+    }
+
+    pub fn translation(t: Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        m.cols[3] = [t.x, t.y, t.z, 1.0];
+        m
+    }
+
+    pub fn scale(s: Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        m.cols[0][0] = s.x;
+        m.cols[1][1] = s.y;
+        m.cols[2][2] = s.z;
+        m
+    }
+
+    pub fn from_quat(q: Quat) -> Self {
+        let x = q.rotate(Vec3::X);
+        let y = q.rotate(Vec3::Y);
+        let z = q.rotate(Vec3::Z);
+        Self::from_cols(
+            x.extend(0.0),
+            y.extend(0.0),
+            z.extend(0.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    pub fn from_scale_rotation_translation(scale: Vec3, rotation: Quat, translation: Vec3) -> Self {
+        let x = rotation.rotate(Vec3::X) * scale.x;
+        let y = rotation.rotate(Vec3::Y) * scale.y;
+        let z = rotation.rotate(Vec3::Z) * scale.z;
+        Self::from_cols(
+            x.extend(0.0),
+            y.extend(0.0),
+            z.extend(0.0),
+            translation.extend(1.0),
+        )
+    }
+
+    /// Right handed view matrix looking from `eye` towards `target`.
+    pub fn look_at_rh(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let f = (target - eye).normalize();
+        let s = f.cross(up).normalize();
+        let u = s.cross(f);
+        Self {
+            cols: [
+                [s.x, u.x, -f.x, 0.0],
+                [s.y, u.y, -f.y, 0.0],
+                [s.z, u.z, -f.z, 0.0],
+                [-s.dot(eye), -u.dot(eye), f.dot(eye), 1.0],
+            ],
+        }
+    }
+
+    /// Right handed perspective projection mapping depth to 0..1.
+    pub fn perspective_rh(fovy: f32, aspect: f32, znear: f32, zfar: f32) -> Self {
+        let f = 1.0 / (fovy * 0.5).tan();
+        let r = zfar / (znear - zfar);
+        Self {
+            cols: [
+                [f / aspect, 0.0, 0.0, 0.0],
+                [0.0, f, 0.0, 0.0],
+                [0.0, 0.0, r, -1.0],
+                [0.0, 0.0, r * znear, 0.0],
+            ],
+        }
+    }
+
+    /// Right handed perspective projection mapping depth from 1 at `znear` to 0 infinitely
+    /// far away, for `DepthMode::Reversed`.
+    pub fn perspective_infinite_reverse_rh(fovy: f32, aspect: f32, znear: f32) -> Self {
+        let f = 1.0 / (fovy * 0.5).tan();
+        Self {
+            cols: [
+                [f / aspect, 0.0, 0.0, 0.0],
+                [0.0, f, 0.0, 0.0],
+                [0.0, 0.0, 0.0, -1.0],
+                [0.0, 0.0, znear, 0.0],
+            ],
+        }
+    }
+
+    /// Right handed orthographic projection mapping depth to 0..1.
+    pub fn orthographic_rh(
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    ) -> Self {
+        let rl = 1.0 / (right - left);
+        let tb = 1.0 / (top - bottom);
+        let r = 1.0 / (near - far);
+        Self {
+            cols: [
+                [2.0 * rl, 0.0, 0.0, 0.0],
+                [0.0, 2.0 * tb, 0.0, 0.0],
+                [0.0, 0.0, r, 0.0],
+                [-(right + left) * rl, -(top + bottom) * tb, r * near, 1.0],
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = *self;
+        for c in 0..4 {
+            for r in 0..4 {
+                m.cols[c][r] = self.cols[r][c];
+            }
+        }
+        m
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = &self.cols;
+        let (a, b, c, d) = (m[0], m[1], m[2], m[3]);
+        let s0 = a[0] * b[1] - b[0] * a[1];
+        let s1 = a[0] * b[2] - b[0] * a[2];
+        let s2 = a[0] * b[3] - b[0] * a[3];
+        let s3 = a[1] * b[2] - b[1] * a[2];
+        let s4 = a[1] * b[3] - b[1] * a[3];
+        let s5 = a[2] * b[3] - b[2] * a[3];
+        let c5 = c[2] * d[3] - d[2] * c[3];
+        let c4 = c[1] * d[3] - d[1] * c[3];
+        let c3 = c[1] * d[2] - d[1] * c[2];
+        let c2 = c[0] * d[3] - d[0] * c[3];
+        let c1 = c[0] * d[2] - d[0] * c[2];
+        let c0 = c[0] * d[1] - d[0] * c[1];
+        s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0
+    }
+
+    /// General inverse. Returns the identity for singular matrices.
+    pub fn inverse(&self) -> Self {
+        let m = &self.cols;
+        let (a, b, c, d) = (m[0], m[1], m[2], m[3]);
+        let s0 = a[0] * b[1] - b[0] * a[1];
+        let s1 = a[0] * b[2] - b[0] * a[2];
+        let s2 = a[0] * b[3] - b[0] * a[3];
+        let s3 = a[1] * b[2] - b[1] * a[2];
+        let s4 = a[1] * b[3] - b[1] * a[3];
+        let s5 = a[2] * b[3] - b[2] * a[3];
+        let c5 = c[2] * d[3] - d[2] * c[3];
+        let c4 = c[1] * d[3] - d[1] * c[3];
+        let c3 = c[1] * d[2] - d[1] * c[2];
+        let c2 = c[0] * d[3] - d[0] * c[3];
+        let c1 = c[0] * d[2] - d[0] * c[2];
+        let c0 = c[0] * d[1] - d[0] * c[1];
+        let det = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        if det.abs() <= f32::EPSILON * f32::EPSILON {
+            return Self::IDENTITY;
+        }
+        let inv = 1.0 / det;
+        Self {
+            cols: [
+                [
+                    (b[1] * c5 - b[2] * c4 + b[3] * c3) * inv,
+                    (-a[1] * c5 + a[2] * c4 - a[3] * c3) * inv,
+                    (d[1] * s5 - d[2] * s4 + d[3] * s3) * inv,
+                    (-c[1] * s5 + c[2] * s4 - c[3] * s3) * inv,
+                ],
+                [
+                    (-b[0] * c5 + b[2] * c2 - b[3] * c1) * inv,
+                    (a[0] * c5 - a[2] * c2 + a[3] * c1) * inv,
+                    (-d[0] * s5 + d[2] * s2 - d[3] * s1) * inv,
+                    (c[0] * s5 - c[2] * s2 + c[3] * s1) * inv,
+                ],
+                [
+                    (b[0] * c4 - b[1] * c2 + b[3] * c0) * inv,
+                    (-a[0] * c4 + a[1] * c2 - a[3] * c0) * inv,
+                    (d[0] * s4 - d[1] * s2 + d[3] * s0) * inv,
+                    (-c[0] * s4 + c[1] * s2 - c[3] * s0) * inv,
+                ],
+                [
+                    (-b[0] * c3 + b[1] * c1 - b[2] * c0) * inv,
+                    (a[0] * c3 - a[1] * c1 + a[2] * c0) * inv,
+                    (-d[0] * s3 + d[1] * s1 - d[2] * s0) * inv,
+                    (c[0] * s3 - c[1] * s1 + c[2] * s0) * inv,
+                ],
+            ],
+        }
+    }
+
+    pub fn mul_vec4(&self, v: Vec4) -> Vec4 {
+        self.col(0) * v.x + self.col(1) * v.y + self.col(2) * v.z + self.col(3) * v.w
+    }
+
+    /// Transforms a point, including the perspective divide.
+    pub fn transform_point3(&self, p: Vec3) -> Vec3 {
+        let v = self.mul_vec4(p.extend(1.0));
+        if v.w != 0.0 && v.w != 1.0 {
+            v.truncate() / v.w
+        } else {
+            v.truncate()
+        }
+    }
+
+    /// Transforms a direction, ignoring translation.
+    pub fn transform_vector3(&self, v: Vec3) -> Vec3 {
+        self.mul_vec4(v.extend(0.0)).truncate()
+    }
+
+    pub fn to_cols_array_2d(&self) -> [[f32; 4]; 4] {
+        self.cols
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        Self::from_cols(
+            self.mul_vec4(o.col(0)),
+            self.mul_vec4(o.col(1)),
+            self.mul_vec4(o.col(2)),
+            self.mul_vec4(o.col(3)),
+        )
+    }
+}
+
+impl Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+    fn mul(self, v: Vec4) -> Vec4 {
+        self.mul_vec4(v)
+    }
+}
@@ -0,0 +1,497 @@
+//! Indexed triangle meshes: the vertex data on the CPU, loading it from Wavefront OBJ and
+//! uploading it into vertex and index buffers.
+
+use wgpu::util::DeviceExt;
+
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::math::{Vec2, Vec3};
+use crate::stats::Tracked;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MeshVertex {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub uv: Vec2,
+}
+unsafe impl bytemuck::Pod for MeshVertex {}
+unsafe impl bytemuck::Zeroable for MeshVertex {}
+
+impl MeshVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MeshError {
+    /// 1 based.
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+/// Triangles as three indices each into `vertices`, counter-clockwise when seen from the
+/// front.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<MeshVertex>, indices: Vec<u32>) -> Self {
+        Self { vertices, indices }
+    }
+
+    /// Parses the positions, texture coordinates, normals and faces of an OBJ file, all
+    /// objects and groups merged into one mesh. Polygons are split into fans. Normals are
+    /// computed when the file has none.
+    pub fn from_obj(source: &str) -> Result<Self, MeshError> {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+        let mut mesh = Mesh::default();
+        // OBJ indexes positions, uvs and normals separately, vertices are unique triples
+        let mut vertex_ids = std::collections::HashMap::new();
+        let mut has_normals = true;
+
+        for (i, line) in source.lines().enumerate() {
+            let error = |message: String| MeshError {
+                line: i + 1,
+                message,
+            };
+            let mut parts = line.split_whitespace();
+            let floats = |parts: std::str::SplitWhitespace, n: usize| {
+                let values: Vec<f32> = parts
+                    .take(n)
+                    .map(|s| s.parse::<f32>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| error(e.to_string()))?;
+                if values.len() < n.min(2) {
+                    return Err(error(format!("expected {} numbers", n)));
+                }
+                Ok(values)
+            };
+            match parts.next() {
+                Some("v") => {
+                    let v = floats(parts, 3)?;
+                    positions.push(Vec3::new(v[0], v[1], *v.get(2).unwrap_or(&0.0)));
+                }
+                Some("vt") => {
+                    let v = floats(parts, 2)?;
+                    // OBJ puts v = 0 at the bottom, textures have it at the top
+                    uvs.push(Vec2::new(v[0], 1.0 - v[1]));
+                }
+                Some("vn") => {
+                    let v = floats(parts, 3)?;
+                    normals.push(Vec3::new(v[0], v[1], *v.get(2).unwrap_or(&0.0)));
+                }
+                Some("f") => {
+                    let mut face = Vec::new();
+                    for corner in parts {
+                        let mut ids = corner.split('/');
+                        let mut index = |len: usize| -> Result<Option<usize>, MeshError> {
+                            match ids.next() {
+                                None | Some("") => Ok(None),
+                                Some(s) => {
+                                    let n: i64 = s
+                                        .parse()
+                                        .map_err(|_| error(format!("bad index {:?}", corner)))?;
+                                    // negative indices count back from the latest element
+                                    let n = if n < 0 { len as i64 + n } else { n - 1 };
+                                    if n < 0 || n as usize >= len {
+                                        return Err(error(format!("index {} out of range", s)));
+                                    }
+                                    Ok(Some(n as usize))
+                                }
+                            }
+                        };
+                        let position = index(positions.len())?
+                            .ok_or_else(|| error("face without a position".into()))?;
+                        let uv = index(uvs.len())?;
+                        let normal = index(normals.len())?;
+                        has_normals &= normal.is_some();
+                        let key = (position, uv, normal);
+                        let id = *vertex_ids.entry(key).or_insert_with(|| {
+                            mesh.vertices.push(MeshVertex {
+                                position: positions[position],
+                                normal: normal.map_or(Vec3::ZERO, |n| normals[n]),
+                                uv: uv.map_or(Vec2::ZERO, |t| uvs[t]),
+                            });
+                            mesh.vertices.len() as u32 - 1
+                        });
+                        face.push(id);
+                    }
+                    if face.len() < 3 {
+                        return Err(error("face with fewer than 3 corners".into()));
+                    }
+                    for k in 1..face.len() - 1 {
+                        mesh.indices
+                            .extend_from_slice(&[face[0], face[k], face[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !has_normals {
+            mesh.compute_normals();
+        }
+        Ok(mesh)
+    }
+
+    /// Sets every vertex normal to the area weighted average of the faces around it.
+    pub fn compute_normals(&mut self) {
+        for vertex in &mut self.vertices {
+            vertex.normal = Vec3::ZERO;
+        }
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            let p = [a, b, c].map(|i| self.vertices[i].position);
+            // the cross product's length is twice the area, which does the weighting
+            let normal = (p[1] - p[0]).cross(p[2] - p[0]);
+            for i in [a, b, c] {
+                self.vertices[i].normal += normal;
+            }
+        }
+        for vertex in &mut self.vertices {
+            vertex.normal = vertex.normal.normalize();
+        }
+    }
+
+    /// The box around every vertex, `None` for an empty mesh.
+    pub fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(self.vertices.iter().map(|v| v.position))
+    }
+
+    /// A sphere around every vertex, `None` for an empty mesh.
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        let points: Vec<Vec3> = self.vertices.iter().map(|v| v.position).collect();
+        BoundingSphere::from_points(&points)
+    }
+
+    /// A coarser version with about `ratio` of the triangles, for distant levels of detail.
+    /// Vertices are snapped to a grid over the mesh's bounds and merged per cell, with the
+    /// finest grid that gets down to the target. Texture seams inside a cell blur, which
+    /// is rarely visible at the distances the result is meant for.
+    pub fn simplify(&self, ratio: f32) -> Mesh {
+        let target = (self.indices.len() / 3) as f32 * ratio.clamp(0.0, 1.0);
+        if ratio >= 1.0 || self.vertices.is_empty() {
+            return self.clone();
+        }
+        let (mut low, mut high) = (1, 1024);
+        let mut best = self.cluster(1);
+        while low <= high {
+            let resolution = (low + high) / 2;
+            let mesh = self.cluster(resolution);
+            if (mesh.indices.len() / 3) as f32 <= target {
+                best = mesh;
+                low = resolution + 1;
+            } else {
+                high = resolution - 1;
+            }
+        }
+        best
+    }
+
+    fn cluster(&self, resolution: u32) -> Mesh {
+        let Aabb { min, max } = self.aabb().expect("simplify skips empty meshes");
+        let extent = (max - min).max(Vec3::splat(f32::EPSILON));
+        let cell = |p: Vec3| {
+            let axis = |value: f32, min: f32, extent: f32| {
+                (((value - min) / extent * resolution as f32) as u32).min(resolution - 1)
+            };
+            (
+                axis(p.x, min.x, extent.x),
+                axis(p.y, min.y, extent.y),
+                axis(p.z, min.z, extent.z),
+            )
+        };
+
+        let mut cells = std::collections::HashMap::new();
+        let mut sums: Vec<(MeshVertex, f32)> = Vec::new();
+        let remap: Vec<u32> = self
+            .vertices
+            .iter()
+            .map(|v| {
+                let id = *cells.entry(cell(v.position)).or_insert_with(|| {
+                    sums.push((MeshVertex::default(), 0.0));
+                    sums.len() as u32 - 1
+                });
+                let (sum, count) = &mut sums[id as usize];
+                sum.position += v.position;
+                sum.normal += v.normal;
+                sum.uv += v.uv;
+                *count += 1.0;
+                id
+            })
+            .collect();
+
+        let vertices = sums
+            .into_iter()
+            .map(|(sum, count)| MeshVertex {
+                position: sum.position / count,
+                normal: sum.normal.normalize(),
+                uv: sum.uv / count,
+            })
+            .collect();
+        let mut mesh = Mesh::new(vertices, Vec::new());
+        let mut seen = std::collections::HashSet::new();
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| remap[triangle[i] as usize]);
+            if a == b || b == c || c == a {
+                continue;
+            }
+            // the same triangle from several merged ones, in any rotation
+            let first = a.min(b).min(c);
+            let key = match first {
+                _ if first == a => (a, b, c),
+                _ if first == b => (b, c, a),
+                _ => (c, a, b),
+            };
+            if seen.insert(key) {
+                mesh.indices.extend_from_slice(&[a, b, c]);
+            }
+        }
+        mesh
+    }
+
+    /// Merges vertices with exactly the same position, normal and uv.
+    pub fn deduplicate_vertices(&mut self) {
+        let mut ids = std::collections::HashMap::new();
+        let mut vertices = Vec::new();
+        let remap: Vec<u32> = self
+            .vertices
+            .iter()
+            .map(|v| {
+                let key: [u32; 8] = bytemuck::cast(*v);
+                *ids.entry(key).or_insert_with(|| {
+                    vertices.push(*v);
+                    vertices.len() as u32 - 1
+                })
+            })
+            .collect();
+        for index in &mut self.indices {
+            *index = remap[*index as usize];
+        }
+        self.vertices = vertices;
+    }
+
+    /// Reorders the triangles so vertices are reused while still in the GPU's post
+    /// transform cache, with Tom Forsyth's linear-speed vertex cache optimisation.
+    pub fn optimize_vertex_cache(&mut self) {
+        const CACHE_SIZE: usize = 32;
+        let triangle_count = self.indices.len() / 3;
+        let mut vertex_triangles = vec![Vec::new(); self.vertices.len()];
+        for (t, triangle) in self.indices.chunks_exact(3).enumerate() {
+            for &v in triangle {
+                vertex_triangles[v as usize].push(t as u32);
+            }
+        }
+        let vertex_score = |cache_position: Option<usize>, live: usize| {
+            if live == 0 {
+                return -1.0;
+            }
+            let cache = match cache_position {
+                // the last triangle's vertices score the same, whichever order they came in
+                Some(p) if p < 3 => 0.75,
+                Some(p) => (1.0 - (p - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+                None => 0.0,
+            };
+            // favour vertices with few triangles left, so they're finished off
+            cache + 2.0 * (live as f32).powf(-0.5)
+        };
+
+        let mut cache_position = vec![None; self.vertices.len()];
+        let mut scores: Vec<f32> = vertex_triangles
+            .iter()
+            .map(|triangles| vertex_score(None, triangles.len()))
+            .collect();
+        let triangle_score = |t: usize, scores: &[f32], indices: &[u32]| {
+            (0..3)
+                .map(|i| scores[indices[t * 3 + i] as usize])
+                .sum::<f32>()
+        };
+        let mut triangle_scores: Vec<f32> = (0..triangle_count)
+            .map(|t| triangle_score(t, &scores, &self.indices))
+            .collect();
+        let mut added = vec![false; triangle_count];
+        let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+        let mut indices = Vec::with_capacity(self.indices.len());
+        let mut next_unadded = 0;
+
+        for _ in 0..triangle_count {
+            // the best triangle using a cached vertex, or failing that the first left
+            let best = cache
+                .iter()
+                .flat_map(|&v| vertex_triangles[v as usize].iter().copied())
+                .map(|t| t as usize)
+                .max_by(|&a, &b| triangle_scores[a].total_cmp(&triangle_scores[b]))
+                .unwrap_or_else(|| {
+                    while added[next_unadded] {
+                        next_unadded += 1;
+                    }
+                    next_unadded
+                });
+            added[best] = true;
+            let triangle = [0, 1, 2].map(|i| self.indices[best * 3 + i]);
+            indices.extend_from_slice(&triangle);
+
+            for &v in &triangle {
+                let triangles = &mut vertex_triangles[v as usize];
+                triangles.retain(|&t| t as usize != best);
+                cache.retain(|&c| c != v);
+            }
+            cache.splice(0..0, triangle);
+            for &evicted in cache.iter().skip(CACHE_SIZE) {
+                cache_position[evicted as usize] = None;
+            }
+            let evicted = cache.split_off(cache.len().min(CACHE_SIZE));
+            for (position, &v) in cache.iter().enumerate() {
+                cache_position[v as usize] = Some(position);
+            }
+            for &v in cache.iter().chain(&evicted) {
+                let v = v as usize;
+                scores[v] = vertex_score(cache_position[v], vertex_triangles[v].len());
+            }
+            for &v in cache.iter().chain(&evicted) {
+                for &t in &vertex_triangles[v as usize] {
+                    triangle_scores[t as usize] =
+                        triangle_score(t as usize, &scores, &self.indices);
+                }
+            }
+        }
+        self.indices = indices;
+    }
+
+    /// Reorders the vertices to the order the triangles first use them in, so they're
+    /// fetched front to back, and drops unused ones.
+    pub fn optimize_vertex_fetch(&mut self) {
+        let mut remap = vec![u32::MAX; self.vertices.len()];
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        for index in &mut self.indices {
+            let new = &mut remap[*index as usize];
+            if *new == u32::MAX {
+                *new = vertices.len() as u32;
+                vertices.push(self.vertices[*index as usize]);
+            }
+            *index = *new;
+        }
+        self.vertices = vertices;
+    }
+
+    /// Deduplicates vertices and optimizes for the vertex cache and fetch order, returning
+    /// the stats from before and after.
+    pub fn optimize(&mut self) -> (MeshStats, MeshStats) {
+        let before = self.stats();
+        self.deduplicate_vertices();
+        self.optimize_vertex_cache();
+        self.optimize_vertex_fetch();
+        (before, self.stats())
+    }
+
+    /// Counts, and how well the triangle order uses a 16 entry FIFO vertex cache.
+    pub fn stats(&self) -> MeshStats {
+        const CACHE_SIZE: usize = 16;
+        let mut cache = std::collections::VecDeque::with_capacity(CACHE_SIZE);
+        let mut misses = 0;
+        for &index in &self.indices {
+            if !cache.contains(&index) {
+                misses += 1;
+                if cache.len() == CACHE_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(index);
+            }
+        }
+        let triangles = self.indices.len() / 3;
+        MeshStats {
+            vertices: self.vertices.len(),
+            triangles,
+            acmr: misses as f32 / triangles.max(1) as f32,
+            atvr: misses as f32 / self.vertices.len().max(1) as f32,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MeshStats {
+    pub vertices: usize,
+    pub triangles: usize,
+    /// Average cache miss ratio, vertices transformed per triangle. 0.5 is the best a
+    /// regular grid can do, 3 means no reuse at all.
+    pub acmr: f32,
+    /// Average transform to vertex ratio, 1 means every vertex is transformed once.
+    pub atvr: f32,
+}
+
+impl std::fmt::Display for MeshStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} vertices, {} triangles, ACMR {:.3}, ATVR {:.3}",
+            self.vertices, self.triangles, self.acmr, self.atvr
+        )
+    }
+}
+
+/// A mesh uploaded for drawing, with `MeshVertex::desc` as its vertex layout.
+pub struct GpuMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+    /// Bounds of the mesh in its own space, a point at the origin for an empty mesh.
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+    _tracked: Tracked,
+}
+
+impl GpuMesh {
+    pub fn new(device: &wgpu::Device, mesh: &Mesh, label: &str) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", label)),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", label)),
+            contents: bytemuck::cast_slice(&mesh.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let tracked = Tracked::new(0, 2, 0)
+            .with_buffer(&vertex_buffer)
+            .with_buffer(&index_buffer);
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_count: mesh.indices.len() as u32,
+            aabb: mesh.aabb().unwrap_or(Aabb::new(Vec3::ZERO, Vec3::ZERO)),
+            bounding_sphere: mesh
+                .bounding_sphere()
+                .unwrap_or(BoundingSphere::new(Vec3::ZERO, 0.0)),
+            _tracked: tracked,
+        }
+    }
+
+    /// Binds the buffers and draws `instances`, with the pipeline and bind groups already
+    /// set.
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, instances: std::ops::Range<u32>) {
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        pass.draw_indexed(0..self.index_count, 0, instances);
+    }
+}
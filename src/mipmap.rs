@@ -0,0 +1,84 @@
+//! Filling in the mip levels of a texture from the first, by blitting each level into the
+//! next at half the size with linear filtering.
+
+use crate::blit::Blitter;
+
+/// Number of levels down to 1x1 for a texture of this size.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Wraps a `Blitter`, reuse it for many textures.
+pub struct MipmapGenerator {
+    blitter: Blitter,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            blitter: Blitter::new(device),
+        }
+    }
+
+    /// Records rendering every level of `texture` after the first from the one before.
+    /// The texture needs `TEXTURE_BINDING` and `COPY_DST` usage and a format that can be
+    /// rendered to and filtered, e.g. `Rgba8UnormSrgb`.
+    ///
+    /// Each level is rendered into a scratch texture and copied over, since the GL backend
+    /// can't sample a single level other than the first.
+    pub fn generate(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        let format = texture.format();
+        encoder.push_debug_group("Mipmaps");
+        let mut source = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Mipmap Source View"),
+            base_mip_level: 0,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        for level in 1..texture.mip_level_count() {
+            let size = texture
+                .size()
+                .mip_level_size(level, wgpu::TextureDimension::D2);
+            let scratch = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Mipmap Scratch Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let target = scratch.create_view(&wgpu::TextureViewDescriptor::default());
+            self.blitter.blit(
+                device,
+                encoder,
+                &source,
+                &target,
+                format,
+                wgpu::FilterMode::Linear,
+            );
+            encoder.copy_texture_to_texture(
+                scratch.as_image_copy(),
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                size,
+            );
+            source = target;
+        }
+        // every source was made for this call
+        self.blitter.trim();
+        encoder.pop_debug_group();
+    }
+}
@@ -0,0 +1,177 @@
+//! Motion blur along each pixel's velocity, as a `PostEffect`.
+//!
+//! The velocity is how far each pixel moved on screen since the last frame, which a
+//! `MaterialRenderer` made `with_velocity` writes into a second color target of
+//! `VELOCITY_FORMAT` as it draws, from the camera's and each instance's motion. The blur
+//! averages samples of the frame along that motion, scaled by how long the shutter is open.
+//! Pixels nothing was drawn on have the velocity the target was cleared to, usually 0.
+
+use crate::post::{PostEffect, PostFrame, PostPipeline, PostTarget};
+use crate::stats::Tracked;
+
+/// Format of the velocity target, in uv units per frame.
+pub const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+const SHADER: &str = r#"
+struct Params {
+    shutter: f32,
+    max_length: f32,
+    samples: u32,
+};
+
+@group(1) @binding(0) var velocity: texture_2d<f32>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(source));
+    let moved = textureLoad(velocity, vec2<i32>(in.position.xy), 0).xy * params.shutter;
+    // clamped in pixels so fast motion doesn't smear across the whole frame
+    let pixels = length(moved * size);
+    let blur = moved * min(1.0, params.max_length / max(pixels, 1e-4));
+    let centre = textureSample(source, source_sampler, in.uv);
+    var sum = vec3<f32>(0.0);
+    let samples = max(params.samples, 2u);
+    for (var i = 0u; i < samples; i = i + 1u) {
+        // centred on the pixel, half the motion before and half after
+        let t = f32(i) / f32(samples - 1u) - 0.5;
+        sum = sum + textureSample(source, source_sampler, in.uv + blur * t).rgb;
+    }
+    return vec4<f32>(sum / f32(samples), centre.a);
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MotionBlurParams {
+    /// Share of the frame the shutter is open for, 0 for no blur and 1 to blur across all
+    /// of the last frame's motion.
+    pub shutter: f32,
+    /// Longest blur in pixels.
+    pub max_length: f32,
+    /// Samples along the motion of each pixel.
+    pub samples: u32,
+}
+unsafe impl bytemuck::Pod for MotionBlurParams {}
+unsafe impl bytemuck::Zeroable for MotionBlurParams {}
+
+impl Default for MotionBlurParams {
+    fn default() -> Self {
+        Self {
+            shutter: 0.5,
+            max_length: 32.0,
+            samples: 8,
+        }
+    }
+}
+
+pub struct MotionBlur {
+    pub params: MotionBlurParams,
+    pass: PostPipeline,
+    velocity_layout: wgpu::BindGroupLayout,
+    velocity_bind_group: Option<wgpu::BindGroup>,
+    /// Bound until `set_velocity` is called, with the blur off.
+    still_bind_group: wgpu::BindGroup,
+    _tracked: Tracked,
+}
+
+impl MotionBlur {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let velocity_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Motion Blur Velocity Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let pass = PostPipeline::new(
+            device,
+            "Motion Blur",
+            SHADER,
+            std::mem::size_of::<MotionBlurParams>() as u64,
+            &[&velocity_layout],
+        );
+        let still = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Motion Blur Still Velocity"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: VELOCITY_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let still_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Motion Blur Still Bind Group"),
+            layout: &velocity_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(
+                    &still.create_view(&wgpu::TextureViewDescriptor::default()),
+                ),
+            }],
+        });
+        let tracked = Tracked::new(0, 0, 1).with_texture(&still);
+        Self {
+            params: MotionBlurParams::default(),
+            pass,
+            velocity_layout,
+            velocity_bind_group: None,
+            still_bind_group,
+            _tracked: tracked,
+        }
+    }
+
+    pub fn with_params(mut self, params: MotionBlurParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// The velocity target the scene was drawn with, the size of the chain. Set it again
+    /// when it's recreated, e.g. on resize.
+    pub fn set_velocity(&mut self, device: &wgpu::Device, velocity: &wgpu::TextureView) {
+        self.velocity_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Motion Blur Velocity Bind Group"),
+            layout: &self.velocity_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(velocity),
+            }],
+        }));
+    }
+}
+
+impl PostEffect for MotionBlur {
+    fn label(&self) -> &str {
+        "Motion Blur"
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    ) {
+        let mut params = self.params;
+        let velocity = match &self.velocity_bind_group {
+            Some(velocity) => velocity,
+            None => {
+                log::warn!("motion blur applied before the velocity was set");
+                params.shutter = 0.0;
+                &self.still_bind_group
+            }
+        };
+        self.pass
+            .draw(frame, encoder, source, target, &params, &[velocity]);
+    }
+}
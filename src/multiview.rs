@@ -0,0 +1,347 @@
+//! Drawing the same scene into several layers of a texture array, for stereo or the six
+//! faces of a cubemap.
+//!
+//! With the `MULTIVIEW` feature one pass draws every layer, the vertex shader picking each
+//! layer's view projection by `@builtin(view_index)`. Without it there is a pass per
+//! layer, binding the uniform at the offset of that layer's index. Shaders handle both
+//! with `#ifdef MULTIVIEW` around the builtin, see `VIEWS_SHADER`, preprocessed with
+//! `Multiview::defines`.
+
+use std::num::NonZeroU32;
+
+use crate::depth;
+use crate::material::preprocess;
+use crate::math::Mat4;
+use crate::stats::Tracked;
+use crate::uniform_arena::UniformArena;
+
+/// Layers a `Multiview` draws at most, enough for a cubemap.
+pub const MAX_VIEWS: usize = 6;
+
+/// The view uniform at group 0 of pipelines drawing with a `Multiview`. Prepend it to the
+/// shader and get the view projection with `views.view_proj[view]`, where `view` is
+/// `u32(view_index)` under `#ifdef MULTIVIEW` and `views.index` otherwise.
+pub const VIEWS_SHADER: &str = r#"
+struct Views {
+    view_proj: array<mat4x4<f32>, 6>,
+    // the layer being drawn, when each gets its own pass
+    index: u32,
+};
+@group(0) @binding(0)
+var<uniform> views: Views;
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct ViewsUniform {
+    view_proj: [Mat4; MAX_VIEWS],
+    index: u32,
+    _padding: [u32; 3],
+}
+unsafe impl bytemuck::Pod for ViewsUniform {}
+unsafe impl bytemuck::Zeroable for ViewsUniform {}
+
+/// A color and depth texture array to draw into with a `Multiview`.
+pub struct MultiviewTarget {
+    color: wgpu::Texture,
+    color_array: wgpu::TextureView,
+    depth_array: wgpu::TextureView,
+    color_layers: Vec<wgpu::TextureView>,
+    depth_layers: Vec<wgpu::TextureView>,
+    _tracked: Tracked,
+}
+
+impl MultiviewTarget {
+    /// `layers` layers of `width` by `height`. The color texture can be sampled afterwards.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        layers: u32,
+    ) -> Self {
+        let create = |label: &str, format: wgpu::TextureFormat, usage: wgpu::TextureUsages| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: layers.max(1),
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let color = create(
+            "Multiview Color Target",
+            format,
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+        );
+        let depth = create(
+            "Multiview Depth Target",
+            depth_format,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let array_view = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            })
+        };
+        let layer_views = |texture: &wgpu::Texture| {
+            (0..layers.max(1))
+                .map(|layer| {
+                    texture.create_view(&wgpu::TextureViewDescriptor {
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_array_layer: layer,
+                        array_layer_count: Some(1),
+                        ..Default::default()
+                    })
+                })
+                .collect()
+        };
+        let tracked = Tracked::new(0, 0, 2)
+            .with_texture(&color)
+            .with_texture(&depth);
+        Self {
+            color_array: array_view(&color),
+            depth_array: array_view(&depth),
+            color_layers: layer_views(&color),
+            depth_layers: layer_views(&depth),
+            color,
+            _tracked: tracked,
+        }
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.color.depth_or_array_layers()
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.color
+    }
+
+    /// Every layer of the color texture.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.color_array
+    }
+
+    pub fn layer_view(&self, layer: u32) -> &wgpu::TextureView {
+        &self.color_layers[layer as usize]
+    }
+
+    /// A pass drawing into `layer` alone, for drawing the layers one by one with renderers
+    /// that don't know about multiview.
+    pub fn layer_pass(&self, layer: u32) -> ViewPass<'_> {
+        ViewPass {
+            color: &self.color_layers[layer as usize],
+            depth: &self.depth_layers[layer as usize],
+            layer: Some(layer),
+            offset: 0,
+        }
+    }
+}
+
+/// One pass of `Multiview::draw`, covering every layer or just `layer`.
+pub struct ViewPass<'t> {
+    color: &'t wgpu::TextureView,
+    depth: &'t wgpu::TextureView,
+    /// `None` when the pass draws every layer at once.
+    pub layer: Option<u32>,
+    /// To bind `Multiview::bind_group` at.
+    pub offset: u32,
+}
+
+impl<'t> ViewPass<'t> {
+    /// Begins the pass, clearing color to `clear` if given and depth to the far value of
+    /// the `depth::mode`.
+    pub fn begin<'p>(
+        &self,
+        encoder: &'p mut wgpu::CommandEncoder,
+        clear: Option<wgpu::Color>,
+    ) -> wgpu::RenderPass<'p>
+    where
+        't: 'p,
+    {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Multiview Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: self.color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: self.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: if clear.is_some() {
+                        wgpu::LoadOp::Clear(depth::mode().clear_value())
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+}
+
+/// Draws into every layer of a `MultiviewTarget`, in one pass when the device has the
+/// `MULTIVIEW` feature and in a pass per layer when it doesn't.
+pub struct Multiview {
+    layers: u32,
+    single_pass: bool,
+    views: UniformArena,
+    view_proj: [Mat4; MAX_VIEWS],
+    offsets: Vec<u32>,
+}
+
+impl Multiview {
+    /// Draws `layers` layers, at most `MAX_VIEWS`.
+    pub fn new(device: &wgpu::Device, layers: u32) -> Self {
+        let layers = layers.clamp(1, MAX_VIEWS as u32);
+        Self {
+            layers,
+            single_pass: device.features().contains(wgpu::Features::MULTIVIEW),
+            views: UniformArena::new(
+                device,
+                "Multiview Views",
+                std::mem::size_of::<ViewsUniform>() as u64,
+                wgpu::ShaderStages::VERTEX,
+            ),
+            view_proj: [Mat4::IDENTITY; MAX_VIEWS],
+            offsets: Vec::new(),
+        }
+    }
+
+    /// Draws a pass per layer even when the device could draw them in one, e.g. to compare
+    /// the two.
+    pub fn with_multi_pass(mut self) -> Self {
+        self.single_pass = false;
+        self
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    /// Whether every layer is drawn in one pass.
+    pub fn is_single_pass(&self) -> bool {
+        self.single_pass
+    }
+
+    /// For the `multiview` field of the pipeline descriptors.
+    pub fn pipeline_multiview(&self) -> Option<NonZeroU32> {
+        if self.single_pass {
+            NonZeroU32::new(self.layers)
+        } else {
+            None
+        }
+    }
+
+    /// `MULTIVIEW` when drawing in one pass.
+    pub fn defines(&self) -> &'static [&'static str] {
+        if self.single_pass {
+            &["MULTIVIEW"]
+        } else {
+            &[]
+        }
+    }
+
+    /// `source` with `VIEWS_SHADER` prepended, preprocessed with `defines`.
+    pub fn shader_source(&self, source: &str) -> String {
+        preprocess(&format!("{}{}", VIEWS_SHADER, source), self.defines())
+    }
+
+    /// For group 0 of the pipeline layouts.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        self.views.layout()
+    }
+
+    /// Bind at group 0 with the `ViewPass::offset` of the pass.
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        self.views.bind_group()
+    }
+
+    /// The view projection of each layer, extra ones are ignored.
+    pub fn set_views(&mut self, view_proj: &[Mat4]) {
+        for (view, matrix) in self.view_proj.iter_mut().zip(view_proj) {
+            *view = *matrix;
+        }
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.views.clear();
+        let passes = if self.single_pass { 1 } else { self.layers };
+        self.offsets = (0..passes)
+            .map(|index| {
+                self.views.push(&ViewsUniform {
+                    view_proj: self.view_proj,
+                    index,
+                    _padding: [0; 3],
+                })
+            })
+            .collect();
+        self.views.upload(device, queue);
+    }
+
+    /// Calls `draw` for every pass needed to cover the layers of `target`, once or once per
+    /// layer. It should begin the pass with `ViewPass::begin` and draw the same things
+    /// whichever layer it is.
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &MultiviewTarget,
+        mut draw: impl FnMut(&mut wgpu::CommandEncoder, &ViewPass),
+    ) {
+        if target.layers() != self.layers {
+            log::warn!(
+                "multiview target has {} layers, expected {}",
+                target.layers(),
+                self.layers
+            );
+            return;
+        }
+        if self.offsets.is_empty() {
+            log::warn!("multiview drawn without prepare");
+            return;
+        }
+        if self.single_pass {
+            draw(
+                encoder,
+                &ViewPass {
+                    color: &target.color_array,
+                    depth: &target.depth_array,
+                    layer: None,
+                    offset: self.offsets[0],
+                },
+            );
+            return;
+        }
+        for (layer, &offset) in (0..self.layers).zip(&self.offsets) {
+            encoder.push_debug_group(&format!("Multiview Layer {}", layer));
+            draw(
+                encoder,
+                &ViewPass {
+                    color: &target.color_layers[layer as usize],
+                    depth: &target.depth_layers[layer as usize],
+                    layer: Some(layer),
+                    offset,
+                },
+            );
+            encoder.pop_debug_group();
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Selection outlines around picked objects, drawn from the `Picker`'s id buffer.
+//!
+//! The pixels of the selected entities seed a jump flood: a pass per power of two step,
+//! down to one pixel, each pixel keeping the nearest seed any of its eight neighbours at
+//! that step knows of. Afterwards every pixel knows how far it is from the selection, so
+//! the outline costs the same whatever its width. Only what's visible of the selection is
+//! in the id buffer, so the outline hugs the parts in front of everything else and is
+//! drawn on top of the scene.
+
+use std::sync::Arc;
+
+use crate::bind_group_cache::BindGroupCache;
+use crate::picking::Picker;
+use crate::stats::Tracked;
+use crate::uniform_arena::UniformArena;
+
+/// Entities an `Outline` can select at once.
+pub const MAX_SELECTED: usize = 16;
+
+/// Widest outline, in pixels.
+pub const MAX_WIDTH: f32 = 64.0;
+
+const SEED_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg32Float;
+
+const SHADER: &str = r#"
+struct Params {
+    // entity ids plus one, as in the id buffer
+    selected: array<vec4<u32>, 4>,
+    color: vec4<f32>,
+    step: i32,
+    count: u32,
+    width: f32,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(1) @binding(0) var ids: texture_2d<u32>;
+// the nearest seed's pixel, negative when none was found yet
+@group(1) @binding(1) var seeds: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+fn is_selected(pixel: vec2<i32>) -> bool {
+    let id = textureLoad(ids, pixel, 0).r;
+    for (var i = 0u; i < params.count; i = i + 1u) {
+        if params.selected[i / 4u][i % 4u] == id {
+            return true;
+        }
+    }
+    return false;
+}
+
+@fragment
+fn fs_seed(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    if is_selected(pixel) {
+        return vec4<f32>(vec2<f32>(pixel), 0.0, 0.0);
+    }
+    return vec4<f32>(-1.0, -1.0, 0.0, 0.0);
+}
+
+@fragment
+fn fs_jump(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let size = vec2<i32>(textureDimensions(seeds));
+    var nearest = vec2<f32>(-1.0);
+    var nearest_distance = 1e20;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let neighbour = pixel + vec2<i32>(x, y) * params.step;
+            if any(neighbour < vec2<i32>(0)) || any(neighbour >= size) {
+                continue;
+            }
+            let seed = textureLoad(seeds, neighbour, 0).xy;
+            let d = distance(seed, vec2<f32>(pixel));
+            if seed.x >= 0.0 && d < nearest_distance {
+                nearest = seed;
+                nearest_distance = d;
+            }
+        }
+    }
+    return vec4<f32>(nearest, 0.0, 0.0);
+}
+
+@fragment
+fn fs_composite(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let seed = textureLoad(seeds, pixel, 0).xy;
+    if seed.x < 0.0 || is_selected(pixel) {
+        discard;
+    }
+    let coverage = clamp(params.width + 1.0 - distance(seed, vec2<f32>(pixel)), 0.0, 1.0);
+    return vec4<f32>(params.color.rgb, params.color.a * coverage);
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Params {
+    selected: [[u32; 4]; MAX_SELECTED / 4],
+    color: [f32; 4],
+    step: i32,
+    count: u32,
+    width: f32,
+    _padding: u32,
+}
+unsafe impl bytemuck::Pod for Params {}
+unsafe impl bytemuck::Zeroable for Params {}
+
+/// Outlines the entities selected with `select`, as they were drawn by a `Picker` the same
+/// size as the outline.
+pub struct Outline {
+    /// Alpha blended over the scene.
+    pub color: [f32; 4],
+    /// In pixels, up to `MAX_WIDTH`.
+    pub width: f32,
+    selected: Vec<u32>,
+    seed_pipeline: wgpu::RenderPipeline,
+    jump_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    seeds_layout: wgpu::BindGroupLayout,
+    params: UniformArena,
+    bind_groups: BindGroupCache,
+    seeds: [(wgpu::Texture, wgpu::TextureView); 2],
+    /// The bind group and params offset `render` draws with, after `encode`.
+    composite: Option<(Arc<wgpu::BindGroup>, u32)>,
+    tracked: Tracked,
+}
+
+impl Outline {
+    /// An outline drawn into `format`, `width` by `height` like the picker's.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let params = UniformArena::new(
+            device,
+            "Outline Params",
+            std::mem::size_of::<Params>() as u64,
+            wgpu::ShaderStages::FRAGMENT,
+        );
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let seeds_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Outline Seeds Layout"),
+            entries: &[
+                texture_entry(0, wgpu::TextureSampleType::Uint),
+                texture_entry(1, wgpu::TextureSampleType::Float { filterable: false }),
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Pipeline Layout"),
+            bind_group_layouts: &[params.layout(), &seeds_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point, target: wgpu::ColorTargetState| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(target)],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let seed_pipeline = create_pipeline("Outline Seed Pipeline", "fs_seed", SEED_FORMAT.into());
+        let jump_pipeline = create_pipeline("Outline Jump Pipeline", "fs_jump", SEED_FORMAT.into());
+        let composite_pipeline = create_pipeline(
+            "Outline Composite Pipeline",
+            "fs_composite",
+            wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            },
+        );
+
+        let (seeds, tracked) = Self::create_seeds(device, width, height);
+        Self {
+            color: [1.0, 0.6, 0.1, 1.0],
+            width: 3.0,
+            selected: Vec::new(),
+            seed_pipeline,
+            jump_pipeline,
+            composite_pipeline,
+            seeds_layout,
+            params,
+            bind_groups: BindGroupCache::new(),
+            seeds,
+            composite: None,
+            tracked,
+        }
+    }
+
+    fn create_seeds(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> ([(wgpu::Texture, wgpu::TextureView); 2], Tracked) {
+        let create = || {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Outline Seeds"),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: SEED_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+        let seeds = [create(), create()];
+        let tracked = Tracked::new(3, 0, 2)
+            .with_texture(&seeds[0].0)
+            .with_texture(&seeds[1].0);
+        (seeds, tracked)
+    }
+
+    /// Resize along with the picker.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (seeds, tracked) = Self::create_seeds(device, width, height);
+        self.seeds = seeds;
+        self.tracked = tracked;
+        self.composite = None;
+    }
+
+    /// Outlines `entities`, the ids they were drawn into the picker with. Past
+    /// `MAX_SELECTED` they're ignored, and an empty slice clears the selection.
+    pub fn select(&mut self, entities: &[u32]) {
+        if entities.len() > MAX_SELECTED {
+            log::warn!(
+                "{} entities selected, only the first {} are outlined",
+                entities.len(),
+                MAX_SELECTED
+            );
+        }
+        self.selected = entities.iter().take(MAX_SELECTED).copied().collect();
+    }
+
+    pub fn selection(&self) -> &[u32] {
+        &self.selected
+    }
+
+    /// Floods the distance to the selection out from the picker's last ids. Call after
+    /// `Picker::pick` and before `render`.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        picker: &Picker,
+    ) {
+        self.composite = None;
+        self.bind_groups.end_frame();
+        if self.selected.is_empty() {
+            return;
+        }
+
+        let width = self.width.clamp(0.0, MAX_WIDTH);
+        let mut selected = [[0; 4]; MAX_SELECTED / 4];
+        for (i, entity) in self.selected.iter().enumerate() {
+            selected[i / 4][i % 4] = entity.wrapping_add(1);
+        }
+        let params = |step| Params {
+            selected,
+            color: self.color,
+            step,
+            count: self.selected.len() as u32,
+            width,
+            _padding: 0,
+        };
+        // halving down to 1 from a step that together with the smaller ones reaches past
+        // `width` pixels out
+        let mut steps = Vec::new();
+        let mut step = (width.ceil() as u32 + 1).next_power_of_two();
+        while step >= 1 {
+            steps.push(step as i32);
+            step /= 2;
+        }
+        self.params.clear();
+        let seed_offset = self.params.push(&params(0));
+        let step_offsets: Vec<u32> = steps
+            .iter()
+            .map(|&step| self.params.push(&params(step)))
+            .collect();
+        self.params.upload(device, queue);
+
+        let bind_group = |cache: &mut BindGroupCache, layout, seeds: &wgpu::TextureView| {
+            cache.get(
+                device,
+                "Outline Seeds Bind Group",
+                layout,
+                &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(picker.id_view()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(seeds),
+                    },
+                ],
+            )
+        };
+        let reads = [
+            bind_group(&mut self.bind_groups, &self.seeds_layout, &self.seeds[0].1),
+            bind_group(&mut self.bind_groups, &self.seeds_layout, &self.seeds[1].1),
+        ];
+
+        encoder.push_debug_group("Outline");
+        let mut pass = |pipeline, target: usize, offset: u32| {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Outline Jump Flood Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.seeds[target].1,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, self.params.bind_group(), &[offset]);
+            render_pass.set_bind_group(1, &reads[1 - target], &[]);
+            render_pass.draw(0..3, 0..1);
+        };
+        let mut current = 0;
+        pass(&self.seed_pipeline, current, seed_offset);
+        for offset in step_offsets {
+            current = 1 - current;
+            pass(&self.jump_pipeline, current, offset);
+        }
+        encoder.pop_debug_group();
+
+        self.composite = Some((reads[current].clone(), seed_offset));
+    }
+
+    /// Draws the outline over the scene, in a pass the size of the picker.
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let Some((bind_group, offset)) = &self.composite else {
+            return;
+        };
+        render_pass.push_debug_group("Outline");
+        render_pass.set_pipeline(&self.composite_pipeline);
+        render_pass.set_bind_group(0, self.params.bind_group(), &[*offset]);
+        render_pass.set_bind_group(1, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
@@ -0,0 +1,456 @@
+//! CPU simulated particles drawn as instanced camera facing quads.
+
+use crate::camera::Camera;
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::math::Vec3;
+use crate::random::Rng;
+use crate::stats::Tracked;
+use crate::tween::Curve;
+
+const SHADER: &str = r#"
+struct InstanceInput {
+    @location(0) position: vec3<f32>,
+    @location(1) size: f32,
+    @location(2) color: vec4<f32>,
+    @location(3) rotation: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: InstanceInput) -> VertexOutput {
+    // two triangles making a unit quad centered on the particle
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, 0.5),
+    );
+    let corner = corners[index];
+    let c = cos(instance.rotation);
+    let s = sin(instance.rotation);
+    let rotated = vec2<f32>(corner.x * c - corner.y * s, corner.x * s + corner.y * c) * instance.size;
+    let world = instance.position
+        + globals.inverse_view[0].xyz * rotated.x
+        + globals.inverse_view[1].xyz * rotated.y;
+
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(world, 1.0);
+    out.uv = corner * 2.0;
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // soft round sprite, no texture needed
+    let falloff = 1.0 - smoothstep(0.5, 1.0, length(in.uv));
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
+"#;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Regular transparency, particles are sorted back to front.
+    #[default]
+    Alpha,
+    /// Colors add up, good for fire and sparks. Order doesn't matter.
+    Additive,
+}
+
+impl BlendMode {
+    pub fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EmitterConfig {
+    /// Particles spawned per second while emitting.
+    pub spawn_rate: f32,
+    /// Lifetime in seconds, picked uniformly from `min..max`.
+    pub lifetime: (f32, f32),
+    /// Main emission direction.
+    pub direction: Vec3,
+    /// Half angle of the emission cone in radians. `PI` emits in all directions.
+    pub spread: f32,
+    /// Initial speed, picked uniformly from `min..max`.
+    pub speed: (f32, f32),
+    /// Constant acceleration, e.g. gravity.
+    pub acceleration: Vec3,
+    /// Fraction of velocity lost per second.
+    pub drag: f32,
+    /// Angular velocity in radians per second, picked uniformly from `min..max`.
+    pub spin: (f32, f32),
+    /// World size over normalized lifetime.
+    pub size: Curve<f32>,
+    /// RGBA over normalized lifetime.
+    pub color: Curve<[f32; 4]>,
+    pub max_particles: usize,
+    pub blend: BlendMode,
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        Self {
+            spawn_rate: 50.0,
+            lifetime: (1.0, 2.0),
+            direction: Vec3::Y,
+            spread: 0.3,
+            speed: (1.0, 2.0),
+            acceleration: Vec3::new(0.0, -1.0, 0.0),
+            drag: 0.0,
+            spin: (0.0, 0.0),
+            size: Curve::linear(0.1, 0.0),
+            color: Curve::linear([1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0]),
+            max_particles: 1000,
+            blend: BlendMode::Alpha,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Particle {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub rotation: f32,
+    pub spin: f32,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+impl Particle {
+    /// Normalized age, 0 at spawn and 1 at death.
+    pub fn life(&self) -> f32 {
+        (self.age / self.lifetime).min(1.0)
+    }
+}
+
+pub struct ParticleEmitter {
+    pub config: EmitterConfig,
+    pub position: Vec3,
+    pub emitting: bool,
+    particles: Vec<Particle>,
+    spawn_accumulator: f32,
+    rng: Rng,
+}
+
+impl ParticleEmitter {
+    pub fn new(config: EmitterConfig) -> Self {
+        Self::with_rng(config, Rng::from_time())
+    }
+
+    pub fn with_rng(config: EmitterConfig, rng: Rng) -> Self {
+        Self {
+            particles: Vec::with_capacity(config.max_particles),
+            config,
+            position: Vec3::ZERO,
+            emitting: true,
+            spawn_accumulator: 0.0,
+            rng,
+        }
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Still has something to show, either emitting or with particles alive.
+    pub fn is_alive(&self) -> bool {
+        self.emitting || !self.particles.is_empty()
+    }
+
+    /// Spawns `count` particles at once, ignoring the spawn rate.
+    pub fn burst(&mut self, count: usize) {
+        for _ in 0..count {
+            self.spawn();
+        }
+    }
+
+    fn spawn(&mut self) {
+        if self.particles.len() >= self.config.max_particles {
+            return;
+        }
+        let c = &self.config;
+        let direction = self.rng.cone(c.direction, c.spread);
+        let speed = self.rng.range(c.speed.0, c.speed.1);
+        let particle = Particle {
+            position: self.position,
+            velocity: direction * speed,
+            rotation: self.rng.range(0.0, std::f32::consts::TAU),
+            spin: self.rng.range(c.spin.0, c.spin.1),
+            age: 0.0,
+            lifetime: self.rng.range(c.lifetime.0, c.lifetime.1).max(f32::EPSILON),
+        };
+        self.particles.push(particle);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let c = &self.config;
+        let damping = (1.0 - c.drag * dt).max(0.0);
+        for p in &mut self.particles {
+            p.age += dt;
+            p.velocity += c.acceleration * dt;
+            p.velocity *= damping;
+            p.position += p.velocity * dt;
+            p.rotation += p.spin * dt;
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        if self.emitting {
+            self.spawn_accumulator += self.config.spawn_rate * dt;
+            while self.spawn_accumulator >= 1.0 {
+                self.spawn_accumulator -= 1.0;
+                self.spawn();
+            }
+        } else {
+            self.spawn_accumulator = 0.0;
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct ParticleInstance {
+    position: [f32; 3],
+    size: f32,
+    color: [f32; 4],
+    rotation: f32,
+}
+unsafe impl bytemuck::Pod for ParticleInstance {}
+unsafe impl bytemuck::Zeroable for ParticleInstance {}
+
+impl ParticleInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x3, 1 => Float32, 2 => Float32x4, 3 => Float32
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+struct Batch {
+    blend: BlendMode,
+    instances: std::ops::Range<u32>,
+}
+
+/// Draws any number of emitters. Call `prepare` every frame before `render`.
+pub struct ParticleRenderer {
+    alpha_pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    instance_buffer: wgpu::Buffer,
+    capacity: usize,
+    instances: Vec<ParticleInstance>,
+    batches: Vec<Batch>,
+    tracked: Tracked,
+}
+
+impl ParticleRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |label, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[ParticleInstance::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        let alpha_pipeline = pipeline("Particle Alpha Pipeline", BlendMode::Alpha.blend_state());
+        let additive_pipeline = pipeline(
+            "Particle Additive Pipeline",
+            BlendMode::Additive.blend_state(),
+        );
+
+        let capacity = 1024;
+        let instance_buffer = Self::create_instance_buffer(device, capacity);
+        let tracked = Tracked::new(2, 2, 0)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&instance_buffer);
+
+        Self {
+            alpha_pipeline,
+            additive_pipeline,
+            globals_buffer,
+            bind_group,
+            instance_buffer,
+            capacity,
+            instances: Vec::new(),
+            batches: Vec::new(),
+            tracked,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Instance Buffer"),
+            size: (capacity * std::mem::size_of::<ParticleInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Uploads the current state of `emitters`, growing the instance buffer if needed.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        emitters: &[&ParticleEmitter],
+    ) {
+        let globals = FrameGlobals::from_camera(camera);
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        self.instances.clear();
+        self.batches.clear();
+        let forward = camera.forward();
+        let mut order = Vec::new();
+        for emitter in emitters {
+            let particles = emitter.particles();
+            if particles.is_empty() {
+                continue;
+            }
+            order.clear();
+            order.extend(0..particles.len());
+            if emitter.config.blend == BlendMode::Alpha {
+                // back to front along the view direction
+                let depth = |i: &usize| (particles[*i].position - camera.eye).dot(forward);
+                order.sort_by(|a, b| depth(b).total_cmp(&depth(a)));
+            }
+
+            let start = self.instances.len() as u32;
+            let config = &emitter.config;
+            self.instances.extend(order.iter().map(|&i| {
+                let p = &particles[i];
+                ParticleInstance {
+                    position: p.position.to_array(),
+                    size: config.size.sample(p.life()),
+                    color: config.color.sample(p.life()),
+                    rotation: p.rotation,
+                }
+            }));
+            self.batches.push(Batch {
+                blend: config.blend,
+                instances: start..self.instances.len() as u32,
+            });
+        }
+
+        if self.instances.len() > self.capacity {
+            self.capacity = self.instances.len().next_power_of_two();
+            let instance_buffer = Self::create_instance_buffer(device, self.capacity);
+            self.tracked
+                .replace_buffer(&self.instance_buffer, &instance_buffer);
+            self.instance_buffer = instance_buffer;
+        }
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.batches.is_empty() {
+            return;
+        }
+        render_pass.push_debug_group("Particles");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        for batch in &self.batches {
+            render_pass.set_pipeline(match batch.blend {
+                BlendMode::Alpha => &self.alpha_pipeline,
+                BlendMode::Additive => &self.additive_pipeline,
+            });
+            render_pass.draw(0..6, batch.instances.clone());
+        }
+        render_pass.pop_debug_group();
+    }
+}
@@ -0,0 +1,469 @@
+//! Object picking through an ID buffer.
+//!
+//! Pickable meshes are drawn with their entity id into an offscreen `R32Uint` target with
+//! its own depth buffer. The texel under the cursor is copied into a small buffer and read
+//! back asynchronously, so the result of one `pick` shows up in `picked_entity` after a
+//! later `pick` call, usually the next frame.
+
+use std::sync::{Arc, Mutex};
+
+use wgpu::util::DeviceExt;
+
+use crate::depth;
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::math::{Mat4, Vec3};
+use crate::stats::Tracked;
+
+const SHADER: &str = r#"
+struct InstanceInput {
+    @location(1) model_0: vec4<f32>,
+    @location(2) model_1: vec4<f32>,
+    @location(3) model_2: vec4<f32>,
+    @location(4) model_3: vec4<f32>,
+    @location(5) id: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) id: u32,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * model * vec4<f32>(position, 1.0);
+    out.id = instance.id;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) u32 {
+    return in.id;
+}
+"#;
+
+const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct PickInstance {
+    model: Mat4,
+    /// Entity id plus one, zero is the cleared background.
+    id: u32,
+}
+unsafe impl bytemuck::Pod for PickInstance {}
+unsafe impl bytemuck::Zeroable for PickInstance {}
+
+impl PickInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        1 => Float32x4, 2 => Float32x4, 3 => Float32x4, 4 => Float32x4, 5 => Uint32
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PickMeshId(usize);
+
+struct PickMesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    _tracked: Tracked,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Readback {
+    Idle,
+    /// Copied and waiting for the map to finish.
+    Pending,
+    Mapped,
+}
+
+pub struct Picker {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    meshes: Vec<PickMesh>,
+    queued: Vec<(PickMeshId, PickInstance)>,
+    id_texture: wgpu::Texture,
+    depth_texture: wgpu::Texture,
+    id_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    readback_buffer: wgpu::Buffer,
+    readback: Arc<Mutex<Readback>>,
+    picked: Option<u32>,
+    tracked: Tracked,
+}
+
+impl Picker {
+    /// `width` and `height` should match the view the cursor position refers to.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Pick Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Pick Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Pick Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pick Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Pick Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<Vec3>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                    },
+                    PickInstance::desc(),
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(depth::depth_stencil(DEPTH_FORMAT)),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let instance_capacity = 64;
+        let (id_texture, depth_texture, id_view, depth_view) =
+            Self::create_targets(device, width, height);
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Readback Buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let tracked = Tracked::new(1, 3, 2)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&instance_buffer)
+            .with_buffer(&readback_buffer)
+            .with_texture(&id_texture)
+            .with_texture(&depth_texture);
+
+        Self {
+            pipeline,
+            globals_buffer,
+            bind_group,
+            instance_buffer,
+            instance_capacity,
+            meshes: Vec::new(),
+            queued: Vec::new(),
+            id_texture,
+            depth_texture,
+            id_view,
+            depth_view,
+            readback_buffer,
+            readback: Arc::new(Mutex::new(Readback::Idle)),
+            picked: None,
+            tracked,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Instance Buffer"),
+            size: (capacity * std::mem::size_of::<PickInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> (
+        wgpu::Texture,
+        wgpu::Texture,
+        wgpu::TextureView,
+        wgpu::TextureView,
+    ) {
+        let size = wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let id_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Pick Id Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ID_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Pick Depth Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let id_view = id_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Pick Id View"),
+            ..Default::default()
+        });
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Pick Depth View"),
+            ..Default::default()
+        });
+        (id_texture, depth_texture, id_view, depth_view)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (id_texture, depth_texture, id_view, depth_view) =
+            Self::create_targets(device, width, height);
+        self.tracked.replace_texture(&self.id_texture, &id_texture);
+        self.tracked
+            .replace_texture(&self.depth_texture, &depth_texture);
+        self.id_texture = id_texture;
+        self.depth_texture = depth_texture;
+        self.id_view = id_view;
+        self.depth_view = depth_view;
+    }
+
+    /// Uploads a triangle mesh that can be drawn into the ID buffer.
+    pub fn add_mesh(
+        &mut self,
+        device: &wgpu::Device,
+        positions: &[Vec3],
+        indices: &[u32],
+    ) -> PickMeshId {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Pick Vertex Buffer"),
+            contents: bytemuck::cast_slice(positions),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Pick Index Buffer"),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let tracked = Tracked::new(0, 2, 0)
+            .with_buffer(&vertex_buffer)
+            .with_buffer(&index_buffer);
+        self.meshes.push(PickMesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            _tracked: tracked,
+        });
+        PickMeshId(self.meshes.len() - 1)
+    }
+
+    /// Queues a mesh for the next `pick`. `entity` is whatever id the app uses to find the
+    /// object again, `u32::MAX` is reserved.
+    pub fn draw(&mut self, mesh: PickMeshId, transform: Mat4, entity: u32) {
+        self.queued.push((
+            mesh,
+            PickInstance {
+                model: transform,
+                id: entity.wrapping_add(1),
+            },
+        ));
+    }
+
+    /// The entity under the cursor as of the last finished read back.
+    pub fn picked_entity(&self) -> Option<u32> {
+        self.picked
+    }
+
+    /// The entity id plus one of every pixel as of the last `pick`, zero where nothing
+    /// was drawn.
+    pub fn id_view(&self) -> &wgpu::TextureView {
+        &self.id_view
+    }
+
+    /// Collects the previous read back if it finished, then renders the queued meshes and
+    /// starts reading the id under `cursor`, in pixels from the top left. Skips reading
+    /// while the previous read back is still in flight.
+    pub fn pick(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view_proj: Mat4,
+        cursor: [u32; 2],
+    ) {
+        device.poll(wgpu::Maintain::Poll);
+        let state = *self.readback.lock().unwrap();
+        match state {
+            Readback::Pending => {}
+            Readback::Mapped => {
+                let id = {
+                    let data = self.readback_buffer.slice(..4).get_mapped_range();
+                    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+                };
+                self.readback_buffer.unmap();
+                self.picked = id.checked_sub(1);
+                *self.readback.lock().unwrap() = Readback::Idle;
+            }
+            Readback::Idle => {}
+        }
+        queue.write_buffer(
+            &self.globals_buffer,
+            0,
+            bytemuck::bytes_of(&FrameGlobals::from_view_proj(view_proj)),
+        );
+        if self.queued.len() > self.instance_capacity {
+            self.instance_capacity = self.queued.len().next_power_of_two();
+            let instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+            self.tracked
+                .replace_buffer(&self.instance_buffer, &instance_buffer);
+            self.instance_buffer = instance_buffer;
+        }
+        let instances: Vec<PickInstance> = self.queued.iter().map(|(_, i)| *i).collect();
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Pick Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Pick Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.id_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(depth::mode().clear_value()),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            for (i, (mesh, _)) in self.queued.iter().enumerate() {
+                let mesh = &self.meshes[mesh.0];
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass
+                    .set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..mesh.index_count, 0, i as u32..i as u32 + 1);
+            }
+        }
+        self.queued.clear();
+
+        // the ids are drawn regardless, for `Outline`
+        let [x, y] = cursor;
+        let in_view = x < self.id_texture.width() && y < self.id_texture.height();
+        if !in_view {
+            self.picked = None;
+        }
+        if !in_view || state == Readback::Pending {
+            queue.submit(Some(encoder.finish()));
+            return;
+        }
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.id_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: Some(1),
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        *self.readback.lock().unwrap() = Readback::Pending;
+        let readback = self.readback.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                *readback.lock().unwrap() = match result {
+                    Ok(()) => Readback::Mapped,
+                    Err(e) => {
+                        log::warn!("Failed to read back the pick buffer: {}", e);
+                        Readback::Idle
+                    }
+                };
+            });
+    }
+}
@@ -0,0 +1,418 @@
+//! Render pipelines for an app's own shaders, with the state the built-in renderers
+//! otherwise decide for themselves.
+//!
+//! `PipelineBuilder` starts from what the built-in pipelines use: `vs_main` and `fs_main`,
+//! triangle lists with back faces culled, one color target without blending and, once
+//! given a depth format, the depth test of the `depth::mode`. Stencil testing needs a
+//! format with a stencil aspect, such as the one a `DepthTarget` made `with_stencil` has;
+//! the reference value the faces compare against is set per pass with
+//! `RenderPass::set_stencil_reference`.
+//!
+//! Masking takes two pipelines: one drawing the mask's shape with `stencil_replace` and
+//! color writes off, then one drawing what's seen through it with `stencil_equal`, both
+//! with the same reference. Portals give each their own reference value.
+//!
+//! Conservative rasterization and unclipped depth need device features the context asks
+//! for when the adapter has them; `build` fails with a `PipelineError` when they're
+//! missing instead of the validation error wgpu would panic with.
+//!
+//! A pipeline only draws into targets of the format it was built for, while everything
+//! else it uses, buffers, textures, bind groups and assets, works with any target of the
+//! same device. `PipelineVariants` builds one pipeline per format as they're needed, so the
+//! same drawing code serves windows whose surfaces ended up with different formats and
+//! offscreen targets alike.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use crate::depth;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineError {
+    /// The pipeline uses options the device doesn't have the features for.
+    MissingFeatures(wgpu::Features),
+    /// Conservative rasterization only works on filled triangles.
+    ConservativeTopology(wgpu::PrimitiveTopology),
+    /// Alpha-to-coverage needs more than one sample per pixel.
+    AlphaToCoverageWithoutMsaa,
+}
+
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineError::MissingFeatures(features) => {
+                write!(f, "the device doesn't support {:?}", features)
+            }
+            PipelineError::ConservativeTopology(topology) => write!(
+                f,
+                "conservative rasterization needs triangles, not {:?}",
+                topology
+            ),
+            PipelineError::AlphaToCoverageWithoutMsaa => {
+                write!(f, "alpha-to-coverage needs a sample count above 1")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Stencil faces writing the reference value wherever they're drawn.
+pub fn stencil_replace() -> wgpu::StencilFaceState {
+    wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Always,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Replace,
+    }
+}
+
+/// Stencil faces only drawn where the stencil holds the reference value, leaving it as
+/// it is.
+pub fn stencil_equal() -> wgpu::StencilFaceState {
+    wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Equal,
+        ..wgpu::StencilFaceState::IGNORE
+    }
+}
+
+/// Creates a render pipeline from a shader module, see the module docs for the defaults.
+pub struct PipelineBuilder<'a> {
+    label: &'a str,
+    shader: &'a wgpu::ShaderModule,
+    vertex_entry: &'a str,
+    fragment_entry: Option<&'a str>,
+    bind_group_layouts: Vec<&'a wgpu::BindGroupLayout>,
+    vertex_buffers: Vec<wgpu::VertexBufferLayout<'a>>,
+    targets: Vec<Option<wgpu::ColorTargetState>>,
+    topology: wgpu::PrimitiveTopology,
+    strip_index_format: Option<wgpu::IndexFormat>,
+    cull_mode: Option<wgpu::Face>,
+    conservative: bool,
+    unclipped_depth: bool,
+    sample_count: u32,
+    alpha_to_coverage: bool,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    /// Read and write masks, applied when the stencil is used.
+    stencil_masks: (u32, u32),
+}
+
+impl<'a> PipelineBuilder<'a> {
+    /// Draws into a single target of `format`.
+    pub fn new(
+        label: &'a str,
+        shader: &'a wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            label,
+            shader,
+            vertex_entry: "vs_main",
+            fragment_entry: Some("fs_main"),
+            bind_group_layouts: Vec::new(),
+            vertex_buffers: Vec::new(),
+            targets: vec![Some(format.into())],
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            cull_mode: Some(wgpu::Face::Back),
+            conservative: false,
+            unclipped_depth: false,
+            sample_count: 1,
+            alpha_to_coverage: false,
+            depth_stencil: None,
+            stencil_masks: (!0, !0),
+        }
+    }
+
+    pub fn with_vertex_entry(mut self, entry_point: &'a str) -> Self {
+        self.vertex_entry = entry_point;
+        self
+    }
+
+    /// `None` for a pipeline only writing depth and stencil, e.g. a shadow or mask pass.
+    pub fn with_fragment_entry(mut self, entry_point: Option<&'a str>) -> Self {
+        self.fragment_entry = entry_point;
+        self
+    }
+
+    /// Appends the layout of the next bind group, starting at group 0.
+    pub fn with_bind_group_layout(mut self, layout: &'a wgpu::BindGroupLayout) -> Self {
+        self.bind_group_layouts.push(layout);
+        self
+    }
+
+    /// Appends the layout of the next vertex buffer slot, starting at slot 0.
+    pub fn with_vertex_buffer(mut self, layout: wgpu::VertexBufferLayout<'a>) -> Self {
+        self.vertex_buffers.push(layout);
+        self
+    }
+
+    /// Replaces every color target with `targets`, e.g. to blend or to add a second one.
+    pub fn with_targets(mut self, targets: &[wgpu::ColorTargetState]) -> Self {
+        self.targets = targets.iter().cloned().map(Some).collect();
+        self
+    }
+
+    /// Blends into every color target.
+    pub fn with_blend(mut self, blend: wgpu::BlendState) -> Self {
+        for target in self.targets.iter_mut().flatten() {
+            target.blend = Some(blend);
+        }
+        self
+    }
+
+    /// Which channels of every color target are written, `ColorWrites::empty()` to only
+    /// touch depth and stencil.
+    pub fn with_color_writes(mut self, write_mask: wgpu::ColorWrites) -> Self {
+        for target in self.targets.iter_mut().flatten() {
+            target.write_mask = write_mask;
+        }
+        self
+    }
+
+    /// How vertices are put together. With `PointList` every vertex is one pixel, whatever
+    /// the shader does: WGSL has no point size, so draw bigger points as quads around the
+    /// vertex. Culling only applies to triangles.
+    pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// The index format of indexed draws with `LineStrip` or `TriangleStrip`, which has to
+    /// match the index buffer's. Its largest value, `0xffff` or `0xffffffff`, then restarts
+    /// the strip. Non-indexed strips don't need it.
+    pub fn with_strip_index_format(mut self, format: wgpu::IndexFormat) -> Self {
+        self.strip_index_format = Some(format);
+        self
+    }
+
+    /// `None` draws both sides.
+    pub fn with_cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Covers every pixel a triangle touches at all instead of those whose centre it
+    /// covers, e.g. to voxelize a mesh without gaps. Needs
+    /// `Features::CONSERVATIVE_RASTERIZATION`.
+    pub fn with_conservative(mut self, conservative: bool) -> Self {
+        self.conservative = conservative;
+        self
+    }
+
+    /// Clamps depth to the near and far values instead of clipping what's beyond them,
+    /// e.g. for shadow maps, where casters behind the light's near plane are pancaked onto
+    /// it and still cast shadows. Needs `Features::DEPTH_CLIP_CONTROL`.
+    pub fn with_unclipped_depth(mut self, unclipped_depth: bool) -> Self {
+        self.unclipped_depth = unclipped_depth;
+        self
+    }
+
+    /// Samples per pixel of the color and depth targets, e.g. 4 for MSAA. Every target of
+    /// a pass has to have the same count.
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    /// Covers as many of each pixel's samples as the alpha of the first target says
+    /// instead of blending, which gives cutouts such as foliage smooth edges without
+    /// sorting them. Needs `with_sample_count` above 1.
+    pub fn with_alpha_to_coverage(mut self, enabled: bool) -> Self {
+        self.alpha_to_coverage = enabled;
+        self
+    }
+
+    /// Tests and writes depth in a target of `format` the way the built-in pipelines do.
+    pub fn with_depth(mut self, format: wgpu::TextureFormat) -> Self {
+        self.depth_stencil = Some(depth::depth_stencil(format));
+        self
+    }
+
+    /// Whether depth is written, after `with_depth`. Masks and transparent surfaces
+    /// usually only test it.
+    pub fn with_depth_write(mut self, enabled: bool) -> Self {
+        match &mut self.depth_stencil {
+            Some(state) => state.depth_write_enabled = enabled,
+            None => log::warn!("{}: depth write set without a depth format", self.label),
+        }
+        self
+    }
+
+    /// Replaces the mode's depth test, after `with_depth`. `Always` draws over everything.
+    pub fn with_depth_compare(mut self, compare: wgpu::CompareFunction) -> Self {
+        match &mut self.depth_stencil {
+            Some(state) => state.depth_compare = compare,
+            None => log::warn!("{}: depth compare set without a depth format", self.label),
+        }
+        self
+    }
+
+    /// Tests and updates the stencil the same way for front and back faces, after
+    /// `with_depth` with a format that has stencil, e.g. `stencil_replace()` to write a
+    /// mask and `stencil_equal()` to draw inside it.
+    pub fn with_stencil(self, face: wgpu::StencilFaceState) -> Self {
+        self.with_stencil_faces(face, face)
+    }
+
+    /// Like `with_stencil` with different operations per face, e.g. to count how often a
+    /// shadow volume is entered and left.
+    pub fn with_stencil_faces(
+        mut self,
+        front: wgpu::StencilFaceState,
+        back: wgpu::StencilFaceState,
+    ) -> Self {
+        match &mut self.depth_stencil {
+            Some(state) if state.format.has_stencil_aspect() => {
+                state.stencil.front = front;
+                state.stencil.back = back;
+            }
+            Some(state) => log::warn!(
+                "{}: stencil set with {:?}, which has no stencil",
+                self.label,
+                state.format
+            ),
+            None => log::warn!("{}: stencil set without a depth format", self.label),
+        }
+        self
+    }
+
+    /// Bits of the stencil value the test reads and the operations write, all by default.
+    pub fn with_stencil_masks(mut self, read_mask: u32, write_mask: u32) -> Self {
+        self.stencil_masks = (read_mask, write_mask);
+        self
+    }
+
+    pub fn build(self, device: &wgpu::Device) -> Result<wgpu::RenderPipeline, PipelineError> {
+        let mut needed = wgpu::Features::empty();
+        needed.set(
+            wgpu::Features::CONSERVATIVE_RASTERIZATION,
+            self.conservative,
+        );
+        needed.set(wgpu::Features::DEPTH_CLIP_CONTROL, self.unclipped_depth);
+        let missing = needed - device.features();
+        if !missing.is_empty() {
+            return Err(PipelineError::MissingFeatures(missing));
+        }
+        if self.conservative
+            && !matches!(
+                self.topology,
+                wgpu::PrimitiveTopology::TriangleList | wgpu::PrimitiveTopology::TriangleStrip
+            )
+        {
+            return Err(PipelineError::ConservativeTopology(self.topology));
+        }
+        if self.alpha_to_coverage && self.sample_count <= 1 {
+            return Err(PipelineError::AlphaToCoverageWithoutMsaa);
+        }
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} Layout", self.label)),
+            bind_group_layouts: &self.bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        let strip_index_format = match self.strip_index_format {
+            Some(_) if !self.topology.is_strip() => {
+                log::warn!(
+                    "{}: strip index format ignored for {:?}",
+                    self.label,
+                    self.topology
+                );
+                None
+            }
+            format => format,
+        };
+        let mut depth_stencil = self.depth_stencil;
+        if let Some(state) = &mut depth_stencil {
+            let stencil = &state.stencil;
+            // `StencilState::is_enabled` is false until the masks are set
+            if stencil.front != wgpu::StencilFaceState::IGNORE
+                || stencil.back != wgpu::StencilFaceState::IGNORE
+            {
+                (state.stencil.read_mask, state.stencil.write_mask) = self.stencil_masks;
+            }
+        }
+        Ok(
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(self.label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: self.shader,
+                    entry_point: self.vertex_entry,
+                    buffers: &self.vertex_buffers,
+                },
+                fragment: self.fragment_entry.map(|entry_point| wgpu::FragmentState {
+                    module: self.shader,
+                    entry_point,
+                    targets: &self.targets,
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: self.topology,
+                    strip_index_format,
+                    cull_mode: self.cull_mode,
+                    unclipped_depth: self.unclipped_depth,
+                    conservative: self.conservative,
+                    ..Default::default()
+                },
+                depth_stencil,
+                multisample: wgpu::MultisampleState {
+                    count: self.sample_count,
+                    alpha_to_coverage_enabled: self.alpha_to_coverage,
+                    ..Default::default()
+                },
+                multiview: None,
+            }),
+        )
+    }
+}
+
+type BuildPipeline =
+    Box<dyn Fn(&wgpu::Device, wgpu::TextureFormat) -> Result<wgpu::RenderPipeline, PipelineError>>;
+
+/// A pipeline per target format, built by a closure the first time each format is asked
+/// for, see the module docs.
+pub struct PipelineVariants {
+    build: BuildPipeline,
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl PipelineVariants {
+    /// `build` makes the pipeline for a format, typically a `PipelineBuilder` with the
+    /// shader module moved into the closure.
+    pub fn new(
+        build: impl Fn(&wgpu::Device, wgpu::TextureFormat) -> Result<wgpu::RenderPipeline, PipelineError>
+            + 'static,
+    ) -> Self {
+        Self {
+            build: Box::new(build),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// The pipeline drawing into `format`, e.g. `Frame::format`, built now if it's the
+    /// first time. A failed build is tried again next time.
+    pub fn get(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Result<&wgpu::RenderPipeline, PipelineError> {
+        match self.pipelines.entry(format) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert((self.build)(device, format)?)),
+        }
+    }
+
+    /// Formats built so far.
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Drops the pipelines, e.g. after the shader changed, so they're built again.
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+}
@@ -0,0 +1,235 @@
+//! The scene mirrored about a plane, drawn into a texture for water and mirror materials.
+//!
+//! The mirrored view is drawn with the camera's own renderers: prepare them with
+//! `PlanarReflection::view_proj` and `eye` instead of the camera's, and draw in the pass
+//! from `begin_pass`. The projection's near plane is the mirror plane itself, so nothing
+//! behind the mirror ends up in the reflection. The image is also flipped horizontally,
+//! which keeps triangles' winding and so the renderers' culling as it is; materials
+//! sampling it use `REFLECTION_SHADER`, which flips it back.
+
+use crate::camera::Camera;
+use crate::depth;
+use crate::math::{Mat4, Vec3, Vec4};
+use crate::stats::Tracked;
+use crate::texture::Texture;
+
+/// `reflection_uv` gives the coordinates to sample a planar reflection at for a point,
+/// from its clip position in the main view.
+pub const REFLECTION_SHADER: &str = r#"
+fn reflection_uv(clip: vec4<f32>) -> vec2<f32> {
+    let ndc = clip.xy / clip.w;
+    // the reflection is drawn flipped horizontally
+    return vec2<f32>(0.5 - ndc.x * 0.5, 0.5 - ndc.y * 0.5);
+}
+"#;
+
+/// The points `p` where `normal.dot(p) + d` is 0.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Plane {
+    /// Unit length.
+    pub normal: Vec3,
+    pub d: f32,
+}
+
+impl Plane {
+    /// The plane through `point` facing `normal`.
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        let normal = normal.normalize();
+        Self {
+            normal,
+            d: -normal.dot(point),
+        }
+    }
+
+    /// Positive on the side `normal` points to.
+    pub fn distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+
+    pub fn reflect_point(&self, point: Vec3) -> Vec3 {
+        point - self.normal * (2.0 * self.distance(point))
+    }
+
+    /// Mirrors points about the plane.
+    pub fn reflection(&self) -> Mat4 {
+        let n = self.normal;
+        let column = |axis: Vec3| (axis - n * (2.0 * n.dot(axis))).extend(0.0);
+        Mat4::from_cols(
+            column(Vec3::X),
+            column(Vec3::Y),
+            column(Vec3::Z),
+            (n * (-2.0 * self.d)).extend(1.0),
+        )
+    }
+
+    fn to_vec4(self) -> Vec4 {
+        self.normal.extend(self.d)
+    }
+}
+
+pub struct PlanarReflection {
+    pub plane: Plane,
+    /// Lets geometry this far behind the mirror plane into the reflection, hiding gaps
+    /// along the edges of things standing in the water.
+    pub clip_offset: f32,
+    color: Texture,
+    depth: wgpu::TextureView,
+    format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    _tracked: Tracked,
+}
+
+impl PlanarReflection {
+    /// A reflection about `plane` drawn `width` by `height` into `format`, with depth in
+    /// `depth_format`. Half the window's size is usually enough.
+    pub fn new(
+        device: &wgpu::Device,
+        plane: Plane,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (color, depth, tracked) =
+            Self::create_targets(device, format, depth_format, width, height);
+        Self {
+            plane,
+            clip_offset: 0.0,
+            color,
+            depth,
+            format,
+            depth_format,
+            _tracked: tracked,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> (Texture, wgpu::TextureView, Tracked) {
+        let (width, height) = (width.max(1), height.max(1));
+        let color = Texture::builder("Planar Reflection")
+            .with_format(format)
+            .with_usage(wgpu::TextureUsages::RENDER_ATTACHMENT)
+            .build_empty(device, width, height);
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Planar Reflection Depth"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: depth_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let tracked = Tracked::new(0, 0, 1).with_texture(&depth);
+        let depth = depth.create_view(&wgpu::TextureViewDescriptor::default());
+        (color, depth, tracked)
+    }
+
+    /// Recreates the targets at a new size, e.g. when the window is resized.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if self.color.width() == width.max(1) && self.color.height() == height.max(1) {
+            return;
+        }
+        let (color, depth, tracked) =
+            Self::create_targets(device, self.format, self.depth_format, width, height);
+        self.color = color;
+        self.depth = depth;
+        self._tracked = tracked;
+    }
+
+    /// The plane with its normal towards `eye`, the side that is reflected.
+    fn facing_plane(&self, eye: Vec3) -> Plane {
+        if self.plane.distance(eye) < 0.0 {
+            Plane {
+                normal: -self.plane.normal,
+                d: -self.plane.d,
+            }
+        } else {
+            self.plane
+        }
+    }
+
+    /// Where `camera` sees itself in the mirror, for renderers lighting with the eye
+    /// position.
+    pub fn eye(&self, camera: &Camera) -> Vec3 {
+        self.plane.reflect_point(camera.eye)
+    }
+
+    /// The mirrored view projection of `camera`, clipped at the plane.
+    pub fn view_proj(&self, camera: &Camera) -> Mat4 {
+        let plane = self.facing_plane(camera.eye);
+        let view = camera.view() * plane.reflection();
+        let mut projection = camera.projection();
+
+        // Replace the near plane with the mirror plane (Lengyel's oblique frustum, for depth
+        // from 0 to 1), scaled so the far plane still passes through the far corner it
+        // points to. Reversed, the near plane is where depth reaches w rather than 0, and
+        // the far corner is at infinity.
+        let clip = Plane {
+            d: plane.d + self.clip_offset,
+            ..plane
+        };
+        let clip = view.inverse().transpose().mul_vec4(clip.to_vec4());
+        let reversed = depth::mode().is_reversed();
+        let far = if reversed { 0.0 } else { 1.0 };
+        let corner =
+            projection
+                .inverse()
+                .mul_vec4(Vec4::new(clip.x.signum(), clip.y.signum(), far, 1.0));
+        let scale = projection.row(3).dot(corner) / clip.dot(corner);
+        let row = if reversed {
+            projection.row(3) - clip * scale
+        } else {
+            clip * scale
+        };
+        for (column, value) in projection.cols.iter_mut().zip(row.to_array()) {
+            column[2] = value;
+        }
+
+        Mat4::scale(Vec3::new(-1.0, 1.0, 1.0)) * projection * view
+    }
+
+    /// Begins a pass into the reflection, clearing color to `clear` if given and depth
+    /// to the far value of the `depth::mode`.
+    pub fn begin_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        clear: Option<wgpu::Color>,
+    ) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Planar Reflection Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.color.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(depth::mode().clear_value()),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+
+    /// The reflection, to sample with `reflection_uv`.
+    pub fn texture(&self) -> &Texture {
+        &self.color
+    }
+}
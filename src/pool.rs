@@ -0,0 +1,332 @@
+//! Recycling of short lived buffers and textures, such as post processing targets and
+//! per frame uploads.
+//!
+//! Acquired resources are handed out as shared handles. Once every handle to one is
+//! dropped it goes back to the pool, and the next request for a matching one reuses it
+//! instead of allocating. Resources left unused for `max_idle_frames` are freed.
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::stats::{texture_bytes, Tracked};
+
+/// Buffers are pooled in power of two sizes from this up.
+const MIN_BUFFER_SIZE: wgpu::BufferAddress = 256;
+
+/// A buffer on loan from a `ResourcePool`, possibly larger than requested.
+#[derive(Clone, Debug)]
+pub struct PooledBuffer(Arc<wgpu::Buffer>);
+
+impl Deref for PooledBuffer {
+    type Target = wgpu::Buffer;
+
+    fn deref(&self) -> &wgpu::Buffer {
+        &self.0
+    }
+}
+
+/// A texture on loan from a `ResourcePool`, with a view of all of it.
+#[derive(Clone, Debug)]
+pub struct PooledTexture(Arc<TextureAndView>);
+
+#[derive(Debug)]
+struct TextureAndView {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl PooledTexture {
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.0.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.0.view
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub buffers_created: usize,
+    pub buffers_reused: usize,
+    pub textures_created: usize,
+    pub textures_reused: usize,
+    /// Resources the pool holds, lent out or idle.
+    pub buffers: usize,
+    pub textures: usize,
+    /// Estimated size of everything the pool holds.
+    pub bytes: u64,
+    /// Highest `bytes` has been.
+    pub peak_bytes: u64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct TextureKey {
+    size: wgpu::Extent3d,
+    mip_level_count: u32,
+    sample_count: u32,
+    dimension: wgpu::TextureDimension,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+}
+
+struct Entry<T, K> {
+    resource: Arc<T>,
+    key: K,
+    bytes: u64,
+    last_used: u64,
+    _tracked: Tracked,
+}
+
+/// The pooled resources of one kind, lent out while a handle shares the `Arc`. A request
+/// reuses an idle one with the same key.
+struct Entries<T, K> {
+    entries: Vec<Entry<T, K>>,
+}
+
+impl<T, K: PartialEq> Entries<T, K> {
+    fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// An idle resource with `key`, lent out from `frame` on.
+    fn reuse(&mut self, key: &K, frame: u64) -> Option<Arc<T>> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| Arc::strong_count(&entry.resource) == 1 && entry.key == *key)?;
+        entry.last_used = frame;
+        Some(entry.resource.clone())
+    }
+
+    fn add(&mut self, resource: Arc<T>, key: K, bytes: u64, frame: u64, tracked: Tracked) {
+        self.entries.push(Entry {
+            resource,
+            key,
+            bytes,
+            last_used: frame,
+            _tracked: tracked,
+        });
+    }
+
+    /// Counts what's lent out as used in `frame` and frees what was last used before
+    /// `oldest`. Returns the bytes freed.
+    fn expire(&mut self, frame: u64, oldest: u64) -> u64 {
+        let mut freed = 0;
+        self.entries.retain_mut(|entry| {
+            if Arc::strong_count(&entry.resource) > 1 {
+                entry.last_used = frame;
+            }
+            let keep = entry.last_used >= oldest;
+            if !keep {
+                freed += entry.bytes;
+            }
+            keep
+        });
+        freed
+    }
+
+    /// Frees everything idle.
+    fn trim(&mut self) {
+        self.entries
+            .retain(|entry| Arc::strong_count(&entry.resource) > 1);
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.bytes).sum()
+    }
+}
+
+pub struct ResourcePool {
+    buffers: Entries<wgpu::Buffer, (wgpu::BufferAddress, wgpu::BufferUsages)>,
+    textures: Entries<TextureAndView, TextureKey>,
+    frame: u64,
+    stats: PoolStats,
+    /// Frames an idle resource is kept around for before it is freed.
+    pub max_idle_frames: u64,
+}
+
+impl Default for ResourcePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourcePool {
+    pub fn new() -> Self {
+        Self {
+            buffers: Entries::new(),
+            textures: Entries::new(),
+            frame: 0,
+            stats: PoolStats::default(),
+            max_idle_frames: 3,
+        }
+    }
+
+    /// A buffer of at least `size` bytes with exactly `usage`. Its contents are whatever
+    /// the last user left in it.
+    pub fn acquire_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        size: wgpu::BufferAddress,
+        usage: wgpu::BufferUsages,
+    ) -> PooledBuffer {
+        let size = size.next_power_of_two().max(MIN_BUFFER_SIZE);
+        if let Some(buffer) = self.buffers.reuse(&(size, usage), self.frame) {
+            self.stats.buffers_reused += 1;
+            return PooledBuffer(buffer);
+        }
+
+        let buffer = Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pooled Buffer"),
+            size,
+            usage,
+            mapped_at_creation: false,
+        }));
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+        self.buffers
+            .add(buffer.clone(), (size, usage), size, self.frame, tracked);
+        self.stats.buffers_created += 1;
+        self.stats.buffers += 1;
+        self.add_bytes(size);
+        PooledBuffer(buffer)
+    }
+
+    /// A texture matching `desc` in everything but its label.
+    pub fn acquire_texture(
+        &mut self,
+        device: &wgpu::Device,
+        desc: &wgpu::TextureDescriptor,
+    ) -> PooledTexture {
+        let key = TextureKey {
+            size: desc.size,
+            mip_level_count: desc.mip_level_count,
+            sample_count: desc.sample_count,
+            dimension: desc.dimension,
+            format: desc.format,
+            usage: desc.usage,
+        };
+        if let Some(texture) = self.textures.reuse(&key, self.frame) {
+            self.stats.textures_reused += 1;
+            return PooledTexture(texture);
+        }
+
+        let texture = device.create_texture(desc);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: desc.label,
+            ..Default::default()
+        });
+        let bytes = texture_bytes(&texture);
+        let tracked = Tracked::new(0, 0, 1).with_texture(&texture);
+        let texture = Arc::new(TextureAndView { texture, view });
+        self.textures
+            .add(texture.clone(), key, bytes, self.frame, tracked);
+        self.stats.textures_created += 1;
+        self.stats.textures += 1;
+        self.add_bytes(bytes);
+        PooledTexture(texture)
+    }
+
+    /// A 2D render target, as used between post processing passes.
+    pub fn acquire_render_target(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> PooledTexture {
+        self.acquire_texture(
+            device,
+            &wgpu::TextureDescriptor {
+                label: Some("Pooled Render Target"),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+        )
+    }
+
+    fn add_bytes(&mut self, bytes: u64) {
+        self.stats.bytes += bytes;
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.stats.bytes);
+    }
+
+    /// Call once per frame. Anything lent out counts as used this frame, and what has sat
+    /// idle for longer than `max_idle_frames` is freed.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        let oldest = frame.saturating_sub(self.max_idle_frames);
+        let freed = self.buffers.expire(frame, oldest) + self.textures.expire(frame, oldest);
+        self.stats.bytes -= freed;
+        self.stats.buffers = self.buffers.len();
+        self.stats.textures = self.textures.len();
+        self.frame += 1;
+    }
+
+    /// Frees every idle resource now.
+    pub fn trim(&mut self) {
+        self.buffers.trim();
+        self.textures.trim();
+        self.stats.buffers = self.buffers.len();
+        self.stats.textures = self.textures.len();
+        self.stats.bytes = self.buffers.bytes() + self.textures.bytes();
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(entries: &mut Entries<&'static str, u32>, name: &'static str, key: u32, frame: u64) {
+        entries.add(Arc::new(name), key, 100, frame, Tracked::new(0, 0, 0));
+    }
+
+    #[test]
+    fn reuses_idle_matching_resources() {
+        let mut entries = Entries::new();
+        add(&mut entries, "a", 1, 0);
+        add(&mut entries, "b", 2, 0);
+        let lent = entries.reuse(&1, 0).unwrap();
+        assert_eq!(*lent, "a");
+        // lent out, and nothing else has the key
+        assert!(entries.reuse(&1, 0).is_none());
+        drop(lent);
+        assert_eq!(entries.reuse(&1, 1).as_deref(), Some(&"a"));
+        assert_eq!(entries.reuse(&2, 1).as_deref(), Some(&"b"));
+        assert!(entries.reuse(&3, 1).is_none());
+    }
+
+    #[test]
+    fn frees_what_sat_idle() {
+        let mut entries = Entries::new();
+        add(&mut entries, "idle", 1, 0);
+        add(&mut entries, "lent", 2, 0);
+        let lent = entries.reuse(&2, 0).unwrap();
+        assert_eq!(entries.expire(5, 2), 100);
+        assert_eq!(entries.len(), 1);
+        drop(lent);
+        // used in frame 5 when it was last lent out, so it stays until then
+        assert_eq!(entries.expire(6, 5), 0);
+        entries.trim();
+        assert_eq!((entries.len(), entries.bytes()), (0, 0));
+    }
+}
@@ -0,0 +1,451 @@
+//! Post-processing: full screen effects applied one after another to the finished frame.
+//!
+//! The scene is drawn into `PostChain::input` instead of the surface, then `run` passes it
+//! through every enabled `PostEffect` in order, ping-ponging between two textures the size
+//! of the frame, the last effect drawing into the surface.
+//!
+//! Most effects are one full screen draw reading the previous result, which `PostPipeline`
+//! does: prepend `POST_SHADER` to a shader with a `Params` struct and an `fs_main`, and
+//! `draw` binds the source, the params and any textures of the effect's own.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::bind_group_cache::BindGroupCache;
+use crate::blit::Blitter;
+use crate::stats::Tracked;
+
+/// The full screen triangle and group 0 of a `PostPipeline`: the source texture, its
+/// sampler and `params`, whose `Params` struct the effect declares.
+pub const POST_SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> params: Params;
+"#;
+
+/// What an effect gets besides its source and target.
+pub struct PostFrame<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    /// Format of the chain's textures, and so of every effect's source.
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Seconds since the last frame, as given to `PostChain::update`.
+    pub dt: f32,
+    /// Seconds of `dt` added up, for animated effects.
+    pub time: f32,
+}
+
+/// A texture an effect draws into.
+#[derive(Copy, Clone)]
+pub struct PostTarget<'a> {
+    pub view: &'a wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+}
+
+/// One step of a `PostChain`.
+pub trait PostEffect: Any {
+    /// For debug groups.
+    fn label(&self) -> &str;
+
+    /// Called with the chain's new size, for effects with textures the size of the frame.
+    fn resize(&mut self, _device: &wgpu::Device, _width: u32, _height: u32) {}
+
+    /// Records drawing `source` with the effect applied over the whole of `target`.
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    );
+}
+
+struct Slot {
+    effect: Box<dyn PostEffect>,
+    enabled: bool,
+}
+
+/// The textures the frame goes through on its way to the surface, and the effects
+/// applied on the way.
+pub struct PostChain {
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    targets: [(wgpu::Texture, wgpu::TextureView); 2],
+    effects: Vec<Slot>,
+    blitter: Blitter,
+    dt: f32,
+    time: f32,
+    tracked: Tracked,
+}
+
+impl PostChain {
+    /// A chain of `width` by `height` textures in `format`. A float format such as
+    /// `Rgba16Float` keeps values above 1 for effects working on HDR.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (targets, tracked) = Self::create_targets(device, format, width, height);
+        Self {
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            targets,
+            effects: Vec::new(),
+            blitter: Blitter::new(device),
+            dt: 0.0,
+            time: 0.0,
+            tracked,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> ([(wgpu::Texture, wgpu::TextureView); 2], Tracked) {
+        let create = || {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Post Chain Target"),
+                size: wgpu::Extent3d {
+                    width: width.max(1),
+                    height: height.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+        let targets = [create(), create()];
+        let tracked = Tracked::new(0, 0, 2)
+            .with_texture(&targets[0].0)
+            .with_texture(&targets[1].0);
+        (targets, tracked)
+    }
+
+    pub fn with_effect(mut self, effect: impl PostEffect) -> Self {
+        self.push(effect);
+        self
+    }
+
+    /// Adds an effect after the others, returning its index.
+    pub fn push(&mut self, effect: impl PostEffect) -> usize {
+        self.effects.push(Slot {
+            effect: Box::new(effect),
+            enabled: true,
+        });
+        self.effects.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.effects[index].enabled = enabled;
+    }
+
+    pub fn is_enabled(&self, index: usize) -> bool {
+        self.effects[index].enabled
+    }
+
+    /// The first effect of type `T`, to change its settings.
+    pub fn effect<T: PostEffect>(&self) -> Option<&T> {
+        self.effects
+            .iter()
+            .find_map(|slot| (slot.effect.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    pub fn effect_mut<T: PostEffect>(&mut self) -> Option<&mut T> {
+        self.effects
+            .iter_mut()
+            .find_map(|slot| (slot.effect.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    /// The texture to draw the scene into.
+    pub fn input(&self) -> &wgpu::TextureView {
+        &self.targets[0].1
+    }
+
+    /// Advances the time effects animate and adapt with, call once per frame with the
+    /// app's `dt`.
+    pub fn update(&mut self, dt: f32) {
+        self.dt = dt;
+        self.time += dt;
+    }
+
+    /// Recreates the textures at a new size, e.g. when the window is resized.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        let (targets, tracked) = Self::create_targets(device, self.format, width, height);
+        self.targets = targets;
+        self.tracked = tracked;
+        self.width = width;
+        self.height = height;
+        for slot in &mut self.effects {
+            slot.effect.resize(device, width, height);
+        }
+    }
+
+    /// Records the enabled effects, the last drawing into `output`, a texture the size of
+    /// the chain. With none enabled the input is copied over.
+    pub fn run(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        output: PostTarget,
+    ) {
+        let frame = PostFrame {
+            device,
+            queue,
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            dt: self.dt,
+            time: self.time,
+        };
+        encoder.push_debug_group("Post Chain");
+        let enabled = self.effects.iter().filter(|slot| slot.enabled).count();
+        if enabled == 0 {
+            self.blitter.blit(
+                device,
+                encoder,
+                &self.targets[0].1,
+                output.view,
+                output.format,
+                wgpu::FilterMode::Nearest,
+            );
+            self.blitter.end_frame();
+        }
+        let mut current = 0;
+        let mut remaining = enabled;
+        for slot in self.effects.iter_mut().filter(|slot| slot.enabled) {
+            remaining -= 1;
+            let target = if remaining == 0 {
+                output
+            } else {
+                PostTarget {
+                    view: &self.targets[1 - current].1,
+                    format: self.format,
+                }
+            };
+            encoder.push_debug_group(slot.effect.label());
+            slot.effect
+                .apply(&frame, encoder, &self.targets[current].1, target);
+            encoder.pop_debug_group();
+            current = 1 - current;
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+/// A full screen draw of an effect's shader, see `POST_SHADER`. Keeps a pipeline per
+/// target format.
+pub struct PostPipeline {
+    label: String,
+    shader: wgpu::ShaderModule,
+    source_layout: wgpu::BindGroupLayout,
+    layout: wgpu::PipelineLayout,
+    sampler: wgpu::Sampler,
+    params: wgpu::Buffer,
+    pipelines: HashMap<wgpu::TextureFormat, (wgpu::RenderPipeline, Tracked)>,
+    bind_groups: BindGroupCache,
+    _tracked: Tracked,
+}
+
+impl PostPipeline {
+    /// `shader` is the effect's part, without `POST_SHADER`. Its `Params` are
+    /// `params_size` bytes, and `layouts` are the groups it binds after group 0.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        shader: &str,
+        params_size: u64,
+        layouts: &[&wgpu::BindGroupLayout],
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&format!("{} Shader", label)),
+            source: wgpu::ShaderSource::Wgsl(format!("{}{}", POST_SHADER, shader).into()),
+        });
+        let source_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("{} Source Layout", label)),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let mut bind_group_layouts = vec![&source_layout];
+        bind_group_layouts.extend_from_slice(layouts);
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} Pipeline Layout", label)),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&format!("{} Sampler", label)),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Params", label)),
+            size: params_size.next_multiple_of(16),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&params);
+        Self {
+            label: label.to_string(),
+            shader,
+            source_layout,
+            layout,
+            sampler,
+            params,
+            pipelines: HashMap::new(),
+            bind_groups: BindGroupCache::new(),
+            _tracked: tracked,
+        }
+    }
+
+    /// Records drawing over `target` with `params` uploaded, `bind_groups` bound from group
+    /// 1 on. Draw once per frame, `params` are shared by every draw.
+    pub fn draw(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+        params: &impl bytemuck::Pod,
+        bind_groups: &[&wgpu::BindGroup],
+    ) {
+        let device = frame.device;
+        frame
+            .queue
+            .write_buffer(&self.params, 0, bytemuck::bytes_of(params));
+        let (pipeline, _) = self.pipelines.entry(target.format).or_insert_with(|| {
+            let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&format!("{} Pipeline", self.label)),
+                layout: Some(&self.layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(target.format.into())],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+            (pipeline, Tracked::new(1, 0, 0))
+        });
+        self.bind_groups.end_frame();
+        let source_bind_group: Arc<wgpu::BindGroup> = self.bind_groups.get(
+            device,
+            &format!("{} Source Bind Group", self.label),
+            &self.source_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.params.as_entire_binding(),
+                },
+            ],
+        );
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&format!("{} Pass", self.label)),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &source_bind_group, &[]);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            render_pass.set_bind_group(index as u32 + 1, bind_group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,272 @@
+//! Single pass post effects for a `PostChain`: vignette, chromatic aberration and film
+//! grain.
+//!
+//! Each keeps its settings in a `pub params` struct laid out like the shader's uniform,
+//! uploaded as is every frame.
+
+use crate::post::{PostEffect, PostFrame, PostPipeline, PostTarget};
+
+const VIGNETTE_SHADER: &str = r#"
+struct Params {
+    color: vec4<f32>,
+    intensity: f32,
+    radius: f32,
+    smoothness: f32,
+    roundness: f32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    let size = vec2<f32>(textureDimensions(source));
+    let stretch = vec2<f32>(mix(1.0, size.x / size.y, params.roundness), 1.0);
+    // 1 in the corners when round
+    let from_centre = length((in.uv - 0.5) * stretch) * 1.41421356;
+    let visible = smoothstep(params.radius, params.radius - params.smoothness, from_centre);
+    let amount = (1.0 - visible) * params.intensity;
+    return vec4<f32>(mix(color.rgb, params.color.rgb, amount), color.a);
+}
+"#;
+
+const CHROMATIC_ABERRATION_SHADER: &str = r#"
+struct Params {
+    intensity: f32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // red and blue pushed apart along the line from the centre, more towards the edges
+    let offset = (in.uv - 0.5) * params.intensity;
+    let red = textureSample(source, source_sampler, in.uv - offset).r;
+    let color = textureSample(source, source_sampler, in.uv);
+    let blue = textureSample(source, source_sampler, in.uv + offset).b;
+    return vec4<f32>(red, color.g, blue, color.a);
+}
+"#;
+
+const FILM_GRAIN_SHADER: &str = r#"
+struct Params {
+    intensity: f32,
+    size: f32,
+    luminance_response: f32,
+    time: f32,
+};
+
+fn hash(p: vec3<f32>) -> f32 {
+    var q = fract(p * vec3<f32>(0.1031, 0.1030, 0.0973));
+    q = q + dot(q, q.yxz + 33.33);
+    return fract((q.x + q.y) * q.z);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    let cell = floor(in.position.xy / max(params.size, 1.0));
+    // a new pattern 24 times a second, like film
+    let noise = hash(vec3<f32>(cell, floor(params.time * 24.0))) * 2.0 - 1.0;
+    let luminance = dot(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(0.2126, 0.7152, 0.0722));
+    let grain = noise * params.intensity * mix(1.0, 1.0 - luminance, params.luminance_response);
+    return vec4<f32>(max(color.rgb + vec3<f32>(grain), vec3<f32>(0.0)), color.a);
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VignetteParams {
+    /// What the edges fade to.
+    pub color: [f32; 4],
+    /// How much of the color the corners get, from 0 to 1.
+    pub intensity: f32,
+    /// Distance from the centre the fade ends at, 1 for the corners.
+    pub radius: f32,
+    /// Width of the fade.
+    pub smoothness: f32,
+    /// 1 for a circle, 0 for an ellipse stretched to the frame's shape.
+    pub roundness: f32,
+}
+unsafe impl bytemuck::Pod for VignetteParams {}
+unsafe impl bytemuck::Zeroable for VignetteParams {}
+
+impl Default for VignetteParams {
+    fn default() -> Self {
+        Self {
+            color: [0.0, 0.0, 0.0, 1.0],
+            intensity: 0.6,
+            radius: 1.1,
+            smoothness: 0.6,
+            roundness: 1.0,
+        }
+    }
+}
+
+/// Darkens the edges of the frame.
+pub struct Vignette {
+    pub params: VignetteParams,
+    pass: PostPipeline,
+}
+
+impl Vignette {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            params: VignetteParams::default(),
+            pass: PostPipeline::new(
+                device,
+                "Vignette",
+                VIGNETTE_SHADER,
+                std::mem::size_of::<VignetteParams>() as u64,
+                &[],
+            ),
+        }
+    }
+
+    pub fn with_params(mut self, params: VignetteParams) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+impl PostEffect for Vignette {
+    fn label(&self) -> &str {
+        "Vignette"
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    ) {
+        let params = self.params;
+        self.pass.draw(frame, encoder, source, target, &params, &[]);
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChromaticAberrationParams {
+    /// How far apart the red and blue are at the edges, as a share of the frame.
+    pub intensity: f32,
+}
+unsafe impl bytemuck::Pod for ChromaticAberrationParams {}
+unsafe impl bytemuck::Zeroable for ChromaticAberrationParams {}
+
+impl Default for ChromaticAberrationParams {
+    fn default() -> Self {
+        Self { intensity: 0.01 }
+    }
+}
+
+/// Splits red and blue towards the edges of the frame, like a cheap lens.
+pub struct ChromaticAberration {
+    pub params: ChromaticAberrationParams,
+    pass: PostPipeline,
+}
+
+impl ChromaticAberration {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            params: ChromaticAberrationParams::default(),
+            pass: PostPipeline::new(
+                device,
+                "Chromatic Aberration",
+                CHROMATIC_ABERRATION_SHADER,
+                std::mem::size_of::<ChromaticAberrationParams>() as u64,
+                &[],
+            ),
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.params.intensity = intensity;
+        self
+    }
+}
+
+impl PostEffect for ChromaticAberration {
+    fn label(&self) -> &str {
+        "Chromatic Aberration"
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    ) {
+        let params = self.params;
+        self.pass.draw(frame, encoder, source, target, &params, &[]);
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FilmGrainParams {
+    /// Strength of the noise added.
+    pub intensity: f32,
+    /// Size of a grain in pixels.
+    pub size: f32,
+    /// How much less grain bright areas get, from 0 to 1.
+    pub luminance_response: f32,
+    /// Seconds, set from the chain's time every frame.
+    pub time: f32,
+}
+unsafe impl bytemuck::Pod for FilmGrainParams {}
+unsafe impl bytemuck::Zeroable for FilmGrainParams {}
+
+impl Default for FilmGrainParams {
+    fn default() -> Self {
+        Self {
+            intensity: 0.05,
+            size: 1.5,
+            luminance_response: 0.8,
+            time: 0.0,
+        }
+    }
+}
+
+/// Animated noise over the frame. Needs `PostChain::update` to move.
+pub struct FilmGrain {
+    pub params: FilmGrainParams,
+    pass: PostPipeline,
+}
+
+impl FilmGrain {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            params: FilmGrainParams::default(),
+            pass: PostPipeline::new(
+                device,
+                "Film Grain",
+                FILM_GRAIN_SHADER,
+                std::mem::size_of::<FilmGrainParams>() as u64,
+                &[],
+            ),
+        }
+    }
+
+    pub fn with_params(mut self, params: FilmGrainParams) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+impl PostEffect for FilmGrain {
+    fn label(&self) -> &str {
+        "Film Grain"
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    ) {
+        // wrapped so the grain keeps its precision after hours
+        self.params.time = frame.time % 3600.0;
+        let params = self.params;
+        self.pass.draw(frame, encoder, source, target, &params, &[]);
+    }
+}
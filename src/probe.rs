@@ -0,0 +1,194 @@
+//! Reflection probes: the scene rendered into a cubemap from a point, for materials to
+//! sample reflections from.
+//!
+//! The six faces are drawn either one at a time with a `Camera` each, so any renderer can
+//! draw them, or all at once with a `Multiview` for shaders written for it. A probe
+//! redraws every frame or only when asked to, see `ProbeRefresh`.
+
+use crate::camera::Camera;
+use crate::math::{Mat4, Vec3};
+use crate::multiview::{Multiview, MultiviewTarget, ViewPass};
+
+/// Cubemaps are left handed, so the probe is sampled with z flipped. `probe_direction`
+/// turns a world direction into the one to sample `texture_cube`s of a probe with.
+pub const PROBE_SHADER: &str = r#"
+fn probe_direction(direction: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(direction.x, direction.y, -direction.z);
+}
+"#;
+
+/// The direction each layer of the cubemap looks in and its up, +X, -X, +Y, -Y, +Z, -Z
+/// in cubemap terms. With z flipped, the faces come out unmirrored with right handed
+/// cameras.
+const FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::new(-1.0, 0.0, 0.0), Vec3::Y),
+    (Vec3::Y, Vec3::Z),
+    (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+    (Vec3::new(0.0, 0.0, -1.0), Vec3::Y),
+    (Vec3::Z, Vec3::Y),
+];
+
+/// When a `ReflectionProbe` redraws its cubemap.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ProbeRefresh {
+    /// For probes near things that move.
+    EveryFrame,
+    /// After `ReflectionProbe::request_update`, and once after it's created.
+    #[default]
+    OnDemand,
+}
+
+pub struct ReflectionProbe {
+    pub position: Vec3,
+    pub znear: f32,
+    pub zfar: f32,
+    pub refresh: ProbeRefresh,
+    dirty: bool,
+    target: MultiviewTarget,
+    multiview: Multiview,
+    cube_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl ReflectionProbe {
+    /// A probe at `position` whose faces are `size` pixels square in `format`, with depth
+    /// in `depth_format`.
+    pub fn new(
+        device: &wgpu::Device,
+        position: Vec3,
+        size: u32,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+    ) -> Self {
+        let target = MultiviewTarget::new(device, format, depth_format, size, size, 6);
+        let cube_view = target.texture().create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Reflection Probe Cube View"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Reflection Probe Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            position,
+            znear: 0.05,
+            zfar: 100.0,
+            refresh: ProbeRefresh::default(),
+            dirty: true,
+            target,
+            multiview: Multiview::new(device, 6),
+            cube_view,
+            sampler,
+        }
+    }
+
+    pub fn with_refresh(mut self, refresh: ProbeRefresh) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Redraws the cubemap the next time it's updated.
+    pub fn request_update(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Whether the next `update` redraws the cubemap.
+    pub fn needs_update(&self) -> bool {
+        self.dirty || self.refresh == ProbeRefresh::EveryFrame
+    }
+
+    /// The camera of each face, 90 degrees wide and square.
+    pub fn cameras(&self) -> [Camera; 6] {
+        FACES.map(|(forward, up)| Camera {
+            eye: self.position,
+            target: self.position + forward,
+            up,
+            aspect: 1.0,
+            fovy: 90f32.to_radians(),
+            znear: self.znear,
+            zfar: self.zfar,
+        })
+    }
+
+    pub fn view_projs(&self) -> [Mat4; 6] {
+        self.cameras().map(|camera| camera.view_proj())
+    }
+
+    /// Redraws the faces one at a time if `needs_update`, returning whether it did. `draw`
+    /// is called with each face's index, camera and pass into it, and should prepare its
+    /// renderers with the camera and begin the pass with `ViewPass::begin`. Each face is
+    /// submitted before the next, so the renderers' uniforms can be rewritten.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mut draw: impl FnMut(usize, &Camera, &mut wgpu::CommandEncoder, &ViewPass),
+    ) -> bool {
+        if !self.needs_update() {
+            return false;
+        }
+        for (face, camera) in self.cameras().iter().enumerate() {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Reflection Probe Encoder"),
+            });
+            encoder.push_debug_group(&format!("Reflection Probe Face {}", face));
+            draw(
+                face,
+                camera,
+                &mut encoder,
+                &self.target.layer_pass(face as u32),
+            );
+            encoder.pop_debug_group();
+            queue.submit(Some(encoder.finish()));
+        }
+        self.dirty = false;
+        true
+    }
+
+    /// Like `update`, drawing every face at once with `multiview`, whose views are set to
+    /// the faces'. `draw` is called once per pass `Multiview::draw` needs, with the
+    /// `Multiview` to bind at group 0.
+    pub fn update_multiview(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        mut draw: impl FnMut(&mut wgpu::CommandEncoder, &ViewPass, &Multiview),
+    ) -> bool {
+        if !self.needs_update() {
+            return false;
+        }
+        self.multiview.set_views(&self.view_projs());
+        self.multiview.prepare(device, queue);
+        encoder.push_debug_group("Reflection Probe");
+        let multiview = &self.multiview;
+        multiview.draw(encoder, &self.target, |encoder, pass| {
+            draw(encoder, pass, multiview)
+        });
+        encoder.pop_debug_group();
+        self.dirty = false;
+        true
+    }
+
+    /// For the pipelines `update_multiview` draws with.
+    pub fn multiview(&self) -> &Multiview {
+        &self.multiview
+    }
+
+    /// The cubemap, for `texture_cube` bindings.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.cube_view
+    }
+
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        self.target.texture()
+    }
+}
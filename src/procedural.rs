@@ -0,0 +1,331 @@
+//! Textures generated instead of loaded, for placeholders and tests that shouldn't need
+//! asset files. Patterns are drawn on the CPU, noise is computed on the GPU.
+
+use wgpu::util::DeviceExt;
+
+use crate::texture::Texture;
+
+const WORKGROUP_SIZE: u32 = 8;
+
+const NOISE_SHADER: &str = r#"
+struct Params {
+    scale: f32,
+    octaves: u32,
+    seed: u32,
+    kind: u32,
+};
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var output: texture_storage_2d<rgba8unorm, write>;
+
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn hash(cell: vec2<i32>, seed: u32) -> f32 {
+    let h = pcg(bitcast<u32>(cell.x) ^ pcg(bitcast<u32>(cell.y) ^ pcg(seed)));
+    return f32(h) / 4294967295.0;
+}
+
+fn value_noise(p: vec2<f32>, seed: u32) -> f32 {
+    let cell = vec2<i32>(floor(p));
+    let f = fract(p);
+    let t = f * f * (3.0 - 2.0 * f);
+    let a = hash(cell, seed);
+    let b = hash(cell + vec2<i32>(1, 0), seed);
+    let c = hash(cell + vec2<i32>(0, 1), seed);
+    let d = hash(cell + vec2<i32>(1, 1), seed);
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y);
+}
+
+fn gradient(cell: vec2<i32>, seed: u32, offset: vec2<f32>) -> f32 {
+    let angle = hash(cell, seed) * 6.2831853;
+    return dot(vec2<f32>(cos(angle), sin(angle)), offset);
+}
+
+fn perlin_noise(p: vec2<f32>, seed: u32) -> f32 {
+    let cell = vec2<i32>(floor(p));
+    let f = fract(p);
+    let t = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let a = gradient(cell, seed, f);
+    let b = gradient(cell + vec2<i32>(1, 0), seed, f - vec2<f32>(1.0, 0.0));
+    let c = gradient(cell + vec2<i32>(0, 1), seed, f - vec2<f32>(0.0, 1.0));
+    let d = gradient(cell + vec2<i32>(1, 1), seed, f - vec2<f32>(1.0, 1.0));
+    // 2D gradient noise stays within +-sqrt(0.5)
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y) * 0.7071 + 0.5;
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    var p = vec2<f32>(id.xy) / f32(max(size.x, size.y)) * params.scale;
+    var amplitude = 0.5;
+    var total = 0.0;
+    var sum = 0.0;
+    for (var octave = 0u; octave < max(params.octaves, 1u); octave++) {
+        let seed = params.seed + octave;
+        var n: f32;
+        if (params.kind == 0u) {
+            n = value_noise(p, seed);
+        } else {
+            n = perlin_noise(p, seed);
+        }
+        sum += n * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        p *= 2.0;
+    }
+    let v = clamp(sum / total, 0.0, 1.0);
+    textureStore(output, vec2<i32>(id.xy), vec4<f32>(v, v, v, 1.0));
+}
+"#;
+
+/// Images drawn on the CPU. Colors are sRGB with straight alpha, from 0 to 1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Solid([f32; 4]),
+    /// Squares of `cell` pixels alternating between the two colors, the first in the top
+    /// left corner.
+    Checkerboard {
+        cell: u32,
+        colors: [[f32; 4]; 2],
+    },
+    /// From `from` to `to` along `angle` radians, 0 runs left to right and a quarter turn
+    /// top to bottom.
+    LinearGradient {
+        from: [f32; 4],
+        to: [f32; 4],
+        angle: f32,
+    },
+    /// From `inner` at the center to `outer` at the middle of the edges and beyond.
+    RadialGradient {
+        inner: [f32; 4],
+        outer: [f32; 4],
+    },
+}
+
+impl Pattern {
+    /// Magenta and black, the usual stand in for a missing texture.
+    pub fn missing() -> Self {
+        Pattern::Checkerboard {
+            cell: 8,
+            colors: [[1.0, 0.0, 1.0, 1.0], [0.0, 0.0, 0.0, 1.0]],
+        }
+    }
+
+    /// Tightly packed RGBA8 pixels, as `Texture::from_rgba8` takes them.
+    pub fn pixels(&self, width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.extend(to_rgba8(self.color_at(x, y, width, height)));
+            }
+        }
+        pixels
+    }
+
+    fn color_at(&self, x: u32, y: u32, width: u32, height: u32) -> [f32; 4] {
+        // pixel centers, from 0 to 1 across the image
+        let u = (x as f32 + 0.5) / width as f32;
+        let v = (y as f32 + 0.5) / height as f32;
+        match *self {
+            Pattern::Solid(color) => color,
+            Pattern::Checkerboard { cell, colors } => {
+                let cell = cell.max(1);
+                colors[((x / cell + y / cell) % 2) as usize]
+            }
+            Pattern::LinearGradient { from, to, angle } => {
+                let (sin, cos) = angle.sin_cos();
+                // projected onto the direction, so the corners it starts and ends in
+                // get exactly `from` and `to`
+                let project = |u: f32, v: f32| (u - 0.5) * cos + (v - 0.5) * sin;
+                let extent = project(0.5 + 0.5 * cos.signum(), 0.5 + 0.5 * sin.signum());
+                let t = if extent > 0.0 {
+                    project(u, v) / extent * 0.5 + 0.5
+                } else {
+                    0.5
+                };
+                lerp(from, to, t)
+            }
+            Pattern::RadialGradient { inner, outer } => {
+                let distance = ((u - 0.5).powi(2) + (v - 0.5).powi(2)).sqrt() * 2.0;
+                lerp(inner, outer, distance)
+            }
+        }
+    }
+
+    pub fn texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        label: &str,
+    ) -> Texture {
+        let pixels = self.pixels(width, height);
+        Texture::from_rgba8(device, queue, width, height, &pixels, label)
+    }
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let t = t.clamp(0.0, 1.0);
+    std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+fn to_rgba8(color: [f32; 4]) -> [u8; 4] {
+    color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NoiseKind {
+    /// Random values at grid points blended smoothly, blobby.
+    Value,
+    /// Random gradients at grid points, smoother and less grid aligned than value noise.
+    Perlin,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct NoiseParams {
+    scale: f32,
+    octaves: u32,
+    seed: u32,
+    kind: u32,
+}
+unsafe impl bytemuck::Pod for NoiseParams {}
+unsafe impl bytemuck::Zeroable for NoiseParams {}
+
+/// Grayscale fractal noise from 0 to 1, computed on the GPU into an `Rgba8Unorm` texture
+/// that can be copied from, e.g. to read it back as a heightmap.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    /// Grid cells across the longer side of the texture at the first octave.
+    pub scale: f32,
+    /// Layers added at twice the frequency and half the amplitude of the one before.
+    pub octaves: u32,
+    pub seed: u32,
+}
+
+impl Noise {
+    pub fn new(kind: NoiseKind) -> Self {
+        Self {
+            kind,
+            scale: 8.0,
+            octaves: 4,
+            seed: 0,
+        }
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Creates the texture and submits the compute pass filling it. Builds the pipeline
+    /// every call, fine for a few textures at startup.
+    pub fn texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        label: &str,
+    ) -> Texture {
+        let texture = Texture::builder(label)
+            .with_format(wgpu::TextureFormat::Rgba8Unorm)
+            .with_usage(wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC)
+            .build_empty(device, width, height);
+
+        let params = NoiseParams {
+            scale: self.scale,
+            octaves: self.octaves,
+            seed: self.seed,
+            kind: match self.kind {
+                NoiseKind::Value => 0,
+                NoiseKind::Perlin => 1,
+            },
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Noise Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Noise Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture.storage_layout_entry(1, wgpu::StorageTextureAccess::WriteOnly),
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Noise Bind Group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+            ],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Noise Shader"),
+            source: wgpu::ShaderSource::Wgsl(NOISE_SHADER.into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Noise Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Noise Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Noise Encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Noise Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let (x, y) = texture.workgroups(WORKGROUP_SIZE);
+            pass.dispatch_workgroups(x, y, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        texture
+    }
+}
@@ -0,0 +1,34 @@
+//! Profiler scopes around the phases of a frame, for the Tracy and puffin profilers.
+//!
+//! With the `profile-tracy` feature the run loops start a Tracy client, connect with the
+//! Tracy viewer to see the zones. With `profile-puffin` scopes go to puffin's global
+//! profiler, which the app serves or shows itself, for example with `puffin_http` or
+//! `puffin_egui`. Without either feature this compiles to nothing.
+
+/// Times the rest of the enclosing block under `name`.
+macro_rules! profile_scope {
+    ($name:literal) => {
+        #[cfg(feature = "profile-tracy")]
+        let _tracy_span = tracy_client::span!($name);
+        #[cfg(feature = "profile-puffin")]
+        puffin::profile_scope!($name);
+    };
+}
+
+pub(crate) use profile_scope;
+
+/// Starts the profilers, called by the run loops before the first frame.
+pub(crate) fn start() {
+    #[cfg(feature = "profile-tracy")]
+    tracy_client::Client::start();
+    #[cfg(feature = "profile-puffin")]
+    puffin::set_scopes_on(true);
+}
+
+/// Marks the end of a frame, after it was presented.
+pub(crate) fn finish_frame() {
+    #[cfg(feature = "profile-tracy")]
+    tracy_client::frame_mark();
+    #[cfg(feature = "profile-puffin")]
+    puffin::GlobalProfiler::lock().new_frame();
+}
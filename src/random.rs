@@ -0,0 +1,106 @@
+//! Tiny pseudo random generator used by effects like particles.
+//! Not cryptographically secure, just fast and reproducible from a seed.
+
+use crate::math::{Vec2, Vec3};
+
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 the seed so small seeds don't produce correlated output
+        let mut rng = Self { state: seed };
+        rng.state = rng.splitmix();
+        if rng.state == 0 {
+            rng.state = 0x9E37_79B9_7F4A_7C15;
+        }
+        rng
+    }
+
+    /// Seeded from the system clock, or from the seed sequence in deterministic mode.
+    pub fn from_time() -> Self {
+        if let Some(seed) = crate::deterministic::next_seed() {
+            return Self::new(seed);
+        }
+        let nanos = crate::time::SystemTime::now()
+            .duration_since(crate::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos)
+    }
+
+    fn splitmix(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// xorshift64*
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform in 0.0..1.0
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// Uniform in `0..n`. Returns 0 when `n` is 0.
+    pub fn below(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        ((self.next_u32() as u64 * n as u64) >> 32) as u32
+    }
+
+    pub fn bool(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub fn unit_vec2(&mut self) -> Vec2 {
+        Vec2::from_angle(self.range(0.0, std::f32::consts::TAU))
+    }
+
+    /// Uniformly distributed direction on the unit sphere.
+    pub fn unit_vec3(&mut self) -> Vec3 {
+        let z = self.range(-1.0, 1.0);
+        let a = self.range(0.0, std::f32::consts::TAU);
+        let r = (1.0 - z * z).sqrt();
+        Vec3::new(r * a.cos(), r * a.sin(), z)
+    }
+
+    /// Direction inside a cone of `half_angle` radians around `axis`.
+    pub fn cone(&mut self, axis: Vec3, half_angle: f32) -> Vec3 {
+        let axis = axis.normalize();
+        let cos_max = half_angle.cos();
+        let z = self.range(cos_max, 1.0);
+        let a = self.range(0.0, std::f32::consts::TAU);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let u = axis.any_orthogonal();
+        let v = axis.cross(u);
+        u * (r * a.cos()) + v * (r * a.sin()) + axis * z
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::from_time()
+    }
+}
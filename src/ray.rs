@@ -0,0 +1,193 @@
+//! Rays and ray intersection tests for picking and dragging on the CPU.
+//!
+//! Every test returns the distance along the ray to the first hit in front of the origin,
+//! so the hit point is `ray.at(t)`.
+
+use crate::math::Vec3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Unit length.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    /// Normalizes `direction`.
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// Hit against the plane through `point` facing either way.
+    pub fn intersect_plane(&self, point: Vec3, normal: Vec3) -> Option<f32> {
+        let denom = normal.dot(self.direction);
+        if denom.abs() <= f32::EPSILON {
+            return None;
+        }
+        let t = (point - self.origin).dot(normal) / denom;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// Slab test against an axis aligned box, 0 when the origin is inside it.
+    pub fn intersect_aabb(&self, min: Vec3, max: Vec3) -> Option<f32> {
+        let mut near = f32::NEG_INFINITY;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let origin = self.origin[axis];
+            let direction = self.direction[axis];
+            if direction.abs() <= f32::EPSILON {
+                // parallel to this pair of slabs, either always between them or never
+                if origin < min[axis] || origin > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (min[axis] - origin) / direction;
+            let t1 = (max[axis] - origin) / direction;
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        (near <= far && far >= 0.0).then_some(near.max(0.0))
+    }
+
+    /// First hit with a sphere's surface, or its far side when the origin is inside.
+    pub fn intersect_sphere(&self, center: Vec3, radius: f32) -> Option<f32> {
+        let to_origin = self.origin - center;
+        let b = to_origin.dot(self.direction);
+        let c = to_origin.length_squared() - radius * radius;
+        let discriminant = b * b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        let near = -b - root;
+        let far = -b + root;
+        if near >= 0.0 {
+            Some(near)
+        } else if far >= 0.0 {
+            Some(far)
+        } else {
+            None
+        }
+    }
+
+    /// Möller–Trumbore test, hits both faces of the triangle.
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let ab = b - a;
+        let ac = c - a;
+        let p = self.direction.cross(ac);
+        let det = ab.dot(p);
+        if det.abs() <= f32::EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(ab);
+        let v = self.direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = ac.dot(q) * inv_det;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// Nearest hit with an indexed triangle mesh, with the index of the triangle hit.
+    pub fn intersect_mesh(&self, positions: &[Vec3], indices: &[u32]) -> Option<(f32, usize)> {
+        indices
+            .chunks_exact(3)
+            .enumerate()
+            .filter_map(|(i, tri)| {
+                let [a, b, c] = [tri[0], tri[1], tri[2]].map(|index| positions[index as usize]);
+                self.intersect_triangle(a, b, c).map(|t| (t, i))
+            })
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn down_from(x: f32, z: f32) -> Ray {
+        Ray::new(Vec3::new(x, 5.0, z), Vec3::new(0.0, -2.0, 0.0))
+    }
+
+    #[test]
+    fn new_normalizes_the_direction() {
+        let ray = down_from(0.0, 0.0);
+        assert_eq!(ray.direction, Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(ray.at(2.0), Vec3::new(0.0, 3.0, 0.0));
+    }
+
+    #[test]
+    fn planes_are_hit_in_front_only() {
+        let ray = down_from(0.0, 0.0);
+        assert_eq!(ray.intersect_plane(Vec3::ZERO, Vec3::Y), Some(5.0));
+        assert_eq!(ray.intersect_plane(Vec3::ZERO, -Vec3::Y), Some(5.0));
+        assert_eq!(ray.intersect_plane(Vec3::new(0.0, 6.0, 0.0), Vec3::Y), None);
+        assert_eq!(ray.intersect_plane(Vec3::ZERO, Vec3::X), None);
+    }
+
+    #[test]
+    fn boxes_are_hit_from_outside_and_inside() {
+        let (min, max) = (Vec3::splat(-1.0), Vec3::splat(1.0));
+        assert_eq!(down_from(0.5, 0.5).intersect_aabb(min, max), Some(4.0));
+        assert_eq!(down_from(2.0, 0.0).intersect_aabb(min, max), None);
+        let inside = Ray::new(Vec3::ZERO, Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(inside.intersect_aabb(min, max), Some(0.0));
+        let away = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::Y);
+        assert_eq!(away.intersect_aabb(min, max), None);
+    }
+
+    #[test]
+    fn spheres_are_hit_on_the_near_side() {
+        let ray = down_from(0.0, 0.0);
+        assert_eq!(ray.intersect_sphere(Vec3::ZERO, 1.0), Some(4.0));
+        assert_eq!(
+            ray.intersect_sphere(Vec3::new(0.0, 5.0, 0.0), 1.0),
+            Some(1.0)
+        );
+        assert_eq!(ray.intersect_sphere(Vec3::new(3.0, 0.0, 0.0), 1.0), None);
+        assert_eq!(ray.intersect_sphere(Vec3::new(0.0, 10.0, 0.0), 1.0), None);
+    }
+
+    #[test]
+    fn triangles_and_meshes_are_hit_on_both_faces() {
+        let positions = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(1.0, 2.0, 0.0),
+            Vec3::new(0.0, 2.0, 1.0),
+        ];
+        let [a, b, c, ..] = positions;
+        let ray = down_from(0.25, 0.25);
+        assert_near(ray.intersect_triangle(a, b, c).unwrap(), 5.0);
+        assert_near(ray.intersect_triangle(a, c, b).unwrap(), 5.0);
+        assert_eq!(down_from(0.75, 0.75).intersect_triangle(a, b, c), None);
+
+        let (t, triangle) = ray.intersect_mesh(&positions, &[0, 1, 2, 3, 4, 5]).unwrap();
+        assert_near(t, 3.0);
+        assert_eq!(triangle, 1);
+        assert_eq!(
+            down_from(2.0, 2.0).intersect_mesh(&positions, &[0, 1, 2]),
+            None
+        );
+    }
+}
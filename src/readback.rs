@@ -0,0 +1,281 @@
+//! Reading textures back to the CPU as images, for screenshots, golden tests and tools, and
+//! buffers as the values they hold, for compute results.
+//!
+//! A readback copies the first mip level into a buffer padded to wgpu's row alignment,
+//! submits the copy and asks for the buffer to be mapped. `PendingReadback::poll` checks
+//! on it without blocking, so a frame loop or the web can pick the image up once it's
+//! there; `wait` blocks until then. The rows are then unpadded and converted to RGBA8:
+//! 8-bit formats as they're stored, BGRA swizzled, and float formats clamped to 0..1 and
+//! sRGB encoded, the way an sRGB target would store them. `read_buffer` copies a whole
+//! buffer the same way and hands back its bytes cast to any `Pod` type.
+
+use std::sync::{Arc, OnceLock};
+
+use image::RgbaImage;
+
+use crate::stats::Tracked;
+
+#[derive(Debug)]
+pub enum ReadbackError {
+    /// Formats other than 8-bit RGBA, BGRA and R, and 16 or 32-bit float RGBA and R.
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// The texture or buffer wasn't created with `COPY_SRC` usage.
+    MissingCopySrc,
+    /// Multisampled textures can't be copied, read their resolve target instead.
+    Multisampled,
+    Map(wgpu::BufferAsyncError),
+    /// `wait` was called before the buffer was mapped somewhere blocking isn't possible,
+    /// such as the web.
+    NotReady,
+}
+
+impl std::fmt::Display for ReadbackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadbackError::UnsupportedFormat(format) => {
+                write!(f, "can't read back {:?} textures", format)
+            }
+            ReadbackError::MissingCopySrc => write!(f, "no COPY_SRC usage to copy from"),
+            ReadbackError::Multisampled => write!(f, "can't read back a multisampled texture"),
+            ReadbackError::Map(e) => write!(f, "failed to map the readback buffer: {}", e),
+            ReadbackError::NotReady => write!(f, "the readback isn't finished"),
+        }
+    }
+}
+
+impl std::error::Error for ReadbackError {}
+
+/// Bytes per pixel of the formats that can be read back.
+fn pixel_size(format: wgpu::TextureFormat) -> Option<u32> {
+    use wgpu::TextureFormat as F;
+    match format {
+        F::R8Unorm => Some(1),
+        F::Rgba8Unorm | F::Rgba8UnormSrgb | F::Bgra8Unorm | F::Bgra8UnormSrgb | F::R32Float => {
+            Some(4)
+        }
+        F::Rgba16Float => Some(8),
+        F::Rgba32Float => Some(16),
+        _ => None,
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Linear 0..1 to an sRGB encoded byte.
+fn encode_srgb(linear: f32) -> u8 {
+    let c = linear.clamp(0.0, 1.0);
+    let encoded = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// One pixel of `format` as RGBA8.
+fn convert(format: wgpu::TextureFormat, pixel: &[u8]) -> [u8; 4] {
+    use wgpu::TextureFormat as F;
+    let f32_at =
+        |i: usize| f32::from_le_bytes([pixel[i], pixel[i + 1], pixel[i + 2], pixel[i + 3]]);
+    let f16_at = |i: usize| f16_to_f32(u16::from_le_bytes([pixel[i], pixel[i + 1]]));
+    // alpha isn't color, it's stored linearly
+    let alpha = |a: f32| (a.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match format {
+        F::R8Unorm => [pixel[0], pixel[0], pixel[0], 255],
+        F::Bgra8Unorm | F::Bgra8UnormSrgb => [pixel[2], pixel[1], pixel[0], pixel[3]],
+        F::R32Float => {
+            let grey = encode_srgb(f32_at(0));
+            [grey, grey, grey, 255]
+        }
+        F::Rgba16Float => [
+            encode_srgb(f16_at(0)),
+            encode_srgb(f16_at(2)),
+            encode_srgb(f16_at(4)),
+            alpha(f16_at(6)),
+        ],
+        F::Rgba32Float => [
+            encode_srgb(f32_at(0)),
+            encode_srgb(f32_at(4)),
+            encode_srgb(f32_at(8)),
+            alpha(f32_at(12)),
+        ],
+        _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+    }
+}
+
+/// A texture being copied back, from `PendingReadback::start`.
+pub struct PendingReadback {
+    buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    padded_row: u32,
+    /// Set by the map callback, `None` until then.
+    mapped: Arc<OnceLock<Result<(), wgpu::BufferAsyncError>>>,
+    _tracked: Tracked,
+}
+
+impl PendingReadback {
+    /// Submits a copy of the first mip level of `texture`, after everything submitted
+    /// before, and starts mapping it.
+    pub fn start(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+    ) -> Result<Self, ReadbackError> {
+        let format = texture.format();
+        let pixel_size = pixel_size(format).ok_or(ReadbackError::UnsupportedFormat(format))?;
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            return Err(ReadbackError::MissingCopySrc);
+        }
+        if texture.sample_count() > 1 {
+            return Err(ReadbackError::Multisampled);
+        }
+        let (width, height) = (texture.width(), texture.height());
+        let padded_row = (width * pixel_size).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = Arc::new(OnceLock::new());
+        let set = mapped.clone();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = set.set(result);
+            });
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+        Ok(Self {
+            buffer,
+            format,
+            width,
+            height,
+            padded_row,
+            mapped,
+            _tracked: tracked,
+        })
+    }
+
+    /// Whether the image can be taken with `wait` without blocking. Doesn't block itself.
+    pub fn poll(&self, device: &wgpu::Device) -> bool {
+        if self.mapped.get().is_none() {
+            device.poll(wgpu::Maintain::Poll);
+        }
+        self.mapped.get().is_some()
+    }
+
+    /// The image, blocking until the copy is done unless `poll` said it is.
+    pub fn wait(self, device: &wgpu::Device) -> Result<RgbaImage, ReadbackError> {
+        if self.mapped.get().is_none() {
+            device.poll(wgpu::Maintain::Wait);
+        }
+        match self.mapped.get() {
+            Some(Ok(())) => Ok(self.read()),
+            Some(Err(e)) => Err(ReadbackError::Map(e.clone())),
+            None => Err(ReadbackError::NotReady),
+        }
+    }
+
+    fn read(&self) -> RgbaImage {
+        let pixel_size = pixel_size(self.format).unwrap_or(4) as usize;
+        let row = self.width as usize * pixel_size;
+        let mut pixels = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for padded in data.chunks(self.padded_row as usize) {
+                for pixel in padded[..row].chunks_exact(pixel_size) {
+                    pixels.extend_from_slice(&convert(self.format, pixel));
+                }
+            }
+        }
+        self.buffer.unmap();
+        RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("read back size matches the texture")
+    }
+}
+
+/// Reads back the first mip level of `texture`, blocking until it's copied.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<RgbaImage, ReadbackError> {
+    PendingReadback::start(device, queue, texture)?.wait(device)
+}
+
+/// Reads back all of `buffer` as `T`s, blocking until it's copied, e.g. the results of a
+/// compute pass. Bytes that don't fill a whole `T` at the end are left out.
+pub fn read_buffer<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> Result<Vec<T>, ReadbackError> {
+    if !buffer.usage().contains(wgpu::BufferUsages::COPY_SRC) {
+        return Err(ReadbackError::MissingCopySrc);
+    }
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let mapped = Arc::new(OnceLock::new());
+    let set = mapped.clone();
+    staging
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            let _ = set.set(result);
+        });
+    device.poll(wgpu::Maintain::Wait);
+    match mapped.get() {
+        Some(Ok(())) => {}
+        Some(Err(e)) => return Err(ReadbackError::Map(e.clone())),
+        None => return Err(ReadbackError::NotReady),
+    }
+    let values = {
+        let data = staging.slice(..).get_mapped_range();
+        let whole = data.len() / std::mem::size_of::<T>().max(1) * std::mem::size_of::<T>();
+        bytemuck::pod_collect_to_vec(&data[..whole])
+    };
+    staging.unmap();
+    Ok(values)
+}
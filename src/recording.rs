@@ -0,0 +1,436 @@
+//! Recording every presented frame, for demo footage: to numbered PNGs, a GIF with the
+//! `gif` feature, or any video format ffmpeg writes, through an ffmpeg process.
+//!
+//! Frames are copied into readback buffers as part of their own submission and mapped
+//! without waiting. A few frames later, once the GPU is done with them, they are handed to
+//! a writer thread, so neither the GPU nor encoding holds up rendering.
+//!
+//! Clips play back at a fixed rate, so frames drawn at an uneven pace play back unevenly.
+//! Deterministic mode gives every frame the same time step.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::{Arc, OnceLock};
+use std::thread::JoinHandle;
+
+use image::RgbaImage;
+
+use crate::stats::Tracked;
+
+/// Frames waiting for their readback before rendering waits for the oldest one.
+const MAX_IN_FLIGHT: usize = 4;
+
+#[derive(Debug)]
+pub enum RecordingError {
+    /// Only 8 bit RGBA and BGRA frames can be recorded.
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// The surface doesn't allow copying from its textures.
+    NotCopyable,
+    /// Videos and GIFs can't change size, so recording stops on a resize.
+    SizeChanged {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Io(PathBuf, std::io::Error),
+    #[cfg(feature = "gif")]
+    Gif(gif::EncodingError),
+    /// ffmpeg exited with an error, it will have printed what went wrong.
+    Ffmpeg(std::process::ExitStatus),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::UnsupportedFormat(format) => {
+                write!(f, "frames in {:?} can't be recorded", format)
+            }
+            RecordingError::NotCopyable => write!(f, "the surface can't be copied from"),
+            RecordingError::SizeChanged { expected, actual } => write!(
+                f,
+                "frame size changed from {}x{} to {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            RecordingError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            #[cfg(feature = "gif")]
+            RecordingError::Gif(e) => write!(f, "GIF encoding failed: {}", e),
+            RecordingError::Ffmpeg(status) => write!(f, "ffmpeg failed: {}", status),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+/// Where recorded frames go. Runs on the writer thread and gets the frames in order.
+pub trait FrameEncoder: Send + 'static {
+    fn encode(&mut self, frame: RgbaImage) -> Result<(), RecordingError>;
+    /// Called once after the last frame.
+    fn finish(&mut self) -> Result<(), RecordingError> {
+        Ok(())
+    }
+}
+
+/// Writes `frame_00000.png`, `frame_00001.png` and so on into a directory.
+pub struct PngSequence {
+    dir: PathBuf,
+    next: u32,
+}
+
+impl PngSequence {
+    /// Creates `dir` if it doesn't exist.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, RecordingError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| RecordingError::Io(dir.clone(), e))?;
+        Ok(Self { dir, next: 0 })
+    }
+}
+
+impl FrameEncoder for PngSequence {
+    fn encode(&mut self, frame: RgbaImage) -> Result<(), RecordingError> {
+        let path = self.dir.join(format!("frame_{:05}.png", self.next));
+        self.next += 1;
+        frame.save(&path).map_err(|e| match e {
+            image::ImageError::IoError(e) => RecordingError::Io(path, e),
+            e => RecordingError::Io(path, std::io::Error::other(e)),
+        })
+    }
+}
+
+/// Checks every frame is the size of the first.
+fn check_size(expected: (u32, u32), frame: &RgbaImage) -> Result<(), RecordingError> {
+    if frame.dimensions() != expected {
+        return Err(RecordingError::SizeChanged {
+            expected,
+            actual: frame.dimensions(),
+        });
+    }
+    Ok(())
+}
+
+/// A looping GIF. Each frame is reduced to its own 256 colour palette, which is slow for
+/// large frames, so keep these small.
+#[cfg(feature = "gif")]
+pub struct GifEncoder {
+    path: PathBuf,
+    /// Hundredths of a second per frame, as GIF counts time.
+    delay: u16,
+    /// 1 to 30, higher is faster and worse looking.
+    pub speed: i32,
+    encoder: Option<gif::Encoder<std::io::BufWriter<std::fs::File>>>,
+    size: (u32, u32),
+}
+
+#[cfg(feature = "gif")]
+impl GifEncoder {
+    /// GIF can only time frames in hundredths of a second, so `fps` is rounded to fit.
+    pub fn new(path: impl Into<PathBuf>, fps: f32) -> Self {
+        Self {
+            path: path.into(),
+            delay: (100.0 / fps).round().max(1.0) as u16,
+            speed: 10,
+            encoder: None,
+            size: (0, 0),
+        }
+    }
+}
+
+#[cfg(feature = "gif")]
+impl FrameEncoder for GifEncoder {
+    fn encode(&mut self, frame: RgbaImage) -> Result<(), RecordingError> {
+        let (width, height) = frame.dimensions();
+        if self.encoder.is_none() {
+            let file = std::fs::File::create(&self.path)
+                .map_err(|e| RecordingError::Io(self.path.clone(), e))?;
+            let mut gif = gif::Encoder::new(
+                std::io::BufWriter::new(file),
+                width as u16,
+                height as u16,
+                &[],
+            )
+            .map_err(RecordingError::Gif)?;
+            gif.set_repeat(gif::Repeat::Infinite)
+                .map_err(RecordingError::Gif)?;
+            self.encoder = Some(gif);
+            self.size = (width, height);
+        }
+        check_size(self.size, &frame)?;
+        let mut pixels = frame.into_raw();
+        let mut frame =
+            gif::Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, self.speed);
+        frame.delay = self.delay;
+        self.encoder
+            .as_mut()
+            .unwrap()
+            .write_frame(&frame)
+            .map_err(RecordingError::Gif)
+    }
+
+    fn finish(&mut self) -> Result<(), RecordingError> {
+        if let Some(encoder) = self.encoder.take() {
+            let mut file = encoder.into_inner().map_err(RecordingError::Gif)?;
+            file.flush()
+                .map_err(|e| RecordingError::Io(self.path.clone(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Pipes raw frames into an `ffmpeg` on the `PATH`, which picks the format from the
+/// extension of `path`, e.g. `clip.mp4` or `clip.webm`.
+pub struct FfmpegEncoder {
+    path: PathBuf,
+    fps: f32,
+    process: Option<(Child, ChildStdin, (u32, u32))>,
+}
+
+impl FfmpegEncoder {
+    pub fn new(path: impl Into<PathBuf>, fps: f32) -> Self {
+        Self {
+            path: path.into(),
+            fps,
+            process: None,
+        }
+    }
+
+    fn spawn(&self, width: u32, height: u32) -> Result<(Child, ChildStdin), RecordingError> {
+        let mut child = Command::new("ffmpeg")
+            .args([
+                "-y",
+                "-loglevel",
+                "error",
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgba",
+            ])
+            .args(["-s", &format!("{}x{}", width, height)])
+            .args(["-r", &self.fps.to_string(), "-i", "-"])
+            // most players only take 4:2:0 video, which needs even sizes
+            .args([
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                "-pix_fmt",
+                "yuv420p",
+            ])
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| RecordingError::Io(PathBuf::from("ffmpeg"), e))?;
+        let stdin = child
+            .stdin
+            .take()
+            .expect("ffmpeg was started with a piped stdin");
+        Ok((child, stdin))
+    }
+}
+
+impl FrameEncoder for FfmpegEncoder {
+    fn encode(&mut self, frame: RgbaImage) -> Result<(), RecordingError> {
+        let (width, height) = frame.dimensions();
+        if self.process.is_none() {
+            let (child, stdin) = self.spawn(width, height)?;
+            self.process = Some((child, stdin, (width, height)));
+        }
+        let (_, stdin, size) = self.process.as_mut().unwrap();
+        check_size(*size, &frame)?;
+        stdin
+            .write_all(frame.as_raw())
+            .map_err(|e| RecordingError::Io(PathBuf::from("ffmpeg"), e))
+    }
+
+    fn finish(&mut self) -> Result<(), RecordingError> {
+        let Some((mut child, stdin, _)) = self.process.take() else {
+            return Ok(());
+        };
+        // closing stdin is what tells ffmpeg the video is over
+        drop(stdin);
+        let status = child
+            .wait()
+            .map_err(|e| RecordingError::Io(PathBuf::from("ffmpeg"), e))?;
+        if !status.success() {
+            return Err(RecordingError::Ffmpeg(status));
+        }
+        Ok(())
+    }
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    _tracked: Tracked,
+}
+
+struct PendingFrame {
+    readback: Readback,
+    index: u32,
+    width: u32,
+    height: u32,
+    padded_row: u32,
+    /// Set by the map callback, `None` until then.
+    mapped: Arc<OnceLock<Result<(), wgpu::BufferAsyncError>>>,
+    map_requested: bool,
+}
+
+pub(crate) struct FrameRecorder {
+    bgra: bool,
+    frames: u32,
+    pending: VecDeque<PendingFrame>,
+    free: Vec<Readback>,
+    images: mpsc::Sender<RgbaImage>,
+    writer: JoinHandle<()>,
+}
+
+impl FrameRecorder {
+    pub(crate) fn new(
+        format: wgpu::TextureFormat,
+        mut encoder: Box<dyn FrameEncoder>,
+    ) -> Result<Self, RecordingError> {
+        let bgra = match format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => return Err(RecordingError::UnsupportedFormat(format)),
+        };
+        let (images, received) = mpsc::channel::<RgbaImage>();
+        let writer = std::thread::Builder::new()
+            .name("frame writer".into())
+            .spawn(move || {
+                let result = received
+                    .into_iter()
+                    .try_for_each(|image| encoder.encode(image))
+                    .and_then(|()| encoder.finish());
+                if let Err(e) = result {
+                    log::warn!("recording stopped: {}", e);
+                }
+            })
+            .expect("Frame writer thread could not be started");
+        Ok(Self {
+            bgra,
+            frames: 0,
+            pending: VecDeque::new(),
+            free: Vec::new(),
+            images,
+            writer,
+        })
+    }
+
+    /// Records a copy of `texture` into `encoder`, to be read back once submitted.
+    pub(crate) fn capture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        let (width, height) = (texture.width(), texture.height());
+        let padded_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let size = (padded_row * height) as wgpu::BufferAddress;
+        // buffers from before a resize are the wrong size, let those go
+        self.free.retain(|readback| readback.buffer.size() == size);
+        let readback = self.free.pop().unwrap_or_else(|| {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Recording Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            let _tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+            Readback { buffer, _tracked }
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        self.pending.push_back(PendingFrame {
+            readback,
+            index: self.frames,
+            width,
+            height,
+            padded_row,
+            mapped: Arc::new(OnceLock::new()),
+            map_requested: false,
+        });
+        self.frames += 1;
+    }
+
+    /// Starts reading back the frames just submitted and writes out any that are ready.
+    pub(crate) fn after_submit(&mut self, device: &wgpu::Device) {
+        for frame in self.pending.iter_mut().filter(|f| !f.map_requested) {
+            let mapped = frame.mapped.clone();
+            frame
+                .readback
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = mapped.set(result);
+                });
+            frame.map_requested = true;
+        }
+        let wait = self.pending.len() > MAX_IN_FLIGHT;
+        device.poll(if wait {
+            wgpu::Maintain::Wait
+        } else {
+            wgpu::Maintain::Poll
+        });
+        self.write_ready();
+    }
+
+    fn write_ready(&mut self) {
+        while self
+            .pending
+            .front()
+            .is_some_and(|f| f.mapped.get().is_some())
+        {
+            let frame = self.pending.pop_front().unwrap();
+            match frame.mapped.get().unwrap() {
+                Ok(()) => {
+                    let image = self.read(&frame);
+                    let _ = self.images.send(image);
+                }
+                Err(e) => log::warn!("reading back frame {} failed: {}", frame.index, e),
+            }
+            self.free.push(frame.readback);
+        }
+    }
+
+    fn read(&self, frame: &PendingFrame) -> RgbaImage {
+        let buffer = &frame.readback.buffer;
+        let row = frame.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row * frame.height as usize);
+        {
+            let data = buffer.slice(..).get_mapped_range();
+            for padded in data.chunks(frame.padded_row as usize) {
+                pixels.extend_from_slice(&padded[..row]);
+            }
+        }
+        buffer.unmap();
+        if self.bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        RgbaImage::from_raw(frame.width, frame.height, pixels)
+            .expect("read back size matches the frame")
+    }
+
+    /// Waits for every frame captured to be read back and written, returns how many were.
+    pub(crate) fn finish(mut self, device: &wgpu::Device) -> u32 {
+        while !self.pending.is_empty() {
+            device.poll(wgpu::Maintain::Wait);
+            self.write_ready();
+        }
+        drop(self.images);
+        if self.writer.join().is_err() {
+            log::warn!("frame writer thread panicked");
+        }
+        self.frames
+    }
+}
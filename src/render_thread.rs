@@ -0,0 +1,346 @@
+//! Running game logic and rendering on separate threads.
+//!
+//! The main thread keeps the winit event loop, as most platforms require, and runs the
+//! `Simulation`. After every update it hands a snapshot of whatever needs drawing to a
+//! dedicated render thread, which owns the `Context` and draws the newest snapshot it has.
+//! Only the latest snapshot is kept, so a slow renderer skips states rather than queueing
+//! them, and a slow update leaves the renderer presenting the last state it got.
+//!
+//! In deterministic mode the two threads run in lockstep instead: every update waits for
+//! the renderer to take its snapshot, and the renderer draws each snapshot exactly once.
+//!
+//! The surface is created on the render thread, which macOS and the web do not allow, use
+//! `window::run_app` there.
+
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::EventLoop;
+
+use crate::context::Context;
+use crate::deterministic;
+use crate::events;
+use crate::frame::Frame;
+use crate::profile::profile_scope;
+use crate::settings::GraphicsSettings;
+use crate::time::Instant;
+use crate::window::{step_frame, window_builder};
+
+/// How often the render thread looks for the focus coming back while
+/// `FocusPolicy::pause_rendering` has it paused.
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// The longest an update waits for the renderer to take the last snapshot outside
+/// lockstep, a frame at 60 Hz, so a slow renderer doesn't hold up input.
+const MAX_SNAPSHOT_WAIT: Duration = Duration::from_millis(16);
+
+/// The update side, living on the main thread.
+pub trait Simulation: 'static {
+    /// Everything the renderer needs to draw one state of the simulation.
+    type Snapshot: Send + 'static;
+
+    /// Called once per update, `dt` in seconds.
+    fn update(&mut self, dt: f32);
+    /// Return `true` if the event was handled and the default handling should be skipped.
+    fn input(&mut self, _event: &WindowEvent) -> bool {
+        false
+    }
+    fn snapshot(&self) -> Self::Snapshot;
+}
+
+/// The drawing side, built and living on the render thread.
+pub trait SnapshotRenderer: 'static {
+    type Snapshot;
+
+    fn resize(&mut self, _ctx: &mut Context, _size: winit::dpi::PhysicalSize<u32>) {}
+    fn render(&mut self, ctx: &mut Context, frame: &mut Frame, snapshot: &Self::Snapshot);
+}
+
+/// Messages from the main thread other than snapshots.
+enum Command {
+    Resize(winit::dpi::PhysicalSize<u32>),
+    ScaleFactorChanged(f64),
+    /// Events the simulation left alone, for the debug overlay and `Context::events`.
+    Input(WindowEvent<'static>),
+    Resumed,
+    Suspended,
+    Exit,
+}
+
+/// Holds the newest snapshot until the render thread takes it.
+struct Mailbox<T> {
+    value: Mutex<Option<T>>,
+    changed: Condvar,
+}
+
+impl<T> Mailbox<T> {
+    fn new() -> Self {
+        Self {
+            value: Mutex::new(None),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Replaces any snapshot not taken yet.
+    fn put(&self, value: T) {
+        *self.value.lock().unwrap() = Some(value);
+        self.changed.notify_all();
+    }
+
+    fn take(&self) -> Option<T> {
+        let value = self.value.lock().unwrap().take();
+        self.changed.notify_all();
+        value
+    }
+
+    /// Waits up to `timeout` for a snapshot to arrive.
+    fn wait_for_value(&self, timeout: Duration) -> Option<T> {
+        let guard = self.value.lock().unwrap();
+        let (mut guard, _) = self
+            .changed
+            .wait_timeout_while(guard, timeout, |value| value.is_none())
+            .unwrap();
+        let value = guard.take();
+        self.changed.notify_all();
+        value
+    }
+
+    /// Waits up to `timeout` for the current snapshot to be taken, returns whether it was.
+    fn wait_until_taken(&self, timeout: Duration) -> bool {
+        let guard = self.value.lock().unwrap();
+        let (guard, _) = self
+            .changed
+            .wait_timeout_while(guard, timeout, |value| value.is_some())
+            .unwrap();
+        guard.is_none()
+    }
+}
+
+/// Opens a window and runs `simulation` on the main thread with a renderer built by `init`
+/// on a thread of its own, until the window is closed.
+///
+/// Updates run at most one snapshot ahead of the renderer, so they still follow its pace
+/// when they are cheap, but never wait on it for longer than a frame at 60 Hz.
+pub fn run_threaded<S, R>(
+    title: &str,
+    simulation: S,
+    init: impl FnOnce(&mut Context) -> R + Send + 'static,
+) where
+    S: Simulation,
+    R: SnapshotRenderer<Snapshot = S::Snapshot>,
+{
+    run_threaded_with_settings(title, &GraphicsSettings::default(), simulation, init)
+}
+
+/// Like `run_threaded`, with the window and device set up as `settings` say, e.g. from
+/// `GraphicsSettings::from_args`.
+pub fn run_threaded_with_settings<S, R>(
+    title: &str,
+    settings: &GraphicsSettings,
+    mut simulation: S,
+    init: impl FnOnce(&mut Context) -> R + Send + 'static,
+) where
+    S: Simulation,
+    R: SnapshotRenderer<Snapshot = S::Snapshot>,
+{
+    env_logger::init();
+    let event_loop = EventLoop::new();
+    let window = Arc::new(
+        window_builder(&event_loop, title, settings)
+            .build(&event_loop)
+            .expect("Window could not be created"),
+    );
+    let window_id = window.id();
+
+    crate::profile::start();
+    let mailbox = Arc::new(Mailbox::new());
+    let (commands, receiver) = mpsc::channel();
+    let deterministic = deterministic::current();
+    let render_thread = {
+        let mailbox = mailbox.clone();
+        let settings = settings.clone();
+        std::thread::Builder::new()
+            .name("render".into())
+            .spawn(move || {
+                if let Some(settings) = deterministic {
+                    deterministic::enable(settings);
+                }
+                let mut ctx = pollster::block_on(Context::new(window, &settings));
+                let renderer = init(&mut ctx);
+                render_loop(ctx, renderer, &mailbox, &receiver);
+            })
+            .expect("Render thread could not be started")
+    };
+    let mut render_thread = Some(render_thread);
+    let mut last_update = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| {
+        let mut exit = false;
+        match event {
+            Event::Resumed => {
+                let _ = commands.send(Command::Resumed);
+            }
+            Event::Suspended => {
+                let _ = commands.send(Command::Suspended);
+            }
+            Event::MainEventsCleared => {
+                if render_thread.as_ref().is_some_and(|t| t.is_finished()) {
+                    exit = true;
+                } else {
+                    let now = Instant::now();
+                    let dt = deterministic::timestep().unwrap_or((now - last_update).as_secs_f32());
+                    last_update = now;
+                    {
+                        profile_scope!("update");
+                        simulation.update(dt);
+                    }
+                    mailbox.put(simulation.snapshot());
+                    if deterministic.is_some() {
+                        // in lockstep, wait however long the renderer takes unless it is gone
+                        while !mailbox.wait_until_taken(Duration::from_millis(100))
+                            && render_thread.as_ref().is_some_and(|t| !t.is_finished())
+                        {
+                        }
+                    } else {
+                        // a snapshot not taken by then is replaced by the next one
+                        mailbox.wait_until_taken(MAX_SNAPSHOT_WAIT);
+                    }
+                }
+            }
+            Event::WindowEvent {
+                window_id: id,
+                event,
+            } if id == window_id && !simulation.input(&event) => match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => exit = true,
+                WindowEvent::Resized(size) => {
+                    let _ = commands.send(Command::Resize(size));
+                }
+                WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    let _ = commands.send(Command::ScaleFactorChanged(scale_factor));
+                    let _ = commands.send(Command::Resize(*new_inner_size));
+                }
+                event => {
+                    if let Some(event) = event.to_static() {
+                        let _ = commands.send(Command::Input(event));
+                    }
+                }
+            },
+            _ => {}
+        }
+        if exit {
+            let _ = commands.send(Command::Exit);
+            if let Some(thread) = render_thread.take() {
+                if thread.join().is_err() {
+                    log::warn!("render thread panicked");
+                }
+            }
+            control_flow.set_exit();
+        }
+    });
+}
+
+fn render_loop<R: SnapshotRenderer>(
+    mut ctx: Context,
+    mut renderer: R,
+    mailbox: &Mailbox<R::Snapshot>,
+    commands: &mpsc::Receiver<Command>,
+) {
+    let mut snapshot = None;
+    let mut last_frame = Instant::now();
+    loop {
+        for command in commands.try_iter() {
+            match command {
+                Command::Resize(size) => {
+                    ctx.resize(size);
+                    ctx.events.send(events::WindowEvent::Resized(size));
+                    renderer.resize(&mut ctx, size);
+                }
+                Command::ScaleFactorChanged(scale_factor) => {
+                    ctx.input.set_scale_factor(scale_factor);
+                    ctx.events
+                        .send(events::WindowEvent::ScaleFactorChanged(scale_factor));
+                }
+                Command::Input(event) => {
+                    if !ctx.mutes_input(&event)
+                        && !ctx.capture_input(&event)
+                        && !ctx
+                            .debug_overlay
+                            .as_mut()
+                            .is_some_and(|overlay| overlay.input(&event))
+                    {
+                        ctx.record_window_event(&event);
+                    }
+                }
+                Command::Resumed => ctx.resume(),
+                Command::Suspended => ctx.suspend(),
+                Command::Exit => return,
+            }
+        }
+        if !ctx.has_surface() {
+            // suspended, wait for the next command instead of spinning
+            match commands.recv_timeout(Duration::from_millis(100)) {
+                Ok(Command::Exit) | Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                Ok(Command::Resumed) => ctx.resume(),
+                Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+            }
+            continue;
+        }
+        if ctx.is_rendering_paused() {
+            std::thread::sleep(PAUSED_POLL_INTERVAL);
+            last_frame = Instant::now();
+            continue;
+        }
+
+        // before taking a snapshot, so the one drawn is as fresh as it can be
+        ctx.pace_frame();
+        let lockstep = deterministic::is_enabled();
+        let latest = match &snapshot {
+            Some(_) if !lockstep => mailbox.take(),
+            // nothing to draw yet, or in lockstep nothing new, don't spin
+            _ => mailbox.wait_for_value(Duration::from_millis(100)),
+        };
+        if latest.is_some() {
+            snapshot = latest;
+        } else if lockstep {
+            continue;
+        }
+        let Some(snapshot) = &snapshot else {
+            continue;
+        };
+
+        let now = Instant::now();
+        let dt = deterministic::timestep().unwrap_or((now - last_frame).as_secs_f32());
+        last_frame = now;
+        step_frame(&mut ctx, dt);
+
+        match ctx.begin_frame() {
+            Ok(mut frame) => {
+                {
+                    profile_scope!("encode");
+                    renderer.render(&mut ctx, &mut frame, snapshot);
+                    if let Some(mut overlay) = ctx.debug_overlay.take() {
+                        overlay.draw(&ctx, &mut frame);
+                        ctx.debug_overlay = Some(overlay);
+                    }
+                }
+                ctx.end_frame(frame);
+            }
+            Err(wgpu::SurfaceError::Lost) => ctx.resize(ctx.size()),
+            Err(wgpu::SurfaceError::OutOfMemory) => return,
+            Err(e) => log::warn!("{:?}", e),
+        }
+    }
+}
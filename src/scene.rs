@@ -0,0 +1,387 @@
+//! Scenes saved to and loaded from RON or JSON files.
+//!
+//! A scene is plain data: a tree of nodes, each with a transform relative to its parent
+//! and optionally a mesh and material by asset path, a light or a camera. Tools build and
+//! edit a `Scene`, `save` writes it and `load` reads it back. With the `ecs` feature,
+//! `Scene::spawn` turns it into entities.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera::Camera;
+use crate::transform::Transform;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Ron(ron::Error),
+    Json(serde_json::Error),
+    /// The path's extension is neither `ron` nor `json`.
+    UnknownFormat(PathBuf),
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "failed to access scene file: {}", e),
+            SceneError::Ron(e) => write!(f, "invalid RON scene: {}", e),
+            SceneError::Json(e) => write!(f, "invalid JSON scene: {}", e),
+            SceneError::UnknownFormat(path) => {
+                write!(f, "unknown scene format: {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<ron::error::SpannedError> for SceneError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        SceneError::Ron(e.code)
+    }
+}
+
+/// The file formats a scene is saved in, picked from the extension by `save` and `load`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SceneFormat {
+    Ron,
+    Json,
+}
+
+impl SceneFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "ron" => Some(SceneFormat::Ron),
+            "json" => Some(SceneFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Surface properties of a node's mesh. How they are shaded is up to the app.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    /// Multiplied with `base_color`.
+    pub texture: Option<PathBuf>,
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            texture: None,
+            metallic: 0.0,
+            roughness: 0.5,
+        }
+    }
+}
+
+/// A light placed and pointed by its node's transform.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Light {
+    /// Shines along the node's forward direction from infinitely far away.
+    Directional { color: [f32; 3], intensity: f32 },
+    Point {
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+    },
+    /// Shines along the node's forward direction, angles in radians.
+    Spot {
+        color: [f32; 3],
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+/// A perspective camera looking along its node's forward direction.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Perspective {
+    /// Vertical field of view in radians.
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Default for Perspective {
+    fn default() -> Self {
+        let camera = Camera::default();
+        Self {
+            fovy: camera.fovy,
+            znear: camera.znear,
+            zfar: camera.zfar,
+        }
+    }
+}
+
+impl Perspective {
+    /// The camera at `transform`, a node's world transform.
+    pub fn camera(&self, transform: &Transform, aspect: f32) -> Camera {
+        Camera {
+            eye: transform.translation,
+            target: transform.translation + transform.forward(),
+            up: transform.up(),
+            aspect,
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    /// Relative to the parent node.
+    #[serde(default)]
+    pub transform: Transform,
+    /// Asset path of a mesh, e.g. an OBJ file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<Material>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light: Option<Light>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<Perspective>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_mesh(mut self, path: impl Into<PathBuf>) -> Self {
+        self.mesh = Some(path.into());
+        self
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = Some(material);
+        self
+    }
+
+    pub fn with_light(mut self, light: Light) -> Self {
+        self.light = Some(light);
+        self
+    }
+
+    pub fn with_camera(mut self, camera: Perspective) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn with_child(mut self, child: Node) -> Self {
+        self.children.push(child);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    /// The root nodes.
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_node(mut self, node: Node) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    /// Every node depth first, parents before their children, with its world transform.
+    pub fn flatten(&self) -> Vec<(&Node, Transform)> {
+        fn visit<'a>(node: &'a Node, parent: &Transform, out: &mut Vec<(&'a Node, Transform)>) {
+            let world = parent.mul_transform(&node.transform);
+            out.push((node, world));
+            for child in &node.children {
+                visit(child, &world, out);
+            }
+        }
+
+        let mut out = Vec::new();
+        for node in &self.nodes {
+            visit(node, &Transform::IDENTITY, &mut out);
+        }
+        out
+    }
+
+    /// The first node named `name`, depth first.
+    pub fn find(&self, name: &str) -> Option<&Node> {
+        fn find<'a>(nodes: &'a [Node], name: &str) -> Option<&'a Node> {
+            nodes.iter().find_map(|node| {
+                (node.name == name)
+                    .then_some(node)
+                    .or_else(|| find(&node.children, name))
+            })
+        }
+        find(&self.nodes, name)
+    }
+
+    /// Every distinct mesh and texture path the scene refers to, e.g. to preload them.
+    pub fn asset_paths(&self) -> Vec<&Path> {
+        let mut paths = Vec::new();
+        for (node, _) in self.flatten() {
+            let texture = node.material.as_ref().and_then(|m| m.texture.as_deref());
+            for path in node.mesh.as_deref().into_iter().chain(texture) {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths
+    }
+
+    pub fn to_ron(&self) -> Result<String, SceneError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(SceneError::Ron)
+    }
+
+    pub fn from_ron(s: &str) -> Result<Self, SceneError> {
+        Ok(ron::from_str(s)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, SceneError> {
+        serde_json::to_string_pretty(self).map_err(SceneError::Json)
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, SceneError> {
+        serde_json::from_str(s).map_err(SceneError::Json)
+    }
+
+    /// Writes the scene as RON or JSON depending on the extension of `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        let path = path.as_ref();
+        let contents = match SceneFormat::from_path(path) {
+            Some(SceneFormat::Ron) => self.to_ron()?,
+            Some(SceneFormat::Json) => self.to_json()?,
+            None => return Err(SceneError::UnknownFormat(path.to_path_buf())),
+        };
+        std::fs::write(path, contents).map_err(SceneError::Io)
+    }
+
+    /// Reads a scene saved by `save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let format = SceneFormat::from_path(path)
+            .ok_or_else(|| SceneError::UnknownFormat(path.to_path_buf()))?;
+        let contents = std::fs::read_to_string(path).map_err(SceneError::Io)?;
+        match format {
+            SceneFormat::Ron => Self::from_ron(&contents),
+            SceneFormat::Json => Self::from_json(&contents),
+        }
+    }
+
+    /// Spawns an entity per node with its world `Transform`, and a `MeshHandle` loaded
+    /// through `assets`, the `Material`, the `Light` and a `Camera` where the node has
+    /// them. Returns the entities in `flatten` order.
+    #[cfg(feature = "ecs")]
+    pub fn spawn(
+        &self,
+        world: &mut crate::ecs::World,
+        assets: &mut crate::assets::Assets,
+        aspect: f32,
+    ) -> Vec<crate::ecs::Entity> {
+        self.flatten()
+            .into_iter()
+            .map(|(node, transform)| {
+                let entity = world.spawn();
+                world.insert(entity, transform);
+                if let Some(path) = &node.mesh {
+                    world.insert(entity, crate::ecs::MeshHandle(assets.load(path)));
+                }
+                if let Some(material) = &node.material {
+                    world.insert(entity, material.clone());
+                }
+                if let Some(light) = node.light {
+                    world.insert(entity, light);
+                }
+                if let Some(camera) = &node.camera {
+                    world.insert(entity, camera.camera(&transform, aspect));
+                }
+                entity
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec3;
+
+    fn scene() -> Scene {
+        Scene::new()
+            .with_node(
+                Node::new("ship")
+                    .with_transform(Transform::from_translation(Vec3::new(1.0, 2.0, 3.0)))
+                    .with_mesh("meshes/ship.obj")
+                    .with_material(Material {
+                        base_color: [0.5, 0.25, 1.0, 1.0],
+                        texture: Some("textures/hull.png".into()),
+                        ..Material::default()
+                    })
+                    .with_child(
+                        Node::new("lamp")
+                            .with_transform(Transform::from_translation(Vec3::new(0.0, 1.0, 0.0)))
+                            .with_light(Light::Point {
+                                color: [1.0, 0.9, 0.8],
+                                intensity: 4.0,
+                                range: 10.0,
+                            }),
+                    ),
+            )
+            .with_node(Node::new("camera").with_camera(Perspective::default()))
+    }
+
+    #[test]
+    fn round_trips() {
+        let scene = scene();
+        assert_eq!(Scene::from_ron(&scene.to_ron().unwrap()).unwrap(), scene);
+        assert_eq!(Scene::from_json(&scene.to_json().unwrap()).unwrap(), scene);
+    }
+
+    #[test]
+    fn missing_fields_default() {
+        let scene = Scene::from_ron("(nodes: [(name: \"empty\", material: Some(()))])").unwrap();
+        assert_eq!(scene.nodes[0].transform, Transform::IDENTITY);
+        assert_eq!(scene.nodes[0].material, Some(Material::default()));
+    }
+
+    #[test]
+    fn flattens_with_world_transforms() {
+        let scene = scene();
+        let nodes = scene.flatten();
+        let names: Vec<_> = nodes.iter().map(|(node, _)| node.name.as_str()).collect();
+        assert_eq!(names, ["ship", "lamp", "camera"]);
+        assert_eq!(nodes[1].1.translation, Vec3::new(1.0, 3.0, 3.0));
+        assert!(scene.find("lamp").and_then(|node| node.light).is_some());
+        assert_eq!(
+            scene.asset_paths(),
+            [Path::new("meshes/ship.obj"), Path::new("textures/hull.png")]
+        );
+    }
+}
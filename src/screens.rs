@@ -0,0 +1,560 @@
+//! Menus, gameplay, pause and the like as separate `Screen`s on a stack, instead of one
+//! `App` switching between them.
+//!
+//! A `ScreenStack` is an `App` that hands everything to the screen on top. A screen's
+//! `update` returns a `Transition` to push another screen over it, pop itself to go back
+//! to the one below, or replace itself. Screens below the top are kept as they are, so a
+//! pause menu pushed over the game returns to it exactly as it was, and an overlay screen
+//! has the one below drawn first, for menus over the game. With `with_fade` the stack fades
+//! to a color and back across every transition.
+//!
+//! `LoadingScreen` is a ready made screen that shows a progress bar while assets load,
+//! then replaces itself with the screen that needs them.
+
+use winit::event::WindowEvent;
+
+use crate::assets::{Asset, Assets, Handle, LoadState};
+use crate::context::Context;
+use crate::frame::Frame;
+use crate::math::Vec2;
+use crate::shapes::ShapeRenderer;
+use crate::text::{Font, FontId, TextRenderer};
+use crate::window::App;
+
+const LOADING_BAR_HEIGHT: f32 = 8.0;
+/// Of the smaller side of the window.
+const LOADING_BAR_WIDTH: f32 = 0.6;
+const LOADING_FONT_SIZE: f32 = 18.0;
+
+/// What a screen's `update` asks of the stack.
+#[derive(Default)]
+pub enum Transition {
+    #[default]
+    None,
+    /// Puts a screen on top, pausing this one until it's popped.
+    Push(Box<dyn Screen>),
+    /// Removes the top screen, resuming the one below.
+    Pop,
+    /// Removes the top screen and puts another in its place.
+    Replace(Box<dyn Screen>),
+    /// Removes every screen and starts over with another, e.g. back to the title.
+    Reset(Box<dyn Screen>),
+}
+
+/// One state of the game, see the module docs. Only `render` is required.
+pub trait Screen: 'static {
+    /// Called when the screen is put on the stack.
+    fn on_enter(&mut self, _ctx: &mut Context) {}
+    /// Called when the screen is removed from the stack.
+    fn on_exit(&mut self, _ctx: &mut Context) {}
+    /// Called when another screen is pushed over this one.
+    fn on_pause(&mut self, _ctx: &mut Context) {}
+    /// Called when the screen is on top again after the one over it was popped.
+    fn on_resume(&mut self, _ctx: &mut Context) {}
+    /// Called once per frame while the screen is on top, like `App::update`.
+    fn update(&mut self, _ctx: &mut Context, _dt: f32) -> Transition {
+        Transition::None
+    }
+    /// Called while the screen is on top, like `App::fixed_update`.
+    fn fixed_update(&mut self, _ctx: &mut Context, _dt: f32) {}
+    /// Called while the screen is on top, like `App::input`.
+    fn input(&mut self, _ctx: &mut Context, _event: &WindowEvent) -> bool {
+        false
+    }
+    /// Called for every screen on the stack.
+    fn resize(&mut self, _ctx: &mut Context, _size: winit::dpi::PhysicalSize<u32>) {}
+    fn render(&mut self, ctx: &mut Context, frame: &mut Frame);
+    /// Whether the screen below is drawn before this one, for menus and dialogs shown over
+    /// the game. Only the top screen is updated either way.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+/// The `Screen` hooks a transition calls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Hook {
+    Enter,
+    Exit,
+    Pause,
+    Resume,
+}
+
+/// A fade across a transition: out to the color, the transition, back in.
+struct Fade {
+    /// Real seconds since the fade started.
+    elapsed: f32,
+    /// Applied once the screen is covered, `None` after.
+    transition: Option<Transition>,
+}
+
+/// Runs a stack of screens as an `App`, see the module docs.
+pub struct ScreenStack {
+    screens: Vec<Box<dyn Screen>>,
+    /// Seconds a faded transition takes, out and in together. 0 switches at once.
+    fade_duration: f32,
+    fade_color: [f32; 3],
+    fade: Option<Fade>,
+    /// Created with the first fade.
+    shapes: Option<ShapeRenderer>,
+}
+
+impl ScreenStack {
+    /// A stack with `screen` entered on it.
+    pub fn new(ctx: &mut Context, screen: impl Screen) -> Self {
+        let mut stack = Self {
+            screens: Vec::new(),
+            fade_duration: 0.0,
+            fade_color: [0.0; 3],
+            fade: None,
+            shapes: None,
+        };
+        stack.apply(ctx, Transition::Push(Box::new(screen)));
+        stack
+    }
+
+    /// Fades to `color` and back over `duration` seconds of real time on every transition.
+    /// Screens aren't updated while fading out.
+    pub fn with_fade(mut self, duration: f32, color: [f32; 3]) -> Self {
+        self.fade_duration = duration.max(0.0);
+        self.fade_color = color;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.screens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.screens.is_empty()
+    }
+
+    /// Carries out `transition` at once, without fading, e.g. for transitions from outside
+    /// the screens.
+    pub fn apply(&mut self, ctx: &mut Context, transition: Transition) {
+        self.apply_with(transition, |screen, hook| match hook {
+            Hook::Enter => screen.on_enter(ctx),
+            Hook::Exit => screen.on_exit(ctx),
+            Hook::Pause => screen.on_pause(ctx),
+            Hook::Resume => screen.on_resume(ctx),
+        });
+    }
+
+    /// `apply`, calling the screens' hooks through `call`.
+    fn apply_with(&mut self, transition: Transition, mut call: impl FnMut(&mut dyn Screen, Hook)) {
+        match transition {
+            Transition::None => {}
+            Transition::Push(mut screen) => {
+                if let Some(top) = self.screens.last_mut() {
+                    call(top.as_mut(), Hook::Pause);
+                }
+                call(screen.as_mut(), Hook::Enter);
+                self.screens.push(screen);
+            }
+            Transition::Pop => {
+                if let Some(mut screen) = self.screens.pop() {
+                    call(screen.as_mut(), Hook::Exit);
+                }
+                if let Some(top) = self.screens.last_mut() {
+                    call(top.as_mut(), Hook::Resume);
+                }
+            }
+            Transition::Replace(mut screen) => {
+                if let Some(mut old) = self.screens.pop() {
+                    call(old.as_mut(), Hook::Exit);
+                }
+                call(screen.as_mut(), Hook::Enter);
+                self.screens.push(screen);
+            }
+            Transition::Reset(mut screen) => {
+                while let Some(mut old) = self.screens.pop() {
+                    call(old.as_mut(), Hook::Exit);
+                }
+                call(screen.as_mut(), Hook::Enter);
+                self.screens.push(screen);
+            }
+        }
+    }
+
+    /// Carries out `transition`, fading across it if `with_fade` set a duration. Ignored
+    /// while another transition fades out.
+    pub fn transition(&mut self, ctx: &mut Context, transition: Transition) {
+        if matches!(transition, Transition::None) || self.is_fading_out() {
+            return;
+        }
+        if self.fade_duration > 0.0 {
+            self.fade = Some(Fade {
+                elapsed: 0.0,
+                transition: Some(transition),
+            });
+        } else {
+            self.apply(ctx, transition);
+        }
+    }
+
+    fn is_fading_out(&self) -> bool {
+        self.fade
+            .as_ref()
+            .is_some_and(|fade| fade.transition.is_some())
+    }
+
+    /// How much the fade color covers the screens, from 0 to 1.
+    fn fade_amount(&self) -> f32 {
+        let Some(fade) = &self.fade else {
+            return 0.0;
+        };
+        let half = self.fade_duration * 0.5;
+        if fade.transition.is_some() {
+            (fade.elapsed / half).min(1.0)
+        } else {
+            (1.0 - (fade.elapsed - half) / half).clamp(0.0, 1.0)
+        }
+    }
+
+    fn update_fade(&mut self, ctx: &mut Context) {
+        let Some(fade) = &mut self.fade else {
+            return;
+        };
+        fade.elapsed += ctx.time.real_delta();
+        if fade.elapsed >= self.fade_duration * 0.5 {
+            if let Some(transition) = fade.transition.take() {
+                self.apply(ctx, transition);
+            }
+        }
+        if self
+            .fade
+            .as_ref()
+            .is_some_and(|fade| fade.elapsed >= self.fade_duration)
+        {
+            self.fade = None;
+        }
+    }
+
+    fn draw_fade(&mut self, ctx: &Context, frame: &mut Frame) {
+        let amount = self.fade_amount();
+        if amount <= 0.0 {
+            return;
+        }
+        let shapes = self
+            .shapes
+            .get_or_insert_with(|| ShapeRenderer::new(ctx.device(), ctx.surface_format()));
+        let size = ctx.size();
+        let [r, g, b] = self.fade_color;
+        shapes.fill_rect(
+            Vec2::ZERO,
+            Vec2::new(size.width as f32, size.height as f32),
+            [r, g, b, amount],
+        );
+        let view_proj = ShapeRenderer::pixel_projection(size.width as f32, size.height as f32);
+        shapes.prepare(ctx.device(), ctx.queue(), view_proj);
+        let mut render_pass = frame.begin_named_pass("Screen Fade Pass", None);
+        shapes.render(&mut render_pass);
+    }
+}
+
+impl App for ScreenStack {
+    fn update(&mut self, ctx: &mut Context, dt: f32) {
+        self.update_fade(ctx);
+        if self.is_fading_out() {
+            return;
+        }
+        if let Some(top) = self.screens.last_mut() {
+            let transition = top.update(ctx, dt);
+            self.transition(ctx, transition);
+        }
+    }
+
+    fn fixed_update(&mut self, ctx: &mut Context, dt: f32) {
+        if self.is_fading_out() {
+            return;
+        }
+        if let Some(top) = self.screens.last_mut() {
+            top.fixed_update(ctx, dt);
+        }
+    }
+
+    fn input(&mut self, ctx: &mut Context, event: &WindowEvent) -> bool {
+        if self.is_fading_out() {
+            return false;
+        }
+        self.screens
+            .last_mut()
+            .is_some_and(|top| top.input(ctx, event))
+    }
+
+    fn resize(&mut self, ctx: &mut Context, size: winit::dpi::PhysicalSize<u32>) {
+        for screen in &mut self.screens {
+            screen.resize(ctx, size);
+        }
+    }
+
+    fn render(&mut self, ctx: &mut Context, frame: &mut Frame) {
+        if self.screens.is_empty() {
+            frame.begin_named_pass("Screen Clear Pass", Some(wgpu::Color::BLACK));
+        }
+        // the top screen and the ones it's an overlay of, bottom first
+        let first = self
+            .screens
+            .iter()
+            .rposition(|screen| !screen.is_overlay())
+            .unwrap_or(0);
+        for screen in &mut self.screens[first..] {
+            screen.render(ctx, frame);
+        }
+        self.draw_fade(ctx, frame);
+    }
+}
+
+type LoadCheck = Box<dyn Fn(&Assets) -> LoadState>;
+type NextScreen = Box<dyn FnOnce(&mut Context) -> Box<dyn Screen>>;
+
+/// Shows the progress of a set of asset loads, then replaces itself with the next screen
+/// once every one of them has loaded or failed.
+///
+/// Track the handles the next screen uses and have the closure building it take clones of
+/// them, so they stay loaded in between.
+pub struct LoadingScreen {
+    /// The load state of each tracked handle, which the closures keep alive.
+    loads: Vec<LoadCheck>,
+    next: Option<NextScreen>,
+    font: Option<Font>,
+    pub background: [f32; 3],
+    pub bar_color: [f32; 4],
+    pub track_color: [f32; 4],
+    /// Created when the screen is entered.
+    renderers: Option<(ShapeRenderer, Option<(TextRenderer, FontId)>)>,
+}
+
+impl LoadingScreen {
+    /// A loading screen replaced by what `next` builds once the tracked handles are in.
+    pub fn new<S: Screen>(next: impl FnOnce(&mut Context) -> S + 'static) -> Self {
+        Self {
+            loads: Vec::new(),
+            next: Some(Box::new(move |ctx| Box::new(next(ctx)))),
+            font: None,
+            background: [0.02, 0.02, 0.03],
+            bar_color: [0.9, 0.9, 0.9, 1.0],
+            track_color: [1.0, 1.0, 1.0, 0.15],
+            renderers: None,
+        }
+    }
+
+    pub fn with_handle<T: Asset>(mut self, handle: &Handle<T>) -> Self {
+        self.track(handle);
+        self
+    }
+
+    /// Waits for `handle` too, keeping its asset loaded until the next screen takes over.
+    pub fn track<T: Asset>(&mut self, handle: &Handle<T>) {
+        let handle = handle.clone();
+        self.loads
+            .push(Box::new(move |assets| assets.load_state(&handle)));
+    }
+
+    /// Writes how many of the assets are in above the bar, which is all that's shown
+    /// without a font.
+    pub fn with_font(mut self, font: Font) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    /// Tracked assets done loading, failed ones included, and how many there are.
+    pub fn progress(&self, assets: &Assets) -> (usize, usize) {
+        let done = self
+            .loads
+            .iter()
+            .filter(|state| state(assets) != LoadState::Loading)
+            .count();
+        (done, self.loads.len())
+    }
+}
+
+impl Screen for LoadingScreen {
+    fn on_enter(&mut self, ctx: &mut Context) {
+        let format = ctx.surface_format();
+        let text = self.font.take().map(|font| {
+            let mut text = TextRenderer::new(ctx.device(), format);
+            let font = text.add_font(font);
+            (text, font)
+        });
+        self.renderers = Some((ShapeRenderer::new(ctx.device(), format), text));
+    }
+
+    fn update(&mut self, ctx: &mut Context, _dt: f32) -> Transition {
+        let (done, total) = self.progress(&ctx.assets);
+        if done < total {
+            return Transition::None;
+        }
+        match self.next.take() {
+            Some(next) => Transition::Replace(next(ctx)),
+            None => Transition::None,
+        }
+    }
+
+    fn render(&mut self, ctx: &mut Context, frame: &mut Frame) {
+        let (done, total) = self.progress(&ctx.assets);
+        let Some((shapes, text)) = &mut self.renderers else {
+            return;
+        };
+        let size = ctx.size();
+        let (width, height) = (size.width as f32, size.height as f32);
+        let bar_width = width.min(height) * LOADING_BAR_WIDTH;
+        let min = Vec2::new(
+            (width - bar_width) * 0.5,
+            (height - LOADING_BAR_HEIGHT) * 0.5,
+        );
+        let max = min + Vec2::new(bar_width, LOADING_BAR_HEIGHT);
+        let fraction = if total == 0 {
+            1.0
+        } else {
+            done as f32 / total as f32
+        };
+        shapes.fill_rect(min, max, self.track_color);
+        shapes.fill_rect(
+            min,
+            Vec2::new(min.x + bar_width * fraction, max.y),
+            self.bar_color,
+        );
+
+        let view_proj = ShapeRenderer::pixel_projection(width, height);
+        shapes.prepare(ctx.device(), ctx.queue(), view_proj);
+        if let Some((text, font)) = text {
+            let label = format!("Loading {} / {}", done, total);
+            let label_size = text.measure(*font, &label, LOADING_FONT_SIZE);
+            let position = Vec2::new(
+                (width - label_size.x) * 0.5,
+                min.y - label_size.y - LOADING_BAR_HEIGHT,
+            );
+            text.draw(*font, &label, position, LOADING_FONT_SIZE, self.bar_color);
+            text.prepare(ctx.device(), ctx.queue(), view_proj);
+        }
+
+        let [r, g, b] = self.background;
+        let clear = wgpu::Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: 1.0,
+        };
+        let mut render_pass = frame.begin_named_pass("Loading Screen Pass", Some(clear));
+        shapes.render(&mut render_pass);
+        if let Some((text, _)) = text {
+            text.render(&mut render_pass);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sized, so that every screen gets an address of its own.
+    struct Named {
+        _name: &'static str,
+    }
+
+    impl Screen for Named {
+        fn render(&mut self, _ctx: &mut Context, _frame: &mut Frame) {}
+    }
+
+    /// Screens are told apart by address, as the hooks only see `dyn Screen`. The latest
+    /// screen at an address wins, as a dropped screen's may be reused.
+    struct Harness {
+        stack: ScreenStack,
+        names: Vec<(*const (), &'static str)>,
+        log: Vec<(&'static str, Hook)>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            Self {
+                stack: ScreenStack {
+                    screens: Vec::new(),
+                    fade_duration: 0.0,
+                    fade_color: [0.0; 3],
+                    fade: None,
+                    shapes: None,
+                },
+                names: Vec::new(),
+                log: Vec::new(),
+            }
+        }
+
+        fn screen(&mut self, name: &'static str) -> Box<dyn Screen> {
+            let screen: Box<dyn Screen> = Box::new(Named { _name: name });
+            self.names
+                .push((screen.as_ref() as *const dyn Screen as *const (), name));
+            screen
+        }
+
+        fn apply(&mut self, transition: Transition) {
+            let (names, log) = (&self.names, &mut self.log);
+            self.stack.apply_with(transition, |screen, hook| {
+                let address = screen as *const dyn Screen as *const ();
+                let name = names.iter().rev().find(|(a, _)| *a == address).unwrap().1;
+                log.push((name, hook));
+            });
+        }
+
+        fn take_log(&mut self) -> Vec<(&'static str, Hook)> {
+            std::mem::take(&mut self.log)
+        }
+    }
+
+    #[test]
+    fn push_pauses_and_pop_resumes() {
+        let mut h = Harness::new();
+        let game = h.screen("game");
+        h.apply(Transition::Push(game));
+        let pause = h.screen("pause");
+        h.apply(Transition::Push(pause));
+        assert_eq!(
+            h.take_log(),
+            [
+                ("game", Hook::Enter),
+                ("game", Hook::Pause),
+                ("pause", Hook::Enter)
+            ]
+        );
+        assert_eq!(h.stack.len(), 2);
+        h.apply(Transition::Pop);
+        assert_eq!(
+            h.take_log(),
+            [("pause", Hook::Exit), ("game", Hook::Resume)]
+        );
+        assert_eq!(h.stack.len(), 1);
+    }
+
+    #[test]
+    fn replace_and_reset() {
+        let mut h = Harness::new();
+        for name in ["title", "game", "pause"] {
+            let screen = h.screen(name);
+            h.apply(Transition::Push(screen));
+        }
+        h.take_log();
+        let options = h.screen("options");
+        h.apply(Transition::Replace(options));
+        assert_eq!(
+            h.take_log(),
+            [("pause", Hook::Exit), ("options", Hook::Enter)]
+        );
+        assert_eq!(h.stack.len(), 3);
+        let title = h.screen("title again");
+        h.apply(Transition::Reset(title));
+        assert_eq!(
+            h.take_log(),
+            [
+                ("options", Hook::Exit),
+                ("game", Hook::Exit),
+                ("title", Hook::Exit),
+                ("title again", Hook::Enter),
+            ]
+        );
+        assert_eq!(h.stack.len(), 1);
+        h.apply(Transition::Pop);
+        h.apply(Transition::Pop);
+        assert!(h.stack.is_empty());
+        assert_eq!(h.take_log(), [("title again", Hook::Exit)]);
+    }
+}
@@ -0,0 +1,433 @@
+//! Graphics settings chosen at startup: backend, adapter, vsync, window size and mode, and
+//! MSAA, read from the standard command line flags, the environment and a settings file,
+//! so every app doesn't parse its own and packaged apps can ship settings users can edit.
+//!
+//! Every setting has a name and takes a string value:
+//!
+//! ```text
+//! backend     vulkan|metal|dx12|gl|webgpu   backends to try, comma separated
+//! adapter     <name or index>               an adapter whose name contains it, or the nth
+//! vsync       on|off
+//! fullscreen  on|off
+//! size        1280x720                      window size in physical pixels
+//! msaa        1|2|4|8                       samples per pixel
+//! ```
+//!
+//! Start from the defaults or the app's own `with_*` values, then lay the sources over
+//! them in the order they should win: `with_file` (needs the `config` feature), `with_env`
+//! and `with_args`. The last one hands back the arguments it doesn't know for the app.
+//! Pass the result to `window::run_app_with_settings`, or
+//! `render_thread::run_threaded_with_settings`.
+
+use std::fmt;
+
+/// Names of the settings, see the module docs.
+const NAMES: [&str; 6] = ["backend", "adapter", "vsync", "fullscreen", "size", "msaa"];
+
+#[derive(Debug)]
+pub enum SettingsError {
+    /// A flag that needs a value came last.
+    MissingValue(String),
+    /// `source` is the flag, environment variable or file the value came from.
+    InvalidValue { source: String, value: String },
+    #[cfg(feature = "config")]
+    Config(crate::config::ConfigError),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            SettingsError::InvalidValue { source, value } => {
+                write!(f, "invalid value for {}: {}", source, value)
+            }
+            #[cfg(feature = "config")]
+            SettingsError::Config(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// The flags `with_args` knows, for an app's `--help`.
+pub const USAGE: &str = "\
+  --backend <list>     graphics backends to try: vulkan, metal, dx12, gl, webgpu
+  --adapter <name|n>   GPU whose name contains <name>, or the <n>th one found
+  --vsync, --no-vsync  wait for vertical blank or not
+  --fullscreen         start in borderless fullscreen
+  --size <W>x<H>       window size in pixels
+  --msaa <samples>     samples per pixel for anti-aliasing: 1, 2, 4 or 8";
+
+/// See the module docs. The defaults leave every choice to the crate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphicsSettings {
+    /// `None` for `WGPU_BACKEND` if set, or else the primary backends of the platform.
+    pub backends: Option<wgpu::Backends>,
+    /// Part of an adapter's name, case insensitive, or its index among the adapters of
+    /// `backends`. `None` picks one that can draw to the window.
+    pub adapter: Option<String>,
+    /// `None` keeps the default, which is vsync.
+    pub vsync: Option<bool>,
+    pub fullscreen: bool,
+    /// Inner size of the window in physical pixels, `None` for the platform's default.
+    pub size: Option<winit::dpi::PhysicalSize<u32>>,
+    /// Samples per pixel apps should draw with, see `Context::sample_count`.
+    pub msaa: u32,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            backends: None,
+            adapter: None,
+            vsync: None,
+            fullscreen: false,
+            size: None,
+            msaa: 1,
+        }
+    }
+}
+
+impl GraphicsSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = Some(backends);
+        self
+    }
+
+    pub fn with_adapter(mut self, adapter: impl Into<String>) -> Self {
+        self.adapter = Some(adapter.into());
+        self
+    }
+
+    pub fn with_vsync(mut self, vsync: bool) -> Self {
+        self.vsync = Some(vsync);
+        self
+    }
+
+    pub fn with_fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some(winit::dpi::PhysicalSize::new(width, height));
+        self
+    }
+
+    pub fn with_msaa(mut self, samples: u32) -> Self {
+        self.msaa = samples;
+        self
+    }
+
+    /// Sets the setting called `name` from `value`, as the module docs list them. `source`
+    /// names where the value came from in the error.
+    fn set(&mut self, name: &str, value: &str, source: &str) -> Result<(), SettingsError> {
+        let invalid = || SettingsError::InvalidValue {
+            source: source.to_string(),
+            value: value.to_string(),
+        };
+        match name {
+            "backend" => self.backends = Some(parse_backends(value).ok_or_else(invalid)?),
+            "adapter" => self.adapter = Some(value.to_string()),
+            "vsync" => self.vsync = Some(parse_switch(value).ok_or_else(invalid)?),
+            "fullscreen" => self.fullscreen = parse_switch(value).ok_or_else(invalid)?,
+            "size" => self.size = Some(parse_size(value).ok_or_else(invalid)?),
+            "msaa" => {
+                self.msaa = match value.trim().parse() {
+                    Ok(samples @ (1 | 2 | 4 | 8)) => samples,
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => unreachable!("unknown setting {}", name),
+        }
+        Ok(())
+    }
+
+    /// Settings from the process's command line, and the arguments left for the app.
+    pub fn from_args() -> Result<(Self, Vec<String>), SettingsError> {
+        Self::default().with_args(std::env::args().skip(1))
+    }
+
+    /// Like `from_args`, for `args` without the program name.
+    pub fn parse_args<S: Into<String>>(
+        args: impl IntoIterator<Item = S>,
+    ) -> Result<(Self, Vec<String>), SettingsError> {
+        Self::default().with_args(args)
+    }
+
+    /// Overrides the settings `args` set with `--name value` or `--name=value`, and
+    /// returns the arguments left for the app. `--vsync` and `--fullscreen` may go without
+    /// a value to turn them on, then an `on` or `off` after them is theirs and anything else
+    /// is left for the app. `--no-vsync` turns vsync off.
+    pub fn with_args<S: Into<String>>(
+        mut self,
+        args: impl IntoIterator<Item = S>,
+    ) -> Result<(Self, Vec<String>), SettingsError> {
+        let mut rest = Vec::new();
+        let mut args = args.into_iter().map(Into::into).peekable();
+        while let Some(arg) = args.next() {
+            if arg == "--no-vsync" {
+                self.vsync = Some(false);
+                continue;
+            }
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            let Some(name) = flag.strip_prefix("--").filter(|name| NAMES.contains(name)) else {
+                rest.push(arg);
+                continue;
+            };
+            let value = match inline {
+                Some(value) => value,
+                None if matches!(name, "vsync" | "fullscreen") => match args.peek() {
+                    Some(next) if parse_switch(next).is_some() => args.next().unwrap_or_default(),
+                    _ => "on".to_string(),
+                },
+                None => args
+                    .next()
+                    .ok_or_else(|| SettingsError::MissingValue(flag.to_string()))?,
+            };
+            self.set(name, &value, flag)?;
+        }
+        Ok((self, rest))
+    }
+
+    /// Overrides the settings set in the environment, in variables named `prefix`, an
+    /// underscore and the setting's name in upper case, e.g. `MYGAME_MSAA=4`. Unset and
+    /// empty variables are skipped.
+    pub fn with_env(mut self, prefix: &str) -> Result<Self, SettingsError> {
+        for name in NAMES {
+            let variable = format!("{}_{}", prefix, name.to_uppercase());
+            match std::env::var(&variable) {
+                Ok(value) if !value.is_empty() => self.set(name, &value, &variable)?,
+                _ => {}
+            }
+        }
+        Ok(self)
+    }
+
+    /// Overrides the settings a RON or TOML file sets, by name at the top level, with
+    /// booleans for the switches, `msaa` a number and the rest strings. Other entries are
+    /// left to the app, so the file can hold its settings too. A file that doesn't exist
+    /// changes nothing, so it only needs to be shipped once there's something to set.
+    #[cfg(feature = "config")]
+    pub fn with_file(mut self, path: impl AsRef<std::path::Path>) -> Result<Self, SettingsError> {
+        use crate::config::ConfigError;
+
+        let path = path.as_ref();
+        let file: SettingsFile = match crate::config::read(path) {
+            Ok(file) => file,
+            Err(ConfigError::Io(_, e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(self)
+            }
+            Err(e) => return Err(SettingsError::Config(e)),
+        };
+        let values = [
+            ("backend", file.backend),
+            ("adapter", file.adapter),
+            ("vsync", file.vsync.map(switch)),
+            ("fullscreen", file.fullscreen.map(switch)),
+            ("size", file.size),
+            ("msaa", file.msaa.map(|samples| samples.to_string())),
+        ];
+        for (name, value) in values {
+            if let Some(value) = value {
+                self.set(name, &value, &format!("{}: {}", path.display(), name))?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// The backends to create the instance with, see `backends`.
+    pub(crate) fn instance_backends(&self) -> wgpu::Backends {
+        self.backends
+            .or_else(wgpu::util::backend_bits_from_env)
+            .unwrap_or(wgpu::Backends::PRIMARY)
+    }
+
+    /// The adapter named by `adapter` among those of `backends`, if any is.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn find_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        backends: wgpu::Backends,
+    ) -> Option<wgpu::Adapter> {
+        let wanted = self.adapter.as_deref()?;
+        let adapters: Vec<wgpu::Adapter> = instance.enumerate_adapters(backends).collect();
+        let found = match wanted.parse::<usize>() {
+            Ok(index) => adapters.into_iter().nth(index),
+            Err(_) => {
+                let wanted = wanted.to_lowercase();
+                adapters
+                    .into_iter()
+                    .find(|adapter| adapter.get_info().name.to_lowercase().contains(&wanted))
+            }
+        };
+        if found.is_none() {
+            log::warn!("no adapter matches {:?}, picking one", wanted);
+        }
+        found
+    }
+}
+
+/// The settings `with_file` reads, each optional.
+#[cfg(feature = "config")]
+#[derive(serde::Deserialize)]
+struct SettingsFile {
+    backend: Option<String>,
+    adapter: Option<String>,
+    vsync: Option<bool>,
+    fullscreen: Option<bool>,
+    size: Option<String>,
+    msaa: Option<u32>,
+}
+
+#[cfg(feature = "config")]
+fn switch(on: bool) -> String {
+    if on { "on" } else { "off" }.to_string()
+}
+
+/// `None` for names wgpu doesn't know.
+fn parse_backends(list: &str) -> Option<wgpu::Backends> {
+    let backends = wgpu::util::parse_backends_from_comma_list(&list.to_lowercase());
+    (!backends.is_empty()).then_some(backends)
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Some(true),
+        "off" | "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_size(size: &str) -> Option<winit::dpi::PhysicalSize<u32>> {
+    let (width, height) = size.split_once(['x', 'X'])?;
+    let (width, height) = (width.trim().parse().ok()?, height.trim().parse().ok()?);
+    (width > 0 && height > 0).then(|| winit::dpi::PhysicalSize::new(width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<(GraphicsSettings, Vec<String>), SettingsError> {
+        GraphicsSettings::parse_args(args.iter().copied())
+    }
+
+    #[test]
+    fn flags_with_values() {
+        let (settings, rest) = parse(&[
+            "--backend",
+            "gl",
+            "--size=800x600",
+            "--msaa",
+            "4",
+            "--adapter",
+            "1",
+        ])
+        .unwrap();
+        assert_eq!(settings.backends, Some(wgpu::Backends::GL));
+        assert_eq!(settings.size, Some(winit::dpi::PhysicalSize::new(800, 600)));
+        assert_eq!(settings.msaa, 4);
+        assert_eq!(settings.adapter.as_deref(), Some("1"));
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn unknown_arguments_are_left_for_the_app() {
+        let (settings, rest) = parse(&["level.map", "--vsync", "--speed", "2"]).unwrap();
+        assert_eq!(settings.vsync, Some(true));
+        assert_eq!(rest, ["level.map", "--speed", "2"]);
+    }
+
+    #[test]
+    fn switches_take_an_optional_value() {
+        let (settings, rest) = parse(&["--vsync", "off", "--fullscreen", "false"]).unwrap();
+        assert_eq!(settings.vsync, Some(false));
+        assert!(!settings.fullscreen);
+        assert!(rest.is_empty());
+
+        let (settings, _) = parse(&["--fullscreen", "--no-vsync"]).unwrap();
+        assert!(settings.fullscreen);
+        assert_eq!(settings.vsync, Some(false));
+
+        let (settings, _) = parse(&["--vsync=no"]).unwrap();
+        assert_eq!(settings.vsync, Some(false));
+
+        let (settings, rest) = parse(&["--fullscreen", "level.map", "--vsync", "4"]).unwrap();
+        assert!(settings.fullscreen);
+        assert_eq!(settings.vsync, Some(true));
+        assert_eq!(rest, ["level.map", "4"]);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(matches!(
+            parse(&["--vsync=sometimes"]),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--msaa", "3"]),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--size", "0x600"]),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--backend", "glide"]),
+            Err(SettingsError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse(&["--msaa"]),
+            Err(SettingsError::MissingValue(_))
+        ));
+    }
+
+    #[test]
+    fn environment_overrides() {
+        // a prefix of its own, tests run in parallel in one process
+        std::env::set_var("SETTINGS_TEST_MSAA", "2");
+        std::env::set_var("SETTINGS_TEST_VSYNC", "off");
+        std::env::set_var("SETTINGS_TEST_ADAPTER", "");
+        let settings = GraphicsSettings::new()
+            .with_adapter("nvidia")
+            .with_env("SETTINGS_TEST")
+            .unwrap();
+        assert_eq!(settings.msaa, 2);
+        assert_eq!(settings.vsync, Some(false));
+        assert_eq!(settings.adapter.as_deref(), Some("nvidia"));
+
+        std::env::set_var("SETTINGS_TEST_BAD_SIZE", "big");
+        assert!(matches!(
+            GraphicsSettings::new().with_env("SETTINGS_TEST_BAD"),
+            Err(SettingsError::InvalidValue { source, .. }) if source == "SETTINGS_TEST_BAD_SIZE"
+        ));
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn file_overrides() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("settings_test_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "msaa = 8\nfullscreen = true\nsize = \"640x480\"\nname = \"app\"\n",
+        )
+        .unwrap();
+        let settings = GraphicsSettings::new().with_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(settings.msaa, 8);
+        assert!(settings.fullscreen);
+        assert_eq!(settings.size, Some(winit::dpi::PhysicalSize::new(640, 480)));
+
+        let missing = GraphicsSettings::new().with_file(dir.join("no_such_settings.toml"));
+        assert_eq!(missing.unwrap(), GraphicsSettings::new());
+    }
+}
@@ -0,0 +1,551 @@
+//! Filled and stroked 2D vector shapes, tessellated with lyon.
+//!
+//! Build paths with `shapes::path::Path::builder()` (or `svg_builder()` for arcs),
+//! queue them every frame, then `prepare` and `render`.
+//!
+//! Shapes draw by the renderer's `layer` when they were queued, lower layers first and in
+//! queue order within a layer. Consecutive shapes with the same blend mode after sorting
+//! share a draw call.
+
+use std::ops::{Range, RangeBounds};
+
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator,
+    StrokeVertex, VertexBuffers,
+};
+
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::lines::{LineCap, LineJoin};
+use crate::math::{Mat4, Vec2};
+use crate::particles::BlendMode;
+use crate::stats::Tracked;
+
+pub use lyon::math::{point, Box2D, Point};
+pub use lyon::path;
+pub use lyon::path::{FillRule, Path, Winding};
+
+const SHADER: &str = r#"
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(in.position, 0.0, 1.0);
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct ShapeVertex {
+    pub(crate) position: [f32; 2],
+    pub(crate) color: [f32; 4],
+}
+unsafe impl bytemuck::Pod for ShapeVertex {}
+unsafe impl bytemuck::Zeroable for ShapeVertex {}
+
+impl ShapeVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4];
+
+    pub(crate) fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Outline settings for `stroke_*` calls. Width is in the same units as the shape.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    pub miter_limit: f32,
+}
+
+impl Default for Stroke {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+        }
+    }
+}
+
+impl Stroke {
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn options(&self, tolerance: f32) -> StrokeOptions {
+        let join = match self.join {
+            LineJoin::Miter => lyon::tessellation::LineJoin::MiterClip,
+            LineJoin::Round => lyon::tessellation::LineJoin::Round,
+            LineJoin::Bevel => lyon::tessellation::LineJoin::Bevel,
+        };
+        let cap = match self.cap {
+            LineCap::Butt => lyon::tessellation::LineCap::Butt,
+            LineCap::Square => lyon::tessellation::LineCap::Square,
+            LineCap::Round => lyon::tessellation::LineCap::Round,
+        };
+        StrokeOptions::tolerance(tolerance)
+            .with_line_width(self.width)
+            .with_line_join(join)
+            .with_line_cap(cap)
+            .with_miter_limit(self.miter_limit.max(1.0))
+    }
+}
+
+/// Indices of shapes queued in one layer with one blend mode.
+struct ShapeDraw {
+    layer: i32,
+    blend: BlendMode,
+    indices: Range<u32>,
+}
+
+/// Immediate mode 2D shapes in the space defined by the `view_proj` given to `prepare`.
+/// Use `ShapeRenderer::pixel_projection` to work in window pixels, origin top left.
+pub struct ShapeRenderer {
+    alpha_pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    vertex_capacity: usize,
+    index_capacity: usize,
+    geometry: VertexBuffers<ShapeVertex, u32>,
+    /// Shapes queued since the last `prepare`, as ranges of `geometry.indices`.
+    queued: Vec<ShapeDraw>,
+    /// Draw calls of the last `prepare`, runs of a blend mode after sorting by layer.
+    batches: Vec<(BlendMode, Range<u32>)>,
+    /// Each layer the last `prepare` uploaded and its indices, in order.
+    layers: Vec<(i32, Range<u32>)>,
+    fill_tessellator: FillTessellator,
+    stroke_tessellator: StrokeTessellator,
+    /// Maximum distance between a curve and its flattened approximation.
+    pub tolerance: f32,
+    /// Layer of the shapes queued from now on, higher layers draw on top.
+    pub layer: i32,
+    /// Blend mode of the shapes queued from now on.
+    pub blend: BlendMode,
+    tracked: Tracked,
+}
+
+impl ShapeRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shape Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shape Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shape Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shape Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shape Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |label, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[ShapeVertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        let alpha_pipeline = pipeline("Shape Alpha Pipeline", BlendMode::Alpha.blend_state());
+        let additive_pipeline =
+            pipeline("Shape Additive Pipeline", BlendMode::Additive.blend_state());
+
+        let vertex_capacity = 4096;
+        let index_capacity = 8192;
+        let vertex_buffer = Self::create_buffer(
+            device,
+            "Shape Vertex Buffer",
+            vertex_capacity * std::mem::size_of::<ShapeVertex>(),
+            wgpu::BufferUsages::VERTEX,
+        );
+        let index_buffer = Self::create_buffer(
+            device,
+            "Shape Index Buffer",
+            index_capacity * 4,
+            wgpu::BufferUsages::INDEX,
+        );
+        let tracked = Tracked::new(2, 3, 0)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&vertex_buffer)
+            .with_buffer(&index_buffer);
+
+        Self {
+            alpha_pipeline,
+            additive_pipeline,
+            globals_buffer,
+            bind_group,
+            vertex_buffer,
+            index_buffer,
+            vertex_capacity,
+            index_capacity,
+            geometry: VertexBuffers::new(),
+            queued: Vec::new(),
+            batches: Vec::new(),
+            layers: Vec::new(),
+            fill_tessellator: FillTessellator::new(),
+            stroke_tessellator: StrokeTessellator::new(),
+            tolerance: 0.1,
+            layer: 0,
+            blend: BlendMode::Alpha,
+            tracked,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        size: usize,
+        usage: wgpu::BufferUsages,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size as wgpu::BufferAddress,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Orthographic projection for drawing in pixels with the origin in the top left corner.
+    pub fn pixel_projection(width: f32, height: f32) -> Mat4 {
+        Mat4::orthographic_rh(0.0, width, height, 0.0, -1.0, 1.0)
+    }
+
+    /// Fills with the even-odd rule, so inner sub-paths become holes regardless of winding.
+    pub fn fill_path(&mut self, path: &Path, color: [f32; 4]) {
+        self.fill_path_with_rule(path, color, FillRule::EvenOdd);
+    }
+
+    pub fn fill_path_with_rule(&mut self, path: &Path, color: [f32; 4], rule: FillRule) {
+        let start = self.geometry.indices.len() as u32;
+        let options = FillOptions::tolerance(self.tolerance).with_fill_rule(rule);
+        let result = self.fill_tessellator.tessellate_path(
+            path,
+            &options,
+            &mut BuffersBuilder::new(&mut self.geometry, |v: FillVertex| ShapeVertex {
+                position: v.position().to_array(),
+                color,
+            }),
+        );
+        if let Err(e) = result {
+            log::warn!("Failed to fill path: {:?}", e);
+        }
+        self.record(start);
+    }
+
+    pub fn stroke_path(&mut self, path: &Path, stroke: &Stroke, color: [f32; 4]) {
+        let start = self.geometry.indices.len() as u32;
+        let options = stroke.options(self.tolerance);
+        let result = self.stroke_tessellator.tessellate_path(
+            path,
+            &options,
+            &mut BuffersBuilder::new(&mut self.geometry, |v: StrokeVertex| ShapeVertex {
+                position: v.position().to_array(),
+                color,
+            }),
+        );
+        if let Err(e) = result {
+            log::warn!("Failed to stroke path: {:?}", e);
+        }
+        self.record(start);
+    }
+
+    /// Files the indices added since `start` under the current layer and blend mode.
+    fn record(&mut self, start: u32) {
+        let end = self.geometry.indices.len() as u32;
+        if end == start {
+            return;
+        }
+        match self.queued.last_mut() {
+            Some(last) if last.layer == self.layer && last.blend == self.blend => {
+                last.indices.end = end
+            }
+            _ => self.queued.push(ShapeDraw {
+                layer: self.layer,
+                blend: self.blend,
+                indices: start..end,
+            }),
+        }
+    }
+
+    pub fn fill_rect(&mut self, min: Vec2, max: Vec2, color: [f32; 4]) {
+        self.fill_path(&rect_path(min, max), color);
+    }
+
+    pub fn stroke_rect(&mut self, min: Vec2, max: Vec2, stroke: &Stroke, color: [f32; 4]) {
+        self.stroke_path(&rect_path(min, max), stroke, color);
+    }
+
+    pub fn fill_rounded_rect(&mut self, min: Vec2, max: Vec2, radius: f32, color: [f32; 4]) {
+        self.fill_path(&rounded_rect_path(min, max, radius), color);
+    }
+
+    pub fn stroke_rounded_rect(
+        &mut self,
+        min: Vec2,
+        max: Vec2,
+        radius: f32,
+        stroke: &Stroke,
+        color: [f32; 4],
+    ) {
+        self.stroke_path(&rounded_rect_path(min, max, radius), stroke, color);
+    }
+
+    pub fn fill_circle(&mut self, center: Vec2, radius: f32, color: [f32; 4]) {
+        self.fill_path(&circle_path(center, radius), color);
+    }
+
+    pub fn stroke_circle(&mut self, center: Vec2, radius: f32, stroke: &Stroke, color: [f32; 4]) {
+        self.stroke_path(&circle_path(center, radius), stroke, color);
+    }
+
+    /// Closed polygon through `points`.
+    pub fn fill_polygon(&mut self, points: &[Vec2], color: [f32; 4]) {
+        if let Some(path) = polygon_path(points, true) {
+            self.fill_path(&path, color);
+        }
+    }
+
+    pub fn stroke_polyline(
+        &mut self,
+        points: &[Vec2],
+        closed: bool,
+        stroke: &Stroke,
+        color: [f32; 4],
+    ) {
+        if let Some(path) = polygon_path(points, closed) {
+            self.stroke_path(&path, stroke, color);
+        }
+    }
+
+    /// Uploads everything queued since the last call, sorted by layer.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_proj: Mat4) {
+        queue.write_buffer(
+            &self.globals_buffer,
+            0,
+            bytemuck::bytes_of(&FrameGlobals::from_view_proj(view_proj)),
+        );
+
+        // stable, so shapes in a layer keep the order they were queued in
+        self.queued.sort_by_key(|draw| draw.layer);
+        self.batches.clear();
+        self.layers.clear();
+        let mut indices = Vec::with_capacity(self.geometry.indices.len());
+        for draw in self.queued.drain(..) {
+            let start = indices.len() as u32;
+            indices.extend_from_slice(
+                &self.geometry.indices[draw.indices.start as usize..draw.indices.end as usize],
+            );
+            let end = indices.len() as u32;
+            match self.layers.last_mut() {
+                Some((layer, range)) if *layer == draw.layer => range.end = end,
+                _ => self.layers.push((draw.layer, start..end)),
+            }
+            // batches carry on across layers, sorting already put them in order
+            match self.batches.last_mut() {
+                Some((blend, range)) if *blend == draw.blend => range.end = end,
+                _ => self.batches.push((draw.blend, start..end)),
+            }
+        }
+
+        // index buffer writes must be a multiple of 4 bytes, u32 indices always are
+        let vertices = &self.geometry.vertices;
+        if vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = vertices.len().next_power_of_two();
+            let vertex_buffer = Self::create_buffer(
+                device,
+                "Shape Vertex Buffer",
+                self.vertex_capacity * std::mem::size_of::<ShapeVertex>(),
+                wgpu::BufferUsages::VERTEX,
+            );
+            self.tracked
+                .replace_buffer(&self.vertex_buffer, &vertex_buffer);
+            self.vertex_buffer = vertex_buffer;
+        }
+        if indices.len() > self.index_capacity {
+            self.index_capacity = indices.len().next_power_of_two();
+            let index_buffer = Self::create_buffer(
+                device,
+                "Shape Index Buffer",
+                self.index_capacity * 4,
+                wgpu::BufferUsages::INDEX,
+            );
+            self.tracked
+                .replace_buffer(&self.index_buffer, &index_buffer);
+            self.index_buffer = index_buffer;
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertices));
+        queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+
+        self.geometry.vertices.clear();
+        self.geometry.indices.clear();
+    }
+
+    /// Draw calls the last `prepare` made.
+    pub fn batch_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.render_layers(render_pass, ..);
+    }
+
+    /// Draws only the shapes in `layers`, to interleave them with other renderers such as
+    /// `SpriteBatch::render_layers`.
+    pub fn render_layers<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        layers: impl RangeBounds<i32>,
+    ) {
+        // layers are sorted, so the ones asked for are one run of indices
+        let mut drawn = self
+            .layers
+            .iter()
+            .filter(|(layer, _)| layers.contains(layer))
+            .map(|(_, range)| range.clone());
+        let Some(first) = drawn.next() else {
+            return;
+        };
+        let drawn = first.start..drawn.last().unwrap_or(first).end;
+        render_pass.push_debug_group("Shapes");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        let mut bound = None;
+        for (blend, range) in &self.batches {
+            let indices = range.start.max(drawn.start)..range.end.min(drawn.end);
+            if indices.is_empty() {
+                continue;
+            }
+            if bound != Some(*blend) {
+                render_pass.set_pipeline(match blend {
+                    BlendMode::Alpha => &self.alpha_pipeline,
+                    BlendMode::Additive => &self.additive_pipeline,
+                });
+                bound = Some(*blend);
+            }
+            render_pass.draw_indexed(indices, 0, 0..1);
+        }
+        render_pass.pop_debug_group();
+    }
+}
+
+fn to_point(v: Vec2) -> Point {
+    point(v.x, v.y)
+}
+
+pub fn rect_path(min: Vec2, max: Vec2) -> Path {
+    let mut builder = Path::builder();
+    builder.add_rectangle(&Box2D::new(to_point(min), to_point(max)), Winding::Positive);
+    builder.build()
+}
+
+pub fn rounded_rect_path(min: Vec2, max: Vec2, radius: f32) -> Path {
+    let mut builder = Path::builder();
+    builder.add_rounded_rectangle(
+        &Box2D::new(to_point(min), to_point(max)),
+        &path::builder::BorderRadii::new(radius),
+        Winding::Positive,
+    );
+    builder.build()
+}
+
+pub fn circle_path(center: Vec2, radius: f32) -> Path {
+    let mut builder = Path::builder();
+    builder.add_circle(to_point(center), radius, Winding::Positive);
+    builder.build()
+}
+
+/// `None` when there are fewer than two points.
+pub fn polygon_path(points: &[Vec2], closed: bool) -> Option<Path> {
+    let (first, rest) = points.split_first()?;
+    if rest.is_empty() {
+        return None;
+    }
+    let mut builder = Path::builder();
+    builder.begin(to_point(*first));
+    for p in rest {
+        builder.line_to(to_point(*p));
+    }
+    builder.end(closed);
+    Some(builder.build())
+}
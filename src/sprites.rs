@@ -0,0 +1,748 @@
+//! Textured 2D sprites drawn in batches, plus sprite sheet animation.
+//!
+//! Sprites draw by `layer`, lower layers first, and in the order they were queued within
+//! a layer, so scenes don't need drawing back to front by hand. Runs of sprites sharing a
+//! texture and blend mode after sorting share a draw call.
+
+use std::ops::RangeBounds;
+
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::math::{Mat4, Vec2};
+use crate::particles::BlendMode;
+use crate::stats::Tracked;
+use crate::texture::Texture;
+
+const SHADER: &str = r#"
+@group(1) @binding(0)
+var sprite_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var sprite_sampler: sampler;
+
+struct InstanceInput {
+    @location(0) origin: vec2<f32>,
+    @location(1) axis_x: vec2<f32>,
+    @location(2) axis_y: vec2<f32>,
+    @location(3) uv_min: vec2<f32>,
+    @location(4) uv_max: vec2<f32>,
+    @location(5) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: InstanceInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[index];
+    let world = instance.origin + instance.axis_x * corner.x + instance.axis_y * corner.y;
+
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(world, 0.0, 1.0);
+    out.uv = mix(instance.uv_min, instance.uv_max, corner);
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(sprite_texture, sprite_sampler, in.uv) * in.color;
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct SpriteInstance {
+    origin: [f32; 2],
+    axis_x: [f32; 2],
+    axis_y: [f32; 2],
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    color: [f32; 4],
+}
+unsafe impl bytemuck::Pod for SpriteInstance {}
+unsafe impl bytemuck::Zeroable for SpriteInstance {}
+
+impl SpriteInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x2,
+        4 => Float32x2,
+        5 => Float32x4
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Handle to a texture added to a `SpriteBatch`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(usize);
+
+/// One textured quad. Sizes and positions are in the units of the batch's `view_proj`,
+/// with y growing down like `ShapeRenderer::pixel_projection`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sprite {
+    pub position: Vec2,
+    pub size: Vec2,
+    /// Radians, around the anchor.
+    pub rotation: f32,
+    /// Point of the sprite placed at `position`, (0, 0) top left and (1, 1) bottom right.
+    pub anchor: Vec2,
+    /// Texture region in UVs.
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+    pub color: [f32; 4],
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Draw order, higher layers on top. Sprites in the same layer keep queue order.
+    pub layer: i32,
+    pub blend: BlendMode,
+}
+
+impl Sprite {
+    pub fn new(position: Vec2, size: Vec2) -> Self {
+        Self {
+            position,
+            size,
+            rotation: 0.0,
+            anchor: Vec2::splat(0.5),
+            uv_min: Vec2::ZERO,
+            uv_max: Vec2::ONE,
+            color: [1.0; 4],
+            flip_x: false,
+            flip_y: false,
+            layer: 0,
+            blend: BlendMode::Alpha,
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_anchor(mut self, anchor: Vec2) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn with_region(mut self, uv_min: Vec2, uv_max: Vec2) -> Self {
+        self.uv_min = uv_min;
+        self.uv_max = uv_max;
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_flip(mut self, flip_x: bool, flip_y: bool) -> Self {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    fn instance(&self) -> SpriteInstance {
+        let axis_x = Vec2::new(self.size.x, 0.0).rotate(self.rotation);
+        let axis_y = Vec2::new(0.0, self.size.y).rotate(self.rotation);
+        let origin = self.position - axis_x * self.anchor.x - axis_y * self.anchor.y;
+        let (mut uv_min, mut uv_max) = (self.uv_min, self.uv_max);
+        if self.flip_x {
+            std::mem::swap(&mut uv_min.x, &mut uv_max.x);
+        }
+        if self.flip_y {
+            std::mem::swap(&mut uv_min.y, &mut uv_max.y);
+        }
+        SpriteInstance {
+            origin: origin.to_array(),
+            axis_x: axis_x.to_array(),
+            axis_y: axis_y.to_array(),
+            uv_min: uv_min.to_array(),
+            uv_max: uv_max.to_array(),
+            color: self.color,
+        }
+    }
+}
+
+/// Border insets for `SpriteBatch::draw_nine_slice`, in texels of the sprite's texture.
+/// Corners keep their size, edges stretch along one axis and the center along both.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NineSlice {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    /// Output units per border texel.
+    pub scale: f32,
+}
+
+impl NineSlice {
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+            scale: 1.0,
+        }
+    }
+
+    pub fn uniform(border: f32) -> Self {
+        Self::new(border, border, border, border)
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum PlayMode {
+    /// Stops on the last frame.
+    Once,
+    #[default]
+    Loop,
+    /// Plays forwards then backwards without repeating the end frames.
+    PingPong,
+}
+
+/// Steps through sprite sheet frames at a fixed rate. Call `update` with the frame's delta
+/// time and draw it with `SpriteBatch::draw_animated`.
+#[derive(Clone, Debug)]
+pub struct AnimatedSprite {
+    pub texture: TextureId,
+    frames: Vec<(Vec2, Vec2)>,
+    pub fps: f32,
+    pub mode: PlayMode,
+    playing: bool,
+    time: f32,
+}
+
+impl AnimatedSprite {
+    /// `frames` are UV regions, min then max.
+    pub fn new(texture: TextureId, frames: Vec<(Vec2, Vec2)>, fps: f32) -> Self {
+        Self {
+            texture,
+            frames,
+            fps,
+            mode: PlayMode::Loop,
+            playing: true,
+            time: 0.0,
+        }
+    }
+
+    /// `count` frames of a sheet cut into `columns` x `rows` cells, read left to right and
+    /// top to bottom starting at cell `first`.
+    pub fn from_grid(
+        texture: TextureId,
+        columns: u32,
+        rows: u32,
+        first: u32,
+        count: u32,
+        fps: f32,
+    ) -> Self {
+        let cell = Vec2::new(1.0 / columns as f32, 1.0 / rows as f32);
+        let frames = (first..first + count)
+            .map(|i| {
+                let min = Vec2::new((i % columns) as f32, (i / columns) as f32).mul_elem(cell);
+                (min, min + cell)
+            })
+            .collect();
+        Self::new(texture, frames, fps)
+    }
+
+    pub fn with_mode(mut self, mode: PlayMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn play(&mut self) {
+        if self.is_finished() {
+            self.time = 0.0;
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Pauses and rewinds to the first frame.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.time = 0.0;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Only `PlayMode::Once` animations finish.
+    pub fn is_finished(&self) -> bool {
+        self.mode == PlayMode::Once && self.ticks() >= self.frames.len()
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn set_frame(&mut self, frame: usize) {
+        self.time = frame as f32 / self.fps.max(f32::EPSILON);
+    }
+
+    fn ticks(&self) -> usize {
+        (self.time * self.fps).max(0.0) as usize
+    }
+
+    pub fn frame(&self) -> usize {
+        let n = self.frames.len();
+        if n <= 1 {
+            return 0;
+        }
+        let ticks = self.ticks();
+        match self.mode {
+            PlayMode::Once => ticks.min(n - 1),
+            PlayMode::Loop => ticks % n,
+            PlayMode::PingPong => {
+                let period = n * 2 - 2;
+                let step = ticks % period;
+                if step < n {
+                    step
+                } else {
+                    period - step
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if !self.playing {
+            return;
+        }
+        self.time += dt;
+        if self.is_finished() {
+            self.playing = false;
+        }
+    }
+
+    /// UV region of the current frame.
+    pub fn region(&self) -> (Vec2, Vec2) {
+        self.frames
+            .get(self.frame())
+            .copied()
+            .unwrap_or((Vec2::ZERO, Vec2::ONE))
+    }
+}
+
+struct SpriteTexture {
+    texture: Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A sprite queued for `prepare`, with what orders and batches it.
+struct QueuedSprite {
+    layer: i32,
+    texture: TextureId,
+    blend: BlendMode,
+    instance: SpriteInstance,
+}
+
+struct SpriteDraw {
+    texture: TextureId,
+    blend: BlendMode,
+    instances: std::ops::Range<u32>,
+}
+
+/// Immediate mode sprites, sorted by layer. Consecutive draws with the same texture and
+/// blend mode share a draw call, otherwise queue order is kept so later sprites in a
+/// layer draw on top.
+pub struct SpriteBatch {
+    alpha_pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    texture_layout: wgpu::BindGroupLayout,
+    textures: Vec<SpriteTexture>,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    queued: Vec<QueuedSprite>,
+    batches: Vec<SpriteDraw>,
+    /// Each layer drawn and the instances in it, in order.
+    layers: Vec<(i32, std::ops::Range<u32>)>,
+    tracked: Tracked,
+}
+
+impl SpriteBatch {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite Globals Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Globals Bind Group"),
+            layout: &globals_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite Texture Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[&globals_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |label, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[SpriteInstance::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        let alpha_pipeline = pipeline("Sprite Alpha Pipeline", BlendMode::Alpha.blend_state());
+        let additive_pipeline = pipeline(
+            "Sprite Additive Pipeline",
+            BlendMode::Additive.blend_state(),
+        );
+
+        let instance_capacity = 256;
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+        let tracked = Tracked::new(2, 2, 0)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&instance_buffer);
+
+        Self {
+            alpha_pipeline,
+            additive_pipeline,
+            globals_buffer,
+            globals_bind_group,
+            texture_layout,
+            textures: Vec::new(),
+            instance_buffer,
+            instance_capacity,
+            queued: Vec::new(),
+            batches: Vec::new(),
+            layers: Vec::new(),
+            tracked,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Instance Buffer"),
+            size: (capacity * std::mem::size_of::<SpriteInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn add_texture(&mut self, device: &wgpu::Device, texture: Texture) -> TextureId {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Texture Bind Group"),
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        });
+        self.textures.push(SpriteTexture {
+            texture,
+            bind_group,
+        });
+        TextureId(self.textures.len() - 1)
+    }
+
+    pub fn texture(&self, id: TextureId) -> &Texture {
+        &self.textures[id.0].texture
+    }
+
+    pub fn draw(&mut self, texture: TextureId, sprite: &Sprite) {
+        self.queue(texture, sprite, sprite.instance());
+    }
+
+    fn queue(&mut self, texture: TextureId, sprite: &Sprite, instance: SpriteInstance) {
+        self.queued.push(QueuedSprite {
+            layer: sprite.layer,
+            texture,
+            blend: sprite.blend,
+            instance,
+        });
+    }
+
+    /// Draws the animation's current frame, replacing the sprite's texture region.
+    pub fn draw_animated(&mut self, animation: &AnimatedSprite, sprite: &Sprite) {
+        let (uv_min, uv_max) = animation.region();
+        self.draw(animation.texture, &sprite.with_region(uv_min, uv_max));
+    }
+
+    /// Draws the sprite as a scalable panel whose borders keep their size. Borders shrink
+    /// evenly when the sprite is smaller than both of them together.
+    pub fn draw_nine_slice(&mut self, texture: TextureId, sprite: &Sprite, slice: &NineSlice) {
+        let texel = {
+            let texture = self.texture(texture);
+            Vec2::new(1.0 / texture.width() as f32, 1.0 / texture.height() as f32)
+        };
+        let (mut left, mut right) = (slice.left, slice.right);
+        let (mut top, mut bottom) = (slice.top, slice.bottom);
+        let (mut u0, mut u1) = (sprite.uv_min.x, sprite.uv_max.x);
+        let (mut v0, mut v1) = (sprite.uv_min.y, sprite.uv_max.y);
+        if sprite.flip_x {
+            std::mem::swap(&mut left, &mut right);
+            std::mem::swap(&mut u0, &mut u1);
+        }
+        if sprite.flip_y {
+            std::mem::swap(&mut top, &mut bottom);
+            std::mem::swap(&mut v0, &mut v1);
+        }
+        let us = [
+            u0,
+            u0 + (u1 - u0).signum() * left * texel.x,
+            u1 - (u1 - u0).signum() * right * texel.x,
+            u1,
+        ];
+        let vs = [
+            v0,
+            v0 + (v1 - v0).signum() * top * texel.y,
+            v1 - (v1 - v0).signum() * bottom * texel.y,
+            v1,
+        ];
+
+        let size = sprite.size;
+        let fit_x = (size.x / ((left + right) * slice.scale)).min(1.0);
+        let fit_y = (size.y / ((top + bottom) * slice.scale)).min(1.0);
+        let xs = [
+            0.0,
+            left * slice.scale * fit_x,
+            size.x - right * slice.scale * fit_x,
+            size.x,
+        ];
+        let ys = [
+            0.0,
+            top * slice.scale * fit_y,
+            size.y - bottom * slice.scale * fit_y,
+            size.y,
+        ];
+
+        let right_axis = Vec2::X.rotate(sprite.rotation);
+        let down_axis = Vec2::Y.rotate(sprite.rotation);
+        let origin = sprite.position
+            - right_axis * (size.x * sprite.anchor.x)
+            - down_axis * (size.y * sprite.anchor.y);
+        for j in 0..3 {
+            for i in 0..3 {
+                let width = xs[i + 1] - xs[i];
+                let height = ys[j + 1] - ys[j];
+                if width <= 0.0 || height <= 0.0 {
+                    continue;
+                }
+                let corner = origin + right_axis * xs[i] + down_axis * ys[j];
+                self.queue(
+                    texture,
+                    sprite,
+                    SpriteInstance {
+                        origin: corner.to_array(),
+                        axis_x: (right_axis * width).to_array(),
+                        axis_y: (down_axis * height).to_array(),
+                        uv_min: [us[i], vs[j]],
+                        uv_max: [us[i + 1], vs[j + 1]],
+                        color: sprite.color,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Uploads the sprites queued since the last call, sorted by layer.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_proj: Mat4) {
+        queue.write_buffer(
+            &self.globals_buffer,
+            0,
+            bytemuck::bytes_of(&FrameGlobals::from_view_proj(view_proj)),
+        );
+
+        // stable, so sprites in a layer keep the order they were queued in
+        self.queued.sort_by_key(|sprite| sprite.layer);
+        self.batches.clear();
+        self.layers.clear();
+        let mut instances = Vec::with_capacity(self.queued.len());
+        for sprite in self.queued.drain(..) {
+            let index = instances.len() as u32;
+            match self.layers.last_mut() {
+                Some((layer, range)) if *layer == sprite.layer => range.end = index + 1,
+                _ => self.layers.push((sprite.layer, index..index + 1)),
+            }
+            // batches carry on across layers, sorting already put them in order
+            match self.batches.last_mut() {
+                Some(last) if last.texture == sprite.texture && last.blend == sprite.blend => {
+                    last.instances.end = index + 1
+                }
+                _ => self.batches.push(SpriteDraw {
+                    texture: sprite.texture,
+                    blend: sprite.blend,
+                    instances: index..index + 1,
+                }),
+            }
+            instances.push(sprite.instance);
+        }
+
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            let instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+            self.tracked
+                .replace_buffer(&self.instance_buffer, &instance_buffer);
+            self.instance_buffer = instance_buffer;
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+    }
+
+    /// Draw calls the last `prepare` made.
+    pub fn batch_count(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.render_layers(render_pass, ..);
+    }
+
+    /// Draws only the sprites in `layers`, to interleave them with other renderers such as
+    /// `ShapeRenderer::render_layers`.
+    pub fn render_layers<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        layers: impl RangeBounds<i32>,
+    ) {
+        // layers are sorted, so the ones asked for are one run of instances
+        let mut drawn = self
+            .layers
+            .iter()
+            .filter(|(layer, _)| layers.contains(layer))
+            .map(|(_, range)| range.clone());
+        let Some(first) = drawn.next() else {
+            return;
+        };
+        let drawn = first.start..drawn.last().unwrap_or(first).end;
+        render_pass.push_debug_group("Sprites");
+        render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        let mut bound = None;
+        for batch in &self.batches {
+            let instances =
+                batch.instances.start.max(drawn.start)..batch.instances.end.min(drawn.end);
+            if instances.is_empty() {
+                continue;
+            }
+            if bound != Some(batch.blend) {
+                render_pass.set_pipeline(match batch.blend {
+                    BlendMode::Alpha => &self.alpha_pipeline,
+                    BlendMode::Additive => &self.additive_pipeline,
+                });
+                bound = Some(batch.blend);
+            }
+            render_pass.set_bind_group(1, &self.textures[batch.texture.0].bind_group, &[]);
+            render_pass.draw(0..6, instances);
+        }
+        render_pass.pop_debug_group();
+    }
+}
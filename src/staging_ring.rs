@@ -0,0 +1,235 @@
+//! Per frame data such as uniforms, instance data and dynamic vertices, suballocated from
+//! one large buffer per frame in flight instead of a small buffer each.
+//!
+//! Call `begin_frame` at the start of every frame, `push` the frame's data and bind each
+//! allocation at the offset it got, then `upload` everything in one write before
+//! submitting. Each frame in flight writes a buffer of its own: `begin_frame` moves on to
+//! the oldest one, which the GPU has normally finished with by then, and waits for it
+//! when it hasn't, so no frame overwrites data another is still reading. A buffer grows
+//! to the most a frame pushed and is kept at that size, so bind groups using it only need
+//! creating again after it grew, see `generation`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::stats::Tracked;
+
+const INITIAL_CAPACITY: u64 = 64 * 1024;
+
+/// Where `StagingRing::push` put some data in the current frame's buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RingAllocation {
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl RingAllocation {
+    /// The offset as a dynamic offset to bind a uniform or storage buffer at.
+    pub fn dynamic_offset(&self) -> u32 {
+        self.offset as u32
+    }
+}
+
+struct RingFrame {
+    buffer: wgpu::Buffer,
+    /// Set once the GPU finished the last frame that used the buffer.
+    done: Arc<AtomicBool>,
+    /// Bumped when the buffer is replaced by a bigger one.
+    generation: u32,
+    _tracked: Tracked,
+}
+
+/// Suballocates per frame data, see the module docs.
+pub struct StagingRing {
+    label: String,
+    usage: wgpu::BufferUsages,
+    alignment: u64,
+    frames: Vec<RingFrame>,
+    current: usize,
+    data: Vec<u8>,
+    /// Times `begin_frame` had to wait for the GPU.
+    stalls: u64,
+}
+
+impl StagingRing {
+    /// A ring with a buffer for each of `frames` frames in flight, usually
+    /// `Context::max_frames_in_flight`, of `usage` on top of `COPY_DST`. Allocations are
+    /// aligned for binding at a dynamic offset when `usage` has `UNIFORM` or `STORAGE`.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        usage: wgpu::BufferUsages,
+        frames: usize,
+    ) -> Self {
+        let limits = device.limits();
+        let mut alignment = wgpu::COPY_BUFFER_ALIGNMENT;
+        if usage.contains(wgpu::BufferUsages::UNIFORM) {
+            alignment = alignment.max(limits.min_uniform_buffer_offset_alignment as u64);
+        }
+        if usage.contains(wgpu::BufferUsages::STORAGE) {
+            alignment = alignment.max(limits.min_storage_buffer_offset_alignment as u64);
+        }
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+        let frames = (0..frames.max(1))
+            .map(|_| Self::create_frame(device, label, usage, INITIAL_CAPACITY))
+            .collect();
+        Self {
+            label: label.to_string(),
+            usage,
+            alignment,
+            frames,
+            current: 0,
+            data: Vec::new(),
+            stalls: 0,
+        }
+    }
+
+    fn create_frame(
+        device: &wgpu::Device,
+        label: &str,
+        usage: wgpu::BufferUsages,
+        size: u64,
+    ) -> RingFrame {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+        RingFrame {
+            buffer,
+            done: Arc::new(AtomicBool::new(true)),
+            generation: 0,
+            _tracked: tracked,
+        }
+    }
+
+    /// Moves on to the next frame's buffer, waiting for the GPU to finish the frame that
+    /// used it last if it hasn't yet. Call before pushing anything for the frame, after
+    /// the last frame was submitted.
+    pub fn begin_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        // work submitted until now includes the last frame
+        let done = Arc::new(AtomicBool::new(false));
+        let signal = done.clone();
+        queue.on_submitted_work_done(move || signal.store(true, Ordering::Release));
+        self.frames[self.current].done = done;
+
+        self.current = (self.current + 1) % self.frames.len();
+        self.data.clear();
+        let next = &self.frames[self.current];
+        if !next.done.load(Ordering::Acquire) {
+            device.poll(wgpu::Maintain::Poll);
+            if !next.done.load(Ordering::Acquire) {
+                self.stalls += 1;
+                device.poll(wgpu::Maintain::Wait);
+            }
+        }
+    }
+
+    /// Adds `values` to this frame's data, on the GPU after `upload`.
+    pub fn push<T: bytemuck::Pod>(&mut self, values: &[T]) -> RingAllocation {
+        self.push_bytes(bytemuck::cast_slice(values))
+    }
+
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> RingAllocation {
+        append(&mut self.data, bytes, self.alignment)
+    }
+
+    /// Writes this frame's data to its buffer, growing it first if it doesn't fit.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let len = self.data.len() as u64;
+        let frame = &mut self.frames[self.current];
+        if len > frame.buffer.size() {
+            log::debug!("{} grew to {} bytes", self.label, len.next_power_of_two());
+            let grown =
+                Self::create_frame(device, &self.label, self.usage, len.next_power_of_two());
+            frame.buffer = grown.buffer;
+            frame._tracked = grown._tracked;
+            frame.generation += 1;
+        }
+        if !self.data.is_empty() {
+            queue.write_buffer(&frame.buffer, 0, &self.data);
+        }
+    }
+
+    /// This frame's buffer. It differs from frame to frame, so keep a bind group per buffer,
+    /// e.g. with a `BindGroupCache`.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.frames[self.current].buffer
+    }
+
+    /// Changes when this frame's buffer is replaced by `upload` growing it.
+    pub fn generation(&self) -> u32 {
+        self.frames[self.current].generation
+    }
+
+    /// The allocation in this frame's buffer, e.g. for `set_vertex_buffer`.
+    pub fn slice(&self, allocation: RingAllocation) -> wgpu::BufferSlice<'_> {
+        self.buffer()
+            .slice(allocation.offset..allocation.offset + allocation.size)
+    }
+
+    /// The allocation as a binding of its own, without a dynamic offset.
+    pub fn binding(&self, allocation: RingAllocation) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: self.buffer(),
+            offset: allocation.offset,
+            size: wgpu::BufferSize::new(allocation.size),
+        })
+    }
+
+    /// Bytes pushed this frame, alignment included.
+    pub fn used(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// Frames that had to wait for the GPU to finish with their buffer. If it keeps rising
+    /// the ring has fewer buffers than there are frames in flight.
+    pub fn stalls(&self) -> u64 {
+        self.stalls
+    }
+}
+
+/// Appends `bytes` to `data` at the next multiple of `alignment`, padded to a size that can
+/// be copied.
+fn append(data: &mut Vec<u8>, bytes: &[u8], alignment: u64) -> RingAllocation {
+    let offset = (data.len() as u64).next_multiple_of(alignment);
+    let size = (bytes.len() as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+    data.resize(offset as usize, 0);
+    data.extend_from_slice(bytes);
+    data.resize((offset + size) as usize, 0);
+    RingAllocation { offset, size }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_aligned() {
+        let mut data = Vec::new();
+        let first = append(&mut data, &[1, 2, 3], 256);
+        assert_eq!(first, RingAllocation { offset: 0, size: 4 });
+        let second = append(&mut data, &[4; 8], 256);
+        assert_eq!(
+            second,
+            RingAllocation {
+                offset: 256,
+                size: 8
+            }
+        );
+        assert_eq!(&data[..4], &[1, 2, 3, 0]);
+        assert_eq!(&data[256..], &[4; 8]);
+        // vertex data only needs copy alignment
+        let third = append(&mut data, &[5; 5], wgpu::COPY_BUFFER_ALIGNMENT);
+        assert_eq!(
+            third,
+            RingAllocation {
+                offset: 264,
+                size: 8
+            }
+        );
+        assert_eq!(data.len(), 272);
+    }
+}
@@ -0,0 +1,264 @@
+//! Live counts and sizes of the GPU resources owned by the crate's renderers.
+//!
+//! Every renderer registers the pipelines, buffers and textures it keeps for as long as it
+//! is alive, along with their sizes in bytes. Buffers that grow are replaced, so they count
+//! once at their current size. Resources an app creates directly through `Context::device`
+//! are not included, `Context::wgpu_report` covers everything the device holds.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+static PIPELINES: AtomicUsize = AtomicUsize::new(0);
+static BUFFERS: AtomicUsize = AtomicUsize::new(0);
+static TEXTURES: AtomicUsize = AtomicUsize::new(0);
+static BYTES: [AtomicU64; MemoryCategory::ALL.len()] =
+    [const { AtomicU64::new(0) }; MemoryCategory::ALL.len()];
+
+/// What a buffer or texture is used for, going by its usage flags.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    Vertex,
+    Index,
+    Uniform,
+    Storage,
+    /// Buffers the CPU maps to read results back.
+    Readback,
+    /// Buffers used for anything else, such as copies.
+    OtherBuffer,
+    /// Sampled textures.
+    Texture,
+    /// Textures rendered to, including depth buffers.
+    RenderTarget,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 8] = [
+        MemoryCategory::Vertex,
+        MemoryCategory::Index,
+        MemoryCategory::Uniform,
+        MemoryCategory::Storage,
+        MemoryCategory::Readback,
+        MemoryCategory::OtherBuffer,
+        MemoryCategory::Texture,
+        MemoryCategory::RenderTarget,
+    ];
+
+    pub fn of_buffer(usage: wgpu::BufferUsages) -> Self {
+        use wgpu::BufferUsages as U;
+        if usage.contains(U::MAP_READ) {
+            MemoryCategory::Readback
+        } else if usage.contains(U::STORAGE) {
+            MemoryCategory::Storage
+        } else if usage.contains(U::UNIFORM) {
+            MemoryCategory::Uniform
+        } else if usage.contains(U::INDEX) {
+            MemoryCategory::Index
+        } else if usage.contains(U::VERTEX) {
+            MemoryCategory::Vertex
+        } else {
+            MemoryCategory::OtherBuffer
+        }
+    }
+
+    pub fn of_texture(usage: wgpu::TextureUsages) -> Self {
+        if usage.contains(wgpu::TextureUsages::RENDER_ATTACHMENT) {
+            MemoryCategory::RenderTarget
+        } else {
+            MemoryCategory::Texture
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryCategory::Vertex => "vertex",
+            MemoryCategory::Index => "index",
+            MemoryCategory::Uniform => "uniform",
+            MemoryCategory::Storage => "storage",
+            MemoryCategory::Readback => "readback",
+            MemoryCategory::OtherBuffer => "other buffers",
+            MemoryCategory::Texture => "textures",
+            MemoryCategory::RenderTarget => "render targets",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Bytes a texture takes, rounded up to whole blocks, with every mip level, layer and
+/// sample. Drivers may add padding on top.
+pub fn texture_bytes(texture: &wgpu::Texture) -> u64 {
+    let format = texture.format();
+    let dimension = texture.dimension();
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_size(None).unwrap_or(4) as u64;
+    let layers = match dimension {
+        wgpu::TextureDimension::D3 => 1,
+        _ => texture.depth_or_array_layers() as u64,
+    };
+    (0..texture.mip_level_count())
+        .map(|level| {
+            let size = texture.size().mip_level_size(level, dimension);
+            let blocks_x = size.width.div_ceil(block_width) as u64;
+            let blocks_y = size.height.div_ceil(block_height) as u64;
+            let depth = match dimension {
+                wgpu::TextureDimension::D3 => size.depth_or_array_layers as u64,
+                _ => 1,
+            };
+            blocks_x * blocks_y * depth * block_size
+        })
+        .sum::<u64>()
+        * layers
+        * texture.sample_count() as u64
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceCounts {
+    pub pipelines: usize,
+    pub buffers: usize,
+    pub textures: usize,
+}
+
+pub fn resource_counts() -> ResourceCounts {
+    ResourceCounts {
+        pipelines: PIPELINES.load(Ordering::Relaxed),
+        buffers: BUFFERS.load(Ordering::Relaxed),
+        textures: TEXTURES.load(Ordering::Relaxed),
+    }
+}
+
+/// Resource counts with the bytes in each category.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub counts: ResourceCounts,
+    bytes: [u64; MemoryCategory::ALL.len()],
+}
+
+impl MemoryReport {
+    pub fn bytes(&self, category: MemoryCategory) -> u64 {
+        self.bytes[category.index()]
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes.iter().sum()
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} pipelines, {} buffers, {} textures, {:.2} MiB",
+            self.counts.pipelines,
+            self.counts.buffers,
+            self.counts.textures,
+            self.total_bytes() as f64 / (1024.0 * 1024.0)
+        )?;
+        for category in MemoryCategory::ALL {
+            let bytes = self.bytes(category);
+            if bytes > 0 {
+                writeln!(
+                    f,
+                    "  {:<15} {:>10.1} KiB",
+                    category.name(),
+                    bytes as f64 / 1024.0
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn memory_report() -> MemoryReport {
+    MemoryReport {
+        counts: resource_counts(),
+        bytes: std::array::from_fn(|i| BYTES[i].load(Ordering::Relaxed)),
+    }
+}
+
+/// Adds its counts and bytes to the totals until dropped.
+pub(crate) struct Tracked {
+    counts: ResourceCounts,
+    bytes: [u64; MemoryCategory::ALL.len()],
+}
+
+impl Tracked {
+    pub(crate) fn new(pipelines: usize, buffers: usize, textures: usize) -> Self {
+        PIPELINES.fetch_add(pipelines, Ordering::Relaxed);
+        BUFFERS.fetch_add(buffers, Ordering::Relaxed);
+        TEXTURES.fetch_add(textures, Ordering::Relaxed);
+        Self {
+            counts: ResourceCounts {
+                pipelines,
+                buffers,
+                textures,
+            },
+            bytes: [0; MemoryCategory::ALL.len()],
+        }
+    }
+
+    pub(crate) fn with_buffer(mut self, buffer: &wgpu::Buffer) -> Self {
+        self.add_buffer(buffer);
+        self
+    }
+
+    pub(crate) fn with_texture(mut self, texture: &wgpu::Texture) -> Self {
+        self.add_texture(texture);
+        self
+    }
+
+    fn add_bytes(&mut self, category: MemoryCategory, bytes: u64) {
+        self.bytes[category.index()] += bytes;
+        BYTES[category.index()].fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn remove_bytes(&mut self, category: MemoryCategory, bytes: u64) {
+        self.bytes[category.index()] -= bytes;
+        BYTES[category.index()].fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_buffer(&mut self, buffer: &wgpu::Buffer) {
+        self.add_bytes(MemoryCategory::of_buffer(buffer.usage()), buffer.size());
+    }
+
+    pub(crate) fn remove_buffer(&mut self, buffer: &wgpu::Buffer) {
+        self.remove_bytes(MemoryCategory::of_buffer(buffer.usage()), buffer.size());
+    }
+
+    pub(crate) fn add_texture(&mut self, texture: &wgpu::Texture) {
+        self.add_bytes(
+            MemoryCategory::of_texture(texture.usage()),
+            texture_bytes(texture),
+        );
+    }
+
+    pub(crate) fn remove_texture(&mut self, texture: &wgpu::Texture) {
+        self.remove_bytes(
+            MemoryCategory::of_texture(texture.usage()),
+            texture_bytes(texture),
+        );
+    }
+
+    /// Swaps the bytes of a buffer being replaced for those of its replacement.
+    pub(crate) fn replace_buffer(&mut self, old: &wgpu::Buffer, new: &wgpu::Buffer) {
+        self.remove_buffer(old);
+        self.add_buffer(new);
+    }
+
+    pub(crate) fn replace_texture(&mut self, old: &wgpu::Texture, new: &wgpu::Texture) {
+        self.remove_texture(old);
+        self.add_texture(new);
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        PIPELINES.fetch_sub(self.counts.pipelines, Ordering::Relaxed);
+        BUFFERS.fetch_sub(self.counts.buffers, Ordering::Relaxed);
+        TEXTURES.fetch_sub(self.counts.textures, Ordering::Relaxed);
+        for (total, bytes) in BYTES.iter().zip(self.bytes) {
+            total.fetch_sub(bytes, Ordering::Relaxed);
+        }
+    }
+}
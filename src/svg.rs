@@ -0,0 +1,579 @@
+//! SVG files loaded with usvg, tessellated once into cached meshes and drawn like sprites.
+//!
+//! Only solid paint is supported. Gradients use their first stop, patterns, images and
+//! text are skipped.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeTessellator, StrokeVertex,
+    VertexBuffers,
+};
+use wgpu::util::DeviceExt;
+
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::jobs;
+use crate::lines::{LineCap, LineJoin};
+use crate::math::{Mat4, Vec2};
+use crate::shapes::{self, ShapeVertex, Stroke};
+use crate::stats::Tracked;
+
+/// Fewest paths a job tessellates, fewer aren't worth handing to another thread.
+const PATHS_PER_JOB: usize = 64;
+
+const SHADER: &str = r#"
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct InstanceInput {
+    @location(2) offset: vec2<f32>,
+    @location(3) axis_x: vec2<f32>,
+    @location(4) axis_y: vec2<f32>,
+    @location(5) tint: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+    let world = instance.offset + instance.axis_x * in.position.x + instance.axis_y * in.position.y;
+    out.clip_position = globals.view_proj * vec4<f32>(world, 0.0, 1.0);
+    out.color = in.color * instance.tint;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct SvgInstance {
+    offset: [f32; 2],
+    axis_x: [f32; 2],
+    axis_y: [f32; 2],
+    tint: [f32; 4],
+}
+unsafe impl bytemuck::Pod for SvgInstance {}
+unsafe impl bytemuck::Zeroable for SvgInstance {}
+
+impl SvgInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        2 => Float32x2,
+        3 => Float32x2,
+        4 => Float32x2,
+        5 => Float32x4
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SvgError {
+    Io(std::io::Error),
+    Parse(usvg::Error),
+}
+
+impl std::fmt::Display for SvgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SvgError::Io(e) => write!(f, "failed to read svg: {}", e),
+            SvgError::Parse(e) => write!(f, "failed to parse svg: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SvgError {}
+
+/// Handle to a mesh loaded into an `SvgRenderer`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SvgId(usize);
+
+struct SvgMesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    size: Vec2,
+    _tracked: Tracked,
+}
+
+/// Owns the loaded SVG meshes and draws queued instances of them. Meshes are centred on
+/// their origin and keep the document's units, so a scale of 1 draws the SVG at its
+/// declared size in whatever space `view_proj` describes.
+pub struct SvgRenderer {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    meshes: Vec<SvgMesh>,
+    cache: HashMap<PathBuf, SvgId>,
+    queued: Vec<(SvgId, SvgInstance)>,
+    batches: Vec<(SvgId, std::ops::Range<u32>)>,
+    /// Flattening tolerance in SVG units, used by meshes loaded after it is changed.
+    pub tolerance: f32,
+    tracked: Tracked,
+}
+
+impl SvgRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Svg Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Svg Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Svg Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Svg Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Svg Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Svg Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[ShapeVertex::desc(), SvgInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let instance_capacity = 64;
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+        let tracked = Tracked::new(1, 2, 0)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&instance_buffer);
+
+        Self {
+            pipeline,
+            globals_buffer,
+            bind_group,
+            instance_buffer,
+            instance_capacity,
+            meshes: Vec::new(),
+            cache: HashMap::new(),
+            queued: Vec::new(),
+            batches: Vec::new(),
+            tolerance: 0.1,
+            tracked,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Svg Instance Buffer"),
+            size: (capacity * std::mem::size_of::<SvgInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Loads and tessellates an SVG file. Loading the same path again returns the cached mesh.
+    pub fn load(
+        &mut self,
+        device: &wgpu::Device,
+        path: impl AsRef<Path>,
+    ) -> Result<SvgId, SvgError> {
+        let path = path.as_ref();
+        if let Some(id) = self.cache.get(path) {
+            return Ok(*id);
+        }
+        let data = std::fs::read(path).map_err(SvgError::Io)?;
+        let options = usvg::Options {
+            resources_dir: path.parent().map(Path::to_path_buf),
+            ..Default::default()
+        };
+        let tree = usvg::Tree::from_data(&data, &options).map_err(SvgError::Parse)?;
+        let id = self.add_tree(device, &tree, &path.display().to_string());
+        self.cache.insert(path.to_path_buf(), id);
+        Ok(id)
+    }
+
+    /// Tessellates SVG source (optionally gzipped) without caching it.
+    pub fn load_data(&mut self, device: &wgpu::Device, data: &[u8]) -> Result<SvgId, SvgError> {
+        let tree =
+            usvg::Tree::from_data(data, &usvg::Options::default()).map_err(SvgError::Parse)?;
+        Ok(self.add_tree(device, &tree, "data"))
+    }
+
+    fn add_tree(&mut self, device: &wgpu::Device, tree: &usvg::Tree, name: &str) -> SvgId {
+        let size = Vec2::new(tree.size().width(), tree.size().height());
+        let mut paths = Vec::new();
+        collect_paths(tree.root(), 1.0, &mut paths);
+        // paths are tessellated apart on the job pool, then joined up in drawing order
+        let tolerance = self.tolerance;
+        jobs::for_each_chunk(&mut paths, PATHS_PER_JOB, |_, paths| {
+            let mut tessellator = Tessellator {
+                fill: FillTessellator::new(),
+                stroke: StrokeTessellator::new(),
+                tolerance,
+                origin: size * 0.5,
+            };
+            for path in paths {
+                tessellator.path(path);
+            }
+        });
+        let mut geometry = VertexBuffers::<ShapeVertex, u32>::new();
+        for path in paths {
+            let base = geometry.vertices.len() as u32;
+            geometry.vertices.extend(path.geometry.vertices);
+            geometry
+                .indices
+                .extend(path.geometry.indices.iter().map(|i| i + base));
+        }
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("Svg Vertex Buffer ({})", name)),
+            contents: bytemuck::cast_slice(&geometry.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("Svg Index Buffer ({})", name)),
+            contents: bytemuck::cast_slice(&geometry.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let tracked = Tracked::new(0, 2, 0)
+            .with_buffer(&vertex_buffer)
+            .with_buffer(&index_buffer);
+        self.meshes.push(SvgMesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: geometry.indices.len() as u32,
+            size,
+            _tracked: tracked,
+        });
+        SvgId(self.meshes.len() - 1)
+    }
+
+    /// Document size in SVG units.
+    pub fn size(&self, id: SvgId) -> Vec2 {
+        self.meshes[id.0].size
+    }
+
+    /// Queues the SVG centred on `position`. `scale` multiplies the document size,
+    /// `rotation` is in radians and `tint` multiplies every vertex color.
+    pub fn draw(&mut self, id: SvgId, position: Vec2, scale: Vec2, rotation: f32, tint: [f32; 4]) {
+        let axis_x = Vec2::new(scale.x, 0.0).rotate(rotation);
+        let axis_y = Vec2::new(0.0, scale.y).rotate(rotation);
+        self.queued.push((
+            id,
+            SvgInstance {
+                offset: position.to_array(),
+                axis_x: axis_x.to_array(),
+                axis_y: axis_y.to_array(),
+                tint,
+            },
+        ));
+    }
+
+    /// Queues the SVG scaled to cover `size` units.
+    pub fn draw_sized(&mut self, id: SvgId, position: Vec2, size: Vec2, tint: [f32; 4]) {
+        let scale = Vec2::new(
+            size.x / self.size(id).x.max(f32::EPSILON),
+            size.y / self.size(id).y.max(f32::EPSILON),
+        );
+        self.draw(id, position, scale, 0.0, tint);
+    }
+
+    /// Uploads the draws queued since the last call. Consecutive draws of the same SVG
+    /// share one instanced draw call, otherwise submission order is kept.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_proj: Mat4) {
+        queue.write_buffer(
+            &self.globals_buffer,
+            0,
+            bytemuck::bytes_of(&FrameGlobals::from_view_proj(view_proj)),
+        );
+
+        self.batches.clear();
+        let mut instances = Vec::with_capacity(self.queued.len());
+        for (id, instance) in self.queued.drain(..) {
+            let index = instances.len() as u32;
+            match self.batches.last_mut() {
+                Some((last, range)) if *last == id => range.end = index + 1,
+                _ => self.batches.push((id, index..index + 1)),
+            }
+            instances.push(instance);
+        }
+
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            let instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+            self.tracked
+                .replace_buffer(&self.instance_buffer, &instance_buffer);
+            self.instance_buffer = instance_buffer;
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.batches.is_empty() {
+            return;
+        }
+        render_pass.push_debug_group("Svg");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        for (id, range) in &self.batches {
+            let mesh = &self.meshes[id.0];
+            if mesh.index_count == 0 {
+                continue;
+            }
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..mesh.index_count, 0, range.clone());
+        }
+        render_pass.pop_debug_group();
+    }
+}
+
+/// A visible path of the document and what it tessellated into.
+struct PathGeometry<'a> {
+    path: &'a usvg::Path,
+    /// Of the groups it's in.
+    opacity: f32,
+    geometry: VertexBuffers<ShapeVertex, u32>,
+}
+
+/// The visible paths in `group`, in drawing order.
+fn collect_paths<'a>(group: &'a usvg::Group, opacity: f32, paths: &mut Vec<PathGeometry<'a>>) {
+    let opacity = opacity * group.opacity().get();
+    for node in group.children() {
+        match node {
+            usvg::Node::Group(group) => collect_paths(group, opacity, paths),
+            usvg::Node::Path(path) if path.is_visible() => paths.push(PathGeometry {
+                path,
+                opacity,
+                geometry: VertexBuffers::new(),
+            }),
+            usvg::Node::Text(text) => collect_paths(text.flattened(), opacity, paths),
+            _ => {}
+        }
+    }
+}
+
+struct Tessellator {
+    fill: FillTessellator,
+    stroke: StrokeTessellator,
+    tolerance: f32,
+    origin: Vec2,
+}
+
+impl Tessellator {
+    fn path(&mut self, target: &mut PathGeometry) {
+        let (path, opacity, geometry) = (target.path, target.opacity, &mut target.geometry);
+        let Some(lyon_path) = to_lyon_path(path.data()) else {
+            return;
+        };
+        let transform = path.abs_transform();
+        let origin = self.origin;
+        let to_vertex = move |p: lyon::math::Point, color: [f32; 4]| {
+            let mut p = usvg::tiny_skia_path::Point::from_xy(p.x, p.y);
+            transform.map_point(&mut p);
+            ShapeVertex {
+                position: [p.x - origin.x, p.y - origin.y],
+                color,
+            }
+        };
+
+        let fill = path
+            .fill()
+            .and_then(|f| paint_color(f.paint(), f.opacity().get() * opacity).map(|c| (f, c)));
+        let stroke = path
+            .stroke()
+            .and_then(|s| paint_color(s.paint(), s.opacity().get() * opacity).map(|c| (s, c)));
+
+        let draw_fill = |this: &mut Self, geometry: &mut VertexBuffers<ShapeVertex, u32>| {
+            let Some((fill, color)) = fill else {
+                return;
+            };
+            let rule = match fill.rule() {
+                usvg::FillRule::NonZero => shapes::FillRule::NonZero,
+                usvg::FillRule::EvenOdd => shapes::FillRule::EvenOdd,
+            };
+            let options = FillOptions::tolerance(this.tolerance).with_fill_rule(rule);
+            let result = this.fill.tessellate_path(
+                &lyon_path,
+                &options,
+                &mut BuffersBuilder::new(geometry, |v: FillVertex| to_vertex(v.position(), color)),
+            );
+            if let Err(e) = result {
+                log::warn!("Failed to fill svg path: {:?}", e);
+            }
+        };
+        let draw_stroke = |this: &mut Self, geometry: &mut VertexBuffers<ShapeVertex, u32>| {
+            let Some((stroke, color)) = stroke else {
+                return;
+            };
+            let options = Stroke {
+                width: stroke.width().get(),
+                join: match stroke.linejoin() {
+                    usvg::LineJoin::Miter | usvg::LineJoin::MiterClip => LineJoin::Miter,
+                    usvg::LineJoin::Round => LineJoin::Round,
+                    usvg::LineJoin::Bevel => LineJoin::Bevel,
+                },
+                cap: match stroke.linecap() {
+                    usvg::LineCap::Butt => LineCap::Butt,
+                    usvg::LineCap::Round => LineCap::Round,
+                    usvg::LineCap::Square => LineCap::Square,
+                },
+                miter_limit: stroke.miterlimit().get(),
+            }
+            .options(this.tolerance);
+            let result = this.stroke.tessellate_path(
+                &lyon_path,
+                &options,
+                &mut BuffersBuilder::new(geometry, |v: StrokeVertex| {
+                    to_vertex(v.position(), color)
+                }),
+            );
+            if let Err(e) = result {
+                log::warn!("Failed to stroke svg path: {:?}", e);
+            }
+        };
+
+        match path.paint_order() {
+            usvg::PaintOrder::FillAndStroke => {
+                draw_fill(self, geometry);
+                draw_stroke(self, geometry);
+            }
+            usvg::PaintOrder::StrokeAndFill => {
+                draw_stroke(self, geometry);
+                draw_fill(self, geometry);
+            }
+        }
+    }
+}
+
+fn to_lyon_path(data: &usvg::tiny_skia_path::Path) -> Option<lyon::path::Path> {
+    use usvg::tiny_skia_path::PathSegment;
+
+    let mut builder = lyon::path::Path::builder();
+    let mut open = false;
+    for segment in data.segments() {
+        match segment {
+            PathSegment::MoveTo(p) => {
+                if open {
+                    builder.end(false);
+                }
+                builder.begin(shapes::point(p.x, p.y));
+                open = true;
+            }
+            PathSegment::LineTo(p) if open => {
+                builder.line_to(shapes::point(p.x, p.y));
+            }
+            PathSegment::QuadTo(c, p) if open => {
+                builder.quadratic_bezier_to(shapes::point(c.x, c.y), shapes::point(p.x, p.y));
+            }
+            PathSegment::CubicTo(c1, c2, p) if open => {
+                builder.cubic_bezier_to(
+                    shapes::point(c1.x, c1.y),
+                    shapes::point(c2.x, c2.y),
+                    shapes::point(p.x, p.y),
+                );
+            }
+            PathSegment::Close if open => {
+                builder.end(true);
+                open = false;
+            }
+            _ => {}
+        }
+    }
+    if open {
+        builder.end(false);
+    }
+    Some(builder.build()).filter(|path| path.iter().next().is_some())
+}
+
+/// Linear RGBA for a paint, or `None` for paints that can't be drawn as a solid color.
+fn paint_color(paint: &usvg::Paint, opacity: f32) -> Option<[f32; 4]> {
+    let (color, opacity) = match paint {
+        usvg::Paint::Color(color) => (*color, opacity),
+        usvg::Paint::LinearGradient(g) => {
+            let stop = g.stops().first()?;
+            (stop.color(), opacity * stop.opacity().get())
+        }
+        usvg::Paint::RadialGradient(g) => {
+            let stop = g.stops().first()?;
+            (stop.color(), opacity * stop.opacity().get())
+        }
+        usvg::Paint::Pattern(_) => return None,
+    };
+    Some([
+        srgb_to_linear(color.red),
+        srgb_to_linear(color.green),
+        srgb_to_linear(color.blue),
+        opacity,
+    ])
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -0,0 +1,608 @@
+//! Terrain built from a heightmap: a grid split into square chunks, each meshed at a few
+//! levels of detail, drawn with up to four tiling textures blended by a splat map.
+//!
+//! Neighbouring chunks at different levels don't share all their edge vertices, which
+//! leaves cracks. Every chunk hangs a skirt down from its edges to cover them.
+
+use std::path::Path;
+
+use crate::camera::Camera;
+use crate::depth;
+use crate::globals::{FrameGlobals, FRAME_GLOBALS_WGSL};
+use crate::math::{Vec2, Vec3};
+use crate::mesh::{GpuMesh, Mesh, MeshVertex};
+use crate::stats::Tracked;
+use crate::texture::{SamplerOptions, Texture, TextureError};
+
+const SHADER: &str = r#"
+struct Globals {
+    frame: FrameGlobals,
+    // xyz the direction the light travels in, w the ambient light
+    light: vec4<f32>,
+    // x how often the layers repeat across the terrain
+    params: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(1) @binding(0) var splat_map: texture_2d<f32>;
+@group(1) @binding(1) var splat_sampler: sampler;
+@group(1) @binding(2) var layer0: texture_2d<f32>;
+@group(1) @binding(3) var layer1: texture_2d<f32>;
+@group(1) @binding(4) var layer2: texture_2d<f32>;
+@group(1) @binding(5) var layer3: texture_2d<f32>;
+@group(1) @binding(6) var layer_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = globals.frame.view_proj * vec4<f32>(in.position, 1.0);
+    out.normal = in.normal;
+    out.uv = in.uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var weights = textureSample(splat_map, splat_sampler, in.uv);
+    weights = weights / max(weights.x + weights.y + weights.z + weights.w, 0.0001);
+    let uv = in.uv * globals.params.x;
+    let color = textureSample(layer0, layer_sampler, uv).rgb * weights.x
+        + textureSample(layer1, layer_sampler, uv).rgb * weights.y
+        + textureSample(layer2, layer_sampler, uv).rgb * weights.z
+        + textureSample(layer3, layer_sampler, uv).rgb * weights.w;
+    let ambient = globals.light.w;
+    let diffuse = max(dot(normalize(in.normal), -globals.light.xyz), 0.0);
+    return vec4<f32>(color * (ambient + (1.0 - ambient) * diffuse), 1.0);
+}
+"#;
+
+/// Heights from 0 to 1 on a grid, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    /// `heights` has `width * height` values, at least 2x2.
+    pub fn new(width: u32, height: u32, heights: Vec<f32>) -> Self {
+        assert!(
+            width >= 2 && height >= 2,
+            "heightmaps need at least 2x2 samples"
+        );
+        assert_eq!(heights.len(), (width * height) as usize);
+        Self {
+            width,
+            height,
+            heights,
+        }
+    }
+
+    /// Brightness of each pixel, 16 bit images keep their full precision.
+    pub fn from_image(image: &image::DynamicImage) -> Self {
+        let luma = image.to_luma16();
+        let heights = luma.pixels().map(|p| p.0[0] as f32 / 65535.0).collect();
+        Self::new(luma.width(), luma.height(), heights)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TextureError> {
+        let image = image::load_from_memory(bytes).map_err(TextureError::Image)?;
+        Ok(Self::from_image(&image))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TextureError> {
+        let bytes = std::fs::read(path).map_err(TextureError::Io)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The sample at `x`, `y`, clamped to the edges.
+    pub fn get(&self, x: i64, y: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.heights[y * self.width as usize + x]
+    }
+
+    /// Bilinearly interpolated between samples, `u` and `v` from 0 to 1 across the map.
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        let x = u.clamp(0.0, 1.0) * (self.width - 1) as f32;
+        let y = v.clamp(0.0, 1.0) * (self.height - 1) as f32;
+        let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+        let (tx, ty) = (x.fract(), y.fract());
+        let top = self.get(x0, y0) * (1.0 - tx) + self.get(x0 + 1, y0) * tx;
+        let bottom = self.get(x0, y0 + 1) * (1.0 - tx) + self.get(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TerrainConfig {
+    /// World size along x and z, centered on the origin.
+    pub size: Vec2,
+    /// World height of a heightmap value of 1.
+    pub height_scale: f32,
+    /// Heightmap cells along each side of a chunk.
+    pub chunk_size: u32,
+    /// Meshes per chunk, each with half the vertices along a side of the one before.
+    pub lod_levels: u32,
+    /// How far the skirts hang below the chunk edges.
+    pub skirt_depth: f32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            size: Vec2::new(100.0, 100.0),
+            height_scale: 10.0,
+            chunk_size: 64,
+            lod_levels: 4,
+            skirt_depth: 1.0,
+        }
+    }
+}
+
+impl TerrainConfig {
+    pub fn with_size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_height_scale(mut self, height_scale: f32) -> Self {
+        self.height_scale = height_scale;
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn with_lod_levels(mut self, lod_levels: u32) -> Self {
+        self.lod_levels = lod_levels;
+        self
+    }
+
+    pub fn with_skirt_depth(mut self, skirt_depth: f32) -> Self {
+        self.skirt_depth = skirt_depth;
+        self
+    }
+}
+
+/// One chunk's meshes, the most detailed first.
+#[derive(Clone, Debug)]
+pub struct TerrainChunk {
+    pub lods: Vec<Mesh>,
+    /// Middle of the chunk's bounds, the distance to it picks the level of detail.
+    pub center: Vec3,
+    /// Half the diagonal of the chunk's bounds.
+    pub radius: f32,
+}
+
+/// The terrain meshes on the CPU.
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    pub config: TerrainConfig,
+    pub heightmap: Heightmap,
+    pub chunks: Vec<TerrainChunk>,
+}
+
+impl Terrain {
+    pub fn new(heightmap: Heightmap, config: TerrainConfig) -> Self {
+        let chunk_size = config.chunk_size.max(1);
+        let cells_x = heightmap.width - 1;
+        let cells_z = heightmap.height - 1;
+        let mut terrain = Self {
+            config,
+            heightmap,
+            chunks: Vec::new(),
+        };
+        for z in (0..cells_z).step_by(chunk_size as usize) {
+            for x in (0..cells_x).step_by(chunk_size as usize) {
+                let end_x = (x + chunk_size).min(cells_x);
+                let end_z = (z + chunk_size).min(cells_z);
+                let chunk = terrain.build_chunk(x..end_x, z..end_z);
+                terrain.chunks.push(chunk);
+            }
+        }
+        terrain
+    }
+
+    /// Terrain height under world `x`, `z`, interpolated between heightmap samples.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let size = self.config.size;
+        let u = x / size.x + 0.5;
+        let v = z / size.y + 0.5;
+        self.heightmap.sample(u, v) * self.config.height_scale
+    }
+
+    fn position(&self, x: u32, z: u32) -> Vec3 {
+        let size = self.config.size;
+        let u = x as f32 / (self.heightmap.width - 1) as f32;
+        let v = z as f32 / (self.heightmap.height - 1) as f32;
+        Vec3::new(
+            (u - 0.5) * size.x,
+            self.heightmap.get(x as i64, z as i64) * self.config.height_scale,
+            (v - 0.5) * size.y,
+        )
+    }
+
+    /// From the slope between the neighbouring samples, so chunk edges line up.
+    fn normal(&self, x: u32, z: u32) -> Vec3 {
+        let (x, z) = (x as i64, z as i64);
+        let step_x = self.config.size.x / (self.heightmap.width - 1) as f32;
+        let step_z = self.config.size.y / (self.heightmap.height - 1) as f32;
+        let scale = self.config.height_scale;
+        let dx =
+            (self.heightmap.get(x + 1, z) - self.heightmap.get(x - 1, z)) * scale / (2.0 * step_x);
+        let dz =
+            (self.heightmap.get(x, z + 1) - self.heightmap.get(x, z - 1)) * scale / (2.0 * step_z);
+        Vec3::new(-dx, 1.0, -dz).normalize()
+    }
+
+    fn vertex(&self, x: u32, z: u32) -> MeshVertex {
+        MeshVertex {
+            position: self.position(x, z),
+            normal: self.normal(x, z),
+            uv: Vec2::new(
+                x as f32 / (self.heightmap.width - 1) as f32,
+                z as f32 / (self.heightmap.height - 1) as f32,
+            ),
+        }
+    }
+
+    fn build_chunk(&self, xs: std::ops::Range<u32>, zs: std::ops::Range<u32>) -> TerrainChunk {
+        let lods = (0..self.config.lod_levels.max(1))
+            .map(|lod| self.build_lod(&xs, &zs, 1 << lod))
+            .collect::<Vec<_>>();
+        let aabb = lods[0].aabb().expect("chunks have vertices");
+        TerrainChunk {
+            lods,
+            center: aabb.center(),
+            radius: aabb.half_extents().length(),
+        }
+    }
+
+    fn build_lod(&self, xs: &std::ops::Range<u32>, zs: &std::ops::Range<u32>, step: u32) -> Mesh {
+        // every `step`th sample, always ending on the chunk's last one
+        let samples = |range: &std::ops::Range<u32>| {
+            let mut samples: Vec<u32> = (range.start..range.end).step_by(step as usize).collect();
+            samples.push(range.end);
+            samples
+        };
+        let xs = samples(xs);
+        let zs = samples(zs);
+        let mut mesh = Mesh::default();
+        for &z in &zs {
+            for &x in &xs {
+                mesh.vertices.push(self.vertex(x, z));
+            }
+        }
+        let row = xs.len() as u32;
+        for j in 0..zs.len() as u32 - 1 {
+            for i in 0..row - 1 {
+                let a = j * row + i;
+                let b = a + row;
+                mesh.indices
+                    .extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+            }
+        }
+
+        let last_row = (zs.len() as u32 - 1) * row;
+        let edges: [(Vec<u32>, Vec3); 4] = [
+            ((0..row).collect(), -Vec3::Z),
+            ((last_row..last_row + row).collect(), Vec3::Z),
+            ((0..zs.len() as u32).map(|j| j * row).collect(), -Vec3::X),
+            (
+                (0..zs.len() as u32).map(|j| j * row + row - 1).collect(),
+                Vec3::X,
+            ),
+        ];
+        for (edge, outward) in edges {
+            self.add_skirt(&mut mesh, &edge, outward);
+        }
+        mesh
+    }
+
+    fn add_skirt(&self, mesh: &mut Mesh, edge: &[u32], outward: Vec3) {
+        let first = mesh.vertices.len() as u32;
+        for &i in edge {
+            let mut vertex = mesh.vertices[i as usize];
+            vertex.position.y -= self.config.skirt_depth;
+            mesh.vertices.push(vertex);
+        }
+        for k in 0..edge.len() - 1 {
+            let (a, b) = (edge[k], edge[k + 1]);
+            let (c, d) = (first + k as u32, first + k as u32 + 1);
+            let [pa, pb, pc] = [a, b, c].map(|i| mesh.vertices[i as usize].position);
+            // wound to face out of the chunk
+            if (pb - pa).cross(pc - pa).dot(outward) >= 0.0 {
+                mesh.indices.extend_from_slice(&[a, b, c, b, d, c]);
+            } else {
+                mesh.indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+    }
+}
+
+/// The textures a terrain is drawn with. The splat map stretches over the whole terrain
+/// and its red, green, blue and alpha channels weigh the four layers, which tile `tiling`
+/// times across it. Layers are sampled repeating, whatever their own sampler does; give
+/// them mipmaps so they don't shimmer in the distance.
+pub struct SplatMaterial {
+    pub splat_map: Texture,
+    pub layers: [Texture; 4],
+    pub tiling: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Globals {
+    frame: FrameGlobals,
+    light: [f32; 4],
+    params: [f32; 4],
+}
+unsafe impl bytemuck::Pod for Globals {}
+unsafe impl bytemuck::Zeroable for Globals {}
+
+struct GpuChunk {
+    lods: Vec<GpuMesh>,
+    center: Vec3,
+    radius: f32,
+    lod: usize,
+}
+
+/// Draws a `Terrain`. Depth testing needs a depth attachment in the pass of the format
+/// given to `new`, without one chunks draw over each other.
+pub struct TerrainRenderer {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    material_bind_group: wgpu::BindGroup,
+    material: SplatMaterial,
+    chunks: Vec<GpuChunk>,
+    /// Distance to a chunk's edge within which it's drawn at full detail. Each level after
+    /// the first takes over at twice the distance of the one before.
+    pub lod_distance: f32,
+    /// Direction the sunlight travels in.
+    pub light_direction: Vec3,
+    /// Light reaching surfaces facing away from the sun, from 0 to 1.
+    pub ambient: f32,
+    _tracked: Tracked,
+}
+
+impl TerrainRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        terrain: &Terrain,
+        material: SplatMaterial,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Terrain Shader"),
+            source: wgpu::ShaderSource::Wgsl([FRAME_GLOBALS_WGSL, SHADER].concat().into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Terrain Globals"),
+            size: std::mem::size_of::<Globals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Terrain Globals Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain Globals Bind Group"),
+            layout: &globals_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let material_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Terrain Material Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                sampler_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                texture_entry(4),
+                texture_entry(5),
+                sampler_entry(6),
+            ],
+        });
+        let layer_sampler = SamplerOptions {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            anisotropy: 16,
+            ..Default::default()
+        }
+        .create_sampler(device);
+        let [layer0, layer1, layer2, layer3] = material
+            .layers
+            .each_ref()
+            .map(|layer| wgpu::BindingResource::TextureView(&layer.view));
+        let material_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Terrain Material Bind Group"),
+            layout: &material_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&material.splat_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&material.splat_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: layer0,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: layer1,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: layer2,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: layer3,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Sampler(&layer_sampler),
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Terrain Pipeline Layout"),
+            bind_group_layouts: &[&globals_layout, &material_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Terrain Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[MeshVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: depth_format.map(depth::depth_stencil),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let chunks = terrain
+            .chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| GpuChunk {
+                lods: chunk
+                    .lods
+                    .iter()
+                    .enumerate()
+                    .map(|(lod, mesh)| {
+                        GpuMesh::new(device, mesh, &format!("Terrain Chunk {} LOD {}", i, lod))
+                    })
+                    .collect(),
+                center: chunk.center,
+                radius: chunk.radius,
+                lod: 0,
+            })
+            .collect();
+
+        let tracked = Tracked::new(1, 1, 0).with_buffer(&globals_buffer);
+        Self {
+            pipeline,
+            globals_buffer,
+            globals_bind_group,
+            material_bind_group,
+            material,
+            chunks,
+            lod_distance: terrain.config.size.max_element() / 8.0,
+            light_direction: Vec3::new(-0.4, -1.0, -0.3).normalize(),
+            ambient: 0.3,
+            _tracked: tracked,
+        }
+    }
+
+    pub fn material(&self) -> &SplatMaterial {
+        &self.material
+    }
+
+    /// Uploads the camera and picks each chunk's level of detail from its distance.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let globals = Globals {
+            frame: FrameGlobals::from_camera(camera),
+            light: self
+                .light_direction
+                .normalize()
+                .extend(self.ambient)
+                .to_array(),
+            params: [self.material.tiling, 0.0, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        for chunk in &mut self.chunks {
+            let distance = (camera.eye.distance(chunk.center) - chunk.radius).max(0.0);
+            let lod_distance = self.lod_distance.max(f32::EPSILON);
+            let lod = if distance < lod_distance {
+                0
+            } else {
+                (distance / lod_distance).log2() as usize + 1
+            };
+            chunk.lod = lod.min(chunk.lods.len() - 1);
+        }
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.push_debug_group("Terrain");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.material_bind_group, &[]);
+        for chunk in &self.chunks {
+            chunk.lods[chunk.lod].draw(render_pass, 0..1);
+        }
+        render_pass.pop_debug_group();
+    }
+}
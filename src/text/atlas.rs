@@ -0,0 +1,68 @@
+//! CPU side glyph atlas, packed in shelves and uploaded to the GPU when it changes.
+
+pub(crate) const ATLAS_WIDTH: u32 = 1024;
+/// Stays within the downlevel (WebGL2) texture size limit.
+const MAX_HEIGHT: u32 = 2048;
+
+pub(crate) struct GlyphAtlas {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) pixels: Vec<u8>,
+    cursor_x: u32,
+    cursor_y: u32,
+    shelf_height: u32,
+    /// Rows written since the last upload.
+    pub(crate) dirty: Option<std::ops::Range<u32>>,
+}
+
+impl GlyphAtlas {
+    pub(crate) fn new() -> Self {
+        let height = 256;
+        Self {
+            width: ATLAS_WIDTH,
+            height,
+            pixels: vec![0; (ATLAS_WIDTH * height * 4) as usize],
+            cursor_x: 0,
+            cursor_y: 0,
+            shelf_height: 0,
+            dirty: None,
+        }
+    }
+
+    /// Copies an RGBA image into the atlas, returning its top left corner.
+    /// Images are kept one texel apart so linear filtering doesn't bleed between them.
+    pub(crate) fn insert(&mut self, width: u32, height: u32, data: &[u8]) -> Option<[u32; 2]> {
+        if width + 1 > self.width {
+            return None;
+        }
+        if self.cursor_x + width + 1 > self.width {
+            self.cursor_x = 0;
+            self.cursor_y += self.shelf_height;
+            self.shelf_height = 0;
+        }
+        while self.cursor_y + height + 1 > self.height {
+            if self.height >= MAX_HEIGHT {
+                return None;
+            }
+            self.height *= 2;
+            self.pixels
+                .resize((self.width * self.height * 4) as usize, 0);
+            self.dirty = Some(0..self.height);
+        }
+
+        let (x, y) = (self.cursor_x, self.cursor_y);
+        for row in 0..height {
+            let src = (row * width * 4) as usize;
+            let dst = (((y + row) * self.width + x) * 4) as usize;
+            self.pixels[dst..dst + (width * 4) as usize]
+                .copy_from_slice(&data[src..src + (width * 4) as usize]);
+        }
+        self.cursor_x += width + 1;
+        self.shelf_height = self.shelf_height.max(height + 1);
+        self.dirty = Some(match self.dirty.take() {
+            Some(rows) => rows.start.min(y)..rows.end.max(y + height),
+            None => y..y + height,
+        });
+        Some([x, y])
+    }
+}
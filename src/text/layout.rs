@@ -0,0 +1,449 @@
+//! Paragraph layout: styled spans, word wrapping, alignment and line spacing.
+//!
+//! Text is shaped with rustybuzz in runs of one font, script and direction, so ligatures,
+//! marks and complex scripts come out right. Characters the span's font lacks fall back to
+//! the renderer's fallback fonts, and mixed direction lines are reordered with the bidi
+//! algorithm after wrapping.
+
+use ab_glyph::GlyphId;
+use rustybuzz::{Direction, UnicodeBuffer};
+use unicode_bidi::BidiInfo;
+use unicode_script::{Script, UnicodeScript};
+
+use crate::math::Vec2;
+
+use super::{Font, FontId};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Font, em size and color of a run of text.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    pub font: FontId,
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+impl TextStyle {
+    pub fn new(font: FontId, size: f32) -> Self {
+        Self {
+            font,
+            size,
+            color: [1.0; 4],
+        }
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+/// Styled text waiting to be laid out by `TextRenderer::layout`.
+#[derive(Clone, Debug)]
+pub struct Paragraph {
+    spans: Vec<(String, TextStyle)>,
+    max_width: Option<f32>,
+    align: Align,
+    line_spacing: f32,
+}
+
+impl Paragraph {
+    pub fn new() -> Self {
+        Self {
+            spans: Vec::new(),
+            max_width: None,
+            align: Align::Left,
+            line_spacing: 1.0,
+        }
+    }
+
+    pub fn span(mut self, text: impl Into<String>, style: TextStyle) -> Self {
+        self.spans.push((text.into(), style));
+        self
+    }
+
+    /// Wraps lines at word boundaries, or inside words longer than `width`.
+    pub fn with_max_width(mut self, width: f32) -> Self {
+        self.max_width = Some(width);
+        self
+    }
+
+    /// Lines are aligned within the max width, or the widest line without one.
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Multiplies the fonts' natural line height.
+    pub fn with_line_spacing(mut self, spacing: f32) -> Self {
+        self.line_spacing = spacing;
+        self
+    }
+}
+
+impl Default for Paragraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct PositionedGlyph {
+    pub(crate) font: FontId,
+    pub(crate) id: GlyphId,
+    /// Pen position on the baseline.
+    pub(crate) position: Vec2,
+    pub(crate) size: f32,
+    pub(crate) color: [f32; 4],
+}
+
+/// Placement of one laid out line, relative to the layout's top left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LineMetrics {
+    pub x: f32,
+    pub top: f32,
+    pub baseline: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Result of laying out a `Paragraph`, ready to measure or draw any number of times.
+#[derive(Clone, Debug, Default)]
+pub struct TextLayout {
+    pub(crate) glyphs: Vec<PositionedGlyph>,
+    lines: Vec<LineMetrics>,
+}
+
+impl TextLayout {
+    pub fn lines(&self) -> &[LineMetrics] {
+        &self.lines
+    }
+
+    /// Tight box around the lines, relative to the layout's top left corner. Alignment
+    /// inside a max width can leave the minimum away from the origin.
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        if self.lines.is_empty() {
+            return (Vec2::ZERO, Vec2::ZERO);
+        }
+        self.lines.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), line| {
+                (
+                    min.min(Vec2::new(line.x, line.top)),
+                    max.max(Vec2::new(line.x + line.width, line.top + line.height)),
+                )
+            },
+        )
+    }
+
+    pub fn size(&self) -> Vec2 {
+        let (min, max) = self.bounds();
+        max - min
+    }
+}
+
+/// A shaped glyph in logical order, before line breaking.
+#[derive(Copy, Clone, Debug)]
+struct ShapedGlyph {
+    font: FontId,
+    id: GlyphId,
+    advance: f32,
+    /// Shaper offset from the pen position, y down.
+    offset: Vec2,
+    size: f32,
+    color: [f32; 4],
+    whitespace: bool,
+    /// Bidi embedding level, odd levels run right to left.
+    level: u8,
+}
+
+enum Item {
+    Glyph(ShapedGlyph),
+    /// Hard line break, styled so empty lines still get a height.
+    Newline(TextStyle),
+}
+
+/// Joiners and variation selectors stay in the font of the character they modify.
+fn is_modifier(c: char) -> bool {
+    matches!(c, '\u{200c}' | '\u{200d}' | '\u{fe00}'..='\u{fe0f}')
+}
+
+/// Picks the span's font when it covers `c`, then the font already in use, then the first
+/// fallback that does.
+fn pick_font(
+    fonts: &[Font],
+    fallbacks: &[FontId],
+    primary: FontId,
+    current: Option<FontId>,
+    c: char,
+) -> FontId {
+    if let Some(current) = current {
+        if c.is_whitespace() || is_modifier(c) {
+            return current;
+        }
+    }
+    if fonts[primary.0].has_glyph(c) {
+        return primary;
+    }
+    if let Some(current) = current.filter(|f| fonts[f.0].has_glyph(c)) {
+        return current;
+    }
+    fallbacks
+        .iter()
+        .copied()
+        .find(|f| fonts[f.0].has_glyph(c))
+        .unwrap_or(primary)
+}
+
+/// Splits the spans into runs of one font, script and direction and shapes each of them.
+fn shape(fonts: &[Font], fallbacks: &[FontId], paragraph: &Paragraph) -> Vec<Item> {
+    let text: String = paragraph.spans.iter().map(|(t, _)| t.as_str()).collect();
+    let bidi = BidiInfo::new(&text, None);
+
+    let mut items = Vec::new();
+    let mut span_start = 0;
+    for (span, style) in &paragraph.spans {
+        let mut run: Option<(usize, FontId, Script, u8)> = None;
+        let mut font = None;
+        let mut script = Script::Common;
+        for (i, c) in span.char_indices() {
+            let index = span_start + i;
+            if c == '\n' {
+                if let Some((start, font, _, level)) = run.take() {
+                    shape_run(fonts, &text[start..index], font, level, style, &mut items);
+                }
+                items.push(Item::Newline(*style));
+                continue;
+            }
+            let picked = pick_font(fonts, fallbacks, style.font, font, c);
+            font = Some(picked);
+            let char_script = c.script();
+            if !matches!(
+                char_script,
+                Script::Common | Script::Inherited | Script::Unknown
+            ) {
+                script = char_script;
+            }
+            let level = bidi.levels[index].number();
+            match run {
+                Some((_, f, s, l)) if f == picked && s == script && l == level => {}
+                _ => {
+                    if let Some((start, font, _, level)) = run.take() {
+                        shape_run(fonts, &text[start..index], font, level, style, &mut items);
+                    }
+                    run = Some((index, picked, script, level));
+                }
+            }
+        }
+        let span_end = span_start + span.len();
+        if let Some((start, font, _, level)) = run {
+            shape_run(
+                fonts,
+                &text[start..span_end],
+                font,
+                level,
+                style,
+                &mut items,
+            );
+        }
+        span_start = span_end;
+    }
+    items
+}
+
+fn shape_run(
+    fonts: &[Font],
+    text: &str,
+    font_id: FontId,
+    level: u8,
+    style: &TextStyle,
+    items: &mut Vec<Item>,
+) {
+    let font = &fonts[font_id.0];
+    let Some(face) = font.face() else {
+        log::warn!("Failed to parse font for shaping");
+        return;
+    };
+    let mut buffer = UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.set_direction(if level % 2 == 1 {
+        Direction::RightToLeft
+    } else {
+        Direction::LeftToRight
+    });
+    let output = rustybuzz::shape(&face, &[], buffer);
+
+    let scale = style.size / font.units_per_em();
+    let glyphs = output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, position)| {
+            let whitespace = text[info.cluster as usize..]
+                .chars()
+                .next()
+                .is_some_and(char::is_whitespace);
+            ShapedGlyph {
+                font: font_id,
+                id: GlyphId(info.glyph_id as u16),
+                advance: position.x_advance as f32 * scale,
+                offset: Vec2::new(position.x_offset as f32, -position.y_offset as f32) * scale,
+                size: style.size,
+                color: style.color,
+                whitespace,
+                level,
+            }
+        });
+    // the shaper returns right to left runs in visual order, lines are broken in logical order
+    let start = items.len();
+    items.extend(glyphs.map(Item::Glyph));
+    if level % 2 == 1 {
+        items[start..].reverse();
+    }
+}
+
+struct Line {
+    glyphs: Vec<ShapedGlyph>,
+    /// Index of the first glyph after the last break opportunity.
+    word_start: usize,
+    width: f32,
+    /// Style of the line break ending the line, for the height of empty lines.
+    style: Option<TextStyle>,
+}
+
+impl Line {
+    fn new() -> Self {
+        Self {
+            glyphs: Vec::new(),
+            word_start: 0,
+            width: 0.0,
+            style: None,
+        }
+    }
+}
+
+pub(crate) fn layout(fonts: &[Font], fallbacks: &[FontId], paragraph: &Paragraph) -> TextLayout {
+    let max_width = paragraph.max_width.unwrap_or(f32::INFINITY);
+    let mut lines: Vec<Line> = Vec::new();
+    let mut line = Line::new();
+
+    for item in shape(fonts, fallbacks, paragraph) {
+        let glyph = match item {
+            Item::Glyph(glyph) => glyph,
+            Item::Newline(style) => {
+                line.style = Some(style);
+                lines.push(std::mem::replace(&mut line, Line::new()));
+                continue;
+            }
+        };
+        if !glyph.whitespace && line.width + glyph.advance > max_width && !line.glyphs.is_empty() {
+            let mut next = Line::new();
+            if line.word_start > 0 && line.word_start < line.glyphs.len() {
+                // carry the unfinished word over to the next line
+                next.glyphs = line.glyphs.split_off(line.word_start);
+                next.width = next.glyphs.iter().map(|g| g.advance).sum();
+            }
+            lines.push(std::mem::replace(&mut line, next));
+        }
+        line.glyphs.push(glyph);
+        line.width += glyph.advance;
+        if glyph.whitespace {
+            line.word_start = line.glyphs.len();
+        }
+    }
+    lines.push(line);
+
+    let mut placed = Vec::with_capacity(lines.len());
+    for mut line in lines {
+        while line.glyphs.last().is_some_and(|g| g.whitespace) {
+            line.glyphs.pop();
+        }
+        reorder(&mut line.glyphs);
+
+        let (mut ascent, mut height) = line.style.map_or((0.0, 0.0), |style| {
+            let font = &fonts[style.font.0];
+            (font.ascent() * style.size, font.line_height() * style.size)
+        });
+        for glyph in &line.glyphs {
+            let font = &fonts[glyph.font.0];
+            ascent = f32::max(ascent, font.ascent() * glyph.size);
+            height = f32::max(height, font.line_height() * glyph.size);
+        }
+        if line.glyphs.is_empty() && line.style.is_none() {
+            // last line after a trailing break, or an empty paragraph
+            if let Some(style) = paragraph.spans.last().map(|(_, style)| style) {
+                let font = &fonts[style.font.0];
+                ascent = font.ascent() * style.size;
+                height = font.line_height() * style.size;
+            }
+        }
+        let width = line.glyphs.iter().map(|g| g.advance).sum::<f32>();
+        placed.push((line.glyphs, width, ascent, height));
+    }
+
+    let box_width = paragraph
+        .max_width
+        .unwrap_or_else(|| placed.iter().map(|l| l.1).fold(0.0, f32::max));
+    let factor = match paragraph.align {
+        Align::Left => 0.0,
+        Align::Center => 0.5,
+        Align::Right => 1.0,
+    };
+
+    let mut result = TextLayout::default();
+    let mut top = 0.0;
+    for (glyphs, width, ascent, height) in placed {
+        let x = ((box_width - width) * factor).max(0.0);
+        let baseline = top + ascent;
+        let mut pen = x;
+        for glyph in glyphs {
+            result.glyphs.push(PositionedGlyph {
+                font: glyph.font,
+                id: glyph.id,
+                position: Vec2::new(pen, baseline) + glyph.offset,
+                size: glyph.size,
+                color: glyph.color,
+            });
+            pen += glyph.advance;
+        }
+        result.lines.push(LineMetrics {
+            x,
+            top,
+            baseline,
+            width,
+            height,
+        });
+        top += height * paragraph.line_spacing;
+    }
+    result
+}
+
+/// Puts a line's glyphs in visual order by reversing every run at or above each odd
+/// embedding level, highest first (rule L2 of the bidi algorithm).
+fn reorder(glyphs: &mut [ShapedGlyph]) {
+    let Some(highest) = glyphs.iter().map(|g| g.level).max() else {
+        return;
+    };
+    let lowest_odd = glyphs.iter().map(|g| g.level).min().unwrap_or(0) | 1;
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < glyphs.len() {
+            if glyphs[i].level < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < glyphs.len() && glyphs[i].level >= level {
+                i += 1;
+            }
+            glyphs[start..i].reverse();
+        }
+    }
+}
@@ -0,0 +1,592 @@
+//! Text drawn from multi-channel signed distance field glyphs, so it stays sharp at any
+//! scale or rotation. Glyph fields are generated on first use and packed into one atlas.
+
+mod atlas;
+mod layout;
+mod msdf;
+mod system;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ab_glyph::{Font as _, FontVec, GlyphId};
+
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::math::{Mat4, Vec2};
+use crate::stats::Tracked;
+
+use atlas::GlyphAtlas;
+
+pub use layout::{Align, LineMetrics, Paragraph, TextLayout, TextStyle};
+pub use system::SystemFonts;
+
+/// Size of one em in the atlas, in texels.
+const GLYPH_EM_SIZE: f32 = 32.0;
+/// Distance range of the fields in atlas texels, also `PX_RANGE` in the shader.
+const PX_RANGE: f32 = 4.0;
+
+const SHADER: &str = r#"
+@group(0) @binding(1)
+var atlas: texture_2d<f32>;
+@group(0) @binding(2)
+var atlas_sampler: sampler;
+
+const PX_RANGE: f32 = 4.0;
+
+struct InstanceInput {
+    @location(0) origin: vec2<f32>,
+    @location(1) axis_x: vec2<f32>,
+    @location(2) axis_y: vec2<f32>,
+    @location(3) uv_min: vec2<f32>,
+    @location(4) uv_max: vec2<f32>,
+    @location(5) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, instance: InstanceInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[index];
+    let world = instance.origin + instance.axis_x * corner.x + instance.axis_y * corner.y;
+
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(world, 0.0, 1.0);
+    out.uv = mix(instance.uv_min, instance.uv_max, corner) / vec2<f32>(textureDimensions(atlas));
+    out.color = instance.color;
+    return out;
+}
+
+fn median(a: f32, b: f32, c: f32) -> f32 {
+    return max(min(a, b), min(max(a, b), c));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let msd = textureSample(atlas, atlas_sampler, in.uv).rgb;
+    let distance = median(msd.r, msd.g, msd.b) - 0.5;
+    // how many screen pixels the field's range covers at this scale
+    let unit_range = vec2<f32>(PX_RANGE) / vec2<f32>(textureDimensions(atlas));
+    let screen_size = vec2<f32>(1.0) / fwidth(in.uv);
+    let screen_range = max(0.5 * dot(unit_range, screen_size), 1.0);
+    let coverage = clamp(distance * screen_range + 0.5, 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct GlyphInstance {
+    origin: [f32; 2],
+    axis_x: [f32; 2],
+    axis_y: [f32; 2],
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    color: [f32; 4],
+}
+unsafe impl bytemuck::Pod for GlyphInstance {}
+unsafe impl bytemuck::Zeroable for GlyphInstance {}
+
+impl GlyphInstance {
+    const ATTRIBS: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+        2 => Float32x2,
+        3 => Float32x2,
+        4 => Float32x2,
+        5 => Float32x4
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FontError(ab_glyph::InvalidFont);
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to load font: {}", self.0)
+    }
+}
+
+impl std::error::Error for FontError {}
+
+/// A TrueType or OpenType font.
+#[derive(Clone)]
+pub struct Font {
+    font: Arc<FontVec>,
+    index: u32,
+}
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, FontError> {
+        Self::from_collection(data, 0)
+    }
+
+    /// Loads face `index` of a font collection (.ttc), 0 for single fonts.
+    pub fn from_collection(data: Vec<u8>, index: u32) -> Result<Self, FontError> {
+        let font = FontVec::try_from_vec_and_index(data, index).map_err(FontError)?;
+        Ok(Self {
+            font: Arc::new(font),
+            index,
+        })
+    }
+
+    /// True when the font has a glyph for `c`.
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.font.glyph_id(c).0 != 0
+    }
+
+    fn face(&self) -> Option<rustybuzz::Face<'_>> {
+        rustybuzz::Face::from_slice(self.font.as_slice(), self.index)
+    }
+
+    fn units_per_em(&self) -> f32 {
+        self.font.units_per_em().unwrap_or(1000.0)
+    }
+
+    /// Distance from the top of a line to the baseline, in ems.
+    pub fn ascent(&self) -> f32 {
+        self.font.ascent_unscaled() / self.units_per_em()
+    }
+
+    /// Baseline to baseline distance, in ems.
+    pub fn line_height(&self) -> f32 {
+        (self.font.ascent_unscaled() - self.font.descent_unscaled() + self.font.line_gap_unscaled())
+            / self.units_per_em()
+    }
+}
+
+/// Handle to a font added to a `TextRenderer`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FontId(usize);
+
+/// Where a glyph's field sits in the atlas and how it lines up with the pen position.
+#[derive(Copy, Clone, Debug)]
+struct AtlasGlyph {
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    /// Quad offset from the pen position and its size, in ems with y down.
+    offset: Vec2,
+    size: Vec2,
+}
+
+/// Immediate mode text in the space defined by the `view_proj` given to `prepare`,
+/// laid out with y growing down like `ShapeRenderer::pixel_projection`.
+pub struct TextRenderer {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    atlas_texture: wgpu::Texture,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    instance_count: u32,
+    atlas: GlyphAtlas,
+    fonts: Vec<Font>,
+    fallbacks: Vec<FontId>,
+    glyphs: HashMap<(FontId, GlyphId), Option<AtlasGlyph>>,
+    queued: Vec<GlyphInstance>,
+    tracked: Tracked,
+}
+
+impl TextRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Text Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Text Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Text Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Text Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let atlas = GlyphAtlas::new();
+        let atlas_texture = Self::create_atlas_texture(device, &atlas);
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &globals_buffer,
+            &atlas_texture,
+            &sampler,
+        );
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Text Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[GlyphInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let instance_capacity = 1024;
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+        let tracked = Tracked::new(1, 2, 1)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&instance_buffer)
+            .with_texture(&atlas_texture);
+
+        Self {
+            pipeline,
+            globals_buffer,
+            bind_group_layout,
+            bind_group,
+            sampler,
+            atlas_texture,
+            instance_buffer,
+            instance_capacity,
+            instance_count: 0,
+            atlas,
+            fonts: Vec::new(),
+            fallbacks: Vec::new(),
+            glyphs: HashMap::new(),
+            queued: Vec::new(),
+            tracked,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Text Instance Buffer"),
+            size: (capacity * std::mem::size_of::<GlyphInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_atlas_texture(device: &wgpu::Device, atlas: &GlyphAtlas) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Text Atlas"),
+            size: wgpu::Extent3d {
+                width: atlas.width,
+                height: atlas.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // distances, not colors, so no sRGB decoding
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        globals_buffer: &wgpu::Buffer,
+        atlas_texture: &wgpu::Texture,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        let view = atlas_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Text Atlas View"),
+            ..Default::default()
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Text Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: globals_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    pub fn add_font(&mut self, font: Font) -> FontId {
+        self.fonts.push(font);
+        FontId(self.fonts.len() - 1)
+    }
+
+    /// Adds a font to try, in the order added, for characters a span's own font lacks.
+    /// Color bitmap emoji fonts have no outlines, use a monochrome emoji font instead.
+    pub fn add_fallback(&mut self, font: Font) -> FontId {
+        let id = self.add_font(font);
+        self.fallbacks.push(id);
+        id
+    }
+
+    pub fn font(&self, id: FontId) -> &Font {
+        &self.fonts[id.0]
+    }
+
+    /// Returns the cached glyph, generating its distance field the first time.
+    fn glyph(&mut self, font_id: FontId, id: GlyphId) -> Option<AtlasGlyph> {
+        if let Some(glyph) = self.glyphs.get(&(font_id, id)) {
+            return *glyph;
+        }
+        let glyph = self.generate_glyph(font_id, id);
+        self.glyphs.insert((font_id, id), glyph);
+        glyph
+    }
+
+    fn generate_glyph(&mut self, font_id: FontId, id: GlyphId) -> Option<AtlasGlyph> {
+        let font = &self.fonts[font_id.0];
+        let units_per_em = font.units_per_em();
+        let scale = GLYPH_EM_SIZE / units_per_em;
+        let outline = font.font.outline(id)?;
+        let shape = msdf::Shape::from_outline(&outline.curves, scale)?;
+
+        let padding = PX_RANGE.ceil() as u32;
+        let (min, max) = shape.bounds();
+        let width = ((max.x - min.x) * scale).ceil() as u32 + padding * 2;
+        let height = ((max.y - min.y) * scale).ceil() as u32 + padding * 2;
+        let origin = Vec2::new(
+            min.x - padding as f32 / scale,
+            max.y + padding as f32 / scale,
+        );
+        let pixels = shape.generate(width, height, origin, scale, PX_RANGE);
+
+        let Some([x, y]) = self.atlas.insert(width, height, &pixels) else {
+            log::warn!("Text atlas is full, glyph {:?} will not be drawn", id);
+            return None;
+        };
+        Some(AtlasGlyph {
+            uv_min: [x as f32, y as f32],
+            uv_max: [(x + width) as f32, (y + height) as f32],
+            offset: Vec2::new(origin.x, -origin.y) / units_per_em,
+            size: Vec2::new(width as f32, height as f32) / GLYPH_EM_SIZE,
+        })
+    }
+
+    /// Queues `text` with its first line's top left corner at `position`. `size` is the em
+    /// size in output units. Returns the size of the text block.
+    pub fn draw(
+        &mut self,
+        font: FontId,
+        text: &str,
+        position: Vec2,
+        size: f32,
+        color: [f32; 4],
+    ) -> Vec2 {
+        self.draw_rotated(font, text, position, size, 0.0, color)
+    }
+
+    /// Like `draw`, turning the text by `rotation` radians around `position`.
+    pub fn draw_rotated(
+        &mut self,
+        font: FontId,
+        text: &str,
+        position: Vec2,
+        size: f32,
+        rotation: f32,
+        color: [f32; 4],
+    ) -> Vec2 {
+        let paragraph = Paragraph::new().span(text, TextStyle::new(font, size).with_color(color));
+        let layout = self.layout(&paragraph);
+        self.draw_layout_rotated(&layout, position, rotation);
+        layout.size()
+    }
+
+    /// Size of the block `draw` would produce, without queuing anything.
+    pub fn measure(&self, font: FontId, text: &str, size: f32) -> Vec2 {
+        self.layout(&Paragraph::new().span(text, TextStyle::new(font, size)))
+            .size()
+    }
+
+    /// Lays out a paragraph so it can be measured before it is drawn.
+    pub fn layout(&self, paragraph: &Paragraph) -> TextLayout {
+        layout::layout(&self.fonts, &self.fallbacks, paragraph)
+    }
+
+    /// Lays out and queues a paragraph with its top left corner at `position`.
+    pub fn draw_paragraph(&mut self, paragraph: &Paragraph, position: Vec2) -> TextLayout {
+        let layout = self.layout(paragraph);
+        self.draw_layout(&layout, position);
+        layout
+    }
+
+    pub fn draw_layout(&mut self, layout: &TextLayout, position: Vec2) {
+        self.draw_layout_rotated(layout, position, 0.0);
+    }
+
+    /// Queues a laid out paragraph turned by `rotation` radians around `position`.
+    pub fn draw_layout_rotated(&mut self, layout: &TextLayout, position: Vec2, rotation: f32) {
+        let right = Vec2::X.rotate(rotation);
+        let down = Vec2::Y.rotate(rotation);
+        for positioned in &layout.glyphs {
+            let Some(glyph) = self.glyph(positioned.font, positioned.id) else {
+                continue;
+            };
+            let offset = positioned.position + glyph.offset * positioned.size;
+            let size = glyph.size * positioned.size;
+            self.queued.push(GlyphInstance {
+                origin: (position + right * offset.x + down * offset.y).to_array(),
+                axis_x: (right * size.x).to_array(),
+                axis_y: (down * size.y).to_array(),
+                uv_min: glyph.uv_min,
+                uv_max: glyph.uv_max,
+                color: positioned.color,
+            });
+        }
+    }
+
+    /// Uploads new glyphs and everything queued since the last call.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view_proj: Mat4) {
+        queue.write_buffer(
+            &self.globals_buffer,
+            0,
+            bytemuck::bytes_of(&FrameGlobals::from_view_proj(view_proj)),
+        );
+
+        if let Some(mut rows) = self.atlas.dirty.take() {
+            if self.atlas_texture.height() != self.atlas.height {
+                rows = 0..self.atlas.height;
+                let atlas_texture = Self::create_atlas_texture(device, &self.atlas);
+                self.tracked
+                    .replace_texture(&self.atlas_texture, &atlas_texture);
+                self.atlas_texture = atlas_texture;
+                self.bind_group = Self::create_bind_group(
+                    device,
+                    &self.bind_group_layout,
+                    &self.globals_buffer,
+                    &self.atlas_texture,
+                    &self.sampler,
+                );
+            }
+            let start = (rows.start * self.atlas.width * 4) as usize;
+            let end = (rows.end * self.atlas.width * 4) as usize;
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.atlas_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: rows.start,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &self.atlas.pixels[start..end],
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.atlas.width * 4),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: self.atlas.width,
+                    height: rows.end - rows.start,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        if self.queued.len() > self.instance_capacity {
+            self.instance_capacity = self.queued.len().next_power_of_two();
+            let instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+            self.tracked
+                .replace_buffer(&self.instance_buffer, &instance_buffer);
+            self.instance_buffer = instance_buffer;
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.queued));
+        self.instance_count = self.queued.len() as u32;
+        self.queued.clear();
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.instance_count == 0 {
+            return;
+        }
+        render_pass.push_debug_group("Text");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.instance_count);
+        render_pass.pop_debug_group();
+    }
+}
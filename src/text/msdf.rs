@@ -0,0 +1,359 @@
+//! Multi-channel signed distance fields for glyph outlines.
+//!
+//! Curves are flattened to line segments, corners are found on the original curves and the
+//! edges between them are coloured so that every corner is shared by two edges with only one
+//! colour channel in common. Each channel then stores the distance to the nearest edge of its
+//! colour, and the median of the three reconstructs sharp corners in the shader.
+
+use ab_glyph::OutlineCurve;
+
+use crate::math::Vec2;
+
+const RED: u8 = 1;
+const GREEN: u8 = 2;
+const BLUE: u8 = 4;
+const CYAN: u8 = GREEN | BLUE;
+const MAGENTA: u8 = RED | BLUE;
+const YELLOW: u8 = RED | GREEN;
+const WHITE: u8 = RED | GREEN | BLUE;
+
+/// sin(3 rad), edges meeting at a sharper angle than this are corners.
+const CORNER_THRESHOLD: f32 = 0.141_12;
+
+#[derive(Copy, Clone, Debug)]
+struct Segment {
+    a: Vec2,
+    b: Vec2,
+    color: u8,
+    /// The segment starts at a corner of the original outline.
+    corner: bool,
+}
+
+/// A flattened, edge coloured outline ready for distance field generation.
+pub(crate) struct Shape {
+    segments: Vec<Segment>,
+    /// 1 when contours wind counter clockwise around filled areas (y up), -1 otherwise.
+    orientation: f32,
+    min: Vec2,
+    max: Vec2,
+}
+
+impl Shape {
+    /// `pixels_per_unit` picks how finely curves are flattened.
+    pub(crate) fn from_outline(curves: &[OutlineCurve], pixels_per_unit: f32) -> Option<Self> {
+        let mut contours: Vec<Vec<&OutlineCurve>> = Vec::new();
+        let mut last_end: Option<Vec2> = None;
+        for curve in curves {
+            let (start, end) = endpoints(curve);
+            if last_end.is_none_or(|e| e.distance(start) > 1e-3) {
+                contours.push(Vec::new());
+            }
+            if let Some(contour) = contours.last_mut() {
+                contour.push(curve);
+            }
+            last_end = Some(end);
+        }
+
+        let mut segments = Vec::new();
+        for contour in &contours {
+            let start = segments.len();
+            flatten_contour(contour, pixels_per_unit, &mut segments);
+            color_edges(&mut segments[start..]);
+        }
+        if segments.is_empty() {
+            return None;
+        }
+
+        let area: f32 = segments.iter().map(|s| s.a.cross(s.b)).sum();
+        let (min, max) = segments.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), s| (min.min(s.a).min(s.b), max.max(s.a).max(s.b)),
+        );
+        Some(Self {
+            segments,
+            orientation: if area > 0.0 { 1.0 } else { -1.0 },
+            min,
+            max,
+        })
+    }
+
+    /// Bounding box in outline units.
+    pub(crate) fn bounds(&self) -> (Vec2, Vec2) {
+        (self.min, self.max)
+    }
+
+    /// Renders an RGBA distance field, rows top to bottom. `origin` is the outline
+    /// position of the bitmap's top left corner, y grows up in outline space. Distances of
+    /// `range` pixels map to the ends of the 0..255 range, 128 is the edge.
+    pub(crate) fn generate(
+        &self,
+        width: u32,
+        height: u32,
+        origin: Vec2,
+        pixels_per_unit: f32,
+        range: f32,
+    ) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        let range = range / pixels_per_unit;
+        for y in 0..height {
+            for x in 0..width {
+                let p = Vec2::new(
+                    origin.x + (x as f32 + 0.5) / pixels_per_unit,
+                    origin.y - (y as f32 + 0.5) / pixels_per_unit,
+                );
+                let mut channels = self.distances(p);
+                let inside = self.winding(p) != 0;
+                let median = median(channels[0], channels[1], channels[2]);
+                if (median > 0.0) != inside {
+                    // the edge colouring can't represent this texel, fall back to a plain sdf
+                    let distance = self
+                        .segments
+                        .iter()
+                        .map(|s| closest(s, p).0)
+                        .fold(f32::INFINITY, f32::min);
+                    channels = [if inside { distance } else { -distance }; 3];
+                }
+                for d in channels {
+                    pixels.push(((d / range + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8);
+                }
+                pixels.push(255);
+            }
+        }
+        pixels
+    }
+
+    /// Signed pseudo-distance per channel to the nearest edge of that channel's colour,
+    /// positive inside.
+    fn distances(&self, p: Vec2) -> [f32; 3] {
+        let mut best = [(f32::INFINITY, 0.0, 0usize); 3];
+        for (i, segment) in self.segments.iter().enumerate() {
+            let (distance, orthogonality) = closest(segment, p);
+            for (channel, best) in best.iter_mut().enumerate() {
+                if segment.color & (1 << channel) == 0 {
+                    continue;
+                }
+                if distance < best.0 || (distance == best.0 && orthogonality > best.1) {
+                    *best = (distance, orthogonality, i);
+                }
+            }
+        }
+        best.map(|(distance, _, i)| {
+            if distance.is_infinite() {
+                return -distance;
+            }
+            let s = &self.segments[i];
+            let dir = s.b - s.a;
+            self.orientation * dir.cross(p - s.a) / dir.length()
+        })
+    }
+
+    /// Non-zero winding number of `p`.
+    fn winding(&self, p: Vec2) -> i32 {
+        let mut winding = 0;
+        for s in &self.segments {
+            if (s.a.y <= p.y) != (s.b.y <= p.y) {
+                let side = (s.b - s.a).cross(p - s.a);
+                if s.b.y > s.a.y && side > 0.0 {
+                    winding += 1;
+                } else if s.b.y <= s.a.y && side < 0.0 {
+                    winding -= 1;
+                }
+            }
+        }
+        winding
+    }
+}
+
+/// Unsigned distance from `p` to the segment and how perpendicular that distance is,
+/// used to pick between two segments sharing the nearest endpoint.
+fn closest(s: &Segment, p: Vec2) -> (f32, f32) {
+    let dir = s.b - s.a;
+    let t = (p - s.a).dot(dir) / dir.length_squared();
+    if t <= 0.0 {
+        let to = p - s.a;
+        (to.length(), dir.normalize().cross(to.normalize()).abs())
+    } else if t >= 1.0 {
+        let to = p - s.b;
+        (to.length(), dir.normalize().cross(to.normalize()).abs())
+    } else {
+        ((p - (s.a + dir * t)).length(), 1.0)
+    }
+}
+
+fn median(a: f32, b: f32, c: f32) -> f32 {
+    a.min(b).max(a.max(b).min(c))
+}
+
+fn to_vec(p: ab_glyph::Point) -> Vec2 {
+    Vec2::new(p.x, p.y)
+}
+
+fn endpoints(curve: &OutlineCurve) -> (Vec2, Vec2) {
+    match *curve {
+        OutlineCurve::Line(a, b) => (to_vec(a), to_vec(b)),
+        OutlineCurve::Quad(a, _, b) => (to_vec(a), to_vec(b)),
+        OutlineCurve::Cubic(a, _, _, b) => (to_vec(a), to_vec(b)),
+    }
+}
+
+/// Directions leaving the start and arriving at the end of a curve.
+fn tangents(curve: &OutlineCurve) -> (Vec2, Vec2) {
+    let points: Vec<Vec2> = match *curve {
+        OutlineCurve::Line(a, b) => vec![to_vec(a), to_vec(b)],
+        OutlineCurve::Quad(a, b, c) => vec![to_vec(a), to_vec(b), to_vec(c)],
+        OutlineCurve::Cubic(a, b, c, d) => vec![to_vec(a), to_vec(b), to_vec(c), to_vec(d)],
+    };
+    let first = points[0];
+    let last = points[points.len() - 1];
+    let start = points[1..]
+        .iter()
+        .map(|p| *p - first)
+        .find(|d| d.length_squared() > 0.0)
+        .unwrap_or(Vec2::ZERO);
+    let end = points[..points.len() - 1]
+        .iter()
+        .rev()
+        .map(|p| last - *p)
+        .find(|d| d.length_squared() > 0.0)
+        .unwrap_or(Vec2::ZERO);
+    (start.normalize(), end.normalize())
+}
+
+fn is_corner(incoming: Vec2, outgoing: Vec2) -> bool {
+    incoming.dot(outgoing) <= 0.0 || incoming.cross(outgoing).abs() > CORNER_THRESHOLD
+}
+
+fn flatten_contour(contour: &[&OutlineCurve], pixels_per_unit: f32, out: &mut Vec<Segment>) {
+    let Some(last) = contour.last() else {
+        return;
+    };
+    let mut incoming = tangents(last).1;
+    let first = out.len();
+    for curve in contour {
+        let (start_tangent, end_tangent) = tangents(curve);
+        let mut corner = is_corner(incoming, start_tangent);
+        incoming = end_tangent;
+
+        let points = match **curve {
+            OutlineCurve::Line(a, b) => vec![to_vec(a), to_vec(b)],
+            OutlineCurve::Quad(a, b, c) => {
+                let (a, b, c) = (to_vec(a), to_vec(b), to_vec(c));
+                let n = subdivisions(&[a, b, c], pixels_per_unit);
+                (0..=n)
+                    .map(|i| {
+                        let t = i as f32 / n as f32;
+                        let u = 1.0 - t;
+                        a * (u * u) + b * (2.0 * u * t) + c * (t * t)
+                    })
+                    .collect()
+            }
+            OutlineCurve::Cubic(a, b, c, d) => {
+                let (a, b, c, d) = (to_vec(a), to_vec(b), to_vec(c), to_vec(d));
+                let n = subdivisions(&[a, b, c, d], pixels_per_unit);
+                (0..=n)
+                    .map(|i| {
+                        let t = i as f32 / n as f32;
+                        let u = 1.0 - t;
+                        a * (u * u * u)
+                            + b * (3.0 * u * u * t)
+                            + c * (3.0 * u * t * t)
+                            + d * (t * t * t)
+                    })
+                    .collect()
+            }
+        };
+        for pair in points.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if a.distance(b) <= f32::EPSILON {
+                continue;
+            }
+            // joints inside a curve share the exact same point so distance ties resolve
+            let a = out.last().filter(|_| out.len() > first).map_or(a, |s| s.b);
+            out.push(Segment {
+                a,
+                b,
+                color: WHITE,
+                corner,
+            });
+            corner = false;
+        }
+    }
+
+    // close the contour if the font left a gap
+    if let (Some(start), Some(end)) = (out.get(first).map(|s| s.a), out.last().map(|s| s.b)) {
+        if out.len() > first && start.distance(end) > f32::EPSILON {
+            out.push(Segment {
+                a: end,
+                b: start,
+                color: WHITE,
+                corner: true,
+            });
+            out[first].corner = true;
+        }
+    }
+}
+
+/// Line segments for a curve, about one per 4 pixels of control polygon length.
+fn subdivisions(points: &[Vec2], pixels_per_unit: f32) -> usize {
+    let length: f32 = points.windows(2).map(|p| p[0].distance(p[1])).sum();
+    ((length * pixels_per_unit / 4.0).ceil() as usize).clamp(2, 16)
+}
+
+fn color_edges(segments: &mut [Segment]) {
+    let corners: Vec<usize> = (0..segments.len())
+        .filter(|&i| segments[i].corner)
+        .collect();
+    let n = segments.len();
+    match corners.len() {
+        0 => {}
+        1 => {
+            // a teardrop, split the single smooth edge in three
+            if n < 3 {
+                return;
+            }
+            let start = corners[0];
+            for i in 0..n {
+                segments[(start + i) % n].color = if i * 3 < n {
+                    MAGENTA
+                } else if i * 3 < n * 2 {
+                    WHITE
+                } else {
+                    YELLOW
+                };
+            }
+        }
+        _ => {
+            let start = corners[0];
+            let mut color = CYAN;
+            let mut splines = vec![(0, color)];
+            for i in 0..n {
+                let segment = &mut segments[(start + i) % n];
+                if segment.corner && i > 0 {
+                    color = next_color(color);
+                    splines.push((i, color));
+                }
+                segment.color = color;
+            }
+            // the last edge meets the first at a corner, so they must differ as well
+            let (last_start, last_color) = splines[splines.len() - 1];
+            if last_color == CYAN {
+                let previous = splines[splines.len() - 2].1;
+                let replacement = [MAGENTA, YELLOW]
+                    .into_iter()
+                    .find(|c| *c != previous)
+                    .unwrap_or(MAGENTA);
+                for i in last_start..n {
+                    segments[(start + i) % n].color = replacement;
+                }
+            }
+        }
+    }
+}
+
+fn next_color(color: u8) -> u8 {
+    match color {
+        CYAN => MAGENTA,
+        MAGENTA => YELLOW,
+        _ => CYAN,
+    }
+}
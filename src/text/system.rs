@@ -0,0 +1,72 @@
+//! Fonts installed on the system, found with fontdb.
+
+use rustybuzz::ttf_parser;
+
+use super::Font;
+
+pub struct SystemFonts {
+    db: fontdb::Database,
+}
+
+impl SystemFonts {
+    /// Scans the system font directories, which can take a moment.
+    pub fn load() -> Self {
+        let mut db = fontdb::Database::new();
+        db.load_system_fonts();
+        Self { db }
+    }
+
+    /// Regular face of a family. "serif", "sans-serif" and "monospace" pick the system's
+    /// default for that generic family.
+    pub fn query(&self, family: &str) -> Option<Font> {
+        let family = match family {
+            "serif" => fontdb::Family::Serif,
+            "sans-serif" => fontdb::Family::SansSerif,
+            "monospace" => fontdb::Family::Monospace,
+            name => fontdb::Family::Name(name),
+        };
+        let id = self.db.query(&fontdb::Query {
+            families: &[family],
+            ..Default::default()
+        })?;
+        self.load_face(id)
+    }
+
+    /// First installed font with an outline for `c`, handy for picking fallbacks.
+    /// Reads every font until one matches, so cache the result.
+    pub fn find_for_char(&self, c: char) -> Option<Font> {
+        self.db
+            .faces()
+            .find(|face| {
+                self.db
+                    .with_face_data(face.id, |data, index| {
+                        let face = ttf_parser::Face::parse(data, index).ok()?;
+                        let id = face.glyph_index(c)?;
+                        // bitmap only glyphs (color emoji) can't become distance fields
+                        face.outline_glyph(id, &mut NoOutline).map(|_| ())
+                    })
+                    .flatten()
+                    .is_some()
+            })
+            .and_then(|face| self.load_face(face.id))
+    }
+
+    fn load_face(&self, id: fontdb::ID) -> Option<Font> {
+        self.db
+            .with_face_data(id, |data, index| {
+                Font::from_collection(data.to_vec(), index)
+            })?
+            .map_err(|e| log::warn!("{}", e))
+            .ok()
+    }
+}
+
+struct NoOutline;
+
+impl ttf_parser::OutlineBuilder for NoOutline {
+    fn move_to(&mut self, _x: f32, _y: f32) {}
+    fn line_to(&mut self, _x: f32, _y: f32) {}
+    fn quad_to(&mut self, _x1: f32, _y1: f32, _x: f32, _y: f32) {}
+    fn curve_to(&mut self, _x1: f32, _y1: f32, _x2: f32, _y2: f32, _x: f32, _y: f32) {}
+    fn close(&mut self) {}
+}
@@ -0,0 +1,480 @@
+//! Sampled 2D textures loaded from memory or image files, or written by compute passes.
+
+use std::path::Path;
+
+use crate::mipmap::MipmapGenerator;
+use crate::readback::{PendingReadback, ReadbackError};
+use crate::stats::Tracked;
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    #[cfg(feature = "ktx2")]
+    Ktx2(String),
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "failed to read image: {}", e),
+            TextureError::Image(e) => write!(f, "failed to decode image: {}", e),
+            #[cfg(feature = "ktx2")]
+            TextureError::Ktx2(e) => write!(f, "failed to read KTX2 texture: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+/// How a texture is sampled, see `Texture::with_sampler`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SamplerOptions {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    /// Blending between mip levels, only matters for textures with mipmaps.
+    pub mipmap_filter: wgpu::FilterMode,
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    /// Samples taken at steep angles, 1 turns anisotropic filtering off. Clamped to the
+    /// 16 every device supports, and only used when all three filters are `Linear`.
+    pub anisotropy: u16,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            anisotropy: 1,
+        }
+    }
+}
+
+impl SamplerOptions {
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+            .iter()
+            .all(|&filter| filter == wgpu::FilterMode::Linear);
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Texture Sampler"),
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: if linear {
+                self.anisotropy.clamp(1, 16)
+            } else {
+                1
+            },
+            ..Default::default()
+        })
+    }
+}
+
+/// A color texture with its view and sampler, sRGB unless built with another format.
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    sampler_options: SamplerOptions,
+    _tracked: Tracked,
+}
+
+/// Creates textures with mipmaps or sampler options, from `Texture::builder`.
+pub struct TextureBuilder<'a> {
+    label: &'a str,
+    mipmaps: bool,
+    generator: Option<&'a mut MipmapGenerator>,
+    sampler: SamplerOptions,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+}
+
+impl<'a> TextureBuilder<'a> {
+    /// Fills in a full mip chain after uploading, for textures drawn smaller than their
+    /// size. KTX2 textures keep the levels stored in the file.
+    pub fn with_mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    /// Reuses a generator for the mipmaps instead of creating one per texture, worth it
+    /// when loading many.
+    pub fn with_generator(mut self, generator: &'a mut MipmapGenerator) -> Self {
+        self.mipmaps = true;
+        self.generator = Some(generator);
+        self
+    }
+
+    /// `Rgba8UnormSrgb` by default. Images are uploaded as they are, so anything given to
+    /// `build_rgba8` or `build_bytes` has to be `Rgba8Unorm` or `Rgba8UnormSrgb`, the
+    /// latter can't be used as a storage texture.
+    pub fn with_format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Usages on top of `TEXTURE_BINDING` and `COPY_DST`, e.g. `STORAGE_BINDING` for a
+    /// texture compute passes write into.
+    pub fn with_usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerOptions) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Sets both the magnification and minification filter.
+    pub fn with_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.sampler.mag_filter = filter;
+        self.sampler.min_filter = filter;
+        self
+    }
+
+    pub fn with_mipmap_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.sampler.mipmap_filter = filter;
+        self
+    }
+
+    /// Sets the address mode in both directions, `Repeat` tiles the texture.
+    pub fn with_address_mode(mut self, mode: wgpu::AddressMode) -> Self {
+        self.sampler.address_mode_u = mode;
+        self.sampler.address_mode_v = mode;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: u16) -> Self {
+        self.sampler.anisotropy = anisotropy;
+        self
+    }
+
+    /// A texture with undefined contents, e.g. the output of a compute pass, see
+    /// `Texture::storage_layout_entry`.
+    pub fn build_empty(self, device: &wgpu::Device, width: u32, height: u32) -> Texture {
+        let texture = self.create_texture(device, width, height, 1);
+        Texture::from_parts(device, texture, self.sampler, self.label)
+    }
+
+    /// Uploads tightly packed RGBA8 pixels, `width * height * 4` bytes.
+    pub fn build_rgba8(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Texture {
+        let mip_level_count = if self.mipmaps {
+            crate::mipmap::mip_level_count(width, height)
+        } else {
+            1
+        };
+        let texture = self.create_texture(device, width, height, mip_level_count);
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: Some(height),
+            },
+            texture.size(),
+        );
+        if mip_level_count > 1 {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Mipmap Encoder"),
+            });
+            match self.generator {
+                Some(generator) => generator.generate(device, &mut encoder, &texture),
+                None => MipmapGenerator::new(device).generate(device, &mut encoder, &texture),
+            }
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+        Texture::from_parts(device, texture, self.sampler, self.label)
+    }
+
+    fn create_texture(
+        &self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        mip_level_count: u32,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(self.label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | self.usage,
+            view_formats: &[],
+        })
+    }
+
+    /// Decodes a PNG or JPEG image, or with the `ktx2` feature reads a KTX2 texture.
+    pub fn build_bytes(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+    ) -> Result<Texture, TextureError> {
+        #[cfg(feature = "ktx2")]
+        if crate::ktx::is_ktx2(bytes) {
+            let image = crate::ktx::KtxImage::parse(bytes)?;
+            return self.build_ktx_image(device, queue, image);
+        }
+        let image = image::load_from_memory(bytes)
+            .map_err(TextureError::Image)?
+            .into_rgba8();
+        Ok(self.build_rgba8(device, queue, image.width(), image.height(), &image))
+    }
+
+    /// Uploads the mip levels of a KTX2 texture, block compressed if the device supports
+    /// the format, see `ktx` for what it does when it doesn't.
+    #[cfg(feature = "ktx2")]
+    pub(crate) fn build_ktx_image(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: crate::ktx::KtxImage,
+    ) -> Result<Texture, TextureError> {
+        use wgpu::util::DeviceExt;
+
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let mip_level_count = image.mip_level_count();
+        let (format, data) = image.into_data(device.features())?;
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(self.label),
+                size,
+                mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | self.usage,
+                view_formats: &[],
+            },
+            &data,
+        );
+        Ok(Texture::from_parts(
+            device,
+            texture,
+            self.sampler,
+            self.label,
+        ))
+    }
+
+    /// Reads and decodes an image file, labelled with its path unless a label was given.
+    pub fn load(
+        self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> Result<Texture, TextureError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(TextureError::Io)?;
+        let label = path.display().to_string();
+        let TextureBuilder {
+            label: given,
+            mipmaps,
+            generator,
+            sampler,
+            format,
+            usage,
+        } = self;
+        TextureBuilder {
+            label: if given.is_empty() { &label } else { given },
+            mipmaps,
+            generator,
+            sampler,
+            format,
+            usage,
+        }
+        .build_bytes(device, queue, &bytes)
+    }
+}
+
+impl Texture {
+    /// Starts a texture with mipmaps or sampler options other than the defaults.
+    pub fn builder(label: &str) -> TextureBuilder<'_> {
+        TextureBuilder {
+            label,
+            mipmaps: false,
+            generator: None,
+            sampler: SamplerOptions::default(),
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::empty(),
+        }
+    }
+
+    fn from_parts(
+        device: &wgpu::Device,
+        texture: wgpu::Texture,
+        sampler_options: SamplerOptions,
+        label: &str,
+    ) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(label),
+            ..Default::default()
+        });
+        let sampler = sampler_options.create_sampler(device);
+        let tracked = Tracked::new(0, 0, 1).with_texture(&texture);
+        Self {
+            texture,
+            view,
+            sampler,
+            sampler_options,
+            _tracked: tracked,
+        }
+    }
+
+    /// Uploads tightly packed RGBA8 pixels, `width * height * 4` bytes.
+    pub fn from_rgba8(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        label: &str,
+    ) -> Self {
+        Self::builder(label).build_rgba8(device, queue, width, height, pixels)
+    }
+
+    /// Decodes a PNG or JPEG image, or with the `ktx2` feature reads a KTX2 texture.
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+    ) -> Result<Self, TextureError> {
+        Self::builder(label).build_bytes(device, queue, bytes)
+    }
+
+    /// Uploads the mip levels of a KTX2 texture, block compressed if the device supports
+    /// the format, see `ktx` for what it does when it doesn't.
+    #[cfg(feature = "ktx2")]
+    pub fn from_ktx2(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+    ) -> Result<Self, TextureError> {
+        let image = crate::ktx::KtxImage::parse(bytes)?;
+        Self::builder(label).build_ktx_image(device, queue, image)
+    }
+
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, TextureError> {
+        Self::builder("").load(device, queue, path)
+    }
+
+    /// Swaps the sampler filter, `Nearest` keeps pixel art crisp.
+    pub fn with_filter(self, device: &wgpu::Device, filter: wgpu::FilterMode) -> Self {
+        let options = SamplerOptions {
+            mag_filter: filter,
+            min_filter: filter,
+            ..self.sampler_options
+        };
+        self.with_sampler(device, options)
+    }
+
+    /// Replaces the sampler, keeping the texture.
+    pub fn with_sampler(mut self, device: &wgpu::Device, options: SamplerOptions) -> Self {
+        self.sampler = options.create_sampler(device);
+        self.sampler_options = options;
+        self
+    }
+
+    pub fn sampler_options(&self) -> SamplerOptions {
+        self.sampler_options
+    }
+
+    /// The layout entry for binding this texture as a storage texture in a compute shader,
+    /// `texture_storage_2d<format, access>` in WGSL. Needs `STORAGE_BINDING` usage and a
+    /// single mip level; `ReadWrite` only works for formats the adapter allows, see
+    /// `Context::supports_read_write_storage`.
+    pub fn storage_layout_entry(
+        &self,
+        binding: u32,
+        access: wgpu::StorageTextureAccess,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access,
+                format: self.format(),
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        }
+    }
+
+    /// Reads the texture back, blocking until it's copied. It needs `COPY_SRC` usage, see
+    /// `TextureBuilder::with_usage`, and a format `readback` can convert.
+    pub fn read_to_cpu(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<image::RgbaImage, ReadbackError> {
+        crate::readback::read_texture(device, queue, &self.texture)
+    }
+
+    /// Like `read_to_cpu` without blocking, poll the readback until it's done.
+    pub fn start_read_to_cpu(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<PendingReadback, ReadbackError> {
+        PendingReadback::start(device, queue, &self.texture)
+    }
+
+    /// Workgroups to dispatch to cover every pixel with `workgroup_size` square groups.
+    pub fn workgroups(&self, workgroup_size: u32) -> (u32, u32) {
+        (
+            self.width().div_ceil(workgroup_size),
+            self.height().div_ceil(workgroup_size),
+        )
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.texture.format()
+    }
+
+    pub fn width(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.texture.height()
+    }
+}
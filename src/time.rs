@@ -0,0 +1,144 @@
+//! Game time: how long the last frame took and how much has passed, scaled and pausable.
+//!
+//! The run loops tick `Context::time` with the measured frame time before anything else
+//! happens in a frame, and everything they drive from there, `App::update`,
+//! `App::fixed_update`, tweens and the shader time in `globals`, gets the scaled `delta`.
+//! Setting `time_scale` to 0.5 runs the game at half speed, pausing stops it while frames
+//! keep being drawn, for pause menus. The real time is still there for what has to keep
+//! moving regardless, such as the menu itself.
+//!
+//! Fixed updates run at `fixed_timestep` intervals of scaled time, as many per frame as
+//! have built up, for physics and anything else that has to step evenly.
+
+use std::time::Duration;
+
+/// The clock frames are measured with. std's panics in browsers, so on the web it's
+/// `performance.now()` and `Date.now()` through `web-time`.
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+pub use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Fixed updates a single frame runs at most, so a long stall doesn't snowball into
+/// ever longer frames catching up.
+const MAX_FIXED_STEPS: u32 = 8;
+
+#[derive(Clone, Debug)]
+pub struct Time {
+    /// Multiplies the real frame time, 1 for real time. Negative values count as 0.
+    pub time_scale: f32,
+    paused: bool,
+    delta: f32,
+    elapsed: f64,
+    real_delta: f32,
+    real_elapsed: f64,
+    frame: u64,
+    fixed_timestep: f32,
+    /// Scaled time not yet used up by fixed updates.
+    accumulator: f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Self {
+            time_scale: 1.0,
+            paused: false,
+            delta: 0.0,
+            elapsed: 0.0,
+            real_delta: 0.0,
+            real_elapsed: 0.0,
+            frame: 0,
+            fixed_timestep: 1.0 / 60.0,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Starts a frame that took `real_delta` seconds. The run loops call this, custom loops
+    /// should too.
+    pub fn tick(&mut self, real_delta: f32) {
+        self.real_delta = real_delta.max(0.0);
+        self.real_elapsed += self.real_delta as f64;
+        self.delta = if self.paused {
+            0.0
+        } else {
+            self.real_delta * self.time_scale.max(0.0)
+        };
+        self.elapsed += self.delta as f64;
+        self.accumulator += self.delta;
+        self.frame += 1;
+    }
+
+    /// Scaled seconds since the last frame, 0 while paused.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Scaled seconds since the start, not moving while paused.
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Seconds the last frame really took, whatever the scale.
+    pub fn real_delta(&self) -> f32 {
+        self.real_delta
+    }
+
+    pub fn real_elapsed(&self) -> f64 {
+        self.real_elapsed
+    }
+
+    /// Frames ticked so far, paused ones included. The first frame is 1.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Scaled seconds between fixed updates, 1/60 by default.
+    pub fn fixed_timestep(&self) -> f32 {
+        self.fixed_timestep
+    }
+
+    pub fn set_fixed_timestep(&mut self, timestep: Duration) {
+        self.fixed_timestep = timestep.as_secs_f32().max(f32::EPSILON);
+    }
+
+    /// How many fixed updates this frame runs, using up the time they cover. Called once
+    /// per frame by the run loops.
+    pub fn take_fixed_steps(&mut self) -> u32 {
+        let steps = (self.accumulator / self.fixed_timestep) as u32;
+        if steps > MAX_FIXED_STEPS {
+            log::debug!("dropping {} fixed updates", steps - MAX_FIXED_STEPS);
+            self.accumulator = 0.0;
+            return MAX_FIXED_STEPS;
+        }
+        self.accumulator -= steps as f32 * self.fixed_timestep;
+        steps
+    }
+
+    /// How far the time is between the last fixed update and the next, from 0 to 1, for
+    /// interpolating what fixed updates move when drawing it.
+    pub fn fixed_alpha(&self) -> f32 {
+        (self.accumulator / self.fixed_timestep).clamp(0.0, 1.0)
+    }
+}
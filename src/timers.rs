@@ -0,0 +1,307 @@
+//! Callbacks run after a delay or at an interval, for gameplay scripting and UI timing
+//! without keeping timestamps around.
+//!
+//! `Context::timers` holds them, and the run loops advance them by the frame's scaled
+//! delta before `App::fixed_update`, so they stop while `Context::time` is paused unless
+//! made `with_real_time`. Callbacks get the `Context`, and can schedule more timers from
+//! there to chain steps one after another. The `TimerHandle` a timer is added with cancels
+//! it from anywhere, callbacks included, and so does `Timers::clear` for all of them.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::context::Context;
+use crate::tween::Repeat;
+
+type Callback<C> = Box<dyn FnMut(&mut C)>;
+
+/// Cancels a timer and tells whether it's still going. Cloned handles refer to the same
+/// timer.
+#[derive(Clone, Debug)]
+pub struct TimerHandle(Rc<Cell<bool>>);
+
+impl TimerHandle {
+    /// Stops the timer before it runs again. Does nothing once it's done.
+    pub fn cancel(&self) {
+        self.0.set(false);
+    }
+
+    /// Whether the timer will still run, `false` once cancelled or done.
+    pub fn is_active(&self) -> bool {
+        self.0.get()
+    }
+}
+
+/// A callback and when to run it, see `Timers::add`. `C` is what the callback gets, the
+/// `Context` but in the module's tests.
+pub struct Timer<C = Context> {
+    interval: f32,
+    /// Seconds until the callback runs next.
+    remaining: f32,
+    repeat: Repeat,
+    real_time: bool,
+    /// Times the callback ran.
+    runs: u32,
+    active: Rc<Cell<bool>>,
+    callback: Callback<C>,
+}
+
+impl Timer {
+    /// Runs `callback` once, `seconds` from now.
+    pub fn after(seconds: f32, callback: impl FnMut(&mut Context) + 'static) -> Self {
+        Self::once(seconds, callback)
+    }
+
+    /// Runs `callback` every `seconds` from now on, or every frame for 0.
+    pub fn every(seconds: f32, callback: impl FnMut(&mut Context) + 'static) -> Self {
+        Self::once(seconds, callback).with_repeat(Repeat::Forever)
+    }
+}
+
+impl<C> Timer<C> {
+    fn once(seconds: f32, callback: impl FnMut(&mut C) + 'static) -> Self {
+        let interval = seconds.max(0.0);
+        Self {
+            interval,
+            remaining: interval,
+            repeat: Repeat::Once,
+            real_time: false,
+            runs: 0,
+            active: Rc::new(Cell::new(true)),
+            callback: Box::new(callback),
+        }
+    }
+
+    pub fn with_repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Counts real time instead of scaled time, so the timer keeps going while the game is
+    /// paused or slowed down, e.g. for menus.
+    pub fn with_real_time(mut self) -> Self {
+        self.real_time = true;
+        self
+    }
+
+    pub fn handle(&self) -> TimerHandle {
+        TimerHandle(self.active.clone())
+    }
+
+    fn is_done(&self) -> bool {
+        match self.repeat {
+            Repeat::Once => self.runs >= 1,
+            Repeat::Times(times) => self.runs >= times,
+            Repeat::Forever => false,
+        }
+    }
+
+    /// Moves the timer on by `dt` and runs the callback as often as it came due.
+    fn advance(&mut self, ctx: &mut C, dt: f32)
+    where
+        C: TimerHost,
+    {
+        self.remaining -= dt;
+        while self.active.get() && self.remaining <= 0.0 {
+            (self.callback)(ctx);
+            self.runs += 1;
+            if self.is_done() || ctx.timers().cleared {
+                self.active.set(false);
+            }
+            if self.interval <= 0.0 {
+                // every frame, not every iteration
+                self.remaining = 0.0;
+                break;
+            }
+            self.remaining += self.interval;
+        }
+    }
+}
+
+/// The timers of a `Context`, see the module docs.
+pub struct Timers<C = Context> {
+    timers: Vec<Timer<C>>,
+    /// Set by `clear` while `update` has the timers out, so it cancels them too.
+    cleared: bool,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<C> Default for Timers<C> {
+    fn default() -> Self {
+        Self {
+            timers: Vec::new(),
+            cleared: false,
+        }
+    }
+}
+
+impl<C> Timers<C> {
+    pub fn add(&mut self, timer: Timer<C>) -> TimerHandle {
+        let handle = timer.handle();
+        self.timers.push(timer);
+        handle
+    }
+
+    /// Runs `callback` once, `seconds` of game time from now.
+    pub fn after(&mut self, seconds: f32, callback: impl FnMut(&mut C) + 'static) -> TimerHandle {
+        self.add(Timer::once(seconds, callback))
+    }
+
+    /// Runs `callback` every `seconds` of game time until cancelled.
+    pub fn every(&mut self, seconds: f32, callback: impl FnMut(&mut C) + 'static) -> TimerHandle {
+        self.add(Timer::once(seconds, callback).with_repeat(Repeat::Forever))
+    }
+
+    /// Timers still going.
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Cancels every timer, from a timer's callback too: the ones that haven't run yet this
+    /// frame don't, and timers added after it are kept.
+    pub fn clear(&mut self) {
+        for timer in self.timers.drain(..) {
+            timer.active.set(false);
+        }
+        self.cleared = true;
+    }
+}
+
+/// Advances `ctx.timers` by the frame's delta in `ctx.time` and runs what came due, in the
+/// order the timers were added. The run loops call this, custom loops should too.
+pub fn update(ctx: &mut Context) {
+    let (delta, real_delta) = (ctx.time.delta(), ctx.time.real_delta());
+    run(ctx, delta, real_delta);
+}
+
+/// What timer callbacks get and where their `Timers` are, so callbacks can add and clear
+/// them.
+trait TimerHost: Sized {
+    fn timers(&mut self) -> &mut Timers<Self>;
+}
+
+impl TimerHost for Context {
+    fn timers(&mut self) -> &mut Timers<Self> {
+        &mut self.timers
+    }
+}
+
+fn run<C: TimerHost>(host: &mut C, delta: f32, real_delta: f32) {
+    // taken out so the callbacks can have the host, `clear` marks them cancelled meanwhile
+    let mut timers = std::mem::take(&mut host.timers().timers);
+    host.timers().cleared = false;
+    for timer in &mut timers {
+        if host.timers().cleared {
+            timer.active.set(false);
+            continue;
+        }
+        let dt = if timer.real_time { real_delta } else { delta };
+        timer.advance(host, dt);
+    }
+    timers.retain(|timer| timer.active.get());
+    // added by the callbacks, they start counting next frame
+    timers.append(&mut host.timers().timers);
+    host.timers().timers = timers;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Host {
+        timers: Timers<Host>,
+        log: Vec<&'static str>,
+    }
+
+    impl TimerHost for Host {
+        fn timers(&mut self) -> &mut Timers<Self> {
+            &mut self.timers
+        }
+    }
+
+    fn count(host: &Host, entry: &str) -> usize {
+        host.log.iter().filter(|logged| **logged == entry).count()
+    }
+
+    #[test]
+    fn runs_when_due() {
+        let mut host = Host::default();
+        let once = host.timers.after(1.0, |host| host.log.push("once"));
+        host.timers.add(
+            Timer::once(0.25, |host: &mut Host| host.log.push("tick"))
+                .with_repeat(Repeat::Times(6)),
+        );
+        run(&mut host, 0.5, 0.5);
+        assert_eq!((count(&host, "once"), count(&host, "tick")), (0, 2));
+        // a long frame runs a repeating timer as often as it came due
+        run(&mut host, 0.75, 0.75);
+        assert_eq!((count(&host, "once"), count(&host, "tick")), (1, 5));
+        assert!(!once.is_active());
+        run(&mut host, 1.0, 1.0);
+        assert_eq!(count(&host, "tick"), 6);
+        assert!(host.timers.is_empty());
+    }
+
+    #[test]
+    fn real_time_keeps_going_while_paused() {
+        let mut host = Host::default();
+        host.timers.after(0.5, |host| host.log.push("game"));
+        host.timers
+            .add(Timer::once(0.5, |host: &mut Host| host.log.push("real")).with_real_time());
+        run(&mut host, 0.0, 1.0);
+        assert_eq!(host.log, ["real"]);
+    }
+
+    #[test]
+    fn every_frame() {
+        let mut host = Host::default();
+        host.timers.every(0.0, |host| host.log.push("frame"));
+        run(&mut host, 1.0, 1.0);
+        run(&mut host, 0.0, 0.0);
+        assert_eq!(count(&host, "frame"), 2);
+    }
+
+    #[test]
+    fn cancel_from_a_callback() {
+        let mut host = Host::default();
+        let victim = host.timers.every(0.1, |host| host.log.push("victim"));
+        let cancelled = victim.clone();
+        host.timers.after(0.0, move |_| cancelled.cancel());
+        run(&mut host, 0.1, 0.1);
+        run(&mut host, 0.1, 0.1);
+        assert_eq!(count(&host, "victim"), 1);
+        assert!(!victim.is_active());
+        assert!(host.timers.is_empty());
+    }
+
+    #[test]
+    fn clear_from_a_callback() {
+        let mut host = Host::default();
+        let first = host.timers.every(0.1, |host| host.timers.clear());
+        let later = host.timers.every(0.1, |host| host.log.push("later"));
+        run(&mut host, 0.1, 0.1);
+        run(&mut host, 0.1, 0.1);
+        assert!(host.log.is_empty());
+        assert!(!first.is_active() && !later.is_active());
+        assert!(host.timers.is_empty());
+
+        // timers added after the clear are kept
+        let clearing = host.timers.after(0.0, |host| {
+            host.timers.clear();
+            host.timers.after(1.0, |_| {});
+        });
+        run(&mut host, 0.1, 0.1);
+        assert!(!clearing.is_active());
+        assert_eq!(host.timers.len(), 1);
+    }
+}
@@ -0,0 +1,467 @@
+//! Tonemapping HDR frames to the display range, with exposure set by hand or adapting to
+//! the scene's brightness like an eye.
+//!
+//! Auto exposure builds a histogram of the frame's log luminance in a compute pass, averages
+//! it in a second one and moves the exposure towards that average a little every frame,
+//! all without reading anything back. The `Tonemapper` then scales the frame by the
+//! exposure and compresses it with its `ToneCurve`. Run it on a `PostChain` with a float
+//! format, before effects that expect display colors such as `ColorGrading`.
+
+use crate::bind_group_cache::BindGroupCache;
+use crate::post::{PostEffect, PostFrame, PostPipeline, PostTarget};
+use crate::stats::Tracked;
+
+/// Bins of the luminance histogram. Bin 0 counts black pixels, which are left out of the
+/// average.
+const BINS: u32 = 64;
+const WORKGROUP_SIZE: u32 = 16;
+
+const HISTOGRAM_SHADER: &str = r#"
+struct Params {
+    min_ev: f32,
+    ev_range: f32,
+    speed_up: f32,
+    speed_down: f32,
+    dt: f32,
+    pixel_count: u32,
+};
+
+// x the adapted average log2 luminance, y 1 once it has been set
+struct State {
+    average: f32,
+    initialized: f32,
+};
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params: Params;
+@group(0) @binding(2) var<storage, read_write> histogram: array<atomic<u32>, 64>;
+@group(0) @binding(3) var<storage, read_write> state: State;
+
+var<workgroup> local_bins: array<atomic<u32>, 64>;
+var<workgroup> weighted: array<u32, 64>;
+
+@compute @workgroup_size(16, 16)
+fn cs_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    if index < 64u {
+        atomicStore(&local_bins[index], 0u);
+    }
+    workgroupBarrier();
+    let size = textureDimensions(source);
+    if all(id.xy < size) {
+        let color = textureLoad(source, vec2<i32>(id.xy), 0).rgb;
+        let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+        var bin = 0u;
+        if luminance > 1e-6 {
+            let t = clamp((log2(luminance) - params.min_ev) / params.ev_range, 0.0, 1.0);
+            bin = u32(t * 62.0 + 1.0);
+        }
+        atomicAdd(&local_bins[bin], 1u);
+    }
+    workgroupBarrier();
+    if index < 64u {
+        atomicAdd(&histogram[index], atomicLoad(&local_bins[index]));
+    }
+}
+
+@compute @workgroup_size(64)
+fn cs_average(@builtin(local_invocation_index) index: u32) {
+    let count = atomicLoad(&histogram[index]);
+    weighted[index] = count * index;
+    atomicStore(&histogram[index], 0u);
+    workgroupBarrier();
+    for (var stride = 32u; stride > 0u; stride = stride >> 1u) {
+        if index < stride {
+            weighted[index] = weighted[index] + weighted[index + stride];
+        }
+        workgroupBarrier();
+    }
+    if index == 0u {
+        // `count` is the black pixels here
+        let lit = f32(params.pixel_count - count);
+        var target_ev = params.min_ev;
+        if lit > 0.0 {
+            let bin = f32(weighted[0]) / lit - 1.0;
+            target_ev = params.min_ev + bin / 62.0 * params.ev_range;
+        }
+        if state.initialized == 0.0 {
+            state.average = target_ev;
+            state.initialized = 1.0;
+        } else {
+            let speed = select(params.speed_down, params.speed_up, target_ev > state.average);
+            state.average = state.average + (target_ev - state.average) * (1.0 - exp(-params.dt * speed));
+        }
+    }
+}
+"#;
+
+const TONEMAP_SHADER: &str = r#"
+struct Params {
+    // 2 to the power of the manual exposure or the compensation
+    exposure: f32,
+    auto_exposure: u32,
+    curve: u32,
+};
+
+struct State {
+    average: f32,
+    initialized: f32,
+};
+
+@group(1) @binding(0) var<storage, read> state: State;
+
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    // Narkowicz's fit of the ACES filmic curve
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    var exposure = params.exposure;
+    if params.auto_exposure != 0u {
+        // the average brightness ends up middle grey
+        exposure = exposure * 0.18 / exp2(state.average);
+    }
+    let c = max(color.rgb * exposure, vec3<f32>(0.0));
+    var mapped = clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+    if params.curve == 1u {
+        mapped = c / (1.0 + c);
+    } else if params.curve == 2u {
+        mapped = aces(c);
+    }
+    return vec4<f32>(mapped, color.a);
+}
+"#;
+
+/// How HDR values are squeezed into 0..1.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ToneCurve {
+    /// Clips at 1.
+    Clamp,
+    /// `x / (1 + x)`, never quite white.
+    Reinhard,
+    /// The filmic curve of ACES, with a toe and a shoulder.
+    #[default]
+    Aces,
+}
+
+/// Settings of the exposure following the scene's brightness.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AutoExposure {
+    /// The darkest average log2 luminance the exposure adapts to, darker scenes stay dark.
+    pub min_ev: f32,
+    /// The brightest, brighter scenes stay bright.
+    pub max_ev: f32,
+    /// How quickly the exposure follows the scene getting brighter, per second.
+    pub speed_up: f32,
+    /// And darker, usually slower like an eye.
+    pub speed_down: f32,
+    /// EV added after adapting, to brighten or darken the result.
+    pub compensation: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            min_ev: -8.0,
+            max_ev: 6.0,
+            speed_up: 3.0,
+            speed_down: 1.0,
+            compensation: 0.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Exposure {
+    /// EV the frame is brightened by, 0 leaves it as it is.
+    Manual(f32),
+    Auto(AutoExposure),
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure::Auto(AutoExposure::default())
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct HistogramParams {
+    min_ev: f32,
+    ev_range: f32,
+    speed_up: f32,
+    speed_down: f32,
+    dt: f32,
+    pixel_count: u32,
+    _padding: [u32; 2],
+}
+unsafe impl bytemuck::Pod for HistogramParams {}
+unsafe impl bytemuck::Zeroable for HistogramParams {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct TonemapParams {
+    exposure: f32,
+    auto_exposure: u32,
+    curve: u32,
+    _padding: u32,
+}
+unsafe impl bytemuck::Pod for TonemapParams {}
+unsafe impl bytemuck::Zeroable for TonemapParams {}
+
+pub struct Tonemapper {
+    pub exposure: Exposure,
+    pub curve: ToneCurve,
+    histogram_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    histogram_layout: wgpu::BindGroupLayout,
+    histogram_params: wgpu::Buffer,
+    histogram: wgpu::Buffer,
+    state: wgpu::Buffer,
+    state_bind_group: wgpu::BindGroup,
+    bind_groups: BindGroupCache,
+    pass: PostPipeline,
+    _tracked: Tracked,
+}
+
+impl Tonemapper {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Auto Exposure Shader"),
+            source: wgpu::ShaderSource::Wgsl(HISTOGRAM_SHADER.into()),
+        });
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let histogram_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Auto Exposure Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(2),
+                storage_entry(3),
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Auto Exposure Pipeline Layout"),
+            bind_group_layouts: &[&histogram_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let histogram_pipeline =
+            create_pipeline("Auto Exposure Histogram Pipeline", "cs_histogram");
+        let average_pipeline = create_pipeline("Auto Exposure Average Pipeline", "cs_average");
+
+        let histogram_params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Auto Exposure Params"),
+            size: std::mem::size_of::<HistogramParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let histogram = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Auto Exposure Histogram"),
+            size: (BINS as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let state = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Auto Exposure State"),
+            size: 8,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let state_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tonemap State Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let state_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tonemap State Bind Group"),
+            layout: &state_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: state.as_entire_binding(),
+            }],
+        });
+        let pass = PostPipeline::new(
+            device,
+            "Tonemap",
+            TONEMAP_SHADER,
+            std::mem::size_of::<TonemapParams>() as u64,
+            &[&state_layout],
+        );
+
+        let tracked = Tracked::new(2, 3, 0)
+            .with_buffer(&histogram_params)
+            .with_buffer(&histogram)
+            .with_buffer(&state);
+        Self {
+            exposure: Exposure::default(),
+            curve: ToneCurve::default(),
+            histogram_pipeline,
+            average_pipeline,
+            histogram_layout,
+            histogram_params,
+            histogram,
+            state,
+            state_bind_group,
+            bind_groups: BindGroupCache::new(),
+            pass,
+            _tracked: tracked,
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: Exposure) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_curve(mut self, curve: ToneCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    fn adapt(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        settings: &AutoExposure,
+    ) {
+        let min_ev = settings.min_ev.min(settings.max_ev);
+        let params = HistogramParams {
+            min_ev,
+            ev_range: (settings.max_ev - min_ev).max(f32::EPSILON),
+            speed_up: settings.speed_up,
+            speed_down: settings.speed_down,
+            dt: frame.dt,
+            pixel_count: frame.width * frame.height,
+            _padding: [0; 2],
+        };
+        frame
+            .queue
+            .write_buffer(&self.histogram_params, 0, bytemuck::bytes_of(&params));
+        self.bind_groups.end_frame();
+        let bind_group = self.bind_groups.get(
+            frame.device,
+            "Auto Exposure Bind Group",
+            &self.histogram_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.histogram_params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.histogram.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.state.as_entire_binding(),
+                },
+            ],
+        );
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Auto Exposure Pass"),
+            timestamp_writes: None,
+        });
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_pipeline(&self.histogram_pipeline);
+        pass.dispatch_workgroups(
+            frame.width.div_ceil(WORKGROUP_SIZE),
+            frame.height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+        pass.set_pipeline(&self.average_pipeline);
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+}
+
+impl PostEffect for Tonemapper {
+    fn label(&self) -> &str {
+        "Tonemap"
+    }
+
+    fn apply(
+        &mut self,
+        frame: &PostFrame,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: PostTarget,
+    ) {
+        let (exposure, auto_exposure) = match self.exposure {
+            Exposure::Manual(ev) => (ev.exp2(), false),
+            Exposure::Auto(settings) => {
+                self.adapt(frame, encoder, source, &settings);
+                (settings.compensation.exp2(), true)
+            }
+        };
+        let params = TonemapParams {
+            exposure,
+            auto_exposure: auto_exposure as u32,
+            curve: match self.curve {
+                ToneCurve::Clamp => 0,
+                ToneCurve::Reinhard => 1,
+                ToneCurve::Aces => 2,
+            },
+            _padding: 0,
+        };
+        self.pass.draw(
+            frame,
+            encoder,
+            source,
+            target,
+            &params,
+            &[&self.state_bind_group],
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! Secondary windows such as inspectors and previews, opened and closed while the app
+//! runs, each drawn by a callback of its own.
+//!
+//! `Context::tool_windows` takes a `ToolWindow` and hands back an id right away; the run
+//! loop creates the window once the current event is handled, as winit only creates
+//! windows from the event loop. Tool windows share the context's device, so the textures,
+//! meshes, shaders and bind groups the app made with it work in them too. Their surfaces
+//! take the main window's format where they support it, so renderers made for
+//! `ctx.surface_format()`, the built-in ones included, draw into them as they are. Where a
+//! surface doesn't, `Frame::format` tells which one it has instead: the built-in renderers
+//! are made for one format and need creating for it, the app's own pipelines can follow it
+//! through `PipelineVariants`. While a tool window draws, the frame globals have its
+//! resolution, and it has a depth target of its own. Each redraws on its own, after the
+//! main window's frames, and closing one just closes it. Only `window::run_app` and its
+//! variants open them, in a headless context or on a render thread they stay pending.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use winit::event::WindowEvent;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::WindowBuilder;
+
+use crate::context::{choose_surface_format, Context};
+use crate::depth::DepthTarget;
+use crate::frame::{Frame, FrameOutput};
+
+type RenderFn = Box<dyn FnMut(&mut Context, &mut Frame, &DepthTarget)>;
+type InputFn = Box<dyn FnMut(&mut Context, &WindowEvent) -> bool>;
+
+/// Ids are unique across the context's windows, including ones opened from callbacks.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ToolWindowId(u64);
+
+/// A window to open, see `ToolWindows::open`.
+pub struct ToolWindow {
+    title: String,
+    size: Option<winit::dpi::LogicalSize<u32>>,
+    render: RenderFn,
+    input: Option<InputFn>,
+}
+
+impl ToolWindow {
+    /// A window titled `title` drawn by `render`, which gets the frame of the tool
+    /// window, not the main one, and a depth target its size for `Frame::begin_depth_pass`.
+    pub fn new(
+        title: &str,
+        render: impl FnMut(&mut Context, &mut Frame, &DepthTarget) + 'static,
+    ) -> Self {
+        Self {
+            title: title.to_string(),
+            size: None,
+            render: Box::new(render),
+            input: None,
+        }
+    }
+
+    /// Inner size in logical pixels, the platform's default otherwise.
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some(winit::dpi::LogicalSize::new(width, height));
+        self
+    }
+
+    /// Hands the window's events to `input` before the default handling, which resizes
+    /// the surface and closes the window when asked to. Return `true` to skip it.
+    pub fn with_input(
+        mut self,
+        input: impl FnMut(&mut Context, &WindowEvent) -> bool + 'static,
+    ) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+}
+
+struct OpenWindow {
+    id: ToolWindowId,
+    surface: wgpu::Surface,
+    // dropped after the surface, which draws into it
+    window: winit::window::Window,
+    config: wgpu::SurfaceConfiguration,
+    depth: DepthTarget,
+    render: RenderFn,
+    input: Option<InputFn>,
+}
+
+/// The tool windows of a `Context`, see the module docs.
+#[derive(Default)]
+pub struct ToolWindows {
+    pending: Vec<(ToolWindowId, ToolWindow)>,
+    closing: Vec<ToolWindowId>,
+    open: Vec<OpenWindow>,
+}
+
+impl ToolWindows {
+    /// Opens `window` once the current event is handled.
+    pub fn open(&mut self, window: ToolWindow) -> ToolWindowId {
+        let id = ToolWindowId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        self.pending.push((id, window));
+        id
+    }
+
+    /// Closes the window once the current event is handled. Does nothing if it's closed
+    /// already.
+    pub fn close(&mut self, id: ToolWindowId) {
+        self.closing.push(id);
+    }
+
+    /// Whether the window is open or about to be, `false` once it's closed, by the user or
+    /// by `close`.
+    pub fn is_open(&self, id: ToolWindowId) -> bool {
+        !self.closing.contains(&id)
+            && (self.open.iter().any(|open| open.id == id)
+                || self.pending.iter().any(|(pending, _)| *pending == id))
+    }
+
+    /// The winit window, once it's been created, e.g. to retitle or move it.
+    pub fn window(&self, id: ToolWindowId) -> Option<&winit::window::Window> {
+        self.open
+            .iter()
+            .find(|open| open.id == id)
+            .map(|open| &open.window)
+    }
+
+    /// Windows open, not counting pending ones.
+    pub fn len(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.open.is_empty()
+    }
+
+    /// Whether `window_id` is one of the open tool windows.
+    pub(crate) fn contains(&self, window_id: winit::window::WindowId) -> bool {
+        self.open.iter().any(|open| open.window.id() == window_id)
+    }
+
+    /// Takes on what was opened and closed while these were taken out of the context.
+    fn merge(&mut self, mut other: ToolWindows) {
+        self.pending.append(&mut other.pending);
+        self.closing.append(&mut other.closing);
+    }
+}
+
+/// Opens and closes what was asked for and requests a frame of every open window. The run
+/// loop calls this once its events are handled.
+pub(crate) fn update<T>(ctx: &mut Context, target: &EventLoopWindowTarget<T>) {
+    let closing = std::mem::take(&mut ctx.tool_windows.closing);
+    ctx.tool_windows
+        .open
+        .retain(|open| !closing.contains(&open.id));
+    ctx.tool_windows
+        .pending
+        .retain(|(id, _)| !closing.contains(id));
+    for (id, window) in std::mem::take(&mut ctx.tool_windows.pending) {
+        match create(ctx, target, id, window) {
+            Some(open) => ctx.tool_windows.open.push(open),
+            None => log::warn!("tool window could not be created"),
+        }
+    }
+    for open in &ctx.tool_windows.open {
+        open.window.request_redraw();
+    }
+}
+
+fn create<T>(
+    ctx: &Context,
+    target: &EventLoopWindowTarget<T>,
+    id: ToolWindowId,
+    window: ToolWindow,
+) -> Option<OpenWindow> {
+    let mut builder = WindowBuilder::new().with_title(&window.title);
+    if let Some(size) = window.size {
+        builder = builder.with_inner_size(size);
+    }
+    let winit_window = builder.build(target).ok()?;
+    let surface = unsafe { ctx.instance().create_surface(&winit_window) }.ok()?;
+    if !ctx.adapter().is_surface_supported(&surface) {
+        return None;
+    }
+    let caps = surface.get_capabilities(ctx.adapter());
+    // the main window's format where possible, which the built-in renderers are made for
+    let format = if caps.formats.contains(&ctx.surface_format()) {
+        ctx.surface_format()
+    } else {
+        choose_surface_format(&caps.formats, ctx.surface_format_preferences())
+    };
+    let size = winit_window.inner_size();
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width: size.width.max(1),
+        height: size.height.max(1),
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: caps.alpha_modes[0],
+        view_formats: vec![],
+    };
+    surface.configure(ctx.device(), &config);
+    let depth = DepthTarget::new(ctx.device(), config.width, config.height);
+    Some(OpenWindow {
+        id,
+        surface,
+        window: winit_window,
+        config,
+        depth,
+        render: window.render,
+        input: window.input,
+    })
+}
+
+/// Runs `f` with the open tool window of `window_id` and the context, with the windows
+/// taken out of it meanwhile.
+fn with_window(
+    ctx: &mut Context,
+    window_id: winit::window::WindowId,
+    f: impl FnOnce(&mut Context, &mut OpenWindow),
+) {
+    let mut windows = std::mem::take(&mut ctx.tool_windows);
+    if let Some(open) = windows
+        .open
+        .iter_mut()
+        .find(|open| open.window.id() == window_id)
+    {
+        f(ctx, open);
+    }
+    windows.merge(std::mem::take(&mut ctx.tool_windows));
+    ctx.tool_windows = windows;
+}
+
+/// Handles an event of a tool window, see `ToolWindow::with_input`.
+pub(crate) fn handle_event(
+    ctx: &mut Context,
+    window_id: winit::window::WindowId,
+    event: &WindowEvent,
+) {
+    with_window(ctx, window_id, |ctx, open| {
+        if open.input.as_mut().is_some_and(|input| input(ctx, event)) {
+            return;
+        }
+        match event {
+            WindowEvent::CloseRequested => ctx.tool_windows.close(open.id),
+            WindowEvent::Resized(size) => resize(ctx, open, *size),
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                resize(ctx, open, **new_inner_size)
+            }
+            _ => {}
+        }
+    });
+}
+
+fn resize(ctx: &Context, open: &mut OpenWindow, size: winit::dpi::PhysicalSize<u32>) {
+    if size.width == 0 || size.height == 0 {
+        return;
+    }
+    open.config.width = size.width;
+    open.config.height = size.height;
+    open.surface.configure(ctx.device(), &open.config);
+    open.depth.resize(ctx.device(), size.width, size.height);
+}
+
+/// Draws a frame of a tool window.
+pub(crate) fn render(ctx: &mut Context, window_id: winit::window::WindowId) {
+    with_window(ctx, window_id, |ctx, open| {
+        let output = match open.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                open.surface.configure(ctx.device(), &open.config);
+                return;
+            }
+            Err(e) => {
+                log::warn!("tool window frame: {:?}", e);
+                return;
+            }
+        };
+        let encoder = ctx
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Tool Window Encoder"),
+            });
+        let mut frame = Frame::new(
+            FrameOutput::Surface(output),
+            encoder,
+            ctx.crash_dump().clone(),
+        );
+        let (width, height) = crate::globals::set_resolution(open.config.width, open.config.height);
+        (open.render)(ctx, &mut frame, &open.depth);
+        crate::globals::set_resolution(width, height);
+        let (output, encoder) = frame.finish();
+        ctx.queue().submit(std::iter::once(encoder.finish()));
+        if let FrameOutput::Surface(output) = output {
+            output.present();
+        }
+    });
+}
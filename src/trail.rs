@@ -0,0 +1,359 @@
+//! Ribbon trails following a moving object, drawn as camera facing triangle strips.
+
+use std::collections::VecDeque;
+
+use crate::camera::Camera;
+use crate::globals::{with_frame_globals, FrameGlobals};
+use crate::math::Vec3;
+use crate::particles::BlendMode;
+use crate::stats::Tracked;
+use crate::transform::Transform;
+use crate::tween::Curve;
+
+const SHADER: &str = r#"
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = globals.view_proj * vec4<f32>(in.position, 1.0);
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+"#;
+
+#[derive(Clone, Debug)]
+pub struct TrailConfig {
+    /// Seconds a recorded point stays in the trail.
+    pub lifetime: f32,
+    /// New points are only recorded after moving at least this far.
+    pub min_distance: f32,
+    pub max_points: usize,
+    /// World width over normalized age (0 at the head, 1 at the tail).
+    pub width: Curve<f32>,
+    /// RGBA over normalized age, usually fading alpha to 0 towards the tail.
+    pub color: Curve<[f32; 4]>,
+    pub blend: BlendMode,
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        Self {
+            lifetime: 0.5,
+            min_distance: 0.05,
+            max_points: 64,
+            width: Curve::linear(0.1, 0.0),
+            color: Curve::linear([1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 0.0]),
+            blend: BlendMode::Alpha,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct TrailPoint {
+    position: Vec3,
+    age: f32,
+}
+
+/// Records the recent path of a transform.
+pub struct Trail {
+    pub config: TrailConfig,
+    head: Option<Vec3>,
+    // newest first
+    points: VecDeque<TrailPoint>,
+}
+
+impl Trail {
+    pub fn new(config: TrailConfig) -> Self {
+        Self {
+            config,
+            head: None,
+            points: VecDeque::new(),
+        }
+    }
+
+    /// Ages the recorded points and follows the transform's translation.
+    pub fn update(&mut self, dt: f32, transform: &Transform) {
+        self.follow(dt, transform.translation);
+    }
+
+    pub fn follow(&mut self, dt: f32, position: Vec3) {
+        for point in &mut self.points {
+            point.age += dt;
+        }
+        let lifetime = self.config.lifetime;
+        while self.points.back().is_some_and(|p| p.age >= lifetime) {
+            self.points.pop_back();
+        }
+
+        self.head = Some(position);
+        let moved = self
+            .points
+            .front()
+            .is_none_or(|p| p.position.distance(position) >= self.config.min_distance);
+        if moved {
+            self.points.push_front(TrailPoint { position, age: 0.0 });
+            self.points.truncate(self.config.max_points.max(2));
+        }
+    }
+
+    /// Drops the recorded path, e.g. after teleporting.
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.points.clear();
+    }
+
+    /// Points from head to tail with their normalized age.
+    fn samples(&self) -> impl Iterator<Item = (Vec3, f32)> + '_ {
+        let lifetime = self.config.lifetime.max(f32::EPSILON);
+        let head = self
+            .head
+            .filter(|h| self.points.front().is_none_or(|p| p.position != *h))
+            .map(|h| (h, 0.0));
+        head.into_iter().chain(
+            self.points
+                .iter()
+                .map(move |p| (p.position, (p.age / lifetime).min(1.0))),
+        )
+    }
+
+    /// Appends the ribbon as a triangle strip, two vertices per point.
+    fn build(&self, eye: Vec3, out: &mut Vec<TrailVertex>) {
+        let samples: Vec<(Vec3, f32)> = self.samples().collect();
+        if samples.len() < 2 {
+            return;
+        }
+        for (i, &(position, age)) in samples.iter().enumerate() {
+            let prev = samples[i.saturating_sub(1)].0;
+            let next = samples[(i + 1).min(samples.len() - 1)].0;
+            let tangent = (prev - next).normalize();
+            let to_camera = (eye - position).normalize();
+            let mut side = tangent.cross(to_camera).normalize();
+            if side == Vec3::ZERO {
+                side = tangent.any_orthogonal();
+            }
+            let half = self.config.width.sample(age) * 0.5;
+            let color = self.config.color.sample(age);
+            out.push(TrailVertex {
+                position: (position + side * half).to_array(),
+                color,
+            });
+            out.push(TrailVertex {
+                position: (position - side * half).to_array(),
+                color,
+            });
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct TrailVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+unsafe impl bytemuck::Pod for TrailVertex {}
+unsafe impl bytemuck::Zeroable for TrailVertex {}
+
+impl TrailVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+struct Batch {
+    blend: BlendMode,
+    vertices: std::ops::Range<u32>,
+}
+
+/// Draws any number of trails. Call `prepare` every frame before `render`.
+pub struct TrailRenderer {
+    alpha_pipeline: wgpu::RenderPipeline,
+    additive_pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    capacity: usize,
+    vertices: Vec<TrailVertex>,
+    batches: Vec<Batch>,
+    tracked: Tracked,
+}
+
+impl TrailRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Trail Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_frame_globals(SHADER).into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Trail Globals"),
+            size: std::mem::size_of::<FrameGlobals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Trail Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Trail Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Trail Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |label, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[TrailVertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        let alpha_pipeline = pipeline("Trail Alpha Pipeline", BlendMode::Alpha.blend_state());
+        let additive_pipeline =
+            pipeline("Trail Additive Pipeline", BlendMode::Additive.blend_state());
+
+        let capacity = 1024;
+        let vertex_buffer = Self::create_vertex_buffer(device, capacity);
+        let tracked = Tracked::new(2, 2, 0)
+            .with_buffer(&globals_buffer)
+            .with_buffer(&vertex_buffer);
+
+        Self {
+            alpha_pipeline,
+            additive_pipeline,
+            globals_buffer,
+            bind_group,
+            vertex_buffer,
+            capacity,
+            vertices: Vec::new(),
+            batches: Vec::new(),
+            tracked,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Trail Vertex Buffer"),
+            size: (capacity * std::mem::size_of::<TrailVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        trails: &[&Trail],
+    ) {
+        queue.write_buffer(
+            &self.globals_buffer,
+            0,
+            bytemuck::bytes_of(&FrameGlobals::from_camera(camera)),
+        );
+
+        self.vertices.clear();
+        self.batches.clear();
+        for trail in trails {
+            let start = self.vertices.len() as u32;
+            trail.build(camera.eye, &mut self.vertices);
+            let end = self.vertices.len() as u32;
+            if end > start {
+                self.batches.push(Batch {
+                    blend: trail.config.blend,
+                    vertices: start..end,
+                });
+            }
+        }
+
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            let vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+            self.tracked
+                .replace_buffer(&self.vertex_buffer, &vertex_buffer);
+            self.vertex_buffer = vertex_buffer;
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.batches.is_empty() {
+            return;
+        }
+        render_pass.push_debug_group("Trails");
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        for batch in &self.batches {
+            render_pass.set_pipeline(match batch.blend {
+                BlendMode::Alpha => &self.alpha_pipeline,
+                BlendMode::Additive => &self.additive_pipeline,
+            });
+            render_pass.draw(batch.vertices.clone(), 0..1);
+        }
+        render_pass.pop_debug_group();
+    }
+}
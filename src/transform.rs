@@ -0,0 +1,70 @@
+use crate::math::{Mat4, Quat, Vec3};
+
+/// Position, rotation and scale of an object.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "scene", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.rotation.rotate(-Vec3::Z)
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation.rotate(Vec3::X)
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation.rotate(Vec3::Y)
+    }
+
+    /// Treats `self` as the parent of `child`, e.g. to get a child's world transform.
+    pub fn mul_transform(&self, child: &Transform) -> Transform {
+        Transform {
+            translation: self.transform_point(child.translation),
+            rotation: self.rotation * child.rotation,
+            scale: self.scale.mul_elem(child.scale),
+        }
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.rotation.rotate(point.mul_elem(self.scale)) + self.translation
+    }
+}
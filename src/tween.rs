@@ -0,0 +1,668 @@
+use std::cell::Cell;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+/// Anything that can be linearly interpolated between two values.
+pub trait Lerp: Copy {
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t as f64
+    }
+}
+
+impl<const N: usize> Lerp for [f32; N] {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut out = *self;
+        for (o, b) in out.iter_mut().zip(other) {
+            *o = o.lerp(b, t);
+        }
+        out
+    }
+}
+
+/// Standard easing curves (see https://easings.net).
+/// All of them map 0.0 to 0.0 and 1.0 to 1.0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+    ElasticOut,
+    BounceOut,
+    /// Step straight to the end value once the tween finishes.
+    Step,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        const BACK: f32 = 1.70158;
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Easing::SineOut => (t * PI / 2.0).sin(),
+            Easing::SineInOut => -((PI * t).cos() - 1.0) / 2.0,
+            Easing::ExpoIn => {
+                if t == 0.0 {
+                    0.0
+                } else {
+                    2f32.powf(10.0 * t - 10.0)
+                }
+            }
+            Easing::ExpoOut => {
+                if t == 1.0 {
+                    1.0
+                } else {
+                    1.0 - 2f32.powf(-10.0 * t)
+                }
+            }
+            Easing::ExpoInOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else if t < 0.5 {
+                    2f32.powf(20.0 * t - 10.0) / 2.0
+                } else {
+                    (2.0 - 2f32.powf(-20.0 * t + 10.0)) / 2.0
+                }
+            }
+            Easing::BackIn => (BACK + 1.0) * t * t * t - BACK * t * t,
+            Easing::BackOut => 1.0 + (BACK + 1.0) * (t - 1.0).powi(3) + BACK * (t - 1.0).powi(2),
+            Easing::BackInOut => {
+                let c = BACK * 1.525;
+                if t < 0.5 {
+                    ((2.0 * t).powi(2) * ((c + 1.0) * 2.0 * t - c)) / 2.0
+                } else {
+                    ((2.0 * t - 2.0).powi(2) * ((c + 1.0) * (t * 2.0 - 2.0) + c) + 2.0) / 2.0
+                }
+            }
+            Easing::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    t
+                } else {
+                    2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+                }
+            }
+            Easing::BounceOut => {
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+                if t < 1.0 / D {
+                    N * t * t
+                } else if t < 2.0 / D {
+                    let t = t - 1.5 / D;
+                    N * t * t + 0.75
+                } else if t < 2.5 / D {
+                    let t = t - 2.25 / D;
+                    N * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D;
+                    N * t * t + 0.984375
+                }
+            }
+            Easing::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+}
+
+/// Something that advances with time. Tweens, delays, sequences and
+/// parallel groups all implement this so they can be nested freely.
+pub trait Animation {
+    /// Advances by `dt` seconds and returns the part of `dt` that was not
+    /// consumed because the animation finished (used by sequences).
+    fn advance(&mut self, dt: f32) -> f32;
+    fn is_finished(&self) -> bool;
+    /// Rewinds to the start.
+    fn reset(&mut self);
+}
+
+/// Shared read access to the current value of a tween, so the value can be
+/// read after the tween itself has been moved into a group or a `Tweens`.
+#[derive(Clone, Debug)]
+pub struct TweenHandle<T: Lerp>(Rc<Cell<T>>);
+
+impl<T: Lerp> TweenHandle<T> {
+    pub fn get(&self) -> T {
+        self.0.get()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    Once,
+    Times(u32),
+    Forever,
+}
+
+pub struct Tween<T: Lerp> {
+    from: T,
+    to: T,
+    duration: f32,
+    delay: f32,
+    elapsed: f32,
+    easing: Easing,
+    repeat: Repeat,
+    yoyo: bool,
+    cycle: u32,
+    current: Rc<Cell<T>>,
+}
+
+impl<T: Lerp> Tween<T> {
+    pub fn new(from: T, to: T, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration: duration.max(0.0),
+            delay: 0.0,
+            elapsed: 0.0,
+            easing: Easing::Linear,
+            repeat: Repeat::Once,
+            yoyo: false,
+            cycle: 0,
+            current: Rc::new(Cell::new(from)),
+        }
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Waits `delay` seconds (holding the start value) before moving.
+    pub fn with_delay(mut self, delay: f32) -> Self {
+        self.delay = delay.max(0.0);
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Play every other cycle backwards. Only meaningful with a repeat.
+    pub fn with_yoyo(mut self, yoyo: bool) -> Self {
+        self.yoyo = yoyo;
+        self
+    }
+
+    pub fn handle(&self) -> TweenHandle<T> {
+        TweenHandle(self.current.clone())
+    }
+
+    pub fn value(&self) -> T {
+        self.current.get()
+    }
+
+    /// Progress of the current cycle in 0..=1, before easing.
+    pub fn progress(&self) -> f32 {
+        if self.duration == 0.0 {
+            return if self.elapsed >= self.delay { 1.0 } else { 0.0 };
+        }
+        ((self.elapsed - self.delay) / self.duration).clamp(0.0, 1.0)
+    }
+
+    /// Convenience wrapper around `Animation::advance` returning the new value.
+    pub fn update(&mut self, dt: f32) -> T {
+        self.advance(dt);
+        self.value()
+    }
+
+    fn cycles(&self) -> Option<u32> {
+        match self.repeat {
+            Repeat::Once => Some(1),
+            Repeat::Times(n) => Some(n.max(1)),
+            Repeat::Forever => None,
+        }
+    }
+
+    fn sample(&self) -> T {
+        let mut t = self.easing.apply(self.progress());
+        if self.yoyo && self.cycle % 2 == 1 {
+            t = 1.0 - t;
+        }
+        self.from.lerp(&self.to, t)
+    }
+}
+
+impl<T: Lerp> Animation for Tween<T> {
+    fn advance(&mut self, dt: f32) -> f32 {
+        if self.is_finished() {
+            return dt;
+        }
+        self.elapsed += dt;
+        let mut leftover = 0.0;
+        let cycle_end = self.delay + self.duration;
+        while self.elapsed >= cycle_end {
+            let next = self.cycle + 1;
+            if self.cycles().is_some_and(|n| next >= n) {
+                leftover = self.elapsed - cycle_end;
+                self.elapsed = cycle_end;
+                break;
+            }
+            if self.duration == 0.0 {
+                // A zero length cycle that repeats forever would never leave this loop.
+                self.elapsed = cycle_end;
+                break;
+            }
+            self.cycle = next;
+            // The delay only applies before the first cycle.
+            self.elapsed -= self.duration;
+        }
+        self.current.set(self.sample());
+        leftover
+    }
+
+    fn is_finished(&self) -> bool {
+        match self.cycles() {
+            Some(n) => self.cycle + 1 >= n && self.elapsed >= self.delay + self.duration,
+            None => false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.cycle = 0;
+        self.current.set(self.from);
+    }
+}
+
+/// Does nothing for a while. Useful as a gap inside a `Sequence`.
+pub struct Wait {
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Wait {
+    /// Negative durations count as zero.
+    pub fn new(duration: f32) -> Self {
+        Self {
+            duration: duration.max(0.0),
+            elapsed: 0.0,
+        }
+    }
+}
+
+impl Animation for Wait {
+    fn advance(&mut self, dt: f32) -> f32 {
+        self.elapsed += dt;
+        let leftover = (self.elapsed - self.duration).max(0.0);
+        self.elapsed = self.elapsed.min(self.duration);
+        leftover
+    }
+
+    fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+/// Plays its children one after another.
+#[derive(Default)]
+pub struct Sequence {
+    children: Vec<Box<dyn Animation>>,
+    index: usize,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then(mut self, animation: impl Animation + 'static) -> Self {
+        self.children.push(Box::new(animation));
+        self
+    }
+
+    pub fn wait(self, seconds: f32) -> Self {
+        self.then(Wait::new(seconds))
+    }
+}
+
+impl Animation for Sequence {
+    fn advance(&mut self, mut dt: f32) -> f32 {
+        while let Some(child) = self.children.get_mut(self.index) {
+            dt = child.advance(dt);
+            if !child.is_finished() {
+                return 0.0;
+            }
+            self.index += 1;
+        }
+        dt
+    }
+
+    fn is_finished(&self) -> bool {
+        self.index >= self.children.len()
+    }
+
+    fn reset(&mut self) {
+        self.index = 0;
+        self.children.iter_mut().for_each(|c| c.reset());
+    }
+}
+
+/// Plays all of its children at the same time. Finishes when the longest one does.
+#[derive(Default)]
+pub struct Parallel {
+    children: Vec<Box<dyn Animation>>,
+}
+
+impl Parallel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, animation: impl Animation + 'static) -> Self {
+        self.children.push(Box::new(animation));
+        self
+    }
+}
+
+impl Animation for Parallel {
+    fn advance(&mut self, dt: f32) -> f32 {
+        self.children
+            .iter_mut()
+            .filter(|c| !c.is_finished())
+            .map(|c| c.advance(dt))
+            .fold(dt, f32::min)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.children.iter().all(|c| c.is_finished())
+    }
+
+    fn reset(&mut self) {
+        self.children.iter_mut().for_each(|c| c.reset());
+    }
+}
+
+/// Owns running animations and drops them once they finish.
+/// Advanced once per frame from the update loop.
+#[derive(Default)]
+pub struct Tweens {
+    running: Vec<Box<dyn Animation>>,
+}
+
+impl Tweens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, animation: impl Animation + 'static) {
+        self.running.push(Box::new(animation));
+    }
+
+    /// Convenience for the common case: starts a tween and returns a handle to its value.
+    pub fn tween<T: Lerp + 'static>(&mut self, tween: Tween<T>) -> TweenHandle<T> {
+        let handle = tween.handle();
+        self.add(tween);
+        handle
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        for animation in &mut self.running {
+            animation.advance(dt);
+        }
+        self.running.retain(|a| !a.is_finished());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.running.clear();
+    }
+}
+
+/// Piecewise linear keyframe curve over `t` (usually normalized 0..1),
+/// e.g. size or color over a particle's lifetime.
+#[derive(Clone, Debug)]
+pub struct Curve<T: Lerp> {
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Lerp> Curve<T> {
+    /// Keys don't need to be sorted. Panics if `keys` is empty.
+    pub fn new(mut keys: Vec<(f32, T)>) -> Self {
+        assert!(!keys.is_empty(), "Curve needs at least one key");
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    pub fn constant(value: T) -> Self {
+        Self::new(vec![(0.0, value)])
+    }
+
+    pub fn linear(from: T, to: T) -> Self {
+        Self::new(vec![(0.0, from), (1.0, to)])
+    }
+
+    pub fn with_key(mut self, t: f32, value: T) -> Self {
+        self.keys.push((t, value));
+        self.keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self
+    }
+
+    pub fn sample(&self, t: f32) -> T {
+        let first = self.keys[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.keys.windows(2) {
+            let (t0, a) = pair[0];
+            let (t1, b) = pair[1];
+            if t <= t1 {
+                let span = t1 - t0;
+                let local = if span > 0.0 { (t - t0) / span } else { 1.0 };
+                return a.lerp(&b, local);
+            }
+        }
+        self.keys[self.keys.len() - 1].1
+    }
+}
+
+impl<T: Lerp> From<T> for Curve<T> {
+    fn from(value: T) -> Self {
+        Self::constant(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_EASINGS: [Easing; 19] = [
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicIn,
+        Easing::CubicOut,
+        Easing::CubicInOut,
+        Easing::SineIn,
+        Easing::SineOut,
+        Easing::SineInOut,
+        Easing::ExpoIn,
+        Easing::ExpoOut,
+        Easing::ExpoInOut,
+        Easing::BackIn,
+        Easing::BackOut,
+        Easing::BackInOut,
+        Easing::ElasticOut,
+        Easing::BounceOut,
+        Easing::Step,
+    ];
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn easings_keep_their_endpoints() {
+        for easing in ALL_EASINGS {
+            assert_near(easing.apply(0.0), 0.0);
+            assert_near(easing.apply(1.0), 1.0);
+            // out of range input is clamped
+            assert_near(easing.apply(-1.0), 0.0);
+            assert_near(easing.apply(2.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn tween_moves_and_finishes() {
+        let mut tween = Tween::new(0.0f32, 10.0, 2.0);
+        let handle = tween.handle();
+        assert_near(tween.update(0.5), 2.5);
+        assert_near(handle.get(), 2.5);
+        assert!(!tween.is_finished());
+        assert_near(tween.advance(2.0), 0.5);
+        assert!(tween.is_finished());
+        assert_near(handle.get(), 10.0);
+        tween.reset();
+        assert_near(tween.value(), 0.0);
+    }
+
+    #[test]
+    fn tween_delay_holds_the_start() {
+        let mut tween = Tween::new(1.0f32, 2.0, 1.0).with_delay(1.0);
+        assert_near(tween.update(0.5), 1.0);
+        assert_near(tween.update(1.0), 1.5);
+        assert_near(tween.progress(), 0.5);
+    }
+
+    #[test]
+    fn tween_repeats_with_yoyo() {
+        let mut tween = Tween::new(0.0f32, 1.0, 1.0)
+            .with_repeat(Repeat::Times(2))
+            .with_yoyo(true);
+        assert_near(tween.update(0.25), 0.25);
+        assert_near(tween.update(1.0), 0.75);
+        assert!(!tween.is_finished());
+        assert_near(tween.advance(1.0), 0.25);
+        assert!(tween.is_finished());
+        assert_near(tween.value(), 0.0);
+
+        let mut forever = Tween::new(0.0f32, 1.0, 1.0).with_repeat(Repeat::Forever);
+        assert_near(forever.update(10.5), 0.5);
+        assert!(!forever.is_finished());
+    }
+
+    #[test]
+    fn zero_length_tweens_finish_at_once() {
+        let mut tween = Tween::new(0.0f32, 1.0, -1.0).with_repeat(Repeat::Forever);
+        assert_near(tween.update(0.1), 1.0);
+        let mut once = Tween::new(0.0f32, 1.0, 0.0);
+        assert_near(once.advance(0.1), 0.1);
+        assert!(once.is_finished());
+    }
+
+    #[test]
+    fn negative_waits_count_as_zero() {
+        let mut wait = Wait::new(-1.0);
+        assert!(wait.is_finished());
+        assert_near(wait.advance(0.5), 0.5);
+        assert!(wait.is_finished());
+    }
+
+    #[test]
+    fn sequence_passes_leftover_time_on() {
+        let first = Tween::new(0.0f32, 1.0, 1.0);
+        let second = Tween::new(0.0f32, 1.0, 1.0);
+        let (a, b) = (first.handle(), second.handle());
+        let mut sequence = Sequence::new().then(first).wait(0.5).then(second);
+        assert_near(sequence.advance(1.75), 0.0);
+        assert_near(a.get(), 1.0);
+        assert_near(b.get(), 0.25);
+        assert_near(sequence.advance(1.0), 0.25);
+        assert!(sequence.is_finished());
+        sequence.reset();
+        assert!(!sequence.is_finished());
+    }
+
+    #[test]
+    fn parallel_finishes_with_the_longest() {
+        let short = Tween::new(0.0f32, 1.0, 1.0);
+        let long = Tween::new(0.0f32, 1.0, 2.0);
+        let (a, b) = (short.handle(), long.handle());
+        let mut parallel = Parallel::new().with(short).with(long);
+        assert_near(parallel.advance(1.5), 0.0);
+        assert!(!parallel.is_finished());
+        assert_near(a.get(), 1.0);
+        assert_near(b.get(), 0.75);
+        assert_near(parallel.advance(1.0), 0.5);
+        assert!(parallel.is_finished());
+    }
+
+    #[test]
+    fn tweens_drop_finished_animations() {
+        let mut tweens = Tweens::new();
+        let handle = tweens.tween(Tween::new([0.0f32, 0.0], [2.0, 4.0], 1.0));
+        tweens.add(Wait::new(2.0));
+        tweens.update(0.5);
+        assert_eq!(handle.get(), [1.0, 2.0]);
+        tweens.update(1.0);
+        assert_eq!(handle.get(), [2.0, 4.0]);
+        assert!(!tweens.is_empty());
+        tweens.update(1.0);
+        assert!(tweens.is_empty());
+    }
+
+    #[test]
+    fn curve_samples_between_keys() {
+        let curve = Curve::new(vec![(1.0, 10.0f32), (0.0, 0.0)]).with_key(0.5, 20.0);
+        assert_near(curve.sample(-1.0), 0.0);
+        assert_near(curve.sample(0.25), 10.0);
+        assert_near(curve.sample(0.75), 15.0);
+        assert_near(curve.sample(2.0), 10.0);
+        assert_near(Curve::from(3.0f32).sample(0.5), 3.0);
+        assert_near(Curve::linear(0.0f32, 2.0).sample(0.5), 1.0);
+    }
+}
@@ -0,0 +1,589 @@
+//! A small retained mode UI: labels, buttons, checkboxes and sliders arranged in row and
+//! column containers, drawn with the shape and text renderers.
+//!
+//! Widgets live in a tree owned by `Ui` and are addressed by `WidgetId`. Feed window events
+//! to `Ui::input` and read back what the user did with `Ui::events`. Layout runs in
+//! `prepare`, so hit-testing uses the rectangles of the last prepared frame.
+
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+use crate::math::Vec2;
+use crate::shapes::ShapeRenderer;
+use crate::text::{Font, FontId, TextRenderer};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WidgetId(usize);
+
+/// How a container stacks its children.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum Direction {
+    /// Top to bottom, children aligned left.
+    #[default]
+    Column,
+    /// Left to right, children centred vertically.
+    Row,
+}
+
+/// Something the user did, collected by `Ui::input`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UiEvent {
+    /// A button was pressed and released with the cursor over it.
+    Clicked(WidgetId),
+    Toggled(WidgetId, bool),
+    /// A slider was dragged to a new value.
+    Changed(WidgetId, f32),
+}
+
+/// Colours and metrics shared by every widget, in window pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UiStyle {
+    pub font_size: f32,
+    /// Space between a widget's frame and its contents, and around the root panel.
+    pub padding: f32,
+    /// Gap between the children of a container.
+    pub spacing: f32,
+    pub corner_radius: f32,
+    pub slider_width: f32,
+    pub text: [f32; 4],
+    pub panel: [f32; 4],
+    pub widget: [f32; 4],
+    pub hovered: [f32; 4],
+    pub pressed: [f32; 4],
+    pub accent: [f32; 4],
+}
+
+impl Default for UiStyle {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl UiStyle {
+    /// Light text on dark panels, the default.
+    pub fn dark() -> Self {
+        Self {
+            font_size: 16.0,
+            padding: 6.0,
+            spacing: 6.0,
+            corner_radius: 4.0,
+            slider_width: 160.0,
+            text: [0.92, 0.92, 0.92, 1.0],
+            panel: [0.1, 0.1, 0.12, 0.9],
+            widget: [0.24, 0.24, 0.28, 1.0],
+            hovered: [0.32, 0.32, 0.38, 1.0],
+            pressed: [0.18, 0.18, 0.2, 1.0],
+            accent: [0.26, 0.52, 0.96, 1.0],
+        }
+    }
+
+    /// Dark text on light panels.
+    pub fn light() -> Self {
+        Self {
+            text: [0.1, 0.1, 0.12, 1.0],
+            panel: [0.95, 0.95, 0.96, 0.9],
+            widget: [0.84, 0.84, 0.87, 1.0],
+            hovered: [0.76, 0.76, 0.8, 1.0],
+            pressed: [0.9, 0.9, 0.92, 1.0],
+            accent: [0.16, 0.44, 0.9, 1.0],
+            ..Self::dark()
+        }
+    }
+
+    /// The style matching `theme`, e.g. `Context::theme`.
+    pub fn for_theme(theme: winit::window::Theme) -> Self {
+        match theme {
+            winit::window::Theme::Light => Self::light(),
+            winit::window::Theme::Dark => Self::dark(),
+        }
+    }
+}
+
+enum Kind {
+    Container {
+        direction: Direction,
+        children: Vec<WidgetId>,
+    },
+    Label {
+        text: String,
+    },
+    Button {
+        text: String,
+    },
+    Checkbox {
+        text: String,
+        checked: bool,
+    },
+    Slider {
+        min: f32,
+        max: f32,
+        value: f32,
+    },
+}
+
+struct Node {
+    kind: Kind,
+    /// Top left corner and size from the last layout.
+    min: Vec2,
+    size: Vec2,
+    /// Measured size of the widget's text.
+    text_size: Vec2,
+}
+
+/// Retained widgets in a panel with its top left corner at `position`.
+pub struct Ui {
+    shapes: ShapeRenderer,
+    text: TextRenderer,
+    font: FontId,
+    nodes: Vec<Node>,
+    pub position: Vec2,
+    pub style: UiStyle,
+    cursor: Vec2,
+    hovered: Option<WidgetId>,
+    /// Widget the left button went down on, it receives drags and the release.
+    active: Option<WidgetId>,
+    events: Vec<UiEvent>,
+}
+
+impl Ui {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, font: Font) -> Self {
+        let mut text = TextRenderer::new(device, format);
+        let font = text.add_font(font);
+        let mut ui = Self {
+            shapes: ShapeRenderer::new(device, format),
+            text,
+            font,
+            nodes: Vec::new(),
+            position: Vec2::ZERO,
+            style: UiStyle::default(),
+            cursor: Vec2::splat(f32::NEG_INFINITY),
+            hovered: None,
+            active: None,
+            events: Vec::new(),
+        };
+        ui.clear();
+        ui
+    }
+
+    /// The column every other widget is nested in.
+    pub fn root(&self) -> WidgetId {
+        WidgetId(0)
+    }
+
+    /// Removes every widget but the root.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.nodes.push(Node::new(Kind::Container {
+            direction: Direction::Column,
+            children: Vec::new(),
+        }));
+        self.hovered = None;
+        self.active = None;
+    }
+
+    /// Gives access to the text renderer, e.g. to add fallback fonts.
+    pub fn text_renderer(&mut self) -> &mut TextRenderer {
+        &mut self.text
+    }
+
+    pub fn add_container(&mut self, parent: WidgetId, direction: Direction) -> WidgetId {
+        self.add(
+            parent,
+            Kind::Container {
+                direction,
+                children: Vec::new(),
+            },
+        )
+    }
+
+    pub fn add_label(&mut self, parent: WidgetId, text: impl Into<String>) -> WidgetId {
+        self.add(parent, Kind::Label { text: text.into() })
+    }
+
+    pub fn add_button(&mut self, parent: WidgetId, text: impl Into<String>) -> WidgetId {
+        self.add(parent, Kind::Button { text: text.into() })
+    }
+
+    pub fn add_checkbox(
+        &mut self,
+        parent: WidgetId,
+        text: impl Into<String>,
+        checked: bool,
+    ) -> WidgetId {
+        self.add(
+            parent,
+            Kind::Checkbox {
+                text: text.into(),
+                checked,
+            },
+        )
+    }
+
+    /// A horizontal slider over `min..=max`.
+    pub fn add_slider(&mut self, parent: WidgetId, min: f32, max: f32, value: f32) -> WidgetId {
+        self.add(
+            parent,
+            Kind::Slider {
+                min,
+                max,
+                value: value.clamp(min.min(max), max.max(min)),
+            },
+        )
+    }
+
+    /// Panics if `parent` is not a container.
+    fn add(&mut self, parent: WidgetId, kind: Kind) -> WidgetId {
+        let id = WidgetId(self.nodes.len());
+        let Kind::Container { children, .. } = &mut self.nodes[parent.0].kind else {
+            panic!("widget {:?} is not a container", parent);
+        };
+        children.push(id);
+        self.nodes.push(Node::new(kind));
+        id
+    }
+
+    /// Replaces the text of a label, button or checkbox.
+    pub fn set_text(&mut self, id: WidgetId, text: impl Into<String>) {
+        match &mut self.nodes[id.0].kind {
+            Kind::Label { text: t } | Kind::Button { text: t } | Kind::Checkbox { text: t, .. } => {
+                *t = text.into()
+            }
+            _ => log::warn!("Widget {:?} has no text", id),
+        }
+    }
+
+    /// `false` for anything but a checked checkbox.
+    pub fn is_checked(&self, id: WidgetId) -> bool {
+        matches!(self.nodes[id.0].kind, Kind::Checkbox { checked: true, .. })
+    }
+
+    pub fn set_checked(&mut self, id: WidgetId, checked: bool) {
+        match &mut self.nodes[id.0].kind {
+            Kind::Checkbox { checked: c, .. } => *c = checked,
+            _ => log::warn!("Widget {:?} is not a checkbox", id),
+        }
+    }
+
+    /// A slider's value, 0 for other widgets.
+    pub fn value(&self, id: WidgetId) -> f32 {
+        match self.nodes[id.0].kind {
+            Kind::Slider { value, .. } => value,
+            _ => 0.0,
+        }
+    }
+
+    pub fn set_value(&mut self, id: WidgetId, value: f32) {
+        match &mut self.nodes[id.0].kind {
+            Kind::Slider { min, max, value: v } => *v = value.clamp(min.min(*max), max.max(*min)),
+            _ => log::warn!("Widget {:?} is not a slider", id),
+        }
+    }
+
+    /// The interactive widget under the cursor.
+    pub fn hovered(&self) -> Option<WidgetId> {
+        self.hovered
+    }
+
+    /// Whether `point` is over the root panel.
+    pub fn contains(&self, point: Vec2) -> bool {
+        let (min, max) = self.panel_rect();
+        point.x >= min.x && point.y >= min.y && point.x < max.x && point.y < max.y
+    }
+
+    /// Updates hover, press and drag state. Returns `true` when the event was meant for the
+    /// UI, i.e. a click on the panel or a drag that started on a widget.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Vec2::new(position.x as f32, position.y as f32);
+                self.hovered = self.hit(self.cursor);
+                match self.active {
+                    Some(id) => {
+                        self.drag(id);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = Vec2::splat(f32::NEG_INFINITY);
+                self.hovered = None;
+                false
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => match state {
+                ElementState::Pressed => {
+                    self.active = self.hovered;
+                    if let Some(id) = self.active {
+                        self.drag(id);
+                    }
+                    self.contains(self.cursor)
+                }
+                ElementState::Released => {
+                    let Some(id) = self.active.take() else {
+                        return false;
+                    };
+                    if self.hovered == Some(id) {
+                        self.release(id);
+                    }
+                    true
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// Takes the events collected since the last call.
+    pub fn events(&mut self) -> std::vec::Drain<'_, UiEvent> {
+        self.events.drain(..)
+    }
+
+    fn hit(&self, point: Vec2) -> Option<WidgetId> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, node)| {
+                let max = node.min + node.size;
+                matches!(
+                    node.kind,
+                    Kind::Button { .. } | Kind::Checkbox { .. } | Kind::Slider { .. }
+                ) && point.x >= node.min.x
+                    && point.y >= node.min.y
+                    && point.x < max.x
+                    && point.y < max.y
+            })
+            .map(|(i, _)| WidgetId(i))
+    }
+
+    /// Moves a slider's value to the cursor.
+    fn drag(&mut self, id: WidgetId) {
+        let (start, end) = self.track(id);
+        let node = &mut self.nodes[id.0];
+        if let Kind::Slider { min, max, value } = &mut node.kind {
+            let t = ((self.cursor.x - start) / (end - start).max(1.0)).clamp(0.0, 1.0);
+            let new = *min + (*max - *min) * t;
+            if new != *value {
+                *value = new;
+                self.events.push(UiEvent::Changed(id, new));
+            }
+        }
+    }
+
+    fn release(&mut self, id: WidgetId) {
+        match &mut self.nodes[id.0].kind {
+            Kind::Button { .. } => self.events.push(UiEvent::Clicked(id)),
+            Kind::Checkbox { checked, .. } => {
+                *checked = !*checked;
+                self.events.push(UiEvent::Toggled(id, *checked));
+            }
+            _ => {}
+        }
+    }
+
+    fn knob_radius(&self) -> f32 {
+        self.style.font_size * 0.45
+    }
+
+    /// Horizontal extent of a slider's knob centre.
+    fn track(&self, id: WidgetId) -> (f32, f32) {
+        let node = &self.nodes[id.0];
+        let r = self.knob_radius();
+        (node.min.x + r, node.min.x + node.size.x - r)
+    }
+
+    fn panel_rect(&self) -> (Vec2, Vec2) {
+        let padding = Vec2::splat(self.style.padding);
+        let root = &self.nodes[0];
+        (root.min - padding, root.min + root.size + padding)
+    }
+
+    /// Sets every node's size, bottom up.
+    fn measure(&mut self, id: WidgetId) -> Vec2 {
+        let style = self.style;
+        let line = self.text.font(self.font).line_height() * style.font_size;
+        let text_size = match &self.nodes[id.0].kind {
+            Kind::Label { text } | Kind::Button { text } | Kind::Checkbox { text, .. } => {
+                self.text.measure(self.font, text, style.font_size)
+            }
+            _ => Vec2::ZERO,
+        };
+        let size = match &self.nodes[id.0].kind {
+            Kind::Container {
+                direction,
+                children,
+            } => {
+                let direction = *direction;
+                let children = children.clone();
+                let mut size = Vec2::ZERO;
+                for (i, child) in children.into_iter().enumerate() {
+                    let child = self.measure(child);
+                    let gap = if i > 0 { style.spacing } else { 0.0 };
+                    size = match direction {
+                        Direction::Column => Vec2::new(size.x.max(child.x), size.y + gap + child.y),
+                        Direction::Row => Vec2::new(size.x + gap + child.x, size.y.max(child.y)),
+                    };
+                }
+                size
+            }
+            Kind::Label { .. } => text_size,
+            Kind::Button { .. } => text_size + Vec2::splat(style.padding * 2.0),
+            Kind::Checkbox { .. } => Vec2::new(
+                style.font_size + style.spacing + text_size.x,
+                text_size.y.max(style.font_size),
+            ),
+            Kind::Slider { .. } => Vec2::new(style.slider_width, line + style.padding * 2.0),
+        };
+        let node = &mut self.nodes[id.0];
+        node.size = size;
+        node.text_size = text_size;
+        size
+    }
+
+    /// Places every node below `id`, top down.
+    fn arrange(&mut self, id: WidgetId, min: Vec2) {
+        self.nodes[id.0].min = min;
+        let height = self.nodes[id.0].size.y;
+        let Kind::Container {
+            direction,
+            children,
+        } = &self.nodes[id.0].kind
+        else {
+            return;
+        };
+        let direction = *direction;
+        let mut pen = min;
+        for child in children.clone() {
+            let size = self.nodes[child.0].size;
+            match direction {
+                Direction::Column => {
+                    self.arrange(child, pen);
+                    pen.y += size.y + self.style.spacing;
+                }
+                Direction::Row => {
+                    self.arrange(child, Vec2::new(pen.x, pen.y + (height - size.y) * 0.5));
+                    pen.x += size.x + self.style.spacing;
+                }
+            }
+        }
+    }
+
+    fn state_color(&self, id: WidgetId) -> [f32; 4] {
+        if self.active == Some(id) && self.hovered == Some(id) {
+            self.style.pressed
+        } else if self.active == Some(id) || (self.active.is_none() && self.hovered == Some(id)) {
+            self.style.hovered
+        } else {
+            self.style.widget
+        }
+    }
+
+    /// Lays out the widgets, queues them and uploads everything for `render`.
+    /// `width` and `height` are the window size in pixels, the space cursor events use.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: f32, height: f32) {
+        let style = self.style;
+        let root = self.root();
+        self.measure(root);
+        self.arrange(root, self.position + Vec2::splat(style.padding));
+
+        let (panel_min, panel_max) = self.panel_rect();
+        self.shapes
+            .fill_rounded_rect(panel_min, panel_max, style.corner_radius, style.panel);
+
+        for i in 1..self.nodes.len() {
+            let id = WidgetId(i);
+            let color = self.state_color(id);
+            let node = &self.nodes[i];
+            let (min, size) = (node.min, node.size);
+            let text_min = |x: f32| Vec2::new(x, min.y + (size.y - node.text_size.y) * 0.5);
+            match &node.kind {
+                Kind::Container { .. } => {}
+                Kind::Label { text } => {
+                    self.text
+                        .draw(self.font, text, min, style.font_size, style.text);
+                }
+                Kind::Button { text } => {
+                    self.shapes
+                        .fill_rounded_rect(min, min + size, style.corner_radius, color);
+                    let x = min.x + (size.x - node.text_size.x) * 0.5;
+                    self.text
+                        .draw(self.font, text, text_min(x), style.font_size, style.text);
+                }
+                Kind::Checkbox { text, checked } => {
+                    let side = style.font_size;
+                    let box_min = Vec2::new(min.x, min.y + (size.y - side) * 0.5);
+                    self.shapes.fill_rounded_rect(
+                        box_min,
+                        box_min + Vec2::splat(side),
+                        style.corner_radius * 0.5,
+                        color,
+                    );
+                    if *checked {
+                        let inset = Vec2::splat(side * 0.22);
+                        self.shapes.fill_rounded_rect(
+                            box_min + inset,
+                            box_min + Vec2::splat(side) - inset,
+                            style.corner_radius * 0.25,
+                            style.accent,
+                        );
+                    }
+                    let x = min.x + side + style.spacing;
+                    self.text
+                        .draw(self.font, text, text_min(x), style.font_size, style.text);
+                }
+                Kind::Slider {
+                    min: lo,
+                    max: hi,
+                    value,
+                } => {
+                    let t = if hi == lo {
+                        0.0
+                    } else {
+                        (value - lo) / (hi - lo)
+                    };
+                    let (start, end) = self.track(id);
+                    let y = min.y + size.y * 0.5;
+                    let knob = Vec2::new(start + (end - start) * t, y);
+                    let half = Vec2::new(0.0, 2.0);
+                    self.shapes.fill_rounded_rect(
+                        Vec2::new(start, y) - half,
+                        Vec2::new(end, y) + half,
+                        2.0,
+                        style.widget,
+                    );
+                    self.shapes.fill_rounded_rect(
+                        Vec2::new(start, y) - half,
+                        knob + half,
+                        2.0,
+                        style.accent,
+                    );
+                    let radius = self.knob_radius();
+                    self.shapes.fill_circle(knob, radius, color);
+                    self.shapes.fill_circle(knob, radius * 0.6, style.text);
+                }
+            }
+        }
+
+        let view_proj = ShapeRenderer::pixel_projection(width, height);
+        self.shapes.prepare(device, queue, view_proj);
+        self.text.prepare(device, queue, view_proj);
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.shapes.render(render_pass);
+        self.text.render(render_pass);
+    }
+}
+
+impl Node {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            min: Vec2::ZERO,
+            size: Vec2::ZERO,
+            text_size: Vec2::ZERO,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Per object uniforms packed into one buffer each frame and bound at a dynamic offset per
+//! draw, instead of a buffer and bind group per object.
+//!
+//! Clear the arena at the start of a frame, push every object's uniform, upload, and bind
+//! the arena's one bind group with the offset `push` returned before each draw. The
+//! buffer grows to the most values pushed in a frame and is kept at that size.
+
+use crate::stats::Tracked;
+
+const INITIAL_CAPACITY: u64 = 64;
+
+pub struct UniformArena {
+    label: String,
+    layout: wgpu::BindGroupLayout,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// Size of one value as the shader sees it.
+    item_size: u64,
+    /// `item_size` rounded up to the device's dynamic offset alignment.
+    stride: u64,
+    capacity: u64,
+    data: Vec<u8>,
+    _tracked: Tracked,
+}
+
+impl UniformArena {
+    /// Holds values of `item_size` bytes, bound at binding 0 of a group visible to
+    /// `visibility`.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        item_size: u64,
+        visibility: wgpu::ShaderStages,
+    ) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("{} Bind Group Layout", label)),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(item_size),
+                },
+                count: None,
+            }],
+        });
+        let stride = item_size.next_multiple_of(alignment);
+        let (buffer, bind_group) =
+            Self::create_buffer(device, label, &layout, item_size, stride * INITIAL_CAPACITY);
+        let tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+        Self {
+            label: label.to_string(),
+            layout,
+            buffer,
+            bind_group,
+            item_size,
+            stride,
+            capacity: INITIAL_CAPACITY,
+            data: Vec::new(),
+            _tracked: tracked,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::BindGroupLayout,
+        item_size: u64,
+        size: u64,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} Bind Group", label)),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(item_size),
+                }),
+            }],
+        });
+        (buffer, bind_group)
+    }
+
+    /// The layout of `bind_group`, for the pipelines drawing with it.
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    /// Bind with an offset returned by `push` since the last `clear`.
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Drops the values pushed so far, call once per frame before pushing.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// Adds `value` and returns the dynamic offset to bind it at. It's on the GPU after the
+    /// next `upload`.
+    pub fn push<T: bytemuck::Pod>(&mut self, value: &T) -> u32 {
+        let bytes = bytemuck::bytes_of(value);
+        assert!(
+            bytes.len() as u64 <= self.item_size,
+            "values pushed to an arena fit in its item size"
+        );
+        let offset = self.data.len();
+        self.data.extend_from_slice(bytes);
+        self.data.resize(offset + self.stride as usize, 0);
+        offset as u32
+    }
+
+    /// Values pushed since the last `clear`.
+    pub fn len(&self) -> usize {
+        self.data.len() / self.stride as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Writes the pushed values to the buffer, growing it first if they don't fit. Growing
+    /// replaces the bind group.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let len = self.len() as u64;
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two();
+            let (buffer, bind_group) = Self::create_buffer(
+                device,
+                &self.label,
+                &self.layout,
+                self.item_size,
+                self.stride * self.capacity,
+            );
+            self._tracked = Tracked::new(0, 1, 0).with_buffer(&buffer);
+            self.buffer = buffer;
+            self.bind_group = bind_group;
+        }
+        if !self.data.is_empty() {
+            queue.write_buffer(&self.buffer, 0, &self.data);
+        }
+    }
+}
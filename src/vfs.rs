@@ -0,0 +1,94 @@
+//! A virtual filesystem assets are read through: files embedded in the binary, layered
+//! under directories on disk.
+//!
+//! Directories mounted later are searched first, and embedded files come last, so a
+//! development build can mount the asset directory over what a release build ships
+//! embedded, and get hot reloading for it. An app that only embeds needs no filesystem,
+//! which is what the web has.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+#[derive(Clone, Debug, Default)]
+pub struct Vfs {
+    /// Searched last first.
+    directories: Vec<PathBuf>,
+    embedded: HashMap<PathBuf, Arc<Cow<'static, [u8]>>>,
+}
+
+/// `a/./b` and `./a/b` both name `a/b`.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+impl Vfs {
+    /// Empty, with nothing to read from.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Searched before the directories mounted earlier and the embedded files. Absolute
+    /// paths bypass the directories, so mount `.` to also read from anywhere on disk.
+    pub fn mount(&mut self, dir: impl Into<PathBuf>) {
+        self.directories.push(dir.into());
+    }
+
+    pub fn with_directory(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.mount(dir);
+        self
+    }
+
+    /// Makes `bytes` readable at `path`, usually with `include_bytes!`.
+    pub fn embed(&mut self, path: impl AsRef<Path>, bytes: impl Into<Cow<'static, [u8]>>) {
+        self.embedded
+            .insert(normalize(path.as_ref()), Arc::new(bytes.into()));
+    }
+
+    pub fn with_embedded(
+        mut self,
+        path: impl AsRef<Path>,
+        bytes: impl Into<Cow<'static, [u8]>>,
+    ) -> Self {
+        self.embed(path, bytes);
+        self
+    }
+
+    /// Where on disk `path` would be read from, if anywhere.
+    fn find_file(&self, path: &Path) -> Option<PathBuf> {
+        self.directories
+            .iter()
+            .rev()
+            .map(|dir| dir.join(path))
+            .find(|file| file.is_file())
+    }
+
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        self.find_file(path).is_some() || self.embedded.contains_key(&normalize(path))
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> std::io::Result<Vec<u8>> {
+        let path = path.as_ref();
+        if let Some(file) = self.find_file(path) {
+            return std::fs::read(file);
+        }
+        match self.embedded.get(&normalize(path)) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "not in any mounted directory or embedded",
+            )),
+        }
+    }
+
+    /// When the file on disk was last changed, `None` for embedded files, which never do.
+    pub fn modified(&self, path: impl AsRef<Path>) -> Option<SystemTime> {
+        let file = self.find_file(path.as_ref())?;
+        std::fs::metadata(file).and_then(|m| m.modified()).ok()
+    }
+}
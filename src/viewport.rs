@@ -0,0 +1,165 @@
+//! Several views of the scene in one window, for split screen and editor layouts.
+//!
+//! Each viewport has its own camera and covers a fraction of the window. They are drawn one
+//! after another with the frame submitted in between, so the same renderers can be prepared
+//! with each viewport's camera in turn.
+
+use crate::camera::Camera;
+use crate::context::Context;
+use crate::frame::Frame;
+use crate::math::Vec2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ViewportId(usize);
+
+#[derive(Copy, Clone, Debug)]
+pub struct Viewport {
+    /// Top left corner as a fraction of the window, 0..1 with y down.
+    pub min: Vec2,
+    /// Size as a fraction of the window.
+    pub size: Vec2,
+    pub camera: Camera,
+    /// Left out of `Viewports::draw` while false.
+    pub visible: bool,
+}
+
+impl Viewport {
+    pub fn new(min: Vec2, size: Vec2, camera: Camera) -> Self {
+        Self {
+            min,
+            size,
+            camera,
+            visible: true,
+        }
+    }
+
+    /// Top left corner and size in pixels within a target `target` pixels large, snapped
+    /// to whole pixels so neighbouring viewports neither overlap nor leave gaps.
+    pub fn pixel_rect(&self, target: Vec2) -> (Vec2, Vec2) {
+        let min = self.min.mul_elem(target);
+        let max = (self.min + self.size).mul_elem(target);
+        let min = Vec2::new(min.x.round(), min.y.round());
+        let max = Vec2::new(max.x.round(), max.y.round()).min(target);
+        (min, (max - min).max(Vec2::ZERO))
+    }
+
+    pub fn contains(&self, point: Vec2, target: Vec2) -> bool {
+        let (min, size) = self.pixel_rect(target);
+        let max = min + size;
+        point.x >= min.x && point.y >= min.y && point.x < max.x && point.y < max.y
+    }
+
+    /// Restricts drawing to the viewport, in a pass over a target `target` pixels large.
+    pub fn set_viewport(&self, render_pass: &mut wgpu::RenderPass<'_>, target: Vec2) {
+        let (min, size) = self.pixel_rect(target);
+        render_pass.set_viewport(min.x, min.y, size.x.max(1.0), size.y.max(1.0), 0.0, 1.0);
+        render_pass.set_scissor_rect(
+            min.x as u32,
+            min.y as u32,
+            size.x.max(1.0) as u32,
+            size.y.max(1.0) as u32,
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct Viewports {
+    viewports: Vec<Viewport>,
+}
+
+impl Viewports {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Left and right halves of the window, each with its own camera.
+    pub fn split_horizontal(left: Camera, right: Camera) -> Self {
+        let mut viewports = Self::new();
+        viewports.add(Viewport::new(Vec2::ZERO, Vec2::new(0.5, 1.0), left));
+        viewports.add(Viewport::new(
+            Vec2::new(0.5, 0.0),
+            Vec2::new(0.5, 1.0),
+            right,
+        ));
+        viewports
+    }
+
+    /// Top and bottom halves of the window, each with its own camera.
+    pub fn split_vertical(top: Camera, bottom: Camera) -> Self {
+        let mut viewports = Self::new();
+        viewports.add(Viewport::new(Vec2::ZERO, Vec2::new(1.0, 0.5), top));
+        viewports.add(Viewport::new(
+            Vec2::new(0.0, 0.5),
+            Vec2::new(1.0, 0.5),
+            bottom,
+        ));
+        viewports
+    }
+
+    /// Viewports are drawn in the order they were added, later ones on top.
+    pub fn add(&mut self, viewport: Viewport) -> ViewportId {
+        self.viewports.push(viewport);
+        ViewportId(self.viewports.len() - 1)
+    }
+
+    pub fn get(&self, id: ViewportId) -> &Viewport {
+        &self.viewports[id.0]
+    }
+
+    pub fn get_mut(&mut self, id: ViewportId) -> &mut Viewport {
+        &mut self.viewports[id.0]
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (ViewportId, &Viewport)> {
+        self.viewports
+            .iter()
+            .enumerate()
+            .map(|(i, viewport)| (ViewportId(i), viewport))
+    }
+
+    /// Topmost visible viewport under a point in pixels, such as the cursor, to route
+    /// input to the right camera.
+    pub fn at(&self, point: Vec2, target: Vec2) -> Option<ViewportId> {
+        self.iter()
+            .rev()
+            .find(|(_, viewport)| viewport.visible && viewport.contains(point, target))
+            .map(|(id, _)| id)
+    }
+
+    /// Draws every visible viewport in turn. `clear` clears the whole frame first, then
+    /// `draw` is called once per viewport with its camera's aspect matching its rect, and
+    /// should prepare its renderers with that camera and draw in a pass that calls
+    /// `Viewport::set_viewport`.
+    pub fn draw(
+        &mut self,
+        ctx: &Context,
+        frame: &mut Frame,
+        clear: Option<wgpu::Color>,
+        mut draw: impl FnMut(ViewportId, &Viewport, &mut Frame),
+    ) {
+        if clear.is_some() {
+            frame.begin_named_pass("Viewport Clear Pass", clear);
+        }
+        let size = frame.texture().size();
+        let target = Vec2::new(size.width as f32, size.height as f32);
+        let mut first = true;
+        for (i, viewport) in self.viewports.iter_mut().enumerate() {
+            if !viewport.visible {
+                continue;
+            }
+            let (_, pixels) = viewport.pixel_rect(target);
+            if pixels.x < 1.0 || pixels.y < 1.0 {
+                continue;
+            }
+            if !first {
+                // the previous viewport's uniforms must be consumed before they're overwritten
+                frame.submit(ctx.device(), ctx.queue());
+            }
+            first = false;
+            viewport.camera.set_aspect(pixels.x as u32, pixels.y as u32);
+            frame.push_debug_group(&format!("Viewport {}", i));
+            draw(ViewportId(i), viewport, frame);
+            frame.pop_debug_group();
+        }
+    }
+}
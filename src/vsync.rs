@@ -0,0 +1,133 @@
+//! Turning vsync off while frames keep missing the display's refresh, and back on once
+//! they fit in it again.
+//!
+//! With vsync on, a frame that misses a refresh waits for the next one, so a game that
+//! can't quite keep up drops to half the refresh rate. `AdaptiveVsync` watches the frame
+//! interval and, when misses are sustained, switches the surface to `AutoNoVsync`, which
+//! tears instead of waiting. Once frames fit comfortably again it goes back to
+//! `AutoVsync`.
+
+use std::collections::VecDeque;
+
+/// The adaptive vsync switched the present mode at the end of a frame, see
+/// `Context::vsync_events`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VsyncEvent {
+    /// Frames kept missing the refresh, vsync is now off. `missed` of the last `window`
+    /// frames were late.
+    Disabled { missed: usize, window: usize },
+    /// Frames fit in the refresh again, vsync is now on.
+    Enabled,
+}
+
+#[derive(Clone, Debug)]
+pub struct AdaptiveVsync {
+    /// Of the display, in Hz. `Context::set_adaptive_vsync` fills it in from the
+    /// window's monitor when it's 0.
+    pub refresh_rate: f32,
+    /// Frames looked at before deciding to switch, at least 1, see `with_window`.
+    window: usize,
+    /// Share of the `window` frames that have to miss the refresh to turn vsync off.
+    pub miss_ratio: f32,
+    /// With vsync off, every frame of the `window` has to take less than this share of
+    /// the refresh interval to turn it back on. Below 1 so it doesn't flip back and forth.
+    pub headroom: f32,
+    intervals: VecDeque<f32>,
+    vsync: bool,
+}
+
+impl Default for AdaptiveVsync {
+    fn default() -> Self {
+        Self {
+            refresh_rate: 0.0,
+            window: 60,
+            miss_ratio: 0.25,
+            headroom: 0.8,
+            intervals: VecDeque::new(),
+            vsync: true,
+        }
+    }
+}
+
+impl AdaptiveVsync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_refresh_rate(mut self, refresh_rate: f32) -> Self {
+        self.refresh_rate = refresh_rate;
+        self
+    }
+
+    pub fn with_window(mut self, frames: usize) -> Self {
+        self.window = frames.max(1);
+        self
+    }
+
+    /// Frames looked at before deciding to switch.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    pub fn with_miss_ratio(mut self, ratio: f32) -> Self {
+        self.miss_ratio = ratio;
+        self
+    }
+
+    pub fn with_headroom(mut self, headroom: f32) -> Self {
+        self.headroom = headroom;
+        self
+    }
+
+    /// Whether it currently has vsync on.
+    pub fn is_vsync(&self) -> bool {
+        self.vsync
+    }
+
+    /// The present mode it wants now.
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        if self.vsync {
+            wgpu::PresentMode::AutoVsync
+        } else {
+            wgpu::PresentMode::AutoNoVsync
+        }
+    }
+
+    /// Takes the time since the previous frame, in seconds, and returns an event when the
+    /// present mode should change.
+    pub fn record(&mut self, interval: f32) -> Option<VsyncEvent> {
+        self.intervals.push_back(interval);
+        while self.intervals.len() > self.window {
+            self.intervals.pop_front();
+        }
+        if self.intervals.len() < self.window || self.refresh_rate <= 0.0 {
+            return None;
+        }
+
+        let refresh = 1.0 / self.refresh_rate;
+        let event = if self.vsync {
+            // a late frame waits for the refresh after the one it missed
+            let missed = self
+                .intervals
+                .iter()
+                .filter(|&&interval| interval > refresh * 1.5)
+                .count();
+            (missed as f32 >= self.miss_ratio * self.window as f32).then_some(
+                VsyncEvent::Disabled {
+                    missed,
+                    window: self.window,
+                },
+            )
+        } else {
+            self.intervals
+                .iter()
+                .all(|&interval| interval < refresh * self.headroom)
+                .then_some(VsyncEvent::Enabled)
+        };
+        if event.is_some() {
+            self.vsync = !self.vsync;
+            self.intervals.clear();
+        }
+        event
+    }
+}
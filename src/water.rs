@@ -0,0 +1,388 @@
+//! Animated water: a grid displaced by Gerstner waves in the vertex shader, showing a
+//! `PlanarReflection` and the scene beneath it blended by a fresnel term.
+//!
+//! A frame with water takes three passes. The reflection is drawn first, with the
+//! renderers prepared with the `PlanarReflection` of `WaterRenderer::plane`. Then the scene
+//! without the water is drawn into a refraction texture, which is also what the water is
+//! drawn over. Both are bound with `set_targets` and sampled a little off where the waves
+//! bend them.
+
+use crate::camera::Camera;
+use crate::depth;
+use crate::globals::{FrameGlobals, FRAME_GLOBALS_WGSL};
+use crate::math::{Vec2, Vec3};
+use crate::mesh::{GpuMesh, Mesh, MeshVertex};
+use crate::planar_reflection::Plane;
+use crate::stats::Tracked;
+use crate::texture::Texture;
+
+/// Waves a `WaterRenderer` sums, more are ignored.
+pub const MAX_WAVES: usize = 4;
+
+const SHADER: &str = r#"
+struct Globals {
+    frame: FrameGlobals,
+    // xyz the direction the light travels in
+    light: vec4<f32>,
+    // a how much of the color under the water it hides
+    deep_color: vec4<f32>,
+    // x the reflectance looking straight down, y how far the waves bend what's sampled,
+    // z the water level, w the number of waves
+    params: vec4<f32>,
+    // xy the direction, z the steepness, w the wavelength
+    waves: array<vec4<f32>, 4>,
+};
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(1) @binding(0) var reflection_texture: texture_2d<f32>;
+@group(1) @binding(1) var refraction_texture: texture_2d<f32>;
+@group(1) @binding(2) var target_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) clip: vec4<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let time = globals.frame.time;
+    var position = vec3<f32>(in.position.x, globals.params.z, in.position.z);
+    var tangent = vec3<f32>(1.0, 0.0, 0.0);
+    var bitangent = vec3<f32>(0.0, 0.0, 1.0);
+    for (var i = 0u; i < u32(globals.params.w); i = i + 1u) {
+        let wave = globals.waves[i];
+        let d = normalize(wave.xy);
+        let k = 6.2831853 / wave.w;
+        let speed = sqrt(9.8 / k);
+        let f = k * (dot(d, in.position.xz) - speed * time);
+        let a = wave.z / k;
+        position = position + vec3<f32>(d.x * a * cos(f), a * sin(f), d.y * a * cos(f));
+        tangent = tangent + vec3<f32>(
+            -d.x * d.x * wave.z * sin(f),
+            d.x * wave.z * cos(f),
+            -d.x * d.y * wave.z * sin(f),
+        );
+        bitangent = bitangent + vec3<f32>(
+            -d.x * d.y * wave.z * sin(f),
+            d.y * wave.z * cos(f),
+            -d.y * d.y * wave.z * sin(f),
+        );
+    }
+    var out: VertexOutput;
+    out.clip_position = globals.frame.view_proj * vec4<f32>(position, 1.0);
+    out.world_position = position;
+    out.normal = normalize(cross(bitangent, tangent));
+    out.clip = out.clip_position;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = normalize(in.normal);
+    let v = normalize(globals.frame.eye.xyz - in.world_position);
+    let ndc = in.clip.xy / in.clip.w;
+    let bend = n.xz * globals.params.y;
+    let screen_uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+    // the reflection is drawn flipped horizontally, see planar_reflection
+    let reflection_uv = vec2<f32>(1.0 - screen_uv.x, screen_uv.y);
+    let reflection = textureSample(reflection_texture, target_sampler, reflection_uv + bend).rgb;
+    let refraction = textureSample(refraction_texture, target_sampler, screen_uv + bend).rgb;
+
+    let r0 = globals.params.x;
+    let fresnel = r0 + (1.0 - r0) * pow(1.0 - max(dot(n, v), 0.0), 5.0);
+    let under = mix(refraction, globals.deep_color.rgb, globals.deep_color.a);
+    let h = normalize(v - globals.light.xyz);
+    let specular = pow(max(dot(n, h), 0.0), 256.0);
+    return vec4<f32>(mix(under, reflection, fresnel) + vec3<f32>(specular), 1.0);
+}
+"#;
+
+/// One Gerstner wave.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Wave {
+    /// Direction the wave travels in on the water plane, x and z.
+    pub direction: Vec2,
+    /// From 0 for flat to 1 for crests about to break. The waves' steepnesses should add
+    /// up to less than 1.
+    pub steepness: f32,
+    /// Distance between crests in world units. Longer waves travel faster.
+    pub wavelength: f32,
+}
+
+impl Wave {
+    pub fn new(direction: Vec2, steepness: f32, wavelength: f32) -> Self {
+        Self {
+            direction,
+            steepness,
+            wavelength,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Globals {
+    frame: FrameGlobals,
+    light: [f32; 4],
+    deep_color: [f32; 4],
+    params: [f32; 4],
+    waves: [[f32; 4]; MAX_WAVES],
+}
+unsafe impl bytemuck::Pod for Globals {}
+unsafe impl bytemuck::Zeroable for Globals {}
+
+/// A square of water `size` wide around the origin, at height `level`.
+pub struct WaterRenderer {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    targets_layout: wgpu::BindGroupLayout,
+    targets_bind_group: Option<wgpu::BindGroup>,
+    sampler: wgpu::Sampler,
+    grid: GpuMesh,
+    pub level: f32,
+    pub waves: Vec<Wave>,
+    /// The color of deep water, with how much of what's under it it hides as alpha.
+    pub deep_color: [f32; 4],
+    /// Share of light reflected looking straight down, about 0.02 for water.
+    pub reflectance: f32,
+    /// How far in texture coordinates the waves bend the reflection and refraction.
+    pub distortion: f32,
+    /// Direction the light the water glints with travels in.
+    pub light_direction: Vec3,
+    _tracked: Tracked,
+}
+
+impl WaterRenderer {
+    /// A grid of `resolution` by `resolution` quads, `size` wide. The waves can't be
+    /// finer than the grid.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        size: f32,
+        resolution: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Water Shader"),
+            source: wgpu::ShaderSource::Wgsl([FRAME_GLOBALS_WGSL, SHADER].concat().into()),
+        });
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Water Globals"),
+            size: std::mem::size_of::<Globals>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let globals_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Water Globals Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Water Globals Bind Group"),
+            layout: &globals_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let targets_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Water Targets Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Water Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Water Pipeline Layout"),
+            bind_group_layouts: &[&globals_layout, &targets_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Water Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[MeshVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(format.into())],
+            }),
+            // seen from below as well as above
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: depth_format.map(depth::depth_stencil),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let tracked = Tracked::new(1, 1, 0).with_buffer(&globals_buffer);
+        Self {
+            pipeline,
+            globals_buffer,
+            globals_bind_group,
+            targets_layout,
+            targets_bind_group: None,
+            sampler,
+            grid: GpuMesh::new(device, &grid(size, resolution.max(1)), "Water Grid"),
+            level: 0.0,
+            waves: vec![
+                Wave::new(Vec2::new(1.0, 0.3), 0.15, 8.0),
+                Wave::new(Vec2::new(-0.4, 1.0), 0.1, 4.5),
+                Wave::new(Vec2::new(0.7, -0.6), 0.08, 2.2),
+            ],
+            deep_color: [0.02, 0.12, 0.18, 0.6],
+            reflectance: 0.02,
+            distortion: 0.03,
+            light_direction: Vec3::new(-0.4, -1.0, -0.3).normalize(),
+            _tracked: tracked,
+        }
+    }
+
+    /// The water's resting surface, for the `PlanarReflection` it shows.
+    pub fn plane(&self) -> Plane {
+        Plane::new(Vec3::new(0.0, self.level, 0.0), Vec3::Y)
+    }
+
+    /// The reflection and the scene under the water, sampled when drawing. They can't be
+    /// the target the water is drawn into. Set them again when they're recreated.
+    pub fn set_targets(
+        &mut self,
+        device: &wgpu::Device,
+        reflection: &Texture,
+        refraction: &Texture,
+    ) {
+        self.targets_bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Water Targets Bind Group"),
+            layout: &self.targets_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&reflection.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&refraction.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        }));
+    }
+
+    /// Uploads the camera and the waves at `time` seconds.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, camera: &Camera, time: f32) {
+        if self.waves.len() > MAX_WAVES {
+            log::warn!(
+                "water has {} waves, only {} are used",
+                self.waves.len(),
+                MAX_WAVES
+            );
+        }
+        let mut waves = [[0.0; 4]; MAX_WAVES];
+        for (wave, gpu) in self.waves.iter().zip(&mut waves) {
+            *gpu = [
+                wave.direction.x,
+                wave.direction.y,
+                wave.steepness,
+                wave.wavelength.max(f32::EPSILON),
+            ];
+        }
+        let globals = Globals {
+            frame: FrameGlobals::from_camera(camera).with_time(time),
+            light: self.light_direction.normalize().extend(0.0).to_array(),
+            deep_color: self.deep_color,
+            params: [
+                self.reflectance,
+                self.distortion,
+                self.level,
+                self.waves.len().min(MAX_WAVES) as f32,
+            ],
+            waves,
+        };
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+    }
+
+    pub fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let Some(targets) = &self.targets_bind_group else {
+            log::warn!("water drawn before its targets were set");
+            return;
+        };
+        render_pass.push_debug_group("Water");
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+        render_pass.set_bind_group(1, targets, &[]);
+        self.grid.draw(render_pass, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
+
+/// A flat grid on the xz plane centred on the origin, facing up.
+fn grid(size: f32, resolution: u32) -> Mesh {
+    let row = resolution + 1;
+    let mut vertices = Vec::with_capacity((row * row) as usize);
+    for z in 0..row {
+        for x in 0..row {
+            let u = x as f32 / resolution as f32;
+            let v = z as f32 / resolution as f32;
+            vertices.push(MeshVertex {
+                position: Vec3::new((u - 0.5) * size, 0.0, (v - 0.5) * size),
+                normal: Vec3::Y,
+                uv: Vec2::new(u, v),
+            });
+        }
+    }
+    let mut indices = Vec::with_capacity((resolution * resolution * 6) as usize);
+    for z in 0..resolution {
+        for x in 0..resolution {
+            let i = z * row + x;
+            indices.extend_from_slice(&[i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+        }
+    }
+    Mesh::new(vertices, indices)
+}
@@ -0,0 +1,91 @@
+//! Which browser API draws the frames when running on the web.
+//!
+//! The choice is made when building, not at run time: wgpu 0.18 builds in one browser
+//! backend, WebGPU by default or WebGL2 with the crate's `webgl2-only` feature, so a build
+//! can't fall back from one to the other. A page serving both builds picks between them
+//! itself, loading the WebGL2 one when the browser has no `navigator.gpu`. Each build checks
+//! for it too and warns when it's in the wrong browser, and `Context::web_backend` reports
+//! which one it draws with. On WebGL2 the device is created with WebGL2 limits and without
+//! compute.
+
+/// The browser API a context draws with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WebBackend {
+    WebGpu,
+    /// The fallback for browsers without WebGPU, with lower limits and no compute or storage
+    /// buffers.
+    WebGl2,
+}
+
+impl WebBackend {
+    /// The one this build draws with.
+    pub fn compiled() -> Self {
+        if cfg!(feature = "webgl2-only") {
+            WebBackend::WebGl2
+        } else {
+            WebBackend::WebGpu
+        }
+    }
+
+    /// What `backend` is on the web, `None` when not on the web.
+    pub fn from_backend(backend: wgpu::Backend) -> Option<Self> {
+        if !cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        match backend {
+            wgpu::Backend::BrowserWebGpu => Some(WebBackend::WebGpu),
+            wgpu::Backend::Gl => Some(WebBackend::WebGl2),
+            _ => None,
+        }
+    }
+
+    /// The wgpu backends to create the instance with.
+    pub(crate) fn backends(self) -> wgpu::Backends {
+        match self {
+            WebBackend::WebGpu => wgpu::Backends::BROWSER_WEBGPU,
+            WebBackend::WebGl2 => wgpu::Backends::GL,
+        }
+    }
+
+    /// The limits to request on this backend.
+    pub(crate) fn limits(self) -> wgpu::Limits {
+        match self {
+            WebBackend::WebGpu => wgpu::Limits::default(),
+            WebBackend::WebGl2 => wgpu::Limits::downlevel_webgl2_defaults(),
+        }
+    }
+}
+
+/// Whether the browser exposes WebGPU through `navigator.gpu`, in a window or a worker.
+/// Always `false` when not on the web.
+pub fn webgpu_supported() -> bool {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let navigator = js_sys::Reflect::get(&js_sys::global(), &"navigator".into());
+        navigator.is_ok_and(|navigator| {
+            !navigator.is_undefined()
+                && js_sys::Reflect::get(&navigator, &"gpu".into())
+                    .is_ok_and(|gpu| !gpu.is_undefined() && !gpu.is_null())
+        })
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    false
+}
+
+/// The backend this build draws with, warning when the browser would be better served by
+/// the other build.
+pub(crate) fn select() -> WebBackend {
+    let compiled = WebBackend::compiled();
+    match (compiled, webgpu_supported()) {
+        (WebBackend::WebGpu, false) => log::warn!(
+            "this browser has no WebGPU, serve the build with the `webgl2-only` feature to it \
+             instead"
+        ),
+        (WebBackend::WebGl2, true) => log::warn!(
+            "this browser has WebGPU, drawing with WebGL2 only because this build has the \
+             `webgl2-only` feature"
+        ),
+        _ => {}
+    }
+    compiled
+}
@@ -0,0 +1,398 @@
+use winit::{event::*, event_loop::EventLoop, window::WindowBuilder};
+
+use wgpu::util::DeviceExt;
+
+use crate::context::Context;
+use crate::frame::Frame;
+use crate::profile::profile_scope;
+use crate::settings::GraphicsSettings;
+
+/// Hooks the run loop calls into. Only `render` is required.
+pub trait App: 'static {
+    /// Called once per frame before rendering, `dt` in seconds of `Context::time`, 0 while
+    /// it's paused.
+    fn update(&mut self, _ctx: &mut Context, _dt: f32) {}
+    /// Called before `update` once for every `Time::fixed_timestep` of game time passed
+    /// since the last frame, `dt` being the timestep.
+    fn fixed_update(&mut self, _ctx: &mut Context, _dt: f32) {}
+    /// Return `true` if the event was handled and the default handling should be skipped.
+    fn input(&mut self, _ctx: &mut Context, _event: &WindowEvent) -> bool {
+        false
+    }
+    fn resize(&mut self, _ctx: &mut Context, _size: winit::dpi::PhysicalSize<u32>) {}
+    fn render(&mut self, ctx: &mut Context, frame: &mut Frame);
+}
+
+/// Starts a frame that took `dt` seconds: ticks `ctx.time` and the frame globals, advances
+/// the tweens, creates the assets that finished loading, makes the events sent and input
+/// changes since the last frame readable and runs the timers that came due. Every run loop
+/// calls this once per frame, before `run_updates` where there's an `App`.
+pub(crate) fn step_frame(ctx: &mut Context, dt: f32) {
+    ctx.time.tick(dt);
+    let size = ctx.size();
+    crate::globals::set_frame(ctx.time.delta(), size.width, size.height);
+    ctx.tweens.update(ctx.time.delta());
+    ctx.update_assets();
+    ctx.events.update();
+    ctx.input.update(ctx.time.real_delta());
+    crate::timers::update(ctx);
+    if let Some(overlay) = &mut ctx.debug_overlay {
+        overlay.record_frame(dt);
+    }
+}
+
+/// Runs the fixed updates `ctx.time` has built up and the update, after `step_frame`.
+pub(crate) fn run_updates<A: App>(app: &mut A, ctx: &mut Context) {
+    let timestep = ctx.time.fixed_timestep();
+    for _ in 0..ctx.time.take_fixed_steps() {
+        app.fixed_update(ctx, timestep);
+    }
+    let dt = ctx.time.delta();
+    app.update(ctx, dt);
+}
+
+const SHADER: &str = r#"
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = model.color;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
+
+"#;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Vertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+unsafe impl bytemuck::Pod for Vertex {}
+unsafe impl bytemuck::Zeroable for Vertex {}
+
+const VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-0.5, 0.5, 0.0],
+        color: [1.0, 0.0, 0.0],
+    }, // Top-left
+    Vertex {
+        position: [-0.5, -0.5, 0.0],
+        color: [0.0, 1.0, 0.0],
+    }, // Bottom-left
+    Vertex {
+        position: [0.5, -0.5, 0.0],
+        color: [0.0, 0.0, 1.0],
+    }, // Bottom-right
+    Vertex {
+        position: [0.5, -0.5, 0.0],
+        color: [0.0, 0.0, 1.0],
+    }, // Bottom-right
+    Vertex {
+        position: [0.5, 0.5, 0.0],
+        color: [1.0, 1.0, 0.0],
+    }, // Top-right
+    Vertex {
+        position: [-0.5, 0.5, 0.0],
+        color: [1.0, 0.0, 0.0],
+    }, // Top-left
+];
+
+impl Vertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// The demo shown by `run`.
+struct RainbowSquare {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+}
+
+impl RainbowSquare {
+    fn new(ctx: &Context) -> Self {
+        let device = ctx.device();
+        let num_vertices = VERTICES.len() as u32;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                // 3.
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    // 4.
+                    format: ctx.surface_format(),
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Self {
+            render_pipeline,
+            vertex_buffer,
+            num_vertices,
+        }
+    }
+}
+
+impl App for RainbowSquare {
+    fn render(&mut self, _ctx: &mut Context, frame: &mut Frame) {
+        let mut render_pass = frame.begin_pass(Some(wgpu::Color::WHITE));
+
+        render_pass.set_pipeline(&self.render_pipeline); // 2.
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
+
+/// Opens a window showing the rainbow square demo.
+pub async fn run(title: &str) {
+    run_app(title, |ctx| RainbowSquare::new(ctx)).await
+}
+
+/// Opens a window and drives `app` until the window is closed.
+/// `init` builds the app once the device is ready.
+pub async fn run_app<A: App>(title: &str, init: impl FnOnce(&mut Context) -> A) {
+    run_app_with_settings(title, &GraphicsSettings::default(), init).await
+}
+
+/// Like `run_app`, with the window and device set up as `settings` say, e.g. from
+/// `GraphicsSettings::from_args`.
+pub async fn run_app_with_settings<A: App>(
+    title: &str,
+    settings: &GraphicsSettings,
+    init: impl FnOnce(&mut Context) -> A,
+) {
+    env_logger::init();
+    run_event_loop(EventLoop::new(), title, settings, init).await
+}
+
+/// Like `run_app`, for the `android_main` of an Android app. Needs the `android-activity`
+/// feature. The surface is created when the activity resumes and dropped when it's
+/// suspended, frames are skipped in between.
+#[cfg(all(target_os = "android", feature = "android-activity"))]
+pub async fn run_android_app<A: App>(
+    android_app: winit::platform::android::activity::AndroidApp,
+    title: &str,
+    init: impl FnOnce(&mut Context) -> A,
+) {
+    use winit::platform::android::EventLoopBuilderExtAndroid;
+
+    let event_loop = winit::event_loop::EventLoopBuilder::new()
+        .with_android_app(android_app)
+        .build();
+    run_event_loop(event_loop, title, &GraphicsSettings::default(), init).await
+}
+
+/// The main window, sized as `settings` say.
+pub(crate) fn window_builder<T>(
+    event_loop: &EventLoop<T>,
+    title: &str,
+    settings: &GraphicsSettings,
+) -> WindowBuilder {
+    let mut builder = WindowBuilder::new().with_title(title);
+    if let Some(size) = settings.size {
+        builder = builder.with_inner_size(size);
+    }
+    if settings.fullscreen {
+        builder = builder.with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+    }
+    // draw at the screen's native resolution, the Metal layer wgpu creates uses it too
+    #[cfg(target_os = "ios")]
+    if let Some(monitor) = event_loop.primary_monitor() {
+        use winit::platform::ios::WindowBuilderExtIOS;
+        builder = builder.with_scale_factor(monitor.scale_factor());
+    }
+    #[cfg(not(target_os = "ios"))]
+    let _ = event_loop;
+    builder
+}
+
+async fn run_event_loop<A: App>(
+    event_loop: EventLoop<()>,
+    title: &str,
+    settings: &GraphicsSettings,
+    init: impl FnOnce(&mut Context) -> A,
+) {
+    let window = window_builder(&event_loop, title, settings)
+        .build(&event_loop)
+        .expect("Window could not be created");
+
+    crate::profile::start();
+    let mut ctx = Context::new(std::sync::Arc::new(window), settings).await;
+    let mut app = init(&mut ctx);
+    let mut last_update = crate::time::Instant::now();
+
+    event_loop.run(move |event, target, control_flow| match event {
+        Event::Resumed => {
+            ctx.resume();
+            // time spent in the background isn't a frame
+            last_update = crate::time::Instant::now();
+            control_flow.set_poll();
+        }
+        Event::Suspended => ctx.suspend(),
+        Event::RedrawRequested(window_id)
+            if window_id == ctx.window().id() && ctx.has_surface() =>
+        {
+            ctx.pace_frame();
+            let now = crate::time::Instant::now();
+            let dt = crate::deterministic::timestep().unwrap_or((now - last_update).as_secs_f32());
+            last_update = now;
+
+            {
+                profile_scope!("update");
+                step_frame(&mut ctx, dt);
+                run_updates(&mut app, &mut ctx);
+            }
+
+            match ctx.begin_frame() {
+                Ok(mut frame) => {
+                    {
+                        profile_scope!("encode");
+                        app.render(&mut ctx, &mut frame);
+                        if let Some(mut overlay) = ctx.debug_overlay.take() {
+                            overlay.draw(&ctx, &mut frame);
+                            ctx.debug_overlay = Some(overlay);
+                        }
+                    }
+                    ctx.end_frame(frame);
+                }
+                Err(wgpu::SurfaceError::Lost) => ctx.resize(ctx.size()),
+                Err(wgpu::SurfaceError::OutOfMemory) => control_flow.set_exit(),
+                Err(e) => eprintln!("{:?}", e),
+            }
+        }
+        Event::RedrawRequested(window_id) if ctx.tool_windows.contains(window_id) => {
+            crate::tool_windows::render(&mut ctx, window_id);
+        }
+        Event::MainEventsCleared => {
+            crate::tool_windows::update(&mut ctx, target);
+            // redraw loop, keeps tweens and other animations moving
+            if ctx.has_surface() && !ctx.is_rendering_paused() {
+                ctx.window().request_redraw();
+            } else {
+                control_flow.set_wait();
+            }
+        }
+        Event::WindowEvent { window_id, event } if window_id == ctx.window().id() => {
+            if event == WindowEvent::Focused(true) && ctx.is_rendering_paused() {
+                // time spent paused isn't a frame either
+                last_update = crate::time::Instant::now();
+                control_flow.set_poll();
+            }
+            if ctx.mutes_input(&event)
+                || ctx.capture_input(&event)
+                || ctx
+                    .debug_overlay
+                    .as_mut()
+                    .is_some_and(|overlay| overlay.input(&event))
+            {
+                return;
+            }
+            ctx.record_window_event(&event);
+            if app.input(&mut ctx, &event) {
+                return;
+            }
+            match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    println!("Closed window!");
+                    control_flow.set_exit();
+                }
+                WindowEvent::Resized(physical_size) => {
+                    ctx.resize(physical_size);
+                    app.resize(&mut ctx, physical_size);
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    ctx.resize(*new_inner_size);
+                    app.resize(&mut ctx, *new_inner_size);
+                }
+                _ => {}
+            };
+        }
+        Event::WindowEvent { window_id, event } if ctx.tool_windows.contains(window_id) => {
+            crate::tool_windows::handle_event(&mut ctx, window_id, &event);
+        }
+        _ => (),
+    });
+}